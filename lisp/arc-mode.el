@@ -231,18 +231,17 @@ Archive and member name will be added."
                              (when 7z
                                (file-name-nondirectory 7z))))
 
-(defcustom archive-zip-extract
-  (cond ((executable-find "unzip")   '("unzip" "-qq" "-c"))
-	(archive-7z-program          `(,archive-7z-program "x" "-so"))
-	((executable-find "pkunzip") '("pkunzip" "-e" "-o-"))
-	(t                           '("unzip" "-qq" "-c")))
+(defcustom archive-zip-extract nil
   "Program and its options to run in order to extract a zip file member.
 Extraction should happen to standard output.  Archive and member name will
-be added."
-  :type '(list (string :tag "Program")
-	       (repeat :tag "Options"
-		       :inline t
-		       (string :format "%v")))
+be added.  If nil, members are extracted by Emacs itself, with
+`archive-insert-entry', and no program is needed."
+  :type '(choice (const :tag "Built-in" nil)
+		 (list (string :tag "Program")
+		       (repeat :tag "Options"
+			       :inline t
+			       (string :format "%v"))))
+  :version "27.1"
   :group 'archive-zip)
 
 ;; For several reasons the latter behavior is not desirable in general.
@@ -1911,6 +1910,8 @@ This doesn't recover lost files, it just undoes changes in the buffer itself."
 
 (defun archive-zip-extract (archive name)
   (cond
+   ((null archive-zip-extract)
+    (archive-insert-entry archive name))
    ((member-ignore-case (car archive-zip-extract) '("pkunzip" "pkzip"))
     (archive-*-extract archive name archive-zip-extract))
    ((equal (car archive-zip-extract) archive-7z-program)
@@ -233,11 +233,13 @@ The result is a sequence of the same type as SEQUENCE."
   "Sort SEQUENCE using PRED as a comparison function.
 Elements of SEQUENCE are transformed by FUNCTION before being
 sorted.  FUNCTION must be a function of one argument."
-  (seq-sort (lambda (a b)
-              (funcall pred
-                       (funcall function a)
-                       (funcall function b)))
-            sequence))
+  (if (seq--list-or-vector-p sequence)
+      (seq--sort-by function pred sequence)
+    (seq-sort (lambda (a b)
+                (funcall pred
+                         (funcall function a)
+                         (funcall function b)))
+              sequence)))
 
 (cl-defgeneric seq-reverse (sequence)
   "Return a sequence with elements of SEQUENCE in reverse order."
@@ -375,11 +377,14 @@ Equality is defined by TESTFN if non-nil or by `equal' if nil."
 (cl-defgeneric seq-uniq (sequence &optional testfn)
   "Return a list of the elements of SEQUENCE with duplicates removed.
 TESTFN is used to compare elements, or `equal' if TESTFN is nil."
-  (let ((result '()))
-    (seq-doseq (elt sequence)
-      (unless (seq-contains result elt testfn)
-        (setq result (cons elt result))))
-    (nreverse result)))
+  (if (and (seq--list-or-vector-p sequence)
+           (seq--hashable-test-p testfn))
+      (seq--uniq sequence testfn)
+    (let ((result '()))
+      (seq-doseq (elt sequence)
+        (unless (seq-contains result elt testfn)
+          (setq result (cons elt result))))
+      (nreverse result))))
 
 (cl-defgeneric seq-mapcat (function sequence &optional type)
   "Concatenate the result of applying FUNCTION to each element of SEQUENCE.
@@ -411,27 +416,33 @@ Equality is defined by TESTFN if non-nil or by `equal' if nil."
 (cl-defgeneric seq-difference (sequence1 sequence2 &optional testfn)
   "Return a list of the elements that appear in SEQUENCE1 but not in SEQUENCE2.
 Equality is defined by TESTFN if non-nil or by `equal' if nil."
-  (seq-reduce (lambda (acc elt)
-                (if (not (seq-contains sequence2 elt testfn))
-                    (cons elt acc)
-                  acc))
-              (seq-reverse sequence1)
-              '()))
+  (if (and (seq--list-or-vector-p sequence1)
+           (seq--list-or-vector-p sequence2)
+           (seq--hashable-test-p testfn))
+      (seq--difference sequence1 sequence2 testfn)
+    (seq-reduce (lambda (acc elt)
+                  (if (not (seq-contains sequence2 elt testfn))
+                      (cons elt acc)
+                    acc))
+                (seq-reverse sequence1)
+                '())))
 
 (cl-defgeneric seq-group-by (function sequence)
   "Apply FUNCTION to each element of SEQUENCE.
 Separate the elements of SEQUENCE into an alist using the results as
 keys.  Keys are compared using `equal'."
-  (seq-reduce
-   (lambda (acc elt)
-     (let* ((key (funcall function elt))
-            (cell (assoc key acc)))
-       (if cell
-           (setcdr cell (push elt (cdr cell)))
-         (push (list key elt) acc))
-       acc))
-   (seq-reverse sequence)
-   nil))
+  (if (seq--list-or-vector-p sequence)
+      (seq--group-by function sequence)
+    (seq-reduce
+     (lambda (acc elt)
+       (let* ((key (funcall function elt))
+              (cell (assoc key acc)))
+         (if cell
+             (setcdr cell (push elt (cdr cell)))
+           (push (list key elt) acc))
+         acc))
+     (seq-reverse sequence)
+     nil)))
 
 (cl-defgeneric seq-min (sequence)
   "Return the smallest element of SEQUENCE.
@@ -536,6 +547,17 @@ Signal an error if SEQUENCE is empty."
       sequence
     (concat sequence)))
 
+(defun seq--list-or-vector-p (sequence)
+  "Return non-nil if SEQUENCE is a list or a vector.
+The primitives `seq--uniq', `seq--difference', `seq--group-by' and
+`seq--sort-by' handle such sequences faster than the generic code."
+  (or (listp sequence) (vectorp sequence)))
+
+(defun seq--hashable-test-p (testfn)
+  "Return non-nil if TESTFN can be the test of a hash table.
+TESTFN is the test of a seq.el function, nil meaning `equal'."
+  (memq testfn '(nil eq eql equal)))
+
 (defun seq--activate-font-lock-keywords ()
   "Activate font-lock keywords for some symbols defined in seq."
   (font-lock-add-keywords 'emacs-lisp-mode
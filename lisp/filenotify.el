@@ -22,8 +22,8 @@
 ;;; Commentary
 
 ;; This package is an abstraction layer from the different low-level
-;; file notification packages `inotify', `kqueue', `gfilenotify' and
-;; `w32notify'.
+;; file notification packages `rustnotify', `inotify', `kqueue',
+;; `gfilenotify' and `w32notify'.
 
 ;;; Code:
 
@@ -32,6 +32,7 @@
 
 (defconst file-notify--library
   (cond
+   ((featurep 'rustnotify) 'rustnotify)
    ((featurep 'inotify) 'inotify)
    ((featurep 'kqueue) 'kqueue)
    ((featurep 'gfilenotify) 'gfilenotify)
@@ -79,8 +80,8 @@ If it is registered in `file-notify-descriptors', a stopped event is sent."
          `(,descriptor stopped ,(file-notify--watch-absolute-filename watch)))
       (remhash descriptor file-notify-descriptors))))
 
-;; This function is used by `inotify', `kqueue', `gfilenotify' and
-;; `w32notify' events.
+;; This function is used by `rustnotify', `inotify', `kqueue',
+;; `gfilenotify' and `w32notify' events.
 ;;;###autoload
 (defun file-notify-handle-event (event)
   "Handle file system monitoring event.
@@ -117,7 +118,7 @@ Could be different from the directory watched by the backend library."
       (or  (and (stringp (nth 2 event)) (nth 2 event)) "")
       (file-notify--watch-directory watch)))))
 
-;; Only `gfilenotify' could return two file names.
+;; Only `gfilenotify' and `rustnotify' could return two file names.
 (defun file-notify--event-file1-name (event)
   "Return second file name of file notification event, or nil.
 This is available in case a file has been moved."
@@ -277,8 +278,12 @@ include the following symbols:
   `attribute-change' -- watch for file attributes changes, like
                         permissions or modification time
 
+  `recursive'        -- also watch the subdirectories of a directory
+
 If FILE is a directory, `change' watches for file creation or
-deletion in that directory.  This does not work recursively.
+deletion in that directory.  This only works recursively when
+`recursive' is given, and only with the `rustnotify' package; the
+other packages ignore it.
 
 When any event happens, Emacs will call the CALLBACK function passing
 it a single argument EVENT, which is of the form
@@ -302,7 +307,8 @@ FILE is the name of the file whose event is being reported."
     (signal 'wrong-type-argument `(,file)))
   (setq file (expand-file-name file))
   (unless (and (consp flags)
-	       (null (delq 'change (delq 'attribute-change (copy-tree flags)))))
+	       (null (delq 'change (delq 'attribute-change
+                                         (delq 'recursive (copy-tree flags))))))
     (signal 'wrong-type-argument `(,flags)))
   (unless (functionp callback)
     (signal 'wrong-type-argument `(,callback)))
@@ -331,14 +337,20 @@ FILE is the name of the file whose event is being reported."
       ;; Determine low-level function to be called.
       (setq func
 	    (cond
+	     ((eq file-notify--library 'rustnotify) 'rustnotify-add-watch)
 	     ((eq file-notify--library 'inotify) 'inotify-add-watch)
 	     ((eq file-notify--library 'kqueue) 'kqueue-add-watch)
 	     ((eq file-notify--library 'gfilenotify) 'gfile-add-watch)
 	     ((eq file-notify--library 'w32notify) 'w32notify-add-watch)))
 
       ;; Determine respective flags.
-      (if (eq file-notify--library 'gfilenotify)
-	  (setq l-flags (append '(watch-mounts send-moved) flags))
+      (cond
+       ((eq file-notify--library 'rustnotify)
+        (setq l-flags flags))
+       ((eq file-notify--library 'gfilenotify)
+	(setq l-flags (append '(watch-mounts send-moved)
+                              (remq 'recursive flags))))
+       (t
 	(when (memq 'change flags)
 	  (setq
 	   l-flags
@@ -354,7 +366,7 @@ FILE is the name of the file whose event is being reported."
                  ((eq file-notify--library 'inotify) 'attrib)
                  ((eq file-notify--library 'kqueue) 'attrib)
                  ((eq file-notify--library 'w32notify) 'attributes))
-                l-flags)))
+                l-flags))))
 
       ;; Call low-level function.
       (setq desc (funcall
@@ -391,6 +403,7 @@ DESCRIPTOR should be an object returned by `file-notify-add-watch'."
 
             (funcall
              (cond
+              ((eq file-notify--library 'rustnotify) 'rustnotify-rm-watch)
               ((eq file-notify--library 'inotify) 'inotify-rm-watch)
               ((eq file-notify--library 'kqueue) 'kqueue-rm-watch)
               ((eq file-notify--library 'gfilenotify) 'gfile-rm-watch)
@@ -413,6 +426,7 @@ DESCRIPTOR should be an object returned by `file-notify-add-watch'."
                (funcall handler 'file-notify-valid-p descriptor)
              (funcall
               (cond
+               ((eq file-notify--library 'rustnotify) 'rustnotify-valid-p)
                ((eq file-notify--library 'inotify) 'inotify-valid-p)
                ((eq file-notify--library 'kqueue) 'kqueue-valid-p)
                ((eq file-notify--library 'gfilenotify) 'gfile-valid-p)
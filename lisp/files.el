@@ -2223,8 +2223,12 @@ Do you want to revisit the file normally now? ")
 	  (find-file-noselect-1 buf filename nowarn
 				rawfile truename number))))))
 
+(defvar find-file--map-literally nil
+  "Non-nil means files visited literally are mapped into memory.
+This is bound by `find-file-literally-mmap'.")
+
 (defun find-file-noselect-1 (buf filename nowarn rawfile truename number)
-  (let (error)
+  (let (error mapped)
     (with-current-buffer buf
       (kill-local-variable 'find-file-literally)
       ;; Needed in case we are re-visiting the file with a different
@@ -2238,7 +2242,11 @@ Do you want to revisit the file normally now? ")
       (if rawfile
 	  (condition-case ()
 	      (let ((inhibit-read-only t))
-		(insert-file-contents-literally filename t))
+		(when find-file--map-literally
+		  (set-buffer-multibyte nil)
+		  (setq mapped (insert-file-contents-mapped filename)))
+		(unless mapped
+		  (insert-file-contents-literally filename t)))
 	    (file-error
 	     (when (and (file-exists-p filename)
 			(not (file-readable-p filename)))
@@ -2282,7 +2290,9 @@ Do you want to revisit the file normally now? ")
 	    (set-buffer-multibyte nil)
 	    (setq buffer-file-coding-system 'no-conversion)
 	    (set-buffer-major-mode buf)
-	    (setq-local find-file-literally t))
+	    (setq-local find-file-literally t)
+	    (when mapped
+	      (setq buffer-read-only t)))
 	(after-find-file error (not nowarn)))
       (current-buffer))))
 
@@ -2358,6 +2368,26 @@ the file contents into it using `insert-file-contents-literally'."
   	  "Find file literally: " nil default-directory
   	  (confirm-nonexistent-file-or-buffer))))
   (switch-to-buffer (find-file-noselect filename nil t)))
+
+(defun find-file-literally-mmap (filename)
+  "Visit file FILENAME literally and read-only, mapping it into memory.
+This is like `find-file-literally', except that the text of the
+buffer is the file itself, mapped into memory by
+`insert-file-contents-mapped', instead of a copy of it.  Parts of
+the file which are never looked at are never read, so browsing many
+large files, such as build logs, this way takes little memory.
+
+The buffer is read-only.  If you make it writable and change it, its
+text is copied into memory as usual.  If the file can't be mapped,
+because it is remote or isn't a regular file, it is read as
+`find-file-literally' reads it.  If another program truncates the
+file while it is visited, the text it loses reads as zeros."
+  (interactive
+   (list (read-file-name
+	  "Find file literally (mapped): " nil default-directory
+	  (confirm-nonexistent-file-or-buffer))))
+  (let ((find-file--map-literally t))
+    (switch-to-buffer (find-file-noselect filename nil t))))
 
 (defun after-find-file (&optional error warn noauto
 				  _after-find-file-from-revert-buffer
@@ -4290,9 +4320,17 @@ Interactively, confirmation is required unless you supply a prefix argument."
   "Return an alist of extended attributes of file FILENAME.
 
 Extended attributes are platform-specific metadata about the file,
-such as SELinux context, list of ACL entries, etc."
+such as SELinux context, list of ACL entries, etc.  The `xattrs'
+element is an alist of the names and values of the extended
+attributes the file system keeps for the file, like the macOS
+quarantine flag, except for those which hold its ACL."
   `((acl . ,(file-acl filename))
-    (selinux-context . ,(file-selinux-context filename))))
+    (selinux-context . ,(file-selinux-context filename))
+    (xattrs . ,(delq nil
+                     (mapcar (lambda (name)
+                               (unless (string-prefix-p "system." name)
+                                 (cons name (file-xattr filename name))))
+                             (file-xattrs filename))))))
 
 (defun set-file-extended-attributes (filename attributes)
   "Set extended attributes of file FILENAME to ATTRIBUTES.
@@ -4307,7 +4345,15 @@ Value is t if the function succeeds in setting the attributes."
 	(cond ((eq attr 'acl)
                (setq rv (set-file-acl filename val)))
 	      ((eq attr 'selinux-context)
-               (setq rv (set-file-selinux-context filename val))))
+               (setq rv (set-file-selinux-context filename val)))
+	      ((eq attr 'xattrs)
+               ;; Attributes which need privileges to set, like the
+               ;; SELinux context, may fail to be copied.
+               (setq rv nil)
+               (dolist (xattr val)
+                 (when (ignore-errors
+                         (set-file-xattr filename (car xattr) (cdr xattr)))
+                   (setq rv t)))))
         (setq result (or result rv))))
 
     result))
@@ -7215,31 +7261,25 @@ based on existing mode bits, as in \"og+rX-w\"."
 ;; Trashcan handling.
 (defcustom trash-directory nil
   "Directory for `move-file-to-trash' to move files and directories to.
-This directory is only used when the function `system-move-file-to-trash'
-is not defined.
 Relative paths are interpreted relative to `default-directory'.
-If the value is nil, Emacs uses a freedesktop.org-style trashcan."
+If the value is nil, Emacs uses the trash of the system, through
+`system-move-file-to-trash'."
   :type  '(choice (const nil) directory)
   :group 'auto-save
   :version "23.2")
 
-(defvar trash--hexify-table)
-
-(declare-function system-move-file-to-trash "w32fns.c" (filename))
-
 (defun move-file-to-trash (filename)
   "Move the file (or directory) named FILENAME to the trash.
 When `delete-by-moving-to-trash' is non-nil, this function is
 called by `delete-file' and `delete-directory' instead of
 deleting files outright.
 
-If the function `system-move-file-to-trash' is defined, call it
- with FILENAME as an argument.
-Otherwise, if `trash-directory' is non-nil, move FILENAME to that
- directory.
-Otherwise, trash FILENAME using the freedesktop.org conventions,
- like the GNOME, KDE and XFCE desktop environments.  Emacs only
- moves files to \"home trash\", ignoring per-volume trashcans."
+If `trash-directory' is non-nil, move FILENAME to that directory.
+Otherwise, call `system-move-file-to-trash', which moves FILENAME
+to the trash of the system: the Recycle Bin on MS-Windows, and the
+freedesktop.org trashcan used by the GNOME, KDE and XFCE desktop
+environments elsewhere.  Files in the freedesktop.org trashcan can
+be listed with `trash-list' and restored with `trash-restore'."
   (interactive "fMove file to trash: ")
   (cond (trash-directory
 	 ;; If `trash-directory' is non-nil, move the file there.
@@ -7260,91 +7300,8 @@ Otherwise, trash FILENAME using the freedesktop.org conventions,
 		 (setq new-fn (car (find-backup-file-name new-fn)))))
 	   (let (delete-by-moving-to-trash)
 	     (rename-file fn new-fn))))
-	;; If `system-move-file-to-trash' is defined, use it.
-	((fboundp 'system-move-file-to-trash)
-	 (system-move-file-to-trash filename))
-	;; Otherwise, use the freedesktop.org method, as specified at
-	;; http://freedesktop.org/wiki/Specifications/trash-spec
 	(t
-	 (let* ((xdg-data-dir
-		 (directory-file-name
-		  (expand-file-name "Trash"
-				    (or (getenv "XDG_DATA_HOME")
-					"~/.local/share"))))
-		(trash-files-dir (expand-file-name "files" xdg-data-dir))
-		(trash-info-dir (expand-file-name "info" xdg-data-dir))
-		(fn (directory-file-name (expand-file-name filename))))
-
-	   ;; Check if we have permissions to delete.
-	   (unless (file-writable-p (directory-file-name
-				     (file-name-directory fn)))
-	     (error "Cannot move %s to trash: Permission denied" filename))
-	   ;; The trashed file cannot be the trash dir or its parent.
-	   (if (string-prefix-p fn trash-files-dir)
-	       (error "The trash directory %s is a subdirectory of %s"
-		      trash-files-dir filename))
-	   (if (string-prefix-p fn trash-info-dir)
-	       (error "The trash directory %s is a subdirectory of %s"
-		      trash-info-dir filename))
-
-	   ;; Ensure that the trash directory exists; otherwise, create it.
-	   (with-file-modes #o700
-	     (unless (file-exists-p trash-files-dir)
-	       (make-directory trash-files-dir t))
-	     (unless (file-exists-p trash-info-dir)
-	       (make-directory trash-info-dir t)))
-
-	   ;; Try to move to trash with .trashinfo undo information
-	   (save-excursion
-	     (with-temp-buffer
-	       (set-buffer-file-coding-system 'utf-8-unix)
-	       (insert "[Trash Info]\nPath=")
-	       ;; Perform url-encoding on FN.  For compatibility with
-	       ;; other programs (e.g. XFCE Thunar), allow literal "/"
-	       ;; for path separators.
-	       (unless (boundp 'trash--hexify-table)
-		 (setq trash--hexify-table (make-vector 256 nil))
-		 (let ((unreserved-chars
-			(list ?/ ?a ?b ?c ?d ?e ?f ?g ?h ?i ?j ?k ?l ?m
-			      ?n ?o ?p ?q ?r ?s ?t ?u ?v ?w ?x ?y ?z ?A
-			      ?B ?C ?D ?E ?F ?G ?H ?I ?J ?K ?L ?M ?N ?O
-			      ?P ?Q ?R ?S ?T ?U ?V ?W ?X ?Y ?Z ?0 ?1 ?2
-			      ?3 ?4 ?5 ?6 ?7 ?8 ?9 ?- ?_ ?. ?! ?~ ?* ?'
-			      ?\( ?\))))
-		   (dotimes (byte 256)
-		     (aset trash--hexify-table byte
-			   (if (memq byte unreserved-chars)
-			       (char-to-string byte)
-			     (format "%%%02x" byte))))))
-	       (mapc (lambda (byte)
-		       (insert (aref trash--hexify-table byte)))
-		     (if (multibyte-string-p fn)
-			 (encode-coding-string fn 'utf-8)
-		       fn))
-	       (insert "\nDeletionDate="
-		       (format-time-string "%Y-%m-%dT%T")
-		       "\n")
-
-	       ;; Make a .trashinfo file.  Use O_EXCL, as per trash-spec 1.0.
-	       (let* ((files-base (file-name-nondirectory fn))
-		      (info-fn (expand-file-name
-				(concat files-base ".trashinfo")
-				trash-info-dir)))
-		 (condition-case nil
-		     (write-region nil nil info-fn nil 'quiet info-fn 'excl)
-		   (file-already-exists
-		    ;; Uniquify new-fn.  Some file managers do not
-		    ;; like Emacs-style backup file names.  E.g.:
-		    ;; https://bugs.kde.org/170956
-		    (setq info-fn (make-temp-file
-				   (expand-file-name files-base trash-info-dir)
-				   nil ".trashinfo"))
-		    (setq files-base (file-name-nondirectory info-fn))
-		    (write-region nil nil info-fn nil 'quiet info-fn)))
-		 ;; Finally, try to move the file to the trashcan.
-		 (let ((delete-by-moving-to-trash nil)
-		       (new-fn (expand-file-name files-base trash-files-dir)))
-		   (rename-file fn new-fn)))))))))
+	 (system-move-file-to-trash filename))))
 
 (defsubst file-attribute-type (attributes)
   "The type field in ATTRIBUTES returned by `file-attributes'.
@@ -538,49 +538,41 @@ COMPOSITION-PREDICATE will be used to compose region."
 (defun ucs-normalize-NFD-region (from to)
   "Normalize the current region by the Unicode NFD."
   (interactive "r")
-  (ucs-normalize-region from to
-                        ucs-normalize-nfd-quick-check-regexp
-                        'ucs-normalize-nfd-table nil))
+  (normalize-region from to 'nfd))
 ;;;###autoload
 (defun ucs-normalize-NFD-string (str)
   "Normalize the string STR by the Unicode NFD."
-  (ucs-normalize-string ucs-normalize-NFD-region))
+  (string-normalize str 'nfd))
 
 ;;;###autoload
 (defun ucs-normalize-NFC-region (from to)
   "Normalize the current region by the Unicode NFC."
   (interactive "r")
-  (ucs-normalize-region from to
-                        ucs-normalize-nfc-quick-check-regexp
-                        'ucs-normalize-nfd-table t))
+  (normalize-region from to 'nfc))
 ;;;###autoload
 (defun ucs-normalize-NFC-string (str)
   "Normalize the string STR by the Unicode NFC."
-  (ucs-normalize-string ucs-normalize-NFC-region))
+  (string-normalize str 'nfc))
 
 ;;;###autoload
 (defun ucs-normalize-NFKD-region (from to)
   "Normalize the current region by the Unicode NFKD."
   (interactive "r")
-  (ucs-normalize-region from to
-                        ucs-normalize-nfkd-quick-check-regexp
-                        'ucs-normalize-nfkd-table nil))
+  (normalize-region from to 'nfkd))
 ;;;###autoload
 (defun ucs-normalize-NFKD-string (str)
   "Normalize the string STR by the Unicode NFKD."
-  (ucs-normalize-string ucs-normalize-NFKD-region))
+  (string-normalize str 'nfkd))
 
 ;;;###autoload
 (defun ucs-normalize-NFKC-region (from to)
   "Normalize the current region by the Unicode NFKC."
   (interactive "r")
-  (ucs-normalize-region from to
-                        ucs-normalize-nfkc-quick-check-regexp
-                        'ucs-normalize-nfkd-table t))
+  (normalize-region from to 'nfkc))
 ;;;###autoload
 (defun ucs-normalize-NFKC-string (str)
   "Normalize the string STR by the Unicode NFKC."
-  (ucs-normalize-string ucs-normalize-NFKC-region))
+  (string-normalize str 'nfkc))
 
 ;;;###autoload
 (defun ucs-normalize-HFS-NFD-region (from to)
@@ -430,15 +430,22 @@ There should be no more than seven characters after the final `/'."
                       ;; If visiting, bind off buffer-file-name so that
                       ;; file-locking will not ask whether we should
                       ;; really edit the buffer.
-                      (let ((buffer-file-name
-                             (if visit nil buffer-file-name)))
-                        (jka-compr-call-process uncompress-program
-                                                (concat uncompress-message
-                                                        " " base-name)
-                                                local-file
-                                                t
-                                                nil
-                                                uncompress-args)))
+                      (let* ((buffer-file-name
+                              (if visit nil buffer-file-name))
+                             ;; Files compressed with gzip, zstd or xz
+                             ;; are decompressed as they are read,
+                             ;; without running the program.
+                             (inserted
+                              (insert-file-contents-decompressed local-file)))
+                        (if inserted
+                            (forward-char inserted)
+                          (jka-compr-call-process uncompress-program
+                                                  (concat uncompress-message
+                                                          " " base-name)
+                                                  local-file
+                                                  t
+                                                  nil
+                                                  uncompress-args))))
                     (setq size (- (point) start))
                     (if replace
                         (delete-region (point) (point-max)))
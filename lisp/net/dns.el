@@ -395,7 +395,27 @@ Parses \"/etc/resolv.conf\" or calls \"nslookup\"."
 (defun dns-query (name &optional type fullp reversep)
   "Query a DNS server for NAME of TYPE.
 If FULLP, return the entire record returned.
-If REVERSEP, look up an IP address."
+If REVERSEP, look up an IP address.
+Unless FULLP is non-nil, the records are looked up with
+`dns-resolve' if Emacs has it and it knows TYPE."
+  (setq type (or type 'A))
+  (if (and (not fullp)
+	   (fboundp 'dns-resolve)
+	   (memq (if reversep 'PTR type)
+		 '(A AAAA CNAME NS PTR MX SRV TXT SOA)))
+      (let ((records (ignore-errors
+		       (dns-resolve name (if reversep 'PTR type)))))
+	;; Like the answers of the server, the TXT records come
+	;; together.
+	(if (eq type 'TXT)
+	    (and records (apply #'concat records))
+	  (car records)))
+    (dns-query-server name type fullp reversep)))
+
+(defun dns-query-server (name &optional type fullp reversep)
+  "Query the DNS server in `dns-servers' for NAME of TYPE.
+This is what `dns-query' does with FULLP, REVERSEP, and when
+`dns-resolve' can't be used."
   (setq type (or type 'A))
   (unless (dns-servers-up-to-date-p)
     (dns-set-servers))
@@ -51,6 +51,57 @@
 (autoload 'gnutls-negotiate "gnutls")
 (autoload 'open-gnutls-stream "gnutls")
 
+(defcustom network-stream-tls-backend 'gnutls
+  "The library `open-network-stream' makes TLS connections with.
+If `gnutls', it uses GnuTLS if Emacs was built with it, and
+otherwise the external programs of tls.el and starttls.el.  If
+`rustls', it uses rustls, which is built into Emacs, with
+`rustls-boot'.  This only affects connections of the `tls', `ssl'
+and `starttls' types, and those upgraded with STARTTLS.  rustls is
+only available on Unix; elsewhere, GnuTLS is always used."
+  :type '(choice (const :tag "GnuTLS" gnutls)
+		 (const :tag "Rustls" rustls))
+  :group 'comm
+  :version "27.1")
+
+(defun network-stream-use-rustls-p ()
+  "Return non-nil if TLS connections are made with rustls."
+  (and (eq network-stream-tls-backend 'rustls)
+       (not (memq system-type '(windows-nt ms-dos)))))
+
+(defcustom network-stream-resolve-hosts t
+  "Whether `open-network-stream' looks host names up itself.
+If non-nil, and the connection is not made asynchronously, the
+address of the host is looked up with `dns-resolve', which can be
+interrupted with \\[keyboard-quit], rather than by
+`make-network-process', which can't.  If the lookup fails, the
+host name is passed on to `make-network-process'."
+  :type 'boolean
+  :group 'comm
+  :version "27.1")
+
+(defun network-stream-host-address (host &optional nowait)
+  "Return the address `make-network-process' should connect to for HOST.
+This is the first IPv4 address of HOST, or its first IPv6 address,
+as `dns-resolve' finds them, if `network-stream-resolve-hosts' is
+non-nil and NOWAIT is nil.  Otherwise, or if HOST has no address,
+it is HOST, encoded with `puny-encode-domain'."
+  (let ((host (puny-encode-domain host)))
+    (or (and network-stream-resolve-hosts
+	     (not nowait)
+	     (fboundp 'dns-resolve)
+	     ;; Addresses need no lookup.
+	     (not (string-match-p "\\`[0-9.]+\\'\\|:" host))
+	     (ignore-errors
+	       (or (car (dns-resolve host 'A))
+		   (car (dns-resolve host 'AAAA)))))
+	host)))
+
+(defun network-stream-builtin-tls-p ()
+  "Return non-nil if Emacs can make TLS connections by itself."
+  (or (network-stream-use-rustls-p)
+      (gnutls-available-p)))
+
 ;;;###autoload
 (defun open-network-stream (name buffer host service &rest parameters)
   "Open a TCP connection to HOST, optionally with encryption.
@@ -127,6 +178,10 @@ values:
   certificate.  This parameter will only be used when doing TLS
   or STARTTLS connections.
 
+:alpn-protocols is a list of the names of the protocols to offer
+  the server with ALPN, like \"h2\".  It is only used when
+  `network-stream-tls-backend' is `rustls'.
+
 :use-starttls-if-possible is a boolean that says to do opportunistic
 STARTTLS upgrades even if Emacs doesn't have built-in TLS functionality.
 
@@ -159,7 +214,9 @@ gnutls-boot (as returned by `gnutls-boot-parameters')."
 				(plist-get parameters :capability-command))))))
 	;; The simplest case: wrapper around `make-network-process'.
 	(make-network-process :name name :buffer buffer
-			      :host (puny-encode-domain host) :service service
+			      :host (network-stream-host-address
+				     host (plist-get parameters :nowait))
+			      :service service
 			      :nowait (plist-get parameters :nowait)
                               :tls-parameters
                               (plist-get parameters :tls-parameters))
@@ -213,7 +270,8 @@ gnutls-boot (as returned by `gnutls-boot-parameters')."
 (defun network-stream-open-plain (name buffer host service parameters)
   (let ((start (with-current-buffer buffer (point)))
 	(stream (make-network-process :name name :buffer buffer
-				      :host (puny-encode-domain host)
+				      :host (network-stream-host-address
+					     host (plist-get parameters :nowait))
                                       :service service
 				      :nowait (plist-get parameters :nowait))))
     (when (plist-get parameters :warn-unless-encrypted)
@@ -235,7 +293,7 @@ gnutls-boot (as returned by `gnutls-boot-parameters')."
 				 eoc))
 	 ;; Return (STREAM GREETING CAPABILITIES RESULTING-TYPE)
 	 (stream (make-network-process :name name :buffer buffer
-				       :host (puny-encode-domain host)
+				       :host (network-stream-host-address host)
                                        :service service))
 	 (greeting (and (not (plist-get parameters :nogreeting))
 			(network-stream-get-response stream start eoc)))
@@ -252,14 +310,14 @@ gnutls-boot (as returned by `gnutls-boot-parameters')."
     ;; connection.
     (when (and starttls-command
 	       (setq starttls-available
-		     (or (gnutls-available-p)
+		     (or (network-stream-builtin-tls-p)
 			 (and (or require-tls
 				  (plist-get parameters :use-starttls-if-possible))
 			      (starttls-available-p))))
 	       (not (eq (plist-get parameters :type) 'plain)))
       ;; If using external STARTTLS, drop this connection and start
       ;; anew with `starttls-open-stream'.
-      (unless (gnutls-available-p)
+      (unless (network-stream-builtin-tls-p)
 	(delete-process stream)
 	(setq start (with-current-buffer buffer (point-max)))
 	(let* ((starttls-extra-arguments
@@ -292,20 +350,27 @@ gnutls-boot (as returned by `gnutls-boot-parameters')."
 		   (network-stream-command stream starttls-command eoc)))
 	      (and response (string-match success-string response)))
 	;; The server said it was OK to begin STARTTLS negotiations.
-	(if (gnutls-available-p)
-	    (let ((cert (network-stream-certificate host service parameters)))
-	      (condition-case nil
-		  (gnutls-negotiate :process stream :hostname host
-				    :keylist (and cert (list cert)))
-		;; If we get a gnutls-specific error (for instance if
-		;; the certificate the server gives us is completely
-		;; syntactically invalid), then close the connection
-		;; and possibly (further down) try to create a
-		;; non-encrypted connection.
-		(gnutls-error
-		 (delete-process stream))))
+	(cond
+	 ((network-stream-use-rustls-p)
+	  ;; As with GnuTLS, a failed negotiation leaves the
+	  ;; connection closed, to be reopened further down.
+	  (ignore-errors
+	    (network-stream-rustls-boot stream host service parameters)))
+	 ((gnutls-available-p)
+	  (let ((cert (network-stream-certificate host service parameters)))
+	    (condition-case nil
+		(gnutls-negotiate :process stream :hostname host
+				  :keylist (and cert (list cert)))
+	      ;; If we get a gnutls-specific error (for instance if
+	      ;; the certificate the server gives us is completely
+	      ;; syntactically invalid), then close the connection
+	      ;; and possibly (further down) try to create a
+	      ;; non-encrypted connection.
+	      (gnutls-error
+	       (delete-process stream)))))
+	 (t
 	  (unless (starttls-negotiate stream)
-	    (delete-process stream)))
+	    (delete-process stream))))
 	(if (memq (process-status stream) '(open run))
 	    (setq resulting-type 'tls)
 	  ;; We didn't successfully negotiate STARTTLS; if TLS
@@ -313,7 +378,7 @@ gnutls-boot (as returned by `gnutls-boot-parameters')."
 	  (unless require-tls
 	    (setq stream
 		  (make-network-process :name name :buffer buffer
-					:host (puny-encode-domain host)
+					:host (network-stream-host-address host)
                                         :service service))
 	    (network-stream-get-response stream start eoc)))
         (unless (process-live-p stream)
@@ -344,8 +409,10 @@ gnutls-boot (as returned by `gnutls-boot-parameters')."
 			"' program was found"))))
       (delete-process stream)
       (setq stream nil))
-    ;; Check certificate validity etc.
-    (when (gnutls-available-p)
+    ;; Check certificate validity etc.  Rustls has already verified
+    ;; the certificate, and `nsm-verify-connection' only knows GnuTLS.
+    (when (and (gnutls-available-p)
+	       (not (network-stream-use-rustls-p)))
       (setq stream (nsm-verify-connection
 		    stream host service
 		    (eq resulting-type 'tls)
@@ -376,21 +443,28 @@ gnutls-boot (as returned by `gnutls-boot-parameters')."
   (with-current-buffer buffer
     (let* ((start (point-max))
 	   (stream
-            (if (gnutls-available-p)
-                (open-gnutls-stream name buffer host service
-                                    (plist-get parameters :nowait))
-              (open-tls-stream name buffer host service)))
+            (cond
+             ((network-stream-use-rustls-p)
+              (network-stream-open-rustls name buffer host service
+                                          parameters))
+             ((gnutls-available-p)
+              (open-gnutls-stream name buffer host service
+                                  (plist-get parameters :nowait)))
+             (t
+              (open-tls-stream name buffer host service))))
 	   (eoc (plist-get parameters :end-of-command)))
       (if (plist-get parameters :nowait)
           (list stream nil nil 'tls)
         ;; Check certificate validity etc.
-        (when (and (gnutls-available-p) stream)
+        (when (and (gnutls-available-p)
+                   (not (network-stream-use-rustls-p))
+                   stream)
           (setq stream (nsm-verify-connection stream host service)))
         (if (null stream)
             (list nil nil nil 'plain)
           ;; If we're using tls.el, we have to delete the output from
           ;; openssl/gnutls-cli.
-          (when (and (not (gnutls-available-p))
+          (when (and (not (network-stream-builtin-tls-p))
                      eoc)
             (network-stream-get-response stream start eoc)
             (goto-char (point-min))
@@ -405,6 +479,29 @@ gnutls-boot (as returned by `gnutls-boot-parameters')."
                   (network-stream-command stream capability-command eo-capa)
                   'tls)))))))
 
+(defun network-stream-rustls-boot (stream host service parameters)
+  "Negotiate TLS with HOST on STREAM, with `rustls-boot'.
+SERVICE and PARAMETERS are as for `open-network-stream'.  If the
+negotiation fails, delete STREAM and signal the error."
+  (let ((cert (network-stream-certificate host service parameters)))
+    (condition-case err
+	(rustls-boot stream host
+		     (list :keylist (and cert (list cert))
+			   :alpn-protocols (plist-get parameters
+						      :alpn-protocols)))
+      (error
+       (delete-process stream)
+       (signal (car err) (cdr err))))))
+
+(defun network-stream-open-rustls (name buffer host service parameters)
+  "Open a TLS connection to HOST and SERVICE, made with rustls.
+The connection is always made synchronously."
+  (let ((stream (make-network-process :name name :buffer buffer
+				      :host (network-stream-host-address host)
+				      :service service)))
+    (network-stream-rustls-boot stream host service parameters)
+    stream))
+
 (defun network-stream-open-shell (name buffer host service parameters)
   (require 'format-spec)
   (let* ((capability-command (plist-get parameters :capability-command))
@@ -184,38 +184,6 @@ The final point after the last operation will be returned."
       (setcdr lines (cons (filter-buffer-substring pt (point) t) (cdr lines))))
     ))
 
-;; This is actually the only function that needs to do complicated
-;; stuff like what's happening in `operate-on-rectangle', because the
-;; buffer might be read-only.
-(defun extract-rectangle-line (startcol endcol lines)
-  (let (start end begextra endextra line)
-    (move-to-column startcol)
-    (setq start (point)
-	  begextra (- (current-column) startcol))
-    (move-to-column endcol)
-    (setq end (point)
-	  endextra (- endcol (current-column)))
-    (setq line (buffer-substring start (point)))
-    (if (< begextra 0)
-	(setq endextra (+ endextra begextra)
-	      begextra 0))
-    (if (< endextra 0)
-	(setq endextra 0))
-    (goto-char start)
-    (while (search-forward "\t" end t)
-      (let ((width (- (current-column)
-		      (save-excursion (forward-char -1)
-				      (current-column)))))
-	(setq line (concat (substring line 0 (- (point) end 1))
-			   (spaces-string width)
-			   (substring line (+ (length line)
-					      (- (point) end)))))))
-    (if (or (> begextra 0) (> endextra 0))
-	(setq line (concat (spaces-string begextra)
-			   line
-			   (spaces-string endextra))))
-    (setcdr lines (cons line (cdr lines)))))
-
 (defconst spaces-strings
   '["" " " "  " "   " "    " "     " "      " "       " "        "])
 
@@ -253,9 +221,8 @@ deleted."
 (defun extract-rectangle (start end)
   "Return the contents of the rectangle with corners at START and END.
 Return it as a list of strings, one for each line of the rectangle."
-  (let ((lines (list nil)))
-    (apply-on-rectangle 'extract-rectangle-line start end lines)
-    (nreverse (cdr lines))))
+  (let ((cols (save-excursion (rectangle--pos-cols start end))))
+    (extract-rectangle-columns start end (car cols) (cdr cols))))
 
 (defun extract-rectangle-bounds (start end)
   "Return the bounds of the rectangle with corners at START and END.
@@ -322,19 +289,8 @@ line is inserted at a point vertically under point, etc.
 RECTANGLE should be a list of strings.
 After this command, the mark is at the upper left corner
 and point is at the lower right corner."
-  (let ((lines rectangle)
-	(insertcolumn (current-column))
-	(first t))
-    (push-mark)
-    (while lines
-      (or first
-	  (progn
-	   (forward-line 1)
-	   (or (bolp) (insert ?\n))
-	   (move-to-column insertcolumn t)))
-      (setq first nil)
-      (insert-for-yank (car lines))
-      (setq lines (cdr lines)))))
+  (push-mark)
+  (insert-rectangle-lines rectangle (current-column)))
 
 ;;;###autoload
 (defun open-rectangle (start end &optional fill)
@@ -486,8 +442,9 @@ Called from a program, takes three args; START, END and STRING."
   ;; the first line to be changed by the following command).
   (unless (eq buffer-undo-list t)
     (push (point) buffer-undo-list))
-  (goto-char
-   (apply-on-rectangle 'string-rectangle-line start end string t)))
+  (let ((cols (save-excursion (rectangle--pos-cols start end))))
+    (goto-char
+     (string-rectangle-columns start end (car cols) (cdr cols) string t))))
 
 ;;;###autoload
 (defalias 'replace-rectangle 'string-rectangle)
@@ -508,7 +465,31 @@ This command does not delete or overwrite any existing text."
 				(or (car string-rectangle-history) ""))
 			nil 'string-rectangle-history
 			(car string-rectangle-history)))))
-  (apply-on-rectangle 'string-rectangle-line start end string nil))
+  (let ((cols (save-excursion (rectangle--pos-cols start end))))
+    (string-rectangle-columns start end (car cols) (cdr cols) string nil)))
+
+;;;###autoload
+(defun replace-rectangle-regexp (start end regexp replacement)
+  "Replace matches for REGEXP with REPLACEMENT in the region-rectangle.
+Each line of the rectangle is searched separately, and only matches
+between the columns of the rectangle are replaced.  REPLACEMENT may
+refer to the groups of REGEXP as in `replace-match'.  Return the
+number of replacements.
+
+When called from a program, the rectangle's corners are START and END."
+  (interactive
+   (progn (barf-if-buffer-read-only)
+	  (let* ((regexp (read-regexp "Replace regexp in rectangle"))
+		 (replacement
+		  (read-string (format "Replace regexp %s in rectangle with: "
+				       regexp))))
+	    (list (region-beginning) (region-end) regexp replacement))))
+  (let* ((cols (save-excursion (rectangle--pos-cols start end)))
+	 (count (replace-rectangle-regexp-columns
+		 start end (car cols) (cdr cols) regexp replacement)))
+    (when (called-interactively-p 'interactive)
+      (message "Replaced %d occurrence%s" count (if (= count 1) "" "s")))
+    count))
 
 ;;;###autoload
 (defun clear-rectangle (start end &optional fill)
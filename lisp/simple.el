@@ -1231,14 +1231,7 @@ If called from Lisp, return the number of words between START and
 END, without printing any message."
   (interactive (list nil nil))
   (cond ((not (called-interactively-p 'any))
-	 (let ((words 0))
-	   (save-excursion
-	     (save-restriction
-	       (narrow-to-region start end)
-	       (goto-char (point-min))
-	       (while (forward-word-strictly 1)
-		 (setq words (1+ words)))))
-	   words))
+	 (count-words-fast start end))
 	((use-region-p)
 	 (call-interactively 'count-words-region))
 	(t
@@ -3117,34 +3110,37 @@ This variable only matters if `undo-ask-before-discard' is non-nil.")
 
 ;; When the first undo batch in an undo list is longer than
 ;; undo-outer-limit, this function gets called to warn the user that
-;; the undo info for the current command was discarded.  Garbage
+;; the oldest undo info for the current command is being discarded.
+;; It returns nil to let `truncate_undo_list' trim the batch to the
+;; limit, or t if it dealt with the undo list itself.  Garbage
 ;; collection is inhibited around the call, so it had better not do a
 ;; lot of consing.
 (setq undo-outer-limit-function 'undo-outer-limit-truncate)
 (defun undo-outer-limit-truncate (size)
   (if undo-ask-before-discard
-      (when (or (null undo-extra-outer-limit)
-		(> size undo-extra-outer-limit))
-	;; Don't ask the question again unless it gets even bigger.
-	;; This applies, in particular, if the user quits from the question.
-	;; Such a quit quits out of GC, but something else will call GC
-	;; again momentarily.  It will call this function again,
-	;; but we don't want to ask the question again.
-	(setq undo-extra-outer-limit (+ size 50000))
-	(if (let (use-dialog-box track-mouse executing-kbd-macro )
-	      (yes-or-no-p (format-message
-                            "Buffer `%s' undo info is %d bytes long; discard it? "
-                            (buffer-name) size)))
-	    (progn (setq buffer-undo-list nil)
-		   (setq undo-extra-outer-limit nil)
-		   t)
-	  nil))
+      (progn
+	(when (or (null undo-extra-outer-limit)
+		  (> size undo-extra-outer-limit))
+	  ;; Don't ask the question again unless it gets even bigger.
+	  ;; This applies, in particular, if the user quits from the question.
+	  ;; Such a quit quits out of GC, but something else will call GC
+	  ;; again momentarily.  It will call this function again,
+	  ;; but we don't want to ask the question again.
+	  (setq undo-extra-outer-limit (+ size 50000))
+	  (when (let (use-dialog-box track-mouse executing-kbd-macro )
+		  (yes-or-no-p (format-message
+				"Buffer `%s' undo info is %d bytes long; discard it? "
+				(buffer-name) size)))
+	    (setq buffer-undo-list nil)
+	    (setq undo-extra-outer-limit nil)))
+	;; Keep the undo info unless the user chose to discard it.
+	t)
     (display-warning '(undo discard-info)
 		     (concat
 		      (format-message
                        "Buffer `%s' undo info was %d bytes long.\n"
                        (buffer-name) size)
-		      "The undo info was discarded because it exceeded \
+		      "The oldest undo info was discarded because it exceeded \
 `undo-outer-limit'.
 
 This is normal if you executed a command that made a huge change
@@ -3161,8 +3157,7 @@ You can disable the popping up of this buffer by adding the entry
 \(undo discard-info) to the user option `warning-suppress-types',
 which is defined in the `warnings' library.\n")
 		     :warning)
-    (setq buffer-undo-list nil)
-    t))
+    nil))
 
 (defcustom password-word-equivalents
   '("password" "passcode" "passphrase" "pass phrase"
@@ -4347,24 +4342,11 @@ text Emacs provided.")
 
 ;;;; The kill ring data structure.
 
-(defvar kill-ring nil
-  "List of killed text sequences.
-Since the kill ring is supposed to interact nicely with cut-and-paste
-facilities offered by window systems, use of this variable should
-interact nicely with `interprogram-cut-function' and
-`interprogram-paste-function'.  The functions `kill-new',
-`kill-append', and `current-kill' are supposed to implement this
-interaction; you may want to use them instead of manipulating the kill
-ring directly.")
-
 (defcustom kill-ring-max 60
   "Maximum length of kill ring before oldest elements are thrown away."
   :type 'integer
   :group 'killing)
 
-(defvar kill-ring-yank-pointer nil
-  "The tail of the kill ring whose car is the last thing yanked.")
-
 (defcustom save-interprogram-paste-before-kill nil
   "Save clipboard strings into kill ring before replacing them.
 When one selects something in another program to paste it into Emacs,
@@ -4378,11 +4360,38 @@ before the Emacs kill and one can still paste it using \\[yank] \\[yank-pop]."
 
 (defcustom kill-do-not-save-duplicates nil
   "Do not add a new string to `kill-ring' if it duplicates the last one.
-The comparison is done using `equal-including-properties'."
-  :type 'boolean
+The comparison is done using `equal-including-properties'.
+If the value is `all', also remove older duplicates of a new string
+from `kill-ring'."
+  :type '(choice (const :tag "Keep duplicates" nil)
+                 (const :tag "Do not repeat the last kill" t)
+                 (const :tag "Remove all older duplicates" all))
   :group 'killing
   :version "23.2")
 
+(defcustom kill-ring-file nil
+  "File in which to save the kill ring between sessions.
+If non-nil, `kill-ring' is written to this file when Emacs is killed,
+and read back from it when Emacs starts.  The file is created readable
+only by its owner."
+  :type '(choice (const :tag "Don't save the kill ring" nil) file)
+  :group 'killing
+  :version "27.1")
+
+(defun kill-ring-save-to-file ()
+  "Write `kill-ring' to `kill-ring-file', if that is non-nil."
+  (when kill-ring-file
+    (kill-ring-write-file kill-ring-file)))
+
+(defun kill-ring-restore-from-file ()
+  "Read `kill-ring' from `kill-ring-file', if that names a file."
+  (when (and kill-ring-file (file-readable-p kill-ring-file))
+    (with-demoted-errors "Error reading kill ring: %S"
+      (kill-ring-read-file kill-ring-file))))
+
+(add-hook 'kill-emacs-hook #'kill-ring-save-to-file)
+(add-hook 'after-init-hook #'kill-ring-restore-from-file)
+
 (defun kill-new (string &optional replace)
   "Make STRING the latest kill in the kill ring.
 Set `kill-ring-yank-pointer' to point to it.
@@ -4394,15 +4403,20 @@ When `save-interprogram-paste-before-kill' and `interprogram-paste-function'
 are non-nil, saves the interprogram paste string(s) into `kill-ring' before
 STRING.
 
+If `kill-ring-entry-max-bytes' is non-nil and STRING is larger, it is
+passed to `interprogram-cut-function' but not added to `kill-ring'.
+
 When the yank handler has a non-nil PARAM element, the original STRING
 argument is not used by `insert-for-yank'.  However, since Lisp code
 may access and use elements from the kill ring directly, the STRING
 argument should still be a \"useful\" string for such uses."
-  (unless (and kill-do-not-save-duplicates
-	       ;; Due to text properties such as 'yank-handler that
-	       ;; can alter the contents to yank, comparison using
-	       ;; `equal' is unsafe.
-	       (equal-including-properties string (car kill-ring)))
+  (unless (or (and kill-do-not-save-duplicates
+		   ;; Due to text properties such as 'yank-handler that
+		   ;; can alter the contents to yank, comparison using
+		   ;; `equal' is unsafe.
+		   (equal-including-properties string (car kill-ring)))
+	      (and kill-ring-entry-max-bytes
+		   (> (string-bytes string) kill-ring-entry-max-bytes)))
     (if (fboundp 'menu-bar-update-yank-menu)
 	(menu-bar-update-yank-menu string (and replace (car kill-ring)))))
   (when save-interprogram-paste-before-kill
@@ -4412,17 +4426,8 @@ argument should still be a \"useful\" string for such uses."
         (dolist (s (if (listp interprogram-paste)
 		       (nreverse interprogram-paste)
 		     (list interprogram-paste)))
-	  (unless (and kill-do-not-save-duplicates
-		       (equal-including-properties s (car kill-ring)))
-	    (push s kill-ring))))))
-  (unless (and kill-do-not-save-duplicates
-	       (equal-including-properties string (car kill-ring)))
-    (if (and replace kill-ring)
-	(setcar kill-ring string)
-      (push string kill-ring)
-      (if (> (length kill-ring) kill-ring-max)
-	  (setcdr (nthcdr (1- kill-ring-max) kill-ring) nil))))
-  (setq kill-ring-yank-pointer kill-ring)
+	  (kill-ring-push s)))))
+  (kill-ring-push string replace)
   (if interprogram-cut-function
       (funcall interprogram-cut-function string)))
 
@@ -4492,18 +4497,13 @@ move the yanking point; just return the Nth kill forward."
 	      (mapc 'kill-new (nreverse interprogram-paste))
 	      (kill-new interprogram-paste)))
 	  (car kill-ring))
-      (or kill-ring (error "Kill ring is empty"))
-      (let ((ARGth-kill-element
-	     (nthcdr (mod (- n (length kill-ring-yank-pointer))
-			  (length kill-ring))
-		     kill-ring)))
-	(unless do-not-move
-	  (setq kill-ring-yank-pointer ARGth-kill-element)
-	  (when (and yank-pop-change-selection
-		     (> n 0)
-		     interprogram-cut-function)
-	    (funcall interprogram-cut-function (car ARGth-kill-element))))
-	(car ARGth-kill-element)))))
+      (let ((ARGth-kill (kill-ring-rotate n do-not-move)))
+	(when (and (not do-not-move)
+		   yank-pop-change-selection
+		   (> n 0)
+		   interprogram-cut-function)
+	  (funcall interprogram-cut-function ARGth-kill))
+	ARGth-kill))))
 
 
 
@@ -644,6 +644,26 @@ The break position will be always after LINEBEG and generally before point."
     (indent-line-to (current-left-margin))
     (put-text-property beg (point) 'face 'default)))
 
+(defcustom fill-natively nil
+  "Non-nil means fill paragraphs of plain text with `fill-region-natively'.
+That function chooses where to break lines by the Unicode line
+breaking rules, which suit text in scripts such as Chinese and Japanese
+better, and is faster on large regions.  It is not used when
+`use-hard-newlines' or `fill-nobreak-predicate' is non-nil, or when
+lines are indented to a left margin."
+  :type 'boolean
+  :version "27.1")
+
+(defun fill--natively-p (nosqueeze squeeze-after)
+  "Return non-nil if the current paragraph can be filled natively.
+NOSQUEEZE and SQUEEZE-AFTER are as for `fill-region-as-paragraph'."
+  (and fill-natively
+       (not nosqueeze)
+       (not squeeze-after)
+       (not use-hard-newlines)
+       (not fill-nobreak-predicate)
+       (zerop (current-left-margin))))
+
 (defun fill-region-as-paragraph (from to &optional justify
 				      nosqueeze squeeze-after)
   "Fill the region as one paragraph.
@@ -726,15 +746,20 @@ space does not end a sentence, so don't break a line there."
       (goto-char from)
       (beginning-of-line)
 
-      (if (not justify)	  ; filling disabled: just check indentation
-	  (progn
-	    (goto-char from)
-	    (while (< (point) to)
-	      (if (and (not (eolp))
-		       (< (current-indentation) (current-left-margin)))
-		  (fill-indent-to-left-margin))
-	      (forward-line 1)))
+      (cond
+       ((not justify)	  ; filling disabled: just check indentation
+	(goto-char from)
+	(while (< (point) to)
+	  (if (and (not (eolp))
+		   (< (current-indentation) (current-left-margin)))
+	      (fill-indent-to-left-margin))
+	  (forward-line 1)))
+
+       ((fill--natively-p nosqueeze squeeze-after)
+	(let ((fill-column (current-fill-column)))
+	  (fill-region-natively from to justify)))
 
+       (t
 	(if use-hard-newlines
 	    (remove-list-of-text-properties from to '(hard)))
 	;; Make sure first line is indented (at least) to left margin...
@@ -779,7 +804,7 @@ space does not end a sentence, so don't break a line there."
 
 	      (goto-char to)
 	      ;; Justify this last line, if desired.
-	      (if justify (justify-current-line justify t t))))))
+	      (if justify (justify-current-line justify t t)))))))
       ;; Leave point after final newline.
       (goto-char to)
       (unless (eobp) (forward-char 1))
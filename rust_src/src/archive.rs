@@ -0,0 +1,293 @@
+//! Reading tar and zip archives.
+//!
+//! `archive-entries' lists the members of an archive, and
+//! `archive-insert-entry' inserts one of them into the current buffer,
+//! without running `tar' or `unzip'.  Tar archives may be compressed
+//! with gzip, xz or zstd.  They are read as a stream, only as far as the
+//! member asked for, and members are inserted a chunk at a time, so large
+//! archives need not fit in memory.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::mem;
+
+use libc::{c_char, time_t, timespec};
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    decompress::{create_stream_decoder, insert_stream_at_point, starts_compressed_stream},
+    fileio::encoded_file_path,
+    lisp::defsubr,
+    lisp::LispObject,
+    multibyte::LispStringRef,
+    remacs_sys::{decode_file_name, encode_file_name, make_unibyte_string, report_file_error},
+    remacs_sys::{EmacsInt, Fexpand_file_name, Qnil},
+    threads::ThreadState,
+    time::make_lisp_time,
+};
+
+/// The signatures zip archives start with: that of a local file header,
+/// or that of the end of the central directory, for an empty archive.
+const ZIP_MAGIC: [u8; 4] = [b'P', b'K', 3, 4];
+const EMPTY_ZIP_MAGIC: [u8; 4] = [b'P', b'K', 5, 6];
+
+#[cfg(windows)]
+extern "C" {
+    /// The `mktime' of the C runtime of MS-Windows for 64-bit times,
+    /// which the libc crate doesn't have.
+    fn _mktime64(tm: *mut libc::tm) -> i64;
+}
+
+/// The kinds of archives that can be read.
+enum ArchiveFormat {
+    Tar,
+    Zip,
+}
+
+/// A member of an archive, as `archive-entries' describes it.
+struct ArchiveEntry {
+    name: Vec<u8>,
+    size: u64,
+    mode: Option<u32>,
+    mtime: Option<time_t>,
+}
+
+impl ArchiveEntry {
+    fn to_lisp(&self) -> LispObject {
+        let name = unsafe {
+            decode_file_name(make_unibyte_string(
+                self.name.as_ptr() as *const c_char,
+                self.name.len() as isize,
+            ))
+        };
+        let mtime = self.mtime.map_or(Qnil, |tv_sec| {
+            make_lisp_time(timespec { tv_sec, tv_nsec: 0 })
+        });
+        list!(
+            name,
+            LispObject::from(self.size as EmacsInt),
+            self.mode
+                .map_or(Qnil, |mode| LispObject::from(EmacsInt::from(mode))),
+            mtime
+        )
+    }
+}
+
+/// Return the name of a directory member NAME, ending in a slash, as
+/// both tar and zip archives usually have them.
+fn directory_name(mut name: Vec<u8>) -> Vec<u8> {
+    if !name.ends_with(b"/") {
+        name.push(b'/');
+    }
+    name
+}
+
+/// Open the archive FILENAME, an absolute file name, and tell its format
+/// from its first bytes.
+fn open_archive(filename: LispObject) -> (File, ArchiveFormat) {
+    let encoded = unsafe { encode_file_name(filename) };
+    let mut file = match File::open(encoded_file_path(encoded)) {
+        Ok(file) => file,
+        Err(_) => unsafe {
+            report_file_error("Opening archive\0".as_ptr() as *const c_char, filename)
+        },
+    };
+
+    let mut magic = [0; 4];
+    let is_zip =
+        file.read_exact(&mut magic).is_ok() && (magic == ZIP_MAGIC || magic == EMPTY_ZIP_MAGIC);
+    if file.seek(SeekFrom::Start(0)).is_err() {
+        unsafe { report_file_error("Reading archive\0".as_ptr() as *const c_char, filename) }
+    }
+    let format = if is_zip {
+        ArchiveFormat::Zip
+    } else {
+        ArchiveFormat::Tar
+    };
+    (file, format)
+}
+
+/// Return a reader of the tar archive in FILE, decompressing it if it is
+/// compressed.
+fn tar_archive(file: File) -> io::Result<tar::Archive<Box<Read>>> {
+    let mut input = BufReader::new(file);
+    let compressed = starts_compressed_stream(input.fill_buf()?);
+    let reader: Box<Read> = if compressed {
+        create_stream_decoder(input)?
+    } else {
+        Box::new(input)
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+fn tar_entries(file: File) -> io::Result<Vec<ArchiveEntry>> {
+    let mut archive = tar_archive(file)?;
+    let mut entries = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let header = entry.header();
+        let name = entry.path_bytes().into_owned();
+        entries.push(ArchiveEntry {
+            name: if header.entry_type().is_dir() {
+                directory_name(name)
+            } else {
+                name
+            },
+            size: header.size()?,
+            mode: header.mode().ok(),
+            mtime: header.mtime().ok().map(|mtime| mtime as time_t),
+        });
+    }
+    Ok(entries)
+}
+
+fn zip_error(error: zip::result::ZipError) -> io::Error {
+    match error {
+        zip::result::ZipError::Io(error) => error,
+        error => io::Error::new(io::ErrorKind::InvalidData, error.to_string()),
+    }
+}
+
+/// Return the time ENTRY was last modified.  Zip archives record it as
+/// a DOS timestamp, which is local time.
+fn zip_mtime(entry: &zip::read::ZipFile) -> Option<time_t> {
+    let time = entry.last_modified();
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    tm.tm_year = time.tm_year;
+    tm.tm_mon = time.tm_mon;
+    tm.tm_mday = time.tm_mday;
+    tm.tm_hour = time.tm_hour;
+    tm.tm_min = time.tm_min;
+    tm.tm_sec = time.tm_sec;
+    tm.tm_isdst = -1;
+    match local_time(&mut tm) {
+        -1 => None,
+        mtime => Some(mtime as time_t),
+    }
+}
+
+/// Return the local time TM in seconds since the epoch, or -1 if it
+/// can't be represented.
+#[cfg(unix)]
+fn local_time(tm: &mut libc::tm) -> i64 {
+    i64::from(unsafe { libc::mktime(tm) })
+}
+
+#[cfg(windows)]
+fn local_time(tm: &mut libc::tm) -> i64 {
+    unsafe { _mktime64(tm) }
+}
+
+fn zip_entries(file: File) -> io::Result<Vec<ArchiveEntry>> {
+    let mut archive = zip::ZipArchive::new(file).map_err(zip_error)?;
+    let mut entries = Vec::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let entry = archive.by_index(i).map_err(zip_error)?;
+        // Zip archives already end the names of directories in a slash.
+        entries.push(ArchiveEntry {
+            name: entry.name_raw().to_vec(),
+            size: entry.size(),
+            mode: entry.unix_mode(),
+            mtime: zip_mtime(&entry),
+        });
+    }
+    Ok(entries)
+}
+
+/// Return a list of the members of the tar or zip archive FILENAME.
+/// Each element has the form (NAME SIZE MODES MTIME), where NAME is the
+/// name of the member, which ends in a slash if it is a directory, SIZE
+/// is its size in bytes, MODES are its file modes as `file-modes' returns
+/// them, or nil if the archive doesn't record them, and MTIME is the
+/// time it was last modified, in the style of `current-time', or nil.
+/// The members are in the order of the archive.
+///
+/// Tar archives compressed with gzip, xz or zstd are decompressed as
+/// they are read.  File name handlers are not called; FILENAME must be
+/// a local file.
+#[lisp_fn]
+pub fn archive_entries(filename: LispStringRef) -> LispObject {
+    let directory = ThreadState::current_buffer().directory_;
+    let absname = unsafe { Fexpand_file_name(filename.into(), directory) };
+    let (file, format) = open_archive(absname);
+    let entries = match format {
+        ArchiveFormat::Tar => tar_entries(file),
+        ArchiveFormat::Zip => zip_entries(file),
+    };
+    match entries {
+        Ok(entries) => entries
+            .iter()
+            .map(ArchiveEntry::to_lisp)
+            .collect::<Vec<LispObject>>()
+            .into(),
+        Err(error) => error!(
+            "Error reading archive {}: {}",
+            absname.as_string_or_error(),
+            error
+        ),
+    }
+}
+
+/// Insert the member of the tar archive in FILE named NAME at point.
+/// Return the number of characters inserted, or None if there is no
+/// such member.
+fn insert_tar_entry(file: File, name: &[u8]) -> io::Result<Option<isize>> {
+    let mut archive = tar_archive(file)?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path_bytes().as_ref() == name {
+            return insert_stream_at_point(&mut entry).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+fn insert_zip_entry(file: File, name: &[u8]) -> io::Result<Option<isize>> {
+    let mut archive = zip::ZipArchive::new(file).map_err(zip_error)?;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(zip_error)?;
+        if entry.name_raw() == name {
+            return insert_stream_at_point(&mut entry).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+/// Insert the member NAME of the tar or zip archive FILENAME at point.
+/// NAME is the name of the member as `archive-entries' has it.  The
+/// contents of the member are inserted as raw bytes, without decoding
+/// them, and point is left before them.  Return the number of
+/// characters inserted.  Signal an error if the archive has no member
+/// named NAME.
+///
+/// Only as much of a tar archive is read as it takes to find NAME, and
+/// the member is inserted a chunk at a time.  File name handlers are not
+/// called; FILENAME must be a local file.
+#[lisp_fn]
+pub fn archive_insert_entry(filename: LispStringRef, name: LispStringRef) -> EmacsInt {
+    let directory = ThreadState::current_buffer().directory_;
+    let absname = unsafe { Fexpand_file_name(filename.into(), directory) };
+    let encoded_name = unsafe { encode_file_name(name.into()) };
+    let member = encoded_name.as_string_or_error();
+    let (file, format) = open_archive(absname);
+    let inserted = match format {
+        ArchiveFormat::Tar => insert_tar_entry(file, member.as_slice()),
+        ArchiveFormat::Zip => insert_zip_entry(file, member.as_slice()),
+    };
+    match inserted {
+        Ok(Some(inserted)) => inserted as EmacsInt,
+        Ok(None) => error!(
+            "No member {} in archive {}",
+            name,
+            absname.as_string_or_error()
+        ),
+        Err(error) => error!(
+            "Error reading archive {}: {}",
+            absname.as_string_or_error(),
+            error
+        ),
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/archive_exports.rs"));
@@ -1,5 +1,5 @@
 //! Base64 de- and encoding functions.
-use std::ptr;
+use std::cmp::min;
 use std::slice;
 
 use libc::{c_char, c_uchar};
@@ -7,13 +7,50 @@ use remacs_macros::lisp_fn;
 
 use crate::{
     base64_crate,
+    buffers::{current_buffer_text, validate_region},
     lisp::defsubr,
     lisp::LispObject,
     multibyte::{multibyte_char_at, raw_byte_from_codepoint, LispStringRef, MAX_5_BYTE_CHAR},
-    remacs_sys::make_unibyte_string,
+    remacs_sys::EmacsInt,
+    remacs_sys::{
+        buf_charpos_to_bytepos, del_range_both, del_range_byte, insert, insert_1_both,
+        make_unibyte_string, set_point, set_point_both, signal_after_change,
+    },
     strings::MIME_LINE_LENGTH,
+    threads::ThreadState,
 };
 
+/// Return the configuration for encoding with line breaks if
+/// LINE_BREAK, with the URL and filename safe alphabet of RFC 4648 if
+/// URL, and with padding if PAD.
+fn encode_config(line_break: bool, url: bool, pad: bool) -> base64_crate::Config {
+    let charset = if url {
+        base64_crate::CharacterSet::UrlSafe
+    } else {
+        base64_crate::CharacterSet::Standard
+    };
+    let line_wrap = if line_break {
+        // As in base64_crate::MIME, but with LF instead of CRLF
+        base64_crate::LineWrap::Wrap(76, base64_crate::LineEnding::LF)
+    } else {
+        base64_crate::LineWrap::NoWrap
+    };
+
+    let strip_whitespace = true;
+
+    base64_crate::Config::new(charset, pad, strip_whitespace, line_wrap)
+}
+
+/// Return the configuration for decoding, with the URL and filename
+/// safe alphabet of RFC 4648 if URL.  Embedded newlines are allowed.
+fn decode_config(url: bool) -> base64_crate::Config {
+    if url {
+        encode_config(false, true, false)
+    } else {
+        base64_crate::MIME
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn base64_encode_1(
     from: *const c_char,
@@ -23,20 +60,27 @@ pub extern "C" fn base64_encode_1(
     line_break: bool,
     multibyte: bool,
 ) -> isize {
-    let config = if line_break {
-        // base64_crate::MIME, but with LF instead of CRLF
-        base64_crate::Config::new(
-            base64_crate::CharacterSet::Standard,
-            true, // pad
-            true, // strip whitespace
-            base64_crate::LineWrap::Wrap(76, base64_crate::LineEnding::LF),
-        )
-    } else {
-        base64_crate::STANDARD
-    };
     let bytes = unsafe { slice::from_raw_parts(from as *const u8, in_length as usize) };
-    let mut output = unsafe { slice::from_raw_parts_mut(to as *mut u8, out_length as usize) };
+    let output = unsafe { slice::from_raw_parts_mut(to as *mut u8, out_length as usize) };
+
+    encode_bytes(
+        bytes,
+        encode_config(line_break, false, true),
+        multibyte,
+        output,
+    )
+}
 
+/// Base64-encode BYTES into OUTPUT following CONFIG, and return the
+/// length of the encoded text.  If MULTIBYTE, BYTES are in multibyte
+/// form; return -1 if they contain characters that are neither Latin-1
+/// nor raw bytes.
+fn encode_bytes(
+    bytes: &[u8],
+    config: base64_crate::Config,
+    multibyte: bool,
+    mut output: &mut [u8],
+) -> isize {
     let encoded_size = if multibyte {
         // Transform non-ASCII characters in multibyte string to Latin1,
         // erroring out for non-Latin1 codepoints, and resolve raw 8-bit bytes.
@@ -77,17 +121,39 @@ pub unsafe extern "C" fn base64_decode_1(
     let encoded = slice::from_raw_parts(from as *const u8, length as usize);
     let decoded = slice::from_raw_parts_mut(to as *mut u8, out_length as usize);
 
-    // Use the MIME config to allow embedded newlines.
-    match base64_crate::decode_config_slice(encoded, base64_crate::MIME, decoded) {
+    let mut nchars = 0;
+    let decoded_length = decode_bytes(
+        encoded,
+        decode_config(false),
+        multibyte,
+        decoded,
+        &mut nchars,
+    );
+    if !nchars_return.is_null() {
+        *nchars_return = nchars;
+    }
+    decoded_length
+}
+
+/// Base64-decode ENCODED into DECODED following CONFIG, and return the
+/// length of the decoded text in bytes, or -1 if ENCODED is not valid
+/// base64 data.  If MULTIBYTE, the decoded result is in multibyte form.
+/// Store the number of produced characters in NCHARS.
+fn decode_bytes(
+    encoded: &[u8],
+    config: base64_crate::Config,
+    multibyte: bool,
+    decoded: &mut [u8],
+    nchars: &mut isize,
+) -> isize {
+    match base64_crate::decode_config_slice(encoded, config, decoded) {
         Ok(decoded_length) => {
-            if !nchars_return.is_null() {
-                *nchars_return = decoded_length as isize;
-            }
+            *nchars = decoded_length as isize;
             if multibyte {
                 // Decode non-ASCII bytes into UTF-8 pairs.
                 let s = encode_multibyte_string(&decoded[..decoded_length]);
                 let s_len = s.len();
-                ptr::copy_nonoverlapping(s.as_ptr(), to as *mut u8, s_len);
+                decoded[..s_len].copy_from_slice(&s);
                 s_len as isize
             } else {
                 decoded_length as isize
@@ -161,6 +227,35 @@ ZmFyLXJlYWNoaW5nIGNoYW5nZXMu"
     assert_eq!(expected.len(), length as usize);
 }
 
+#[test]
+fn test_url_base64_encode_bytes() {
+    let input = b"\xfb\xff\xbf";
+    let mut encoded = [0u8; 20];
+
+    let length = encode_bytes(input, encode_config(false, true, true), false, &mut encoded);
+    assert_eq!(b"-_-_", &encoded[..length as usize]);
+
+    let length = encode_bytes(
+        b"ab",
+        encode_config(false, true, false),
+        false,
+        &mut encoded,
+    );
+    assert_eq!(b"YWI", &encoded[..length as usize]);
+
+    let mut decoded = [0u8; 20];
+    let mut nchars = 0;
+    let length = decode_bytes(
+        b"-_-_",
+        decode_config(true),
+        false,
+        &mut decoded,
+        &mut nchars,
+    );
+    assert_eq!(input, &decoded[..length as usize]);
+    assert_eq!(nchars, 3);
+}
+
 #[no_mangle]
 pub extern "C" fn compute_decode_size(len: usize) -> usize {
     ((len + 3) / 4) * 3
@@ -389,57 +484,222 @@ bWFpbGluZyBsaXN0cywgc2VlIDxodHRwOi8vbGlzdHMuZ251Lm9yZy8+LgoK";
     );
 }
 
-/// Base64-encode STRING and return the result.
-/// Optional second argument NO-LINE-BREAK means do not break long lines
-/// into shorter lines.
-#[lisp_fn(min = "1")]
-pub fn base64_encode_string(mut string: LispStringRef, no_line_break: bool) -> LispObject {
+/// Base64-encode STRING following CONFIG.
+fn encode_string(string: LispStringRef, config: base64_crate::Config) -> LispObject {
     // We need to allocate enough room for the encoded text
     let length = string.len_bytes() as usize;
     let allength = pad_base64_size(compute_encode_size(length));
 
     // This function uses SAFE_ALLOCA in the c layer, however I cannot find an equivalent
     // for rust. Instead, we will use a Vec to store the temporary char buffer.
-    let mut buffer: Vec<c_char> = Vec::with_capacity(allength as usize);
-    let encoded = buffer.as_mut_ptr();
-    let encoded_length = base64_encode_1(
-        string.sdata_ptr(),
-        length,
-        encoded,
-        allength,
-        !no_line_break,
-        string.is_multibyte(),
-    );
+    let mut encoded = vec![0u8; allength];
+    let multibyte = string.is_multibyte();
+    let encoded_length = encode_bytes(string.as_slice(), config, multibyte, &mut encoded);
     if encoded_length < 0 {
         error!("Multibyte character in data for base64 encoding");
     }
 
-    unsafe { make_unibyte_string(encoded, encoded_length) }
+    unsafe { make_unibyte_string(encoded.as_ptr() as *const c_char, encoded_length) }
+}
+
+/// Base64-encode STRING and return the result.
+/// Optional second argument NO-LINE-BREAK means do not break long lines
+/// into shorter lines.
+#[lisp_fn(min = "1")]
+pub fn base64_encode_string(string: LispStringRef, no_line_break: bool) -> LispObject {
+    encode_string(string, encode_config(!no_line_break, false, true))
+}
+
+/// Base64url-encode STRING and return the result.
+/// Optional second argument NO-PAD means do not add padding char =.
+///
+/// This produces the URL variant of base 64 encoding defined in RFC 4648.
+#[lisp_fn(min = "1")]
+pub fn base64url_encode_string(string: LispStringRef, no_pad: bool) -> LispObject {
+    encode_string(string, encode_config(false, true, !no_pad))
 }
 
 /// Base64-decode STRING and return the result.
-#[lisp_fn]
-pub fn base64_decode_string(mut string: LispStringRef) -> LispObject {
+/// Optional argument BASE64URL determines whether to use the URL variant
+/// of the base 64 encoding, as defined in RFC 4648.
+#[lisp_fn(min = "1")]
+pub fn base64_decode_string(string: LispStringRef, base64url: bool) -> LispObject {
     let length = compute_decode_size(string.len_bytes() as usize);
-    let mut buffer: Vec<c_char> = Vec::with_capacity(length);
+    let mut decoded = vec![0u8; length];
 
-    let decoded = buffer.as_mut_ptr();
-    let decoded_length = unsafe {
-        base64_decode_1(
-            string.sdata_ptr(),
-            string.len_bytes() as usize,
-            decoded,
-            length,
-            false,
-            ptr::null_mut(),
-        )
+    let mut nchars = 0;
+    let decoded_length = decode_bytes(
+        string.as_slice(),
+        decode_config(base64url),
+        false,
+        &mut decoded,
+        &mut nchars,
+    );
+
+    if decoded_length < 0 {
+        error!("Invalid base64 data");
+    }
+    unsafe { make_unibyte_string(decoded.as_ptr() as *const c_char, decoded_length) }
+}
+
+/// Base64-encode the region between START and END following CONFIG.
+fn encode_region(
+    mut start: LispObject,
+    mut end: LispObject,
+    config: base64_crate::Config,
+) -> isize {
+    unsafe { validate_region(&mut start, &mut end) };
+
+    let mut current_buffer = ThreadState::current_buffer();
+
+    let beg = start.as_fixnum_or_error() as isize;
+    let end = end.as_fixnum_or_error() as isize;
+    let old_pos = current_buffer.pt;
+
+    let ibeg = unsafe { buf_charpos_to_bytepos(current_buffer.as_mut(), beg) };
+    let iend = unsafe { buf_charpos_to_bytepos(current_buffer.as_mut(), end) };
+
+    // We need to allocate enough room for encoding the text.
+    // We need 33 1/3% more space, plus a newline every 76
+    // characters, and then we round up.
+    let length = (iend - ibeg) as usize;
+    let allength = pad_base64_size(compute_encode_size(length));
+
+    let mut encoded = vec![0u8; allength];
+    let region = current_buffer_text(ibeg, iend);
+    let encoded_length = encode_bytes(
+        region,
+        config,
+        current_buffer.multibyte_characters_enabled(),
+        &mut encoded,
+    );
+
+    if encoded_length < 0 {
+        // The encoding wasn't possible.
+        error!("Multibyte character in data for base64 encoding");
+    }
+
+    // Now we have encoded the region, so we insert the new contents
+    // and delete the old.  (Insert first in order to preserve markers.)
+    unsafe {
+        set_point_both(beg, ibeg);
+        insert(encoded.as_ptr() as *const c_char, encoded_length);
+        del_range_byte(ibeg + encoded_length, iend + encoded_length);
+    }
+
+    // If point was outside of the region, restore it exactly; else just
+    // move to the beginning of the region.
+    let old_pos = if old_pos >= end {
+        old_pos + encoded_length - (end - beg)
+    } else if old_pos > beg {
+        beg
+    } else {
+        old_pos
     };
+    unsafe { set_point(old_pos) };
+
+    // We return the length of the encoded text.
+    encoded_length
+}
+
+/// Base64-encode the region between BEG and END.
+/// Return the length of the encoded text.
+/// Optional third argument NO-LINE-BREAK means do not break long lines
+/// into shorter lines.
+#[lisp_fn(min = "2", intspec = "r")]
+pub fn base64_encode_region(beg: LispObject, end: LispObject, no_line_break: bool) -> EmacsInt {
+    encode_region(beg, end, encode_config(!no_line_break, false, true)) as EmacsInt
+}
+
+/// Base64url-encode the region between BEG and END.
+/// Return the length of the encoded text.
+/// Optional third argument NO-PAD means do not add padding char =.
+///
+/// This produces the URL variant of base 64 encoding defined in RFC 4648.
+#[lisp_fn(min = "2", intspec = "r")]
+pub fn base64url_encode_region(beg: LispObject, end: LispObject, no_pad: bool) -> EmacsInt {
+    encode_region(beg, end, encode_config(false, true, !no_pad)) as EmacsInt
+}
+
+/// Base64-decode the region between BEG and END.
+/// Return the length of the decoded text.
+/// If the region can't be decoded, signal an error and don't modify the buffer.
+/// Optional third argument BASE64URL determines whether to use the URL variant
+/// of the base 64 encoding, as defined in RFC 4648.
+#[lisp_fn(min = "2", intspec = "r")]
+pub fn base64_decode_region(mut beg: LispObject, mut end: LispObject, base64url: bool) -> EmacsInt {
+    unsafe { validate_region(&mut beg, &mut end) };
+
+    let mut current_buffer = ThreadState::current_buffer();
+    let multibyte = current_buffer.multibyte_characters_enabled();
+
+    let beg = beg.as_fixnum_or_error() as isize;
+    let end = end.as_fixnum_or_error() as isize;
+    let old_pos = current_buffer.pt;
+
+    let ibeg = unsafe { buf_charpos_to_bytepos(current_buffer.as_mut(), beg) };
+    let iend = unsafe { buf_charpos_to_bytepos(current_buffer.as_mut(), end) };
+
+    let length = (iend - ibeg) as usize;
+
+    // We need to allocate enough room for decoding the text.  If we are
+    // working on a multibyte buffer, each decoded code may occupy at
+    // most two bytes.
+    let allength = compute_decode_size(if multibyte { length * 2 } else { length });
+    let mut decoded = vec![0u8; allength];
+
+    let region = current_buffer_text(ibeg, iend);
+
+    let mut inserted_chars = 0;
+    let decoded_length = decode_bytes(
+        region,
+        decode_config(base64url),
+        multibyte,
+        &mut decoded,
+        &mut inserted_chars,
+    );
 
     if decoded_length < 0 {
+        // The decoding wasn't possible.
         error!("Invalid base64 data");
     }
-    unsafe { buffer.set_len(decoded_length as usize) };
-    unsafe { make_unibyte_string(decoded, decoded_length) }
+
+    // Now we have decoded the region, so we insert the new contents
+    // and delete the old.  (Insert first in order to preserve markers.)
+    current_buffer.set_pt_both(beg, ibeg);
+    unsafe {
+        insert_1_both(
+            decoded.as_ptr() as *const c_char,
+            inserted_chars,
+            decoded_length,
+            false,
+            true,
+            false,
+        );
+        signal_after_change(beg, 0, inserted_chars);
+
+        // Delete the original text.
+        del_range_both(
+            current_buffer.pt,
+            current_buffer.pt_byte,
+            end + inserted_chars,
+            iend + decoded_length,
+            true,
+        );
+    }
+
+    // If point was outside of the region, restore it exactly; else just
+    // move to the beginning of the region.
+    let old_pos = if old_pos >= end {
+        old_pos + inserted_chars - (end - beg)
+    } else if old_pos > beg {
+        beg
+    } else {
+        old_pos
+    };
+    unsafe { set_point(min(old_pos, current_buffer.zv)) };
+
+    inserted_chars as EmacsInt
 }
 
 include!(concat!(env!("OUT_DIR"), "/base64_exports.rs"));
@@ -0,0 +1,181 @@
+//! Bidirectional text in strings.
+//!
+//! Strings are reordered with the Unicode Bidirectional Algorithm, as
+//! implemented by the `unicode-bidi' crate, so that Lisp code can lay
+//! out mixed left-to-right and right-to-left text which isn't
+//! displayed by the display engine, such as columns padded to a width.
+
+use std::ops::Range;
+
+use unicode_bidi::{BidiClass, BidiInfo, Level};
+use unicode_segmentation::UnicodeSegmentation;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    chars::unicode_chars,
+    fns::concat,
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::list,
+    multibyte::LispStringRef,
+    remacs_sys::{wrong_choice, EmacsInt, Fsubstring},
+    remacs_sys::{Qleft_to_right, Qnil, Qright_to_left},
+};
+
+/// A run of characters with the same embedding level, as offsets in
+/// characters.
+type VisualRun = (Range<usize>, Level);
+
+/// Return the paragraph level given by DIRECTION, which is nil for the
+/// level of the first strong character of each paragraph.
+fn paragraph_level(direction: LispObject) -> Option<Level> {
+    if direction.is_nil() {
+        None
+    } else if direction == Qleft_to_right {
+        Some(Level::ltr())
+    } else if direction == Qright_to_left {
+        Some(Level::rtl())
+    } else {
+        unsafe { wrong_choice(list!(Qnil, Qleft_to_right, Qright_to_left), direction) }
+    }
+}
+
+/// Return the runs of TEXT in visual order, with paragraphs in logical
+/// order.  Each paragraph has the level LEVEL, if given.
+fn visual_runs(text: &str, level: Option<Level>) -> Vec<VisualRun> {
+    // The offset in characters of each character boundary, by its byte
+    // offset.
+    let mut char_offsets = vec![0; text.len() + 1];
+    for (i, (byte, _)) in text.char_indices().enumerate() {
+        char_offsets[byte] = i;
+    }
+    char_offsets[text.len()] = text.chars().count();
+
+    let info = BidiInfo::new(text, level);
+    let mut runs = Vec::new();
+    for paragraph in &info.paragraphs {
+        // The separator which ends the paragraph stays at its end, in a
+        // run of its own.
+        let mut line = paragraph.range.clone();
+        let separator = text[line.clone()]
+            .chars()
+            .next_back()
+            .map(char::len_utf8)
+            .filter(|&len| info.original_classes[line.end - len] == BidiClass::B);
+        if let Some(len) = separator {
+            line.end -= len;
+        }
+        if line.start < line.end {
+            let (levels, level_runs) = info.visual_runs(paragraph, line.clone());
+            for run in level_runs {
+                let level = levels[run.start];
+                runs.push((char_offsets[run.start]..char_offsets[run.end], level));
+            }
+        }
+        if let Some(len) = separator {
+            let range = char_offsets[line.end]..char_offsets[line.end + len];
+            runs.push((range, paragraph.level));
+        }
+    }
+    runs
+}
+
+/// Return the directional runs of STRING, in the order they are displayed.
+/// Each element of the result is a list (START END LEVEL), where START
+/// and END are the indices in STRING of the characters of the run, and
+/// LEVEL is their embedding level according to the Unicode
+/// Bidirectional Algorithm: the characters of a run with an even level
+/// are displayed left to right, and those of a run with an odd level
+/// right to left.  The runs are in the visual order of each paragraph,
+/// from left to right, and the paragraphs, which are separated by
+/// newlines, are in order.  The newline at the end of a paragraph is a
+/// run of its own, which comes last.
+///
+/// DIRECTION is the base direction of the paragraphs: `left-to-right',
+/// `right-to-left', or nil, the default, for the direction of the first
+/// strong directional character of each paragraph, as with
+/// `bidi-paragraph-direction'.  Raw bytes and characters outside
+/// Unicode are neutral.
+#[lisp_fn(min = "1")]
+pub fn string_visual_runs(string: LispStringRef, direction: LispObject) -> LispObject {
+    let level = paragraph_level(direction);
+    let text = unicode_chars(string.as_slice(), string.is_multibyte());
+    let runs: Vec<LispObject> = visual_runs(&text, level)
+        .into_iter()
+        .map(|(range, level)| {
+            list!(
+                LispObject::from(range.start as EmacsInt),
+                LispObject::from(range.end as EmacsInt),
+                LispObject::from(EmacsInt::from(level.number()))
+            )
+        })
+        .collect();
+    list(&runs)
+}
+
+/// Return a copy of STRING with its characters in visual order.
+/// The characters of each right-to-left run of STRING, as returned by
+/// `string-visual-runs' with the argument DIRECTION, are reversed, and
+/// the runs are put in the order they are displayed, so that the
+/// result displayed left to right looks like STRING displayed with
+/// bidirectional reordering.  Combining marks and the other parts of a
+/// grapheme cluster are kept in order after their base character, but
+/// characters such as parentheses aren't mirrored.
+///
+/// Text properties are kept, so the result can be inserted where the
+/// display engine doesn't reorder text, or measured and padded piece
+/// by piece.
+#[lisp_fn(min = "1")]
+pub fn string_reorder_visual(string: LispStringRef, direction: LispObject) -> LispObject {
+    let level = paragraph_level(direction);
+    let text = unicode_chars(string.as_slice(), string.is_multibyte());
+    let substring = |range: Range<usize>| unsafe {
+        Fsubstring(
+            string.into(),
+            LispObject::from(range.start as EmacsInt),
+            LispObject::from(range.end as EmacsInt),
+        )
+    };
+
+    let mut pieces = Vec::new();
+    for (range, level) in visual_runs(&text, level) {
+        if level.is_ltr() {
+            pieces.push(substring(range));
+            continue;
+        }
+        let run: String = text.chars().skip(range.start).take(range.len()).collect();
+        let mut end = range.end;
+        for grapheme in run.graphemes(true).rev() {
+            let start = end - grapheme.chars().count();
+            pieces.push(substring(start..end));
+            end = start;
+        }
+    }
+    concat(&mut pieces)
+}
+
+#[test]
+fn test_visual_runs() {
+    let runs = |text: &str, level| {
+        visual_runs(text, level)
+            .into_iter()
+            .map(|(range, level)| (range.start, range.end, level.number()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(runs("", None), vec![]);
+    assert_eq!(runs("abc", None), vec![(0, 3, 0)]);
+    // "abc " followed by three Hebrew letters.
+    assert_eq!(
+        runs("abc \u{5d0}\u{5d1}\u{5d2}", None),
+        vec![(0, 4, 0), (4, 7, 1)]
+    );
+    assert_eq!(runs("\u{5d0}\u{5d1} abc", None), vec![(3, 6, 2), (0, 3, 1)]);
+    assert_eq!(runs("abc", Some(Level::rtl())), vec![(0, 3, 2)]);
+    assert_eq!(
+        runs("\u{5d0}\u{5d1}\nabc\n", None),
+        vec![(0, 2, 1), (2, 3, 1), (3, 6, 0), (6, 7, 0)]
+    );
+}
+
+include!(concat!(env!("OUT_DIR"), "/bidi_exports.rs"));
@@ -0,0 +1,420 @@
+//! CBOR and MessagePack parsing and serialization.
+//!
+//! Like YAML documents, binary data items are converted through the
+//! value representation of JSON, so the keyword arguments and the Lisp
+//! representation of the data are those of the JSON functions.  The
+//! serialized form is a unibyte string.  Byte strings, and map keys
+//! which aren't text strings, have no JSON equivalent and can't be
+//! parsed.
+
+use libc::{c_char, ptrdiff_t};
+use serde_json::{Map, Number, Value};
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    eval::{define_error, put},
+    json::{json_make_string, json_to_lisp, lisp_to_json, JsonConfig},
+    lisp::defsubr,
+    lisp::LispObject,
+    multibyte::LispStringRef,
+    remacs_sys::make_unibyte_string,
+    remacs_sys::{
+        Qcbor_error, Qcbor_parse_error, Qcbor_parse_string, Qcbor_serialize, Qcbor_value_p, Qerror,
+        Qmsgpack_error, Qmsgpack_parse_error, Qmsgpack_parse_string, Qmsgpack_serialize,
+        Qmsgpack_value_p, Qpure, Qside_effect_free, Qt,
+    },
+    strings::string_to_unibyte,
+};
+
+/// Return the bytes of STRING, which must be unibyte or contain only
+/// ASCII and raw-byte (`eight-bit') characters.
+fn string_bytes(string: LispStringRef) -> LispStringRef {
+    string_to_unibyte(string).as_string_or_error()
+}
+
+/// Create a unibyte Lisp string from BYTES.
+fn make_bytes(bytes: &[u8]) -> LispObject {
+    unsafe { make_unibyte_string(bytes.as_ptr() as *const c_char, bytes.len() as ptrdiff_t) }
+}
+
+/// Append the big-endian LEN-byte representation of N to OUT.
+fn put_be(out: &mut Vec<u8>, n: u64, len: usize) {
+    for i in (0..len).rev() {
+        out.push((n >> (8 * i)) as u8);
+    }
+}
+
+/// Append to OUT the MessagePack header of a string, array or map of
+/// LEN elements, too many for its fixed-size form: TAG16 is the tag of
+/// its form with a 16-bit length, which precedes that of its form with
+/// a 32-bit length.
+fn put_msgpack_len(out: &mut Vec<u8>, len: usize, tag16: u8) {
+    if len <= 0xffff {
+        out.push(tag16);
+        put_be(out, len as u64, 2);
+    } else {
+        out.push(tag16 + 1);
+        put_be(out, len as u64, 4);
+    }
+}
+
+/// Append the MessagePack representation of the string S to OUT.
+fn put_msgpack_str(out: &mut Vec<u8>, s: &str) {
+    match s.len() {
+        len @ 0..=31 => out.push(0xa0 | len as u8),
+        len @ 32..=0xff => {
+            out.push(0xd9);
+            put_be(out, len as u64, 1);
+        }
+        len => put_msgpack_len(out, len, 0xda),
+    }
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// Append the MessagePack representation of VALUE to OUT, or return
+/// false if it has none.
+fn put_msgpack(out: &mut Vec<u8>, value: &Value) -> bool {
+    match *value {
+        Value::Null => out.push(0xc0),
+        Value::Bool(b) => out.push(if b { 0xc3 } else { 0xc2 }),
+        Value::Number(ref n) => {
+            if let Some(u) = n.as_u64() {
+                match u {
+                    0..=0x7f => out.push(u as u8),
+                    0x80..=0xff => {
+                        out.push(0xcc);
+                        put_be(out, u, 1);
+                    }
+                    0x100..=0xffff => {
+                        out.push(0xcd);
+                        put_be(out, u, 2);
+                    }
+                    0x1_0000..=0xffff_ffff => {
+                        out.push(0xce);
+                        put_be(out, u, 4);
+                    }
+                    _ => {
+                        out.push(0xcf);
+                        put_be(out, u, 8);
+                    }
+                }
+            } else if let Some(i) = n.as_i64() {
+                match i {
+                    -32..=-1 => out.push(i as u8),
+                    -0x80..=-33 => {
+                        out.push(0xd0);
+                        put_be(out, i as u64, 1);
+                    }
+                    -0x8000..=-0x81 => {
+                        out.push(0xd1);
+                        put_be(out, i as u64, 2);
+                    }
+                    -0x8000_0000..=-0x8001 => {
+                        out.push(0xd2);
+                        put_be(out, i as u64, 4);
+                    }
+                    _ => {
+                        out.push(0xd3);
+                        put_be(out, i as u64, 8);
+                    }
+                }
+            } else {
+                match n.as_f64() {
+                    Some(f) => {
+                        out.push(0xcb);
+                        put_be(out, f.to_bits(), 8);
+                    }
+                    None => return false,
+                }
+            }
+        }
+        Value::String(ref s) => put_msgpack_str(out, s),
+        Value::Array(ref elements) => {
+            match elements.len() {
+                len @ 0..=15 => out.push(0x90 | len as u8),
+                len => put_msgpack_len(out, len, 0xdc),
+            }
+            for element in elements {
+                if !put_msgpack(out, element) {
+                    return false;
+                }
+            }
+        }
+        Value::Object(ref members) => {
+            match members.len() {
+                len @ 0..=15 => out.push(0x80 | len as u8),
+                len => put_msgpack_len(out, len, 0xde),
+            }
+            for (key, member) in members {
+                put_msgpack_str(out, key);
+                if !put_msgpack(out, member) {
+                    return false;
+                }
+            }
+        }
+    }
+    true
+}
+
+/// Remove the first LEN bytes of INPUT and return them.
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], String> {
+    if input.len() < len {
+        return Err("unexpected end of MessagePack data".to_string());
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    Ok(bytes)
+}
+
+/// Remove the first LEN bytes of INPUT and return their big-endian
+/// value.
+fn take_be(input: &mut &[u8], len: usize) -> Result<u64, String> {
+    Ok(take(input, len)?
+        .iter()
+        .fold(0, |n, &byte| n << 8 | u64::from(byte)))
+}
+
+/// Remove a MessagePack string of LEN bytes from INPUT and return it.
+fn take_msgpack_str(input: &mut &[u8], len: u64) -> Result<String, String> {
+    let bytes = take(input, len as usize)?;
+    String::from_utf8(bytes.to_vec()).map_err(|_| "invalid UTF-8 in MessagePack string".to_string())
+}
+
+/// How many levels MessagePack arrays and maps can be nested, so that
+/// parsing hostile data can't overflow the stack.
+const MSGPACK_MAX_DEPTH: usize = 128;
+
+/// Remove the first MessagePack object from INPUT and return its value.
+/// DEPTH is the number of arrays and maps the object is nested in.
+fn take_msgpack(input: &mut &[u8], depth: usize) -> Result<Value, String> {
+    let tag = take_be(input, 1)? as u8;
+    let float = |f: f64| {
+        Number::from_f64(f)
+            .map(Value::Number)
+            .ok_or_else(|| "non-finite MessagePack float".to_string())
+    };
+    match tag {
+        0x00..=0x7f => Ok(Value::from(tag)),
+        0x80..=0x8f => take_msgpack_map(input, u64::from(tag & 0x0f), depth),
+        0x90..=0x9f => take_msgpack_array(input, u64::from(tag & 0x0f), depth),
+        0xa0..=0xbf => take_msgpack_str(input, u64::from(tag & 0x1f)).map(Value::String),
+        0xc0 => Ok(Value::Null),
+        0xc2 => Ok(Value::Bool(false)),
+        0xc3 => Ok(Value::Bool(true)),
+        0xca => float(f64::from(f32::from_bits(take_be(input, 4)? as u32))),
+        0xcb => float(f64::from_bits(take_be(input, 8)?)),
+        0xcc => Ok(Value::from(take_be(input, 1)?)),
+        0xcd => Ok(Value::from(take_be(input, 2)?)),
+        0xce => Ok(Value::from(take_be(input, 4)?)),
+        0xcf => Ok(Value::from(take_be(input, 8)?)),
+        0xd0 => Ok(Value::from(take_be(input, 1)? as i8)),
+        0xd1 => Ok(Value::from(take_be(input, 2)? as i16)),
+        0xd2 => Ok(Value::from(take_be(input, 4)? as i32)),
+        0xd3 => Ok(Value::from(take_be(input, 8)? as i64)),
+        0xd9 => {
+            let len = take_be(input, 1)?;
+            take_msgpack_str(input, len).map(Value::String)
+        }
+        0xda => {
+            let len = take_be(input, 2)?;
+            take_msgpack_str(input, len).map(Value::String)
+        }
+        0xdb => {
+            let len = take_be(input, 4)?;
+            take_msgpack_str(input, len).map(Value::String)
+        }
+        0xdc => {
+            let len = take_be(input, 2)?;
+            take_msgpack_array(input, len, depth)
+        }
+        0xdd => {
+            let len = take_be(input, 4)?;
+            take_msgpack_array(input, len, depth)
+        }
+        0xde => {
+            let len = take_be(input, 2)?;
+            take_msgpack_map(input, len, depth)
+        }
+        0xdf => {
+            let len = take_be(input, 4)?;
+            take_msgpack_map(input, len, depth)
+        }
+        0xe0..=0xff => Ok(Value::from(tag as i8)),
+        0xc4..=0xc6 => Err("MessagePack binary data has no Lisp representation".to_string()),
+        0xc7..=0xc9 | 0xd4..=0xd8 => {
+            Err("MessagePack extension types have no Lisp representation".to_string())
+        }
+        _ => Err(format!("invalid MessagePack tag 0x{:02x}", tag)),
+    }
+}
+
+/// Return an error if an array or map nested in DEPTH others is too
+/// deep.
+fn check_msgpack_depth(depth: usize) -> Result<(), String> {
+    if depth >= MSGPACK_MAX_DEPTH {
+        Err("MessagePack data nested too deeply".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// Remove LEN MessagePack objects from INPUT and return their array,
+/// which is nested in DEPTH arrays and maps.
+fn take_msgpack_array(input: &mut &[u8], len: u64, depth: usize) -> Result<Value, String> {
+    check_msgpack_depth(depth)?;
+    // Each element takes at least a byte, so don't trust LEN further.
+    let mut elements = Vec::with_capacity(len.min(input.len() as u64) as usize);
+    for _ in 0..len {
+        elements.push(take_msgpack(input, depth + 1)?);
+    }
+    Ok(Value::Array(elements))
+}
+
+/// Remove LEN MessagePack key/value pairs from INPUT and return their
+/// map, which is nested in DEPTH arrays and maps.
+fn take_msgpack_map(input: &mut &[u8], len: u64, depth: usize) -> Result<Value, String> {
+    check_msgpack_depth(depth)?;
+    let mut members = Map::new();
+    for _ in 0..len {
+        let key = match take_msgpack(input, depth + 1)? {
+            Value::String(key) => key,
+            _ => return Err("MessagePack map key is not a string".to_string()),
+        };
+        let member = take_msgpack(input, depth + 1)?;
+        members.insert(key, member);
+    }
+    Ok(Value::Object(members))
+}
+
+/// Return the CBOR representation of OBJECT as a unibyte string.
+///
+/// OBJECT can be any value accepted by `json-serialize', or a single
+/// scalar: the Lisp equivalents to the null and false values, t, a
+/// number or a string.  Vectors become CBOR arrays, whereas
+/// hashtables, alists and plists become CBOR maps with text string
+/// keys.
+///
+/// The arguments ARGS are a list of keyword/argument pairs, as for
+/// `json-serialize'.
+/// usage: (cbor-serialize OBJECT &rest ARGS)
+#[lisp_fn(min = "1")]
+pub fn cbor_serialize(args: &mut [LispObject]) -> LispObject {
+    let config = JsonConfig::from_args(&args[1..], false);
+    let value = lisp_to_json(args[0], &config);
+    match serde_cbor::to_vec(&value) {
+        Ok(cbor) => make_bytes(&cbor),
+        Err(_) => wrong_type!(Qcbor_value_p, args[0]),
+    }
+}
+
+/// Parse the CBOR data item in STRING into a Lisp object.
+/// This is essentially the reverse operation of `cbor-serialize', which
+/// see.  STRING must be unibyte, or contain only ASCII and raw-byte
+/// characters, and hold exactly one data item.  Arrays are returned as
+/// vectors and maps as hashtables, alists, or plists.  If STRING isn't
+/// a valid data item, or contains one with no Lisp representation, an
+/// error of type `cbor-parse-error' is signaled, whose data is the
+/// message reported by the parser.
+///
+/// The arguments ARGS are a list of keyword/argument pairs, as for
+/// `json-parse-string', including `:object-type'.
+/// usage: (cbor-parse-string STRING &rest ARGS)
+#[lisp_fn(min = "1")]
+pub fn cbor_parse_string(args: &mut [LispObject]) -> LispObject {
+    let bytes = string_bytes(args[0].as_string_or_error());
+    let config = JsonConfig::from_args(&args[1..], true);
+
+    let value: Value = match serde_cbor::from_slice(bytes.as_slice()) {
+        Ok(value) => value,
+        Err(error) => xsignal!(Qcbor_parse_error, json_make_string(&error.to_string())),
+    };
+    json_to_lisp(value, &config)
+}
+
+/// Return the MessagePack representation of OBJECT as a unibyte string.
+///
+/// OBJECT can be any value accepted by `cbor-serialize', which see.
+/// Vectors become MessagePack arrays, whereas hashtables, alists and
+/// plists become maps with string keys.
+///
+/// The arguments ARGS are a list of keyword/argument pairs, as for
+/// `json-serialize'.
+/// usage: (msgpack-serialize OBJECT &rest ARGS)
+#[lisp_fn(min = "1")]
+pub fn msgpack_serialize(args: &mut [LispObject]) -> LispObject {
+    let config = JsonConfig::from_args(&args[1..], false);
+    let value = lisp_to_json(args[0], &config);
+    let mut msgpack = Vec::new();
+    if !put_msgpack(&mut msgpack, &value) {
+        wrong_type!(Qmsgpack_value_p, args[0]);
+    }
+    make_bytes(&msgpack)
+}
+
+/// Parse the MessagePack object in STRING into a Lisp object.
+/// This is essentially the reverse operation of `msgpack-serialize',
+/// which see.  STRING must be unibyte, or contain only ASCII and
+/// raw-byte characters, and hold exactly one object.  Arrays are
+/// returned as vectors and maps as hashtables, alists, or plists, and
+/// can be nested at most 128 levels deep.  If STRING isn't a valid
+/// object, or contains one with no Lisp representation, an error of
+/// type `msgpack-parse-error' is signaled, whose data is the message
+/// reported by the parser.
+///
+/// The arguments ARGS are a list of keyword/argument pairs, as for
+/// `json-parse-string', including `:object-type'.
+/// usage: (msgpack-parse-string STRING &rest ARGS)
+#[lisp_fn(min = "1")]
+pub fn msgpack_parse_string(args: &mut [LispObject]) -> LispObject {
+    let bytes = string_bytes(args[0].as_string_or_error());
+    let config = JsonConfig::from_args(&args[1..], true);
+
+    let mut input = bytes.as_slice();
+    let value = match take_msgpack(&mut input, 0) {
+        Ok(value) => value,
+        Err(message) => xsignal!(Qmsgpack_parse_error, json_make_string(&message)),
+    };
+    if !input.is_empty() {
+        xsignal!(
+            Qmsgpack_parse_error,
+            json_make_string("trailing data after MessagePack object")
+        );
+    }
+    json_to_lisp(value, &config)
+}
+
+#[no_mangle]
+pub extern "C" fn syms_of_binary_serialization() {
+    def_lisp_sym!(Qcbor_value_p, "cbor-value-p");
+    def_lisp_sym!(Qmsgpack_value_p, "msgpack-value-p");
+
+    def_lisp_sym!(Qcbor_error, "cbor-error");
+    def_lisp_sym!(Qcbor_parse_error, "cbor-parse-error");
+    define_error(Qcbor_error, "generic CBOR error", Qerror);
+    define_error(Qcbor_parse_error, "could not parse CBOR data", Qcbor_error);
+
+    def_lisp_sym!(Qmsgpack_error, "msgpack-error");
+    def_lisp_sym!(Qmsgpack_parse_error, "msgpack-parse-error");
+    define_error(Qmsgpack_error, "generic MessagePack error", Qerror);
+    define_error(
+        Qmsgpack_parse_error,
+        "could not parse MessagePack data",
+        Qmsgpack_error,
+    );
+
+    def_lisp_sym!(Qcbor_serialize, "cbor-serialize");
+    def_lisp_sym!(Qcbor_parse_string, "cbor-parse-string");
+    def_lisp_sym!(Qmsgpack_serialize, "msgpack-serialize");
+    def_lisp_sym!(Qmsgpack_parse_string, "msgpack-parse-string");
+    put(Qcbor_serialize.into(), Qpure, Qt);
+    put(Qcbor_serialize.into(), Qside_effect_free, Qt);
+    put(Qcbor_parse_string.into(), Qpure, Qt);
+    put(Qcbor_parse_string.into(), Qside_effect_free, Qt);
+    put(Qmsgpack_serialize.into(), Qpure, Qt);
+    put(Qmsgpack_serialize.into(), Qside_effect_free, Qt);
+    put(Qmsgpack_parse_string.into(), Qpure, Qt);
+    put(Qmsgpack_parse_string.into(), Qside_effect_free, Qt);
+}
+
+include!(concat!(env!("OUT_DIR"), "/binary_serialization_exports.rs"));
@@ -0,0 +1,258 @@
+//! Bitsets and Bloom filters.
+//!
+//! Both are records around a bool-vector, which keeps their bits
+//! compact, one to a bit, and needs nothing special from the garbage
+//! collector or the dumper.  A bitset grows its bool-vector as larger
+//! numbers are added to it; a Bloom filter's is sized for the number
+//! of elements it was made for.
+
+use std::collections::hash_map::DefaultHasher;
+use std::f64::consts::LN_2;
+use std::hash::Hasher;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    alloc::make_bool_vector,
+    data::{aref, aset},
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::list,
+    remacs_sys::{sxhash, EmacsInt, Frecord, BITS_PER_BITS_WORD},
+    remacs_sys::{Qbitset, Qbitset_p, Qbloom_filter, Qbloom_filter_p, Qbool_vector_p, Qnil},
+    vectors::{recordp, LispBoolVecRef},
+};
+
+const BITS_PER_WORD: usize = BITS_PER_BITS_WORD as usize;
+
+/// Return true if bit INDEX of BITS is set.
+fn bit(bits: LispBoolVecRef, index: usize) -> bool {
+    bits.as_slice()[index / BITS_PER_WORD] & (1 << (index % BITS_PER_WORD)) != 0
+}
+
+/// Return the number of words which hold the bits of BITS.
+fn words(bits: LispBoolVecRef) -> usize {
+    (bits.len() + BITS_PER_WORD - 1) / BITS_PER_WORD
+}
+
+/// Return t if OBJECT is a bitset.
+#[lisp_fn]
+pub fn bitset_p(object: LispObject) -> bool {
+    recordp(object) && aref(object, 0) == Qbitset
+}
+
+/// Return the bits of the bitset BITSET.
+fn bitset_bits(bitset: LispObject) -> LispBoolVecRef {
+    if !bitset_p(bitset) {
+        wrong_type!(Qbitset_p, bitset);
+    }
+    let bits = aref(bitset, 1);
+    match bits.as_bool_vector() {
+        Some(bits) => bits,
+        None => wrong_type!(Qbool_vector_p, bits),
+    }
+}
+
+/// Create and return a new empty bitset, a set of natural numbers kept
+/// as one bit for each number up to the largest in the set.
+/// SIZE is the number of numbers to make room for at first; the
+/// bitset grows as larger numbers are added to it.
+#[lisp_fn(min = "0")]
+pub fn make_bitset(size: LispObject) -> LispObject {
+    let size = if size.is_nil() {
+        64
+    } else {
+        size.as_natnum_or_error() as EmacsInt
+    };
+    callN_raw!(Frecord, Qbitset, make_bool_vector(size, false))
+}
+
+/// Add the natural number N to the bitset BITSET.
+/// Return t if N was not already in BITSET, nil if it was.
+#[lisp_fn]
+pub fn bitset_add(bitset: LispObject, n: LispObject) -> bool {
+    let mut bits = bitset_bits(bitset);
+    let index = n.as_natnum_or_error() as usize;
+    if index >= bits.len() {
+        let size = (index + 1).max(bits.len() * 2);
+        let mut new_bits = make_bool_vector(size as EmacsInt, false)
+            .as_bool_vector()
+            .unwrap();
+        let old_words = words(bits);
+        new_bits.as_mut_slice()[..old_words].copy_from_slice(&bits.as_slice()[..old_words]);
+        aset(bitset, 1, new_bits.into());
+        bits = new_bits;
+    }
+    if bit(bits, index) {
+        false
+    } else {
+        bits.set(index, true);
+        true
+    }
+}
+
+/// Remove the natural number N from the bitset BITSET.
+/// Return t if N was in BITSET, nil if it wasn't.
+#[lisp_fn]
+pub fn bitset_remove(bitset: LispObject, n: LispObject) -> bool {
+    let mut bits = bitset_bits(bitset);
+    let index = n.as_natnum_or_error() as usize;
+    if index < bits.len() && bit(bits, index) {
+        bits.set(index, false);
+        true
+    } else {
+        false
+    }
+}
+
+/// Return t if the natural number N is in the bitset BITSET.
+#[lisp_fn]
+pub fn bitset_member_p(bitset: LispObject, n: LispObject) -> bool {
+    let bits = bitset_bits(bitset);
+    let index = n.as_natnum_or_error() as usize;
+    index < bits.len() && bit(bits, index)
+}
+
+/// Return the number of numbers in the bitset BITSET.
+#[lisp_fn]
+pub fn bitset_count(bitset: LispObject) -> EmacsInt {
+    let bits = bitset_bits(bitset);
+    bits.as_slice()[..words(bits)]
+        .iter()
+        .map(|word| word.count_ones() as EmacsInt)
+        .sum()
+}
+
+/// Return a list of the numbers in the bitset BITSET, in increasing
+/// order.
+#[lisp_fn]
+pub fn bitset_to_list(bitset: LispObject) -> LispObject {
+    let bits = bitset_bits(bitset);
+    let mut numbers = Vec::new();
+    for (i, &word) in bits.as_slice()[..words(bits)].iter().enumerate() {
+        let mut word = word;
+        while word != 0 {
+            let offset = word.trailing_zeros() as usize;
+            numbers.push(LispObject::from(i * BITS_PER_WORD + offset));
+            word &= word - 1;
+        }
+    }
+    list(&numbers)
+}
+
+/// Remove all the numbers from the bitset BITSET, and return BITSET.
+#[lisp_fn]
+pub fn bitset_clear(bitset: LispObject) -> LispObject {
+    let mut bits = bitset_bits(bitset);
+    let words = words(bits);
+    for word in &mut bits.as_mut_slice()[..words] {
+        *word = 0;
+    }
+    bitset
+}
+
+/// Return t if OBJECT is a Bloom filter.
+#[lisp_fn]
+pub fn bloom_filter_p(object: LispObject) -> bool {
+    recordp(object) && aref(object, 0) == Qbloom_filter
+}
+
+/// Return the bits and the number of hash functions of the Bloom filter
+/// FILTER.
+fn bloom_filter_parts(filter: LispObject) -> (LispBoolVecRef, EmacsInt) {
+    if !bloom_filter_p(filter) {
+        wrong_type!(Qbloom_filter_p, filter);
+    }
+    let bits = aref(filter, 1);
+    match bits.as_bool_vector() {
+        Some(bits) => (bits, aref(filter, 2).as_fixnum_or_error()),
+        None => wrong_type!(Qbool_vector_p, bits),
+    }
+}
+
+/// Return the bits of a Bloom filter with SIZE bits and HASHES hash
+/// functions which OBJECT sets.  The hash functions are made from two
+/// hashes of OBJECT, by double hashing.
+fn bloom_bits(object: LispObject, size: usize, hashes: EmacsInt) -> Vec<usize> {
+    let mut hasher = DefaultHasher::new();
+    hasher.write_u64(unsafe { sxhash(object, 0) });
+    let h1 = hasher.finish();
+    hasher.write_u8(1);
+    // An odd step visits every bit when SIZE is a power of two.
+    let h2 = hasher.finish() | 1;
+    (0..hashes as u64)
+        .map(|i| (h1.wrapping_add(i.wrapping_mul(h2)) % size as u64) as usize)
+        .collect()
+}
+
+/// Create and return a new Bloom filter, a set which can say that an
+/// object is certainly not in it, or is probably in it, in a fixed
+/// and small amount of memory.
+///
+/// CAPACITY is the number of objects the filter is meant to hold.
+/// ERROR-RATE is the rate of false positives, the chance that
+/// `bloom-member-p' is non-nil for an object not in the filter once it
+/// holds CAPACITY objects; it defaults to 0.01.  The filter uses about
+/// 10 bits for each object at that rate.
+///
+/// Objects are hashed as by `sxhash-equal', so objects which are
+/// `equal' are the same object to the filter.
+#[lisp_fn(min = "1")]
+pub fn make_bloom_filter(capacity: EmacsInt, error_rate: LispObject) -> LispObject {
+    if capacity <= 0 {
+        args_out_of_range!(LispObject::from(capacity), Qnil);
+    }
+    let rate = if error_rate.is_nil() {
+        0.01
+    } else {
+        error_rate.any_to_float_or_error()
+    };
+    if !(rate > 0.0 && rate < 1.0) {
+        args_out_of_range!(error_rate, Qnil);
+    }
+    let capacity = capacity as f64;
+    let size = (-capacity * rate.ln() / (LN_2 * LN_2)).ceil().max(1.0);
+    let hashes = (size / capacity * LN_2).round().max(1.0);
+    callN_raw!(
+        Frecord,
+        Qbloom_filter,
+        make_bool_vector(size as EmacsInt, false),
+        LispObject::from(hashes as EmacsInt)
+    )
+}
+
+/// Add OBJECT to the Bloom filter FILTER.
+/// Return t if OBJECT was certainly not in FILTER before, nil if it
+/// may have been.
+#[lisp_fn]
+pub fn bloom_add(filter: LispObject, object: LispObject) -> bool {
+    let (mut bits, hashes) = bloom_filter_parts(filter);
+    let mut added = false;
+    for index in bloom_bits(object, bits.len(), hashes) {
+        if !bit(bits, index) {
+            bits.set(index, true);
+            added = true;
+        }
+    }
+    added
+}
+
+/// Return t if OBJECT may be in the Bloom filter FILTER, nil if it
+/// certainly isn't.
+#[lisp_fn]
+pub fn bloom_member_p(filter: LispObject, object: LispObject) -> bool {
+    let (bits, hashes) = bloom_filter_parts(filter);
+    bloom_bits(object, bits.len(), hashes)
+        .into_iter()
+        .all(|index| bit(bits, index))
+}
+
+#[no_mangle]
+pub extern "C" fn syms_of_bitset() {
+    def_lisp_sym!(Qbitset, "bitset");
+    def_lisp_sym!(Qbitset_p, "bitset-p");
+    def_lisp_sym!(Qbloom_filter, "bloom-filter");
+    def_lisp_sym!(Qbloom_filter_p, "bloom-filter-p");
+}
+
+include!(concat!(env!("OUT_DIR"), "/bitset_exports.rs"));
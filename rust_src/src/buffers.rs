@@ -1,7 +1,9 @@
 //! Functions operating on buffers.
 
+use std::borrow::Cow;
+use std::cmp::{max, min};
 use std::sync::Mutex;
-use std::{self, mem, ptr};
+use std::{self, mem, ptr, slice};
 
 use libc::{self, c_char, c_int, c_uchar, c_void, ptrdiff_t};
 
@@ -12,7 +14,7 @@ use remacs_macros::lisp_fn;
 use crate::{
     character::char_head_p,
     chartable::LispCharTableRef,
-    data::Lisp_Fwd,
+    data::{set, Lisp_Fwd},
     editfns::point,
     eval::unbind_to,
     frames::LispFrameRef,
@@ -24,23 +26,27 @@ use crate::{
     multibyte::LispStringRef,
     multibyte::{multibyte_length_by_head, string_char},
     numbers::MOST_POSITIVE_FIXNUM,
+    obarray::intern,
     remacs_sys::{
-        allocate_misc, bset_update_mode_line, buffer_local_flags, buffer_local_value,
-        buffer_window_count, concat2, del_range, delete_all_overlays, globals, internal_equal,
-        last_per_buffer_idx, lookup_char_property, marker_position, modify_overlay,
-        set_buffer_internal_1, specbind, unchain_both, unchain_marker, update_mode_lines,
+        allocate_buffer, allocate_misc, bset_update_mode_line, buf_bytepos_to_charpos,
+        buf_charpos_to_bytepos, buffer_local_flags, buffer_local_value, buffer_window_count,
+        clone_per_buffer_values, concat2, del_range, delete_all_overlays, globals, internal_equal,
+        invalidate_current_column, last_per_buffer_idx, lookup_char_property, marker_position,
+        modify_overlay, move_gap_both, nconc2, reset_buffer, reset_buffer_local_variables,
+        set_buffer_internal_1, set_point, specbind, unchain_both, unchain_marker,
+        update_mode_lines,
     },
     remacs_sys::{
         equal_kind, pvec_type, EmacsInt, Lisp_Buffer, Lisp_Buffer_Local_Value, Lisp_Misc_Type,
-        Lisp_Overlay, Lisp_Type, Vbuffer_alist,
+        Lisp_Overlay, Lisp_Type, Vbuffer_alist, Vrun_hooks,
     },
     remacs_sys::{
         windows_or_buffers_changed, Fcopy_sequence, Fexpand_file_name, Ffind_file_name_handler,
-        Fget_text_property, Fnconc, Fnreverse, Fwiden,
+        Fget_text_property, Fmake_marker, Fnconc, Fnreverse, Fwiden,
     },
     remacs_sys::{
-        Qafter_string, Qbefore_string, Qbuffer_read_only, Qbufferp, Qget_file_buffer,
-        Qinhibit_quit, Qinhibit_read_only, Qnil, Qoverlayp, Qt, Qunbound,
+        Qafter_string, Qbefore_string, Qbuffer_list_update_hook, Qbuffer_read_only, Qbufferp,
+        Qget_file_buffer, Qinhibit_quit, Qinhibit_read_only, Qnil, Qoverlayp, Qt, Qunbound,
     },
     strings::string_equal,
     threads::{c_specpdl_index, ThreadState},
@@ -63,6 +69,25 @@ pub fn point_min_byte() -> EmacsInt {
     ThreadState::current_buffer().begv_byte as EmacsInt
 }
 
+/// Return the bytes of the current buffer between byte positions START
+/// and END as a single slice, moving the gap to END if it is between
+/// them.
+pub fn current_buffer_text<'a>(start: ptrdiff_t, end: ptrdiff_t) -> &'a [u8] {
+    let mut buffer = ThreadState::current_buffer();
+    if start < buffer.gpt_byte() && buffer.gpt_byte() < end {
+        unsafe {
+            let charpos = buf_bytepos_to_charpos(buffer.as_mut(), end);
+            move_gap_both(charpos, end);
+        }
+    }
+    let (before_gap, after_gap) = buffer.region_slices(start, end);
+    if before_gap.is_empty() {
+        after_gap
+    } else {
+        before_gap
+    }
+}
+
 /// Maximum number of bytes in a buffer.
 /// A buffer cannot contain more bytes than a 1-origin fixnum can
 /// represent, nor can it be so large that C pointer arithmetic stops
@@ -99,6 +124,28 @@ pub const BUF_BYTES_MAX: ptrdiff_t = buf_bytes_max();
 pub type LispBufferRef = ExternalPtr<Lisp_Buffer>;
 pub type LispOverlayRef = ExternalPtr<Lisp_Overlay>;
 
+/// An iterator over the bytes of a region of a buffer, which yields the
+/// part before the gap and then the part after it, skipping either if
+/// it is empty.
+pub struct ByteChunks<'a> {
+    before_gap: &'a [u8],
+    after_gap: &'a [u8],
+}
+
+impl<'a> Iterator for ByteChunks<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        if !self.before_gap.is_empty() {
+            Some(mem::replace(&mut self.before_gap, &[]))
+        } else if !self.after_gap.is_empty() {
+            Some(mem::replace(&mut self.after_gap, &[]))
+        } else {
+            None
+        }
+    }
+}
+
 impl LispBufferRef {
     pub fn is_read_only(self) -> bool {
         self.read_only_.into()
@@ -236,6 +283,54 @@ impl LispBufferRef {
         unsafe { *(self.beg_addr().offset(byte_pos - BEG_BYTE + gap)) as c_uchar }
     }
 
+    /// Return the bytes between byte positions START and END as the
+    /// parts before and after the gap, either of which may be empty.
+    pub fn region_slices<'a>(self, start: ptrdiff_t, end: ptrdiff_t) -> (&'a [u8], &'a [u8]) {
+        let gpt = self.gpt_byte();
+        let before_gap: &[u8] = if start < gpt {
+            unsafe {
+                slice::from_raw_parts(self.byte_pos_addr(start), (min(end, gpt) - start) as usize)
+            }
+        } else {
+            &[]
+        };
+        let after_gap: &[u8] = if end > gpt {
+            let from = max(start, gpt);
+            unsafe {
+                slice::from_raw_parts(
+                    self.gap_end_addr().offset(from - gpt),
+                    (end - from) as usize,
+                )
+            }
+        } else {
+            &[]
+        };
+        (before_gap, after_gap)
+    }
+
+    /// Return the bytes between byte positions START and END.  They are
+    /// borrowed from the buffer text unless they straddle the gap, and
+    /// only copied then; borrowed bytes are only valid until the text
+    /// changes or the gap moves, so nothing which might run Lisp code may
+    /// happen while they are held.
+    pub fn text_between<'a>(self, start: ptrdiff_t, end: ptrdiff_t) -> Cow<'a, [u8]> {
+        match self.region_slices(start, end) {
+            (before_gap, after_gap) if after_gap.is_empty() => Cow::Borrowed(before_gap),
+            (before_gap, after_gap) if before_gap.is_empty() => Cow::Borrowed(after_gap),
+            (before_gap, after_gap) => Cow::Owned([before_gap, after_gap].concat()),
+        }
+    }
+
+    /// Return an iterator over the bytes between byte positions START and
+    /// END, as the slices on either side of the gap which aren't empty.
+    pub fn byte_chunks<'a>(self, start: ptrdiff_t, end: ptrdiff_t) -> ByteChunks<'a> {
+        let (before_gap, after_gap) = self.region_slices(start, end);
+        ByteChunks {
+            before_gap,
+            after_gap,
+        }
+    }
+
     /// Return the byte at byte position N.
     pub fn fetch_byte(self, n: ptrdiff_t) -> u8 {
         let offset = if n >= self.gpt_byte() {
@@ -377,6 +472,35 @@ impl LispBufferRef {
         }
     }
 
+    /// Return true if the accessible portion of the buffer is smaller
+    /// than its text.
+    pub fn is_narrowed(self) -> bool {
+        self.begv != self.beg() || self.zv != self.z()
+    }
+
+    /// Restrict the accessible portion of the buffer to the text between
+    /// the character positions START and END, which must be within the
+    /// buffer's text.  Point isn't moved into the new restriction.
+    pub fn narrow(&mut self, start: ptrdiff_t, end: ptrdiff_t) {
+        debug_assert!(self.beg() <= start && start <= end && end <= self.z());
+        if self.begv != start || self.zv != end {
+            self.set_clip_changed(true);
+        }
+        let start_byte = unsafe { buf_charpos_to_bytepos(self.as_mut(), start) };
+        let end_byte = unsafe { buf_charpos_to_bytepos(self.as_mut(), end) };
+        self.set_begv_both(start, start_byte);
+        self.set_zv_both(end, end_byte);
+    }
+
+    /// Make the whole text of the buffer accessible.
+    pub fn widen(&mut self) {
+        if self.is_narrowed() {
+            self.set_clip_changed(true);
+        }
+        self.set_begv_both(self.beg(), self.beg_byte());
+        self.set_zv_both(self.z(), self.z_byte());
+    }
+
     #[allow(clippy::cast_ptr_alignment)]
     pub unsafe fn set_value(&mut self, offset: usize, value: LispObject) {
         let buffer_bytes = self.as_mut() as *mut c_char;
@@ -944,6 +1068,122 @@ pub fn buffer_base_buffer(buffer: LispBufferOrCurrent) -> Option<LispBufferRef>
     buf.base_buffer()
 }
 
+/// Give BUFFER markers for its point and narrowing, which keep them up to
+/// date while the text is changed through other buffers sharing it.
+fn set_narrowing_markers(mut buffer: LispBufferRef) {
+    unsafe {
+        buffer.pt_marker_ = build_marker(buffer.as_mut(), buffer.pt, buffer.pt_byte);
+        buffer.begv_marker_ = build_marker(buffer.as_mut(), buffer.begv, buffer.begv_byte);
+        buffer.zv_marker_ = build_marker(buffer.as_mut(), buffer.zv, buffer.zv_byte);
+    }
+    buffer
+        .zv_marker_
+        .as_marker_or_error()
+        .set_insertion_type(true);
+}
+
+/// Create and return an indirect buffer for buffer BASE-BUFFER, named NAME.
+/// BASE-BUFFER should be a live buffer, or the name of an existing buffer.
+/// NAME should be a string which is not the name of an existing buffer.
+/// Optional argument CLONE non-nil means preserve BASE-BUFFER's state,
+/// such as major and minor modes, in the indirect buffer.
+/// CLONE nil means the indirect buffer's state is reset to default values.
+#[lisp_fn(
+    min = "2",
+    intspec = "bMake indirect buffer (to buffer): \nBName of indirect buffer: "
+)]
+pub fn make_indirect_buffer(
+    base_buffer: LispObject,
+    name: LispStringRef,
+    clone: bool,
+) -> LispBufferRef {
+    if get_buffer(LispBufferOrName::Name(name.into())).is_some() {
+        error!("Buffer name `{}' is in use", name);
+    }
+    let base = match get_buffer(LispBufferOrName::from(base_buffer)) {
+        Some(base) => base,
+        None => error!("No such buffer: `{}'", base_buffer.as_string_or_error()),
+    };
+    if !base.is_live() {
+        error!("Base buffer has been killed");
+    }
+    if name.len_chars() == 0 {
+        error!("Empty string for buffer name is not allowed");
+    }
+
+    // No double indirection: if the base buffer is indirect, the new
+    // buffer becomes an indirect buffer of its base.
+    let mut base = base.base_buffer().unwrap_or(base);
+    let mut buffer = LispBufferRef::new(unsafe { allocate_buffer() });
+    buffer.base_buffer = base.as_mut();
+
+    // Use the base buffer's text, which it now shares.
+    buffer.text = base.text;
+    buffer.indirections = -1;
+    base.indirections += 1;
+    buffer.window_count = -1;
+
+    buffer.set_pt_both(base.pt, base.pt_byte);
+    buffer.set_begv_both(base.begv, base.begv_byte);
+    buffer.set_zv_both(base.zv, base.zv_byte);
+
+    buffer.newline_cache = ptr::null_mut();
+    buffer.width_run_cache = ptr::null_mut();
+    buffer.bidi_paragraph_cache = ptr::null_mut();
+    buffer.width_table_ = Qnil;
+
+    let mut name = unsafe { Fcopy_sequence(name.into()) }.as_string_or_error();
+    unsafe { name.u.s.intervals = ptr::null_mut() };
+    buffer.name_ = name.into();
+
+    // An indirect buffer shares the undo list of its base (Bug#18180).
+    buffer.undo_list_ = base.undo_list_;
+
+    unsafe {
+        reset_buffer(buffer.as_mut());
+        reset_buffer_local_variables(buffer.as_mut(), true);
+    }
+
+    // Put this in the alist of all live buffers.
+    let buf = LispObject::from(buffer);
+    unsafe { Vbuffer_alist = nconc2(Vbuffer_alist, list!(LispObject::cons(name, buf))) };
+
+    buffer.mark_ = unsafe { Fmake_marker() };
+
+    // The multibyte status belongs to the base buffer.
+    buffer.enable_multibyte_characters_ = base.enable_multibyte_characters_;
+
+    // Make sure the base buffer has markers for its narrowing.
+    if base.pt_marker().is_nil() {
+        debug_assert!(base.begv_marker().is_nil() && base.zv_marker().is_nil());
+        set_narrowing_markers(base);
+    }
+
+    if clone {
+        let mut old_buffer = ThreadState::current_buffer();
+        unsafe { clone_per_buffer_values(base.as_mut(), buffer.as_mut()) };
+        buffer.filename_ = Qnil;
+        buffer.file_truename_ = Qnil;
+        buffer.display_count_ = LispObject::from(0);
+        buffer.backed_up_ = Qnil;
+        buffer.auto_save_file_name_ = Qnil;
+        unsafe { set_buffer_internal_1(buffer.as_mut()) };
+        set(intern("buffer-save-without-query"), Qnil);
+        set(intern("buffer-file-number"), Qnil);
+        set(intern("buffer-stale-function"), Qnil);
+        unsafe { set_buffer_internal_1(old_buffer.as_mut()) };
+    } else {
+        // Give the indirect buffer markers for its narrowing.
+        set_narrowing_markers(buffer);
+    }
+
+    if unsafe { Vrun_hooks }.is_not_nil() {
+        call!(unsafe { Vrun_hooks }, Qbuffer_list_update_hook);
+    }
+
+    buffer
+}
+
 /// Force redisplay of the current buffer's mode line and header line.
 /// With optional non-nil ALL, force redisplay of all mode lines and
 /// header lines.  This function also forces recomputation of the
@@ -1064,6 +1304,51 @@ pub fn erase_buffer() {
     }
 }
 
+/// Restrict editing in this buffer to the current region.
+/// The rest of the text becomes temporarily invisible and untouchable
+/// but is not deleted; if you save the buffer in a file, the invisible
+/// text is included in the file.  \\[widen] makes all visible again.
+/// See also `save-restriction'.
+///
+/// When calling from a program, pass two arguments; positions (integers
+/// or markers) bounding the text that should remain visible.
+#[lisp_fn(intspec = "r")]
+pub fn narrow_to_region(start: LispObject, end: LispObject) {
+    let mut start = start.as_number_coerce_marker_or_error().to_fixnum() as ptrdiff_t;
+    let mut end = end.as_number_coerce_marker_or_error().to_fixnum() as ptrdiff_t;
+    if start > end {
+        mem::swap(&mut start, &mut end);
+    }
+
+    let mut buffer = ThreadState::current_buffer();
+    if !(buffer.beg() <= start && end <= buffer.z()) {
+        args_out_of_range!(
+            LispObject::from(start as EmacsInt),
+            LispObject::from(end as EmacsInt)
+        );
+    }
+
+    buffer.narrow(start, end);
+    if buffer.pt < start {
+        unsafe { set_point(start) };
+    } else if buffer.pt > end {
+        unsafe { set_point(end) };
+    }
+
+    // Changing the buffer bounds invalidates any recorded current column.
+    unsafe { invalidate_current_column() };
+}
+
+/// Remove restrictions (narrowing) from current buffer.
+/// This allows the buffer's full text to be seen and edited.
+#[lisp_fn(intspec = "")]
+pub fn widen() {
+    ThreadState::current_buffer().widen();
+
+    // Changing the buffer bounds invalidates any recorded current column.
+    unsafe { invalidate_current_column() };
+}
+
 // We split this away from generate-new-buffer, because rename-buffer
 // and set-visited-file-name ought to be able to use this to really
 // rename the buffer properly.
@@ -1176,4 +1461,19 @@ pub extern "C" fn rust_syms_of_buffer() {
     defvar_per_buffer!(header_line_format_, "header-line-format", Qnil);
 }
 
+#[test]
+fn test_byte_chunks() {
+    let chunks = |before_gap: &'static [u8], after_gap: &'static [u8]| {
+        ByteChunks {
+            before_gap,
+            after_gap,
+        }
+        .collect::<Vec<&[u8]>>()
+    };
+    assert_eq!(chunks(b"ab", b"cd"), vec![&b"ab"[..], &b"cd"[..]]);
+    assert_eq!(chunks(b"", b"cd"), vec![&b"cd"[..]]);
+    assert_eq!(chunks(b"ab", b""), vec![&b"ab"[..]]);
+    assert!(chunks(b"", b"").is_empty());
+}
+
 include!(concat!(env!("OUT_DIR"), "/buffers_exports.rs"));
@@ -1,23 +1,363 @@
 //! Case conversion functions.
 use std::ffi::CString;
+use std::slice;
+
+use libc::{c_char, c_int, ptrdiff_t};
 
 use remacs_macros::lisp_fn;
 
 use crate::{
+    buffers::validate_region,
     keymap::Ctl,
     lisp::defsubr,
     lisp::LispObject,
     lists::put,
     lists::{LispConsCircularChecks, LispConsEndChecks},
+    multibyte::{char_byte8_p, char_to_byte8, multibyte_char_at, raw_byte_codepoint},
+    multibyte::{write_codepoint, Codepoint, MAX_MULTIBYTE_LENGTH},
     obarray::intern,
     remacs_sys::EmacsInt,
-    remacs_sys::{case_action, casify_object, casify_region},
+    remacs_sys::{buf_charpos_to_bytepos, downcase, globals, make_buffer_string, modify_text},
+    remacs_sys::{case_action, casify_object, casify_region, set_case_table, syntaxcode},
     remacs_sys::{control_x_map, initial_define_key, meta_map, scan_words, set_point},
-    remacs_sys::{Qdisabled, Qt},
+    remacs_sys::{record_delete, record_insert, replace_range_2, syntax_property, upcase},
+    remacs_sys::{signal_after_change, update_compositions, CHECK_ALL},
+    remacs_sys::{Qdisabled, Qnil, Qt},
     symbols::symbol_value,
     threads::ThreadState,
 };
 
+const GREEK_CAPITAL_LETTER_SIGMA: Codepoint = 0x03A3;
+const GREEK_SMALL_LETTER_FINAL_SIGMA: Codepoint = 0x03C2;
+
+/// The result of converting the case of a character: another character,
+/// or a string for the special casing rules which turn a character into
+/// several, such as ß into SS.
+enum CasedChar {
+    Char(Codepoint),
+    Special(String),
+}
+
+/// The conversion of characters to upper or lower case done by
+/// `upcase-region' and `downcase-region'.
+struct CaseConverter {
+    up: bool,
+    /// Whether `case-conversion-locale' asks for the Turkic rules.
+    turkic: bool,
+    /// The conversions of the ASCII characters which the case table
+    /// maps to ASCII characters, and to which no special rule applies.
+    ascii: [Option<u8>; 128],
+}
+
+impl CaseConverter {
+    /// Return a converter to upper case if UP, and to lower case
+    /// otherwise, by the case table of the current buffer.
+    fn new(up: bool) -> Self {
+        // If the case table is flagged as modified, rescan it.
+        let table = ThreadState::current_buffer().downcase_table_;
+        let extras = unsafe { table.as_char_table_or_error().extras.as_slice(3) };
+        if extras[1].is_nil() {
+            unsafe { set_case_table(table, false) };
+        }
+
+        let mut converter = CaseConverter {
+            up,
+            turkic: turkic_case_conversion(),
+            ascii: [None; 128],
+        };
+        for byte in 0..128u8 {
+            if converter.turkic && (byte == b'i' || byte == b'I') {
+                continue;
+            }
+            let cased = converter.convert_by_table(Codepoint::from(byte));
+            if cased < 0x80 {
+                converter.ascii[byte as usize] = Some(cased as u8);
+            }
+        }
+        converter
+    }
+
+    /// Return the conversion of C by the case table.
+    fn convert_by_table(&self, c: Codepoint) -> Codepoint {
+        let cased = unsafe {
+            if self.up {
+                upcase(c as c_int)
+            } else {
+                downcase(c as c_int)
+            }
+        };
+        cased as Codepoint
+    }
+
+    /// Return the conversion of C, or None if it doesn't change.  If C
+    /// is a capital sigma, FINAL_SIGMA says whether it ends a word, and
+    /// so has a final form in lower case.
+    fn convert(&self, c: Codepoint, final_sigma: impl FnOnce() -> bool) -> Option<CasedChar> {
+        if self.turkic {
+            match (self.up, c) {
+                (true, 0x69) => return Some(CasedChar::Char(0x130)),
+                (false, 0x49) => return Some(CasedChar::Char(0x131)),
+                (false, 0x130) => return Some(CasedChar::Char(0x69)),
+                _ => (),
+            }
+        }
+
+        // The special casing rules take precedence over the case table.
+        if let Some(ch) = std::char::from_u32(c) {
+            let special = if self.up {
+                ch.to_uppercase()
+                    .nth(1)
+                    .map(|_| ch.to_uppercase().collect())
+            } else {
+                ch.to_lowercase()
+                    .nth(1)
+                    .map(|_| ch.to_lowercase().collect())
+            };
+            if let Some(special) = special {
+                return Some(CasedChar::Special(special));
+            }
+        }
+
+        let cased = self.convert_by_table(c);
+        if cased == c {
+            None
+        } else if !self.up && c == GREEK_CAPITAL_LETTER_SIGMA && final_sigma() {
+            Some(CasedChar::Char(GREEK_SMALL_LETTER_FINAL_SIGMA))
+        } else {
+            Some(CasedChar::Char(cased))
+        }
+    }
+}
+
+/// Return true if `case-conversion-locale' names Turkish or Azerbaijani.
+fn turkic_case_conversion() -> bool {
+    let locale = unsafe { globals.Vcase_conversion_locale };
+    locale.as_string().map_or(false, |locale| {
+        let language = locale
+            .as_slice()
+            .split(|&b| b == b'_' || b == b'-' || b == b'.')
+            .next()
+            .unwrap_or(&[]);
+        language == b"tr" || language == b"az"
+    })
+}
+
+/// Return true if C has word syntax.
+fn word_char_p(c: Codepoint) -> bool {
+    unsafe { syntax_property(c as c_int, true) == syntaxcode::Sword }
+}
+
+/// Extend the range of changed positions CHANGED to cover FROM to TO.
+fn extend_changed(changed: &mut Option<(ptrdiff_t, ptrdiff_t)>, from: ptrdiff_t, to: ptrdiff_t) {
+    *changed = Some((changed.map_or(from, |(first, _)| first), to));
+}
+
+/// Return the address of the byte at POS_BYTE in the current buffer.
+fn buffer_byte_addr(pos_byte: ptrdiff_t) -> *mut u8 {
+    let buffer = ThreadState::current_buffer();
+    unsafe {
+        buffer
+            .byte_pos_addr(pos_byte)
+            .offset(buffer.pos_within_range(pos_byte))
+    }
+}
+
+/// Convert the case of the text of the current buffer, which is
+/// unibyte, between START and END with CONVERTER.  Return the first and
+/// last positions changed, if any.
+fn casify_unibyte_region(
+    converter: &CaseConverter,
+    start: ptrdiff_t,
+    end: ptrdiff_t,
+) -> Option<(ptrdiff_t, ptrdiff_t)> {
+    let mut changed = None;
+    // Character and byte positions are the same in a unibyte buffer.
+    for pos in start..end {
+        let addr = buffer_byte_addr(pos);
+        let byte = unsafe { *addr };
+        let cased = if byte < 0x80 {
+            converter.convert_by_table(Codepoint::from(byte))
+        } else {
+            converter.convert_by_table(raw_byte_codepoint(byte))
+        };
+        // Characters which can't be converted to a byte are left alone.
+        let cased = if char_byte8_p(cased) {
+            char_to_byte8(cased)
+        } else if cased < 0x80 {
+            cased as u8
+        } else {
+            continue;
+        };
+        if cased != byte {
+            unsafe { *addr = cased };
+            extend_changed(&mut changed, pos, pos + 1);
+        }
+    }
+    changed
+}
+
+/// Convert the case of the text of the current buffer, which is
+/// multibyte, between START and END with CONVERTER.  Return the first
+/// and last positions changed, if any, and the number of characters
+/// added, which is negative if characters were removed.
+fn casify_multibyte_region(
+    converter: &CaseConverter,
+    start: ptrdiff_t,
+    mut end: ptrdiff_t,
+) -> (Option<(ptrdiff_t, ptrdiff_t)>, ptrdiff_t) {
+    let mut buffer = ThreadState::current_buffer();
+    let mut changed = None;
+    let mut added = 0;
+    let mut opoint = buffer.pt;
+    let mut pos = start;
+    let mut pos_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), start) };
+    let mut end_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), end) };
+    let mut previous = None;
+
+    while pos < end {
+        // The bytes from POS_BYTE to the gap or the end of the region.
+        let segment_end = if pos_byte < buffer.gpt_byte() {
+            buffer.gpt_byte().min(end_byte)
+        } else {
+            end_byte
+        };
+        let segment = unsafe {
+            slice::from_raw_parts_mut(
+                buffer_byte_addr(pos_byte),
+                (segment_end - pos_byte) as usize,
+            )
+        };
+
+        // Convert the ASCII characters at the start of the segment in
+        // place, without decoding them.
+        let mut ascii = 0;
+        while ascii < segment.len() && segment[ascii] < 0x80 {
+            let byte = segment[ascii];
+            let cased = match converter.ascii[byte as usize] {
+                Some(cased) => cased,
+                None => break,
+            };
+            if cased != byte {
+                segment[ascii] = cased;
+                let changed_pos = pos + ascii as ptrdiff_t;
+                extend_changed(&mut changed, changed_pos, changed_pos + 1);
+            }
+            previous = Some(Codepoint::from(byte));
+            ascii += 1;
+        }
+        if ascii > 0 {
+            pos += ascii as ptrdiff_t;
+            pos_byte += ascii as ptrdiff_t;
+            continue;
+        }
+
+        let (c, len) = multibyte_char_at(segment);
+        let final_sigma = || {
+            let next_byte = pos_byte + len as ptrdiff_t;
+            previous.map_or(false, word_char_p)
+                && (pos + 1 == end
+                    || !word_char_p(buffer.fetch_multibyte_char(next_byte) as Codepoint))
+        };
+        let cased = converter.convert(c, final_sigma);
+        let mut char_bytes = [0; MAX_MULTIBYTE_LENGTH];
+        let (bytes, nchars): (&[u8], ptrdiff_t) = match cased {
+            None => {
+                previous = Some(c);
+                pos += 1;
+                pos_byte += len as ptrdiff_t;
+                continue;
+            }
+            Some(CasedChar::Char(cased)) => {
+                let cased_len = write_codepoint(&mut char_bytes, cased);
+                (&char_bytes[..cased_len], 1)
+            }
+            Some(CasedChar::Special(ref special)) => (
+                // Unicode characters are represented in UTF-8.
+                special.as_bytes(),
+                special.chars().count() as ptrdiff_t,
+            ),
+        };
+
+        if nchars == 1 && bytes.len() == len {
+            segment[..len].copy_from_slice(bytes);
+        } else {
+            // Replace the character with the other(s), keeping text
+            // properties the same.
+            unsafe {
+                replace_range_2(
+                    pos,
+                    pos_byte,
+                    pos + 1,
+                    pos_byte + len as ptrdiff_t,
+                    bytes.as_ptr() as *const c_char,
+                    nchars,
+                    bytes.len() as ptrdiff_t,
+                    false,
+                )
+            };
+            added += nchars - 1;
+            end += nchars - 1;
+            end_byte += bytes.len() as ptrdiff_t - len as ptrdiff_t;
+            if opoint > pos {
+                opoint += nchars - 1;
+            }
+        }
+        extend_changed(&mut changed, pos, pos + nchars);
+        previous = Some(c);
+        pos += nchars;
+        pos_byte += bytes.len() as ptrdiff_t;
+    }
+
+    if buffer.pt != opoint {
+        let opoint_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), opoint) };
+        buffer.set_pt_both(opoint, opoint_byte);
+    }
+    (changed, added)
+}
+
+/// Convert the region between BEG and END to upper case if UP, and to
+/// lower case otherwise, as `upcase-region' and `downcase-region' do.
+/// Return the end of the region after the change.
+fn casify_region_up_or_down(up: bool, mut beg: LispObject, mut end: LispObject) -> ptrdiff_t {
+    unsafe { validate_region(&mut beg, &mut end) };
+    let start = beg.as_fixnum_or_error() as ptrdiff_t;
+    let end = end.as_fixnum_or_error() as ptrdiff_t;
+    if start == end {
+        // Not modifying because nothing marked.
+        return end;
+    }
+    unsafe { modify_text(start, end) };
+    let converter = CaseConverter::new(up);
+
+    unsafe { record_delete(start, make_buffer_string(start, end, true), false) };
+    let (changed, added) = if ThreadState::current_buffer().multibyte_characters_enabled() {
+        let (changed, added) = casify_multibyte_region(&converter, start, end);
+        unsafe { record_insert(start, end - start + added) };
+        (changed, added)
+    } else {
+        unsafe { record_insert(start, end - start) };
+        (casify_unibyte_region(&converter, start, end), 0)
+    };
+
+    if let Some((first, last)) = changed {
+        unsafe {
+            signal_after_change(first, last - first - added, last - first);
+            update_compositions(first, last, CHECK_ALL as c_int);
+        }
+    }
+    end + added
+}
+
+/// Convert the case of the region between BEG and END as FLAG says, and
+/// return the end of the region after the change.
+fn casify(flag: case_action, beg: LispObject, end: LispObject) -> ptrdiff_t {
+    match flag {
+        case_action::CASE_UP => casify_region_up_or_down(true, beg, end),
+        case_action::CASE_DOWN => casify_region_up_or_down(false, beg, end),
+        _ => unsafe { casify_region(flag, beg, end) },
+    }
+}
+
 fn casify_word(flag: case_action, words: EmacsInt) {
     let buffer_ref = ThreadState::current_buffer();
 
@@ -32,13 +372,11 @@ fn casify_word(flag: case_action, words: EmacsInt) {
         n => n,
     };
 
-    let new_pos = unsafe {
-        casify_region(
-            flag,
-            LispObject::from(buffer_ref.pt),
-            LispObject::from(far_end),
-        )
-    };
+    let new_pos = casify(
+        flag,
+        LispObject::from(buffer_ref.pt),
+        LispObject::from(far_end),
+    );
 
     unsafe { set_point(new_pos) };
 }
@@ -91,6 +429,9 @@ pub fn downcase(object: LispObject) -> LispObject {
 /// These arguments specify the starting and ending character numbers
 /// of the region to operate on.  When used as a command, the text
 /// between point and the mark is operated on.
+/// Characters which the special casing rules of Unicode convert to
+/// several characters, such as İ, are replaced by them.  See also
+/// `case-conversion-locale'.
 #[lisp_fn(
     min = "2",
     intspec = "(list (region-beginning) (region-end) (region-noncontiguous-p))"
@@ -151,7 +492,9 @@ pub fn upcase_initials_region(beg: LispObject, end: LispObject) {
 /// These arguments specify the starting and ending character numbers
 /// of the region to operate on.  When used as a command, the text
 /// between point and the mark is operated on.
-/// See also `capitalize-region'.
+/// Characters which the special casing rules of Unicode convert to
+/// several characters, such as ß and ﬁ, are replaced by them.
+/// See also `capitalize-region' and `case-conversion-locale'.
 #[lisp_fn(
     min = "2",
     intspec = "(list (region-beginning) (region-end) (region-noncontiguous-p))"
@@ -181,7 +524,7 @@ fn casefiddle_region(
     action: case_action,
 ) {
     if !region_noncontiguous_p {
-        unsafe { casify_region(action, beg, end) };
+        casify(action, beg, end);
     } else {
         let bounds = call!(
             symbol_value(intern("region-extract-function")),
@@ -190,7 +533,7 @@ fn casefiddle_region(
 
         for elt in bounds.iter_cars(LispConsEndChecks::off, LispConsCircularChecks::off) {
             let (car, cdr) = elt.as_cons_or_error().as_tuple();
-            unsafe { casify_region(action, car, cdr) };
+            casify(action, car, cdr);
         }
     }
 }
@@ -202,6 +545,16 @@ pub extern "C" fn syms_of_casefiddle() {
     def_lisp_sym!(Qspecial_uppercase, "special-uppercase");
     def_lisp_sym!(Qspecial_lowercase, "special-lowercase");
     def_lisp_sym!(Qspecial_titlecase, "special-titlecase");
+
+    /// The locale whose case conversion rules the case commands follow.
+    /// If this is nil, `upcase-region', `downcase-region', `upcase-word'
+    /// and `downcase-word' convert characters by the case table of the
+    /// current buffer and the special casing rules of Unicode which
+    /// don't depend on the language.  If it is a locale name whose
+    /// language is Turkish or Azerbaijani, such as "tr" or "az_AZ", the
+    /// dotted and dotless I are distinct letters: i is upcased to İ, and
+    /// I and İ are downcased to ı and i.
+    defvar_lisp!(Vcase_conversion_locale, "case-conversion-locale", Qnil);
 }
 
 #[no_mangle]
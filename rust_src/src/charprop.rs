@@ -0,0 +1,207 @@
+//! Unicode character properties.
+//!
+//! The properties are looked up in the tables compiled into the `ucd'
+//! crate, which don't need the uni-*.el tables to be loaded.
+
+use ucd::{Codepoint, EastAsianWidth, Number, NumericType, UnicodeCategory};
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::list,
+    obarray::intern,
+    remacs_sys::{wrong_choice, EmacsInt},
+    remacs_sys::{Qdecimal_digit_value, Qeast_asian_width, Qgeneral_category, Qnil, Qscript},
+};
+
+/// A property which `char-unicode-property' looks up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum UnicodeProperty {
+    GeneralCategory,
+    Script,
+    EastAsianWidth,
+    DecimalDigitValue,
+}
+
+impl UnicodeProperty {
+    /// Return the property named by the symbol PROP.
+    fn from_symbol(prop: LispObject) -> Self {
+        if prop == Qgeneral_category {
+            UnicodeProperty::GeneralCategory
+        } else if prop == Qscript {
+            UnicodeProperty::Script
+        } else if prop == Qeast_asian_width {
+            UnicodeProperty::EastAsianWidth
+        } else if prop == Qdecimal_digit_value {
+            UnicodeProperty::DecimalDigitValue
+        } else {
+            unsafe {
+                wrong_choice(
+                    list!(
+                        Qgeneral_category,
+                        Qscript,
+                        Qeast_asian_width,
+                        Qdecimal_digit_value
+                    ),
+                    prop,
+                )
+            }
+        }
+    }
+
+    /// Return the value of the property for C.
+    fn value(self, c: char) -> LispObject {
+        match self {
+            UnicodeProperty::GeneralCategory => intern(general_category_name(c.category())).into(),
+            UnicodeProperty::Script => {
+                // Unassigned code points have the `Unknown' script.
+                let name = c.script().map_or("unknown".to_string(), |script| {
+                    script_name(&format!("{:?}", script))
+                });
+                intern(&name).into()
+            }
+            UnicodeProperty::EastAsianWidth => {
+                intern(east_asian_width_name(c.east_asian_width())).into()
+            }
+            UnicodeProperty::DecimalDigitValue => {
+                decimal_digit_value(c).map_or(Qnil, |value| EmacsInt::from(value).into())
+            }
+        }
+    }
+}
+
+/// Return the short name of the general category CATEGORY, such as
+/// `Lu'.
+fn general_category_name(category: UnicodeCategory) -> &'static str {
+    match category {
+        UnicodeCategory::UppercaseLetter => "Lu",
+        UnicodeCategory::LowercaseLetter => "Ll",
+        UnicodeCategory::TitlecaseLetter => "Lt",
+        UnicodeCategory::ModifierLetter => "Lm",
+        UnicodeCategory::OtherLetter => "Lo",
+        UnicodeCategory::NonspacingMark => "Mn",
+        UnicodeCategory::SpacingMark => "Mc",
+        UnicodeCategory::EnclosingMark => "Me",
+        UnicodeCategory::DecimalNumber => "Nd",
+        UnicodeCategory::LetterNumber => "Nl",
+        UnicodeCategory::OtherNumber => "No",
+        UnicodeCategory::ConnectorPunctuation => "Pc",
+        UnicodeCategory::DashPunctuation => "Pd",
+        UnicodeCategory::OpenPunctuation => "Ps",
+        UnicodeCategory::ClosePunctuation => "Pe",
+        UnicodeCategory::InitialPunctuation => "Pi",
+        UnicodeCategory::FinalPunctuation => "Pf",
+        UnicodeCategory::OtherPunctuation => "Po",
+        UnicodeCategory::MathSymbol => "Sm",
+        UnicodeCategory::CurrencySymbol => "Sc",
+        UnicodeCategory::ModifierSymbol => "Sk",
+        UnicodeCategory::OtherSymbol => "So",
+        UnicodeCategory::SpaceSeparator => "Zs",
+        UnicodeCategory::LineSeparator => "Zl",
+        UnicodeCategory::ParagraphSeparator => "Zp",
+        UnicodeCategory::Control => "Cc",
+        UnicodeCategory::Format => "Cf",
+        UnicodeCategory::Surrogate => "Cs",
+        UnicodeCategory::PrivateUse => "Co",
+        UnicodeCategory::Unassigned => "Cn",
+    }
+}
+
+/// Return the short name of the East Asian width WIDTH, such as `W'.
+fn east_asian_width_name(width: EastAsianWidth) -> &'static str {
+    match width {
+        EastAsianWidth::Neutral => "N",
+        EastAsianWidth::Ambiguous => "A",
+        EastAsianWidth::HalfWidth => "H",
+        EastAsianWidth::FullWidth => "F",
+        EastAsianWidth::Narrow => "Na",
+        EastAsianWidth::Wide => "W",
+    }
+}
+
+/// Return the name of a script as in `char-script-table', given the
+/// name of its `ucd' variant: `OldItalic' is `old-italic'.
+fn script_name(name: &str) -> String {
+    let mut script = String::new();
+    for c in name.chars() {
+        if c.is_ascii_uppercase() && !script.is_empty() {
+            script.push('-');
+        }
+        script.push(c.to_ascii_lowercase());
+    }
+    script
+}
+
+/// Return the value of C as a decimal digit, or None if it isn't one.
+fn decimal_digit_value(c: char) -> Option<u32> {
+    if c.numeric_type() != Some(NumericType::Decimal) {
+        return None;
+    }
+    match c.numeric_value() {
+        Some(Number::Integer(value)) => Some(value as u32),
+        _ => None,
+    }
+}
+
+/// Return the value of the Unicode property PROP of CHAR.
+/// PROP is one of these symbols:
+///
+/// `general-category': the general category, a symbol such as `Lu' for
+/// upper case letters or `Nd' for decimal digits, or `Cn' for
+/// unassigned code points.
+///
+/// `script': the script CHAR belongs to, a symbol such as `latin' or
+/// `old-italic', named as in `char-script-table'; it is `common' for
+/// characters used by several scripts, such as punctuation, and
+/// `unknown' for unassigned code points.
+///
+/// `east-asian-width': the East Asian width, one of the symbols `N', `A',
+/// `H', `F', `Na' and `W'.
+///
+/// `decimal-digit-value': the value of CHAR as a decimal digit, or nil
+/// if it isn't one.
+///
+/// The value is nil for raw bytes and other characters outside Unicode.
+/// Unlike `get-char-code-property', this looks the property up in
+/// tables compiled into Emacs, so the uni-*.el tables need not be
+/// loaded.
+#[lisp_fn]
+pub fn char_unicode_property(character: LispObject, prop: LispObject) -> LispObject {
+    let c = character.as_character_or_error();
+    let property = UnicodeProperty::from_symbol(prop);
+    // Raw bytes and other characters beyond Unicode aren't chars.
+    std::char::from_u32(c).map_or(Qnil, |c| property.value(c))
+}
+
+#[no_mangle]
+pub extern "C" fn syms_of_charprop() {
+    def_lisp_sym!(Qdecimal_digit_value, "decimal-digit-value");
+    def_lisp_sym!(Qeast_asian_width, "east-asian-width");
+    def_lisp_sym!(Qgeneral_category, "general-category");
+    def_lisp_sym!(Qscript, "script");
+}
+
+#[test]
+fn test_script_name() {
+    assert_eq!(script_name("Latin"), "latin");
+    assert_eq!(script_name("OldItalic"), "old-italic");
+}
+
+#[test]
+fn test_decimal_digit_value() {
+    assert_eq!(decimal_digit_value('0'), Some(0));
+    assert_eq!(decimal_digit_value('7'), Some(7));
+    assert_eq!(decimal_digit_value('a'), None);
+    // ARABIC-INDIC DIGIT THREE.
+    assert_eq!(decimal_digit_value('\u{663}'), Some(3));
+    // MATHEMATICAL BOLD DIGIT NINE and DOUBLE-STRUCK DIGIT ZERO, in
+    // consecutive runs of digits.
+    assert_eq!(decimal_digit_value('\u{1D7D7}'), Some(9));
+    assert_eq!(decimal_digit_value('\u{1D7D8}'), Some(0));
+    // VULGAR FRACTION ONE HALF is a number, but not a decimal digit.
+    assert_eq!(decimal_digit_value('\u{BD}'), None);
+}
+
+include!(concat!(env!("OUT_DIR"), "/charprop_exports.rs"));
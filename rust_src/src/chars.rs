@@ -0,0 +1,598 @@
+//! Unicode normalization, folding and segmentation of text.
+//!
+//! Text is normalized and folded with the `unicode-normalization'
+//! crate, and split into grapheme clusters, words and sentences with the
+//! `unicode-segmentation' crate.  Raw bytes and characters outside
+//! Unicode are left alone by normalization, and nothing combines across
+//! them.
+
+use std::cmp::{max, min};
+
+use libc::{c_char, ptrdiff_t};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+use unicode_segmentation::UnicodeSegmentation;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    buffers::{validate_region, LispBufferRef},
+    character::char_head_p,
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::list,
+    multibyte::{multibyte_char_at, LispStringRef},
+    remacs_sys::{buf_charpos_to_bytepos, make_specified_string, replace_range, wrong_choice},
+    remacs_sys::{set_point, validate_subarray, EmacsInt, Fsubstring},
+    remacs_sys::{Qbeginning_of_buffer, Qend_of_buffer, Qnfc, Qnfd, Qnfkc, Qnfkd},
+    threads::ThreadState,
+    utf8::unicode_char_p,
+};
+
+/// A Unicode normalization form.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum NormalizationForm {
+    Nfc,
+    Nfd,
+    Nfkc,
+    Nfkd,
+}
+
+impl NormalizationForm {
+    /// Return the normalization form named by the symbol FORM.
+    fn from_symbol(form: LispObject) -> Self {
+        if form == Qnfc {
+            NormalizationForm::Nfc
+        } else if form == Qnfd {
+            NormalizationForm::Nfd
+        } else if form == Qnfkc {
+            NormalizationForm::Nfkc
+        } else if form == Qnfkd {
+            NormalizationForm::Nfkd
+        } else {
+            unsafe { wrong_choice(list!(Qnfc, Qnfd, Qnfkc, Qnfkd), form) }
+        }
+    }
+
+    /// Append the normalization of TEXT to NORMALIZED.
+    fn normalize_into(self, text: &str, normalized: &mut Vec<u8>) {
+        let text: String = match self {
+            NormalizationForm::Nfc => text.nfc().collect(),
+            NormalizationForm::Nfd => text.nfd().collect(),
+            NormalizationForm::Nfkc => text.nfkc().collect(),
+            NormalizationForm::Nfkd => text.nfkd().collect(),
+        };
+        normalized.extend_from_slice(text.as_bytes());
+    }
+}
+
+/// Return TEXT, multibyte text in Emacs's internal representation,
+/// with each run of Unicode characters transformed by TRANSFORM, which
+/// appends the transformation of a run to a vector.  Raw bytes and
+/// characters outside Unicode are kept.
+fn transform_unicode_runs(text: &[u8], transform: impl Fn(&str, &mut Vec<u8>)) -> Vec<u8> {
+    let mut transformed = Vec::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        let mut end = i;
+        while end < text.len() {
+            let (c, len) = multibyte_char_at(&text[end..]);
+            if !unicode_char_p(c) {
+                break;
+            }
+            end += len;
+        }
+        if end > i {
+            // Unicode characters are represented in UTF-8.
+            let run = std::str::from_utf8(&text[i..end]).unwrap();
+            transform(run, &mut transformed);
+            i = end;
+        } else {
+            let (_, len) = multibyte_char_at(&text[i..]);
+            transformed.extend_from_slice(&text[i..i + len]);
+            i += len;
+        }
+    }
+    transformed
+}
+
+/// Return the normalization in FORM of TEXT, multibyte text in Emacs's
+/// internal representation.
+fn normalize_text(text: &[u8], form: NormalizationForm) -> Vec<u8> {
+    transform_unicode_runs(text, |run, normalized| form.normalize_into(run, normalized))
+}
+
+/// Append TEXT to FOLDED without its diacritics: each character is
+/// replaced by its compatibility decomposition, without the combining
+/// marks.  Hangul syllables are kept whole.
+fn fold_diacritics_into(text: &str, folded: &mut Vec<u8>) {
+    let mut text_folded = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c >= '\u{ac00}' && c <= '\u{d7a3}' {
+            text_folded.push(c);
+        } else {
+            text_folded.extend(std::iter::once(c).nfkd().filter(|&c| !is_combining_mark(c)));
+        }
+    }
+    folded.extend_from_slice(text_folded.as_bytes());
+}
+
+/// Return TEXT, multibyte text in Emacs's internal representation,
+/// without its diacritics.
+fn fold_diacritics_text(text: &[u8]) -> Vec<u8> {
+    transform_unicode_runs(text, fold_diacritics_into)
+}
+
+/// Return the lengths in bytes of the longest common prefix and suffix
+/// of the multibyte texts OLD and NEW which consist of whole characters
+/// and don't overlap.
+fn common_affixes(old: &[u8], new: &[u8]) -> (usize, usize) {
+    let mut prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    while prefix > 0 && prefix < old.len() && !char_head_p(old[prefix]) {
+        prefix -= 1;
+    }
+    let (old_rest, new_rest) = (&old[prefix..], &new[prefix..]);
+    let mut suffix = old_rest
+        .iter()
+        .rev()
+        .zip(new_rest.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    while suffix > 0 && !char_head_p(old_rest[old_rest.len() - suffix]) {
+        suffix -= 1;
+    }
+    (prefix, suffix)
+}
+
+/// Return the number of characters in TEXT, multibyte text in Emacs's
+/// internal representation.
+fn chars_count(text: &[u8]) -> ptrdiff_t {
+    text.iter().filter(|&&b| char_head_p(b)).count() as ptrdiff_t
+}
+
+/// Return the Unicode normalization FORM of STRING.
+/// FORM is one of the symbols `nfc', `nfd', `nfkc' and `nfkd', for the
+/// Normalization Forms C, D, KC and KD of Unicode Standard Annex #15.
+/// The forms D and KD decompose characters into base characters and
+/// combining marks, in canonical order; C and KC then recompose them
+/// into precomposed characters where possible.  The forms KC and KD
+/// also replace compatibility characters, such as ligatures and
+/// full-width letters, with their equivalents.
+///
+/// Raw bytes and characters outside Unicode are left unchanged, and so
+/// is unibyte STRING.  The result is a new string without text
+/// properties.
+#[lisp_fn]
+pub fn string_normalize(string: LispStringRef, form: LispObject) -> LispObject {
+    let form = NormalizationForm::from_symbol(form);
+    transform_string(string, |text| normalize_text(text, form))
+}
+
+/// Normalize the text of the region to the Unicode normalization FORM.
+/// START and END are the limits of the region, and FORM is one of the
+/// symbols `nfc', `nfd', `nfkc' and `nfkd'; see `string-normalize'.
+///
+/// Only the parts of the region which change are replaced, so markers
+/// and text properties in the rest are kept.  The text of a unibyte
+/// buffer is left unchanged.
+#[lisp_fn]
+pub fn normalize_region(start: LispObject, end: LispObject, form: LispObject) {
+    let form = NormalizationForm::from_symbol(form);
+    transform_region(start, end, |text| normalize_text(text, form));
+}
+
+/// Return STRING with diacritics removed, to be compared with text
+/// folded the same way.
+/// Each character is replaced by its compatibility decomposition
+/// without combining marks, so that accented letters become their base
+/// letters and characters such as ligatures and full-width letters
+/// become their plain equivalents: "Ｃafé ﬁ" is folded to "Cafe fi".
+/// Letters without a decomposition, such as ø and ł, and Hangul
+/// syllables are kept, and so are raw bytes, characters outside Unicode
+/// and unibyte STRING.  The result is a new string without text
+/// properties.
+///
+/// Since folding can change the number of characters, positions in the
+/// result don't correspond to positions in STRING.
+#[lisp_fn]
+pub fn string_fold_diacritics(string: LispStringRef) -> LispObject {
+    transform_string(string, fold_diacritics_text)
+}
+
+/// Remove the diacritics from the text of the region.
+/// START and END are the limits of the region.  The text is folded as
+/// by `string-fold-diacritics'.  Only the parts of the region which
+/// change are replaced, and the text of a unibyte buffer is left
+/// unchanged.
+#[lisp_fn]
+pub fn fold_diacritics_region(start: LispObject, end: LispObject) {
+    transform_region(start, end, fold_diacritics_text);
+}
+
+/// Return a new string with the text of STRING transformed by TRANSFORM
+/// if it is multibyte, and otherwise unchanged.
+fn transform_string(string: LispStringRef, transform: impl Fn(&[u8]) -> Vec<u8>) -> LispObject {
+    let multibyte = string.is_multibyte();
+    let transformed = if multibyte {
+        transform(string.as_slice())
+    } else {
+        string.as_slice().to_vec()
+    };
+    unsafe {
+        make_specified_string(
+            transformed.as_ptr() as *const c_char,
+            -1,
+            transformed.len() as ptrdiff_t,
+            multibyte,
+        )
+    }
+}
+
+/// Replace the text between START and END in the current buffer by its
+/// transformation by TRANSFORM, if the buffer is multibyte.  Only the
+/// part which changes is replaced.
+fn transform_region(
+    mut start: LispObject,
+    mut end: LispObject,
+    transform: impl Fn(&[u8]) -> Vec<u8>,
+) {
+    unsafe { validate_region(&mut start, &mut end) };
+    let mut buffer = ThreadState::current_buffer();
+    if !buffer.multibyte_characters_enabled() {
+        return;
+    }
+    let start = start.as_fixnum_or_error() as ptrdiff_t;
+    let end = end.as_fixnum_or_error() as ptrdiff_t;
+
+    let start_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), start) };
+    let end_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), end) };
+    let (before_gap, after_gap) = buffer.region_slices(start_byte, end_byte);
+    let mut text = before_gap.to_vec();
+    text.extend_from_slice(after_gap);
+
+    let transformed = transform(&text);
+    if transformed == text {
+        return;
+    }
+    let (prefix, suffix) = common_affixes(&text, &transformed);
+    let from = start + chars_count(&text[..prefix]);
+    let to = end - chars_count(&text[text.len() - suffix..]);
+    let replacement = &transformed[prefix..transformed.len() - suffix];
+    unsafe {
+        let replacement = make_specified_string(
+            replacement.as_ptr() as *const c_char,
+            -1,
+            replacement.len() as ptrdiff_t,
+            true,
+        );
+        replace_range(from, to, replacement, true, false, true, false);
+    }
+}
+
+/// The number of characters on either side of a buffer position which
+/// are examined to find the grapheme clusters around it.
+const GRAPHEME_CONTEXT: ptrdiff_t = 1024;
+
+/// Return the characters of TEXT, which is in Emacs's internal
+/// representation if MULTIBYTE, with each raw byte and character
+/// outside Unicode replaced by U+FFFD.
+pub fn unicode_chars(text: &[u8], multibyte: bool) -> String {
+    if !multibyte {
+        return text
+            .iter()
+            .map(|&b| if b < 0x80 { char::from(b) } else { '\u{FFFD}' })
+            .collect();
+    }
+    let mut chars = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        let (c, len) = multibyte_char_at(&text[i..]);
+        chars.push(if unicode_char_p(c) {
+            std::char::from_u32(c).unwrap()
+        } else {
+            '\u{FFFD}'
+        });
+        i += len;
+    }
+    chars
+}
+
+/// Return the characters of BUFFER between the positions FROM and TO,
+/// as by `unicode_chars'.
+fn buffer_unicode_chars(mut buffer: LispBufferRef, from: ptrdiff_t, to: ptrdiff_t) -> String {
+    let from_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), from) };
+    let to_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), to) };
+    let (before_gap, after_gap) = buffer.region_slices(from_byte, to_byte);
+    let mut text = before_gap.to_vec();
+    text.extend_from_slice(after_gap);
+    unicode_chars(&text, buffer.multibyte_characters_enabled())
+}
+
+/// Return the offsets in characters of the boundaries of SEGMENTS,
+/// consecutive parts of a text, from 0 to the length of the text.
+fn segment_boundaries<'a>(segments: impl Iterator<Item = &'a str>) -> Vec<usize> {
+    let mut boundaries = vec![0];
+    let mut offset = 0;
+    for segment in segments {
+        offset += segment.chars().count();
+        boundaries.push(offset);
+    }
+    boundaries
+}
+
+/// Return the offsets in characters of the grapheme cluster boundaries
+/// of TEXT, from 0 to its length.
+fn grapheme_boundaries(text: &str) -> Vec<usize> {
+    segment_boundaries(text.graphemes(true))
+}
+
+/// Return the grapheme cluster boundary of BUFFER after POS if FORWARD,
+/// and before it otherwise.  There must be a character in the
+/// accessible portion of BUFFER after, or before, POS.
+fn next_grapheme_boundary(buffer: LispBufferRef, pos: ptrdiff_t, forward: bool) -> ptrdiff_t {
+    let from = max(buffer.begv, pos - GRAPHEME_CONTEXT);
+    let to = min(buffer.zv, pos + GRAPHEME_CONTEXT);
+    let chars: Vec<char> = buffer_unicode_chars(buffer, from, to).chars().collect();
+
+    // Grapheme clusters don't span newlines, so only the line of the
+    // character being moved over is segmented.
+    let index = (if forward { pos } else { pos - 1 } - from) as usize;
+    let line_start = chars[..index]
+        .iter()
+        .rposition(|&c| c == '\n')
+        .map_or(0, |i| i + 1);
+    let line_end = chars[index..]
+        .iter()
+        .position(|&c| c == '\n')
+        .map_or(chars.len(), |i| index + i + 1);
+    let line: String = chars[line_start..line_end].iter().collect();
+    let offset = pos - from - line_start as ptrdiff_t;
+
+    let boundaries = grapheme_boundaries(&line);
+    let boundary = if forward {
+        boundaries
+            .into_iter()
+            .find(|&boundary| boundary as ptrdiff_t > offset)
+    } else {
+        boundaries
+            .into_iter()
+            .rev()
+            .find(|&boundary| (boundary as ptrdiff_t) < offset)
+    };
+    from + line_start as ptrdiff_t + boundary.unwrap() as ptrdiff_t
+}
+
+/// Move point N grapheme clusters forward, or backward if N is negative.
+fn move_graphemes(n: EmacsInt) {
+    let buffer = ThreadState::current_buffer();
+    let mut pos = buffer.pt;
+    let mut signal = None;
+    for _ in 0..n.abs() {
+        if n > 0 && pos == buffer.zv {
+            signal = Some(Qend_of_buffer);
+            break;
+        } else if n < 0 && pos == buffer.begv {
+            signal = Some(Qbeginning_of_buffer);
+            break;
+        }
+        pos = next_grapheme_boundary(buffer, pos, n > 0);
+    }
+
+    unsafe { set_point(pos) };
+    if let Some(signal) = signal {
+        xsignal!(signal);
+    }
+}
+
+/// Return the number of grapheme clusters in STRING.
+/// A grapheme cluster is what a user perceives as a single character,
+/// as defined by Unicode Standard Annex #29: a base character with its
+/// combining marks, a Hangul syllable made of conjoining jamo, or an
+/// emoji sequence joined by zero width joiners, for example.  Each raw
+/// byte and character outside Unicode is taken as U+FFFD.
+#[lisp_fn]
+pub fn string_grapheme_length(string: LispStringRef) -> EmacsInt {
+    let chars = unicode_chars(string.as_slice(), string.is_multibyte());
+    chars.graphemes(true).count() as EmacsInt
+}
+
+/// Return a new string whose contents are a substring of STRING.
+/// This is like `substring', but FROM and TO count grapheme clusters
+/// rather than characters; see `string-grapheme-length'.  The returned
+/// string consists of the grapheme clusters between index FROM
+/// (inclusive) and index TO (exclusive) of STRING.  FROM and TO are
+/// zero-indexed: 0 means the first cluster of STRING.  Negative values
+/// are counted from the end of STRING.  If TO is nil, the substring
+/// runs to the end of STRING.
+///
+/// Clusters are never split, so a string truncated with this function
+/// doesn't end with a dangling combining mark or half of an emoji.
+#[lisp_fn(min = "2")]
+pub fn grapheme_substring(string: LispStringRef, from: LispObject, to: LispObject) -> LispObject {
+    let chars = unicode_chars(string.as_slice(), string.is_multibyte());
+    let boundaries = grapheme_boundaries(&chars);
+    let (mut ifrom, mut ito) = (0, 0);
+    unsafe {
+        validate_subarray(
+            string.into(),
+            from,
+            to,
+            (boundaries.len() - 1) as ptrdiff_t,
+            &mut ifrom,
+            &mut ito,
+        );
+        Fsubstring(
+            string.into(),
+            LispObject::from(boundaries[ifrom as usize] as EmacsInt),
+            LispObject::from(boundaries[ito as usize] as EmacsInt),
+        )
+    }
+}
+
+/// Move point N grapheme clusters forward (backward if N is negative).
+/// A grapheme cluster is what a user perceives as a single character;
+/// see `string-grapheme-length'.
+/// On reaching end or beginning of buffer, stop and signal error.
+/// Interactively, N is the numeric prefix argument.
+/// If N is omitted or nil, move point 1 grapheme cluster forward.
+#[lisp_fn(min = "0", intspec = "^p")]
+pub fn forward_grapheme(n: Option<EmacsInt>) {
+    move_graphemes(n.unwrap_or(1))
+}
+
+/// Move point N grapheme clusters backward (forward if N is negative).
+/// A grapheme cluster is what a user perceives as a single character;
+/// see `string-grapheme-length'.
+/// On attempt to pass beginning or end of buffer, stop and signal error.
+/// Interactively, N is the numeric prefix argument.
+/// If N is omitted or nil, move point 1 grapheme cluster backward.
+#[lisp_fn(min = "0", intspec = "^p")]
+pub fn backward_grapheme(n: Option<EmacsInt>) {
+    move_graphemes(-n.unwrap_or(1))
+}
+
+/// Return the positions of the boundaries in the region between START
+/// and END of the segments into which SPLIT splits its text.
+fn region_boundaries(
+    mut start: LispObject,
+    mut end: LispObject,
+    split: fn(&str) -> Vec<usize>,
+) -> LispObject {
+    unsafe { validate_region(&mut start, &mut end) };
+    let start = start.as_fixnum_or_error() as ptrdiff_t;
+    let end = end.as_fixnum_or_error() as ptrdiff_t;
+    let text = buffer_unicode_chars(ThreadState::current_buffer(), start, end);
+    let boundaries: Vec<LispObject> = split(&text)
+        .into_iter()
+        .map(|offset| LispObject::from(start + offset as ptrdiff_t))
+        .collect();
+    list(&boundaries)
+}
+
+/// Return the word boundaries in the region between START and END.
+/// The boundaries are those of Unicode Standard Annex #29, and the
+/// result is a list of positions in increasing order, starting with
+/// START and ending with END.  Words, runs of white space, and each
+/// punctuation character lie between consecutive boundaries, so the
+/// boundaries don't depend on the syntax table.  Each ideograph is a
+/// word of its own, while runs of kana, and of Thai and other scripts
+/// written without spaces, aren't split.  Raw bytes and characters
+/// outside Unicode are taken as U+FFFD.
+#[lisp_fn]
+pub fn unicode_word_boundaries(start: LispObject, end: LispObject) -> LispObject {
+    region_boundaries(start, end, |text| {
+        segment_boundaries(text.split_word_bounds())
+    })
+}
+
+/// Return the sentence boundaries in the region between START and END.
+/// The boundaries are those of Unicode Standard Annex #29, and the
+/// result is a list of positions in increasing order, starting with
+/// START and ending with END.  Unlike `forward-sentence', this doesn't
+/// depend on `sentence-end' and knows the sentence terminators of all
+/// scripts, such as the ideographic full stop.  Each sentence includes
+/// the white space which follows it.
+#[lisp_fn]
+pub fn unicode_sentence_boundaries(start: LispObject, end: LispObject) -> LispObject {
+    region_boundaries(start, end, |text| {
+        segment_boundaries(text.split_sentence_bounds())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn syms_of_chars() {
+    def_lisp_sym!(Qnfc, "nfc");
+    def_lisp_sym!(Qnfd, "nfd");
+    def_lisp_sym!(Qnfkc, "nfkc");
+    def_lisp_sym!(Qnfkd, "nfkd");
+}
+
+#[test]
+fn test_normalize_text() {
+    let normalize = |text: &str, form| String::from_utf8(normalize_text(text.as_bytes(), form));
+    assert_eq!(
+        normalize("e\u{301}", NormalizationForm::Nfc).unwrap(),
+        "\u{e9}"
+    );
+    assert_eq!(
+        normalize("\u{e9}", NormalizationForm::Nfd).unwrap(),
+        "e\u{301}"
+    );
+    assert_eq!(
+        normalize("\u{fb01}", NormalizationForm::Nfc).unwrap(),
+        "\u{fb01}"
+    );
+    assert_eq!(
+        normalize("\u{fb01}", NormalizationForm::Nfkc).unwrap(),
+        "fi"
+    );
+    assert_eq!(
+        normalize("\u{1112}\u{1161}\u{11ab}", NormalizationForm::Nfc).unwrap(),
+        "\u{d55c}"
+    );
+    // Raw bytes are kept, and nothing combines across them.
+    assert_eq!(
+        normalize_text(b"e\xC1\xBF\xCC\x81", NormalizationForm::Nfc),
+        b"e\xC1\xBF\xCC\x81".to_vec()
+    );
+}
+
+#[test]
+fn test_fold_diacritics_text() {
+    let fold = |text: &str| String::from_utf8(fold_diacritics_text(text.as_bytes())).unwrap();
+    assert_eq!(fold("caf\u{e9}"), "cafe");
+    assert_eq!(fold("e\u{301}\u{323}"), "e");
+    assert_eq!(fold("\u{fb01}\u{ff21}"), "fiA");
+    assert_eq!(fold("\u{f8}"), "\u{f8}");
+    assert_eq!(fold("\u{d55c}"), "\u{d55c}");
+    // Raw bytes are kept.
+    assert_eq!(
+        fold_diacritics_text(b"\xC3\xA9\xC1\xBF"),
+        b"e\xC1\xBF".to_vec()
+    );
+}
+
+#[test]
+fn test_common_affixes() {
+    assert_eq!(common_affixes(b"abc", b"abc"), (3, 0));
+    assert_eq!(common_affixes(b"axc", b"ayyc"), (1, 1));
+    // "\u{e9}" and "e\u{301}", which share no whole character.
+    assert_eq!(common_affixes(b"a\xC3\xA9b", b"ae\xCC\x81b"), (1, 1));
+    // "\u{e9}" and "\u{e8}" share the first byte.
+    assert_eq!(common_affixes(b"\xC3\xA9", b"\xC3\xA8"), (0, 0));
+}
+
+#[test]
+fn test_grapheme_boundaries() {
+    assert_eq!(grapheme_boundaries(""), vec![0]);
+    assert_eq!(grapheme_boundaries("abc"), vec![0, 1, 2, 3]);
+    assert_eq!(grapheme_boundaries("e\u{301}x\r\n"), vec![0, 2, 3, 5]);
+    // A family emoji, and two flags made of regional indicators.
+    assert_eq!(
+        grapheme_boundaries("\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}!"),
+        vec![0, 5, 6]
+    );
+    assert_eq!(
+        grapheme_boundaries("\u{1f1eb}\u{1f1f7}\u{1f1e9}\u{1f1ea}"),
+        vec![0, 2, 4]
+    );
+}
+
+#[test]
+fn test_unicode_chars() {
+    assert_eq!(unicode_chars(b"caf\xE9", false), "caf\u{fffd}");
+    assert_eq!(unicode_chars(b"caf\xC3\xA9", true), "caf\u{e9}");
+    assert_eq!(unicode_chars(b"a\xC1\xBFb", true), "a\u{fffd}b");
+}
+
+#[test]
+fn test_segment_boundaries() {
+    let words = |text: &str| segment_boundaries(text.split_word_bounds());
+    assert_eq!(words(""), vec![0]);
+    assert_eq!(words("can't stop."), vec![0, 5, 6, 10, 11]);
+    assert_eq!(words("\u{6f22}\u{5b57}"), vec![0, 1, 2]);
+    let sentences = |text: &str| segment_boundaries(text.split_sentence_bounds());
+    assert_eq!(sentences("One. Two?  Three"), vec![0, 5, 11, 16]);
+    assert_eq!(sentences("\u{4e00}\u{3002}\u{4e8c}"), vec![0, 2, 3]);
+}
+
+include!(concat!(env!("OUT_DIR"), "/chars_exports.rs"));
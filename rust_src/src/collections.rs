@@ -0,0 +1,710 @@
+//! Ordered hash tables, sorted maps and heaps.
+//!
+//! An ordered hash table is a record holding a hash table, which maps
+//! each key to its position, and a vector of the (KEY . VALUE) entries
+//! in the order they were added.  Removing a key leaves a hole in the
+//! vector, which is squeezed out the next time the vector fills up.
+//!
+//! A sorted map is a record holding an `equal' hash table of its keys
+//! and values, which keeps them alive, and the number of a B-tree of
+//! its keys on the Rust side, which gives their order.  The B-tree is
+//! dropped by a finalizer once the map is garbage.
+//!
+//! A heap is a record holding its predicate and a vector laid out as a
+//! binary heap.  The predicate is Lisp, so the heap is sifted here one
+//! swap at a time rather than with a Rust `BinaryHeap`, and stays a
+//! permutation of its elements if the predicate signals.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Bound;
+use std::sync::Mutex;
+
+use libc::ptrdiff_t;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    data::{aref, aset},
+    hashtable::{
+        clrhash, gethash, hash_table_count, puthash, remhash, HashLookupResult::Found,
+        LispHashTableRef,
+    },
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::{list, plist_get, LispConsCircularChecks, LispConsEndChecks},
+    obarray::intern,
+    remacs_sys::{EmacsInt, Fmake_finalizer, Fmake_hash_table, Fmake_vector, Frecord},
+    remacs_sys::{QCtest, QCweakness, Qclosure, Qequal, Qnil, Qt},
+    remacs_sys::{
+        Qheap, Qheap_p, Qordered_hash_table, Qordered_hash_table_p, Qsorted_map,
+        Qsorted_map__release, Qsorted_map_key_p, Qsorted_map_p,
+    },
+    vectors::recordp,
+};
+
+/// Return t if OBJECT is an ordered hash table.
+#[lisp_fn]
+pub fn ordered_hash_table_p(object: LispObject) -> bool {
+    recordp(object) && aref(object, 0) == Qordered_hash_table
+}
+
+/// Return the index, the entry vector and the number of used entry
+/// slots of the ordered hash table TABLE.
+fn ordered_hash_table_parts(table: LispObject) -> (LispHashTableRef, LispObject, EmacsInt) {
+    if !ordered_hash_table_p(table) {
+        wrong_type!(Qordered_hash_table_p, table);
+    }
+    (
+        aref(table, 1).as_hash_table_or_error(),
+        aref(table, 2),
+        aref(table, 3).as_fixnum_or_error(),
+    )
+}
+
+/// Create and return a new ordered hash table, which is like a hash
+/// table except that it remembers the order in which its keys were
+/// first added, and visits them in that order.
+///
+/// The arguments ARGS are a list of keyword/argument pairs, as for
+/// `make-hash-table', except that the table cannot be weak.
+/// usage: (make-ordered-hash-table &rest ARGS)
+#[lisp_fn]
+pub fn make_ordered_hash_table(args: &mut [LispObject]) -> LispObject {
+    let options = list(args);
+    if plist_get(options, QCweakness).is_not_nil() {
+        error!("Ordered hash tables cannot be weak");
+    }
+    let index = unsafe { Fmake_hash_table(args.len() as ptrdiff_t, args.as_mut_ptr()) };
+    let size = index.as_hash_table_or_error().size().max(1);
+    let entries = unsafe { Fmake_vector(LispObject::from(size), Qnil) };
+    callN_raw!(
+        Frecord,
+        Qordered_hash_table,
+        index,
+        entries,
+        LispObject::from(0)
+    )
+}
+
+/// Look up KEY in the ordered hash table TABLE and return its value.
+/// If KEY is not found, return DEFAULT which defaults to nil.
+#[lisp_fn(min = "2")]
+pub fn ordered_hash_table_get(
+    key: LispObject,
+    table: LispObject,
+    default: LispObject,
+) -> LispObject {
+    let (index, entries, _) = ordered_hash_table_parts(table);
+    let position = gethash(key, index, Qnil);
+    if position.is_nil() {
+        default
+    } else {
+        aref(entries, position.as_fixnum_or_error())
+            .as_cons_or_error()
+            .cdr()
+    }
+}
+
+/// Associate KEY with VALUE in the ordered hash table TABLE and return
+/// VALUE.  If KEY is already present, its value is replaced and it
+/// keeps its place; otherwise it is added after all the other keys.
+#[lisp_fn]
+pub fn ordered_hash_table_put(key: LispObject, value: LispObject, table: LispObject) -> LispObject {
+    let (index, entries, fill) = ordered_hash_table_parts(table);
+    if let Found(idx) = index.lookup(key) {
+        let position = index.get_hash_value(idx).as_fixnum_or_error();
+        aref(entries, position).as_cons_or_error().set_cdr(value);
+        return value;
+    }
+
+    let (entries, fill) = if fill < entries.as_vector_or_error().len() as EmacsInt {
+        (entries, fill)
+    } else {
+        ordered_hash_table_repack(table)
+    };
+    aset(entries, fill, LispObject::cons(key, value));
+    puthash(key, LispObject::from(fill), index);
+    aset(table, 3, LispObject::from(fill + 1));
+    value
+}
+
+/// Move the entries of the ordered hash table TABLE to the start of a
+/// new entry vector, dropping the holes left by removed keys.  The new
+/// vector is twice as big unless at least half the old one was holes.
+/// Return the new vector and the number of entries in it.
+fn ordered_hash_table_repack(table: LispObject) -> (LispObject, EmacsInt) {
+    let (index, entries, fill) = ordered_hash_table_parts(table);
+    let count = hash_table_count(index);
+    let old_size = entries.as_vector_or_error().len() as EmacsInt;
+    let size = if count * 2 <= old_size {
+        old_size.max(8)
+    } else {
+        (old_size * 2).max(8)
+    };
+
+    let new_entries = unsafe { Fmake_vector(LispObject::from(size), Qnil) };
+    let mut new_fill = 0;
+    for position in 0..fill {
+        let entry = aref(entries, position);
+        if let Some(cons) = entry.as_cons() {
+            aset(new_entries, new_fill, entry);
+            puthash(cons.car(), LispObject::from(new_fill), index);
+            new_fill += 1;
+        }
+    }
+    aset(table, 2, new_entries);
+    aset(table, 3, LispObject::from(new_fill));
+    (new_entries, new_fill)
+}
+
+/// Remove KEY from the ordered hash table TABLE.
+#[lisp_fn]
+pub fn ordered_hash_table_remove(key: LispObject, table: LispObject) {
+    let (index, entries, fill) = ordered_hash_table_parts(table);
+    if let Found(idx) = index.lookup(key) {
+        let position = index.get_hash_value(idx).as_fixnum_or_error();
+        aset(entries, position, Qnil);
+        remhash(key, index);
+        if position == fill - 1 {
+            aset(table, 3, LispObject::from(position));
+        }
+    }
+}
+
+/// Return the number of entries in the ordered hash table TABLE.
+#[lisp_fn]
+pub fn ordered_hash_table_count(table: LispObject) -> EmacsInt {
+    let (index, _, _) = ordered_hash_table_parts(table);
+    hash_table_count(index)
+}
+
+/// Remove all the entries of the ordered hash table TABLE, and return
+/// TABLE.
+#[lisp_fn]
+pub fn ordered_hash_table_clear(table: LispObject) -> LispObject {
+    let (index, entries, fill) = ordered_hash_table_parts(table);
+    clrhash(index);
+    for position in 0..fill {
+        aset(entries, position, Qnil);
+    }
+    aset(table, 3, LispObject::from(0));
+    table
+}
+
+/// Call FUNCTION for all the entries of the ordered hash table TABLE,
+/// in the order their keys were added.  FUNCTION is called with two
+/// arguments, KEY and VALUE.  Return nil.
+///
+/// Entries added by FUNCTION are visited too; entries it removes are
+/// not, if they haven't been already.
+#[lisp_fn]
+pub fn ordered_hash_table_map(function: LispObject, table: LispObject) {
+    let mut position = 0;
+    loop {
+        // FUNCTION can add entries, and so repack them.
+        let (index, entries, fill) = ordered_hash_table_parts(table);
+        if position >= fill {
+            break;
+        }
+        if let Some(cons) = aref(entries, position).as_cons() {
+            call!(function, cons.car(), cons.cdr());
+            // Find the place of the entry after this one.
+            if let Found(idx) = index.lookup(cons.car()) {
+                position = index.get_hash_value(idx).as_fixnum_or_error();
+            }
+        }
+        position += 1;
+    }
+}
+
+/// A key of a sorted map, in the order of `sorted-map-key-p'.
+#[derive(Clone, Copy)]
+struct SortKey(LispObject);
+
+/// The kinds of keys a sorted map can have, which sort in this order.
+enum SortKeyKind {
+    Fixnum(EmacsInt),
+    Float(f64),
+    String,
+    Symbol,
+}
+
+impl SortKey {
+    fn kind(self) -> Option<SortKeyKind> {
+        let object = self.0;
+        if let Some(n) = object.as_fixnum() {
+            Some(SortKeyKind::Fixnum(n))
+        } else if let Some(f) = object.as_float() {
+            Some(SortKeyKind::Float(f))
+        } else if object.is_string() {
+            Some(SortKeyKind::String)
+        } else if object.is_symbol() {
+            Some(SortKeyKind::Symbol)
+        } else {
+            None
+        }
+    }
+
+    /// Return the rank of the kind of the key: numbers, then strings,
+    /// then symbols.
+    fn rank(self) -> u8 {
+        match self.kind() {
+            Some(SortKeyKind::Fixnum(_)) | Some(SortKeyKind::Float(_)) => 0,
+            Some(SortKeyKind::String) => 1,
+            Some(SortKeyKind::Symbol) => 2,
+            None => 3,
+        }
+    }
+}
+
+/// Compare the integer N with the float F exactly, putting NaNs after
+/// all integers.
+fn compare_fixnum_float(n: EmacsInt, f: f64) -> Ordering {
+    // 2^63, beyond which no integer lies.
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+    if f.is_nan() || f >= LIMIT {
+        Ordering::Less
+    } else if f < -LIMIT {
+        Ordering::Greater
+    } else {
+        let truncated = f.trunc();
+        n.cmp(&(truncated as EmacsInt))
+            .then_with(|| truncated.partial_cmp(&f).unwrap())
+    }
+}
+
+/// Compare the floats A and B, putting NaNs after all other numbers.
+/// Like `equal', this treats 0.0 and -0.0 as the same, and all NaNs.
+fn compare_floats(a: f64, b: f64) -> Ordering {
+    a.partial_cmp(&b)
+        .unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+}
+
+impl Ord for SortKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        use self::SortKeyKind::*;
+        match (self.kind(), other.kind()) {
+            (Some(Fixnum(a)), Some(Fixnum(b))) => a.cmp(&b),
+            (Some(Float(a)), Some(Float(b))) => compare_floats(a, b),
+            // An integer comes before a float of the same value, which
+            // it is not `equal' to.
+            (Some(Fixnum(a)), Some(Float(b))) => compare_fixnum_float(a, b).then(Ordering::Less),
+            (Some(Float(a)), Some(Fixnum(b))) => {
+                compare_fixnum_float(b, a).reverse().then(Ordering::Greater)
+            }
+            (Some(String), Some(String)) => {
+                let (a, b) = (self.0.as_string().unwrap(), other.0.as_string().unwrap());
+                a.as_slice().cmp(b.as_slice())
+            }
+            (Some(Symbol), Some(Symbol)) => {
+                let (a, b) = (self.0.as_symbol().unwrap(), other.0.as_symbol().unwrap());
+                let (a_name, b_name) = (
+                    a.symbol_name().as_string().unwrap(),
+                    b.symbol_name().as_string().unwrap(),
+                );
+                // Uninterned symbols can share a name.
+                a_name
+                    .as_slice()
+                    .cmp(b_name.as_slice())
+                    .then_with(|| self.0.to_C().cmp(&other.0.to_C()))
+            }
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for SortKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for SortKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for SortKey {}
+
+struct SortedMaps {
+    next_id: EmacsInt,
+    maps: HashMap<EmacsInt, BTreeSet<SortKey>>,
+}
+
+lazy_static! {
+    /// The keys of the live sorted maps, in order, by map number.
+    static ref SORTED_MAPS: Mutex<SortedMaps> = Mutex::new(SortedMaps {
+        next_id: 0,
+        maps: HashMap::new(),
+    });
+}
+
+/// Return t if OBJECT can be a key of a sorted map: a number, a string
+/// or a symbol.  Numbers sort before strings, and strings before
+/// symbols.  Numbers sort by value, strings by their bytes, and symbols
+/// by their names.
+#[lisp_fn]
+pub fn sorted_map_key_p(object: LispObject) -> bool {
+    SortKey(object).kind().is_some()
+}
+
+/// Return KEY as a key of a sorted map, signaling an error if it can't
+/// be one.
+fn sort_key(key: LispObject) -> SortKey {
+    if !sorted_map_key_p(key) {
+        wrong_type!(Qsorted_map_key_p, key);
+    }
+    SortKey(key)
+}
+
+/// Return t if OBJECT is a sorted map.
+#[lisp_fn]
+pub fn sorted_map_p(object: LispObject) -> bool {
+    recordp(object) && aref(object, 0) == Qsorted_map
+}
+
+/// Return the number and the table of keys and values of the sorted
+/// map MAP.
+fn sorted_map_parts(map: LispObject) -> (EmacsInt, LispHashTableRef) {
+    if !sorted_map_p(map) {
+        wrong_type!(Qsorted_map_p, map);
+    }
+    (
+        aref(map, 1).as_fixnum_or_error(),
+        aref(map, 2).as_hash_table_or_error(),
+    )
+}
+
+/// Call F with the ordered keys of the sorted map numbered ID.  F must
+/// not signal, which would leave the maps locked.
+fn with_sorted_keys<T, F>(id: EmacsInt, f: F) -> T
+where
+    F: FnOnce(&mut BTreeSet<SortKey>) -> T,
+{
+    let result = SORTED_MAPS.lock().unwrap().maps.get_mut(&id).map(f);
+    match result {
+        Some(result) => result,
+        None => error!("Sorted map has been released"),
+    }
+}
+
+/// Create and return a new empty sorted map, which maps keys to values
+/// and visits its keys in increasing order.  The keys are numbers,
+/// strings or symbols, and are compared as `sorted-map-key-p' says;
+/// keys which are `equal' are the same key.
+#[lisp_fn]
+pub fn make_sorted_map() -> LispObject {
+    let id = {
+        let mut maps = SORTED_MAPS.lock().unwrap();
+        maps.next_id += 1;
+        let id = maps.next_id;
+        maps.maps.insert(id, BTreeSet::new());
+        id
+    };
+
+    let mut args = [QCtest, Qequal];
+    let table = unsafe { Fmake_hash_table(args.len() as ptrdiff_t, args.as_mut_ptr()) };
+    // Drop the keys' order once the map becomes garbage.
+    let release = list!(
+        Qclosure,
+        list!(Qt),
+        Qnil,
+        list!(Qsorted_map__release, LispObject::from(id))
+    );
+    let finalizer = unsafe { Fmake_finalizer(release) };
+    callN_raw!(Frecord, Qsorted_map, LispObject::from(id), table, finalizer)
+}
+
+/// Look up KEY in the sorted map MAP and return its value.
+/// If KEY is not found, return DEFAULT which defaults to nil.
+#[lisp_fn(min = "2")]
+pub fn sorted_map_get(key: LispObject, map: LispObject, default: LispObject) -> LispObject {
+    let (id, table) = sorted_map_parts(map);
+    // A float key may be stored as another float which is `equal' to
+    // it but hashes differently, such as -0.0 for 0.0.
+    let key = match SortKey(key).kind() {
+        Some(SortKeyKind::Float(_)) => {
+            with_sorted_keys(id, |keys| keys.get(&SortKey(key)).map_or(key, |key| key.0))
+        }
+        _ => key,
+    };
+    gethash(key, table, default)
+}
+
+/// Associate KEY with VALUE in the sorted map MAP and return VALUE.
+#[lisp_fn]
+pub fn sorted_map_put(key: LispObject, value: LispObject, map: LispObject) -> LispObject {
+    let (id, table) = sorted_map_parts(map);
+    let key = sort_key(key);
+    // Keep the key already in the map if there is one, which may be a
+    // different float, so that the table and the keys' order agree.
+    let key = with_sorted_keys(id, |keys| match keys.get(&key) {
+        Some(&old_key) => old_key,
+        None => {
+            keys.insert(key);
+            key
+        }
+    });
+    puthash(key.0, value, table)
+}
+
+/// Remove KEY from the sorted map MAP.
+#[lisp_fn]
+pub fn sorted_map_remove(key: LispObject, map: LispObject) {
+    let (id, table) = sorted_map_parts(map);
+    if !sorted_map_key_p(key) {
+        return;
+    }
+    if let Some(old_key) = with_sorted_keys(id, |keys| keys.take(&SortKey(key))) {
+        remhash(old_key.0, table);
+    }
+}
+
+/// Return the number of entries in the sorted map MAP.
+#[lisp_fn]
+pub fn sorted_map_count(map: LispObject) -> EmacsInt {
+    let (_, table) = sorted_map_parts(map);
+    hash_table_count(table)
+}
+
+/// Remove all the entries of the sorted map MAP, and return MAP.
+#[lisp_fn]
+pub fn sorted_map_clear(map: LispObject) -> LispObject {
+    let (id, table) = sorted_map_parts(map);
+    with_sorted_keys(id, |keys| keys.clear());
+    clrhash(table);
+    map
+}
+
+/// Return the entry of KEY in TABLE as (KEY . VALUE), or nil if KEY is
+/// None.
+fn sorted_map_entry(key: Option<SortKey>, table: LispHashTableRef) -> LispObject {
+    match key {
+        Some(key) => LispObject::cons(key.0, gethash(key.0, table, Qnil)),
+        None => Qnil,
+    }
+}
+
+/// Return the entry of the smallest key of the sorted map MAP, as
+/// (KEY . VALUE), or nil if MAP is empty.
+#[lisp_fn]
+pub fn sorted_map_first(map: LispObject) -> LispObject {
+    let (id, table) = sorted_map_parts(map);
+    let key = with_sorted_keys(id, |keys| keys.iter().next().cloned());
+    sorted_map_entry(key, table)
+}
+
+/// Return the entry of the largest key of the sorted map MAP, as
+/// (KEY . VALUE), or nil if MAP is empty.
+#[lisp_fn]
+pub fn sorted_map_last(map: LispObject) -> LispObject {
+    let (id, table) = sorted_map_parts(map);
+    let key = with_sorted_keys(id, |keys| keys.iter().next_back().cloned());
+    sorted_map_entry(key, table)
+}
+
+/// Call FUNCTION for all the entries of the sorted map MAP, in
+/// increasing order of their keys.  FUNCTION is called with two
+/// arguments, KEY and VALUE.  Return nil.
+///
+/// If FROM is non-nil, start at the first key not less than FROM.  If
+/// TO is non-nil, stop before the first key not less than TO.
+///
+/// The keys to visit are fixed when this starts: entries added by
+/// FUNCTION are not visited, nor are entries it removes.
+#[lisp_fn(min = "2")]
+pub fn sorted_map_map(function: LispObject, map: LispObject, from: LispObject, to: LispObject) {
+    let (id, table) = sorted_map_parts(map);
+    let start = if from.is_nil() {
+        Bound::Unbounded
+    } else {
+        Bound::Included(sort_key(from))
+    };
+    let end = if to.is_nil() {
+        Bound::Unbounded
+    } else {
+        Bound::Excluded(sort_key(to))
+    };
+    let keys: Vec<LispObject> = with_sorted_keys(id, |keys| match (start, end) {
+        // A range which ends before it starts is empty.
+        (Bound::Included(from), Bound::Excluded(to)) if from >= to => Vec::new(),
+        _ => keys.range((start, end)).map(|key| key.0).collect(),
+    });
+
+    // The list keeps the keys alive while FUNCTION runs.
+    let keys = list(&keys);
+    for key in keys.iter_cars(LispConsEndChecks::off, LispConsCircularChecks::off) {
+        if let Found(idx) = table.lookup(key) {
+            call!(function, key, table.get_hash_value(idx));
+        }
+    }
+}
+
+/// Forget the order of the keys of the sorted map numbered ID.
+/// This is for internal use by the finalizer of sorted maps.
+#[lisp_fn(name = "sorted-map--release")]
+pub fn sorted_map_release(id: EmacsInt) -> bool {
+    SORTED_MAPS.lock().unwrap().maps.remove(&id).is_some()
+}
+
+/// Return t if OBJECT is a heap.
+#[lisp_fn]
+pub fn heap_p(object: LispObject) -> bool {
+    recordp(object) && aref(object, 0) == Qheap
+}
+
+/// Return the predicate, the element vector and the number of elements
+/// of the heap HEAP.
+fn heap_parts(heap: LispObject) -> (LispObject, LispObject, EmacsInt) {
+    if !heap_p(heap) {
+        wrong_type!(Qheap_p, heap);
+    }
+    (
+        aref(heap, 1),
+        aref(heap, 2),
+        aref(heap, 3).as_fixnum_or_error(),
+    )
+}
+
+/// Create and return a new empty heap, or priority queue, which gives
+/// back its elements smallest first.
+///
+/// PREDICATE is called with two elements and returns non-nil if the
+/// first is smaller, which is to say it comes out first; it defaults
+/// to `<'.  SIZE is the number of elements to make room for at first.
+#[lisp_fn(min = "0")]
+pub fn make_heap(predicate: LispObject, size: LispObject) -> LispObject {
+    let predicate = if predicate.is_nil() {
+        intern("<").into()
+    } else {
+        predicate
+    };
+    let size = if size.is_nil() {
+        8
+    } else {
+        size.as_natnum_or_error().max(1)
+    };
+    let elements = unsafe { Fmake_vector(LispObject::from(size), Qnil) };
+    callN_raw!(Frecord, Qheap, predicate, elements, LispObject::from(0))
+}
+
+/// Return true if the element of ELEMENTS at A is smaller than the one
+/// at B, as PREDICATE says.
+fn heap_before(predicate: LispObject, elements: LispObject, a: EmacsInt, b: EmacsInt) -> bool {
+    call!(predicate, aref(elements, a), aref(elements, b)).is_not_nil()
+}
+
+fn heap_swap(elements: LispObject, a: EmacsInt, b: EmacsInt) {
+    let element = aref(elements, a);
+    aset(elements, a, aref(elements, b));
+    aset(elements, b, element);
+}
+
+/// Add OBJECT to the heap HEAP, and return OBJECT.
+#[lisp_fn]
+pub fn heap_push(heap: LispObject, object: LispObject) -> LispObject {
+    let (predicate, mut elements, count) = heap_parts(heap);
+    let size = elements.as_vector_or_error().len() as EmacsInt;
+    if count == size {
+        let new_elements = unsafe { Fmake_vector(LispObject::from(size * 2), Qnil) };
+        for position in 0..count {
+            aset(new_elements, position, aref(elements, position));
+        }
+        aset(heap, 2, new_elements);
+        elements = new_elements;
+    }
+    aset(elements, count, object);
+    aset(heap, 3, LispObject::from(count + 1));
+
+    let mut position = count;
+    while position > 0 {
+        let parent = (position - 1) / 2;
+        if !heap_before(predicate, elements, position, parent) {
+            break;
+        }
+        heap_swap(elements, position, parent);
+        position = parent;
+    }
+    object
+}
+
+/// Remove the smallest element of the heap HEAP and return it, or
+/// return nil if HEAP is empty.
+#[lisp_fn]
+pub fn heap_pop(heap: LispObject) -> LispObject {
+    let (predicate, elements, count) = heap_parts(heap);
+    if count == 0 {
+        return Qnil;
+    }
+    let top = aref(elements, 0);
+    let count = count - 1;
+    aset(elements, 0, aref(elements, count));
+    aset(elements, count, Qnil);
+    aset(heap, 3, LispObject::from(count));
+
+    let mut position = 0;
+    loop {
+        let left = 2 * position + 1;
+        if left >= count {
+            break;
+        }
+        let right = left + 1;
+        let child = if right < count && heap_before(predicate, elements, right, left) {
+            right
+        } else {
+            left
+        };
+        if !heap_before(predicate, elements, child, position) {
+            break;
+        }
+        heap_swap(elements, position, child);
+        position = child;
+    }
+    top
+}
+
+/// Return the smallest element of the heap HEAP without removing it,
+/// or nil if HEAP is empty.
+#[lisp_fn]
+pub fn heap_peek(heap: LispObject) -> LispObject {
+    let (_, elements, count) = heap_parts(heap);
+    if count == 0 {
+        Qnil
+    } else {
+        aref(elements, 0)
+    }
+}
+
+/// Return the number of elements in the heap HEAP.
+#[lisp_fn]
+pub fn heap_count(heap: LispObject) -> EmacsInt {
+    let (_, _, count) = heap_parts(heap);
+    count
+}
+
+/// Remove all the elements of the heap HEAP, and return HEAP.
+#[lisp_fn]
+pub fn heap_clear(heap: LispObject) -> LispObject {
+    let (_, elements, count) = heap_parts(heap);
+    for position in 0..count {
+        aset(elements, position, Qnil);
+    }
+    aset(heap, 3, LispObject::from(0));
+    heap
+}
+
+#[no_mangle]
+pub extern "C" fn syms_of_collections() {
+    def_lisp_sym!(Qordered_hash_table, "ordered-hash-table");
+    def_lisp_sym!(Qordered_hash_table_p, "ordered-hash-table-p");
+    def_lisp_sym!(Qsorted_map, "sorted-map");
+    def_lisp_sym!(Qsorted_map_p, "sorted-map-p");
+    def_lisp_sym!(Qsorted_map_key_p, "sorted-map-key-p");
+    def_lisp_sym!(Qsorted_map__release, "sorted-map--release");
+    def_lisp_sym!(Qheap, "heap");
+    def_lisp_sym!(Qheap_p, "heap-p");
+}
+
+include!(concat!(env!("OUT_DIR"), "/collections_exports.rs"));
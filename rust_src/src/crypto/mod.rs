@@ -1,19 +1,35 @@
 #![allow(dead_code)] // XXX unused code belongs into translation of new extract_data_from_object fn
 
-use libc::ptrdiff_t;
+use blake2::{Blake2b, Blake2s};
+use errno::{set_errno, Errno};
+use libc::{c_char, ptrdiff_t};
 use md5;
 use sha1;
 use sha2::{Digest, Sha224, Sha256, Sha384, Sha512};
+use sha3::{Sha3_224, Sha3_256, Sha3_384, Sha3_512};
 use std;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::ptr;
 use std::slice;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, TryRecvError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
 
 use remacs_macros::lisp_fn;
 
 use crate::{
-    buffers::{buffer_file_name, LispBufferOrName, LispBufferRef},
+    buffers::{buffer_file_name, validate_region, LispBufferOrName, LispBufferRef},
+    fileio::encoded_file_path,
     lisp::defsubr,
     lisp::LispObject,
+    lists::list,
+    marker::buf_charpos_to_bytepos,
     multibyte::LispStringRef,
+    obarray::intern,
     remacs_sys::{
         code_convert_string, extract_data_from_object, preferred_coding_system,
         string_char_to_byte, validate_subarray, Fcoding_system_p,
@@ -21,12 +37,16 @@ use crate::{
     remacs_sys::{
         current_thread, make_buffer_string, record_unwind_current_buffer, set_buffer_internal,
     },
+    remacs_sys::{encode_file_name, maybe_quit, report_file_error, EmacsDouble},
     remacs_sys::{globals, Ffind_operation_coding_system, Flocal_variable_p},
     remacs_sys::{make_specified_string, make_uninit_string, EmacsInt},
+    remacs_sys::{Fexpand_file_name, Ffind_file_name_handler},
     remacs_sys::{
-        Qbuffer_file_coding_system, Qcoding_system_error, Qmd5, Qnil, Qraw_text, Qsha1, Qsha224,
-        Qsha256, Qsha384, Qsha512, Qstringp, Qwrite_region,
+        Qblake2b, Qblake2s, Qbuffer_file_coding_system, Qcoding_system_error, Qmd5, Qnil,
+        Qraw_text, Qsha1, Qsha224, Qsha256, Qsha384, Qsha3_224, Qsha3_256, Qsha3_384, Qsha3_512,
+        Qsha512, Qstringp, Qwrite_region,
     },
+    strings::string_to_multibyte,
     symbols::{fboundp, symbol_name},
     threads::ThreadState,
 };
@@ -39,6 +59,12 @@ enum HashAlg {
     SHA256,
     SHA384,
     SHA512,
+    BLAKE2B,
+    BLAKE2S,
+    SHA3_224,
+    SHA3_256,
+    SHA3_384,
+    SHA3_512,
 }
 
 static MD5_DIGEST_LEN: usize = 16;
@@ -47,6 +73,12 @@ static SHA224_DIGEST_LEN: usize = 224 / 8;
 static SHA256_DIGEST_LEN: usize = 256 / 8;
 static SHA384_DIGEST_LEN: usize = 384 / 8;
 static SHA512_DIGEST_LEN: usize = 512 / 8;
+static BLAKE2B_DIGEST_LEN: usize = 512 / 8;
+static BLAKE2S_DIGEST_LEN: usize = 256 / 8;
+static SHA3_224_DIGEST_LEN: usize = 224 / 8;
+static SHA3_256_DIGEST_LEN: usize = 256 / 8;
+static SHA3_384_DIGEST_LEN: usize = 384 / 8;
+static SHA3_512_DIGEST_LEN: usize = 512 / 8;
 
 fn hash_alg(algorithm: LispObject) -> HashAlg {
     algorithm.as_symbol_or_error();
@@ -62,6 +94,18 @@ fn hash_alg(algorithm: LispObject) -> HashAlg {
         HashAlg::SHA384
     } else if algorithm == Qsha512 {
         HashAlg::SHA512
+    } else if algorithm == Qblake2b {
+        HashAlg::BLAKE2B
+    } else if algorithm == Qblake2s {
+        HashAlg::BLAKE2S
+    } else if algorithm == Qsha3_224 {
+        HashAlg::SHA3_224
+    } else if algorithm == Qsha3_256 {
+        HashAlg::SHA3_256
+    } else if algorithm == Qsha3_384 {
+        HashAlg::SHA3_384
+    } else if algorithm == Qsha3_512 {
+        HashAlg::SHA3_512
     } else {
         let name = symbol_name(algorithm.as_symbol_or_error()).as_string_or_error();
         error!("Invalid algorithm arg: {:?}\0", &name.as_slice());
@@ -313,7 +357,8 @@ pub fn md5(
 
 /// Return the secure hash of OBJECT, a buffer or string.
 /// ALGORITHM is a symbol specifying the hash to use:
-/// md5, sha1, sha224, sha256, sha384 or sha512.
+/// md5, sha1, sha224, sha256, sha384, sha512, blake2b, blake2s,
+/// sha3-224, sha3-256, sha3-384 or sha3-512.
 ///
 /// The two optional arguments START and END are positions specifying for
 /// which part of OBJECT to compute the hash.  If nil or omitted, uses the
@@ -342,7 +387,60 @@ fn _secure_hash(
     noerror: LispObject,
     binary: LispObject,
 ) -> LispObject {
-    type HashFn = fn(&[u8], &mut [u8]);
+    let chunks = hash_input(object, start, end, coding_system, noerror);
+    let (digest_size, hash_func) = hash_function(algorithm);
+    make_digest(digest_size, binary, |dest_buf| hash_func(&chunks, dest_buf))
+}
+
+type HashFn = fn(&[&[u8]], &mut [u8]);
+
+/// Return the digest size of ALGORITHM and the function computing it.
+fn hash_function(algorithm: HashAlg) -> (usize, HashFn) {
+    match algorithm {
+        HashAlg::MD5 => (MD5_DIGEST_LEN, md5_buffer as HashFn),
+        HashAlg::SHA1 => (SHA1_DIGEST_LEN, sha1_buffer as HashFn),
+        HashAlg::SHA224 => (SHA224_DIGEST_LEN, sha224_buffer as HashFn),
+        HashAlg::SHA256 => (SHA256_DIGEST_LEN, sha256_buffer as HashFn),
+        HashAlg::SHA384 => (SHA384_DIGEST_LEN, sha384_buffer as HashFn),
+        HashAlg::SHA512 => (SHA512_DIGEST_LEN, sha512_buffer as HashFn),
+        HashAlg::BLAKE2B => (BLAKE2B_DIGEST_LEN, blake2b_buffer as HashFn),
+        HashAlg::BLAKE2S => (BLAKE2S_DIGEST_LEN, blake2s_buffer as HashFn),
+        HashAlg::SHA3_224 => (SHA3_224_DIGEST_LEN, sha3_224_buffer as HashFn),
+        HashAlg::SHA3_256 => (SHA3_256_DIGEST_LEN, sha3_256_buffer as HashFn),
+        HashAlg::SHA3_384 => (SHA3_384_DIGEST_LEN, sha3_384_buffer as HashFn),
+        HashAlg::SHA3_512 => (SHA3_512_DIGEST_LEN, sha3_512_buffer as HashFn),
+    }
+}
+
+/// Return the number of bytes ALGORITHM consumes per compression
+/// round, which is the key block size used by HMAC.
+fn hash_block_size(algorithm: HashAlg) -> usize {
+    match algorithm {
+        HashAlg::MD5 | HashAlg::SHA1 | HashAlg::SHA224 | HashAlg::SHA256 => 64,
+        HashAlg::SHA384 | HashAlg::SHA512 => 128,
+        HashAlg::BLAKE2B => 128,
+        HashAlg::BLAKE2S => 64,
+        // The SHA-3 block size is its rate: 1600 bits less twice the digest size.
+        HashAlg::SHA3_224 => 144,
+        HashAlg::SHA3_256 => 136,
+        HashAlg::SHA3_384 => 104,
+        HashAlg::SHA3_512 => 72,
+    }
+}
+
+/// Return the bytes of OBJECT to be hashed, as slices which are to be
+/// hashed one after the other.  The arguments are as for
+/// `secure-hash' and `md5'.
+fn hash_input<'a>(
+    object: LispObject,
+    start: LispObject,
+    end: LispObject,
+    coding_system: LispObject,
+    noerror: LispObject,
+) -> Vec<&'a [u8]> {
+    if let Some(chunks) = unibyte_buffer_region(object, start, end, coding_system, noerror) {
+        return chunks;
+    }
 
     let spec = list!(object, start, end, coding_system, noerror);
     let mut start_byte: ptrdiff_t = 0;
@@ -359,16 +457,16 @@ fn _secure_hash(
             (end_byte - start_byte) as usize,
         )
     };
+    vec![input_slice]
+}
 
-    let (digest_size, hash_func) = match algorithm {
-        HashAlg::MD5 => (MD5_DIGEST_LEN, md5_buffer as HashFn),
-        HashAlg::SHA1 => (SHA1_DIGEST_LEN, sha1_buffer as HashFn),
-        HashAlg::SHA224 => (SHA224_DIGEST_LEN, sha224_buffer as HashFn),
-        HashAlg::SHA256 => (SHA256_DIGEST_LEN, sha256_buffer as HashFn),
-        HashAlg::SHA384 => (SHA384_DIGEST_LEN, sha384_buffer as HashFn),
-        HashAlg::SHA512 => (SHA512_DIGEST_LEN, sha512_buffer as HashFn),
-    };
-
+/// Return a new string holding a digest of DIGEST_SIZE bytes, which
+/// FILL writes into the start of the buffer it is given.  Unless
+/// BINARY is non-nil, the digest is returned as a hex string.
+fn make_digest<F>(digest_size: usize, binary: LispObject, fill: F) -> LispObject
+where
+    F: FnOnce(&mut [u8]),
+{
     let buffer_size = if binary.is_nil() {
         (digest_size * 2) as EmacsInt
     } else {
@@ -376,13 +474,149 @@ fn _secure_hash(
     };
     let digest = unsafe { make_uninit_string(buffer_size as EmacsInt) };
     let mut digest_str = digest.as_string_or_error();
-    hash_func(input_slice, digest_str.as_mut_slice());
+    fill(digest_str.as_mut_slice());
     if binary.is_nil() {
         hexify_digest_string(digest_str.as_mut_slice(), digest_size);
     }
     digest
 }
 
+/// Return the bytes of OBJECT, a string or buffer, as a vector.
+/// Multibyte text is encoded as for `secure-hash'.
+fn input_bytes(object: LispObject) -> Vec<u8> {
+    hash_input(object, Qnil, Qnil, Qnil, Qnil).concat()
+}
+
+/// Write HMAC of MESSAGE under KEY to the start of DEST_BUF, as defined
+/// in RFC 2104.
+fn hmac_buffer(algorithm: HashAlg, key: &[u8], message: &[&[u8]], dest_buf: &mut [u8]) {
+    let (digest_size, hash_func) = hash_function(algorithm);
+    let block_size = hash_block_size(algorithm);
+
+    let mut key_block = vec![0; block_size];
+    if key.len() > block_size {
+        hash_func(&[key], &mut key_block[..digest_size]);
+    } else {
+        key_block[..key.len()].copy_from_slice(key);
+    }
+    let inner_pad: Vec<u8> = key_block.iter().map(|b| b ^ 0x36).collect();
+    let outer_pad: Vec<u8> = key_block.iter().map(|b| b ^ 0x5c).collect();
+
+    let mut chunks = vec![&inner_pad[..]];
+    chunks.extend_from_slice(message);
+    let mut inner = vec![0; digest_size];
+    hash_func(&chunks, &mut inner);
+    hash_func(&[&outer_pad[..], &inner[..]], dest_buf);
+}
+
+/// Return the HMAC of MESSAGE using KEY and the hash ALGORITHM.
+/// ALGORITHM is a symbol, one of those in `secure-hash-algorithms'.
+/// KEY and MESSAGE are strings or buffers.  Multibyte text is encoded
+/// before hashing, in the same way as for `secure-hash'.
+///
+/// If BINARY is non-nil, returns a string in binary form.
+#[lisp_fn(min = "3")]
+pub fn hmac(
+    algorithm: LispObject,
+    key: LispObject,
+    message: LispObject,
+    binary: LispObject,
+) -> LispObject {
+    let algorithm = hash_alg(algorithm);
+    let (digest_size, _) = hash_function(algorithm);
+    // Copy the key, since extracting the message may run Lisp code.
+    let key = input_bytes(key);
+    let chunks = hash_input(message, Qnil, Qnil, Qnil, Qnil);
+    make_digest(digest_size, binary, |dest_buf| {
+        hmac_buffer(algorithm, &key, &chunks, dest_buf)
+    })
+}
+
+/// Expand the pseudorandom key PRK into LENGTH bytes of keying material.
+/// This is the HKDF-Expand step of RFC 5869, using HMAC with the hash
+/// ALGORITHM, one of those in `secure-hash-algorithms'.
+///
+/// PRK and INFO are strings or buffers; INFO may also be nil, which
+/// means no context information.  LENGTH may be at most 255 times the
+/// digest size of ALGORITHM.  The result is a unibyte string.
+#[lisp_fn(min = "4")]
+pub fn hkdf_expand(
+    algorithm: LispObject,
+    prk: LispObject,
+    info: LispObject,
+    length: EmacsInt,
+) -> LispObject {
+    let algorithm = hash_alg(algorithm);
+    let (digest_size, _) = hash_function(algorithm);
+    if length < 0 || length as usize > 255 * digest_size {
+        args_out_of_range!(
+            LispObject::from(length),
+            LispObject::from(255 * digest_size)
+        );
+    }
+
+    let prk = input_bytes(prk);
+    let info = if info.is_nil() {
+        Vec::new()
+    } else {
+        input_bytes(info)
+    };
+
+    let output = unsafe { make_uninit_string(length) };
+    let mut output_str = output.as_string_or_error();
+    let mut block: Vec<u8> = Vec::new();
+    for (counter, chunk) in output_str
+        .as_mut_slice()
+        .chunks_mut(digest_size)
+        .enumerate()
+    {
+        let previous = block;
+        block = vec![0; digest_size];
+        hmac_buffer(
+            algorithm,
+            &prk,
+            &[&previous[..], &info[..], &[counter as u8 + 1]],
+            &mut block,
+        );
+        chunk.copy_from_slice(&block[..chunk.len()]);
+    }
+    output
+}
+
+/// If OBJECT is a unibyte buffer, return the text between START and END
+/// as the slices on either side of the gap.  Unibyte text is hashed
+/// without any encoding, so it can be fed to the hasher in place instead
+/// of first being copied into a string.  Return None for anything else.
+fn unibyte_buffer_region<'a>(
+    object: LispObject,
+    start: LispObject,
+    end: LispObject,
+    coding_system: LispObject,
+    noerror: LispObject,
+) -> Option<Vec<&'a [u8]>> {
+    let buffer = object.as_buffer()?;
+    if buffer.multibyte_characters_enabled() {
+        return None;
+    }
+    if coding_system.is_not_nil() {
+        check_coding_system_or_error(coding_system, noerror);
+    }
+
+    let mut start_byte = start.map_or(buffer.begv, |v| {
+        v.as_number_coerce_marker_or_error().to_fixnum() as ptrdiff_t
+    });
+    let mut end_byte = end.map_or(buffer.zv, |v| {
+        v.as_number_coerce_marker_or_error().to_fixnum() as ptrdiff_t
+    });
+    if start_byte > end_byte {
+        std::mem::swap(&mut start_byte, &mut end_byte);
+    }
+    if !(buffer.begv <= start_byte && end_byte <= buffer.zv) {
+        args_out_of_range!(start, end);
+    }
+    Some(buffer.byte_chunks(start_byte, end_byte).collect())
+}
+
 /// To avoid a copy, buffer is both the source and the destination of
 /// this transformation. Buffer must contain len bytes of data and
 /// 2*len bytes of space for the final hex string.
@@ -404,45 +638,123 @@ fn hexify_digest_string(buffer: &mut [u8], len: usize) {
 // destination buffer is at least long enough to hold the
 // digest. Additionally, the caller may have been asked to return a
 // hex string, in which case dest_buf will be twice as long as the
-// digest.
+// digest. The input is given as a list of chunks which are hashed
+// in order, as though they were one contiguous buffer.
 
-fn md5_buffer(buffer: &[u8], dest_buf: &mut [u8]) {
-    let output = md5::compute(buffer);
+fn md5_buffer(chunks: &[&[u8]], dest_buf: &mut [u8]) {
+    let mut context = md5::Context::new();
+    for chunk in chunks {
+        context.consume(chunk);
+    }
+    let output = context.compute();
     dest_buf[..output.len()].copy_from_slice(&*output)
 }
 
-fn sha1_buffer(buffer: &[u8], dest_buf: &mut [u8]) {
+fn sha1_buffer(chunks: &[&[u8]], dest_buf: &mut [u8]) {
     let mut hasher = sha1::Sha1::new();
-    hasher.update(buffer);
+    for chunk in chunks {
+        hasher.update(chunk);
+    }
     let output = hasher.digest().bytes();
     dest_buf[..output.len()].copy_from_slice(&output)
 }
 
-/// Given an instance of `Digest`, and `buffer` write its hash to `dest_buf`.
-fn sha2_hash_buffer<D>(hasher: D, buffer: &[u8], dest_buf: &mut [u8])
+/// Given an instance of `Digest`, and `chunks` write their hash to `dest_buf`.
+fn digest_hash_buffer<D>(hasher: D, chunks: &[&[u8]], dest_buf: &mut [u8])
 where
     D: Digest,
 {
     let mut hasher = hasher;
-    hasher.input(buffer);
+    for chunk in chunks {
+        hasher.input(chunk);
+    }
     let output = hasher.result();
     dest_buf[..output.len()].copy_from_slice(&output)
 }
 
-fn sha224_buffer(buffer: &[u8], dest_buf: &mut [u8]) {
-    sha2_hash_buffer(Sha224::new(), buffer, dest_buf);
+fn sha224_buffer(chunks: &[&[u8]], dest_buf: &mut [u8]) {
+    digest_hash_buffer(Sha224::new(), chunks, dest_buf);
+}
+
+fn sha256_buffer(chunks: &[&[u8]], dest_buf: &mut [u8]) {
+    digest_hash_buffer(Sha256::new(), chunks, dest_buf);
+}
+
+fn sha384_buffer(chunks: &[&[u8]], dest_buf: &mut [u8]) {
+    digest_hash_buffer(Sha384::new(), chunks, dest_buf);
+}
+
+fn sha512_buffer(chunks: &[&[u8]], dest_buf: &mut [u8]) {
+    digest_hash_buffer(Sha512::new(), chunks, dest_buf);
+}
+
+fn blake2b_buffer(chunks: &[&[u8]], dest_buf: &mut [u8]) {
+    digest_hash_buffer(Blake2b::new(), chunks, dest_buf);
+}
+
+fn blake2s_buffer(chunks: &[&[u8]], dest_buf: &mut [u8]) {
+    digest_hash_buffer(Blake2s::new(), chunks, dest_buf);
+}
+
+fn sha3_224_buffer(chunks: &[&[u8]], dest_buf: &mut [u8]) {
+    digest_hash_buffer(Sha3_224::new(), chunks, dest_buf);
+}
+
+fn sha3_256_buffer(chunks: &[&[u8]], dest_buf: &mut [u8]) {
+    digest_hash_buffer(Sha3_256::new(), chunks, dest_buf);
+}
+
+fn sha3_384_buffer(chunks: &[&[u8]], dest_buf: &mut [u8]) {
+    digest_hash_buffer(Sha3_384::new(), chunks, dest_buf);
+}
+
+fn sha3_512_buffer(chunks: &[&[u8]], dest_buf: &mut [u8]) {
+    digest_hash_buffer(Sha3_512::new(), chunks, dest_buf);
 }
 
-fn sha256_buffer(buffer: &[u8], dest_buf: &mut [u8]) {
-    sha2_hash_buffer(Sha256::new(), buffer, dest_buf);
+/// Return a list of all the supported `secure_hash' algorithms.
+#[lisp_fn]
+pub fn secure_hash_algorithms() -> LispObject {
+    list(&[
+        Qmd5, Qsha1, Qsha224, Qsha256, Qsha384, Qsha512, Qblake2b, Qblake2s, Qsha3_224, Qsha3_256,
+        Qsha3_384, Qsha3_512,
+    ])
 }
 
-fn sha384_buffer(buffer: &[u8], dest_buf: &mut [u8]) {
-    sha2_hash_buffer(Sha384::new(), buffer, dest_buf);
+/// Return true if A and B hold the same bytes.  The time taken depends
+/// only on the lengths of A and B, not on where they first differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut difference: u8 = 0;
+    for (x, y) in a.iter().zip(b) {
+        difference |= x ^ y;
+    }
+    // A volatile read keeps the comparison from being turned into an
+    // early exit on the first mismatch.
+    unsafe { ptr::read_volatile(&difference) == 0 }
 }
 
-fn sha512_buffer(buffer: &[u8], dest_buf: &mut [u8]) {
-    sha2_hash_buffer(Sha512::new(), buffer, dest_buf);
+/// Return t if STRING1 and STRING2 have identical contents.
+/// Unlike `string=', the time taken does not depend on how much of the
+/// strings match, which makes this suitable for comparing secrets such
+/// as authentication tokens and message digests.  The lengths of the
+/// strings are not kept secret.
+///
+/// Text properties are ignored.  If one string is unibyte and the other
+/// multibyte, the unibyte string is compared as if converted with
+/// `string-to-multibyte', so that its bytes in the range 128..255 only
+/// match raw 8-bit bytes.
+#[lisp_fn]
+pub fn string_equal_constant_time(string1: LispStringRef, string2: LispStringRef) -> bool {
+    let (string1, string2) = match (string1.is_multibyte(), string2.is_multibyte()) {
+        (true, false) => (string1, string_to_multibyte(string2).as_string_or_error()),
+        (false, true) => (string_to_multibyte(string1).as_string_or_error(), string2),
+        _ => (string1, string2),
+    };
+    string1.len_chars() == string2.len_chars()
+        && constant_time_eq(string1.as_slice(), string2.as_slice())
 }
 
 /// Return a hash of the contents of BUFFER-OR-NAME.
@@ -453,16 +765,8 @@ pub fn buffer_hash(buffer_or_name: Option<LispBufferOrName>) -> LispObject {
     let b = buffer_or_name.map_or_else(ThreadState::current_buffer, |b| b.into());
     let mut ctx = sha1::Sha1::new();
 
-    ctx.update(unsafe {
-        slice::from_raw_parts(b.beg_addr(), (b.gpt_byte() - b.beg_byte()) as usize)
-    });
-    if b.gpt_byte() < b.z_byte() {
-        ctx.update(unsafe {
-            slice::from_raw_parts(
-                b.gap_end_addr(),
-                b.z_addr() as usize - b.gap_end_addr() as usize,
-            )
-        });
+    for chunk in b.byte_chunks(b.beg_byte(), b.z_byte()) {
+        ctx.update(chunk);
     }
 
     let formatted = ctx.digest().to_string();
@@ -475,4 +779,274 @@ pub fn buffer_hash(buffer_or_name: Option<LispBufferOrName>) -> LispObject {
     digest
 }
 
+/// Return the hash of the text of the current buffer from START to END.
+/// ALGORITHM is a symbol specifying the hash to use, as for
+/// `secure-hash'.  If BINARY is non-nil, returns a string in binary form.
+///
+/// Like `buffer-hash', this hashes the raw internal form of the text,
+/// disregarding any coding systems.  The text is hashed where it is in
+/// the buffer, without first being copied into a string, so this is
+/// cheap even for a large region.
+#[lisp_fn(min = "3")]
+pub fn buffer_hash_region(
+    start: LispObject,
+    end: LispObject,
+    algorithm: LispObject,
+    binary: LispObject,
+) -> LispObject {
+    let algorithm = hash_alg(algorithm);
+    let (mut start, mut end) = (start, end);
+    unsafe { validate_region(&mut start, &mut end) };
+    let start = start.as_fixnum_or_error() as ptrdiff_t;
+    let end = end.as_fixnum_or_error() as ptrdiff_t;
+
+    let mut buffer = ThreadState::current_buffer();
+    let start_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), start) };
+    let end_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), end) };
+    let chunks: Vec<&[u8]> = buffer.byte_chunks(start_byte, end_byte).collect();
+
+    let (digest_size, hash_func) = hash_function(algorithm);
+    make_digest(digest_size, binary, |dest_buf| hash_func(&chunks, dest_buf))
+}
+
+/// A hash computed a chunk at a time, for data which isn't all in memory
+/// at once.
+trait StreamHash: Send {
+    fn update(&mut self, data: &[u8]);
+    fn finish(self: Box<Self>) -> Vec<u8>;
+}
+
+struct Md5Stream(md5::Context);
+
+impl StreamHash for Md5Stream {
+    fn update(&mut self, data: &[u8]) {
+        self.0.consume(data);
+    }
+
+    fn finish(self: Box<Self>) -> Vec<u8> {
+        self.0.compute().to_vec()
+    }
+}
+
+struct Sha1Stream(sha1::Sha1);
+
+impl StreamHash for Sha1Stream {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finish(self: Box<Self>) -> Vec<u8> {
+        self.0.digest().bytes().to_vec()
+    }
+}
+
+struct DigestStream<D>(D);
+
+impl<D: Digest + Send> StreamHash for DigestStream<D> {
+    fn update(&mut self, data: &[u8]) {
+        self.0.input(data);
+    }
+
+    fn finish(self: Box<Self>) -> Vec<u8> {
+        self.0.result().to_vec()
+    }
+}
+
+fn stream_hash(algorithm: HashAlg) -> Box<StreamHash> {
+    match algorithm {
+        HashAlg::MD5 => Box::new(Md5Stream(md5::Context::new())),
+        HashAlg::SHA1 => Box::new(Sha1Stream(sha1::Sha1::new())),
+        HashAlg::SHA224 => Box::new(DigestStream(Sha224::new())),
+        HashAlg::SHA256 => Box::new(DigestStream(Sha256::new())),
+        HashAlg::SHA384 => Box::new(DigestStream(Sha384::new())),
+        HashAlg::SHA512 => Box::new(DigestStream(Sha512::new())),
+        HashAlg::BLAKE2B => Box::new(DigestStream(Blake2b::new())),
+        HashAlg::BLAKE2S => Box::new(DigestStream(Blake2s::new())),
+        HashAlg::SHA3_224 => Box::new(DigestStream(Sha3_224::new())),
+        HashAlg::SHA3_256 => Box::new(DigestStream(Sha3_256::new())),
+        HashAlg::SHA3_384 => Box::new(DigestStream(Sha3_384::new())),
+        HashAlg::SHA3_512 => Box::new(DigestStream(Sha3_512::new())),
+    }
+}
+
+/// The number of bytes of a file hashed at a time.
+const FILE_DIGEST_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Return the hash of the contents of the file at PATH.
+fn hash_file(path: &Path, mut hash: Box<StreamHash>) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0; FILE_DIGEST_CHUNK_SIZE];
+    loop {
+        match file.read(&mut buffer) {
+            Ok(0) => return Ok(hash.finish()),
+            Ok(read) => hash.update(&buffer[..read]),
+            Err(ref error) if error.kind() == io::ErrorKind::Interrupted => (),
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Return the bytes of the hexadecimal digest HEX, in which case and
+/// surrounding whitespace don't matter, or None if it isn't one.
+fn parse_hex_digest(hex: &[u8]) -> Option<Vec<u8>> {
+    let hex = String::from_utf8_lossy(hex);
+    let hex = hex.trim();
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        })
+        .collect()
+}
+
+/// The number of milliseconds between checks on digests being computed.
+const DIGEST_POLL_MS: u64 = 50;
+
+struct DigestJobs {
+    next_id: EmacsInt,
+    jobs: HashMap<EmacsInt, Receiver<io::Result<bool>>>,
+}
+
+lazy_static! {
+    /// The verifications started by `verify-file-digest' with a callback
+    /// that have not been reported yet, by job number.
+    static ref DIGEST_JOBS: Mutex<DigestJobs> = Mutex::new(DigestJobs {
+        next_id: 0,
+        jobs: HashMap::new(),
+    });
+}
+
+fn schedule_digest_poll(job: LispObject, filename: LispObject, callback: LispObject) {
+    call!(
+        LispObject::from(intern("run-with-timer")),
+        LispObject::from_float(DIGEST_POLL_MS as EmacsDouble / 1000.0),
+        Qnil,
+        LispObject::from(intern("crypto--verify-file-digest-poll")),
+        job,
+        filename,
+        callback
+    );
+}
+
+/// Return non-nil if the digest of the contents of FILE is EXPECTED.
+/// ALGORITHM is a symbol specifying the hash to use, as for
+/// `secure-hash', and EXPECTED is the digest as a hexadecimal string, in
+/// either case.  The file is hashed as it is read, a chunk at a time, on
+/// a separate thread, so that large downloads can be checked without
+/// reading them into memory.  The digests are compared in constant
+/// time.
+///
+/// Without CALLBACK, wait for the result, which can be interrupted with
+/// \\[keyboard-quit], and signal an error if FILE can't be read.  With
+/// CALLBACK, return a job number at once, and when the digest has been
+/// computed, call CALLBACK from a timer with two arguments: the expanded
+/// FILE, and t if its digest is EXPECTED, nil if it isn't, or a string
+/// describing the error if FILE couldn't be read.
+///
+/// FILE must be a local file.
+#[lisp_fn(min = "3")]
+pub fn verify_file_digest(
+    file: LispStringRef,
+    algorithm: LispObject,
+    expected: LispStringRef,
+    callback: LispObject,
+) -> LispObject {
+    let algorithm = hash_alg(algorithm);
+    let expected = match parse_hex_digest(expected.as_slice()) {
+        Some(expected) => expected,
+        None => error!("Invalid hexadecimal digest: {}", expected),
+    };
+    let directory = ThreadState::current_buffer().directory_;
+    let filename = unsafe { Fexpand_file_name(file.into(), directory) };
+    let operation = LispObject::from(intern("verify-file-digest"));
+    if unsafe { Ffind_file_name_handler(filename, operation) }.is_not_nil() {
+        error!("Cannot verify the digest of remote file {}", file);
+    }
+
+    let path = encoded_file_path(unsafe { encode_file_name(filename) });
+    let hash = stream_hash(algorithm);
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        let result = hash_file(&path, hash).map(|digest| constant_time_eq(&digest, &expected));
+        // Nobody may be listening anymore if the wait was quit.
+        let _ = sender.send(result);
+    });
+
+    if callback.is_not_nil() {
+        let mut jobs = DIGEST_JOBS.lock().unwrap();
+        jobs.next_id += 1;
+        let id = jobs.next_id;
+        jobs.jobs.insert(id, receiver);
+        drop(jobs);
+
+        schedule_digest_poll(LispObject::from(id), filename, callback);
+        return LispObject::from(id);
+    }
+
+    let result = loop {
+        match receiver.recv_timeout(Duration::from_millis(DIGEST_POLL_MS)) {
+            Ok(result) => break result,
+            Err(RecvTimeoutError::Timeout) => unsafe { maybe_quit() },
+            Err(RecvTimeoutError::Disconnected) => {
+                error!("Hashing thread exited unexpectedly");
+            }
+        }
+    };
+    match result {
+        Ok(matches) => LispObject::from(matches),
+        Err(error) => unsafe {
+            set_errno(Errno(error.raw_os_error().unwrap_or(libc::EIO)));
+            report_file_error("Reading file\0".as_ptr() as *const c_char, filename)
+        },
+    }
+}
+
+/// Check on the verification JOB of FILENAME.
+/// Call CALLBACK as documented in `verify-file-digest' if JOB is
+/// finished, and reschedule the check otherwise.
+#[lisp_fn(name = "crypto--verify-file-digest-poll")]
+pub fn verify_file_digest_poll(job: EmacsInt, filename: LispObject, callback: LispObject) {
+    let result = {
+        let mut jobs = DIGEST_JOBS.lock().unwrap();
+        let result = match jobs.jobs.get(&job) {
+            None => return,
+            Some(receiver) => match receiver.try_recv() {
+                Err(TryRecvError::Empty) => None,
+                Ok(result) => Some(result.map_err(|err| err.to_string())),
+                Err(TryRecvError::Disconnected) => {
+                    Some(Err("Hashing thread exited unexpectedly".to_string()))
+                }
+            },
+        };
+        if result.is_some() {
+            jobs.jobs.remove(&job);
+        }
+        result
+    };
+
+    match result {
+        None => schedule_digest_poll(LispObject::from(job), filename, callback),
+        Some(Ok(matches)) => {
+            call!(callback, filename, LispObject::from(matches));
+        }
+        Some(Err(message)) => {
+            call!(callback, filename, LispObject::from(message.as_str()));
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn syms_of_crypto() {
+    def_lisp_sym!(Qblake2b, "blake2b");
+    def_lisp_sym!(Qblake2s, "blake2s");
+    def_lisp_sym!(Qsha3_224, "sha3-224");
+    def_lisp_sym!(Qsha3_256, "sha3-256");
+    def_lisp_sym!(Qsha3_384, "sha3-384");
+    def_lisp_sym!(Qsha3_512, "sha3-512");
+}
+
 include!(concat!(env!("OUT_DIR"), "/crypto_exports.rs"));
@@ -0,0 +1,160 @@
+//! CSV parsing.
+
+use std::io::Read;
+
+use ::csv::{ByteRecord, ReaderBuilder};
+use libc::{c_char, ptrdiff_t};
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    buffers::validate_region,
+    eval::define_error,
+    json::json_make_string,
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::list,
+    remacs_sys::{buf_charpos_to_bytepos, make_specified_string, wrong_choice, Fmake_vector},
+    remacs_sys::{QCquote, QCseparator, Qcsv_error, Qcsv_parse_error, Qerror, Qnil, Qplistp},
+    threads::ThreadState,
+};
+
+/// Return the byte for the character CHARACTER, which must be ASCII.
+fn csv_byte(character: LispObject) -> u8 {
+    let c = character.as_character_or_error();
+    if c >= 0x80 {
+        error!("CSV separator and quote must be ASCII characters");
+    }
+    c as u8
+}
+
+/// Return a CSV reader builder for the keyword arguments in ARGS.
+fn csv_reader_builder(args: &[LispObject]) -> ReaderBuilder {
+    let mut builder = ReaderBuilder::new();
+    builder.has_headers(false).flexible(true);
+
+    if args.len() % 2 != 0 {
+        wrong_type!(Qplistp, list(args));
+    }
+
+    for pair in args.chunks(2) {
+        let (key, value) = (pair[0], pair[1]);
+        if key == QCseparator {
+            builder.delimiter(csv_byte(value));
+        } else if key == QCquote {
+            if value.is_nil() {
+                builder.quoting(false);
+            } else {
+                builder.quote(csv_byte(value));
+            }
+        } else {
+            unsafe { wrong_choice(list!(QCseparator, QCquote), key) };
+        }
+    }
+    builder
+}
+
+/// Create a Lisp string from the bytes of FIELD, which are in Emacs's
+/// internal representation if MULTIBYTE.
+fn csv_make_field(field: &[u8], multibyte: bool) -> LispObject {
+    unsafe {
+        make_specified_string(
+            field.as_ptr() as *const c_char,
+            -1,
+            field.len() as ptrdiff_t,
+            multibyte,
+        )
+    }
+}
+
+/// Create a Lisp vector from the elements of ELEMENTS.
+fn csv_make_vector(elements: Vec<LispObject>) -> LispObject {
+    let result = unsafe { Fmake_vector(LispObject::from(elements.len()), Qnil) };
+    let mut vector = result.as_vector_or_error();
+    for (i, element) in elements.into_iter().enumerate() {
+        vector.set(i, element);
+    }
+    result
+}
+
+/// Parse the CSV text read from READER, whose bytes are in Emacs's
+/// internal representation if MULTIBYTE, following the keyword
+/// arguments in ARGS.
+fn csv_parse<R: Read>(reader: R, multibyte: bool, args: &[LispObject]) -> LispObject {
+    let mut reader = csv_reader_builder(args).from_reader(reader);
+    let mut record = ByteRecord::new();
+    let mut rows = Vec::new();
+    loop {
+        match reader.read_byte_record(&mut record) {
+            Ok(true) => {
+                let fields = record
+                    .iter()
+                    .map(|field| csv_make_field(field, multibyte))
+                    .collect();
+                rows.push(csv_make_vector(fields));
+            }
+            Ok(false) => break,
+            Err(error) => xsignal!(Qcsv_parse_error, json_make_string(&error.to_string())),
+        }
+    }
+    csv_make_vector(rows)
+}
+
+/// Parse the CSV text in STRING.
+/// Return a vector with a vector of strings for each row, in order.
+/// Fields may be quoted as described in RFC 4180: a quoted field can
+/// contain separators and newlines, and a doubled quote character
+/// stands for a single one.  Rows can be terminated by either LF or
+/// CRLF, and need not all have the same number of fields.  The
+/// arguments ARGS are a list of keyword/argument pairs:
+///
+/// The keyword argument `:separator' specifies the character which
+/// separates fields.  It defaults to ?,.
+///
+/// The keyword argument `:quote' specifies the character which quotes
+/// fields.  It defaults to ?\", and nil means fields are never quoted.
+///
+/// Both characters must be ASCII.
+/// usage: (csv-parse-string STRING &rest ARGS)
+#[lisp_fn(min = "1")]
+pub fn csv_parse_string(args: &mut [LispObject]) -> LispObject {
+    let string = args[0].as_string_or_error();
+    csv_parse(string.as_slice(), string.is_multibyte(), &args[1..])
+}
+
+/// Parse the CSV text in the region between START and END.
+/// The result and the arguments ARGS are the same as for
+/// `csv-parse-string', which see.
+/// usage: (csv-parse-region START END &rest ARGS)
+#[lisp_fn(min = "2")]
+pub fn csv_parse_region(args: &mut [LispObject]) -> LispObject {
+    let (mut start, mut end) = (args[0], args[1]);
+    unsafe { validate_region(&mut start, &mut end) };
+
+    let mut buffer = ThreadState::current_buffer();
+    let start = start.as_fixnum_or_error() as ptrdiff_t;
+    let end = end.as_fixnum_or_error() as ptrdiff_t;
+    let start_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), start) };
+    let end_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), end) };
+
+    // Read the text through the gap rather than copying it.
+    let (before_gap, after_gap) = buffer.region_slices(start_byte, end_byte);
+    csv_parse(
+        before_gap.chain(after_gap),
+        buffer.multibyte_characters_enabled(),
+        &args[2..],
+    )
+}
+
+#[no_mangle]
+pub extern "C" fn syms_of_csv() {
+    def_lisp_sym!(QCseparator, ":separator");
+    def_lisp_sym!(QCquote, ":quote");
+
+    def_lisp_sym!(Qcsv_error, "csv-error");
+    def_lisp_sym!(Qcsv_parse_error, "csv-parse-error");
+    define_error(Qcsv_error, "generic CSV error", Qerror);
+    define_error(Qcsv_parse_error, "could not parse CSV text", Qcsv_error);
+}
+
+include!(concat!(env!("OUT_DIR"), "/csv_exports.rs"));
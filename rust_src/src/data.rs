@@ -29,13 +29,15 @@ use crate::{
     remacs_sys::{
         Qarrayp, Qautoload, Qbool_vector, Qbuffer, Qchar_table, Qchoice, Qcompiled_function,
         Qcondition_variable, Qcons, Qcyclic_function_indirection, Qdefalias_fset_function, Qdefun,
-        Qfinalizer, Qfloat, Qfont, Qfont_entity, Qfont_object, Qfont_spec, Qframe,
+        Qephemeron, Qfinalizer, Qfloat, Qfont, Qfont_entity, Qfont_object, Qfont_spec, Qframe,
         Qfunction_documentation, Qhash_table, Qinteger, Qmany, Qmarker, Qmodule_function, Qmutex,
         Qnil, Qnone, Qoverlay, Qprocess, Qrange, Qstring, Qsubr, Qsymbol, Qterminal, Qthread,
-        Qunbound, Qunevalled, Quser_ptr, Qvector, Qwatchers, Qwindow, Qwindow_configuration,
+        Qtyped_vector, Qunbound, Qunevalled, Quser_ptr, Qvector, Qwatchers, Qweak_ref, Qwindow,
+        Qwindow_configuration,
     },
     symbols::LispSymbolRef,
     threads::ThreadState,
+    typed_vector::{typed_vector_ref, typed_vector_set},
 };
 
 // Lisp_Fwd predicates which can go away as the callers are ported to Rust
@@ -130,6 +132,9 @@ pub fn type_of(object: LispObject) -> LispObject {
                 pvec_type::PVEC_CONDVAR => Qcondition_variable,
                 pvec_type::PVEC_TERMINAL => Qterminal,
                 pvec_type::PVEC_MODULE_FUNCTION => Qmodule_function,
+                pvec_type::PVEC_WEAK_REF => Qweak_ref,
+                pvec_type::PVEC_EPHEMERON => Qephemeron,
+                pvec_type::PVEC_TYPED_VECTOR => Qtyped_vector,
                 pvec_type::PVEC_FONT => {
                     if object.is_font_spec() {
                         Qfont_spec
@@ -171,7 +176,7 @@ pub fn subr_lang(subr: LispSubrRef) -> LispObject {
 
 /// Return the element of ARRAY at index IDX.
 /// ARRAY may be a vector, a string, a char-table, a bool-vector, a record,
-/// or a byte-code object.  IDX starts at 0.
+/// a typed vector, or a byte-code object.  IDX starts at 0.
 #[lisp_fn]
 pub fn aref(array: LispObject, idx: EmacsInt) -> LispObject {
     if idx < 0 {
@@ -207,14 +212,16 @@ pub fn aref(array: LispObject, idx: EmacsInt) -> LispObject {
         }
         let v = unsafe { vl.as_vector_unchecked() };
         unsafe { v.get_unchecked(idx_u) }
+    } else if let Some(tv) = array.as_typed_vector() {
+        typed_vector_ref(tv, idx)
     } else {
         wrong_type!(Qarrayp, array);
     }
 }
 
 /// Store into the element of ARRAY at index IDX the value NEWELT.
-/// Return NEWELT.  ARRAY may be a vector, a string, a char-table or a
-/// bool-vector.  IDX starts at 0.
+/// Return NEWELT.  ARRAY may be a vector, a string, a char-table, a
+/// bool-vector or a typed vector.  IDX starts at 0.
 #[lisp_fn]
 pub fn aset(array: LispObject, idx: EmacsInt, newelt: LispObject) -> LispObject {
     if let Some(vl) = array.as_vectorlike() {
@@ -228,6 +235,8 @@ pub fn aset(array: LispObject, idx: EmacsInt, newelt: LispObject) -> LispObject
             unsafe { CHAR_TABLE_SET(array, idx as c_int, newelt) };
         } else if let Some(mut record) = vl.as_record() {
             record.set_checked(idx as usize, newelt);
+        } else if let Some(tv) = array.as_typed_vector() {
+            typed_vector_set(tv, idx, newelt);
         } else {
             unreachable!();
         }
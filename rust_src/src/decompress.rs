@@ -1,20 +1,53 @@
-//! Interface to zlib.
+//! Interface to zlib and other compression libraries.
 use std::cmp::min;
-use std::io::prelude::Read;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::prelude::{BufRead, Read};
+use std::io::BufReader;
+use std::ptr;
 use std::slice;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
 
-use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use brotli_decompressor::Decompressor;
+use flate2::bufread::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+use flate2::read::{DeflateEncoder, GzEncoder, ZlibEncoder};
+use flate2::{Compression, GzHeader};
+use libc::c_char;
 use remacs_macros::lisp_fn;
+use xz2::bufread::XzDecoder;
 
 use crate::{
-    buffers::validate_region,
+    buffers::{set_buffer, validate_region, LispBufferOrName},
+    data::set,
+    eval::{define_error, unbind_to},
+    fileio::encoded_file_path,
     lisp::defsubr,
     lisp::LispObject,
+    multibyte::LispStringRef,
+    obarray::intern,
     remacs_sys::{
         buf_charpos_to_bytepos, del_range_2, insert_from_gap, make_gap, maybe_quit, modify_text,
-        move_gap_both, signal_after_change, update_compositions, CHECK_HEAD,
+        move_gap_both, signal_after_change, update_compositions, wrong_choice, CHECK_HEAD,
     },
-    threads::ThreadState,
+    remacs_sys::{
+        code_convert_string, del_range, insert_from_string, make_buffer_string,
+        make_unibyte_string, record_unwind_current_buffer, set_point,
+    },
+    remacs_sys::{
+        encode_file_name, prepare_to_modify_buffer, report_file_error, Fexpand_file_name,
+        Fmake_variable_buffer_local,
+    },
+    remacs_sys::{
+        globals, EmacsDouble, EmacsInt, QCcomment, QCmtime, QCname, Qcorrupt, Qdeflate, Qerror,
+        Qgzip, Qnil, Qtruncated, Qundecided, Qzlib, Qzlib__gzip_header, Qzlib_error,
+    },
+    strings::string_to_unibyte,
+    symbols::symbol_value,
+    threads::{c_specpdl_index, ThreadState},
 };
 
 /// Return t if zlib decompression is available in this instance of Emacs.
@@ -23,41 +56,533 @@ pub fn zlib_available_p() -> bool {
     true
 }
 
-fn create_buffer_decoder<'a>(buffer: &'a [u8]) -> Box<Read + 'a> {
-    let magic_number = buffer[0];
+/// The framing used around a deflate stream.
+#[derive(Clone, Copy)]
+enum ZlibFormat {
+    Deflate,
+    Zlib,
+    Gzip,
+}
+
+impl ZlibFormat {
+    fn from_lisp(format: LispObject) -> Self {
+        if format.is_nil() || format.eq(Qgzip) {
+            ZlibFormat::Gzip
+        } else if format.eq(Qzlib) {
+            ZlibFormat::Zlib
+        } else if format.eq(Qdeflate) {
+            ZlibFormat::Deflate
+        } else {
+            unsafe { wrong_choice(list!(Qdeflate, Qzlib, Qgzip), format) }
+        }
+    }
+}
+
+/// What to do with the bytes of a region.
+#[derive(Clone, Copy)]
+enum RegionCodec<'a> {
+    Decode(DecodeOptions<'a>),
+    DecodeBrotli,
+    Encode(ZlibFormat),
+}
+
+/// Options controlling decompression.
+#[derive(Clone, Copy, Default)]
+struct DecodeOptions<'a> {
+    /// The preset dictionary the data was compressed with.
+    dictionary: Option<&'a [u8]>,
+    /// The largest number of bytes the data may decompress to.
+    max_output: Option<u64>,
+}
+
+impl<'a> DecodeOptions<'a> {
+    fn with_max_output(max_output: LispObject) -> Self {
+        DecodeOptions {
+            dictionary: None,
+            max_output: max_output_from_lisp(max_output),
+        }
+    }
+}
+
+/// Convert MAX-OUTPUT-BYTES, a natural number or nil, to a limit.
+fn max_output_from_lisp(max_output: LispObject) -> Option<u64> {
+    if max_output.is_nil() {
+        None
+    } else {
+        Some(max_output.as_natnum_or_error() as u64)
+    }
+}
+
+/// A reader that fails when INNER produces more than REMAINING bytes.
+struct LimitedReader<R> {
+    inner: R,
+    remaining: u64,
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            // The limit has been reached; this is only an error if there
+            // is more data to come.
+            let mut byte = [0];
+            return match self.inner.read(&mut byte)? {
+                0 => Ok(0),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "decompressed data exceeds the maximum size",
+                )),
+            };
+        }
+
+        let len = min(buf.len() as u64, self.remaining) as usize;
+        let read = self.inner.read(&mut buf[..len])?;
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+/// The magic number at the start of a Zstandard frame.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// The magic number at the start of an XZ stream: 0xFD, '7zXZ', 0x00.
+const XZ_MAGIC: [u8; 6] = [0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00];
+
+/// Return a new unibyte string holding BYTES.
+fn unibyte_string(bytes: &[u8]) -> LispObject {
+    unsafe { make_unibyte_string(bytes.as_ptr() as *const c_char, bytes.len() as isize) }
+}
+
+/// The metadata found in the header of a gzip stream.
+struct GzipHeaderInfo {
+    filename: Option<Vec<u8>>,
+    comment: Option<Vec<u8>>,
+    mtime: u32,
+}
+
+impl GzipHeaderInfo {
+    fn from_header(header: &GzHeader) -> Self {
+        GzipHeaderInfo {
+            filename: header.filename().map(|name| name.to_vec()),
+            comment: header.comment().map(|comment| comment.to_vec()),
+            mtime: header.mtime(),
+        }
+    }
+
+    fn to_plist(&self) -> LispObject {
+        let unibyte = |bytes: &Option<Vec<u8>>| match *bytes {
+            Some(ref bytes) => unibyte_string(bytes),
+            None => Qnil,
+        };
+        // A modification time of zero means none is recorded.
+        let mtime = if self.mtime == 0 {
+            Qnil
+        } else {
+            LispObject::from(self.mtime)
+        };
+
+        list!(
+            QCname,
+            unibyte(&self.filename),
+            QCmtime,
+            mtime,
+            QCcomment,
+            unibyte(&self.comment)
+        )
+    }
+}
+
+/// Record HEADER as the header of the gzip stream last decompressed into
+/// the current buffer, for `zlib-last-gzip-header'.
+fn set_gzip_header(header: Option<GzipHeaderInfo>) {
+    let plist = match header {
+        Some(ref header) => header.to_plist(),
+        None => Qnil,
+    };
+    set(Qzlib__gzip_header.into(), plist);
+}
+
+/// A decoder, and the header of the stream it decodes if that is in
+/// gzip format.
+type StreamDecoder<'a> = (Box<Read + 'a>, Option<GzipHeaderInfo>);
+
+/// Return a decoder reading from INPUT, choosing the decompressor
+/// from the magic number at the start of the data.  Only the first
+/// bytes of INPUT are looked at, so INPUT can be a stream.
+pub fn create_stream_decoder<'a, R: BufRead + 'a>(input: R) -> io::Result<Box<Read + 'a>> {
+    create_stream_decoder_with_options(input, DecodeOptions::default()).map(|(decoder, _)| decoder)
+}
+
+/// Like `create_stream_decoder', but following OPTIONS, and also
+/// returning the gzip header of the stream.
+fn create_stream_decoder_with_options<'a, R: BufRead + 'a>(
+    input: R,
+    options: DecodeOptions<'a>,
+) -> io::Result<StreamDecoder<'a>> {
+    let (decoder, header) = create_dictionary_stream_decoder(input, options.dictionary)?;
+
+    match options.max_output {
+        Some(remaining) => Ok((
+            Box::new(LimitedReader {
+                inner: decoder,
+                remaining,
+            }),
+            header,
+        )),
+        None => Ok((decoder, header)),
+    }
+}
+
+/// Like `create_stream_decoder_with_options', but decode zlib streams
+/// that require a preset dictionary, and raw deflate streams, with
+/// DICTIONARY.
+fn create_dictionary_stream_decoder<'a, R: BufRead + 'a>(
+    mut input: R,
+    dictionary: Option<&'a [u8]>,
+) -> io::Result<StreamDecoder<'a>> {
+    let (is_zstd, is_xz, magic_number, has_dictid) = {
+        let magic = input.fill_buf()?;
+        (
+            magic.starts_with(&ZSTD_MAGIC),
+            magic.starts_with(&XZ_MAGIC),
+            magic.first().cloned(),
+            magic.len() >= 2 && magic[1] & ZLIB_FDICT != 0,
+        )
+    };
+
+    if is_zstd {
+        return Ok((Box::new(zstd::Decoder::new(input)?), None));
+    }
 
-    match magic_number {
+    if is_xz {
+        return Ok((Box::new(XzDecoder::new(input)), None));
+    }
+
+    match (magic_number, dictionary) {
+        // Zlib, compressed with a preset dictionary.
+        (Some(0x78), Some(dictionary)) if has_dictid => {
+            let mut header = [0; 6];
+            input.read_exact(&mut header)?;
+            let dictid = u32::from(header[2]) << 24
+                | u32::from(header[3]) << 16
+                | u32::from(header[4]) << 8
+                | u32::from(header[5]);
+            if dictid != adler32(dictionary) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the dictionary does not match the one used for compression",
+                ));
+            }
+            Ok((create_dictionary_decoder(input, dictionary)?, None))
+        }
         // Zlib
-        0x78 => Box::new(ZlibDecoder::new(buffer)),
-        // Gzlib
-        0x1F => Box::new(GzDecoder::new(buffer)),
+        (Some(0x78), _) => Ok((Box::new(ZlibDecoder::new(input)), None)),
+        // Gzlib.  Like `gzip -d', decompress all members of the stream,
+        // not just the first one.
+        (Some(0x1F), _) => {
+            let decoder = MultiGzDecoder::new(input);
+            let header = decoder.header().map(GzipHeaderInfo::from_header);
+            Ok((Box::new(decoder), header))
+        }
         // Assume the data is raw, if neither zlib nor gzib header can be found.
-        _ => Box::new(DeflateDecoder::new(buffer)),
+        (_, Some(dictionary)) => Ok((create_dictionary_decoder(input, dictionary)?, None)),
+        (_, None) => Ok((Box::new(DeflateDecoder::new(input)), None)),
     }
 }
 
-/// Decompress a gzip- or zlib-compressed region.
-/// Replace the text in the region by the decompressed data.
-/// On failure, return nil and leave the data in place.
-/// This function can be called only in unibyte buffers.
-#[lisp_fn]
-pub fn zlib_decompress_region(mut start: LispObject, mut end: LispObject) -> bool {
-    unsafe { validate_region(&mut start, &mut end) };
+/// The flag in the second byte of a zlib header telling that the header
+/// is followed by the checksum of a preset dictionary.
+const ZLIB_FDICT: u8 = 0x20;
 
-    let mut current_buffer = ThreadState::current_buffer();
+/// Return the Adler-32 checksum of DATA, as used by zlib to identify
+/// preset dictionaries.
+fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
 
-    if current_buffer.multibyte_characters_enabled() {
-        error!("This function can be called only in unibyte buffers");
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 is the largest number of bytes that can be summed before
+    // B may overflow.
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+    b << 16 | a
+}
+
+/// Return a decoder for the raw deflate stream INPUT, compressed with
+/// the preset DICTIONARY.  The dictionary is fed to the decoder as
+/// stored blocks ahead of the data, so that it fills the window just
+/// like a preset dictionary does, and then skipped in the output.
+fn create_dictionary_decoder<'a, R: BufRead + 'a>(
+    input: R,
+    dictionary: &'a [u8],
+) -> io::Result<Box<Read + 'a>> {
+    let mut prefix = Vec::with_capacity(dictionary.len() + 5);
+    for chunk in dictionary.chunks(0xFFFF) {
+        let len = chunk.len() as u16;
+        // BFINAL is 0 and BTYPE is 00: a stored block that is not the last.
+        prefix.push(0);
+        prefix.extend_from_slice(&[len as u8, (len >> 8) as u8, !len as u8, (!len >> 8) as u8]);
+        prefix.extend_from_slice(chunk);
+    }
+
+    let mut decoder = DeflateDecoder::new(io::Cursor::new(prefix).chain(input));
+    let skipped = io::copy(
+        &mut (&mut decoder).take(dictionary.len() as u64),
+        &mut io::sink(),
+    )?;
+    if skipped != dictionary.len() as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "deflate stream ended prematurely",
+        ));
+    }
+
+    Ok(Box::new(decoder))
+}
+
+fn create_buffer_encoder<'a, R: Read + 'a>(buffer: R, format: ZlibFormat) -> Box<Read + 'a> {
+    let level = Compression::default();
+
+    match format {
+        ZlibFormat::Deflate => Box::new(DeflateEncoder::new(buffer, level)),
+        ZlibFormat::Zlib => Box::new(ZlibEncoder::new(buffer, level)),
+        ZlibFormat::Gzip => Box::new(GzEncoder::new(buffer, level)),
+    }
+}
+
+/// Return a reader running BUFFER through CODEC, and the gzip header
+/// of BUFFER if CODEC decompresses it and it is in gzip format.
+fn create_region_reader<'a, R: BufRead + 'a>(
+    buffer: R,
+    codec: RegionCodec<'a>,
+) -> io::Result<StreamDecoder<'a>> {
+    match codec {
+        RegionCodec::Decode(options) => create_stream_decoder_with_options(buffer, options),
+        RegionCodec::DecodeBrotli => Ok((Box::new(Decompressor::new(buffer, 4096)), None)),
+        RegionCodec::Encode(format) => Ok((create_buffer_encoder(buffer, format), None)),
+    }
+}
+
+/// A failure to compress or decompress data.
+struct CodecError {
+    error: io::Error,
+    /// The number of input bytes consumed when the error was detected.
+    consumed: usize,
+    /// Whether the input ended before the compressed data did.
+    truncated: bool,
+}
+
+impl CodecError {
+    /// Make an error for ERROR, detected after CONSUMED input bytes.
+    fn new(error: io::Error, consumed: usize) -> Self {
+        let truncated = error.kind() == io::ErrorKind::UnexpectedEof;
+
+        CodecError {
+            error,
+            consumed,
+            truncated,
+        }
+    }
+}
+
+/// Signal a `zlib-error' describing ERROR, which happened while
+/// decompressing the data starting at position START.  The error data
+/// is a list (REASON POSITION MESSAGE), where REASON is `truncated' if
+/// the data ended prematurely and `corrupt' otherwise, POSITION is the
+/// position at which the error was detected, and MESSAGE describes it.
+fn signal_codec_error(error: &CodecError, start: isize) -> ! {
+    let reason = if error.truncated {
+        Qtruncated
+    } else {
+        Qcorrupt
     };
 
-    let istart = start.as_fixnum_or_error() as isize;
-    let iend = end.as_fixnum_or_error() as isize;
+    xsignal!(
+        Qzlib_error,
+        reason,
+        LispObject::from_fixnum((start + error.consumed as isize) as EmacsInt),
+        LispObject::from(error.error.to_string().as_str())
+    );
+}
 
-    // Empty region, decompress failed.
-    if istart == iend {
-        return false;
+/// The chunk size used when `zlib-decompress-chunk-size' is not positive.
+const DEFAULT_CHUNK_SIZE: isize = 16 * 1024;
+
+/// Read everything READER produces into the gap of the current buffer,
+/// one chunk at a time, inserting each chunk as raw bytes before the
+/// gap.  In a multibyte buffer, bytes which are not ASCII become
+/// `eight-bit' characters.  INSERTED is incremented by the number of
+/// characters inserted so far, so that callers can undo a partial
+/// insertion on error.
+fn insert_from_reader(reader: &mut Read, inserted: &mut isize) -> io::Result<()> {
+    let current_buffer = ThreadState::current_buffer();
+    let multibyte = current_buffer.multibyte_characters_enabled();
+
+    let chunk_size = unsafe { globals.zlib_decompress_chunk_size };
+    let avail_out = if chunk_size > 0 {
+        chunk_size as isize
+    } else {
+        DEFAULT_CHUNK_SIZE
+    };
+    // In a multibyte buffer, each byte read may take up two bytes, so
+    // the chunk is read into the second half of twice the room, and
+    // spread out from the start.
+    let room = if multibyte { 2 * avail_out } else { avail_out };
+
+    loop {
+        let old_gap_size = current_buffer.gap_size();
+
+        if old_gap_size < room {
+            unsafe { make_gap(room - old_gap_size) };
+        }
+
+        let gap_writer =
+            unsafe { slice::from_raw_parts_mut(current_buffer.gap_start_addr(), room as usize) };
+        let read_start = (room - avail_out) as usize;
+
+        match reader.read(&mut gap_writer[read_start..])? {
+            // All data has been read.
+            0 => return Ok(()),
+
+            // Read one batch of data successfully.
+            // Continue with the remaining data.
+            read => {
+                let nbytes = if multibyte {
+                    spread_raw_bytes(gap_writer, read_start, read)
+                } else {
+                    read
+                };
+                let read = read as isize;
+                unsafe { insert_from_gap(read, nbytes as isize, false) };
+
+                *inserted += read;
+
+                unsafe { maybe_quit() };
+            }
+        }
+    }
+}
+
+/// Convert the LEN raw bytes at FROM in AREA to the multibyte form of
+/// `eight-bit' characters, moving them to the start of AREA, and return
+/// the number of bytes they take up.  FROM must be at least LEN, so
+/// that no byte is overwritten before it is converted.
+fn spread_raw_bytes(area: &mut [u8], from: usize, len: usize) -> usize {
+    let mut to = 0;
+    for i in from..from + len {
+        let byte = area[i];
+        if byte.is_ascii() {
+            area[to] = byte;
+            to += 1;
+        } else {
+            // The same as BYTE8_STRING.
+            area[to] = 0xC0 | ((byte >> 6) & 1);
+            area[to + 1] = 0x80 | (byte & 0x3F);
+            to += 2;
+        }
+    }
+    to
+}
+
+/// Insert everything READER produces at point in the current buffer as
+/// raw bytes, as `insert_from_reader' does, leaving point before it.
+/// Return the number of characters inserted.  On failure, the buffer is
+/// left unchanged.
+pub fn insert_stream_at_point(reader: &mut Read) -> io::Result<isize> {
+    let mut current_buffer = ThreadState::current_buffer();
+    let pt = current_buffer.pt;
+    let pt_byte = current_buffer.pt_byte;
+
+    unsafe {
+        prepare_to_modify_buffer(pt, pt, ptr::null_mut());
+
+        move_gap_both(pt, pt_byte);
+    }
+
+    let mut inserted: isize = 0;
+
+    match insert_from_reader(reader, &mut inserted) {
+        Ok(()) => {
+            unsafe {
+                signal_after_change(pt, 0, inserted);
+                update_compositions(pt, pt + inserted, CHECK_HEAD as i32);
+            };
+            Ok(inserted)
+        }
+        Err(error) => {
+            let end_byte =
+                unsafe { buf_charpos_to_bytepos(current_buffer.as_mut(), pt + inserted) };
+            unsafe {
+                del_range_2(pt, pt_byte, pt + inserted, end_byte, false);
+                signal_after_change(pt, 0, 0);
+            };
+            Err(error)
+        }
+    }
+}
+
+/// The magic number at the start of a gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+/// Return true if MAGIC, the start of some data, is the magic number of
+/// a gzip, zstd or xz stream.
+pub fn starts_compressed_stream(magic: &[u8]) -> bool {
+    magic.starts_with(&GZIP_MAGIC) || magic.starts_with(&ZSTD_MAGIC) || magic.starts_with(&XZ_MAGIC)
+}
+
+/// Return a decoder reading from INPUT, the body of an HTTP response
+/// sent with `Content-Encoding: ENCODING'.  The encodings known are
+/// gzip, deflate, which is zlib, brotli and zstd, and identity, the
+/// body as it is.
+pub fn create_content_decoder<'a, R: BufRead + 'a>(
+    encoding: &str,
+    input: R,
+) -> io::Result<Box<Read + 'a>> {
+    match encoding.trim().to_ascii_lowercase().as_str() {
+        "" | "identity" => Ok(Box::new(input)),
+        "gzip" | "x-gzip" | "zstd" => create_stream_decoder(input),
+        "deflate" => Ok(Box::new(ZlibDecoder::new(input))),
+        "br" => Ok(Box::new(Decompressor::new(input, 4096))),
+        encoding => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unknown content encoding {}", encoding),
+        )),
+    }
+}
+
+/// Insert the decompressed contents of the file read by INPUT at point
+/// in the current buffer, if it starts with the magic number of a gzip,
+/// zstd or xz stream, the formats compressed files come in.  The data
+/// is inserted as raw bytes, and point is left before it.  Return the
+/// number of characters inserted, or None if INPUT is not compressed in
+/// one of these formats, in which case nothing is read past its magic
+/// number.  On failure, the buffer is left unchanged.
+pub fn insert_compressed_file<R: BufRead>(mut input: R) -> io::Result<Option<isize>> {
+    if !starts_compressed_stream(input.fill_buf()?) {
+        return Ok(None);
     }
 
+    let mut decoder = create_stream_decoder(input)?;
+    insert_stream_at_point(&mut *decoder).map(Some)
+}
+
+/// Replace the text between ISTART and IEND in the current buffer by
+/// the result of running it through CODEC.  The new text is produced
+/// directly into the gap, after the old text, which is deleted once
+/// CODEC has consumed all of it, and its gzip header is returned if
+/// CODEC decompresses it and it is in gzip format.  On failure, leave
+/// the old text in place and return the error.
+fn transform_region(
+    istart: isize,
+    iend: isize,
+    codec: RegionCodec,
+) -> Result<Option<GzipHeaderInfo>, CodecError> {
+    let mut current_buffer = ThreadState::current_buffer();
+
     unsafe {
         // Do the following before manipulating the gap.
         modify_text(istart, iend);
@@ -65,97 +590,708 @@ pub fn zlib_decompress_region(mut start: LispObject, mut end: LispObject) -> boo
         move_gap_both(iend, iend);
     }
 
-    // Insert the decompressed data at the end of the compressed data.
+    // Insert the transformed data at the end of the original data.
     let charpos = iend;
     let bytepos = unsafe { buf_charpos_to_bytepos(current_buffer.as_mut(), iend as isize) };
     let old_pt = current_buffer.pt;
     current_buffer.set_pt_both(charpos, bytepos);
 
-    let compressed_buffer = unsafe {
+    let original_buffer = unsafe {
         slice::from_raw_parts(
             current_buffer.byte_pos_addr(istart),
             (iend - istart) as usize,
         )
     };
 
-    // The decompressor
-    let mut decoder = create_buffer_decoder(compressed_buffer);
+    let mut transformed_bytes: isize = 0;
 
-    let mut decompressed_bytes: isize = 0;
+    // The part of the original data not yet consumed.
+    let mut remaining = original_buffer;
 
-    loop {
-        let avail_out: isize = 16 * 1024;
+    // The decompressor or compressor
+    let result = create_region_reader(&mut remaining, codec).and_then(|(mut reader, header)| {
+        insert_from_reader(&mut *reader, &mut transformed_bytes).map(|()| header)
+    });
 
-        let old_gap_size = current_buffer.gap_size();
+    match result {
+        // Transforming all data finished.
+        Ok(header) => {
+            // Delete the original data.
+            unsafe {
+                del_range_2(
+                    istart, istart, // byte, char offsets the same
+                    iend, iend, false,
+                );
+                signal_after_change(istart, iend - istart, transformed_bytes);
+
+                update_compositions(istart, istart, CHECK_HEAD as i32);
+            };
+            Ok(header)
+        }
+
+        // Transformation failed.
+        Err(error) => {
+            // Delete any transformed data already inserted on error, but
+            // without calling the change hooks.
 
-        if old_gap_size < avail_out {
-            unsafe { make_gap(avail_out - old_gap_size) };
+            let data_orig = istart;
+            let data_start = iend;
+            let data_end = iend + transformed_bytes;
+
+            unsafe {
+                del_range_2(
+                    data_start, data_start, // byte, char offsets the same
+                    data_end, data_end, false,
+                );
+                update_compositions(data_start, data_start, CHECK_HEAD as i32);
+                // "Balance" the before-change-functions call, which would
+                // otherwise be left "hanging".
+                signal_after_change(data_orig, data_start - data_orig, data_start - data_orig);
+            };
+
+            // Put point where it was, or if the buffer has shrunk because the
+            // original data is bigger than the transformed, at
+            // point-max.
+            let charpos = min(old_pt, current_buffer.zv);
+            let bytepos = unsafe { buf_charpos_to_bytepos(current_buffer.as_mut(), charpos) };
+            current_buffer.set_pt_both(charpos, bytepos);
+
+            Err(CodecError::new(
+                error,
+                original_buffer.len() - remaining.len(),
+            ))
         }
+    }
+}
+
+/// Decompress COMPRESSED in memory, following OPTIONS.  Return the
+/// decompressed bytes, and the gzip header of COMPRESSED if it is in
+/// gzip format.
+fn decompress_bytes(
+    compressed: &[u8],
+    options: DecodeOptions,
+) -> Result<(Vec<u8>, Option<GzipHeaderInfo>), CodecError> {
+    let mut decompressed = Vec::new();
+    let mut remaining = compressed;
+
+    create_stream_decoder_with_options(&mut remaining, options)
+        .and_then(|(mut decoder, header)| {
+            decoder
+                .read_to_end(&mut decompressed)
+                .map(|_| (decompressed, header))
+        })
+        .map_err(|error| CodecError::new(error, compressed.len() - remaining.len()))
+}
+
+/// Decompress the text between ISTART and IEND in the current buffer
+/// in memory.  In a multibyte buffer, the compressed text must consist
+/// of ASCII and `eight-bit' characters only.
+fn decompress_region_bytes(
+    istart: isize,
+    iend: isize,
+    options: DecodeOptions,
+) -> Result<(Vec<u8>, Option<GzipHeaderInfo>), CodecError> {
+    decompress_bytes(region_unibyte_string(istart, iend).as_slice(), options)
+}
+
+/// Return the text between ISTART and IEND in the current buffer as a
+/// unibyte string.
+fn region_unibyte_string(istart: isize, iend: isize) -> LispStringRef {
+    let region = unsafe { make_buffer_string(istart, iend, false) };
+    string_to_unibyte(region.as_string_or_error()).as_string_or_error()
+}
+
+/// Insert the decompressed bytes DECOMPRESSED at point in the current
+/// buffer.  In a multibyte buffer, they are first decoded with the
+/// buffer's `buffer-file-coding-system', or `undecided' if that is nil.
+fn insert_decompressed(decompressed: &[u8]) {
+    let current_buffer = ThreadState::current_buffer();
 
-        let new_gap_size = avail_out;
+    let mut string = unibyte_string(decompressed);
 
-        let gap_writer = unsafe {
-            slice::from_raw_parts_mut(current_buffer.gap_start_addr(), new_gap_size as usize)
+    if current_buffer.multibyte_characters_enabled() {
+        let coding_system = current_buffer.buffer_file_coding_system_;
+        let coding_system = if coding_system.is_nil() {
+            Qundecided
+        } else {
+            coding_system
         };
 
-        match decoder.read(gap_writer) {
-            // Decompress all data finished.
-            Ok(0) => {
-                // Delete the compressed data.
-                unsafe {
-                    del_range_2(
-                        istart, istart, // byte, char offsets the same
-                        iend, iend, false,
-                    );
-                    signal_after_change(istart, iend - istart, decompressed_bytes);
-
-                    update_compositions(istart, istart, CHECK_HEAD as i32);
-                };
-                return true;
-            }
+        string = unsafe { code_convert_string(string, coding_system, Qnil, false, true, true) };
+    }
 
-            // Decompress one batch data successfully.
-            // Continue to decompress the remaining data.
-            Ok(decompressed) => {
-                let decompressed = decompressed as isize;
-                unsafe { insert_from_gap(decompressed, decompressed, false) };
+    let string_ref = string.as_string_or_error();
 
-                decompressed_bytes += decompressed;
+    unsafe {
+        insert_from_string(
+            string,
+            0,
+            0,
+            string_ref.len_chars(),
+            string_ref.len_bytes(),
+            false,
+        )
+    };
+}
 
-                unsafe { maybe_quit() };
+/// Decompress the raw bytes between ISTART and IEND in the current
+/// multibyte buffer.  The compressed text must consist of ASCII and
+/// `eight-bit' characters only.  The decompressed bytes are collected
+/// in a temporary unibyte area and decoded with the buffer's coding
+/// system before they replace the region, so that character and byte
+/// positions stay consistent.  Return the gzip header of the region if
+/// it is in gzip format.
+fn decompress_multibyte_region(
+    istart: isize,
+    iend: isize,
+    options: DecodeOptions,
+) -> Result<Option<GzipHeaderInfo>, CodecError> {
+    let (decompressed, header) = decompress_region_bytes(istart, iend, options)?;
+
+    unsafe {
+        del_range(istart, iend);
+        set_point(istart);
+    };
+
+    insert_decompressed(&decompressed);
+
+    Ok(header)
+}
+
+/// Decompress a gzip-, zlib-, zstd- or xz-compressed region.
+/// Replace the text in the region by the decompressed data.
+/// On failure, return nil and leave the data in place.
+///
+/// Normally, this function can be called only in unibyte buffers.  If
+/// the optional argument ALLOW-MULTIBYTE is non-nil, it may also be
+/// called in a multibyte buffer, where the region must contain only
+/// ASCII and raw-byte (`eight-bit') characters.  The decompressed data
+/// is then decoded with the buffer's `buffer-file-coding-system', or
+/// `undecided' if that is nil, before it is inserted.
+///
+/// If the optional argument STRICT is non-nil, signal a `zlib-error'
+/// instead of returning nil.  This catches truncated data as well as
+/// gzip streams whose CRC32 checksum or length does not match the
+/// trailer.  The error data is a list (REASON POSITION MESSAGE): REASON
+/// is `truncated' if the data ended prematurely, and `corrupt'
+/// otherwise; POSITION is the buffer position at which the error was
+/// detected; MESSAGE is a string describing the error.
+///
+/// The optional argument DICTIONARY is a unibyte string holding the
+/// preset dictionary the data was compressed with.  It is used to
+/// decode raw deflate streams, and zlib streams whose header says that
+/// a dictionary is needed; it must then be the same dictionary.
+///
+/// If the optional argument MAX-OUTPUT-BYTES is non-nil, it is the
+/// largest number of bytes the data may decompress to.  Data that
+/// decompresses to more is treated as invalid, which protects against
+/// decompression bombs in untrusted input.
+#[lisp_fn(min = "2")]
+pub fn zlib_decompress_region(
+    mut start: LispObject,
+    mut end: LispObject,
+    allow_multibyte: bool,
+    strict: bool,
+    dictionary: LispObject,
+    max_output_bytes: LispObject,
+) -> bool {
+    unsafe { validate_region(&mut start, &mut end) };
+
+    let current_buffer = ThreadState::current_buffer();
+
+    let multibyte = current_buffer.multibyte_characters_enabled();
+    if multibyte && !allow_multibyte {
+        error!("This function can be called only in unibyte buffers");
+    };
+
+    let istart = start.as_fixnum_or_error() as isize;
+    let iend = end.as_fixnum_or_error() as isize;
+
+    // Empty region, decompress failed.
+    if istart == iend {
+        return false;
+    }
+
+    let dictionary = if dictionary.is_nil() {
+        None
+    } else {
+        Some(string_to_unibyte(dictionary.as_string_or_error()).as_string_or_error())
+    };
+    let options = DecodeOptions {
+        dictionary: dictionary.as_ref().map(|dictionary| dictionary.as_slice()),
+        max_output: max_output_from_lisp(max_output_bytes),
+    };
+
+    let result = if multibyte {
+        decompress_multibyte_region(istart, iend, options)
+    } else {
+        transform_region(istart, iend, RegionCodec::Decode(options))
+    };
+
+    match result {
+        Ok(header) => {
+            set_gzip_header(header);
+            true
+        }
+        Err(ref error) => {
+            set_gzip_header(None);
+            if strict {
+                signal_codec_error(error, istart);
             }
+            false
+        }
+    }
+}
+
+/// Decompress the region between START and END into BUFFER.
+/// Unlike `zlib-decompress-region', the compressed data is left in place,
+/// and the decompressed data is inserted at point in BUFFER, which may
+/// be a buffer or the name of one.  If BUFFER is multibyte, the data is
+/// decoded with its `buffer-file-coding-system', or `undecided' if that
+/// is nil.  The formats recognized are the same as for
+/// `zlib-decompress-region'.  If the current buffer is multibyte, the
+/// region must contain only ASCII and raw-byte (`eight-bit') characters.
+/// Return t on success, or nil if the region is not valid compressed
+/// data, in which case BUFFER is left unchanged.
+#[lisp_fn]
+pub fn zlib_decompress_region_to_buffer(
+    mut start: LispObject,
+    mut end: LispObject,
+    buffer: LispBufferOrName,
+) -> bool {
+    unsafe { validate_region(&mut start, &mut end) };
+
+    let istart = start.as_fixnum_or_error() as isize;
+    let iend = end.as_fixnum_or_error() as isize;
+
+    // Empty region, decompress failed.
+    if istart == iend {
+        return false;
+    }
+
+    let decompressed = match decompress_region_bytes(istart, iend, DecodeOptions::default()) {
+        Ok((bytes, _)) => bytes,
+        Err(_) => return false,
+    };
+
+    let count = c_specpdl_index();
+    unsafe { record_unwind_current_buffer() };
+
+    set_buffer(buffer);
+    insert_decompressed(&decompressed);
+
+    unbind_to(count, Qnil);
+
+    true
+}
+
+/// How often, in seconds, to check on a decompression running in the
+/// background.
+const ASYNC_POLL_INTERVAL: EmacsDouble = 0.05;
+
+/// A reader that keeps count of the bytes consumed from INNER, so that
+/// the progress of a background decompression can be reported, and
+/// that fails once CANCELLED is set.
+struct ProgressReader<R> {
+    inner: R,
+    consumed: Arc<AtomicUsize>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl<R> ProgressReader<R> {
+    fn check_cancelled(&self) -> io::Result<()> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.check_cancelled()?;
+        let read = self.inner.read(buf)?;
+        self.consumed.fetch_add(read, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for ProgressReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.check_cancelled()?;
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+        self.consumed.fetch_add(amt, Ordering::Relaxed);
+    }
+}
 
-            // Decompress failed.
-            _ => {
-                // Delete any uncompressed data already inserted on error, but
-                // without calling the change hooks.
-
-                let data_orig = istart;
-                let data_start = iend;
-                let data_end = iend + decompressed_bytes;
-
-                unsafe {
-                    del_range_2(
-                        data_start, data_start, // byte, char offsets the same
-                        data_end, data_end, false,
-                    );
-                    update_compositions(data_start, data_start, CHECK_HEAD as i32);
-                    // "Balance" the before-change-functions call, which would
-                    // otherwise be left "hanging".
-                    signal_after_change(data_orig, data_start - data_orig, data_start - data_orig);
+/// A decompression running on a worker thread.
+struct AsyncJob {
+    total: usize,
+    consumed: Arc<AtomicUsize>,
+    cancelled: Arc<AtomicBool>,
+    result: Receiver<io::Result<Vec<u8>>>,
+}
+
+struct AsyncJobs {
+    next_id: EmacsInt,
+    jobs: HashMap<EmacsInt, AsyncJob>,
+}
+
+lazy_static! {
+    /// The background decompressions that have not been reported yet,
+    /// by job number.
+    static ref ASYNC_JOBS: Mutex<AsyncJobs> = Mutex::new(AsyncJobs {
+        next_id: 0,
+        jobs: HashMap::new(),
+    });
+}
+
+/// Start decompressing COMPRESSED on a worker thread and return the
+/// number of the new job.
+fn start_async_job(compressed: Vec<u8>) -> EmacsInt {
+    let consumed = Arc::new(AtomicUsize::new(0));
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = channel();
+
+    let job = AsyncJob {
+        total: compressed.len(),
+        consumed: Arc::clone(&consumed),
+        cancelled: Arc::clone(&cancelled),
+        result: receiver,
+    };
+
+    thread::spawn(move || {
+        let input = ProgressReader {
+            inner: compressed.as_slice(),
+            consumed,
+            cancelled,
+        };
+        let mut decompressed = Vec::new();
+        let result = if input.inner.is_empty() {
+            // Empty region, decompress failed, as in `zlib-decompress-region'.
+            Err(io::Error::new(io::ErrorKind::UnexpectedEof, "no compressed data"))
+        } else {
+            create_stream_decoder(input)
+                .and_then(|mut decoder| decoder.read_to_end(&mut decompressed))
+                .map(|_| decompressed)
+        };
+        // The job may have been cancelled, and nobody is listening anymore.
+        let _ = sender.send(result);
+    });
+
+    let mut jobs = ASYNC_JOBS.lock().unwrap();
+    jobs.next_id += 1;
+    let id = jobs.next_id;
+    jobs.jobs.insert(id, job);
+    id
+}
+
+/// What a background decompression is up to.
+enum AsyncStatus {
+    Running { consumed: usize, total: usize },
+    Done(Option<Vec<u8>>),
+    Unknown,
+}
+
+/// Return the status of background job ID, forgetting about it if it is
+/// finished.
+fn async_job_status(id: EmacsInt) -> AsyncStatus {
+    let mut jobs = ASYNC_JOBS.lock().unwrap();
+
+    let status = match jobs.jobs.get(&id) {
+        None => return AsyncStatus::Unknown,
+        Some(job) => match job.result.try_recv() {
+            Err(TryRecvError::Empty) => {
+                return AsyncStatus::Running {
+                    consumed: job.consumed.load(Ordering::Relaxed),
+                    total: job.total,
                 };
+            }
+            Ok(result) => AsyncStatus::Done(result.ok()),
+            // The worker thread died without sending a result.
+            Err(TryRecvError::Disconnected) => AsyncStatus::Done(None),
+        },
+    };
+
+    jobs.jobs.remove(&id);
+    status
+}
+
+fn schedule_async_poll(job: LispObject, callback: LispObject, progress: LispObject) {
+    call!(
+        LispObject::from(intern("run-with-timer")),
+        LispObject::from_float(ASYNC_POLL_INTERVAL),
+        Qnil,
+        LispObject::from(intern("zlib--decompress-async-poll")),
+        job,
+        callback,
+        progress
+    );
+}
+
+/// Decompress the region between START and END in the background.
+/// The compressed data is copied and decompressed on a separate thread,
+/// so that Emacs stays responsive while large regions are processed.
+/// The region is left unchanged.  The formats recognized are the same
+/// as for `zlib-decompress-region'.
+///
+/// When decompression is finished, CALLBACK is called from a timer with
+/// one argument, the decompressed data as a unibyte string, or nil if
+/// the region was empty or not valid compressed data.  If PROGRESS is non-nil,
+/// it is called periodically while decompression is running, with two
+/// arguments: the number of compressed bytes consumed so far, and the
+/// size of the compressed data.
+///
+/// Return a job number that can be passed to
+/// `zlib-decompress-async-cancel'.
+#[lisp_fn(min = "3")]
+pub fn zlib_decompress_region_async(
+    mut start: LispObject,
+    mut end: LispObject,
+    callback: LispObject,
+    progress: LispObject,
+) -> EmacsInt {
+    unsafe { validate_region(&mut start, &mut end) };
+
+    let istart = start.as_fixnum_or_error() as isize;
+    let iend = end.as_fixnum_or_error() as isize;
 
-                // Put point where it was, or if the buffer has shrunk because the
-                // compressed data is bigger than the uncompressed, at
-                // point-max.
-                let charpos = min(old_pt, current_buffer.zv);
-                let bytepos = unsafe { buf_charpos_to_bytepos(current_buffer.as_mut(), charpos) };
-                current_buffer.set_pt_both(charpos, bytepos);
+    let compressed = region_unibyte_string(istart, iend).as_slice().to_vec();
+    let id = start_async_job(compressed);
 
-                return false;
+    schedule_async_poll(LispObject::from_fixnum(id), callback, progress);
+
+    id
+}
+
+/// Check on the background decompression JOB.
+/// Call CALLBACK or PROGRESS as documented in
+/// `zlib-decompress-region-async', and reschedule the check if JOB is
+/// still running.
+#[lisp_fn(name = "zlib--decompress-async-poll")]
+pub fn zlib_decompress_async_poll(job: EmacsInt, callback: LispObject, progress: LispObject) {
+    match async_job_status(job) {
+        AsyncStatus::Running { consumed, total } => {
+            if progress.is_not_nil() {
+                call!(
+                    progress,
+                    LispObject::from(consumed),
+                    LispObject::from(total)
+                );
             }
-        };
+            schedule_async_poll(LispObject::from_fixnum(job), callback, progress);
+        }
+        AsyncStatus::Done(Some(decompressed)) => {
+            call!(callback, unibyte_string(&decompressed));
+        }
+        AsyncStatus::Done(None) => {
+            call!(callback, Qnil);
+        }
+        // The job was cancelled.
+        AsyncStatus::Unknown => (),
+    }
+}
+
+/// Cancel the background decompression JOB.
+/// JOB is a number returned by `zlib-decompress-region-async'.  Its
+/// callback will not be called.  Return t if JOB was still pending.
+#[lisp_fn]
+pub fn zlib_decompress_async_cancel(job: EmacsInt) -> bool {
+    match ASYNC_JOBS.lock().unwrap().jobs.remove(&job) {
+        Some(job) => {
+            job.cancelled.store(true, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Decompress a brotli-compressed region.
+/// Replace the text in the region by the decompressed data.
+/// Brotli streams have no header that could be used to recognize
+/// them, which is why `zlib-decompress-region' does not handle them.
+/// This is the format used by HTTP responses with `Content-Encoding: br'.
+/// On failure, return nil and leave the data in place.
+/// This function can be called only in unibyte buffers.
+#[lisp_fn]
+pub fn brotli_decompress_region(mut start: LispObject, mut end: LispObject) -> bool {
+    unsafe { validate_region(&mut start, &mut end) };
+
+    let current_buffer = ThreadState::current_buffer();
+
+    if current_buffer.multibyte_characters_enabled() {
+        error!("This function can be called only in unibyte buffers");
+    };
+
+    let istart = start.as_fixnum_or_error() as isize;
+    let iend = end.as_fixnum_or_error() as isize;
+
+    // Empty region, decompress failed.
+    if istart == iend {
+        return false;
+    }
+
+    transform_region(istart, iend, RegionCodec::DecodeBrotli).is_ok()
+}
+
+/// Insert the decompressed contents of FILE into the current buffer.
+/// The file may be compressed with gzip, zlib, zstd or xz, as for
+/// `zlib-decompress-region'.  FILE is decompressed as it is read, so
+/// the compressed data is never held in memory, which matters for large
+/// files.  The data is inserted at point, and point is left before it.
+/// Return the number of bytes inserted, or nil if FILE is not valid
+/// compressed data, in which case the buffer is left unchanged.
+/// Optional argument MAX-OUTPUT-BYTES is as for `zlib-decompress-region'.
+/// This function can be called only in unibyte buffers.
+#[lisp_fn(min = "1")]
+pub fn zlib_decompress_file_into_buffer(
+    file: LispObject,
+    max_output_bytes: LispObject,
+) -> LispObject {
+    let current_buffer = ThreadState::current_buffer();
+
+    if current_buffer.multibyte_characters_enabled() {
+        error!("This function can be called only in unibyte buffers");
+    };
+
+    let file = unsafe { Fexpand_file_name(file, current_buffer.directory_) };
+    let path = encoded_file_path(unsafe { encode_file_name(file) });
+
+    let input = match File::open(path) {
+        Ok(input) => BufReader::new(input),
+        Err(_) => unsafe { report_file_error("Opening input file\0".as_ptr() as *const i8, file) },
+    };
+
+    let options = DecodeOptions::with_max_output(max_output_bytes);
+    let result =
+        create_stream_decoder_with_options(input, options).and_then(|(mut decoder, header)| {
+            insert_stream_at_point(&mut *decoder).map(|inserted| (inserted, header))
+        });
+
+    match result {
+        Ok((inserted, header)) => {
+            set_gzip_header(header);
+            LispObject::from_natnum(inserted as EmacsInt)
+        }
+        Err(_) => {
+            set_gzip_header(None);
+            Qnil
+        }
+    }
+}
+
+/// Return the header of the gzip stream last decompressed into the current buffer.
+/// The value is a plist (:name NAME :mtime MTIME :comment COMMENT),
+/// where NAME and COMMENT are unibyte strings and MTIME is the
+/// modification time of the original file in seconds since the epoch.
+/// Fields that are not recorded in the header are nil.
+/// Return nil if the data last decompressed into the current buffer by
+/// `zlib-decompress-region' or `zlib-decompress-file-into-buffer' was
+/// not in gzip format.
+#[lisp_fn]
+pub fn zlib_last_gzip_header() -> LispObject {
+    symbol_value(Qzlib__gzip_header.into())
+}
+
+/// Return the bytes of STRING, which must be unibyte or contain only
+/// ASCII and raw-byte (`eight-bit') characters.
+fn string_bytes(string: LispStringRef) -> LispStringRef {
+    string_to_unibyte(string).as_string_or_error()
+}
+
+/// Decompress STRING, and return the result as a unibyte string.
+/// The formats recognized are the same as for `zlib-decompress-region'.
+/// STRING must be unibyte, or contain only ASCII and raw-byte
+/// (`eight-bit') characters.  Return nil if STRING is not valid
+/// compressed data.
+#[lisp_fn]
+pub fn zlib_decompress_string(string: LispStringRef) -> LispObject {
+    match decompress_bytes(string_bytes(string).as_slice(), DecodeOptions::default()) {
+        Ok((decompressed, _)) => unibyte_string(&decompressed),
+        Err(_) => Qnil,
+    }
+}
+
+/// Compress STRING, and return the result as a unibyte string.
+/// STRING must be unibyte, or contain only ASCII and raw-byte
+/// (`eight-bit') characters; use `encode-coding-string' first for
+/// other text.  Optional argument FORMAT is as for
+/// `zlib-compress-region'.
+#[lisp_fn(min = "1")]
+pub fn zlib_compress_string(string: LispStringRef, format: LispObject) -> LispObject {
+    let format = ZlibFormat::from_lisp(format);
+
+    let mut compressed = Vec::new();
+    match create_buffer_encoder(string_bytes(string).as_slice(), format)
+        .read_to_end(&mut compressed)
+    {
+        Ok(_) => unibyte_string(&compressed),
+        Err(error) => error!("Compression failed: {}", error),
     }
 }
 
+/// Compress the region between START and END.
+/// Replace the text in the region by the compressed data.
+/// Optional argument FORMAT selects the framing of the compressed data:
+/// `gzip' (the default if FORMAT is nil), `zlib', or `deflate' for a raw
+/// deflate stream without any header.  The result can be decompressed
+/// again with `zlib-decompress-region'.
+/// On failure, return nil and leave the data in place.
+/// This function can be called only in unibyte buffers.
+#[lisp_fn(min = "2")]
+pub fn zlib_compress_region(
+    mut start: LispObject,
+    mut end: LispObject,
+    format: LispObject,
+) -> bool {
+    unsafe { validate_region(&mut start, &mut end) };
+
+    let current_buffer = ThreadState::current_buffer();
+
+    if current_buffer.multibyte_characters_enabled() {
+        error!("This function can be called only in unibyte buffers");
+    };
+
+    let format = ZlibFormat::from_lisp(format);
+
+    let istart = start.as_fixnum_or_error() as isize;
+    let iend = end.as_fixnum_or_error() as isize;
+
+    transform_region(istart, iend, RegionCodec::Encode(format)).is_ok()
+}
+
+#[no_mangle]
+pub extern "C" fn syms_of_decompress() {
+    /// The number of bytes to decompress or compress at a time.
+    /// Data is produced into the buffer in chunks of this size, and
+    /// `quit' is checked between chunks.  Larger values can improve
+    /// throughput on big inputs, at the price of responsiveness.
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    defvar_int!(zlib_decompress_chunk_size, "zlib-decompress-chunk-size", 16 * 1024);
+
+    def_lisp_sym!(Qdeflate, "deflate");
+    def_lisp_sym!(Qzlib, "zlib");
+    def_lisp_sym!(Qgzip, "gzip");
+
+    def_lisp_sym!(Qzlib_error, "zlib-error");
+    def_lisp_sym!(Qtruncated, "truncated");
+    def_lisp_sym!(Qcorrupt, "corrupt");
+    define_error(Qzlib_error, "Invalid compressed data", Qerror);
+
+    def_lisp_sym!(QCmtime, ":mtime");
+    def_lisp_sym!(QCcomment, ":comment");
+
+    /// The header of the gzip stream last decompressed into this buffer,
+    /// as `zlib-last-gzip-header' returns it.
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    defvar_lisp!(Vzlib__gzip_header, "zlib--gzip-header", Qnil);
+    def_lisp_sym!(Qzlib__gzip_header, "zlib--gzip-header");
+    unsafe { Fmake_variable_buffer_local(Qzlib__gzip_header) };
+}
+
 include!(concat!(env!("OUT_DIR"), "/decompress_exports.rs"));
@@ -0,0 +1,547 @@
+//! Comparing texts.
+//!
+//! The texts are compared as sequences of lines or characters, so
+//! that no external diff program is needed, with Myers' algorithm or
+//! the patience diff algorithm.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::Range;
+
+use libc::ptrdiff_t;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    buffers::LispBufferRef,
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::list,
+    multibyte::{multibyte_char_at, Codepoint},
+    remacs_sys::{buf_charpos_to_bytepos, wrong_choice},
+    remacs_sys::{
+        QCalgorithm, QCgranularity, Qbufferp, Qchar, Qline, Qmyers, Qnil, Qpatience, Qplistp,
+    },
+};
+
+#[derive(Clone, Copy)]
+enum Algorithm {
+    Myers,
+    Patience,
+}
+
+/// The settings given by the keyword arguments of `diff-regions'.
+struct DiffConfig {
+    algorithm: Algorithm,
+    by_line: bool,
+}
+
+impl DiffConfig {
+    /// Parse the keyword arguments in ARGS.
+    fn from_args(args: &[LispObject]) -> Self {
+        let mut config = DiffConfig {
+            algorithm: Algorithm::Myers,
+            by_line: true,
+        };
+
+        if args.len() % 2 != 0 {
+            wrong_type!(Qplistp, list(args));
+        }
+
+        for pair in args.chunks(2) {
+            let (key, value) = (pair[0], pair[1]);
+            if key == QCalgorithm {
+                config.algorithm = if value == Qmyers {
+                    Algorithm::Myers
+                } else if value == Qpatience {
+                    Algorithm::Patience
+                } else {
+                    unsafe { wrong_choice(list!(Qmyers, Qpatience), value) }
+                };
+            } else if key == QCgranularity {
+                config.by_line = if value == Qline {
+                    true
+                } else if value == Qchar {
+                    false
+                } else {
+                    unsafe { wrong_choice(list!(Qline, Qchar), value) }
+                };
+            } else {
+                unsafe { wrong_choice(list!(QCalgorithm, QCgranularity), key) };
+            }
+        }
+        config
+    }
+}
+
+/// A text to compare: its characters, and the position of the first.
+struct DiffText {
+    chars: Vec<Codepoint>,
+    start: ptrdiff_t,
+}
+
+/// Return the characters of BUFFER between START and END, which default
+/// to the limits of its accessible portion.
+fn buffer_diff_text(mut buffer: LispBufferRef, start: LispObject, end: LispObject) -> DiffText {
+    if !buffer.is_live() {
+        error!("Selecting deleted buffer");
+    }
+    let position = |pos: LispObject, default| {
+        if pos.is_nil() {
+            default
+        } else {
+            pos.as_number_coerce_marker_or_error().to_fixnum() as ptrdiff_t
+        }
+    };
+    let (start_pos, end_pos) = (position(start, buffer.begv), position(end, buffer.zv));
+    if !(buffer.begv <= start_pos && start_pos <= end_pos && end_pos <= buffer.zv) {
+        args_out_of_range!(start, end);
+    }
+
+    let start_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), start_pos) };
+    let end_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), end_pos) };
+    let (before_gap, after_gap) = buffer.region_slices(start_byte, end_byte);
+    let mut bytes = before_gap.to_vec();
+    bytes.extend_from_slice(after_gap);
+
+    let chars = if buffer.multibyte_characters_enabled() {
+        let mut chars = Vec::with_capacity((end_pos - start_pos) as usize);
+        let mut i = 0;
+        while i < bytes.len() {
+            let (c, len) = multibyte_char_at(&bytes[i..]);
+            chars.push(c);
+            i += len;
+        }
+        chars
+    } else {
+        bytes.into_iter().map(Codepoint::from).collect()
+    };
+    DiffText {
+        chars,
+        start: start_pos,
+    }
+}
+
+/// Return the text designated by OBJECT, a string, a buffer, or a list
+/// (BUFFER START END).
+fn diff_text(object: LispObject) -> DiffText {
+    if let Some(string) = object.as_string() {
+        DiffText {
+            chars: string.chars().collect(),
+            start: 0,
+        }
+    } else if let Some(buffer) = object.as_buffer() {
+        buffer_diff_text(buffer, Qnil, Qnil)
+    } else if let Some((buffer, rest)) = object.as_cons().map(|cons| cons.as_tuple()) {
+        let (start, end) = match rest.as_cons().map(|cons| cons.as_tuple()) {
+            Some((start, end)) => (start, end.as_cons().map_or(Qnil, |cons| cons.car())),
+            None => (Qnil, Qnil),
+        };
+        buffer_diff_text(buffer.as_buffer_or_error(), start, end)
+    } else {
+        wrong_type!(Qbufferp, object)
+    }
+}
+
+/// Split CHARS into the units compared, lines if BY_LINE and otherwise
+/// characters.  Return the units, and the offset of the start of each
+/// followed by the length of CHARS.
+fn diff_units(chars: &[Codepoint], by_line: bool) -> (Vec<&[Codepoint]>, Vec<usize>) {
+    let mut units = Vec::new();
+    let mut offsets = vec![0];
+    let mut start = 0;
+    for (i, &c) in chars.iter().enumerate() {
+        if !by_line || c == Codepoint::from(b'\n') {
+            units.push(&chars[start..=i]);
+            start = i + 1;
+            offsets.push(start);
+        }
+    }
+    if start < chars.len() {
+        units.push(&chars[start..]);
+        offsets.push(chars.len());
+    }
+    (units, offsets)
+}
+
+/// A run of LEN units which are the same in the old text, from OLD,
+/// and in the new one, from NEW.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Same {
+    old: usize,
+    new: usize,
+    len: usize,
+}
+
+fn common_prefix_len<T: PartialEq>(old: &[T], new: &[T]) -> usize {
+    old.iter().zip(new).take_while(|(a, b)| a == b).count()
+}
+
+fn common_suffix_len<T: PartialEq>(old: &[T], new: &[T]) -> usize {
+    old.iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+/// The state of a comparison with Myers' algorithm, as described in "An
+/// O(ND) Difference Algorithm and Its Variations", by Eugene W. Myers,
+/// in linear space.  VF and VB hold the furthest reaching forward and
+/// backward paths on each diagonal, and SAME the runs found so far.
+struct Myers<'a, T: 'a> {
+    old: &'a [T],
+    new: &'a [T],
+    vf: Vec<usize>,
+    vb: Vec<usize>,
+    same: Vec<Same>,
+}
+
+/// Return the runs of units which are the same in OLD and NEW, in order,
+/// for the fewest differences.
+fn myers_diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Same> {
+    // Diagonals range from -(MAX_D + 1) to MAX_D + 1.
+    let max_d = (old.len() + new.len() + 1) / 2 + 1;
+    let mut myers = Myers {
+        old,
+        new,
+        vf: vec![0; 2 * max_d + 1],
+        vb: vec![0; 2 * max_d + 1],
+        same: Vec::new(),
+    };
+    myers.conquer(0..old.len(), 0..new.len());
+    myers.same
+}
+
+impl<'a, T: PartialEq> Myers<'a, T> {
+    /// Find the runs of units which are the same in OLD_RANGE of the old
+    /// text and NEW_RANGE of the new one, by splitting them at the
+    /// middle snake of an optimal path.
+    fn conquer(&mut self, mut old_range: Range<usize>, mut new_range: Range<usize>) {
+        let prefix = common_prefix_len(&self.old[old_range.clone()], &self.new[new_range.clone()]);
+        if prefix > 0 {
+            self.same.push(Same {
+                old: old_range.start,
+                new: new_range.start,
+                len: prefix,
+            });
+            old_range.start += prefix;
+            new_range.start += prefix;
+        }
+        let suffix = common_suffix_len(&self.old[old_range.clone()], &self.new[new_range.clone()]);
+        old_range.end -= suffix;
+        new_range.end -= suffix;
+
+        if old_range.start < old_range.end && new_range.start < new_range.end {
+            if let Some((x, y)) = self.middle_snake(old_range.clone(), new_range.clone()) {
+                self.conquer(old_range.start..x, new_range.start..y);
+                self.conquer(x..old_range.end, y..new_range.end);
+            }
+        }
+
+        if suffix > 0 {
+            self.same.push(Same {
+                old: old_range.end,
+                new: new_range.end,
+                len: suffix,
+            });
+        }
+    }
+
+    /// Return the start of the middle snake of an optimal path from the
+    /// start of OLD_RANGE and NEW_RANGE to their end.
+    fn middle_snake(
+        &mut self,
+        old_range: Range<usize>,
+        new_range: Range<usize>,
+    ) -> Option<(usize, usize)> {
+        let (old, new) = (&self.old[old_range.clone()], &self.new[new_range.clone()]);
+        let (n, m) = (old.len(), new.len());
+        let delta = n as isize - m as isize;
+        let odd = delta & 1 == 1;
+        let max_d = ((n + m + 1) / 2) as isize;
+        let offset = (self.vf.len() / 2) as isize;
+        let at = |k: isize| (k + offset) as usize;
+        let (vf, vb) = (&mut self.vf, &mut self.vb);
+        vf[at(1)] = 0;
+        vb[at(1)] = 0;
+
+        for d in 0..=max_d {
+            for k in (-d..=d).rev().step_by(2) {
+                let mut x = if k == -d || (k != d && vf[at(k - 1)] < vf[at(k + 1)]) {
+                    vf[at(k + 1)]
+                } else {
+                    vf[at(k - 1)] + 1
+                };
+                let y = (x as isize - k) as usize;
+                let (x0, y0) = (x, y);
+                if x < n && y < m {
+                    x += common_prefix_len(&old[x..], &new[y..]);
+                }
+                vf[at(k)] = x;
+                if odd && (k - delta).abs() <= d - 1 && x + vb[at(delta - k)] >= n {
+                    return Some((old_range.start + x0, new_range.start + y0));
+                }
+            }
+
+            for k in (-d..=d).rev().step_by(2) {
+                let mut x = if k == -d || (k != d && vb[at(k - 1)] < vb[at(k + 1)]) {
+                    vb[at(k + 1)]
+                } else {
+                    vb[at(k - 1)] + 1
+                };
+                let mut y = (x as isize - k) as usize;
+                if x < n && y < m {
+                    let len = common_suffix_len(&old[..n - x], &new[..m - y]);
+                    x += len;
+                    y += len;
+                }
+                vb[at(k)] = x;
+                if !odd && (k - delta).abs() <= d && x + vf[at(delta - k)] >= n {
+                    return Some((old_range.end - x, new_range.end - y));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Return the runs of units which are the same in OLD and NEW, in order,
+/// aligning the units which occur once in each first.  The text between
+/// those is compared recursively, and with Myers' algorithm when it has
+/// no such units.
+fn patience_diff<T: Hash + Eq>(old: &[T], new: &[T]) -> Vec<Same> {
+    let mut same = Vec::new();
+    patience_conquer(old, 0..old.len(), new, 0..new.len(), &mut same);
+    same
+}
+
+fn patience_conquer<T: Hash + Eq>(
+    old: &[T],
+    mut old_range: Range<usize>,
+    new: &[T],
+    mut new_range: Range<usize>,
+    same: &mut Vec<Same>,
+) {
+    let prefix = common_prefix_len(&old[old_range.clone()], &new[new_range.clone()]);
+    if prefix > 0 {
+        same.push(Same {
+            old: old_range.start,
+            new: new_range.start,
+            len: prefix,
+        });
+        old_range.start += prefix;
+        new_range.start += prefix;
+    }
+    let suffix = common_suffix_len(&old[old_range.clone()], &new[new_range.clone()]);
+    old_range.end -= suffix;
+    new_range.end -= suffix;
+
+    if old_range.start < old_range.end && new_range.start < new_range.end {
+        let anchors = unique_anchors(old, old_range.clone(), new, new_range.clone());
+        if anchors.is_empty() {
+            for run in myers_diff(&old[old_range.clone()], &new[new_range.clone()]) {
+                same.push(Same {
+                    old: old_range.start + run.old,
+                    new: new_range.start + run.new,
+                    len: run.len,
+                });
+            }
+        } else {
+            let (mut x, mut y) = (old_range.start, new_range.start);
+            for (i, j) in anchors {
+                patience_conquer(old, x..i, new, y..j, same);
+                same.push(Same {
+                    old: i,
+                    new: j,
+                    len: 1,
+                });
+                x = i + 1;
+                y = j + 1;
+            }
+            patience_conquer(old, x..old_range.end, new, y..new_range.end, same);
+        }
+    }
+
+    if suffix > 0 {
+        same.push(Same {
+            old: old_range.end,
+            new: new_range.end,
+            len: suffix,
+        });
+    }
+}
+
+/// Return the positions of the units which occur exactly once in each of
+/// OLD_RANGE of OLD and NEW_RANGE of NEW, as pairs of positions, and of
+/// those the longest sequence which is in the same order in both.
+fn unique_anchors<T: Hash + Eq>(
+    old: &[T],
+    old_range: Range<usize>,
+    new: &[T],
+    new_range: Range<usize>,
+) -> Vec<(usize, usize)> {
+    // The number of occurrences of each unit in each text, and the
+    // position of the last.
+    let mut counts: HashMap<&T, (usize, usize, usize, usize)> = HashMap::new();
+    for i in old_range {
+        let entry = counts.entry(&old[i]).or_insert((0, 0, 0, 0));
+        entry.0 += 1;
+        entry.1 = i;
+    }
+    for j in new_range {
+        if let Some(entry) = counts.get_mut(&new[j]) {
+            entry.2 += 1;
+            entry.3 = j;
+        }
+    }
+    let mut pairs: Vec<(usize, usize)> = counts
+        .values()
+        .filter(|&&(old_count, _, new_count, _)| old_count == 1 && new_count == 1)
+        .map(|&(_, i, _, j)| (i, j))
+        .collect();
+    pairs.sort();
+
+    // Patience sorting: TOPS holds, for each length, the index in PAIRS
+    // of the pair with the least new position ending a sequence of that
+    // length, and PREVIOUS the pair before each in its sequence.
+    let mut tops: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = Vec::with_capacity(pairs.len());
+    for (index, &(_, j)) in pairs.iter().enumerate() {
+        let len = match tops.binary_search_by_key(&j, |&top| pairs[top].1) {
+            Ok(len) | Err(len) => len,
+        };
+        previous.push(if len > 0 { Some(tops[len - 1]) } else { None });
+        if len == tops.len() {
+            tops.push(index);
+        } else {
+            tops[len] = index;
+        }
+    }
+    let mut anchors = Vec::with_capacity(tops.len());
+    let mut index = tops.last().cloned();
+    while let Some(i) = index {
+        anchors.push(pairs[i]);
+        index = previous[i];
+    }
+    anchors.reverse();
+    anchors
+}
+
+/// Return the ranges of OLD and NEW, of lengths OLD_LEN and NEW_LEN,
+/// which differ, given the runs SAME of units which are the same.
+fn changed_ranges(
+    same: &[Same],
+    old_len: usize,
+    new_len: usize,
+) -> Vec<(Range<usize>, Range<usize>)> {
+    let mut changes = Vec::new();
+    let (mut old, mut new) = (0, 0);
+    let end = Same {
+        old: old_len,
+        new: new_len,
+        len: 0,
+    };
+    for run in same.iter().chain(Some(&end)) {
+        if run.old > old || run.new > new {
+            changes.push((old..run.old, new..run.new));
+        }
+        old = run.old + run.len;
+        new = run.new + run.len;
+    }
+    changes
+}
+
+/// Compare the texts OLD and NEW, and return a list of their differences.
+/// Each of OLD and NEW can be a string, a buffer, whose accessible
+/// portion is compared, or a list (BUFFER START END), which stands for
+/// the text of BUFFER between START and END.
+///
+/// Each element of the result is a list (OLD-BEG OLD-END NEW-BEG
+/// NEW-END), meaning that the text between OLD-BEG and OLD-END in OLD
+/// is replaced by that between NEW-BEG and NEW-END in NEW; either part
+/// may be empty, for pure insertions and deletions.  These are buffer
+/// positions for buffers, and indices for strings.  The differences are
+/// in order, and the text between them is the same in OLD and NEW.
+/// Text properties are ignored.
+///
+/// The arguments ARGS are a list of keyword/argument pairs:
+///
+/// The keyword argument `:granularity' specifies the units compared: it
+/// can be `line', the default, for which each difference spans whole
+/// lines, or `char'.
+///
+/// The keyword argument `:algorithm' specifies the diff algorithm: it
+/// can be `myers', the default, which finds the fewest differences, or
+/// `patience', which aligns unique lines first and so often gives more
+/// readable differences for source code.
+/// usage: (diff-regions OLD NEW &rest ARGS)
+#[lisp_fn(min = "2")]
+pub fn diff_regions(args: &mut [LispObject]) -> LispObject {
+    let config = DiffConfig::from_args(&args[2..]);
+    let (old, new) = (diff_text(args[0]), diff_text(args[1]));
+    let (old_units, old_offsets) = diff_units(&old.chars, config.by_line);
+    let (new_units, new_offsets) = diff_units(&new.chars, config.by_line);
+
+    let same = match config.algorithm {
+        Algorithm::Myers => myers_diff(&old_units, &new_units),
+        Algorithm::Patience => patience_diff(&old_units, &new_units),
+    };
+    let hunks: Vec<LispObject> = changed_ranges(&same, old_units.len(), new_units.len())
+        .into_iter()
+        .map(|(old_range, new_range)| {
+            let old_pos = |i: usize| LispObject::from(old.start + old_offsets[i] as ptrdiff_t);
+            let new_pos = |i: usize| LispObject::from(new.start + new_offsets[i] as ptrdiff_t);
+            list!(
+                old_pos(old_range.start),
+                old_pos(old_range.end),
+                new_pos(new_range.start),
+                new_pos(new_range.end)
+            )
+        })
+        .collect();
+    list(&hunks)
+}
+
+#[no_mangle]
+pub extern "C" fn syms_of_diff() {
+    def_lisp_sym!(QCalgorithm, ":algorithm");
+    def_lisp_sym!(QCgranularity, ":granularity");
+    def_lisp_sym!(Qmyers, "myers");
+    def_lisp_sym!(Qpatience, "patience");
+}
+
+#[test]
+fn test_diff_units() {
+    let chars: Vec<Codepoint> = "ab\n\ncd".chars().map(Codepoint::from).collect();
+    let (units, offsets) = diff_units(&chars, true);
+    assert_eq!(units, vec![&chars[0..3], &chars[3..4], &chars[4..6]]);
+    assert_eq!(offsets, vec![0, 3, 4, 6]);
+    let (units, offsets) = diff_units(&chars, false);
+    assert_eq!(units.len(), 6);
+    assert_eq!(offsets, vec![0, 1, 2, 3, 4, 5, 6]);
+    assert_eq!(diff_units(&[], true), (vec![], vec![0]));
+}
+
+#[test]
+fn test_diff_algorithms() {
+    let old = b"abcabba";
+    let new = b"cbabac";
+    let same = myers_diff(old, new);
+    // The longest common subsequence has four units.
+    assert_eq!(same.iter().map(|run| run.len).sum::<usize>(), 4);
+    for run in &same {
+        assert_eq!(
+            old[run.old..run.old + run.len],
+            new[run.new..run.new + run.len]
+        );
+    }
+    assert_eq!(
+        changed_ranges(&patience_diff(b"xaby", b"xcby"), 4, 4),
+        vec![(1..2, 1..2)]
+    );
+    assert_eq!(myers_diff(b"", b"ab"), vec![]);
+    assert_eq!(changed_ranges(&[], 0, 2), vec![(0..0, 0..2)]);
+}
+
+include!(concat!(env!("OUT_DIR"), "/diff_exports.rs"));
@@ -1,5 +1,12 @@
 //! Lisp functions for making directory listings.
 
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use ignore::WalkBuilder;
+use libc::{c_char, ptrdiff_t};
+
 use remacs_macros::lisp_fn;
 
 #[cfg(unix)]
@@ -11,8 +18,15 @@ use dired_windows::{file_attributes_intro, get_users};
 
 use crate::{
     lisp::{defsubr, LispObject},
-    lists::car,
+    lists::{car, list},
+    remacs_sys::{decode_file_name, encode_file_name, fast_string_match_internal},
+    remacs_sys::{make_unibyte_string, maybe_quit, wrong_choice, Fexpand_file_name},
+    remacs_sys::{
+        QCattributes, QCfollow_symlinks, QCignore_files, QCinclude_directories, QCmax_depth, Qnil,
+        Qplistp,
+    },
     strings::string_lessp,
+    threads::ThreadState,
 };
 
 /// Return a list of names of files in DIRECTORY.
@@ -124,4 +138,166 @@ pub fn system_users() -> LispObject {
     get_users()
 }
 
+/// The keyword arguments of `directory-files-recursively-fast'.
+struct WalkOptions {
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+    ignore_files: bool,
+    include_directories: bool,
+    attributes: bool,
+}
+
+impl WalkOptions {
+    /// Parse the keyword arguments in ARGS.
+    fn from_args(args: &[LispObject]) -> Self {
+        let mut options = WalkOptions {
+            max_depth: None,
+            follow_symlinks: false,
+            ignore_files: false,
+            include_directories: false,
+            attributes: false,
+        };
+
+        if args.len() % 2 != 0 {
+            wrong_type!(Qplistp, list(args));
+        }
+
+        for pair in args.chunks(2) {
+            let (key, value) = (pair[0], pair[1]);
+            if key == QCmax_depth {
+                options.max_depth = if value.is_nil() {
+                    None
+                } else {
+                    Some(value.as_natnum_or_error() as usize)
+                };
+            } else if key == QCfollow_symlinks {
+                options.follow_symlinks = value.is_not_nil();
+            } else if key == QCignore_files {
+                options.ignore_files = value.is_not_nil();
+            } else if key == QCinclude_directories {
+                options.include_directories = value.is_not_nil();
+            } else if key == QCattributes {
+                options.attributes = value.is_not_nil();
+            } else {
+                let choices = list!(
+                    QCmax_depth,
+                    QCfollow_symlinks,
+                    QCignore_files,
+                    QCinclude_directories,
+                    QCattributes
+                );
+                unsafe { wrong_choice(choices, key) };
+            }
+        }
+        options
+    }
+}
+
+/// Return the file name BYTES, as the file system has it, as a Lisp
+/// string.
+fn decoded_file_name(bytes: &[u8]) -> LispObject {
+    unsafe {
+        decode_file_name(make_unibyte_string(
+            bytes.as_ptr() as *const c_char,
+            bytes.len() as ptrdiff_t,
+        ))
+    }
+}
+
+/// Return all files under directory DIR whose names match REGEXP.
+/// This is like `directory-files-recursively', except that the
+/// directory tree is walked without calling Lisp, which is much faster
+/// on large trees.  The value is a list of absolute file names, sorted
+/// with `string-lessp'.  Only the nondirectory part of each file name is
+/// matched against REGEXP, ignoring case if `case-fold-search' is
+/// non-nil.  Unreadable directories are skipped.
+///
+/// The rest of the arguments are keywords and values:
+///
+/// `:max-depth' N only descends N levels of subdirectories, so that 0
+/// only lists the files in DIR itself.  The default, nil, has no limit.
+///
+/// `:follow-symlinks' non-nil descends into symbolic links to
+/// directories.  Loops of links are detected and skipped.
+///
+/// `:ignore-files' non-nil skips the files and directories ignored by
+/// `.gitignore', `.ignore' and similar files, and hidden ones, as
+/// `directory-search' and ripgrep do.
+///
+/// `:include-directories' non-nil also includes the directories whose
+/// names match REGEXP, as for `directory-files-recursively'.
+///
+/// `:attributes' non-nil returns an element (FILE . ATTRIBUTES) for each
+/// file instead, where ATTRIBUTES is what `file-attributes' returns, as
+/// for `directory-files-and-attributes'.
+/// usage: (directory-files-recursively-fast DIR REGEXP &rest ARGS)
+#[lisp_fn(min = "2")]
+pub fn directory_files_recursively_fast(args: &mut [LispObject]) -> LispObject {
+    let dir = args[0].as_string_or_error();
+    let regexp = args[1].as_string_or_error();
+    let options = WalkOptions::from_args(&args[2..]);
+
+    let current_buffer = ThreadState::current_buffer();
+    let dir = unsafe { Fexpand_file_name(dir.into(), current_buffer.directory_) };
+    let encoded = unsafe { encode_file_name(dir) };
+    let path = Path::new(OsStr::from_bytes(encoded.as_string_or_error().as_slice()));
+    if !path.is_dir() {
+        error!("Not a directory: {}", path.display());
+    }
+    let case_table = if current_buffer.case_fold_search().is_not_nil() {
+        current_buffer.case_canon_table_
+    } else {
+        Qnil
+    };
+
+    let walker = WalkBuilder::new(path)
+        .standard_filters(options.ignore_files)
+        .follow_links(options.follow_symlinks)
+        .max_depth(options.max_depth.map(|depth| depth + 1))
+        .build();
+    let mut found = Vec::new();
+    for entry in walker {
+        // Unreadable directories and loops of links are skipped.
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if entry.depth() == 0 {
+            continue;
+        }
+        let is_dir = entry.file_type().map_or(false, |t| t.is_dir());
+        if is_dir && !options.include_directories {
+            continue;
+        }
+        let name = decoded_file_name(entry.file_name().as_bytes());
+        if unsafe { fast_string_match_internal(regexp.into(), name, case_table) } >= 0 {
+            found.push(entry.path().to_path_buf());
+        }
+        unsafe { maybe_quit() };
+    }
+    found.sort_by(|a, b| a.as_os_str().as_bytes().cmp(b.as_os_str().as_bytes()));
+
+    let files: Vec<LispObject> = found
+        .iter()
+        .map(|file| {
+            let file = decoded_file_name(file.as_os_str().as_bytes());
+            if options.attributes {
+                LispObject::cons(file, file_attributes(file, Qnil))
+            } else {
+                file
+            }
+        })
+        .collect();
+    list(&files)
+}
+
+#[no_mangle]
+pub extern "C" fn rust_syms_of_dired() {
+    def_lisp_sym!(QCmax_depth, ":max-depth");
+    def_lisp_sym!(QCfollow_symlinks, ":follow-symlinks");
+    def_lisp_sym!(QCignore_files, ":ignore-files");
+    def_lisp_sym!(QCinclude_directories, ":include-directories");
+    def_lisp_sym!(QCattributes, ":attributes");
+}
+
 include!(concat!(env!("OUT_DIR"), "/dired_exports.rs"));
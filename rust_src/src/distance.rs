@@ -0,0 +1,201 @@
+//! Edit distances between strings.
+
+use std::cmp::min;
+
+use rayon::prelude::*;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::{list, LispConsCircularChecks, LispConsEndChecks},
+    multibyte::{Codepoint, LispStringRef},
+    remacs_sys::EmacsInt,
+};
+
+/// Return the edit distance between A and B: the least number of
+/// insertions, deletions and substitutions, and also transpositions of
+/// adjacent elements if TRANSPOSITIONS, which turn A into B.  No
+/// element is edited more than once, so with transpositions this is
+/// the optimal string alignment distance.  Return None if the distance
+/// is greater than MAX.
+fn edit_distance<T: PartialEq>(
+    a: &[T],
+    b: &[T],
+    transpositions: bool,
+    max: Option<usize>,
+) -> Option<usize> {
+    let exceeds = |distance: usize| max.map_or(false, |max| distance > max);
+
+    // Common prefixes and suffixes don't change the distance.
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let suffix = a
+        .iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a, b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+
+    let (n, m) = (a.len(), b.len());
+    if exceeds(if n > m { n - m } else { m - n }) {
+        return None;
+    }
+
+    // Only the last two rows of distances are needed.
+    let mut before: Vec<usize> = vec![0; m + 1];
+    let mut previous: Vec<usize> = (0..=m).collect();
+    let mut current: Vec<usize> = vec![0; m + 1];
+    for i in 1..=n {
+        current[0] = i;
+        let mut row_min = i;
+        for j in 1..=m {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut distance = min(
+                min(previous[j] + 1, current[j - 1] + 1),
+                previous[j - 1] + cost,
+            );
+            if transpositions && i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = min(distance, before[j - 2] + 1);
+            }
+            current[j] = distance;
+            row_min = min(row_min, distance);
+        }
+        // The distances in later rows can't be smaller.
+        if exceeds(row_min) {
+            return None;
+        }
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+    Some(previous[m]).filter(|&distance| !exceeds(distance))
+}
+
+/// Return the characters of STRING.
+fn string_chars(string: LispStringRef) -> Vec<Codepoint> {
+    string.chars().collect()
+}
+
+/// Return the maximum distance given by MAX_DISTANCE, which is nil or a
+/// natural number.
+fn distance_bound(max_distance: Option<EmacsInt>) -> Option<usize> {
+    max_distance.map(|max| {
+        if max < 0 {
+            args_out_of_range!(LispObject::from(max), LispObject::from(0));
+        }
+        max as usize
+    })
+}
+
+/// Return Levenshtein distance between STRING1 and STRING2.
+/// The distance is the number of deletions, insertions, and
+/// substitutions required to transform STRING1 into STRING2.
+/// If BYTECOMPARE is nil or omitted, compute distance in terms of
+/// characters.
+/// If BYTECOMPARE is non-nil, compute distance in terms of bytes.
+/// Letter-case is significant, but text properties are ignored.
+#[lisp_fn(min = "2")]
+pub fn string_distance(
+    string1: LispStringRef,
+    string2: LispStringRef,
+    bytecompare: bool,
+) -> EmacsInt {
+    let distance = if bytecompare {
+        edit_distance(string1.as_slice(), string2.as_slice(), false, None)
+    } else {
+        edit_distance(&string_chars(string1), &string_chars(string2), false, None)
+    };
+    distance.unwrap() as EmacsInt
+}
+
+/// Return the edit distance between STRING1 and STRING2 in characters.
+/// This is the Levenshtein distance computed by `string-distance',
+/// unless TRANSPOSITIONS is non-nil, in which case swapping two
+/// adjacent characters also counts as a single edit, giving the
+/// (restricted) Damerau-Levenshtein distance.  Letter-case is
+/// significant, but text properties are ignored.
+///
+/// If MAX-DISTANCE is non-nil, it must be a natural number, and nil is
+/// returned if the distance is greater; the computation then stops as
+/// soon as that is known, which is much faster for dissimilar strings.
+#[lisp_fn(min = "2")]
+pub fn string_edit_distance(
+    string1: LispStringRef,
+    string2: LispStringRef,
+    transpositions: bool,
+    max_distance: Option<EmacsInt>,
+) -> Option<EmacsInt> {
+    let max = distance_bound(max_distance);
+    edit_distance(
+        &string_chars(string1),
+        &string_chars(string2),
+        transpositions,
+        max,
+    )
+    .map(|distance| distance as EmacsInt)
+}
+
+/// Rank the strings in CANDIDATES by their edit distance from QUERY.
+/// CANDIDATES is a list or vector of strings.  Return a list of conses
+/// (CANDIDATE . DISTANCE), closest first, where DISTANCE is computed
+/// as by `string-edit-distance' with the arguments TRANSPOSITIONS and
+/// MAX-DISTANCE.  Candidates whose distance is greater than
+/// MAX-DISTANCE are left out, and those at equal distances keep their
+/// order.  The distances are computed in parallel.
+#[lisp_fn(min = "2")]
+pub fn string_distance_rank(
+    query: LispStringRef,
+    candidates: LispObject,
+    transpositions: bool,
+    max_distance: Option<EmacsInt>,
+) -> LispObject {
+    let max = distance_bound(max_distance);
+    let elements: Vec<LispObject> = match candidates.as_vector() {
+        Some(vector) => vector.iter().collect(),
+        None => candidates
+            .iter_cars(LispConsEndChecks::on, LispConsCircularChecks::on)
+            .collect(),
+    };
+    let texts: Vec<Vec<Codepoint>> = elements
+        .iter()
+        .map(|element| string_chars(element.as_string_or_error()))
+        .collect();
+
+    let query = string_chars(query);
+    let mut distances: Vec<(usize, usize)> = texts
+        .par_iter()
+        .enumerate()
+        .filter_map(|(i, text)| {
+            edit_distance(&query, text, transpositions, max).map(|distance| (i, distance))
+        })
+        .collect();
+    distances.sort_by_key(|&(_, distance)| distance);
+
+    let ranked: Vec<LispObject> = distances
+        .into_iter()
+        .map(|(i, distance)| LispObject::cons(elements[i], distance as EmacsInt))
+        .collect();
+    list(&ranked)
+}
+
+#[test]
+fn test_edit_distance() {
+    let distance = |a: &str, b: &str, transpositions, max| {
+        let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+        edit_distance(&a, &b, transpositions, max)
+    };
+    assert_eq!(distance("kitten", "sitting", false, None), Some(3));
+    assert_eq!(distance("", "abc", false, None), Some(3));
+    assert_eq!(distance("same", "same", false, Some(0)), Some(0));
+    assert_eq!(distance("ab", "ba", false, None), Some(2));
+    assert_eq!(distance("ab", "ba", true, None), Some(1));
+    assert_eq!(distance("ca", "abc", true, None), Some(3));
+    assert_eq!(distance("àbçdé", "bàçdè", true, None), Some(2));
+    assert_eq!(distance("kitten", "sitting", false, Some(2)), None);
+    assert_eq!(distance("kitten", "sitting", false, Some(3)), Some(3));
+    assert_eq!(distance("a", "abcdef", false, Some(4)), None);
+}
+
+include!(concat!(env!("OUT_DIR"), "/distance_exports.rs"));
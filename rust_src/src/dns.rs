@@ -0,0 +1,328 @@
+//! DNS lookups.
+//!
+//! `dns-resolve' asks the name servers of the system for the records of
+//! a name with trust-dns-resolver, on a separate thread, so that Emacs can
+//! be interrupted while it waits, or go on with something else.  It
+//! knows more types of records than getaddrinfo, like the SRV records
+//! which say which host serves XMPP or LDAP for a domain.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, TryRecvError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use libc::c_char;
+use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
+use trust_dns_resolver::proto::rr::rdata::caa::{self, CAA};
+use trust_dns_resolver::proto::rr::{Name, RData, RecordType};
+use trust_dns_resolver::proto::serialize::binary::BinEncoder;
+use trust_dns_resolver::Resolver;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    lisp::defsubr,
+    lisp::LispObject,
+    multibyte::LispStringRef,
+    obarray::intern,
+    remacs_sys::{make_unibyte_string, maybe_quit, EmacsDouble, EmacsInt, Qnil},
+};
+
+/// The number of milliseconds between checks on lookups in progress.
+const DNS_POLL_MS: u64 = 50;
+
+lazy_static! {
+    /// The resolver all lookups go through, so that they share its cache.
+    static ref DNS_RESOLVER: Result<Resolver, String> =
+        Resolver::from_system_conf().map_err(|error| error.to_string());
+
+    /// The lookups started by `dns-resolve' with a callback that have
+    /// not been reported yet, by job number.
+    static ref DNS_JOBS: Mutex<DnsJobs> = Mutex::new(DnsJobs {
+        next_id: 0,
+        jobs: HashMap::new(),
+    });
+}
+
+struct DnsJobs {
+    next_id: EmacsInt,
+    jobs: HashMap<EmacsInt, Receiver<Result<Vec<DnsRecord>, String>>>,
+}
+
+/// A record, with what `dns-resolve' returns of it.
+enum DnsRecord {
+    Address(IpAddr),
+    Name(Name),
+    Mx(u16, Name),
+    Srv(u16, u16, u16, Name),
+    Txt(Vec<u8>),
+    Caa(bool, String, Vec<u8>),
+    Soa(Name, Name, u32, i32, i32, i32, u32),
+}
+
+/// Return the name of NAME, without the dot at the end of fully
+/// qualified names.
+fn name_string(name: &Name) -> LispObject {
+    LispObject::from(name.to_utf8().trim_end_matches('.'))
+}
+
+fn unibyte_string(bytes: &[u8]) -> LispObject {
+    unsafe { make_unibyte_string(bytes.as_ptr() as *const c_char, bytes.len() as isize) }
+}
+
+impl DnsRecord {
+    fn from_rdata(rdata: &RData) -> Option<Self> {
+        let record = match rdata {
+            RData::A(address) => DnsRecord::Address(IpAddr::V4(*address)),
+            RData::AAAA(address) => DnsRecord::Address(IpAddr::V6(*address)),
+            RData::CNAME(name) => DnsRecord::Name(name.clone()),
+            RData::NS(name) => DnsRecord::Name(name.clone()),
+            RData::PTR(name) => DnsRecord::Name(name.clone()),
+            RData::MX(mx) => DnsRecord::Mx(mx.preference(), mx.exchange().clone()),
+            RData::SRV(srv) => DnsRecord::Srv(
+                srv.priority(),
+                srv.weight(),
+                srv.port(),
+                srv.target().clone(),
+            ),
+            RData::TXT(txt) => DnsRecord::Txt(txt.txt_data().concat()),
+            RData::CAA(caa) => return caa_record(caa),
+            RData::SOA(soa) => DnsRecord::Soa(
+                soa.mname().clone(),
+                soa.rname().clone(),
+                soa.serial(),
+                soa.refresh(),
+                soa.retry(),
+                soa.expire(),
+                soa.minimum(),
+            ),
+            _ => return None,
+        };
+        Some(record)
+    }
+
+    /// Return the record as `dns-resolve' documents it, which is for the
+    /// most part as `dns-query' returns records.
+    fn to_lisp(&self) -> LispObject {
+        let int = |n: i64| LispObject::from(n as EmacsInt);
+        let field = |name: &str, value: LispObject| list!(LispObject::from(intern(name)), value);
+        match self {
+            DnsRecord::Address(address) => LispObject::from(address.to_string().as_str()),
+            DnsRecord::Name(name) => name_string(name),
+            DnsRecord::Mx(preference, exchange) => {
+                LispObject::cons(int(i64::from(*preference)), name_string(exchange))
+            }
+            DnsRecord::Srv(priority, weight, port, target) => list!(
+                field("priority", int(i64::from(*priority))),
+                field("weight", int(i64::from(*weight))),
+                field("port", int(i64::from(*port))),
+                field("target", name_string(target))
+            ),
+            DnsRecord::Txt(text) => unibyte_string(text),
+            DnsRecord::Caa(critical, tag, value) => list!(
+                LispObject::from(*critical),
+                LispObject::from(tag.as_str()),
+                unibyte_string(value)
+            ),
+            DnsRecord::Soa(mname, rname, serial, refresh, retry, expire, minimum) => list!(
+                field("mname", name_string(mname)),
+                field("rname", name_string(rname)),
+                field("serial", int(i64::from(*serial))),
+                field("refresh", int(i64::from(*refresh))),
+                field("retry", int(i64::from(*retry))),
+                field("expire", int(i64::from(*expire))),
+                field("minimum", int(i64::from(*minimum)))
+            ),
+        }
+    }
+}
+
+/// Return the CAA record CAA.  Its tag and value are taken from the
+/// record as it goes on the wire, since trust-dns doesn't give the
+/// parameters of issuers back.
+fn caa_record(record: &CAA) -> Option<DnsRecord> {
+    let mut bytes = Vec::new();
+    caa::emit(&mut BinEncoder::new(&mut bytes), record).ok()?;
+    // A flags byte, the length of the tag, the tag and the value.
+    let tag_end = 2 + *bytes.get(1)? as usize;
+    let tag = String::from_utf8_lossy(bytes.get(2..tag_end)?).into_owned();
+    let value = bytes[tag_end..].to_vec();
+    Some(DnsRecord::Caa(record.issuer_critical(), tag, value))
+}
+
+/// Return the type of records SYMBOL names.
+fn parse_record_type(symbol: LispObject) -> RecordType {
+    let symbol = symbol.as_symbol_or_error();
+    match symbol
+        .symbol_name()
+        .as_string_or_error()
+        .to_string()
+        .as_str()
+    {
+        "A" => RecordType::A,
+        "AAAA" => RecordType::AAAA,
+        "CAA" => RecordType::CAA,
+        "CNAME" => RecordType::CNAME,
+        "MX" => RecordType::MX,
+        "NS" => RecordType::NS,
+        "PTR" => RecordType::PTR,
+        "SOA" => RecordType::SOA,
+        "SRV" => RecordType::SRV,
+        "TXT" => RecordType::TXT,
+        _ => error!(
+            "Unsupported DNS record type: {}",
+            symbol.symbol_name().as_string_or_error()
+        ),
+    }
+}
+
+/// Return the records of type RECORD_TYPE of NAME.  An IP address is
+/// looked up in reverse for PTR records.
+fn lookup(name: &str, record_type: RecordType) -> Result<Vec<DnsRecord>, String> {
+    let resolver = DNS_RESOLVER.as_ref().map_err(String::clone)?;
+    let result = match (record_type, name.parse::<IpAddr>()) {
+        (RecordType::PTR, Ok(address)) => resolver
+            .reverse_lookup(address)
+            .map(|lookup| lookup.iter().cloned().map(DnsRecord::Name).collect()),
+        _ => resolver.lookup(name, record_type).map(|lookup| {
+            lookup
+                .iter()
+                .filter(|rdata| rdata.to_record_type() == record_type)
+                .filter_map(DnsRecord::from_rdata)
+                .collect()
+        }),
+    };
+    match result {
+        Ok(records) => Ok(records),
+        Err(ref error) if no_records(error) => Ok(Vec::new()),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+/// Return true if ERROR only says that there are no such records.
+fn no_records(error: &ResolveError) -> bool {
+    match error.kind() {
+        ResolveErrorKind::NoRecordsFound { .. } => true,
+        _ => false,
+    }
+}
+
+fn records_to_lisp(records: &[DnsRecord]) -> LispObject {
+    records
+        .iter()
+        .map(DnsRecord::to_lisp)
+        .collect::<Vec<LispObject>>()
+        .into()
+}
+
+fn schedule_dns_poll(job: LispObject, callback: LispObject) {
+    call!(
+        LispObject::from(intern("run-with-timer")),
+        LispObject::from_float(DNS_POLL_MS as EmacsDouble / 1000.0),
+        Qnil,
+        LispObject::from(intern("dns--resolve-poll")),
+        job,
+        callback
+    );
+}
+
+/// Return the records of type RECORD-TYPE of the domain NAME.
+/// RECORD-TYPE is one of the symbols `A', `AAAA', `CNAME', `NS', `PTR', `MX',
+/// `SRV', `TXT', `CAA' and `SOA'.  The records are looked up with the
+/// name servers of the system, on a separate thread.  Return nil if
+/// NAME has no such records, or doesn't exist.  The records are:
+///
+/// For A and AAAA, the address, as a string.
+/// For CNAME, NS and PTR, the name, as a string.
+/// For MX, (PREFERENCE . EXCHANGE).
+/// For SRV, ((priority PRIORITY) (weight WEIGHT) (port PORT) (target
+/// TARGET)).
+/// For TXT, the text, with its strings concatenated, as a unibyte
+/// string.
+/// For CAA, (CRITICAL TAG VALUE), where CRITICAL is non-nil if the
+/// issuer critical flag is set, and VALUE is a unibyte string.
+/// For SOA, ((mname MNAME) (rname RNAME) (serial SERIAL) (refresh
+/// REFRESH) (retry RETRY) (expire EXPIRE) (minimum MINIMUM)).
+///
+/// These are in the form `dns-query' returns them in.  For PTR records,
+/// NAME may also be an IP address, to look up in reverse.
+///
+/// Without CALLBACK, wait for the records, which can be interrupted with
+/// \\[keyboard-quit], and signal an error if they can't be looked up.
+/// With CALLBACK, return a job number at once, and call CALLBACK from a
+/// timer with the records, or with a string describing the error.
+#[lisp_fn(min = "2")]
+pub fn dns_resolve(
+    name: LispStringRef,
+    record_type: LispObject,
+    callback: LispObject,
+) -> LispObject {
+    let record_type = parse_record_type(record_type);
+    let name = name.to_string();
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        // Nobody may be listening anymore if the wait was quit.
+        let _ = sender.send(lookup(&name, record_type));
+    });
+
+    if callback.is_not_nil() {
+        let mut jobs = DNS_JOBS.lock().unwrap();
+        jobs.next_id += 1;
+        let id = jobs.next_id;
+        jobs.jobs.insert(id, receiver);
+        drop(jobs);
+
+        schedule_dns_poll(LispObject::from(id), callback);
+        return LispObject::from(id);
+    }
+
+    let result = loop {
+        match receiver.recv_timeout(Duration::from_millis(DNS_POLL_MS)) {
+            Ok(result) => break result,
+            Err(RecvTimeoutError::Timeout) => unsafe { maybe_quit() },
+            Err(RecvTimeoutError::Disconnected) => error!("DNS thread exited unexpectedly"),
+        }
+    };
+    match result {
+        Ok(records) => records_to_lisp(&records),
+        Err(message) => error!("DNS lookup failed: {}", message),
+    }
+}
+
+/// Check on the lookup JOB.
+/// Call CALLBACK as documented in `dns-resolve' if JOB is finished, and
+/// reschedule the check otherwise.
+#[lisp_fn(name = "dns--resolve-poll")]
+pub fn dns_resolve_poll(job: EmacsInt, callback: LispObject) {
+    let result = {
+        let mut jobs = DNS_JOBS.lock().unwrap();
+        let result = match jobs.jobs.get(&job) {
+            None => return,
+            Some(receiver) => match receiver.try_recv() {
+                Err(TryRecvError::Empty) => None,
+                Ok(result) => Some(result),
+                Err(TryRecvError::Disconnected) => {
+                    Some(Err("DNS thread exited unexpectedly".to_string()))
+                }
+            },
+        };
+        if result.is_some() {
+            jobs.jobs.remove(&job);
+        }
+        result
+    };
+
+    match result {
+        None => schedule_dns_poll(LispObject::from(job), callback),
+        Some(Ok(records)) => {
+            call!(callback, records_to_lisp(&records));
+        }
+        Some(Err(message)) => {
+            call!(callback, LispObject::from(message.as_str()));
+        }
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/dns_exports.rs"));
@@ -2,11 +2,12 @@
 
 use std;
 use std::cmp::max;
+use std::collections::HashMap;
 use std::ops::{Add, Sub};
 use std::ptr;
 
 use libc;
-use libc::{c_int, c_uchar, ptrdiff_t};
+use libc::{c_char, c_int, c_uchar, ptrdiff_t};
 
 use remacs_macros::lisp_fn;
 
@@ -15,6 +16,8 @@ use crate::{
     buffers::{LispBufferOrCurrent, LispBufferOrName, LispBufferRef, BUF_BYTES_MAX},
     character::{char_head_p, dec_pos},
     eval::{progn, unbind_to},
+    format::styled_format,
+    hashtable::{puthash, LispHashTableRef},
     lisp::{defsubr, LispObject},
     marker::{
         buf_bytepos_to_charpos, buf_charpos_to_bytepos, marker_position_lisp, point_marker,
@@ -30,17 +33,18 @@ use crate::{
     remacs_sys::{
         buffer_overflow, build_string, current_message, del_range, del_range_1, downcase,
         find_before_next_newline, find_newline, get_char_property_and_overlay, globals, insert,
-        insert_and_inherit, insert_from_buffer, invalidate_current_column, make_buffer_string,
-        make_buffer_string_both, make_save_obj_obj_obj_obj, make_string_from_bytes, maybe_quit,
+        insert_and_inherit, insert_from_buffer, make_buffer_string, make_buffer_string_both,
+        make_save_obj_obj_obj_obj, make_specified_string, make_string_from_bytes, maybe_quit,
         message1, message3, record_unwind_current_buffer, record_unwind_protect,
         save_excursion_restore, save_restriction_restore, save_restriction_save,
-        scan_newline_from_point, set_buffer_internal_1, set_point, set_point_both, styled_format,
+        scan_newline_from_point, set_buffer_internal_1, set_point, set_point_both,
         update_buffer_properties, STRING_BYTES,
     },
     remacs_sys::{
-        Fadd_text_properties, Fcopy_sequence, Fget_pos_property, Fnext_single_char_property_change,
-        Fprevious_single_char_property_change, Fx_popup_dialog,
+        Fadd_text_properties, Fcopy_sequence, Fget_pos_property, Fmake_hash_table,
+        Fnext_single_char_property_change, Fprevious_single_char_property_change, Fx_popup_dialog,
     },
+    remacs_sys::{QCsize, QCtest, Qeql},
     remacs_sys::{Qboundary, Qfield, Qinteger_or_marker_p, Qmark_inactive, Qnil, Qt},
     textprop::get_char_property,
     threads::{c_specpdl_index, ThreadState},
@@ -403,6 +407,58 @@ pub fn char_after(mut pos: LispObject) -> Option<EmacsInt> {
     }
 }
 
+/// Return a hash table counting the characters between START and END.
+/// The keys of the table are the characters which occur in the region,
+/// and the values the number of times each of them occurs.  The table
+/// uses `eql' as its test, and holds the characters in increasing order.
+///
+/// The text is read in a single pass over the bytes of the buffer, which
+/// is much faster than looking at each character with `char-after'.
+#[lisp_fn]
+pub fn region_char_histogram(start: LispObject, end: LispObject) -> LispObject {
+    let (mut start, mut end) = (start, end);
+    unsafe { validate_region(&mut start, &mut end) };
+
+    let start = start.as_fixnum_or_error() as ptrdiff_t;
+    let end = end.as_fixnum_or_error() as ptrdiff_t;
+
+    let mut buffer = ThreadState::current_buffer();
+    let start_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), start) };
+    let end_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), end) };
+    let multibyte = buffer.multibyte_characters_enabled();
+
+    let mut counts: HashMap<Codepoint, EmacsInt> = HashMap::new();
+    // The gap is always between characters.
+    for chunk in buffer.byte_chunks(start_byte, end_byte) {
+        if multibyte {
+            let mut offset = 0;
+            while offset < chunk.len() {
+                let (c, len) = multibyte_char_at(&chunk[offset..]);
+                *counts.entry(c).or_insert(0) += 1;
+                offset += len;
+            }
+        } else {
+            for &byte in chunk {
+                *counts.entry(Codepoint::from(byte)).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort();
+    let mut args = [QCtest, Qeql, QCsize, LispObject::from(counts.len())];
+    let table = unsafe { Fmake_hash_table(args.len() as ptrdiff_t, args.as_mut_ptr()) };
+    let hash_table: LispHashTableRef = table.into();
+    for (c, count) in counts {
+        puthash(
+            LispObject::from(EmacsInt::from(c)),
+            LispObject::from(count),
+            hash_table,
+        );
+    }
+    table
+}
+
 /// Return a copy of STRING with text properties added.
 /// First argument is the string to copy.
 /// Remaining arguments form a sequence of PROPERTY VALUE pairs for text
@@ -1042,13 +1098,7 @@ pub fn lisp_current_message() -> LispObject {
 /// usage: (format STRING &rest OBJECTS)
 #[lisp_fn(min = "1")]
 pub fn format(args: &mut [LispObject]) -> LispObject {
-    unsafe {
-        styled_format(
-            args.len() as isize,
-            args.as_mut_ptr() as *mut LispObject,
-            false,
-        )
-    }
+    styled_format(args, false)
 }
 
 /// Format a string out of a format-string and arguments.
@@ -1063,13 +1113,7 @@ pub fn format(args: &mut [LispObject]) -> LispObject {
 /// usage: (format-message STRING &rest OBJECTS)
 #[lisp_fn(min = "1")]
 pub fn format_message(args: &mut [LispObject]) -> LispObject {
-    unsafe {
-        styled_format(
-            args.len() as isize,
-            args.as_mut_ptr() as *mut LispObject,
-            true,
-        )
-    }
+    styled_format(args, true)
 }
 
 /// Return the contents of the current buffer as a string.
@@ -1115,6 +1159,43 @@ pub fn buffer_substring_no_properties(mut beg: LispObject, mut end: LispObject)
     unsafe { make_buffer_string(b as isize, e as isize, false) }
 }
 
+/// Return the characters of part of BUFFER, without the text properties.
+/// BUFFER defaults to the current buffer.  START and END are positions in
+/// its accessible portion, in either order.
+///
+/// This is `buffer-substring-no-properties' for any buffer, so that
+/// filters and exporters need not make BUFFER current to read from it.
+/// The text is copied into the string in one go, and the intervals of
+/// the buffer are never looked at.
+#[lisp_fn(min = "2")]
+pub fn buffer_substring_literal(
+    start: LispObject,
+    end: LispObject,
+    buffer: LispBufferOrCurrent,
+) -> LispObject {
+    let mut buffer: LispBufferRef = buffer.into();
+    let mut from = start.as_number_coerce_marker_or_error().to_fixnum() as ptrdiff_t;
+    let mut to = end.as_number_coerce_marker_or_error().to_fixnum() as ptrdiff_t;
+    if from > to {
+        std::mem::swap(&mut from, &mut to);
+    }
+    if !(buffer.begv <= from && to <= buffer.zv) {
+        args_out_of_range!(start, end);
+    }
+
+    let from_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), from) };
+    let to_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), to) };
+    let text = buffer.text_between(from_byte, to_byte);
+    unsafe {
+        make_specified_string(
+            text.as_ptr() as *const c_char,
+            to - from,
+            text.len() as ptrdiff_t,
+            buffer.multibyte_characters_enabled(),
+        )
+    }
+}
+
 // Save current buffer state for `save-excursion' special form.
 // We (ab)use Lisp_Misc_Save_Value to allow explicit free and so
 // offload some work from GC.
@@ -1438,23 +1519,4 @@ pub fn time_less_p(t1: LispObject, t2: LispObject) -> bool {
     a < b
 }
 
-/// Remove restrictions (narrowing) from current buffer.
-/// This allows the buffer's full text to be seen and edited.
-#[lisp_fn(intspec = "")]
-pub fn widen() {
-    let mut buffer_ref = ThreadState::current_buffer();
-
-    if buffer_ref.beg() != buffer_ref.begv || buffer_ref.z() != buffer_ref.zv {
-        buffer_ref.set_clip_changed(true);
-    }
-
-    buffer_ref.set_begv_both(buffer_ref.beg(), buffer_ref.beg_byte());
-    buffer_ref.set_zv_both(buffer_ref.z(), buffer_ref.z_byte());
-
-    // Changing the buffer bounds invalidates any recorded current column.
-    unsafe {
-        invalidate_current_column();
-    }
-}
-
 include!(concat!(env!("OUT_DIR"), "/editfns_exports.rs"));
@@ -22,9 +22,10 @@ use crate::{
     remacs_sys::{pvec_type, EmacsInt, Lisp_Compiled, Set_Internal_Bind},
     remacs_sys::{Fapply, Fdefault_value, Fload, Fpurecopy},
     remacs_sys::{
-        QCdocumentation, Qautoload, Qclosure, Qerror, Qexit, Qfunction, Qinteractive,
-        Qinteractive_form, Qinternal_interpreter_environment, Qinvalid_function, Qlambda, Qmacro,
-        Qnil, Qrisky_local_variable, Qsetq, Qt, Qunbound, Qvariable_documentation, Qvoid_function,
+        QCdocumentation, Qautoload, Qclosure, Qerror, Qerror_conditions, Qerror_message, Qexit,
+        Qfunction, Qinteractive, Qinteractive_form, Qinternal_interpreter_environment,
+        Qinvalid_function, Qlambda, Qmacro, Qnil, Qrisky_local_variable, Qsetq, Qt, Qunbound,
+        Qvariable_documentation, Qvoid_function,
     },
     remacs_sys::{Vautoload_queue, Vrun_hooks},
     symbols::{fboundp, symbol_function, LispSymbolRef},
@@ -598,6 +599,22 @@ fn signal_error(msg: &str, arg: LispObject) -> ! {
     );
 }
 
+/// Make NAME an error symbol, with MESSAGE as its message and PARENT as
+/// its parent error.  This is a simplified version of `define-error',
+/// for use in the `syms_of_*' functions.
+pub fn define_error(name: LispObject, message: &str, parent: LispObject) {
+    let parent_conditions = get(parent.into(), Qerror_conditions);
+    debug_assert!(parent_conditions.is_cons());
+    debug_assert!(memq(name, parent_conditions).is_nil());
+
+    put(
+        name.into(),
+        Qerror_conditions,
+        LispObject::cons(name, parent_conditions),
+    );
+    put(name.into(), Qerror_message, LispObject::from(message));
+}
+
 /// Non-nil if FUNCTION makes provisions for interactive calling.
 /// This means it contains a description for how to read arguments to give it.
 /// The value is nil for an invalid function or a symbol with no function
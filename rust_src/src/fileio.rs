@@ -1,24 +1,480 @@
 //! Functions to deal with files
 use errno::{set_errno, Errno};
 
+use std::collections::HashMap;
+#[cfg(target_os = "linux")]
+use std::ffi::CString;
+#[cfg(unix)]
+use std::ffi::OsStr;
+#[cfg(unix)]
+use std::fs::Metadata;
+use std::fs::{self, File, OpenOptions};
+#[cfg(unix)]
+use std::io::Read;
+use std::io::{self, BufReader, Write};
+#[cfg(unix)]
+use std::mem::{self, ManuallyDrop};
+#[cfg(unix)]
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, FromRawFd};
 use std::path;
+use std::ptr;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::sync::Mutex;
+use std::thread;
+
+#[cfg(target_os = "linux")]
+use libc::c_void;
+use libc::{c_char, c_int, off_t, ptrdiff_t};
+#[cfg(unix)]
+use memmap::{Mmap, MmapOptions};
 
 use remacs_macros::lisp_fn;
 
 use crate::{
+    decompress::insert_compressed_file,
+    editfns::buffer_substring_no_properties,
     lisp::defsubr,
+    lisp::LispObject,
     lists::LispCons,
     math::{arithcompare, ArithComparison},
     multibyte::LispStringRef,
+    obarray::intern,
     remacs_sys::{
-        check_executable, check_existing, encode_file_name, file_name_absolute_p,
-        file_name_case_insensitive_p,
+        check_executable, check_existing, code_convert_string, decode_file_name, encode_file_name,
+        file_name_absolute_p, file_name_case_insensitive_p, globals, make_buffer_string,
+        make_unibyte_string, report_file_error, EmacsDouble, EmacsInt,
     },
-    remacs_sys::{Fexpand_file_name, Ffind_file_name_handler},
-    remacs_sys::{Qfile_executable_p, Qfile_exists_p, Qfile_name_case_insensitive_p, Qnil},
+    remacs_sys::{Fexpand_file_name, Ffind_file_name_handler, Ffind_operation_coding_system},
+    remacs_sys::{Qdata, Qfile_executable_p, Qfile_exists_p, Qfile_name_case_insensitive_p},
+    remacs_sys::{Qnil, Qnone, Qraw_text, Qwrite_region},
     threads::ThreadState,
 };
 
+#[cfg(unix)]
+use crate::{
+    remacs_sys::{find_symbol_value, maybe_quit, Qunbound},
+    sigbus,
+};
+
+/// Return ENCODED, a file name as `encode_file_name' returns it, as a
+/// path for the system.
+#[cfg(unix)]
+pub fn encoded_file_path(encoded: LispObject) -> path::PathBuf {
+    path::PathBuf::from(OsStr::from_bytes(encoded.as_string_or_error().as_slice()))
+}
+
+/// Return ENCODED, a file name as `encode_file_name' returns it, as a
+/// path for the system.  File names are encoded in UTF-8 on MS-Windows.
+#[cfg(windows)]
+pub fn encoded_file_path(encoded: LispObject) -> path::PathBuf {
+    let name = String::from_utf8_lossy(encoded.as_string_or_error().as_slice());
+    path::PathBuf::from(name.into_owned())
+}
+
+/// Return the file name of PATH, a path from the system, decoded as
+/// `decode_file_name' decodes it.
+#[cfg(unix)]
+pub fn path_file_name(path: &path::Path) -> LispObject {
+    let name = path.as_os_str().as_bytes();
+    unsafe {
+        decode_file_name(make_unibyte_string(
+            name.as_ptr() as *const c_char,
+            name.len() as ptrdiff_t,
+        ))
+    }
+}
+
+/// Return the file name of PATH, a path from the system, decoded as
+/// `decode_file_name' decodes it.
+#[cfg(windows)]
+pub fn path_file_name(path: &path::Path) -> LispObject {
+    let name = path.to_string_lossy();
+    unsafe {
+        decode_file_name(make_unibyte_string(
+            name.as_ptr() as *const c_char,
+            name.len() as ptrdiff_t,
+        ))
+    }
+}
+
+/// Return true if `insert-file-contents' should decode the TOTAL bytes
+/// it reads from a regular file straight from a mapping of the file,
+/// which is when TOTAL is at least `insert-file-contents-mmap-threshold'.
+#[no_mangle]
+pub extern "C" fn read_file_mapped_p(total: off_t) -> bool {
+    let threshold = unsafe { globals.Vinsert_file_contents_mmap_threshold };
+    match threshold.as_fixnum() {
+        Some(threshold) => cfg!(unix) && total > 0 && total as EmacsInt >= threshold,
+        None => false,
+    }
+}
+
+#[cfg(unix)]
+lazy_static! {
+    /// The mappings `map_file_for_read' made, by address.
+    static ref READ_MAPPINGS: Mutex<HashMap<usize, Mmap>> = Mutex::new(HashMap::new());
+}
+
+/// Map the TOTAL bytes at OFFSET in the regular file open on FD into
+/// memory, unless the file is smaller than that.
+#[cfg(unix)]
+fn map_file(fd: c_int, offset: off_t, total: ptrdiff_t) -> Option<Mmap> {
+    // FD is only borrowed, and is closed by the caller.
+    let file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+    // Mapping more than the file holds would make reading past its end
+    // fault at once.
+    match file.metadata() {
+        Ok(metadata) if metadata.len() >= (offset as u64) + (total as u64) => (),
+        _ => return None,
+    }
+    unsafe {
+        MmapOptions::new()
+            .offset(offset as u64)
+            .len(total as usize)
+            .map(&*file)
+            .ok()
+    }
+}
+
+/// Map the TOTAL bytes at OFFSET in the regular file open on FD into
+/// memory, and return their address, or null if the file couldn't be
+/// mapped.  Pages of the mapping that the file loses before
+/// `unmap_file_for_read' read as zeros.
+#[no_mangle]
+pub extern "C" fn map_file_for_read(fd: c_int, offset: off_t, total: ptrdiff_t) -> *const u8 {
+    #[cfg(unix)]
+    {
+        match map_file(fd, offset, total) {
+            Some(map) => {
+                let addr = map.as_ptr();
+                sigbus::guard_mapping(addr, map.len());
+                READ_MAPPINGS.lock().unwrap().insert(addr as usize, map);
+                addr
+            }
+            None => ptr::null(),
+        }
+    }
+    #[cfg(windows)]
+    {
+        let _ = (fd, offset, total);
+        ptr::null()
+    }
+}
+
+/// Unmap the file `map_file_for_read' mapped at ADDR.  Return false if
+/// the file was truncated while it was mapped, so that some of what was
+/// read from the mapping was zeros instead of the file.
+#[no_mangle]
+pub extern "C" fn unmap_file_for_read(addr: *const u8) -> bool {
+    #[cfg(unix)]
+    {
+        // The mapping may already be gone, if a quit unwound after the
+        // caller unmapped it.
+        match READ_MAPPINGS.lock().unwrap().remove(&(addr as usize)) {
+            Some(_) => sigbus::unguard_mapping(addr),
+            None => true,
+        }
+    }
+    #[cfg(windows)]
+    {
+        let _ = addr;
+        true
+    }
+}
+
+/// The number of bytes `copy-file' copies between checks for a quit and
+/// calls to `copy-file-progress-function'.
+#[cfg(unix)]
+const COPY_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// The size of the buffer `copy-file' reads into when the kernel can't
+/// copy files itself.
+#[cfg(unix)]
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// `_IOW (0x94, 9, int)', which makes a file share the contents of
+/// another on file systems that support it.
+#[cfg(target_os = "linux")]
+const FICLONE: libc::c_ulong = 0x4004_9409;
+
+/// The ways of copying file contents, from the fastest to the most
+/// widely supported.
+#[cfg(unix)]
+#[derive(Clone, Copy, PartialEq)]
+enum CopyMethod {
+    CopyFileRange,
+    Sendfile,
+    ReadWrite,
+}
+
+#[cfg(unix)]
+impl CopyMethod {
+    #[cfg(target_os = "linux")]
+    const FIRST: CopyMethod = CopyMethod::CopyFileRange;
+    #[cfg(not(target_os = "linux"))]
+    const FIRST: CopyMethod = CopyMethod::ReadWrite;
+
+    fn next(self) -> CopyMethod {
+        match self {
+            CopyMethod::CopyFileRange => CopyMethod::Sendfile,
+            _ => CopyMethod::ReadWrite,
+        }
+    }
+}
+
+/// Why copying a file failed.
+#[cfg(unix)]
+enum CopyError {
+    Read(io::Error),
+    Write(io::Error),
+}
+
+/// Make the file open on DEST share the contents of the file open on
+/// SOURCE, and return true if that worked.
+#[cfg(unix)]
+fn clone_file(dest: c_int, source: c_int) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        unsafe { libc::ioctl(dest, FICLONE, source) == 0 }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (dest, source);
+        false
+    }
+}
+
+/// Have the kernel copy up to COPY_CHUNK_SIZE bytes from IFD to OFD with
+/// METHOD, and return the number of bytes copied, or -1 with errno set.
+#[cfg(target_os = "linux")]
+fn kernel_copy(ifd: c_int, ofd: c_int, method: CopyMethod) -> isize {
+    match method {
+        CopyMethod::CopyFileRange => unsafe {
+            libc::syscall(
+                libc::SYS_copy_file_range,
+                ifd,
+                ptr::null_mut::<off_t>(),
+                ofd,
+                ptr::null_mut::<off_t>(),
+                COPY_CHUNK_SIZE,
+                0,
+            ) as isize
+        },
+        CopyMethod::Sendfile => unsafe {
+            libc::sendfile(ofd, ifd, ptr::null_mut(), COPY_CHUNK_SIZE)
+        },
+        CopyMethod::ReadWrite => unreachable!(),
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn kernel_copy(_ifd: c_int, _ofd: c_int, _method: CopyMethod) -> isize {
+    unreachable!()
+}
+
+/// Copy up to COPY_CHUNK_SIZE bytes from IFD to OFD by reading them into
+/// BUFFER, and return the number of bytes copied.
+#[cfg(unix)]
+fn read_write_chunk(ifd: c_int, ofd: c_int, buffer: &mut [u8]) -> Result<usize, CopyError> {
+    // The files are only borrowed, and are closed by the caller.
+    let mut input = ManuallyDrop::new(unsafe { File::from_raw_fd(ifd) });
+    let mut output = ManuallyDrop::new(unsafe { File::from_raw_fd(ofd) });
+
+    let mut copied = 0;
+    while copied < COPY_CHUNK_SIZE {
+        let read = match input.read(buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(ref error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(CopyError::Read(error)),
+        };
+        output
+            .write_all(&buffer[..read])
+            .map_err(CopyError::Write)?;
+        copied += read;
+    }
+    Ok(copied)
+}
+
+/// Copy up to COPY_CHUNK_SIZE bytes from IFD to OFD with METHOD, and
+/// return the number of bytes copied, which is 0 at the end of the
+/// input.  If the kernel can't copy between these files, fall back on
+/// the next method.  STARTED says whether anything has been copied yet.
+#[cfg(unix)]
+fn copy_chunk(
+    ifd: c_int,
+    ofd: c_int,
+    method: &mut CopyMethod,
+    buffer: &mut [u8],
+    started: bool,
+) -> Result<usize, CopyError> {
+    loop {
+        if *method == CopyMethod::ReadWrite {
+            return read_write_chunk(ifd, ofd, buffer);
+        }
+
+        let copied = kernel_copy(ifd, ofd, *method);
+        // Files in some file systems, like /proc, claim to be empty to
+        // the kernel, but can still be read.
+        if copied == 0 && !started {
+            *method = CopyMethod::ReadWrite;
+            continue;
+        }
+        if copied >= 0 {
+            return Ok(copied as usize);
+        }
+
+        let error = io::Error::last_os_error();
+        match error.raw_os_error() {
+            Some(libc::EINTR) => (),
+            Some(libc::ENOSYS)
+            | Some(libc::EXDEV)
+            | Some(libc::EINVAL)
+            | Some(libc::EOPNOTSUPP) => *method = method.next(),
+            // The kernel doesn't say which file failed.
+            _ => return Err(CopyError::Write(error)),
+        }
+    }
+}
+
+/// Call `copy-file-progress-function', if it is set, with COPIED and
+/// TOTAL.
+#[cfg(unix)]
+fn report_copy_progress(copied: off_t, total: off_t) {
+    let function = unsafe { globals.Vcopy_file_progress_function };
+    if function.is_not_nil() {
+        call!(
+            function,
+            LispObject::from(copied as EmacsInt),
+            LispObject::from(total as EmacsInt)
+        );
+    }
+}
+
+/// Copy the contents of the regular file open on IFD, which is SIZE
+/// bytes long, to the file open on OFD, for `copy-file'.  The files share
+/// the data if the file system supports it, and otherwise the kernel
+/// copies it where it can, falling back on reading and writing it.
+/// Return the number of bytes copied, or -1 on a read error and -2 on a
+/// write error, with errno set.  Quits are checked for as the data is
+/// copied, and `copy-file-progress-function' is called.
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn copy_file_contents(ifd: c_int, ofd: c_int, size: off_t) -> off_t {
+    if clone_file(ofd, ifd) {
+        report_copy_progress(size, size);
+        return size;
+    }
+
+    let mut method = CopyMethod::FIRST;
+    let mut buffer = vec![0; COPY_BUFFER_SIZE];
+    let mut copied: off_t = 0;
+    loop {
+        match copy_chunk(ifd, ofd, &mut method, &mut buffer, copied > 0) {
+            Ok(0) => return copied,
+            Ok(chunk) => copied += chunk as off_t,
+            Err(CopyError::Read(error)) => {
+                set_errno(Errno(error.raw_os_error().unwrap_or(libc::EIO)));
+                return -1;
+            }
+            Err(CopyError::Write(error)) => {
+                set_errno(Errno(error.raw_os_error().unwrap_or(libc::EIO)));
+                return -2;
+            }
+        }
+        report_copy_progress(copied, size);
+        unsafe { maybe_quit() };
+    }
+}
+
+/// Return the list of extended attribute names or the value that GET
+/// reads.  GET is called with an empty buffer first, to find out how
+/// much room it needs, and again if that grew in between.
+#[cfg(target_os = "linux")]
+fn read_xattr<F>(get: F) -> io::Result<Vec<u8>>
+where
+    F: Fn(*mut u8, usize) -> isize,
+{
+    loop {
+        let size = get(ptr::null_mut(), 0);
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut buffer = vec![0; size as usize];
+        let read = get(buffer.as_mut_ptr(), buffer.len());
+        if read >= 0 {
+            buffer.truncate(read as usize);
+            return Ok(buffer);
+        }
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() != Some(libc::ERANGE) {
+            return Err(error);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn copy_user_xattrs(ifd: c_int, ofd: c_int) -> Result<(), CopyError> {
+    let names =
+        read_xattr(|list, size| unsafe { libc::flistxattr(ifd, list as *mut c_char, size) })
+            .map_err(CopyError::Read)?;
+    for name in names
+        .split(|&b| b == 0)
+        .filter(|name| name.starts_with(b"user."))
+    {
+        let name = CString::new(name).unwrap();
+        let value = read_xattr(|value, size| unsafe {
+            libc::fgetxattr(ifd, name.as_ptr(), value as *mut c_void, size)
+        })
+        .map_err(CopyError::Read)?;
+        let set = unsafe {
+            libc::fsetxattr(
+                ofd,
+                name.as_ptr(),
+                value.as_ptr() as *const c_void,
+                value.len(),
+                0,
+            )
+        };
+        if set != 0 {
+            return Err(CopyError::Write(io::Error::last_os_error()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn copy_user_xattrs(_ifd: c_int, _ofd: c_int) -> Result<(), CopyError> {
+    Ok(())
+}
+
+/// Copy the extended attributes in the `user' namespace of the file open
+/// on IFD to the file open on OFD, for `copy-file'.  ACL entries and
+/// SELinux contexts are kept in other namespaces, and are copied
+/// separately.  Return 0 on success, and -2 if the attributes couldn't
+/// be read or -1 if they couldn't be written, with errno set.  A file
+/// system without extended attributes is not an error.
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn copy_file_xattrs(ifd: c_int, ofd: c_int) -> c_int {
+    let (result, error) = match copy_user_xattrs(ifd, ofd) {
+        Ok(()) => return 0,
+        Err(CopyError::Read(error)) => (-2, error),
+        Err(CopyError::Write(error)) => (-1, error),
+    };
+    match error.raw_os_error() {
+        Some(libc::ENOTSUP) => 0,
+        errno => {
+            set_errno(Errno(errno.unwrap_or(libc::EIO)));
+            result
+        }
+    }
+}
+
 /// Return t if (car A) is numerically less than (car B).
 #[lisp_fn]
 pub fn car_less_than_car(a: LispCons, b: LispCons) -> bool {
@@ -136,4 +592,464 @@ pub fn file_executable_p(filename: LispStringRef) -> bool {
     }
 }
 
+/// Insert the decompressed contents of FILENAME after point, if it is
+/// compressed with gzip, zstd or xz.  The compression is recognized by
+/// the magic number at the start of the file, whatever its name, and the
+/// file is decompressed as it is read, without running a program.  The
+/// decompressed data is inserted as raw bytes, for the caller to decode,
+/// as `decode-coding-inserted-region' does.  Return the number of
+/// characters inserted, or nil if FILENAME is not compressed in one of
+/// these formats, in which case the buffer is left unchanged.
+/// File name handlers are not called; FILENAME must be a local file.
+#[lisp_fn]
+pub fn insert_file_contents_decompressed(filename: LispStringRef) -> LispObject {
+    let current_buffer = ThreadState::current_buffer();
+    let file = unsafe { Fexpand_file_name(filename.into(), current_buffer.directory_) };
+    let encoded_file = unsafe { encode_file_name(file) };
+    let path = encoded_file_path(encoded_file);
+
+    let input = match File::open(path) {
+        Ok(input) => BufReader::new(input),
+        Err(_) => unsafe {
+            report_file_error(
+                "Opening input file\0".as_ptr() as *const c_char,
+                filename.into(),
+            )
+        },
+    };
+
+    match insert_compressed_file(input) {
+        Ok(Some(inserted)) => LispObject::from(inserted as EmacsInt),
+        Ok(None) => Qnil,
+        Err(err) => error!("Error decompressing file: {}", err),
+    }
+}
+
+/// How much of a file written by `write-region-async' is flushed to
+/// disk once it has been written.
+enum SyncMode {
+    None,
+    Data,
+    Full,
+}
+
+/// Return the sync mode `write-region-sync-mode' asks for.
+fn sync_mode() -> SyncMode {
+    let mode = unsafe { globals.Vwrite_region_sync_mode };
+    if unsafe { globals.write_region_inhibit_fsync } || mode.eq(Qnone) {
+        SyncMode::None
+    } else if mode.eq(Qdata) {
+        SyncMode::Data
+    } else {
+        SyncMode::Full
+    }
+}
+
+/// Write DATA to the file at PATH, appending to it if APPEND, and then
+/// flush it to disk as MODE says.
+fn write_file(path: &path::Path, data: &[u8], append: bool, mode: SyncMode) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)?;
+    file.write_all(data)?;
+    match mode {
+        SyncMode::None => Ok(()),
+        SyncMode::Data => file.sync_data(),
+        SyncMode::Full => file.sync_all(),
+    }
+}
+
+/// Return the coding system to encode TEXT with when writing it to
+/// FILENAME, the way `write-region' chooses it, except that
+/// `select-safe-coding-system' is never asked.
+fn write_coding_system(text: LispObject, filename: LispObject) -> LispObject {
+    let coding_system = unsafe { globals.Vcoding_system_for_write };
+    if coding_system.is_not_nil() {
+        return coding_system;
+    }
+    let mut args = [Qwrite_region, text, Qnil, filename];
+    let val = unsafe { Ffind_operation_coding_system(4, args.as_mut_ptr()) };
+    if let Some(cons) = val.as_cons() {
+        if cons.cdr().is_not_nil() {
+            return cons.cdr();
+        }
+    }
+    let buffer = ThreadState::current_buffer();
+    if buffer.buffer_file_coding_system_.is_not_nil() {
+        buffer.buffer_file_coding_system_
+    } else {
+        Qraw_text
+    }
+}
+
+/// The number of seconds between checks on background writes.
+const WRITE_POLL_INTERVAL: EmacsDouble = 0.05;
+
+struct WriteJobs {
+    next_id: EmacsInt,
+    jobs: HashMap<EmacsInt, Receiver<io::Result<()>>>,
+}
+
+lazy_static! {
+    /// The background writes that have not been reported yet, by job
+    /// number.
+    static ref WRITE_JOBS: Mutex<WriteJobs> = Mutex::new(WriteJobs {
+        next_id: 0,
+        jobs: HashMap::new(),
+    });
+}
+
+fn schedule_write_poll(job: LispObject, filename: LispObject, callback: LispObject) {
+    call!(
+        LispObject::from(intern("run-with-timer")),
+        LispObject::from_float(WRITE_POLL_INTERVAL),
+        Qnil,
+        LispObject::from(intern("fileio--write-region-async-poll")),
+        job,
+        filename,
+        callback
+    );
+}
+
+/// Write current region into specified file in the background.
+/// START and END are as for `write-region': when START is nil, the whole
+/// buffer is written, and when it is a string, that string is written.
+/// The text is encoded as `write-region' would encode it, but is then
+/// written, and flushed to disk as `write-region-sync-mode' says, on a
+/// separate thread, so that Emacs stays responsive while large buffers
+/// are saved.  If APPEND is non-nil, the text is appended to FILENAME.
+///
+/// When the write is finished, CALLBACK is called from a timer with two
+/// arguments: the expanded FILENAME, and nil if the write succeeded or
+/// a string describing the error if it failed.
+///
+/// Unlike `write-region', this does not visit the file, lock it, or run
+/// `write-region-annotate-functions', and FILENAME must be a local file.
+/// Return a job number.
+#[lisp_fn(min = "4")]
+pub fn write_region_async(
+    start: LispObject,
+    end: LispObject,
+    filename: LispStringRef,
+    callback: LispObject,
+    append: bool,
+) -> EmacsInt {
+    let current_buffer = ThreadState::current_buffer();
+    let file = unsafe { Fexpand_file_name(filename.into(), current_buffer.directory_) };
+    if unsafe { Ffind_file_name_handler(file, Qwrite_region) }.is_not_nil() {
+        error!("Cannot write {} in the background", filename);
+    }
+
+    let text = if start.is_nil() {
+        unsafe { make_buffer_string(current_buffer.beg(), current_buffer.z(), false) }
+    } else if start.is_string() {
+        start
+    } else {
+        buffer_substring_no_properties(start, end)
+    };
+    let coding_system = write_coding_system(text, file);
+    let encoded = unsafe { code_convert_string(text, coding_system, Qnil, true, false, false) };
+    let data = encoded.as_string_or_error().as_slice().to_vec();
+
+    let encoded_file = unsafe { encode_file_name(file) };
+    let path = encoded_file_path(encoded_file);
+    let mode = sync_mode();
+    let (sender, receiver) = channel();
+
+    thread::spawn(move || {
+        // Nobody may be listening anymore if Emacs is exiting.
+        let _ = sender.send(write_file(&path, &data, append, mode));
+    });
+
+    let mut jobs = WRITE_JOBS.lock().unwrap();
+    jobs.next_id += 1;
+    let id = jobs.next_id;
+    jobs.jobs.insert(id, receiver);
+    drop(jobs);
+
+    schedule_write_poll(LispObject::from(id), file, callback);
+    id
+}
+
+/// Check on the background write JOB to FILENAME.
+/// Call CALLBACK as documented in `write-region-async' if JOB is
+/// finished, and reschedule the check otherwise.
+#[lisp_fn(name = "fileio--write-region-async-poll")]
+pub fn write_region_async_poll(job: EmacsInt, filename: LispObject, callback: LispObject) {
+    let result = {
+        let mut jobs = WRITE_JOBS.lock().unwrap();
+        let result = match jobs.jobs.get(&job) {
+            None => return,
+            Some(receiver) => match receiver.try_recv() {
+                Err(TryRecvError::Empty) => None,
+                Ok(result) => Some(result.map_err(|err| err.to_string())),
+                // The writing thread died without sending a result.
+                Err(TryRecvError::Disconnected) => {
+                    Some(Err("Writing thread exited unexpectedly".to_string()))
+                }
+            },
+        };
+        if result.is_some() {
+            jobs.jobs.remove(&job);
+        }
+        result
+    };
+
+    match result {
+        None => schedule_write_poll(LispObject::from(job), filename, callback),
+        Some(Ok(())) => {
+            call!(callback, filename, Qnil);
+        }
+        Some(Err(message)) => {
+            call!(callback, filename, LispObject::from(message.as_str()));
+        }
+    }
+}
+
+/// A temporary file `write-region' writes in place of TARGET, and
+/// renames to TARGET once it has all been written.
+struct AtomicWrite {
+    temp: path::PathBuf,
+    target: path::PathBuf,
+}
+
+lazy_static! {
+    /// The atomic writes in progress, by the file descriptor of their
+    /// temporary file.
+    static ref ATOMIC_WRITES: Mutex<HashMap<c_int, AtomicWrite>> = Mutex::new(HashMap::new());
+}
+
+/// Return true if `backup-by-copying' is non-nil, which means the user
+/// wants files to stay where they are when they are saved.
+#[cfg(unix)]
+fn backup_by_copying() -> bool {
+    let value = unsafe { find_symbol_value(LispObject::from(intern("backup-by-copying"))) };
+    value.is_not_nil() && value != Qunbound
+}
+
+fn set_errno_from(error: &io::Error) {
+    set_errno(Errno(error.raw_os_error().unwrap_or(libc::EIO)));
+}
+
+/// Create a temporary file in the directory of TARGET, to be renamed to
+/// TARGET.  If METADATA, the metadata of TARGET, is given, the file gets
+/// the permissions, owner, group and extended attributes of TARGET, and
+/// creating it fails if the owner and group can't be kept.  Otherwise
+/// it gets the permissions MODE, less the umask.  Return the descriptor
+/// and name of the file.
+#[cfg(unix)]
+fn create_atomic_temp(
+    target: &path::Path,
+    metadata: Option<&Metadata>,
+    mode: c_int,
+) -> io::Result<(c_int, path::PathBuf)> {
+    let mut template = target
+        .parent()
+        .map_or_else(path::PathBuf::new, path::Path::to_path_buf)
+        .into_os_string()
+        .into_vec();
+    template.extend_from_slice(b"/.emacs-saveXXXXXX");
+    let template = String::from_utf8_lossy(&template).into_owned();
+    let (fd, temp) = remacs_lib::make_temporary_file(template, libc::O_CLOEXEC)
+        .map_err(io::Error::from_raw_os_error)?;
+    let temp = path::PathBuf::from(temp);
+
+    let result = match metadata {
+        Some(metadata) => copy_owner_and_mode(fd, metadata).map(|()| {
+            // Extended attributes are kept if possible, as `copy-file'
+            // keeps them, but they don't stop the file being saved.
+            if let Ok(original) = File::open(target) {
+                let _ = copy_user_xattrs(original.as_raw_fd(), fd);
+            }
+        }),
+        None => {
+            let umask = unsafe { libc::umask(0) };
+            unsafe { libc::umask(umask) };
+            fchmod(fd, mode as libc::mode_t & !umask)
+        }
+    };
+    match result {
+        Ok(()) => Ok((fd, temp)),
+        Err(error) => {
+            unsafe { libc::close(fd) };
+            let _ = fs::remove_file(&temp);
+            Err(error)
+        }
+    }
+}
+
+#[cfg(unix)]
+fn fchmod(fd: c_int, mode: libc::mode_t) -> io::Result<()> {
+    if unsafe { libc::fchmod(fd, mode) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Give the file open on FD the owner, group and permissions METADATA
+/// has.  The permissions come last, since changing the owner clears the
+/// set-user-ID bit.
+#[cfg(unix)]
+fn copy_owner_and_mode(fd: c_int, metadata: &Metadata) -> io::Result<()> {
+    let mut st: libc::stat = unsafe { mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut st) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if (st.st_uid, st.st_gid) != (metadata.uid(), metadata.gid())
+        && unsafe { libc::fchown(fd, metadata.uid(), metadata.gid()) } != 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    fchmod(fd, metadata.mode() as libc::mode_t & 0o7777)
+}
+
+/// Start writing the file whose encoded name is ENCODED_FILENAME
+/// atomically, for `write-region' when `write-region-atomic' is non-nil.
+/// Return the descriptor of a temporary file in the same directory to
+/// write to instead, which `atomic_write_commit' renames to the file, or
+/// -1 if the file should be written in place.
+///
+/// Replacing a file by renaming another one over it would split it from
+/// its other names if it has several, and would change its owner or
+/// group if Emacs can't give them to the new file, so such files are
+/// written in place, as `backup-by-copying-when-linked' and
+/// `backup-by-copying-when-mismatch' copy them.  So are all files when
+/// `backup-by-copying' is non-nil, and files which aren't regular files.
+/// If the file is a symbolic link, the file it points to is replaced.
+///
+/// Files are always written in place on MS-Windows, where the temporary
+/// file couldn't be given the owner and permissions of the file.
+#[no_mangle]
+pub extern "C" fn atomic_write_open(encoded_filename: LispObject, mode: c_int) -> c_int {
+    #[cfg(unix)]
+    {
+        if backup_by_copying() {
+            return -1;
+        }
+        start_atomic_write(&encoded_file_path(encoded_filename), mode)
+    }
+    #[cfg(windows)]
+    {
+        let _ = (encoded_filename, mode);
+        -1
+    }
+}
+
+/// Create the temporary file to write in place of the file PATH, for
+/// `atomic_write_open'.
+#[cfg(unix)]
+fn start_atomic_write(path: &path::Path, mode: c_int) -> c_int {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => Some(metadata),
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => None,
+        Err(_) => return -1,
+    };
+    let target = match metadata {
+        Some(ref metadata) if !metadata.is_file() || metadata.nlink() > 1 => return -1,
+        Some(_) => match fs::canonicalize(path) {
+            Ok(target) => target,
+            Err(_) => return -1,
+        },
+        // A dangling symbolic link is written through, as `open' does.
+        None if fs::symlink_metadata(path).is_ok() => return -1,
+        None => path.to_path_buf(),
+    };
+
+    match create_atomic_temp(&target, metadata.as_ref(), mode) {
+        Ok((fd, temp)) => {
+            ATOMIC_WRITES
+                .lock()
+                .unwrap()
+                .insert(fd, AtomicWrite { temp, target });
+            fd
+        }
+        Err(_) => -1,
+    }
+}
+
+/// Rename the temporary file of the atomic write whose descriptor was
+/// FD, which has been written and closed, to the file it replaces.
+/// Return false, with errno set, if that failed, in which case the
+/// temporary file is removed.
+#[no_mangle]
+pub extern "C" fn atomic_write_commit(fd: c_int) -> bool {
+    let write = match ATOMIC_WRITES.lock().unwrap().remove(&fd) {
+        Some(write) => write,
+        None => return true,
+    };
+    if let Err(error) = fs::rename(&write.temp, &write.target) {
+        let _ = fs::remove_file(&write.temp);
+        set_errno_from(&error);
+        return false;
+    }
+
+    // The rename itself only survives a crash once the directory is
+    // flushed.
+    match sync_mode() {
+        SyncMode::None => (),
+        _ => {
+            if let Some(dir) = write.target.parent() {
+                if let Ok(dir) = File::open(dir) {
+                    let _ = dir.sync_all();
+                }
+            }
+        }
+    }
+    true
+}
+
+/// Give up the atomic write whose descriptor was FD, removing its
+/// temporary file, because writing it failed or was interrupted.
+#[no_mangle]
+pub extern "C" fn atomic_write_discard(fd: c_int) {
+    if let Some(write) = ATOMIC_WRITES.lock().unwrap().remove(&fd) {
+        let _ = fs::remove_file(&write.temp);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn rust_syms_of_fileio() {
+    /// Size in bytes from which `insert-file-contents' maps files into memory.
+    /// Regular files of at least this size are decoded into the buffer
+    /// straight from a mapping of the file, rather than read in pieces and
+    /// then decoded, which is faster for very large files and never holds
+    /// the undecoded text in the buffer.  If nil, files are never mapped.
+    /// Files are never mapped on MS-Windows.
+    ///
+    /// If the file is truncated while it is being decoded, the text read
+    /// is removed again and a `file-error' is signaled.
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    defvar_lisp!(Vinsert_file_contents_mmap_threshold, "insert-file-contents-mmap-threshold", Qnil);
+
+    /// If non-nil, a function `copy-file' calls as it copies file contents.
+    /// It is called with two arguments, the number of bytes copied so far
+    /// and the size of the file, every megabyte or so.  Commands like
+    /// `dired-do-copy' can bind it to report progress on large files.
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    defvar_lisp!(Vcopy_file_progress_function, "copy-file-progress-function", Qnil);
+
+    /// Non-nil means `write-region' replaces files atomically.
+    /// The text is written to a temporary file in the same directory, which
+    /// is flushed to disk as `write-region-sync-mode' says and then renamed
+    /// over the file, so that a crash while saving never leaves the file
+    /// truncated.  Files with several names, files whose owner or group
+    /// Emacs couldn't keep, and all files when `backup-by-copying' is
+    /// non-nil, are still written in place.  Appending to files and
+    /// auto-saving are not affected.
+    defvar_bool!(write_region_atomic, "write-region-atomic", false);
+
+    /// How `write-region-async' and `write-region' flush files to disk.
+    /// The value `none' means files are not flushed, `data' means only
+    /// their contents are flushed, with `fdatasync', which is faster, and
+    /// `full' means their contents and metadata are flushed, with `fsync'.
+    /// Any other value is treated like `full'.  If `write-region-inhibit-fsync'
+    /// is non-nil, files are not flushed, whatever this says.
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    defvar_lisp!(Vwrite_region_sync_mode, "write-region-sync-mode", LispObject::from(intern("full")));
+}
+
 include!(concat!(env!("OUT_DIR"), "/fileio_exports.rs"));
@@ -0,0 +1,690 @@
+//! Lock files for editing, and advisory locks on files.
+//!
+//! To lock a file FN, Emacs creates a symbolic link .#FN in FN's
+//! directory, with link data USER@HOST.PID:BOOT, where :BOOT is omitted
+//! if the boot time is not available.  When the host in the lock data is
+//! the current host, whether the owner is still alive can be checked
+//! with kill; a lock held on another host is always taken to be live.
+//! Symbolic links are used because all the information about a lock can
+//! be read in a single system call.  On file systems where they don't
+//! work, the lock is a regular file .#FN with the same contents, which
+//! is written under a nonce name and then renamed to .#FN.
+//!
+//! On MS-Windows, where symbolic links can't be relied on, lock files
+//! are always regular files, and are made by filelock.c.
+//!
+//! These lock files only keep out other Emacsen and the tools which
+//! imitate them.  `file-flock' takes the advisory locks of the operating
+//! system instead, which any program can wait for.
+
+#[cfg(unix)]
+use errno::errno;
+use errno::{set_errno, Errno};
+
+use std::collections::HashMap;
+#[cfg(unix)]
+use std::ffi::{CString, OsStr, OsString};
+use std::fs::File;
+#[cfg(unix)]
+use std::fs::{self, OpenOptions};
+use std::io;
+#[cfg(unix)]
+use std::io::{Read, Write};
+#[cfg(unix)]
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+#[cfg(unix)]
+use std::os::unix::fs::{symlink, OpenOptionsExt, PermissionsExt};
+#[cfg(unix)]
+use std::os::unix::io::FromRawFd;
+#[cfg(unix)]
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use fs2::{lock_contended_error, FileExt};
+use libc::c_char;
+#[cfg(unix)]
+use libc::{c_void, pid_t, ENAMETOOLONG, ENOSYS, EPERM};
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    buffers::LispBufferRef,
+    fileio::encoded_file_path,
+    lisp::defsubr,
+    lisp::LispObject,
+    multibyte::LispStringRef,
+    remacs_sys::{encode_file_name, maybe_quit, report_file_error, Fexpand_file_name, Qnil},
+    threads::ThreadState,
+};
+
+#[cfg(unix)]
+use crate::{
+    buffers::get_truename_buffer,
+    fileio::file_exists_p,
+    lisp::LiveBufferIter,
+    obarray::intern,
+    remacs_sys::{get_boot_time, globals, make_string, Lisp_Buffer},
+    remacs_sys::{renameat_noreplace, Fverify_visited_file_modtime},
+    remacs_sys::{Fsystem_name, Fuser_login_name},
+};
+
+#[cfg(windows)]
+use crate::remacs_sys::file_lock_owner;
+
+/// An arbitrary limit on the length of the contents of a lock file.
+#[cfg(unix)]
+const MAX_LFINFO: usize = 8 * 1024;
+
+/// Some Linux kernels return EPERM on file systems that do not support
+/// hard or symbolic links.  There is no way to tell this from a
+/// permissions problem, but the lock file code works either way.
+#[cfg(unix)]
+const LINKS_MIGHT_NOT_WORK: i32 = EPERM;
+
+/// The owner of a lock, as it is recorded in the lock file.
+#[cfg(unix)]
+struct LockInfo {
+    user: Vec<u8>,
+    host: Vec<u8>,
+    pid: Vec<u8>,
+    boot_time: i64,
+}
+
+#[cfg(unix)]
+impl LockInfo {
+    /// Parse the contents of a lock file, USER@HOST.PID with an optional
+    /// :BOOT_TIME appended.  The user is everything before the last @.
+    fn parse(data: &[u8]) -> Option<Self> {
+        let at = data.iter().rposition(|&b| b == b'@')?;
+        let dot = at + data[at..].iter().rposition(|&b| b == b'.')?;
+
+        let after_dot = &data[dot + 1..];
+        let pid_len = after_dot.iter().take_while(|b| b.is_ascii_digit()).count();
+        if pid_len == 0 {
+            return None;
+        }
+
+        // The Linux CIFS client can mistakenly transliterate ':' to
+        // U+F022 in symlink contents (Bug#24656).
+        let boot = &after_dot[pid_len..];
+        let boot = if boot.is_empty() {
+            None
+        } else if boot.starts_with(b":") {
+            Some(&boot[1..])
+        } else if boot.starts_with(b"\xEF\x80\xA2") {
+            Some(&boot[3..])
+        } else {
+            return None;
+        };
+        let boot_time = match boot {
+            None => 0,
+            Some(digits) => {
+                if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+                    return None;
+                }
+                parse_digits(digits).unwrap_or(i64::max_value())
+            }
+        };
+
+        Some(LockInfo {
+            user: data[..at].to_vec(),
+            host: data[at + 1..dot].to_vec(),
+            pid: after_dot[..pid_len].to_vec(),
+            boot_time,
+        })
+    }
+
+    /// Return the process number of the owner, or -1 if it is too large.
+    fn pid(&self) -> i64 {
+        parse_digits(&self.pid).unwrap_or(-1)
+    }
+
+    /// Return USER@HOST (pid PID), as `ask-user-about-lock' shows it.
+    fn description(&self) -> Vec<u8> {
+        let mut description = self.user.clone();
+        description.push(b'@');
+        description.extend_from_slice(&self.host);
+        description.extend_from_slice(b" (pid ");
+        description.extend_from_slice(&self.pid);
+        description.push(b')');
+        description
+    }
+}
+
+#[cfg(unix)]
+fn parse_digits(digits: &[u8]) -> Option<i64> {
+    String::from_utf8_lossy(digits).parse().ok()
+}
+
+/// Who owns a lock file.
+#[cfg(unix)]
+enum LockOwner {
+    /// Nobody, or the lock was obsolete and has been removed.
+    Free,
+    /// Another process.
+    Other(LockInfo),
+    /// This Emacs.
+    Me,
+}
+
+/// Return the bytes of the string OBJECT, or nothing if it isn't a
+/// string.
+#[cfg(unix)]
+fn string_bytes(object: LispObject) -> Vec<u8> {
+    object
+        .as_string()
+        .map_or_else(Vec::new, |s| s.as_slice().to_vec())
+}
+
+/// Return the name of the lock file for the encoded absolute file name
+/// FILE: .#BASE in the directory of FILE.
+#[cfg(unix)]
+fn lock_file_name(file: LispObject) -> PathBuf {
+    let file = file.as_string_or_error();
+    let bytes = file.as_slice();
+    let base = bytes.iter().rposition(|&b| b == b'/').map_or(0, |i| i + 1);
+    let mut name = bytes[..base].to_vec();
+    name.extend_from_slice(b".#");
+    name.extend_from_slice(&bytes[base..]);
+    PathBuf::from(OsString::from_vec(name))
+}
+
+#[cfg(unix)]
+fn errno_error(code: i32) -> io::Error {
+    io::Error::from_raw_os_error(code)
+}
+
+/// Rename OLD to NEW.  If FORCE, replace any existing NEW.  It is OK if
+/// there are temporarily two hard links to OLD.
+#[cfg(unix)]
+fn rename_lock_file(old: &Path, new: &Path, force: bool) -> io::Result<()> {
+    if !force {
+        let old_c = CString::new(old.as_os_str().as_bytes())?;
+        let new_c = CString::new(new.as_os_str().as_bytes())?;
+        let r = unsafe {
+            renameat_noreplace(
+                libc::AT_FDCWD,
+                old_c.as_ptr(),
+                libc::AT_FDCWD,
+                new_c.as_ptr(),
+            )
+        };
+        if r == 0 {
+            return Ok(());
+        }
+        if errno().0 != ENOSYS {
+            return Err(io::Error::last_os_error());
+        }
+
+        match fs::hard_link(old, new) {
+            Ok(()) => {
+                return match fs::remove_file(old) {
+                    Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+                    result => result,
+                };
+            }
+            Err(err) => match err.raw_os_error() {
+                Some(ENOSYS) | Some(LINKS_MIGHT_NOT_WORK) => (),
+                _ => return Err(err),
+            },
+        }
+
+        // Links don't work on this file system, as on a FAT32 file
+        // system mounted on GNU/Linux.  Fall back on renaming, after
+        // checking that NEW doesn't exist.  Another process may create
+        // NEW in between, but that is the best that can be done.
+        match fs::symlink_metadata(new) {
+            Ok(_) => return Err(errno_error(libc::EEXIST)),
+            Err(ref err) if err.raw_os_error() == Some(libc::EOVERFLOW) => {
+                return Err(errno_error(libc::EEXIST))
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => (),
+            Err(err) => return Err(err),
+        }
+    }
+
+    fs::rename(old, new)
+}
+
+/// Create the lock file LFNAME as a regular file holding LOCK_INFO, for
+/// file systems where symbolic links don't work.
+#[cfg(unix)]
+fn create_lock_file_regular(lfname: &Path, lock_info: &[u8], force: bool) -> io::Result<()> {
+    let mut template = lfname
+        .parent()
+        .map_or_else(PathBuf::new, Path::to_path_buf)
+        .into_os_string()
+        .into_vec();
+    if !template.is_empty() {
+        template.push(b'/');
+    }
+    template.extend_from_slice(b".#-emacsXXXXXX");
+    let template = String::from_utf8_lossy(&template).into_owned();
+
+    let (fd, nonce) =
+        remacs_lib::make_temporary_file(template, libc::O_CLOEXEC).map_err(errno_error)?;
+    let nonce = PathBuf::from(nonce);
+    let mut file = unsafe { File::from_raw_fd(fd) };
+
+    // The contents of the lock file need not survive system crashes,
+    // so there is no need to sync it.
+    let written = file
+        .write_all(lock_info)
+        .and_then(|()| file.set_permissions(fs::Permissions::from_mode(0o444)));
+    drop(file);
+    let result = written.and_then(|()| rename_lock_file(&nonce, lfname, force));
+    if result.is_err() {
+        let _ = fs::remove_file(&nonce);
+    }
+    result
+}
+
+/// Create the lock file LFNAME holding LOCK_INFO.  If FORCE, remove any
+/// existing LFNAME if necessary.
+#[cfg(unix)]
+fn create_lock_file(lfname: &Path, lock_info: &[u8], force: bool) -> io::Result<()> {
+    let target = OsStr::from_bytes(lock_info);
+    let mut result = symlink(target, lfname);
+    if force {
+        if let Err(ref err) = result {
+            if err.kind() == io::ErrorKind::AlreadyExists {
+                let _ = fs::remove_file(lfname);
+                result = symlink(target, lfname);
+            }
+        }
+    }
+
+    match result {
+        Err(ref err)
+            if err.raw_os_error() == Some(ENOSYS)
+                || err.raw_os_error() == Some(LINKS_MIGHT_NOT_WORK)
+                || err.raw_os_error() == Some(ENAMETOOLONG) =>
+        {
+            create_lock_file_regular(lfname, lock_info, force)
+        }
+        result => result,
+    }
+}
+
+/// Lock the lock file named LFNAME.  If FORCE, do so even if it is
+/// already locked.
+#[cfg(unix)]
+fn lock_file_1(lfname: &Path, force: bool) -> io::Result<()> {
+    // Call this first because it can GC.
+    let boot = unsafe { get_boot_time() };
+
+    let mut lock_info = string_bytes(unsafe { Fuser_login_name(Qnil) });
+    lock_info.push(b'@');
+    lock_info.extend(string_bytes(unsafe { Fsystem_name() }));
+    lock_info.extend(format!(".{}", unsafe { libc::getpid() }).bytes());
+    if boot != 0 {
+        lock_info.extend(format!(":{}", boot).bytes());
+    }
+    if lock_info.len() > MAX_LFINFO {
+        return Err(errno_error(ENAMETOOLONG));
+    }
+
+    create_lock_file(lfname, &lock_info, force)
+}
+
+/// Read the contents of the lock file LFNAME, whether it is a symbolic
+/// link or a regular file.
+#[cfg(unix)]
+fn read_lock_data(lfname: &Path) -> io::Result<Vec<u8>> {
+    loop {
+        match fs::read_link(lfname) {
+            Ok(target) => return Ok(target.into_os_string().into_vec()),
+            Err(ref err) if err.raw_os_error() == Some(libc::EINVAL) => (),
+            Err(err) => return Err(err),
+        }
+
+        match OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(lfname)
+        {
+            Ok(file) => {
+                let mut data = Vec::new();
+                file.take(MAX_LFINFO as u64 + 1).read_to_end(&mut data)?;
+                return Ok(data);
+            }
+            Err(ref err) if err.raw_os_error() == Some(libc::ELOOP) => (),
+            Err(err) => return Err(err),
+        }
+
+        // read_link saw a regular file, but open saw a symbolic link, so
+        // the one must have been replaced by the other.  Try again.
+        unsafe { maybe_quit() };
+    }
+}
+
+/// Return true if times A and B are no more than one second apart.
+#[cfg(unix)]
+fn within_one_second(a: i64, b: i64) -> bool {
+    (a - b).abs() <= 1
+}
+
+/// Return who owns the lock file LFNAME.  A lock held on this host by a
+/// process which no longer exists is removed.  An error means that
+/// something is wrong with the locking mechanism.
+#[cfg(unix)]
+fn current_lock_owner(lfname: &Path) -> io::Result<LockOwner> {
+    let data = match read_lock_data(lfname) {
+        Ok(data) => data,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(LockOwner::Free),
+        Err(err) => return Err(err),
+    };
+    let invalid = || io::Error::from(io::ErrorKind::InvalidData);
+    if data.len() > MAX_LFINFO {
+        return Err(invalid());
+    }
+    let owner = LockInfo::parse(&data).ok_or_else(invalid)?;
+
+    // If we wanted to check for stale locks held on other hosts, this
+    // is where we would do it.
+    if owner.host != string_bytes(unsafe { Fsystem_name() }) {
+        return Ok(LockOwner::Other(owner));
+    }
+
+    let pid = owner.pid();
+    if pid == i64::from(unsafe { libc::getpid() }) {
+        return Ok(LockOwner::Me);
+    }
+    let alive = 0 < pid
+        && pid <= i64::from(pid_t::max_value())
+        && (unsafe { libc::kill(pid as pid_t, 0) } >= 0 || errno().0 == EPERM)
+        && (owner.boot_time == 0
+            || within_one_second(owner.boot_time, unsafe { get_boot_time() } as i64));
+    if alive {
+        Ok(LockOwner::Other(owner))
+    } else {
+        // The owner is dead or has a strange pid, so remove the lock.
+        fs::remove_file(lfname).map(|()| LockOwner::Free)
+    }
+}
+
+/// Lock the lock file LFNAME if it is free, and return nothing.  If
+/// another process owns the lock, return its owner instead.
+#[cfg(unix)]
+fn lock_if_free(lfname: &Path) -> io::Result<Option<LockInfo>> {
+    loop {
+        match lock_file_1(lfname, false) {
+            Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => (),
+            result => return result.map(|()| None),
+        }
+        match current_lock_owner(lfname)? {
+            LockOwner::Me => return Ok(None),
+            LockOwner::Other(owner) => return Ok(Some(owner)),
+            // A stale lock was removed; try again to lock the file.
+            LockOwner::Free => (),
+        }
+    }
+}
+
+/// Lock FILE, serving notice on the world that it is about to be
+/// edited.  This should only be done when about to modify an unmodified
+/// buffer visiting FILE.  If FILE is locked by someone else, this calls
+/// `ask-user-about-lock' with the file name and the owner of the lock;
+/// it can signal an error, or return t to take the lock, or nil to
+/// ignore it.
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn lock_file(file: LispObject) {
+    // Don't lock files while dumping Emacs; uncompressing wtmp files to
+    // find the boot time needs call-process, which does not work in an
+    // uninitialized Emacs.
+    if unsafe { globals.Vpurify_flag }.is_not_nil() {
+        return;
+    }
+
+    let expanded = unsafe { Fexpand_file_name(file, Qnil) };
+
+    // See if this file is visited and has changed on disk since it was
+    // visited.
+    let subject_buf = get_truename_buffer(file);
+    if subject_buf.is_not_nil()
+        && unsafe { Fverify_visited_file_modtime(subject_buf) }.is_nil()
+        && file_exists_p(expanded.as_string_or_error())
+    {
+        call!(
+            intern("userlock--ask-user-about-supersession-threat").into(),
+            expanded
+        );
+    }
+
+    // Don't lock files if the user has opted out.
+    if !unsafe { globals.create_lockfiles } {
+        return;
+    }
+
+    let lfname = lock_file_name(unsafe { encode_file_name(expanded) });
+    if let Ok(Some(owner)) = lock_if_free(&lfname) {
+        // Someone else has the lock.  Consider breaking it.
+        let description = owner.description();
+        let owner = unsafe {
+            make_string(
+                description.as_ptr() as *const c_char,
+                description.len() as isize,
+            )
+        };
+        let attack = call!(intern("ask-user-about-lock").into(), expanded, owner);
+        if attack.is_not_nil() {
+            let _ = lock_file_1(&lfname, true);
+        }
+    }
+}
+
+/// Remove the lock on FILE, if this Emacs holds it.
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn unlock_file(file: LispObject) {
+    let expanded = unsafe { Fexpand_file_name(file, Qnil) };
+    let lfname = lock_file_name(unsafe { encode_file_name(expanded) });
+    if let Ok(LockOwner::Me) = current_lock_owner(&lfname) {
+        let _ = fs::remove_file(&lfname);
+    }
+}
+
+/// Remove the locks on the files visited by modified buffers.
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn unlock_all_files() {
+    for buffer in LiveBufferIter::new() {
+        unlock_buffer_1(buffer);
+    }
+}
+
+fn unlock_buffer_1(buffer: LispBufferRef) {
+    if buffer.modifications_since_save() < buffer.modifications() && buffer.truename().is_string() {
+        unlock_file(buffer.truename());
+    }
+}
+
+/// Unlock the file visited in BUFFER.
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn unlock_buffer(buffer: *mut Lisp_Buffer) {
+    unlock_buffer_1(LispBufferRef::from_ptr(buffer as *mut c_void).unwrap());
+}
+
+#[cfg(windows)]
+fn lock_file(file: LispObject) {
+    unsafe { crate::remacs_sys::lock_file(file) }
+}
+
+#[cfg(windows)]
+pub fn unlock_file(file: LispObject) {
+    unsafe { crate::remacs_sys::unlock_file(file) }
+}
+
+/// Lock FILE, if current buffer is modified.
+/// FILE defaults to current buffer's visited file,
+/// or else nothing is done if current buffer isn't visiting a file.
+///
+/// If the option `create-lockfiles' is nil, this does nothing.
+#[lisp_fn(min = "0")]
+pub fn lock_buffer(file: LispObject) {
+    let buffer = ThreadState::current_buffer();
+    let file = if file.is_nil() {
+        buffer.truename()
+    } else {
+        file.as_string_or_error().into()
+    };
+    if buffer.modifications_since_save() < buffer.modifications() && file.is_not_nil() {
+        lock_file(file);
+    }
+}
+
+/// Unlock the file visited in the current buffer.
+/// If the buffer is not modified, this does nothing because the file
+/// should not be locked in that case.
+#[lisp_fn(name = "unlock-buffer", c_name = "unlock_buffer")]
+pub fn unlock_buffer_lisp() {
+    unlock_buffer_1(ThreadState::current_buffer());
+}
+
+/// Return a value indicating whether FILENAME is locked.
+/// The value is nil if the FILENAME is not locked,
+/// t if it is locked by you, else a string saying which user has locked it.
+#[lisp_fn]
+pub fn file_locked_p(filename: LispObject) -> LispObject {
+    let expanded = unsafe { Fexpand_file_name(filename, Qnil) };
+    #[cfg(unix)]
+    {
+        let lfname = lock_file_name(unsafe { encode_file_name(expanded) });
+        match current_lock_owner(&lfname) {
+            Ok(LockOwner::Me) => LispObject::from(true),
+            Ok(LockOwner::Other(owner)) => unsafe {
+                make_string(
+                    owner.user.as_ptr() as *const c_char,
+                    owner.user.len() as isize,
+                )
+            },
+            Ok(LockOwner::Free) | Err(_) => Qnil,
+        }
+    }
+    #[cfg(windows)]
+    {
+        unsafe { file_lock_owner(expanded) }
+    }
+}
+
+/// How long `file-flock' waits between attempts to take a lock, in
+/// milliseconds.
+const FLOCK_RETRY_MS: u64 = 50;
+
+lazy_static! {
+    /// The files locked with `file-flock', by their encoded names.  The
+    /// lock on a file lasts as long as it is open.
+    static ref FLOCKS: Mutex<HashMap<PathBuf, File>> = Mutex::new(HashMap::new());
+}
+
+/// Return the encoded absolute name of FILE.
+fn flock_path(file: LispStringRef) -> PathBuf {
+    let expanded = unsafe { Fexpand_file_name(file.into(), Qnil) };
+    encoded_file_path(unsafe { encode_file_name(expanded) })
+}
+
+/// Take a lock on FILE, unless another process holds a conflicting
+/// lock, and return whether it was taken.
+fn try_flock(file: &File, shared: bool) -> io::Result<bool> {
+    let result = if shared {
+        file.try_lock_shared()
+    } else {
+        file.try_lock_exclusive()
+    };
+    match result {
+        Ok(()) => Ok(true),
+        Err(ref err) if err.raw_os_error() == lock_contended_error().raw_os_error() => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+fn report_flock_error(err: &io::Error, file: LispStringRef) -> ! {
+    set_errno(Errno(err.raw_os_error().unwrap_or(0)));
+    unsafe { report_file_error("Locking file\0".as_ptr() as *const c_char, file.into()) }
+}
+
+/// Take an advisory lock on FILE, as the operating system provides it.
+/// The lock is exclusive, unless SHARED is non-nil, in which case other
+/// processes may hold shared locks on FILE at the same time.  If another
+/// process holds a conflicting lock, wait until it is released, or
+/// return nil at once if NOWAIT is non-nil.  Return t once the lock is
+/// taken.  Taking a lock on a file that is already locked by this Emacs
+/// changes the lock to the kind SHARED asks for.
+///
+/// These are the locks of flock(2), which other programs take to
+/// coordinate access to a file; they are unrelated to the lock files
+/// of `lock-buffer'.  The lock is held until `file-funlock' is called
+/// on FILE, or Emacs exits.  File name handlers are not called; FILE
+/// must be a local file, and must exist.
+#[lisp_fn(min = "1")]
+pub fn file_flock(file: LispStringRef, shared: bool, nowait: bool) -> bool {
+    let path = flock_path(file);
+    loop {
+        // The table is not kept locked across a quit or an error, which
+        // leave this function without unwinding it.
+        let result = {
+            let mut flocks = FLOCKS.lock().unwrap();
+            if flocks.contains_key(&path) {
+                try_flock(&flocks[&path], shared)
+            } else {
+                File::open(&path).and_then(|opened| {
+                    let locked = try_flock(&opened, shared)?;
+                    if locked {
+                        flocks.insert(path.clone(), opened);
+                    }
+                    Ok(locked)
+                })
+            }
+        };
+        match result {
+            Ok(true) => return true,
+            Ok(false) if nowait => return false,
+            Ok(false) => (),
+            Err(err) => report_flock_error(&err, file),
+        }
+
+        // Wait in steps, so that the wait can be quit.
+        unsafe { maybe_quit() };
+        thread::sleep(Duration::from_millis(FLOCK_RETRY_MS));
+    }
+}
+
+/// Release the advisory lock on FILE taken by `file-flock'.
+/// Return t if FILE was locked by this Emacs, and nil otherwise.
+#[lisp_fn]
+pub fn file_funlock(file: LispStringRef) -> bool {
+    match FLOCKS.lock().unwrap().remove(&flock_path(file)) {
+        Some(held) => {
+            let _ = held.unlock();
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_lock_info_parse() {
+    let owner = LockInfo::parse(b"jrh@some.host.1234:1500000000").unwrap();
+    assert_eq!(owner.user, b"jrh");
+    assert_eq!(owner.host, b"some.host");
+    assert_eq!(owner.pid(), 1234);
+    assert_eq!(owner.boot_time, 1_500_000_000);
+    assert_eq!(owner.description(), b"jrh@some.host (pid 1234)".to_vec());
+
+    let owner = LockInfo::parse(b"a@b@c.42\xEF\x80\xA27").unwrap();
+    assert_eq!((owner.user, owner.boot_time), (b"a@b".to_vec(), 7));
+    assert_eq!(LockInfo::parse(b"user@host.42").unwrap().boot_time, 0);
+
+    assert!(LockInfo::parse(b"user@host").is_none());
+    assert!(LockInfo::parse(b"user@host.pid").is_none());
+    assert!(LockInfo::parse(b"user@host.42:").is_none());
+    assert!(LockInfo::parse(b"user@host.42x").is_none());
+}
+
+include!(concat!(env!("OUT_DIR"), "/filelock_exports.rs"));
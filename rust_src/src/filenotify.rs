@@ -0,0 +1,423 @@
+//! File notification over the notify crate.
+//!
+//! This backend works the same way on every system notify supports,
+//! whether it uses inotify, FSEvents or kqueue underneath.  Each watch
+//! has a watcher of its own, which coalesces the events for a file over
+//! a short delay and sends them to a thread.  The thread queues them and
+//! writes a byte to a pipe, which Emacs reads in its event loop like the
+//! descriptors of the other backends, turning the queued events into
+//! `file-notify' events.
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::{assq, delq},
+    multibyte::LispStringRef,
+    remacs_sys::{EmacsInt, Qnil},
+};
+
+#[cfg(feature = "rustnotify")]
+use std::collections::HashMap;
+#[cfg(feature = "rustnotify")]
+use std::ffi::OsStr;
+#[cfg(feature = "rustnotify")]
+use std::mem;
+#[cfg(feature = "rustnotify")]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(feature = "rustnotify")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "rustnotify")]
+use std::ptr;
+#[cfg(feature = "rustnotify")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "rustnotify")]
+use std::sync::mpsc::channel;
+#[cfg(feature = "rustnotify")]
+use std::sync::Mutex;
+#[cfg(feature = "rustnotify")]
+use std::thread;
+#[cfg(feature = "rustnotify")]
+use std::time::Duration;
+
+#[cfg(feature = "rustnotify")]
+use libc::{c_char, c_int, c_void, ptrdiff_t};
+#[cfg(feature = "rustnotify")]
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+
+#[cfg(not(feature = "rustnotify"))]
+use crate::remacs_sys::Qfile_notify_error;
+#[cfg(feature = "rustnotify")]
+use crate::{
+    fns::provide,
+    lists::{LispConsCircularChecks, LispConsEndChecks},
+    remacs_sys::Fexpand_file_name,
+    remacs_sys::{add_read_fd, decode_file_name, encode_file_name, event_kind, input_event},
+    remacs_sys::{kbd_buffer_store_event, make_unibyte_string, report_file_notify_error},
+    remacs_sys::{Qattribute_change, Qattribute_changed, Qchange, Qchanged, Qcreated},
+    remacs_sys::{Qdeleted, Qmoved, Qrecursive, Qrustnotify},
+    threads::ThreadState,
+};
+
+// An alist of (DESCRIPTOR . CALLBACK) for the watches, which keeps the
+// callbacks alive for as long as their watches are.
+declare_GC_protected_static!(watch_callbacks, Qnil);
+
+/// How long events for a file are coalesced before they are reported,
+/// in milliseconds.
+#[cfg(feature = "rustnotify")]
+const NOTIFY_DELAY_MS: u64 = 100;
+
+/// The action of an event, as it is reported to `file-notify-callback'.
+#[cfg(feature = "rustnotify")]
+#[derive(Clone, Copy, PartialEq)]
+enum Action {
+    Created,
+    Deleted,
+    Changed,
+    AttributeChanged,
+    Moved,
+}
+
+#[cfg(feature = "rustnotify")]
+impl Action {
+    fn to_lisp(self) -> LispObject {
+        match self {
+            Action::Created => Qcreated,
+            Action::Deleted => Qdeleted,
+            Action::Changed => Qchanged,
+            Action::AttributeChanged => Qattribute_changed,
+            Action::Moved => Qmoved,
+        }
+    }
+}
+
+/// An event for the watch DESCRIPTOR, which has not been reported yet.
+/// FILE1 is where FILE was moved to.
+#[cfg(feature = "rustnotify")]
+struct Notification {
+    descriptor: EmacsInt,
+    action: Action,
+    file: PathBuf,
+    file1: Option<PathBuf>,
+}
+
+/// What a watch reports.
+#[cfg(feature = "rustnotify")]
+#[derive(Clone, Copy)]
+struct WatchFlags {
+    change: bool,
+    attribute_change: bool,
+}
+
+#[cfg(feature = "rustnotify")]
+impl WatchFlags {
+    /// Return the notification EVENT makes for the watch DESCRIPTOR, if
+    /// the watch reports it.
+    fn notification(self, descriptor: EmacsInt, event: DebouncedEvent) -> Option<Notification> {
+        let (action, file, file1) = match event {
+            DebouncedEvent::Create(file) => (Action::Created, file, None),
+            DebouncedEvent::Write(file) => (Action::Changed, file, None),
+            DebouncedEvent::Remove(file) => (Action::Deleted, file, None),
+            DebouncedEvent::Rename(file, file1) => (Action::Moved, file, Some(file1)),
+            DebouncedEvent::Chmod(file) => (Action::AttributeChanged, file, None),
+            // Events before coalescing, rescans and errors aren't
+            // reported.
+            _ => return None,
+        };
+        let reported = if action == Action::AttributeChanged {
+            self.attribute_change
+        } else {
+            self.change
+        };
+        if !reported {
+            return None;
+        }
+        Some(Notification {
+            descriptor,
+            action,
+            file,
+            file1,
+        })
+    }
+}
+
+/// The state shared with the threads which receive events.
+#[cfg(feature = "rustnotify")]
+struct Notifier {
+    /// The watchers, by descriptor.  Dropping a watcher stops it, and
+    /// ends the thread which receives its events.
+    watchers: HashMap<EmacsInt, RecommendedWatcher>,
+    /// The events which haven't been reported yet.
+    pending: Vec<Notification>,
+    /// The pipe written to when there are pending events, once it has
+    /// been made.
+    pipe: Option<(c_int, c_int)>,
+}
+
+#[cfg(feature = "rustnotify")]
+lazy_static! {
+    static ref NOTIFIER: Mutex<Notifier> = Mutex::new(Notifier {
+        watchers: HashMap::new(),
+        pending: Vec::new(),
+        pipe: None,
+    });
+}
+
+/// The descriptor of the next watch.
+#[cfg(feature = "rustnotify")]
+static NEXT_DESCRIPTOR: AtomicUsize = AtomicUsize::new(1);
+
+/// Signal a `file-notify-error' with MESSAGE, which must end in a NUL,
+/// and the error in errno, about FILE.
+#[cfg(feature = "rustnotify")]
+fn notify_error(message: &str, file: LispObject) -> ! {
+    unsafe { report_file_notify_error(message.as_ptr() as *const c_char, file) }
+}
+
+/// Make the pipe which wakes Emacs up when there are pending events,
+/// and start reading it.  Both ends are non-blocking: a full pipe
+/// already means that Emacs will look at the events.
+#[cfg(feature = "rustnotify")]
+fn make_wakeup_pipe(filename: LispObject) -> (c_int, c_int) {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        notify_error("Could not create file notification pipe\0", filename);
+    }
+    for &fd in &fds {
+        unsafe {
+            libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK);
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+    }
+    unsafe { add_read_fd(fds[0], Some(rustnotify_callback), ptr::null_mut()) };
+    (fds[0], fds[1])
+}
+
+/// Return the file name PATH, as the file system has it, as a Lisp
+/// string.
+#[cfg(feature = "rustnotify")]
+fn lisp_file_name(path: &Path) -> LispObject {
+    let bytes = path.as_os_str().as_bytes();
+    unsafe {
+        decode_file_name(make_unibyte_string(
+            bytes.as_ptr() as *const c_char,
+            bytes.len() as ptrdiff_t,
+        ))
+    }
+}
+
+/// Turn the pending events into `file-notify' events.  This is called
+/// when the pipe written to by the threads receiving events is readable.
+#[cfg(feature = "rustnotify")]
+extern "C" fn rustnotify_callback(fd: c_int, _data: *mut c_void) {
+    let mut buffer = [0u8; 64];
+    while unsafe { libc::read(fd, buffer.as_mut_ptr() as *mut c_void, buffer.len()) } > 0 {}
+
+    let pending = mem::replace(&mut NOTIFIER.lock().unwrap().pending, Vec::new());
+    for notification in pending {
+        let descriptor = LispObject::from(notification.descriptor);
+        // The watch may have been removed since.
+        let callback = match assq(descriptor, unsafe { watch_callbacks }).as_cons() {
+            Some(watch) => watch.cdr(),
+            None => continue,
+        };
+        let file = lisp_file_name(&notification.file);
+        let data = match notification.file1 {
+            Some(file1) => list!(
+                descriptor,
+                notification.action.to_lisp(),
+                file,
+                lisp_file_name(&file1)
+            ),
+            None => list!(descriptor, notification.action.to_lisp(), file),
+        };
+
+        let mut event: input_event = unsafe { mem::zeroed() };
+        event.set_kind(event_kind::FILE_NOTIFY_EVENT);
+        event.arg = list!(data, callback);
+        unsafe { kbd_buffer_store_event(&mut event) };
+    }
+}
+
+#[cfg(feature = "rustnotify")]
+fn add_watch(filename: LispStringRef, flags: LispObject, callback: LispObject) -> LispObject {
+    let mut watch_flags = WatchFlags {
+        change: false,
+        attribute_change: false,
+    };
+    let mut mode = RecursiveMode::NonRecursive;
+    for flag in flags.iter_cars(LispConsEndChecks::on, LispConsCircularChecks::on) {
+        if flag == Qchange {
+            watch_flags.change = true;
+        } else if flag == Qattribute_change {
+            watch_flags.attribute_change = true;
+        } else if flag == Qrecursive {
+            mode = RecursiveMode::Recursive;
+        }
+    }
+
+    let file =
+        unsafe { Fexpand_file_name(filename.into(), ThreadState::current_buffer().directory_) };
+    let encoded = unsafe { encode_file_name(file) };
+    let path = Path::new(OsStr::from_bytes(encoded.as_string_or_error().as_slice()));
+
+    let descriptor = NEXT_DESCRIPTOR.fetch_add(1, Ordering::Relaxed) as EmacsInt;
+    let (sender, receiver) = channel();
+    let mut watcher: RecommendedWatcher =
+        match notify::watcher(sender, Duration::from_millis(NOTIFY_DELAY_MS)) {
+            Ok(watcher) => watcher,
+            Err(_) => notify_error("Could not create file notification watcher\0", file),
+        };
+    if watcher.watch(path, mode).is_err() {
+        notify_error("Could not add watch for file\0", file);
+    }
+
+    let mut notifier = NOTIFIER.lock().unwrap();
+    let (_, write_fd) = match notifier.pipe {
+        Some(pipe) => pipe,
+        None => {
+            let pipe = make_wakeup_pipe(file);
+            notifier.pipe = Some(pipe);
+            pipe
+        }
+    };
+    notifier.watchers.insert(descriptor, watcher);
+    drop(notifier);
+
+    thread::spawn(move || {
+        for event in receiver {
+            if let Some(notification) = watch_flags.notification(descriptor, event) {
+                NOTIFIER.lock().unwrap().pending.push(notification);
+                unsafe { libc::write(write_fd, b"\0".as_ptr() as *const c_void, 1) };
+            }
+        }
+    });
+
+    let descriptor = LispObject::from(descriptor);
+    unsafe {
+        watch_callbacks = LispObject::cons(LispObject::cons(descriptor, callback), watch_callbacks);
+    }
+    descriptor
+}
+
+#[cfg(not(feature = "rustnotify"))]
+fn add_watch(filename: LispStringRef, _flags: LispObject, _callback: LispObject) -> LispObject {
+    xsignal!(
+        Qfile_notify_error,
+        LispObject::from("File notification through Rust is not available"),
+        LispObject::from(filename)
+    );
+}
+
+#[cfg(feature = "rustnotify")]
+fn watch_exists(descriptor: EmacsInt) -> bool {
+    NOTIFIER.lock().unwrap().watchers.contains_key(&descriptor)
+}
+
+#[cfg(not(feature = "rustnotify"))]
+fn watch_exists(_descriptor: EmacsInt) -> bool {
+    false
+}
+
+#[cfg(feature = "rustnotify")]
+fn remove_watcher(descriptor: EmacsInt) {
+    NOTIFIER.lock().unwrap().watchers.remove(&descriptor);
+}
+
+#[cfg(not(feature = "rustnotify"))]
+fn remove_watcher(_descriptor: EmacsInt) {}
+
+/// Add a watch for filesystem events pertaining to FILE-NAME.
+/// This arranges for filesystem events pertaining to FILE-NAME to be
+/// reported to Emacs.  Use `rustnotify-rm-watch' to cancel the watch.
+///
+/// Value is a descriptor for the added watch.  If the file cannot be
+/// watched for some reason, this function signals a `file-notify-error'.
+///
+/// FLAGS is a list of conditions to set what will be watched for.  It can
+/// include the following symbols:
+///
+///   `change'           -- watch for file changes, including the creation,
+///                         deletion and renaming of files in a directory
+///   `attribute-change' -- watch for file attributes changes, like
+///                         permissions or modification time
+///   `recursive'        -- also watch the subdirectories of a directory,
+///                         however deep
+///
+/// Events for a file are coalesced over a tenth of a second, so that
+/// writing a file in many pieces is reported once.  When any event
+/// happens, Emacs will call the CALLBACK function passing it a single
+/// argument EVENT, which is of the form
+///
+///   (DESCRIPTOR ACTION FILE [FILE1])
+///
+/// DESCRIPTOR is the same object as the one returned by this function.
+/// ACTION is `created', `deleted', `changed', `attribute-changed' or
+/// `moved', in which case FILE was moved to FILE1.  FILE and FILE1 are
+/// absolute file names.
+///
+/// This works the same way on every system, whatever facility it uses.
+#[lisp_fn]
+pub fn rustnotify_add_watch(
+    file_name: LispStringRef,
+    flags: LispObject,
+    callback: LispObject,
+) -> LispObject {
+    add_watch(file_name, flags, callback)
+}
+
+/// Remove an existing WATCH-DESCRIPTOR.
+/// WATCH-DESCRIPTOR should be an object returned by
+/// `rustnotify-add-watch'.
+#[lisp_fn]
+pub fn rustnotify_rm_watch(watch_descriptor: EmacsInt) {
+    if !watch_exists(watch_descriptor) {
+        xsignal!(
+            Qfile_notify_error,
+            LispObject::from("Invalid descriptor "),
+            LispObject::from(watch_descriptor)
+        );
+    }
+    remove_watcher(watch_descriptor);
+    unsafe {
+        let watch = assq(LispObject::from(watch_descriptor), watch_callbacks);
+        watch_callbacks = delq(watch, watch_callbacks);
+    }
+}
+
+/// Check a watch specified by its WATCH-DESCRIPTOR.
+/// WATCH-DESCRIPTOR should be an object returned by
+/// `rustnotify-add-watch'.
+///
+/// A watch can become invalid if the file or directory it watches is
+/// deleted, or if the watcher thread exits abnormally for any other
+/// reason.  Removing the watch by calling `rustnotify-rm-watch' also
+/// makes it invalid.
+#[lisp_fn]
+pub fn rustnotify_valid_p(watch_descriptor: LispObject) -> bool {
+    watch_descriptor
+        .as_fixnum()
+        .map_or(false, |descriptor| watch_exists(descriptor))
+}
+
+#[no_mangle]
+pub extern "C" fn syms_of_filenotify() {
+    #[cfg(feature = "rustnotify")]
+    {
+        def_lisp_sym!(Qrustnotify, "rustnotify");
+        def_lisp_sym!(Qrecursive, "recursive");
+        def_lisp_sym!(Qchange, "change");
+        def_lisp_sym!(Qattribute_change, "attribute-change");
+        def_lisp_sym!(Qcreated, "created");
+        def_lisp_sym!(Qdeleted, "deleted");
+        def_lisp_sym!(Qchanged, "changed");
+        def_lisp_sym!(Qattribute_changed, "attribute-changed");
+        def_lisp_sym!(Qmoved, "moved");
+
+        provide(Qrustnotify.into(), Qnil);
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/filenotify_exports.rs"));
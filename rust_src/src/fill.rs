@@ -0,0 +1,583 @@
+//! Filling paragraphs.
+//!
+//! `fill-region-natively' fills paragraphs of plain text as
+//! `fill-region' does, but finds the places where lines may be broken
+//! with the Unicode line breaking algorithm (UAX #14) of the
+//! `xi-unicode' crate.  So text in scripts which don't put
+//! spaces between words, such as Chinese and Japanese, is broken
+//! between the characters where the rules of the script allow it.
+//!
+//! The buffer is only changed where the whitespace between words
+//! changes, so the text properties of the words, and the markers in
+//! them, are kept.
+
+use xi_unicode::LineBreakIterator;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    buffers::validate_region,
+    editfns::{
+        bolp, buffer_substring_no_properties, delete_region, goto_char, line_beginning_position,
+        line_end_position, save_excursion_save,
+    },
+    eval::unbind_to,
+    lisp::defsubr,
+    lisp::LispObject,
+    multibyte::LispStringRef,
+    remacs_sys::{record_unwind_protect, save_excursion_restore, EmacsInt, Fchar_width, Finsert},
+    remacs_sys::{Qcenter, Qdefault_justification, Qfill_nospace_between_words_table},
+    remacs_sys::{Qfill_prefix, Qfull, Qnil, Qright, Qsentence_end_double_space, Qt},
+    symbols::{boundp, symbol_value},
+    threads::{c_specpdl_index, ThreadState},
+};
+
+/// How the lines of a paragraph are justified.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Justification {
+    Left,
+    Full,
+    Right,
+    Center,
+}
+
+impl Justification {
+    /// Return the justification named by JUSTIFY, as for `fill-region'.
+    fn from_symbol(justify: LispObject) -> Self {
+        let justify = if justify.eq(Qt) {
+            symbol_value(Qdefault_justification.into())
+        } else {
+            justify
+        };
+        if justify.eq(Qfull) {
+            Justification::Full
+        } else if justify.eq(Qright) {
+            Justification::Right
+        } else if justify.eq(Qcenter) {
+            Justification::Center
+        } else {
+            Justification::Left
+        }
+    }
+}
+
+/// How to fill a paragraph.
+struct FillSettings {
+    /// The text at the beginning of each line.
+    prefix: String,
+    /// The column the lines end before, as `fill-column'.
+    fill_column: usize,
+    justification: Justification,
+    /// Whether a sentence ends with two spaces, as
+    /// `sentence-end-double-space'.
+    sentence_end_double_space: bool,
+}
+
+/// A change to a paragraph: the characters from START to END are
+/// replaced with REPLACEMENT.
+#[derive(Debug, PartialEq)]
+struct Edit {
+    start: usize,
+    end: usize,
+    replacement: String,
+}
+
+/// A place where a line may be broken in the paragraph joined into one
+/// line, with the position where the line before it ends and the
+/// position where the next line starts.
+#[derive(Clone, Copy)]
+struct Opportunity {
+    brk: Break,
+    line_end: usize,
+    next_start: usize,
+}
+
+/// Where a line is broken.
+#[derive(Clone, Copy, PartialEq)]
+enum Break {
+    /// The whitespace after the word with this index.
+    Gap(usize),
+    /// Inside the word with this index, at this byte offset.
+    Inside(usize, usize),
+}
+
+/// Return true if WORD ends a sentence, maybe followed by closing
+/// punctuation.
+fn ends_sentence(word: &str) -> bool {
+    word.trim_end_matches(|c| ")]}\"'\u{2019}\u{201D}".contains(c))
+        .ends_with(|c| ".?!".contains(c))
+}
+
+/// Return the changes that fill PARAGRAPH, the text of the lines of a
+/// paragraph separated by newlines, as the ranges of characters to
+/// replace in order.  CHAR_WIDTH gives the width of a character in
+/// columns, and NOSPACE tells whether a character is written without
+/// spaces between words, so that a line ending or starting with it is
+/// joined to the next one without a space.
+fn fill_paragraph<W, N>(
+    paragraph: &str,
+    settings: &FillSettings,
+    char_width: W,
+    nospace: N,
+) -> Vec<Edit>
+where
+    W: Fn(char) -> usize,
+    N: Fn(char) -> bool,
+{
+    let prefix = settings.prefix.as_str();
+    let sentence_end_double_space = settings.sentence_end_double_space;
+
+    // The words are the runs of characters between whitespace, after
+    // the fill prefix of each line.
+    let mut words = Vec::new();
+    let mut line_start = 0;
+    for line in paragraph.split('\n') {
+        let content = if !prefix.is_empty() && line.starts_with(prefix) {
+            line_start + prefix.len()
+        } else {
+            line_start
+        };
+        let mut word_start = None;
+        for (i, c) in paragraph[content..line_start + line.len()].char_indices() {
+            if c == ' ' || c == '\t' {
+                if let Some(start) = word_start.take() {
+                    words.push(start..content + i);
+                }
+            } else if word_start.is_none() {
+                word_start = Some(content + i);
+            }
+        }
+        if let Some(start) = word_start {
+            words.push(start..line_start + line.len());
+        }
+        line_start += line.len() + 1;
+    }
+    if words.is_empty() {
+        return Vec::new();
+    }
+    let word = |i: usize| &paragraph[words[i].clone()];
+
+    // The whitespace between the words when they are on the same line.
+    let separators: Vec<&str> = (1..words.len())
+        .map(|i| {
+            let before = word(i - 1);
+            let after = word(i);
+            let gap = &paragraph[words[i - 1].end..words[i].start];
+            let sentence_end = sentence_end_double_space && ends_sentence(before);
+            if gap.contains('\n') {
+                let last = before.chars().next_back().unwrap();
+                let first = after.chars().next().unwrap();
+                if nospace(last) || nospace(first) {
+                    ""
+                } else if sentence_end {
+                    "  "
+                } else {
+                    " "
+                }
+            } else if sentence_end && gap.chars().count() >= 2 {
+                "  "
+            } else {
+                " "
+            }
+        })
+        .collect();
+
+    // Join the paragraph into one line, and find where it may be broken.
+    let mut joined = String::with_capacity(paragraph.len());
+    let mut starts = Vec::with_capacity(words.len());
+    for i in 0..words.len() {
+        if i > 0 {
+            joined.push_str(separators[i - 1]);
+        }
+        starts.push(joined.len());
+        joined.push_str(word(i));
+    }
+    let word_end = |i: usize| starts[i] + words[i].len();
+
+    let opportunities: Vec<Opportunity> = LineBreakIterator::new(&joined)
+        .filter_map(|(pos, _)| {
+            let brk = match starts.binary_search(&pos) {
+                Ok(0) => return None,
+                Ok(i) => Break::Gap(i - 1),
+                Err(i) if pos < word_end(i - 1) => Break::Inside(i - 1, pos - starts[i - 1]),
+                Err(_) => return None,
+            };
+            match brk {
+                // A period followed by a single space doesn't end a
+                // sentence, so don't break the line there.
+                Break::Gap(i)
+                    if sentence_end_double_space
+                        && separators[i] == " "
+                        && ends_sentence(word(i)) =>
+                {
+                    None
+                }
+                Break::Gap(i) => Some(Opportunity {
+                    brk,
+                    line_end: word_end(i),
+                    next_start: starts[i + 1],
+                }),
+                Break::Inside(..) => Some(Opportunity {
+                    brk,
+                    line_end: pos,
+                    next_start: pos,
+                }),
+            }
+        })
+        .collect();
+
+    // The column of each character of the joined line.
+    let mut columns = vec![0; joined.len() + 1];
+    let mut column = 0;
+    for (i, c) in joined.char_indices() {
+        columns[i] = column;
+        column += char_width(c);
+    }
+    columns[joined.len()] = column;
+    let width = |start: usize, end: usize| columns[end] - columns[start];
+
+    let prefix_width: usize = prefix.chars().map(&char_width).sum();
+    let available = settings.fill_column.saturating_sub(prefix_width).max(1);
+
+    // Break each line at the last place where it fits, or at the first
+    // place after a word too long to fit.
+    let mut breaks: Vec<usize> = Vec::new();
+    let mut line_start = 0;
+    let mut candidate = None;
+    for (i, opportunity) in opportunities.iter().enumerate() {
+        if width(line_start, opportunity.line_end) <= available {
+            candidate = Some(i);
+            continue;
+        }
+        if let Some(c) = candidate.take() {
+            breaks.push(c);
+            line_start = opportunities[c].next_start;
+            if width(line_start, opportunity.line_end) <= available {
+                candidate = Some(i);
+                continue;
+            }
+        }
+        breaks.push(i);
+        line_start = opportunity.next_start;
+    }
+    if let Some(c) = candidate {
+        if width(line_start, joined.len()) > available {
+            breaks.push(c);
+        }
+    }
+
+    // The lines, as ranges of the joined line.
+    let mut lines = Vec::with_capacity(breaks.len() + 1);
+    let mut line_start = 0;
+    for &b in &breaks {
+        lines.push((line_start, opportunities[b].line_end));
+        line_start = opportunities[b].next_start;
+    }
+    lines.push((line_start, joined.len()));
+    let line_starts: Vec<usize> = lines.iter().map(|&(start, _)| start).collect();
+    let line_of = |pos: usize| match line_starts.binary_search(&pos) {
+        Ok(i) => i,
+        Err(i) => i - 1,
+    };
+
+    let padding = |line: usize| {
+        let (start, end) = lines[line];
+        let room = available.saturating_sub(width(start, end));
+        match settings.justification {
+            Justification::Right => room,
+            Justification::Center => room / 2,
+            _ => 0,
+        }
+    };
+    let line_break = |line: usize| format!("\n{}{}", prefix, " ".repeat(padding(line)));
+
+    // Full justification spreads the room left on each line but the
+    // last over the spaces between its words, the leftmost first.
+    let mut extra_spaces = vec![0; separators.len()];
+    if settings.justification == Justification::Full {
+        let mut gaps_by_line = vec![Vec::new(); lines.len()];
+        for (i, separator) in separators.iter().enumerate() {
+            let line = line_of(starts[i + 1]);
+            if !separator.is_empty() && starts[i + 1] != line_starts[line] {
+                gaps_by_line[line].push(i);
+            }
+        }
+        for (line, gaps) in gaps_by_line.iter().enumerate().take(lines.len() - 1) {
+            if gaps.is_empty() {
+                continue;
+            }
+            let (start, end) = lines[line];
+            let room = available.saturating_sub(width(start, end));
+            for (n, &gap) in gaps.iter().enumerate() {
+                extra_spaces[gap] = room / gaps.len() + (n < room % gaps.len()) as usize;
+            }
+        }
+    }
+
+    let mut edits = Vec::new();
+    edits.push((
+        0..words[0].start,
+        format!("{}{}", prefix, " ".repeat(padding(0))),
+    ));
+    let mut broken = breaks
+        .iter()
+        .map(|&b| opportunities[b].brk)
+        .enumerate()
+        .peekable();
+    for i in 0..words.len() {
+        while let Some(&(line, Break::Inside(w, offset))) = broken.peek() {
+            if w != i {
+                break;
+            }
+            let pos = words[i].start + offset;
+            edits.push((pos..pos, line_break(line + 1)));
+            broken.next();
+        }
+        if i + 1 == words.len() {
+            break;
+        }
+        let gap = words[i].end..words[i + 1].start;
+        match broken.peek() {
+            Some(&(line, Break::Gap(w))) if w == i => {
+                edits.push((gap, line_break(line + 1)));
+                broken.next();
+            }
+            _ => edits.push((
+                gap,
+                format!("{}{}", separators[i], " ".repeat(extra_spaces[i])),
+            )),
+        }
+    }
+    edits.push((words[words.len() - 1].end..paragraph.len(), String::new()));
+
+    // Convert the byte ranges of the changes to character ranges.
+    let mut char_index = vec![0; paragraph.len() + 1];
+    for (n, (i, _)) in paragraph.char_indices().enumerate() {
+        char_index[i] = n;
+    }
+    char_index[paragraph.len()] = paragraph.chars().count();
+
+    edits
+        .into_iter()
+        .filter(|(range, replacement)| paragraph[range.clone()] != replacement[..])
+        .map(|(range, replacement)| Edit {
+            start: char_index[range.start],
+            end: char_index[range.end],
+            replacement,
+        })
+        .collect()
+}
+
+/// Return the byte ranges of the paragraphs of TEXT, which are separated
+/// by blank lines.  A paragraph doesn't include its final newline.
+fn paragraphs(text: &str) -> Vec<(usize, usize)> {
+    let mut paragraphs = Vec::new();
+    let mut current: Option<(usize, usize)> = None;
+    let mut line_start = 0;
+    for line in text.split('\n') {
+        let line_end = line_start + line.len();
+        if line.trim_matches(|c| c == ' ' || c == '\t').is_empty() {
+            paragraphs.extend(current.take());
+        } else {
+            current = Some((current.map_or(line_start, |(start, _)| start), line_end));
+        }
+        line_start = line_end + 1;
+    }
+    paragraphs.extend(current);
+    paragraphs
+}
+
+/// Return the text of the string S, with characters which aren't
+/// Unicode, such as raw bytes, replaced with U+FFFD.
+fn string_text(s: LispStringRef) -> String {
+    s.chars()
+        .map(|c| std::char::from_u32(c).unwrap_or('\u{FFFD}'))
+        .collect()
+}
+
+/// Fill each of the paragraphs in the region between FROM and TO, as
+/// `fill-region' does.  The region is extended to whole lines, and
+/// paragraphs are separated by blank lines.  Lines are broken where the
+/// Unicode line breaking algorithm allows, so text in scripts written
+/// without spaces between words is broken between characters.  A
+/// newline next to a character in `fill-nospace-between-words-table' is
+/// deleted without putting a space in its place.
+///
+/// Each line starts with `fill-prefix', or if that is nil, with the
+/// indentation of the first line of the paragraph.  Lines end before
+/// `fill-column'.  JUSTIFY specifies the kind of justification: `full',
+/// `left', `right', `center', or `none' (equivalent to nil).  A value of
+/// t means to use `default-justification'.
+///
+/// Return the fill prefix used for filling the last paragraph.
+#[lisp_fn(
+    min = "2",
+    intspec = "(progn (barf-if-buffer-read-only) \
+               (list (region-beginning) (region-end) (if current-prefix-arg 'full)))"
+)]
+pub fn fill_region_natively(
+    mut from: LispObject,
+    mut to: LispObject,
+    justify: LispObject,
+) -> LispObject {
+    unsafe { validate_region(&mut from, &mut to) };
+    let count = c_specpdl_index();
+    unsafe { record_unwind_protect(Some(save_excursion_restore), save_excursion_save()) };
+
+    goto_char(from);
+    let start = line_beginning_position(None);
+    goto_char(to);
+    let end = if bolp() && to.as_fixnum_or_error() > start {
+        to.as_fixnum_or_error() - 1
+    } else {
+        line_end_position(None)
+    };
+    let text = string_text(buffer_substring_no_properties(start.into(), end.into()).into());
+
+    let fill_prefix = symbol_value(Qfill_prefix.into()).as_string();
+    let fill_column = ThreadState::current_buffer()
+        .fill_column_
+        .as_fixnum()
+        .map_or(70, |column| column.max(0) as usize);
+    let nospace_table = if boundp(Qfill_nospace_between_words_table.into()) {
+        symbol_value(Qfill_nospace_between_words_table.into()).as_char_table()
+    } else {
+        None
+    };
+    let char_width = |c: char| {
+        unsafe { Fchar_width(EmacsInt::from(c as u32).into()) }.as_fixnum_or_error() as usize
+    };
+    let nospace = |c: char| nospace_table.map_or(false, |table| table.get(c as isize).is_not_nil());
+
+    let mut settings = FillSettings {
+        prefix: String::new(),
+        fill_column,
+        justification: Justification::from_symbol(justify),
+        sentence_end_double_space: symbol_value(Qsentence_end_double_space.into()).is_not_nil(),
+    };
+    let mut edits = Vec::new();
+    for (para_start, para_end) in paragraphs(&text) {
+        let paragraph = &text[para_start..para_end];
+        settings.prefix = match fill_prefix {
+            Some(prefix) => string_text(prefix),
+            None => paragraph
+                .chars()
+                .take_while(|&c| c == ' ' || c == '\t')
+                .collect(),
+        };
+        let offset = start as usize + text[..para_start].chars().count();
+        edits.extend(
+            fill_paragraph(paragraph, &settings, &char_width, &nospace)
+                .into_iter()
+                .map(|edit| Edit {
+                    start: offset + edit.start,
+                    end: offset + edit.end,
+                    replacement: edit.replacement,
+                }),
+        );
+    }
+
+    // Change the text from the end, so that the positions of the changes
+    // before stay the same.
+    for edit in edits.into_iter().rev() {
+        delete_region(
+            (edit.start as EmacsInt).into(),
+            (edit.end as EmacsInt).into(),
+        );
+        goto_char((edit.start as EmacsInt).into());
+        if !edit.replacement.is_empty() {
+            callN_raw!(Finsert, LispObject::from(edit.replacement.as_str()));
+        }
+    }
+
+    unbind_to(count, Qnil);
+    if settings.prefix.is_empty() {
+        Qnil
+    } else {
+        LispObject::from(settings.prefix.as_str())
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn syms_of_fill() {
+    def_lisp_sym!(Qdefault_justification, "default-justification");
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    def_lisp_sym!(Qfill_nospace_between_words_table, "fill-nospace-between-words-table");
+    def_lisp_sym!(Qfill_prefix, "fill-prefix");
+    def_lisp_sym!(Qfull, "full");
+    def_lisp_sym!(Qsentence_end_double_space, "sentence-end-double-space");
+}
+
+#[cfg(test)]
+fn fill_for_test(paragraph: &str, fill_column: usize, justification: Justification) -> String {
+    let settings = FillSettings {
+        prefix: String::new(),
+        fill_column,
+        justification,
+        sentence_end_double_space: true,
+    };
+    let wide = |c: char| c >= '\u{3000}';
+    let edits = fill_paragraph(paragraph, &settings, |c| if wide(c) { 2 } else { 1 }, wide);
+    let mut chars: Vec<char> = paragraph.chars().collect();
+    for edit in edits.into_iter().rev() {
+        chars.splice(edit.start..edit.end, edit.replacement.chars());
+    }
+    chars.into_iter().collect()
+}
+
+#[test]
+fn test_fill_paragraph() {
+    assert_eq!(
+        fill_for_test("aaa bbb\nccc  ddd eee", 8, Justification::Left),
+        "aaa bbb\nccc ddd\neee"
+    );
+    assert_eq!(
+        fill_for_test("aaa bbb ccc ddd", 100, Justification::Left),
+        "aaa bbb ccc ddd"
+    );
+    // Sentences end with two spaces, and abbreviations aren't broken.
+    assert_eq!(
+        fill_for_test("Hi.\nMr. X", 100, Justification::Left),
+        "Hi.  Mr. X"
+    );
+    assert_eq!(fill_for_test("Mr. X", 4, Justification::Left), "Mr. X");
+    // Words longer than the line are left alone.
+    assert_eq!(
+        fill_for_test("a bbbbbbbb c", 4, Justification::Left),
+        "a\nbbbbbbbb\nc"
+    );
+}
+
+#[test]
+fn test_fill_paragraph_cjk() {
+    assert_eq!(
+        fill_for_test("日本語の\n文章です", 8, Justification::Left),
+        "日本語の\n文章です"
+    );
+    assert_eq!(
+        fill_for_test("日本語の文章です", 8, Justification::Left),
+        "日本語の\n文章です"
+    );
+    assert_eq!(
+        fill_for_test("日本語の\n文章です", 20, Justification::Left),
+        "日本語の文章です"
+    );
+}
+
+#[test]
+fn test_fill_paragraph_justification() {
+    assert_eq!(
+        fill_for_test("a b c dd", 6, Justification::Full),
+        "a  b c\ndd"
+    );
+    assert_eq!(fill_for_test("ab cd", 3, Justification::Right), " ab\n cd");
+    assert_eq!(fill_for_test("ab", 6, Justification::Center), "  ab");
+}
+
+#[test]
+fn test_paragraphs() {
+    assert_eq!(paragraphs("a\nb\n\n \nc\n"), vec![(0, 3), (7, 8)]);
+    assert_eq!(paragraphs(""), vec![]);
+}
+
+include!(concat!(env!("OUT_DIR"), "/fill_exports.rs"));
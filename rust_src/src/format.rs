@@ -0,0 +1,1165 @@
+//! Formatting strings, for `format' and `format-message'.
+//!
+//! A format string is parsed once into `Piece's, which are kept in a
+//! cache keyed by its bytes, so that the format strings of logging and
+//! message calls are not parsed again on every call.  Formatting then
+//! goes over the pieces, converting the arguments as the C version did.
+
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+use libc::{c_char, c_int, c_longlong, c_ulonglong, ptrdiff_t};
+
+use crate::{
+    character::char_head_p,
+    editfns::char_to_string,
+    lisp::LispObject,
+    lists::{LispConsCircularChecks, LispConsEndChecks},
+    multibyte::{
+        count_size_as_multibyte, is_ascii, multibyte_char_at, multibyte_chars_in_text,
+        raw_byte_codepoint, write_codepoint, LispStringRef, MAX_MULTIBYTE_LENGTH,
+    },
+    numbers::MOST_POSITIVE_FIXNUM,
+    remacs_sys::{
+        add_text_properties_from_list, extend_property_ranges, lisp_string_width,
+        make_composition_value_copy, make_specified_string, string_overflow, text_property_list,
+        text_quoting_style, Fnreverse, Fprin1_to_string, Qnil, Qt,
+    },
+    remacs_sys::{globals, EmacsDouble, EmacsInt},
+};
+
+/// One more than the largest number of bytes in a string.
+const MAX_BUFSIZE: usize = MOST_POSITIVE_FIXNUM as usize + 1;
+
+/// The largest precision for which a %f conversion of a long double
+/// may end in a digit other than 0.  Larger precisions are padded with
+/// zeros rather than passed to `snprintf', which limits its output to
+/// `INT_MAX' bytes.
+const USEFUL_PRECISION_MAX: usize = 16382;
+
+/// Format strings with more bytes than this are parsed on each call
+/// rather than cached, as they are unlikely to be used again.
+const MAX_CACHED_FORMAT_BYTES: usize = 512;
+
+/// The number of parsed format strings to keep.  The cache is emptied
+/// when it fills up.
+const FORMAT_CACHE_SIZE: usize = 256;
+
+/// The curved quotes, in UTF-8.
+const LEFT_SINGLE_QUOTATION_MARK: &[u8] = b"\xE2\x80\x98";
+const RIGHT_SINGLE_QUOTATION_MARK: &[u8] = b"\xE2\x80\x99";
+
+/// A format specification, from the % to the conversion character:
+///
+///   '%' [field-number '$'] [flags] [field-width] ['.' precision] conversion
+///
+/// START and END are the bytes it takes up in the format string.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Spec {
+    start: usize,
+    end: usize,
+    field: Option<usize>,
+    minus: bool,
+    plus: bool,
+    space: bool,
+    sharp: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    conversion: u8,
+}
+
+/// A part of a parsed format string.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Piece {
+    /// Bytes from START to END which are copied as they are.
+    Text {
+        start: usize,
+        end: usize,
+    },
+    /// A grave accent or apostrophe, which `format-message' may
+    /// requote.
+    Quote(u8),
+    Spec(Spec),
+    /// A format specification cut short by the end of the string.
+    Unfinished,
+}
+
+/// Read the decimal number at the start of BYTES, and return it (0 if
+/// there are no digits) and the number of digits.  Numbers which are
+/// too large are read as `isize::MAX', like `str2num' in C did.
+fn decimal_prefix(bytes: &[u8]) -> (usize, usize) {
+    let digits = bytes.iter().take_while(|b| b.is_ascii_digit()).count();
+    let n = bytes[..digits].iter().fold(0_usize, |n, &b| {
+        n.checked_mul(10)
+            .and_then(|n| n.checked_add(usize::from(b - b'0')))
+            .map_or(isize::max_value() as usize, |n| {
+                n.min(isize::max_value() as usize)
+            })
+    });
+    (n, digits)
+}
+
+/// Parse the format specification which starts with the % at START of
+/// FORMAT.
+fn parse_spec(format: &[u8], start: usize) -> Piece {
+    let mut i = start + 1;
+
+    let mut field = None;
+    let (num, digits) = decimal_prefix(&format[i..]);
+    if digits > 0 && format.get(i + digits) == Some(&b'$') {
+        field = Some(num);
+        i += digits + 1;
+    }
+
+    let (mut minus, mut plus, mut space, mut sharp, mut zero) = (false, false, false, false, false);
+    while let Some(&flag) = format.get(i) {
+        match flag {
+            b'-' => minus = true,
+            b'+' => plus = true,
+            b' ' => space = true,
+            b'#' => sharp = true,
+            b'0' => zero = true,
+            _ => break,
+        }
+        i += 1;
+    }
+
+    let (width, digits) = decimal_prefix(&format[i..]);
+    i += digits;
+
+    let mut precision = None;
+    if format.get(i) == Some(&b'.') {
+        let (num, digits) = decimal_prefix(&format[i + 1..]);
+        precision = Some(num);
+        i += 1 + digits;
+    }
+
+    match format.get(i) {
+        None => Piece::Unfinished,
+        Some(&conversion) => Piece::Spec(Spec {
+            start,
+            end: i + 1,
+            field,
+            minus,
+            plus,
+            // Ignore flags when sprintf ignores them.
+            space: space && !plus,
+            sharp,
+            zero: zero && !minus,
+            width,
+            precision,
+            conversion,
+        }),
+    }
+}
+
+/// Split FORMAT into pieces.
+fn parse_format(format: &[u8]) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    let mut text_start = 0;
+    let mut i = 0;
+    while i < format.len() {
+        let byte = format[i];
+        if byte != b'%' && byte != b'`' && byte != b'\'' {
+            i += 1;
+            continue;
+        }
+        if text_start < i {
+            pieces.push(Piece::Text {
+                start: text_start,
+                end: i,
+            });
+        }
+        if byte == b'%' {
+            let piece = parse_spec(format, i);
+            pieces.push(piece);
+            match piece {
+                Piece::Spec(spec) => i = spec.end,
+                _ => return pieces,
+            }
+        } else {
+            pieces.push(Piece::Quote(byte));
+            i += 1;
+        }
+        text_start = i;
+    }
+    if text_start < format.len() {
+        pieces.push(Piece::Text {
+            start: text_start,
+            end: format.len(),
+        });
+    }
+    pieces
+}
+
+lazy_static! {
+    static ref FORMAT_CACHE: Mutex<HashMap<Vec<u8>, Arc<Vec<Piece>>>> = Mutex::new(HashMap::new());
+}
+
+/// Return the pieces of FORMAT, parsing it unless it is in the cache.
+fn format_pieces(format: &[u8]) -> Arc<Vec<Piece>> {
+    if format.len() > MAX_CACHED_FORMAT_BYTES {
+        return Arc::new(parse_format(format));
+    }
+
+    let mut cache = FORMAT_CACHE.lock().unwrap();
+    if let Some(pieces) = cache.get(format) {
+        return Arc::clone(pieces);
+    }
+    let pieces = Arc::new(parse_format(format));
+    if cache.len() >= FORMAT_CACHE_SIZE {
+        cache.clear();
+    }
+    cache.insert(format.to_vec(), Arc::clone(&pieces));
+    pieces
+}
+
+/// Return the shortest printed representation of X which reads back
+/// as X, as `prin1' prints floats when `float-output-format' is nil.
+/// The digits come from ryu; they are laid out as "%.*g" would lay them
+/// out, with at least `DBL_DIG' digits of precision, and a ".0" is added
+/// when they would otherwise read as an integer.
+fn float_to_shortest(x: f64) -> String {
+    if x.is_infinite() {
+        return if x > 0.0 { "1.0e+INF" } else { "-1.0e+INF" }.to_string();
+    }
+    if x.is_nan() {
+        return if x.is_sign_negative() {
+            "-0.0e+NaN"
+        } else {
+            "0.0e+NaN"
+        }
+        .to_string();
+    }
+    let sign = if x.is_sign_negative() { "-" } else { "" };
+    if x == 0.0 {
+        return format!("{}0.0", sign);
+    }
+
+    // Reduce the output of ryu, which may or may not use an exponent,
+    // to its significant digits and the exponent of the first of them.
+    let mut buffer = ryu::Buffer::new();
+    let printed = buffer.format(x.abs());
+    let (mantissa, exponent) = match printed.find(|c| c == 'e' || c == 'E') {
+        Some(e) => (&printed[..e], printed[e + 1..].parse::<i32>().unwrap_or(0)),
+        None => (printed, 0),
+    };
+    let point = mantissa.find('.').unwrap_or_else(|| mantissa.len());
+    let all_digits: Vec<u8> = mantissa.bytes().filter(|&b| b != b'.').collect();
+    let leading_zeros = all_digits.iter().take_while(|&&b| b == b'0').count();
+    let mut digits = all_digits[leading_zeros..].to_vec();
+    while digits.last() == Some(&b'0') {
+        digits.pop();
+    }
+    let exponent = point as i32 - leading_zeros as i32 - 1 + exponent;
+    let ndigits = digits.len() as i32;
+
+    let precision = if x.abs() < std::f64::MIN_POSITIVE {
+        ndigits
+    } else {
+        ndigits.max(std::f64::DIGITS as i32)
+    };
+
+    let mut out = String::from(sign);
+    if exponent < -4 || exponent >= precision {
+        out.push(char::from(digits[0]));
+        if digits.len() > 1 {
+            out.push('.');
+            out.extend(digits[1..].iter().map(|&b| char::from(b)));
+        }
+        out.push_str(&format!(
+            "e{}{:02}",
+            if exponent < 0 { '-' } else { '+' },
+            exponent.abs()
+        ));
+        return out;
+    }
+
+    if exponent < 0 {
+        out.push_str("0.");
+        out.extend((1..-exponent).map(|_| '0'));
+        out.extend(digits.iter().map(|&b| char::from(b)));
+    } else {
+        let int_digits = exponent as usize + 1;
+        for i in 0..int_digits {
+            out.push(digits.get(i).map_or('0', |&b| char::from(b)));
+        }
+        out.push('.');
+        if digits.len() > int_digits {
+            out.extend(digits[int_digits..].iter().map(|&b| char::from(b)));
+        } else {
+            // The decimal point must be followed by a digit, so that the
+            // value reads back as a float.
+            out.push('0');
+        }
+    }
+    out
+}
+
+/// Return the printed representation of ARG, for %s if NOESCAPE and
+/// for %S otherwise.
+fn prin1_to_string(arg: LispObject, noescape: bool) -> LispObject {
+    match arg.as_float() {
+        Some(x) if unsafe { globals.Vfloat_output_format }.is_nil() => {
+            LispObject::from(float_to_shortest(x).as_str())
+        }
+        _ => unsafe { Fprin1_to_string(arg, if noescape { Qt } else { Qnil }) },
+    }
+}
+
+/// Return whether the string S has text properties.
+fn has_intervals(s: LispStringRef) -> bool {
+    !unsafe { s.u.s.intervals }.is_null()
+}
+
+/// Copy the bytes of a string in SRC to OUT, converting them to
+/// multibyte if MULTIBYTE and the string is unibyte.
+fn copy_text(src: &[u8], src_multibyte: bool, out: &mut Vec<u8>, multibyte: bool) {
+    if src_multibyte || !multibyte || src.iter().all(u8::is_ascii) {
+        out.extend_from_slice(src);
+        return;
+    }
+    let mut buf = [0_u8; MAX_MULTIBYTE_LENGTH];
+    for &byte in src {
+        let len = write_codepoint(&mut buf, raw_byte_codepoint(byte));
+        out.extend_from_slice(&buf[..len]);
+    }
+}
+
+/// Round the decimal DIGITS of an integer to COUNT significant digits,
+/// with ties going to even as in `snprintf'.  Return the digits, padded
+/// with zeros to COUNT, and the decimal exponent of the first one.
+fn round_significant(digits: &[u8], count: usize) -> (Vec<u8>, usize) {
+    let mut exponent = digits.len() - 1;
+    if count >= digits.len() {
+        let mut rounded = digits.to_vec();
+        rounded.resize(count, b'0');
+        return (rounded, exponent);
+    }
+
+    let mut rounded = digits[..count].to_vec();
+    let (first_dropped, rest) = (digits[count], &digits[count + 1..]);
+    let round_up = first_dropped > b'5'
+        || (first_dropped == b'5'
+            && (rest.iter().any(|&d| d != b'0')
+                || rounded.last().map_or(false, |&d| (d - b'0') % 2 == 1)));
+    if round_up {
+        match rounded.iter().rposition(|&d| d != b'9') {
+            Some(i) => {
+                rounded[i] += 1;
+                for d in &mut rounded[i + 1..] {
+                    *d = b'0';
+                }
+            }
+            None => {
+                // All nines carry into a new first digit.
+                for d in &mut rounded {
+                    *d = b'0';
+                }
+                if let Some(first) = rounded.first_mut() {
+                    *first = b'1';
+                }
+                exponent += 1;
+            }
+        }
+    }
+    (rounded, exponent)
+}
+
+/// Format the integer N for the conversion CONVERSION of SPEC, which is
+/// `e', `f' or `g', as `snprintf' would format it exactly, with a
+/// precision of PREC, or 6 if PREC is negative.  Fixnums may have more
+/// digits than a double holds, so they aren't converted to one, as C
+/// converted them to long double.
+fn sprintf_integer_as_float(
+    n: EmacsInt,
+    spec: &Spec,
+    conversion: u8,
+    sharp: bool,
+    prec: c_int,
+) -> Vec<u8> {
+    let prec = if prec < 0 { 6 } else { prec as usize };
+    let mut out = Vec::new();
+    if n < 0 {
+        out.push(b'-');
+    } else if spec.plus {
+        out.push(b'+');
+    } else if spec.space {
+        out.push(b' ');
+    }
+    let digits = (n.wrapping_abs() as u64).to_string().into_bytes();
+
+    // The digits before and after the point, and the exponent if there
+    // is one.
+    let (int_part, mut frac_part, exponent) = match conversion {
+        b'f' => (digits, vec![b'0'; prec], None),
+        b'e' => {
+            let (mut rounded, exponent) = round_significant(&digits, prec + 1);
+            let frac_part = rounded.split_off(1);
+            (rounded, frac_part, Some(exponent))
+        }
+        _ => {
+            let prec = prec.max(1);
+            let (mut rounded, exponent) = round_significant(&digits, prec);
+            if exponent < prec {
+                let frac_part = rounded.split_off(exponent + 1);
+                (rounded, frac_part, None)
+            } else {
+                let frac_part = rounded.split_off(1);
+                (rounded, frac_part, Some(exponent))
+            }
+        }
+    };
+    if conversion == b'g' && !sharp {
+        while frac_part.last() == Some(&b'0') {
+            frac_part.pop();
+        }
+    }
+
+    out.extend_from_slice(&int_part);
+    if !frac_part.is_empty() || sharp {
+        out.push(b'.');
+        out.extend_from_slice(&frac_part);
+    }
+    if let Some(exponent) = exponent {
+        out.extend_from_slice(format!("e+{:02}", exponent).as_bytes());
+    }
+    out
+}
+
+/// Format the number ARG for the conversion CONVERSION of SPEC with
+/// `snprintf', without padding and with a precision of PREC, or none
+/// if PREC is negative.
+fn sprintf_number(
+    arg: LispObject,
+    spec: &Spec,
+    conversion: u8,
+    sharp: bool,
+    prec: c_int,
+) -> Vec<u8> {
+    let float_conversion = conversion == b'e' || conversion == b'f' || conversion == b'g';
+
+    if conversion == b'c' {
+        // Don't use sprintf here, as it might mishandle prec.
+        return if prec == 0 {
+            Vec::new()
+        } else {
+            vec![arg.as_fixnum_or_error() as u8]
+        };
+    }
+
+    if float_conversion {
+        if let Some(n) = arg.as_fixnum() {
+            return sprintf_integer_as_float(n, spec, conversion, sharp, prec);
+        }
+    }
+
+    let mut convspec = String::from("%");
+    if spec.plus {
+        convspec.push('+');
+    }
+    if spec.space {
+        convspec.push(' ');
+    }
+    if sharp {
+        convspec.push('#');
+    }
+    convspec.push_str(".*");
+    if !float_conversion {
+        convspec.push_str("ll");
+    }
+    convspec.push(char::from(conversion));
+    let convspec = CString::new(convspec).unwrap();
+
+    // Numbers are passed to sprintf as the types the conversion expects.
+    #[derive(Clone, Copy)]
+    enum Value {
+        Float(EmacsDouble),
+        Signed(c_longlong),
+        Unsigned(c_ulonglong),
+    }
+    let value = if float_conversion {
+        Value::Float(arg.as_float().unwrap_or(0.0))
+    } else if conversion == b'd' || conversion == b'i' {
+        Value::Signed(match arg.as_fixnum() {
+            Some(n) => n as c_longlong,
+            None => {
+                let d = arg.as_float().unwrap_or(0.0);
+                if d < 0.0 {
+                    if (c_longlong::min_value() as f64) < d {
+                        d as c_longlong
+                    } else {
+                        c_longlong::min_value()
+                    }
+                } else if d < c_longlong::max_value() as f64 {
+                    d as c_longlong
+                } else {
+                    c_longlong::max_value()
+                }
+            }
+        })
+    } else {
+        // Don't sign-extend for octal or hex printing.
+        Value::Unsigned(match arg.as_fixnum() {
+            Some(n) => (n as c_ulonglong) & (c_ulonglong::max_value() >> 2),
+            None => {
+                let d = arg.as_float().unwrap_or(0.0);
+                if d < 0.0 {
+                    0
+                } else if d < c_ulonglong::max_value() as f64 {
+                    d as c_ulonglong
+                } else {
+                    c_ulonglong::max_value()
+                }
+            }
+        })
+    };
+
+    let sprintf = |buf: &mut Vec<u8>| {
+        let (ptr, size) = (buf.as_mut_ptr() as *mut c_char, buf.len());
+        let format = convspec.as_ptr();
+        let written = unsafe {
+            match value {
+                Value::Float(x) => libc::snprintf(ptr, size, format, prec, x),
+                Value::Signed(x) => libc::snprintf(ptr, size, format, prec, x),
+                Value::Unsigned(x) => libc::snprintf(ptr, size, format, prec, x),
+            }
+        };
+        written.max(0) as usize
+    };
+
+    // Most numbers fit in a small buffer; try again with one which is
+    // large enough if this one was not.
+    let mut buf = vec![0_u8; 64];
+    let mut written = sprintf(&mut buf);
+    if written >= buf.len() {
+        buf.resize(written + 1, 0);
+        written = sprintf(&mut buf);
+    }
+    buf.truncate(written);
+    buf
+}
+
+/// The argument of a format specification, and where it went in the
+/// output.
+struct Field {
+    /// The argument, converted to a string if the conversion needed it.
+    argument: LispObject,
+    /// The characters the field takes up in the output.
+    start: usize,
+    end: usize,
+    /// Whether the argument is a string with text properties.
+    intervals: bool,
+}
+
+/// The text formatted so far.
+struct Output {
+    bytes: Vec<u8>,
+    nchars: usize,
+    /// Whether a byte of the output may have combined with the
+    /// multibyte character before it.
+    maybe_combine_byte: bool,
+    /// Whether the output differs from the format string.
+    new_result: bool,
+}
+
+impl Output {
+    /// Return whether the last byte of the output is not ASCII.
+    fn last_byte_not_ascii(&self) -> bool {
+        self.bytes.last().map_or(false, |&b| !b.is_ascii())
+    }
+
+    /// Add N copies of BYTE, which is ASCII.
+    fn push_padding(&mut self, byte: u8, n: usize) {
+        self.bytes.extend((0..n).map(|_| byte));
+        self.nchars += n;
+    }
+
+    /// Add STRING, formatted for SPEC, and record where it went in FIELD.
+    fn push_string(
+        &mut self,
+        spec: &Spec,
+        field: &mut Field,
+        string: LispStringRef,
+        multibyte: bool,
+    ) {
+        // lisp_string_width ignores a precision of 0, but printf
+        // prints 0 characters when the precision is 0.
+        let (width, nchars, nbytes) = match spec.precision {
+            Some(0) => (0, 0, 0),
+            Some(prec) => {
+                let (mut nchars, mut nbytes) = (0, 0);
+                let width = unsafe {
+                    lisp_string_width(string.into(), prec as ptrdiff_t, &mut nchars, &mut nbytes)
+                };
+                (width as usize, nchars as usize, nbytes as usize)
+            }
+            None => {
+                let width = unsafe {
+                    lisp_string_width(string.into(), -1, ptr::null_mut(), ptr::null_mut())
+                };
+                (
+                    width as usize,
+                    string.len_chars() as usize,
+                    string.len_bytes() as usize,
+                )
+            }
+        };
+
+        let text = &string.as_slice()[..nbytes];
+        let padding = spec.width.saturating_sub(width);
+        let convbytes = if multibyte && !string.is_multibyte() {
+            unsafe { count_size_as_multibyte(text.as_ptr(), text.len() as ptrdiff_t) as usize }
+        } else {
+            text.len()
+        };
+        check_size(convbytes, padding);
+
+        if !spec.minus {
+            self.push_padding(b' ', padding);
+        }
+        field.start = self.nchars;
+
+        if multibyte
+            && string.is_multibyte()
+            && self.last_byte_not_ascii()
+            && !text.first().map_or(true, |&b| char_head_p(b))
+        {
+            self.maybe_combine_byte = true;
+        }
+        copy_text(text, string.is_multibyte(), &mut self.bytes, multibyte);
+        self.nchars += nchars;
+
+        if spec.minus {
+            self.push_padding(b' ', padding);
+        }
+        field.end = self.nchars;
+
+        // If this argument has text properties, record where in the
+        // result string it appears.
+        if has_intervals(string) {
+            field.intervals = true;
+        }
+        self.new_result = true;
+    }
+
+    /// Add the number ARG, formatted for SPEC with CONVERSION, and record
+    /// where it went in FIELD.  ZERO says whether to pad with zeros.
+    fn push_number(
+        &mut self,
+        spec: &Spec,
+        field: &mut Field,
+        arg: LispObject,
+        conversion: u8,
+        mut zero: bool,
+    ) {
+        let float_conversion = conversion == b'e' || conversion == b'f' || conversion == b'g';
+        // Avoid undefined behavior in the underlying sprintf.
+        let sharp = spec.sharp && conversion != b'd' && conversion != b'i';
+        if !float_conversion && conversion != b'c' {
+            zero &= spec.precision.is_none();
+        }
+        let prec = spec
+            .precision
+            .map_or(-1, |p| p.min(USEFUL_PRECISION_MAX) as c_int);
+        let number = sprintf_number(arg, spec, conversion, sharp, prec);
+
+        // Deal with excess precision, which sprintf was not given.
+        let mut excess_precision = spec
+            .precision
+            .map_or(0, |p| p - p.min(USEFUL_PRECISION_MAX));
+        let (mut leading_zeros, mut trailing_zeros) = (0, 0);
+        if excess_precision > 0 {
+            if float_conversion {
+                let ends_in_digit = number.last().map_or(false, u8::is_ascii_digit);
+                if (conversion == b'g' && !sharp)
+                    || !ends_in_digit
+                    || (conversion == b'g' && !number.contains(&b'.'))
+                {
+                    excess_precision = 0;
+                }
+                trailing_zeros = excess_precision;
+            } else {
+                leading_zeros = excess_precision;
+            }
+        }
+
+        let numwidth = number.len().saturating_add(excess_precision);
+        let mut padding = spec.width.saturating_sub(numwidth);
+        if MAX_BUFSIZE - number.len() <= excess_precision {
+            unsafe { string_overflow() };
+        }
+        check_size(numwidth, padding);
+
+        let signed = match number.first() {
+            Some(b'-') | Some(b'+') | Some(b' ') => 1,
+            _ => 0,
+        };
+        let hex_prefix = number.get(signed) == Some(&b'0')
+            && (number.get(signed + 1) == Some(&b'x') || number.get(signed + 1) == Some(&b'X'));
+        let prefix_bytes = signed + if hex_prefix { 2 } else { 0 };
+        if zero
+            && number
+                .get(prefix_bytes)
+                .map_or(false, u8::is_ascii_hexdigit)
+        {
+            leading_zeros += padding;
+            padding = 0;
+        }
+
+        let exponent_bytes = if excess_precision > 0 && (conversion == b'e' || conversion == b'g') {
+            number
+                .iter()
+                .position(|&b| b == b'e')
+                .map_or(0, |e| number.len() - e)
+        } else {
+            0
+        };
+
+        field.start = self.nchars;
+        if !spec.minus {
+            self.push_padding(b' ', padding);
+        }
+        let significand_end = number.len() - exponent_bytes;
+        self.bytes.extend_from_slice(&number[..prefix_bytes]);
+        self.push_padding(b'0', leading_zeros);
+        self.bytes
+            .extend_from_slice(&number[prefix_bytes..significand_end]);
+        self.push_padding(b'0', trailing_zeros);
+        self.bytes.extend_from_slice(&number[significand_end..]);
+        self.nchars += number.len();
+        if spec.minus {
+            self.push_padding(b' ', padding);
+        }
+        field.end = self.nchars;
+        self.new_result = true;
+    }
+}
+
+/// How a pass over the format string ended.
+enum Outcome {
+    Formatted(Output),
+    /// The format string is "%s", and this is its argument.
+    Argument(LispObject),
+    /// The output turned out to need to be multibyte.
+    Retry,
+}
+
+/// Check that adding PADDING bytes and then LEN more to a string is
+/// possible.
+fn check_size(len: usize, padding: usize) {
+    if MAX_BUFSIZE - padding <= len {
+        unsafe { string_overflow() };
+    }
+}
+
+/// Run the format string ARGS[0] on the rest of ARGS, for `format' or,
+/// with QUOTING_STYLE, for `format-message'.  FIELDS holds the converted
+/// arguments of previous attempts.
+fn format_pass(
+    args: &[LispObject],
+    pieces: &[Piece],
+    multibyte: bool,
+    quoting_style: Option<text_quoting_style::Type>,
+    fields: &mut Vec<Field>,
+) -> Outcome {
+    let format = args[0].as_string_or_error();
+    let format_bytes = format.as_slice();
+    let multibyte_format = format.is_multibyte();
+
+    let mut out = Output {
+        bytes: Vec::with_capacity(format_bytes.len() * 2),
+        nchars: 0,
+        maybe_combine_byte: false,
+        new_result: false,
+    };
+    let mut n = 0;
+    let mut ispec = 0;
+
+    for piece in pieces {
+        let spec = match *piece {
+            Piece::Text { start, end } => {
+                let text = &format_bytes[start..end];
+                if multibyte_format {
+                    if out.last_byte_not_ascii() && !char_head_p(text[0]) {
+                        out.maybe_combine_byte = true;
+                    }
+                    out.bytes.extend_from_slice(text);
+                    out.nchars += 1 + text[1..].iter().filter(|&&b| char_head_p(b)).count();
+                } else {
+                    if multibyte && !text.iter().all(u8::is_ascii) {
+                        out.new_result = true;
+                    }
+                    copy_text(text, false, &mut out.bytes, multibyte);
+                    out.nchars += text.len();
+                }
+                continue;
+            }
+            Piece::Quote(quote) => {
+                match quoting_style {
+                    Some(text_quoting_style::CURVE_QUOTING_STYLE) => {
+                        if !multibyte {
+                            return Outcome::Retry;
+                        }
+                        out.bytes.extend_from_slice(if quote == b'`' {
+                            LEFT_SINGLE_QUOTATION_MARK
+                        } else {
+                            RIGHT_SINGLE_QUOTATION_MARK
+                        });
+                        out.new_result = true;
+                    }
+                    Some(text_quoting_style::STRAIGHT_QUOTING_STYLE) if quote == b'`' => {
+                        out.bytes.push(b'\'');
+                        out.new_result = true;
+                    }
+                    _ => out.bytes.push(quote),
+                }
+                out.nchars += 1;
+                continue;
+            }
+            Piece::Unfinished => error!("Format string ends in middle of format specifier"),
+            Piece::Spec(spec) => spec,
+        };
+
+        if spec.width >= MAX_BUFSIZE {
+            unsafe { string_overflow() };
+        }
+        if spec.conversion == b'%' {
+            out.bytes.push(b'%');
+            out.nchars += 1;
+            out.new_result = true;
+            continue;
+        }
+
+        n = spec.field.unwrap_or(n + 1);
+        if n >= args.len() {
+            error!("Not enough arguments for format string");
+        }
+
+        if ispec == fields.len() {
+            fields.push(Field {
+                argument: args[n],
+                start: 0,
+                end: 0,
+                intervals: false,
+            });
+        }
+        let field = &mut fields[ispec];
+        ispec += 1;
+        let mut arg = field.argument;
+        let mut conversion = spec.conversion;
+        let mut zero = spec.zero;
+
+        // For 'S', prin1 the argument, and then treat like 's'.  For
+        // 's', princ any argument that is not a string or symbol.  But
+        // don't do this conversion twice, which might happen after
+        // retrying.
+        if conversion == b'S' || (conversion == b's' && !arg.is_string() && !arg.is_symbol()) {
+            if arg.eq(args[n]) {
+                arg = prin1_to_string(arg, conversion == b's');
+                field.argument = arg;
+                if arg.as_string_or_error().is_multibyte() && !multibyte {
+                    return Outcome::Retry;
+                }
+            }
+            conversion = b's';
+        } else if conversion == b'c' {
+            if arg.as_fixnum().map_or(false, |c| !is_ascii(c as u32)) {
+                if !multibyte {
+                    return Outcome::Retry;
+                }
+                arg = char_to_string(arg);
+                field.argument = arg;
+            }
+            if !arg.eq(args[n]) {
+                conversion = b's';
+            }
+            zero = false;
+        }
+
+        if let Some(symbol) = arg.as_symbol() {
+            arg = symbol.symbol_name();
+            field.argument = arg;
+            if arg.as_string_or_error().is_multibyte() && !multibyte {
+                return Outcome::Retry;
+            }
+        }
+
+        let float_conversion = conversion == b'e' || conversion == b'f' || conversion == b'g';
+
+        if conversion == b's' {
+            if spec.start == 0 && spec.end == 2 && format_bytes.len() == 2 && !has_intervals(format)
+            {
+                return Outcome::Argument(arg);
+            }
+
+            out.push_string(&spec, field, arg.as_string_or_error(), multibyte);
+            continue;
+        }
+
+        let is_number_conversion = match conversion {
+            b'c' | b'd' | b'i' | b'o' | b'x' | b'X' => true,
+            _ => float_conversion,
+        };
+        if !is_number_conversion {
+            let bad = &format_bytes[spec.end - 1..];
+            let c = if multibyte_format {
+                multibyte_char_at(bad).0
+            } else {
+                u32::from(bad[0])
+            };
+            error!(
+                "Invalid format operation %{}",
+                std::char::from_u32(c).unwrap_or(std::char::REPLACEMENT_CHARACTER)
+            );
+        }
+        if !(arg.is_fixnum() || (arg.is_float() && conversion != b'c')) {
+            error!("Format specifier doesn't match argument type");
+        }
+
+        out.push_number(&spec, field, arg, conversion, zero);
+    }
+
+    Outcome::Formatted(out)
+}
+
+/// Return which bytes of FORMAT were not copied to the output: 1 for the
+/// bytes of a format specification, and 2 for the bytes after the first
+/// of a multibyte character.
+fn discarded_bytes(format: &[u8], multibyte: bool, pieces: &[Piece]) -> Vec<u8> {
+    let mut discarded = vec![0_u8; format.len()];
+    for piece in pieces {
+        match *piece {
+            Piece::Spec(spec) => {
+                let end = if spec.conversion == b'%' {
+                    spec.end - 1
+                } else {
+                    spec.end
+                };
+                for d in &mut discarded[spec.start..end] {
+                    *d = 1;
+                }
+            }
+            Piece::Text { start, end } if multibyte => {
+                let continuations = discarded[start + 1..end]
+                    .iter_mut()
+                    .zip(&format[start + 1..end])
+                    .filter(|&(_, &b)| !char_head_p(b));
+                for (d, _) in continuations {
+                    *d = 2;
+                }
+            }
+            _ => (),
+        }
+    }
+    discarded
+}
+
+/// Walks the format string and the output together, to move the text
+/// properties of the format string to the output.
+struct Translation<'a> {
+    discarded: &'a [u8],
+    fields: &'a [Field],
+    bytepos: usize,
+    position: usize,
+    translated: usize,
+    fieldn: usize,
+}
+
+impl<'a> Translation<'a> {
+    /// Return the position in the output of the character at POS in the
+    /// format string, which is after the last position translated.
+    fn translate(&mut self, pos: usize) -> usize {
+        while self.position < pos {
+            match self.discarded[self.bytepos] {
+                0 => {
+                    self.position += 1;
+                    self.translated += 1;
+                }
+                1 => {
+                    self.position += 1;
+                    if let Some(field) = self.fields.get(self.fieldn) {
+                        if self.translated == field.start {
+                            self.translated += field.end - field.start;
+                            self.fieldn += 1;
+                        }
+                    }
+                }
+                _ => (),
+            }
+            self.bytepos += 1;
+        }
+        self.translated
+    }
+}
+
+/// Give the string VAL, which was formatted from ARGS[0] with PIECES,
+/// the text properties of the format string and of the arguments.
+fn add_properties(val: LispObject, args: &[LispObject], pieces: &[Piece], fields: &[Field]) {
+    let format = args[0].as_string_or_error();
+    let len = LispObject::from(format.len_chars() as EmacsInt);
+    let props = unsafe { text_property_list(args[0], LispObject::from(0), len, Qnil) };
+    if props.is_cons() {
+        // Put the positions in PROPS in increasing order, so that the
+        // format string is only scanned once.
+        let props = unsafe { Fnreverse(props) };
+        let discarded = discarded_bytes(format.as_slice(), format.is_multibyte(), pieces);
+        let mut translation = Translation {
+            discarded: &discarded,
+            fields,
+            bytepos: 0,
+            position: 0,
+            translated: 0,
+            fieldn: 0,
+        };
+        for item in props.iter_cars(LispConsEndChecks::off, LispConsCircularChecks::off) {
+            let item = item.as_cons_or_error();
+            let start = translation.translate(item.car().as_fixnum_or_error() as usize);
+            item.set_car(LispObject::from(start as EmacsInt));
+            let tail = item.cdr().as_cons_or_error();
+            let end = translation.translate(tail.car().as_fixnum_or_error() as usize);
+            tail.set_car(LispObject::from(end as EmacsInt));
+        }
+        unsafe { add_text_properties_from_list(val, props, LispObject::from(0)) };
+    }
+
+    for (i, field) in fields.iter().enumerate().filter(|(_, f)| f.intervals) {
+        let len = LispObject::from(field.argument.as_string_or_error().len_chars() as EmacsInt);
+        let new_len = LispObject::from((field.end - field.start) as EmacsInt);
+        unsafe {
+            let props = text_property_list(field.argument, LispObject::from(0), len, Qnil);
+            let props = extend_property_ranges(props, len, new_len);
+            // If successive arguments have properties, be sure that the
+            // value of the `composition' property is a copy.
+            if 1 < i && fields[i - 1].end != 0 {
+                make_composition_value_copy(props);
+            }
+            add_text_properties_from_list(val, props, LispObject::from(field.start as EmacsInt));
+        }
+    }
+}
+
+/// Implement `format-message' if MESSAGE, and `format' otherwise.
+pub fn styled_format(args: &[LispObject], message: bool) -> LispObject {
+    let format = args[0].as_string_or_error();
+    let pieces = format_pieces(format.as_slice());
+
+    // The output is multibyte if any of the inputs is.  Objects printed
+    // by prin1 and requoted quotes may turn out to need it too, and then
+    // formatting starts over.
+    let mut multibyte = format.is_multibyte()
+        || args[1..]
+            .iter()
+            .any(|arg| arg.as_string().map_or(false, LispStringRef::is_multibyte));
+    let quoting_style = if message {
+        Some(unsafe { text_quoting_style() })
+    } else {
+        None
+    };
+
+    let mut fields = Vec::new();
+    let out = loop {
+        match format_pass(args, &pieces, multibyte, quoting_style, &mut fields) {
+            Outcome::Formatted(out) => break out,
+            Outcome::Argument(arg) => return arg,
+            Outcome::Retry => multibyte = true,
+        }
+    };
+
+    if !out.new_result {
+        return args[0];
+    }
+
+    let nchars = if out.maybe_combine_byte {
+        unsafe { multibyte_chars_in_text(out.bytes.as_ptr(), out.bytes.len() as ptrdiff_t) }
+    } else {
+        out.nchars as ptrdiff_t
+    };
+    let val = unsafe {
+        make_specified_string(
+            out.bytes.as_ptr() as *const c_char,
+            nchars,
+            out.bytes.len() as ptrdiff_t,
+            multibyte,
+        )
+    };
+
+    if has_intervals(format) || fields.iter().any(|field| field.intervals) {
+        add_properties(val, args, &pieces, &fields);
+    }
+    val
+}
+
+#[test]
+fn test_parse_format() {
+    let format = b"a%-5.2s`%3$x%";
+    let pieces = parse_format(format);
+    assert_eq!(pieces[0], Piece::Text { start: 0, end: 1 });
+    match pieces[1] {
+        Piece::Spec(spec) => {
+            assert_eq!((spec.start, spec.end), (1, 7));
+            assert!(spec.minus && !spec.zero);
+            assert_eq!((spec.width, spec.precision), (5, Some(2)));
+            assert_eq!(spec.conversion, b's');
+        }
+        _ => panic!("expected a format specification"),
+    }
+    assert_eq!(pieces[2], Piece::Quote(b'`'));
+    match pieces[3] {
+        Piece::Spec(spec) => assert_eq!((spec.field, spec.conversion), (Some(3), b'x')),
+        _ => panic!("expected a format specification"),
+    }
+    assert_eq!(pieces[4], Piece::Unfinished);
+}
+
+#[test]
+fn test_float_to_shortest() {
+    assert_eq!(float_to_shortest(1.0), "1.0");
+    assert_eq!(float_to_shortest(-0.0), "-0.0");
+    assert_eq!(float_to_shortest(0.1), "0.1");
+    assert_eq!(float_to_shortest(0.0001), "0.0001");
+    assert_eq!(float_to_shortest(0.00001), "1e-05");
+    assert_eq!(float_to_shortest(123456.5), "123456.5");
+    assert_eq!(float_to_shortest(1e15), "1e+15");
+    assert_eq!(float_to_shortest(1e14), "100000000000000.0");
+    assert_eq!(float_to_shortest(0.1 + 0.2), "0.30000000000000004");
+    assert_eq!(float_to_shortest(1.0 / 0.0), "1.0e+INF");
+    assert_eq!(float_to_shortest(-1.0 / 0.0), "-1.0e+INF");
+}
+
+#[test]
+fn test_sprintf_integer_as_float() {
+    let format = |format: &[u8], n: EmacsInt| match parse_format(format)[0] {
+        Piece::Spec(spec) => {
+            let prec = spec.precision.map_or(-1, |p| p as c_int);
+            let out = sprintf_integer_as_float(n, &spec, spec.conversion, spec.sharp, prec);
+            String::from_utf8(out).unwrap()
+        }
+        _ => panic!("expected a format specification"),
+    };
+    assert_eq!(format(b"%f", 12), "12.000000");
+    assert_eq!(format(b"%+.0f", 12), "+12");
+    assert_eq!(format(b"%#.0f", -12), "-12.");
+    assert_eq!(format(b"%e", 0), "0.000000e+00");
+    assert_eq!(format(b"%.1e", 125), "1.2e+02");
+    assert_eq!(format(b"%.1e", 135), "1.4e+02");
+    assert_eq!(format(b"%.1e", 1251), "1.3e+03");
+    assert_eq!(format(b"% .2e", 9996), " 1.00e+04");
+    assert_eq!(format(b"%g", 100000), "100000");
+    assert_eq!(format(b"%g", 1000000), "1e+06");
+    assert_eq!(format(b"%.3g", 9996), "1e+04");
+    assert_eq!(format(b"%#.3g", 12), "12.0");
+    // Fixnums with more digits than a double holds keep all of them.
+    assert_eq!(format(b"%.0f", 2305843009213693951), "2305843009213693951");
+    assert_eq!(
+        format(b"%.18e", 2305843009213693951),
+        "2.305843009213693951e+18"
+    );
+    assert_eq!(
+        format(b"%.19g", -2305843009213693951),
+        "-2305843009213693951"
+    );
+}
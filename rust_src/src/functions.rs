@@ -84,6 +84,42 @@ macro_rules! mock_multibyte_string {
     }};
 }
 
+#[macro_export]
+macro_rules! mock_vector {
+    ($($elt: expr),*) => {{
+        // Fake an allocated vector by putting its header and contents on the heap
+        // and leaking them.
+        let contents: Vec<crate::lisp::LispObject> = vec![$($elt),*];
+        let mut words = vec![contents.len() as crate::remacs_sys::EmacsInt];
+        words.extend(contents.iter().map(|elt| elt.to_C()));
+        let raw = Box::into_raw(words.into_boxed_slice()) as *mut crate::remacs_sys::Lisp_Vector;
+        let ptr = crate::lisp::ExternalPtr::new(raw);
+        crate::lisp::LispObject::tag_ptr(ptr, crate::remacs_sys::Lisp_Type::Lisp_Vectorlike)
+    }};
+}
+
+#[macro_export]
+macro_rules! mock_bool_vector {
+    ($bits: expr) => {{
+        // Fake an allocated bool-vector the same way, with its size in bits
+        // and the bits themselves after the header.
+        let bits: &[bool] = $bits;
+        let word_bits = crate::remacs_sys::BITS_PER_BITS_WORD as usize;
+        let header = crate::remacs_sys::PSEUDOVECTOR_FLAG
+            | ((crate::remacs_sys::pvec_type::PVEC_BOOL_VECTOR as usize)
+                << crate::remacs_sys::More_Lisp_Bits::PSEUDOVECTOR_AREA_BITS);
+        let mut words = vec![header, bits.len()];
+        words.resize(2 + bits.len() / word_bits + 1, 0);
+        for (i, &bit) in bits.iter().enumerate() {
+            words[2 + i / word_bits] |= (bit as usize) << (i % word_bits);
+        }
+        let raw =
+            Box::into_raw(words.into_boxed_slice()) as *mut crate::remacs_sys::Lisp_Bool_Vector;
+        let ptr = crate::lisp::ExternalPtr::new(raw);
+        crate::lisp::LispObject::tag_ptr(ptr, crate::remacs_sys::Lisp_Type::Lisp_Vectorlike)
+    }};
+}
+
 #[allow(unused_macros)]
 macro_rules! assert_t {
     ($arg: expr) => {{
@@ -104,7 +140,9 @@ macro_rules! assert_nil {
 #[allow(dead_code)]
 #[no_mangle]
 pub extern "C" fn Fcons(car: LispObject, cdr: LispObject) -> LispObject {
-    return Qnil;
+    // Fake an allocated cons by putting its car and cdr on the heap and leaking them.
+    let raw = Box::into_raw(Box::new([car, cdr])) as *mut Lisp_Cons;
+    LispObject::tag_ptr(crate::lisp::ExternalPtr::new(raw), Lisp_Type::Lisp_Cons)
 }
 
 #[cfg(test)]
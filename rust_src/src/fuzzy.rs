@@ -0,0 +1,211 @@
+//! Fuzzy matching for completion.
+//!
+//! Candidates are scored as the fzf fuzzy finder does: the characters
+//! of the query must occur in order in the candidate, and matches at
+//! word boundaries, in camel case humps and in consecutive characters
+//! are preferred.
+
+use rayon::prelude::*;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::{list, LispConsCircularChecks, LispConsEndChecks},
+    multibyte::LispStringRef,
+    remacs_sys::EmacsInt,
+};
+
+const SCORE_MATCH: i64 = 16;
+const SCORE_GAP_START: i64 = -3;
+const SCORE_GAP_EXTENSION: i64 = -1;
+/// The bonus for a match after a non-word character.
+const BONUS_BOUNDARY: i64 = SCORE_MATCH / 2;
+/// The bonus for matching a non-word character.
+const BONUS_NON_WORD: i64 = SCORE_MATCH / 2;
+/// The bonus for a match in a camel case hump or at the start of a
+/// number.
+const BONUS_CAMEL: i64 = BONUS_BOUNDARY + SCORE_GAP_EXTENSION;
+/// The least bonus of each match in a run of consecutive ones, which
+/// makes up for the gap they don't have.
+const BONUS_CONSECUTIVE: i64 = -(SCORE_GAP_START + SCORE_GAP_EXTENSION);
+/// The bonus of the first character of the query is multiplied by this.
+const BONUS_FIRST_CHAR_MULTIPLIER: i64 = 2;
+
+#[derive(Clone, Copy, PartialEq)]
+enum CharClass {
+    NonWord,
+    Lower,
+    Upper,
+    Letter,
+    Number,
+}
+
+fn char_class(c: char) -> CharClass {
+    if c.is_lowercase() {
+        CharClass::Lower
+    } else if c.is_uppercase() {
+        CharClass::Upper
+    } else if c.is_numeric() {
+        CharClass::Number
+    } else if c.is_alphabetic() {
+        CharClass::Letter
+    } else {
+        CharClass::NonWord
+    }
+}
+
+/// Return the bonus for matching a character of class CLASS which
+/// follows one of class PREV.
+fn bonus(prev: CharClass, class: CharClass) -> i64 {
+    match (prev, class) {
+        (CharClass::NonWord, CharClass::NonWord) => BONUS_NON_WORD,
+        (CharClass::NonWord, _) => BONUS_BOUNDARY,
+        (CharClass::Lower, CharClass::Upper) => BONUS_CAMEL,
+        (CharClass::Number, CharClass::Number) => 0,
+        (_, CharClass::Number) => BONUS_CAMEL,
+        (_, CharClass::NonWord) => BONUS_NON_WORD,
+        _ => 0,
+    }
+}
+
+/// Return the lower case of C, if it is a single character.
+fn fold_case(c: char) -> char {
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(l), None) => l,
+        _ => c,
+    }
+}
+
+/// Return the text of STRING.  Each byte of a unibyte string is a
+/// character, and raw bytes in a multibyte string match nothing.
+fn fuzzy_text(string: LispStringRef) -> Vec<char> {
+    if string.is_multibyte() {
+        String::from_utf8_lossy(string.as_slice()).chars().collect()
+    } else {
+        string.as_slice().iter().map(|&b| char::from(b)).collect()
+    }
+}
+
+/// Return the best score of the matches of QUERY in CANDIDATE, or None
+/// if there is none.  Case is ignored unless QUERY contains upper-case
+/// letters.
+///
+/// `scores[j]` is the best score of the query so far with its last
+/// character matched at position J of CANDIDATE, and `runs[j]` the
+/// bonus of the first match of the run of consecutive matches ending
+/// there.
+fn fuzzy_match(candidate: &[char], query: &[char]) -> Option<i64> {
+    if query.is_empty() {
+        return Some(0);
+    }
+    let ignore_case = !query.iter().any(|c| c.is_uppercase());
+    let fold = |c: char| if ignore_case { fold_case(c) } else { c };
+
+    let mut prev_class = CharClass::NonWord;
+    let bonuses: Vec<i64> = candidate
+        .iter()
+        .map(|&c| {
+            let class = char_class(c);
+            let bonus = bonus(prev_class, class);
+            prev_class = class;
+            bonus
+        })
+        .collect();
+    let candidate: Vec<char> = candidate.iter().map(|&c| fold(c)).collect();
+
+    let mut scores: Vec<Option<i64>> = vec![None; candidate.len()];
+    let mut runs = vec![0; candidate.len()];
+    for (i, &q) in query.iter().enumerate() {
+        let q = fold(q);
+        let mut next_scores = vec![None; candidate.len()];
+        let mut next_runs = vec![0; candidate.len()];
+        // The best score of a match of the previous query character
+        // before J - 1, with the gap up to J taken off.
+        let mut gapped: Option<i64> = None;
+        for j in 0..candidate.len() {
+            if j > 1 && i > 0 {
+                let extended = gapped.map(|score| score + SCORE_GAP_EXTENSION);
+                let started = scores[j - 2].map(|score| score + SCORE_GAP_START);
+                gapped = extended.max(started);
+            }
+            if candidate[j] != q {
+                continue;
+            }
+            if i == 0 {
+                next_scores[j] = Some(SCORE_MATCH + bonuses[j] * BONUS_FIRST_CHAR_MULTIPLIER);
+                next_runs[j] = bonuses[j];
+                continue;
+            }
+            let after_gap = gapped.map(|score| (score + SCORE_MATCH + bonuses[j], bonuses[j]));
+            let consecutive = if j > 0 {
+                scores[j - 1].map(|score| {
+                    let run = runs[j - 1].max(bonuses[j]);
+                    let bonus = bonuses[j].max(run).max(BONUS_CONSECUTIVE);
+                    (score + SCORE_MATCH + bonus, run)
+                })
+            } else {
+                None
+            };
+            if let Some((score, run)) = after_gap.max(consecutive) {
+                next_scores[j] = Some(score);
+                next_runs[j] = run;
+            }
+        }
+        scores = next_scores;
+        runs = next_runs;
+    }
+    scores.into_iter().max().and_then(|score| score)
+}
+
+/// Return the score of CANDIDATE as a match for QUERY, or nil if it
+/// doesn't match.
+/// CANDIDATE matches if the characters of QUERY occur in it in order,
+/// not necessarily consecutively.  The higher the score, the better the
+/// match: consecutive characters, and characters at the beginning of
+/// words or after a change from lower to upper case, score higher, and
+/// gaps between the matched characters lower, as in fzf.
+///
+/// Case is ignored unless QUERY contains upper-case letters.  An empty
+/// QUERY matches everything with the score 0.
+#[lisp_fn]
+pub fn fuzzy_score(candidate: LispStringRef, query: LispStringRef) -> Option<EmacsInt> {
+    fuzzy_match(&fuzzy_text(candidate), &fuzzy_text(query)).map(|score| score as EmacsInt)
+}
+
+/// Return the elements of CANDIDATES which match QUERY, best first.
+/// CANDIDATES is a list or vector of strings.  The candidates are
+/// matched and scored as by `fuzzy-score', which see, and those with
+/// equal scores are kept in their original order.  The candidates are
+/// scored in parallel, so this is much faster than calling
+/// `fuzzy-score' for each of them.
+#[lisp_fn]
+pub fn fuzzy_filter(candidates: LispObject, query: LispStringRef) -> LispObject {
+    let elements: Vec<LispObject> = match candidates.as_vector() {
+        Some(vector) => vector.iter().collect(),
+        None => candidates
+            .iter_cars(LispConsEndChecks::on, LispConsCircularChecks::on)
+            .collect(),
+    };
+    let texts: Vec<Vec<char>> = elements
+        .iter()
+        .map(|element| fuzzy_text(element.as_string_or_error()))
+        .collect();
+
+    let query = fuzzy_text(query);
+    let mut scores: Vec<(usize, i64)> = texts
+        .par_iter()
+        .enumerate()
+        .map(|(i, text)| fuzzy_match(text, &query).map(|score| (i, score)))
+        .flatten()
+        .collect();
+    // The sort is stable, so equal scores keep the order of CANDIDATES.
+    scores.sort_by(|a, b| b.1.cmp(&a.1));
+
+    let matches: Vec<LispObject> = scores.into_iter().map(|(i, _)| elements[i]).collect();
+    list(&matches)
+}
+
+include!(concat!(env!("OUT_DIR"), "/fuzzy_exports.rs"));
@@ -0,0 +1,232 @@
+//! Searching the files of a directory tree, as grep does.
+//!
+//! The files are found and searched with the libraries of ripgrep, so
+//! that ignored files are skipped as ripgrep skips them.  Searches can
+//! run in a background thread, whose results are collected by polling.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use grep_matcher::Matcher;
+use grep_regex::{RegexMatcher, RegexMatcherBuilder};
+use grep_searcher::{sinks::Lossy, BinaryDetection, SearcherBuilder};
+use ignore::WalkBuilder;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    fileio::{encoded_file_path, path_file_name},
+    json::json_make_string,
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::list,
+    multibyte::LispStringRef,
+    remacs_sys::{encode_file_name, EmacsInt},
+    remacs_sys::{Fexpand_file_name, Qinvalid_regexp},
+    search::translate_regexp,
+    threads::ThreadState,
+};
+
+/// A matching line: the file, the line number, the column of the first
+/// match in the line, and the text of the line.
+type GrepResult = (PathBuf, u64, usize, String);
+
+/// The state of a search, shared with the thread running it.
+#[derive(Default)]
+struct DirectorySearch {
+    /// The results which haven't been collected yet.
+    results: Mutex<Vec<GrepResult>>,
+    finished: AtomicBool,
+    cancelled: AtomicBool,
+}
+
+lazy_static! {
+    /// The searches running in the background, by their numbers.
+    static ref DIRECTORY_SEARCHES: Mutex<HashMap<EmacsInt, Arc<DirectorySearch>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// The number of the next background search.
+static NEXT_DIRECTORY_SEARCH: AtomicUsize = AtomicUsize::new(1);
+
+/// Return a matcher for the Emacs regexp REGEXP, which ignores case if
+/// `case-fold-search' is non-nil.
+fn grep_matcher(regexp: LispStringRef) -> RegexMatcher {
+    let pattern = match std::str::from_utf8(regexp.as_slice()) {
+        Ok(pattern) if regexp.is_multibyte() || pattern.is_ascii() => pattern,
+        _ => xsignal!(Qinvalid_regexp, json_make_string("Raw bytes in regexp")),
+    };
+    let translated = match translate_regexp(pattern) {
+        Some(translated) => translated,
+        None => xsignal!(
+            Qinvalid_regexp,
+            json_make_string("Regexp uses constructs unsupported by directory-search")
+        ),
+    };
+    let case_fold = ThreadState::current_buffer()
+        .case_fold_search()
+        .is_not_nil();
+    match RegexMatcherBuilder::new()
+        .case_insensitive(case_fold)
+        .line_terminator(Some(b'\n'))
+        .build(&translated.source)
+    {
+        Ok(matcher) => matcher,
+        Err(error) => xsignal!(Qinvalid_regexp, json_make_string(&error.to_string())),
+    }
+}
+
+/// Return the path of the directory DIR, relative to the default
+/// directory of the current buffer.
+fn grep_directory(dir: LispStringRef) -> PathBuf {
+    let dir = unsafe {
+        Fexpand_file_name(
+            LispObject::from(dir),
+            ThreadState::current_buffer().directory_,
+        )
+    };
+    let path = encoded_file_path(unsafe { encode_file_name(dir) });
+    if !path.is_dir() {
+        error!("Not a directory: {}", path.display());
+    }
+    path
+}
+
+/// Search the files under DIR for lines matched by MATCHER, adding the
+/// results to SEARCH, until the search is finished or cancelled.
+fn grep_files(matcher: &RegexMatcher, dir: &Path, search: &DirectorySearch) {
+    let mut searcher = SearcherBuilder::new()
+        .line_number(true)
+        .binary_detection(BinaryDetection::quit(b'\0'))
+        .build();
+    for entry in WalkBuilder::new(dir).build() {
+        if search.cancelled.load(Ordering::Relaxed) {
+            break;
+        }
+        // Unreadable directories and files are skipped.
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if !entry.file_type().map_or(false, |t| t.is_file()) {
+            continue;
+        }
+        let mut results = Vec::new();
+        let _ = searcher.search_path(
+            matcher,
+            entry.path(),
+            Lossy(|line_number, line| {
+                let line = line.trim_end_matches(|c| c == '\n' || c == '\r');
+                let column = match matcher.find(line.as_bytes()) {
+                    Ok(Some(found)) => line[..found.start()].chars().count(),
+                    _ => 0,
+                };
+                results.push((
+                    entry.path().to_path_buf(),
+                    line_number,
+                    column,
+                    line.to_string(),
+                ));
+                Ok(!search.cancelled.load(Ordering::Relaxed))
+            }),
+        );
+        search.results.lock().unwrap().extend(results);
+    }
+    search.finished.store(true, Ordering::Release);
+}
+
+/// Convert RESULTS to a list of Lisp results.
+fn grep_results_to_lisp(results: Vec<GrepResult>) -> LispObject {
+    let results: Vec<LispObject> = results
+        .into_iter()
+        .map(|(file, line, column, text)| {
+            list!(
+                path_file_name(&file),
+                LispObject::from(line as EmacsInt),
+                LispObject::from(column as EmacsInt),
+                json_make_string(&text)
+            )
+        })
+        .collect();
+    list(&results)
+}
+
+/// Return the background search numbered SEARCH.
+fn directory_search_ref(search: EmacsInt) -> Arc<DirectorySearch> {
+    let state = DIRECTORY_SEARCHES.lock().unwrap().get(&search).cloned();
+    match state {
+        Some(state) => state,
+        None => error!("No directory search numbered {}", search),
+    }
+}
+
+/// Search the files in the directory tree DIR for lines matching REGEXP.
+/// Return a list with an element (FILE LINE COLUMN TEXT) for each
+/// matching line, where FILE is the absolute file name, LINE the line
+/// number, counting from 1, COLUMN the column where the first match in
+/// the line starts, counting from 0, and TEXT the contents of the line.
+///
+/// As with ripgrep, files and directories ignored by `.gitignore',
+/// `.ignore' and similar files, and hidden ones, are skipped, and so are
+/// binary files.  Files are read as UTF-8, and invalid sequences are
+/// replaced.  Matching ignores case if `case-fold-search' is non-nil.
+/// REGEXP must not use constructs which depend on the syntax table, nor
+/// back-references; see `re-search-rust-engine'.
+///
+/// If BACKGROUND is non-nil, search in a background thread and return a
+/// number identifying the search, to be passed to
+/// `directory-search-results' and `directory-search-cancel'.
+#[lisp_fn(min = "2")]
+pub fn directory_search(regexp: LispStringRef, dir: LispStringRef, background: bool) -> LispObject {
+    let matcher = grep_matcher(regexp);
+    let dir = grep_directory(dir);
+
+    if !background {
+        let search = DirectorySearch::default();
+        grep_files(&matcher, &dir, &search);
+        return grep_results_to_lisp(search.results.into_inner().unwrap());
+    }
+
+    let number = NEXT_DIRECTORY_SEARCH.fetch_add(1, Ordering::Relaxed) as EmacsInt;
+    let search = Arc::new(DirectorySearch::default());
+    DIRECTORY_SEARCHES
+        .lock()
+        .unwrap()
+        .insert(number, Arc::clone(&search));
+    thread::spawn(move || grep_files(&matcher, &dir, &search));
+    LispObject::from(number)
+}
+
+/// Collect the results of the background directory search SEARCH.
+/// SEARCH is a number returned by `directory-search'.  Return a cons
+/// (FINISHED . RESULTS), where RESULTS is a list of the results found
+/// since the last call, in the form returned by `directory-search', and
+/// FINISHED is t if the search is over.  Once the search is over, its
+/// number is no longer valid.
+#[lisp_fn]
+pub fn directory_search_results(search: EmacsInt) -> LispObject {
+    let state = directory_search_ref(search);
+    // Take the results only after checking whether the search is over,
+    // so that none are lost.
+    let finished = state.finished.load(Ordering::Acquire);
+    let results = std::mem::replace(&mut *state.results.lock().unwrap(), Vec::new());
+    if finished {
+        DIRECTORY_SEARCHES.lock().unwrap().remove(&search);
+    }
+    LispObject::cons(LispObject::from(finished), grep_results_to_lisp(results))
+}
+
+/// Stop the background directory search SEARCH, discarding its results.
+/// SEARCH is a number returned by `directory-search', which is no longer
+/// valid afterwards.
+#[lisp_fn]
+pub fn directory_search_cancel(search: EmacsInt) {
+    let state = directory_search_ref(search);
+    state.cancelled.store(true, Ordering::Relaxed);
+    DIRECTORY_SEARCHES.lock().unwrap().remove(&search);
+}
+
+include!(concat!(env!("OUT_DIR"), "/grep_exports.rs"));
@@ -6,17 +6,19 @@ use std::ptr;
 use remacs_macros::lisp_fn;
 
 use crate::{
+    collections::{ordered_hash_table_map, ordered_hash_table_p, sorted_map_map, sorted_map_p},
     data::aref,
     lisp::defsubr,
     lisp::{ExternalPtr, LispObject},
     lists::{list, put},
+    persistent::{pmap_map, pmap_p},
     remacs_sys::{
         gc_aset, hash_clear, hash_lookup, hash_put, hash_remove_from_table, Fcopy_sequence,
     },
     remacs_sys::{
         pvec_type, EmacsDouble, EmacsInt, EmacsUint, Lisp_Hash_Table, Lisp_Type, CHECK_IMPURE,
     },
-    remacs_sys::{Qhash_table_p, Qhash_table_test},
+    remacs_sys::{Qhash_table_p, Qhash_table_test, Qnil},
     symbols::LispSymbolRef,
 };
 
@@ -297,10 +299,22 @@ pub fn remhash(key: LispObject, hash_table: LispHashTableRef) {
 /// Call FUNCTION for all entries in hash table TABLE.
 /// FUNCTION is called with two arguments, KEY and VALUE.
 /// `maphash' always returns nil.
+///
+/// TABLE can also be an ordered hash table, whose entries are visited
+/// in the order they were added, a sorted map, whose entries are
+/// visited in the order of their keys, or a persistent map.
 #[lisp_fn]
-pub fn maphash(function: LispObject, hash_table: LispHashTableRef) {
-    for (key, value) in hash_table.iter() {
-        call!(function, key, value);
+pub fn maphash(function: LispObject, table: LispObject) {
+    if ordered_hash_table_p(table) {
+        ordered_hash_table_map(function, table);
+    } else if sorted_map_p(table) {
+        sorted_map_map(function, table, Qnil, Qnil);
+    } else if pmap_p(table) {
+        pmap_map(function, table);
+    } else {
+        for (key, value) in table.as_hash_table_or_error().iter() {
+            call!(function, key, value);
+        }
     }
 }
 
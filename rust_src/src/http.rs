@@ -0,0 +1,558 @@
+//! An HTTP client.
+//!
+//! `http-request' sends a request on a separate thread, with reqwest,
+//! which speaks HTTP/1.1 and keeps connections to the hosts it has
+//! talked to open for the next requests.  The response is handed
+//! back to Emacs from a timer as it arrives, a chunk at a time, so that
+//! large bodies can be streamed into a buffer.  Compressed bodies are
+//! decompressed with the decoders of the decompress module.
+
+use std::collections::HashMap;
+use std::io::{self, BufReader, Read};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libc::c_char;
+
+use reqwest::header::{ACCEPT_ENCODING, CONTENT_ENCODING};
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    buffers::set_buffer,
+    decompress::create_content_decoder,
+    eval::unbind_to,
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::{plist_get, LispConsCircularChecks, LispConsEndChecks},
+    multibyte::LispStringRef,
+    obarray::intern,
+    remacs_sys::{insert_from_string, make_unibyte_string, maybe_quit},
+    remacs_sys::{record_unwind_current_buffer, set_point, EmacsDouble, EmacsInt, Qnil},
+    threads::{c_specpdl_index, ThreadState},
+};
+
+/// The number of milliseconds between checks on requests in progress.
+const HTTP_POLL_MS: u64 = 50;
+
+/// The size of the chunks response bodies are read in.
+const HTTP_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The content encodings asked for, unless the request says otherwise.
+const HTTP_ACCEPT_ENCODING: &str = "gzip, br";
+
+lazy_static! {
+    /// The client requests are sent with, so that they share its pool of
+    /// connections.
+    static ref HTTP_CLIENT: reqwest::Client = http_client(None).expect("Can't make an HTTP client");
+
+    /// The requests which haven't finished, by number.
+    static ref HTTP_JOBS: Mutex<HttpJobs> = Mutex::new(HttpJobs {
+        next_id: 0,
+        jobs: HashMap::new(),
+    });
+}
+
+struct HttpJobs {
+    next_id: EmacsInt,
+    jobs: HashMap<EmacsInt, HttpJob>,
+}
+
+/// A request in progress, and what has been received of its response
+/// so far.
+struct HttpJob {
+    events: Receiver<HttpEvent>,
+    status: Option<u16>,
+    headers: Vec<(String, Vec<u8>)>,
+    url: Option<String>,
+    body: Vec<u8>,
+}
+
+/// What the thread sending a request reports.
+enum HttpEvent {
+    Headers {
+        status: u16,
+        headers: Vec<(String, Vec<u8>)>,
+        url: String,
+    },
+    Data(Vec<u8>),
+    Done,
+    Failed(String),
+}
+
+/// A request, as it is sent.
+struct HttpRequest {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Option<Vec<u8>>,
+    timeout: Option<Duration>,
+}
+
+fn http_error(error: reqwest::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, error)
+}
+
+/// Return a client which gives up on connecting, reading or writing
+/// after TIMEOUT, or after reqwest's default of 30 seconds.  Bodies are
+/// decoded here rather than by reqwest, which only knows gzip.
+fn http_client(timeout: Option<Duration>) -> reqwest::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder().gzip(false);
+    if let Some(timeout) = timeout {
+        builder = builder.timeout(timeout);
+    }
+    builder.build()
+}
+
+/// Send REQUEST, and report the response through EVENTS.  Stop early if
+/// nobody listens to them anymore, because the request was cancelled.
+fn perform_request(request: HttpRequest, events: &Sender<HttpEvent>) -> io::Result<()> {
+    let method = reqwest::Method::from_bytes(request.method.as_bytes())
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+    // reqwest can only time out a whole client, so a request with a
+    // timeout of its own gets one of its own.
+    let client = match request.timeout {
+        Some(timeout) => http_client(Some(timeout)).map_err(http_error)?,
+        None => HTTP_CLIENT.clone(),
+    };
+    let mut builder = client.request(method, request.url.as_str());
+
+    // Bodies are only decoded if the encodings were chosen here.
+    let decode = !request
+        .headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case(ACCEPT_ENCODING.as_str()));
+    if decode {
+        builder = builder.header(ACCEPT_ENCODING, HTTP_ACCEPT_ENCODING);
+    }
+    for (name, value) in request.headers {
+        builder = builder.header(name.as_str(), value.as_str());
+    }
+    if let Some(body) = request.body {
+        builder = builder.body(body);
+    }
+
+    let response = builder.send().map_err(http_error)?;
+    let encoding = response
+        .headers()
+        .get(CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .filter(|_| decode)
+        .unwrap_or("identity")
+        .to_string();
+    let headers = response
+        .headers()
+        .iter()
+        .map(|(name, value)| (name.as_str().to_string(), value.as_bytes().to_vec()))
+        .collect();
+    let head = HttpEvent::Headers {
+        status: response.status().as_u16(),
+        headers,
+        url: response.url().to_string(),
+    };
+    if events.send(head).is_err() {
+        return Ok(());
+    }
+
+    let mut body = create_content_decoder(&encoding, BufReader::new(response))?;
+    let mut chunk = vec![0; HTTP_CHUNK_SIZE];
+    loop {
+        let read = body.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        if events
+            .send(HttpEvent::Data(chunk[..read].to_vec()))
+            .is_err()
+        {
+            return Ok(());
+        }
+    }
+    let _ = events.send(HttpEvent::Done);
+    Ok(())
+}
+
+fn unibyte_string(bytes: &[u8]) -> LispObject {
+    unsafe { make_unibyte_string(bytes.as_ptr() as *const c_char, bytes.len() as isize) }
+}
+
+/// Return the request OPTIONS ask for to URL, as documented in
+/// `http-request'.
+fn parse_request(url: LispStringRef, options: LispObject) -> HttpRequest {
+    let option = |name| plist_get(options, intern(name).into());
+
+    let method = option(":method");
+    let method = if method.is_nil() {
+        "GET".to_string()
+    } else if let Some(symbol) = method.as_symbol() {
+        symbol.symbol_name().as_string_or_error().to_string()
+    } else {
+        method.as_string_or_error().to_string()
+    };
+
+    let headers = option(":headers")
+        .iter_cars(LispConsEndChecks::on, LispConsCircularChecks::safe)
+        .map(|header| {
+            let (name, value) = header.as_cons_or_error().as_tuple();
+            (
+                name.as_string_or_error().to_string(),
+                value.as_string_or_error().to_string(),
+            )
+        })
+        .collect();
+
+    let data = option(":data");
+    let body = if data.is_nil() {
+        None
+    } else {
+        Some(data.as_string_or_error().as_slice().to_vec())
+    };
+
+    let timeout = option(":timeout");
+    let timeout = if timeout.is_nil() {
+        None
+    } else {
+        let seconds = timeout.any_to_float_or_error();
+        if seconds < 0.0 {
+            error!("Invalid timeout: {}", seconds);
+        }
+        Some(Duration::from_millis((seconds * 1000.0) as u64))
+    };
+
+    HttpRequest {
+        method,
+        url: url.to_string(),
+        headers,
+        body,
+        timeout,
+    }
+}
+
+/// A request, as Lisp sees it: a list (http-request ID BUFFER CALLBACK
+/// RESPONSE), where RESPONSE is nil until the request is finished.
+struct Handle {
+    id: EmacsInt,
+    buffer: LispObject,
+    callback: LispObject,
+    response: LispObject,
+    response_cell: LispObject,
+}
+
+fn is_handle(object: LispObject) -> bool {
+    object.as_cons().map_or(false, |cons| {
+        cons.car().eq(intern("http-request").into())
+            && cons
+                .cdr()
+                .as_cons()
+                .map_or(false, |id| id.car().is_fixnum())
+    })
+}
+
+fn parse_handle(handle: LispObject) -> Handle {
+    if !is_handle(handle) {
+        wrong_type!(intern("http-request-p").into(), handle);
+    }
+    let fields: Vec<LispObject> = handle
+        .iter_tails(LispConsEndChecks::on, LispConsCircularChecks::safe)
+        .map(LispObject::from)
+        .collect();
+    if fields.len() != 5 {
+        wrong_type!(intern("http-request-p").into(), handle);
+    }
+    let car = |tail: LispObject| tail.as_cons_or_error().car();
+    Handle {
+        id: car(fields[1]).as_fixnum_or_error(),
+        buffer: car(fields[2]),
+        callback: car(fields[3]),
+        response: car(fields[4]),
+        response_cell: fields[4],
+    }
+}
+
+/// Insert BYTES at the end of BUFFER, as raw bytes, leaving its point
+/// where it is, unless point is at the end, where it stays.
+fn insert_at_end(buffer: LispObject, bytes: &[u8]) {
+    let count = c_specpdl_index();
+    unsafe { record_unwind_current_buffer() };
+    set_buffer(buffer.into());
+
+    let current_buffer = ThreadState::current_buffer();
+    let opoint = current_buffer.pt;
+    let at_end = opoint == current_buffer.z();
+    let string = unibyte_string(bytes);
+    let string_ref = string.as_string_or_error();
+    unsafe {
+        set_point(current_buffer.z());
+        insert_from_string(
+            string,
+            0,
+            0,
+            string_ref.len_chars(),
+            string_ref.len_bytes(),
+            false,
+        );
+        if !at_end {
+            set_point(opoint);
+        }
+    }
+
+    unbind_to(count, Qnil);
+}
+
+/// Forget request HANDLE, which is finished, and return its response,
+/// which is also recorded in HANDLE.  Call its callback with it.
+fn finish_request(handle: &Handle, error: Option<String>) -> LispObject {
+    let job = HTTP_JOBS.lock().unwrap().jobs.remove(&handle.id);
+    let job = match job {
+        Some(job) => job,
+        None => return Qnil,
+    };
+
+    let headers: Vec<LispObject> = job
+        .headers
+        .iter()
+        .map(|(name, value)| {
+            LispObject::cons(LispObject::from(name.as_str()), unibyte_string(value))
+        })
+        .collect();
+    let body = if handle.buffer.is_nil() && error.is_none() {
+        unibyte_string(&job.body)
+    } else {
+        Qnil
+    };
+    let response = list!(
+        LispObject::from(intern(":status")),
+        job.status
+            .map_or(Qnil, |status| LispObject::from(EmacsInt::from(status))),
+        LispObject::from(intern(":headers")),
+        LispObject::from(headers),
+        LispObject::from(intern(":url")),
+        job.url
+            .as_ref()
+            .map_or(Qnil, |url| LispObject::from(url.as_str())),
+        LispObject::from(intern(":body")),
+        body,
+        LispObject::from(intern(":error")),
+        error.map_or(Qnil, |error| LispObject::from(error.as_str()))
+    );
+
+    handle.response_cell.as_cons_or_error().set_car(response);
+    if handle.callback.is_not_nil() {
+        call!(handle.callback, response);
+    }
+    response
+}
+
+/// Handle what has been received for request HANDLE since it was last
+/// looked at, waiting up to WAIT for something if there is nothing.
+/// Return the response if the request is finished, and None otherwise.
+fn handle_events(handle: &Handle, wait: Option<Duration>) -> Option<LispObject> {
+    let events = {
+        let jobs = HTTP_JOBS.lock().unwrap();
+        let job = jobs.jobs.get(&handle.id)?;
+        let mut events = Vec::new();
+        loop {
+            match job.events.try_recv() {
+                Ok(event) => events.push(event),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    events.push(HttpEvent::Failed("Request thread exited".to_string()));
+                    break;
+                }
+            }
+        }
+        if let (true, Some(wait)) = (events.is_empty(), wait) {
+            match job.events.recv_timeout(wait) {
+                Ok(event) => events.push(event),
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => {
+                    events.push(HttpEvent::Failed("Request thread exited".to_string()))
+                }
+            }
+        }
+        events
+    };
+
+    for event in events {
+        match event {
+            HttpEvent::Headers {
+                status,
+                headers,
+                url,
+            } => {
+                if let Some(job) = HTTP_JOBS.lock().unwrap().jobs.get_mut(&handle.id) {
+                    job.status = Some(status);
+                    job.headers = headers;
+                    job.url = Some(url);
+                }
+            }
+            HttpEvent::Data(ref bytes) if handle.buffer.as_live_buffer().is_some() => {
+                insert_at_end(handle.buffer, bytes)
+            }
+            HttpEvent::Data(bytes) => {
+                if let Some(job) = HTTP_JOBS.lock().unwrap().jobs.get_mut(&handle.id) {
+                    job.body.extend_from_slice(&bytes);
+                }
+            }
+            HttpEvent::Done => return Some(finish_request(handle, None)),
+            HttpEvent::Failed(error) => return Some(finish_request(handle, Some(error))),
+        }
+    }
+    None
+}
+
+fn schedule_http_poll(handle: LispObject) {
+    call!(
+        LispObject::from(intern("run-with-timer")),
+        LispObject::from_float(HTTP_POLL_MS as EmacsDouble / 1000.0),
+        Qnil,
+        LispObject::from(intern("http--request-poll")),
+        handle
+    );
+}
+
+/// Send an HTTP request to URL, and return a handle on it at once.
+/// The request is sent on a separate thread, over HTTP/1.1, reusing a
+/// connection to the same host if one is open, unless the request has a
+/// timeout.  OPTIONS is a property list of these properties:
+///
+/// :method is the method, a string or symbol like \"POST\"; the default
+/// is \"GET\".
+///
+/// :headers is an alist of the (NAME . VALUE) strings of the headers to
+/// send.  Unless there is an Accept-Encoding header among them, gzip
+/// and brotli compressed bodies are asked for, and decompressed as they
+/// arrive.
+///
+/// :data is a string, the body of the request.  A multibyte string is
+/// sent encoded in UTF-8.
+///
+/// :timeout is how many seconds to wait at most to connect, and for
+/// each read and write; the default is 30.
+///
+/// :buffer is a buffer to insert the body of the response at the end of
+/// as it arrives, as raw bytes, rather than collecting it in a string.
+///
+/// :callback is a function to call with the response, when the request
+/// is finished.
+///
+/// The response is a property list with the properties :status, the
+/// status code, :headers, an alist of the (NAME . VALUE) of the header
+/// fields, with NAME in lower case, :url, the URL the response came
+/// from after redirections, :body, the body as a unibyte string, or nil
+/// if it went to a buffer, and :error, a message saying why the request
+/// failed, or nil.
+///
+/// The handle can be passed to `http-request-wait', to wait for the
+/// response, and to `http-request-cancel'.
+/// usage: (http-request URL &rest OPTIONS)
+#[lisp_fn(min = "1")]
+pub fn http_request(args: &mut [LispObject]) -> LispObject {
+    let url = args[0].as_string_or_error();
+    let options = LispObject::from(args[1..].to_vec());
+    let request = parse_request(url, options);
+    let buffer = plist_get(options, intern(":buffer").into());
+    if buffer.is_not_nil() && buffer.as_live_buffer().is_none() {
+        error!("Not a live buffer");
+    }
+    let callback = plist_get(options, intern(":callback").into());
+
+    let (sender, receiver) = channel();
+    let id = {
+        let mut jobs = HTTP_JOBS.lock().unwrap();
+        jobs.next_id += 1;
+        let id = jobs.next_id;
+        jobs.jobs.insert(
+            id,
+            HttpJob {
+                events: receiver,
+                status: None,
+                headers: Vec::new(),
+                url: None,
+                body: Vec::new(),
+            },
+        );
+        id
+    };
+    thread::spawn(move || {
+        if let Err(error) = perform_request(request, &sender) {
+            let _ = sender.send(HttpEvent::Failed(error.to_string()));
+        }
+    });
+
+    let handle = list!(
+        LispObject::from(intern("http-request")),
+        LispObject::from(id),
+        buffer,
+        callback,
+        Qnil
+    );
+    schedule_http_poll(handle);
+    handle
+}
+
+/// Return t if OBJECT is a handle returned by `http-request'.
+#[lisp_fn]
+pub fn http_request_p(object: LispObject) -> bool {
+    is_handle(object)
+}
+
+/// Check on the request HANDLE, and reschedule the check if it isn't
+/// finished.
+#[lisp_fn(name = "http--request-poll")]
+pub fn http_request_poll(handle: LispObject) {
+    let request = parse_handle(handle);
+    if handle_events(&request, None).is_none()
+        && HTTP_JOBS.lock().unwrap().jobs.contains_key(&request.id)
+    {
+        schedule_http_poll(handle);
+    }
+}
+
+/// Wait for the request HANDLE to finish, and return its response.
+/// The response is as described in `http-request', whose callback is
+/// called first, if it hasn't been yet.  If TIMEOUT, a number of
+/// seconds, is non-nil, return nil if the request isn't finished by
+/// then.  Also return nil if the request was cancelled.  Waiting can be
+/// interrupted with \\[keyboard-quit].
+#[lisp_fn(min = "1")]
+pub fn http_request_wait(handle: LispObject, timeout: LispObject) -> LispObject {
+    let request = parse_handle(handle);
+    if request.response.is_not_nil() {
+        return request.response;
+    }
+    let deadline = if timeout.is_nil() {
+        None
+    } else {
+        let seconds = timeout.any_to_float_or_error().max(0.0);
+        Some(Instant::now() + Duration::from_millis((seconds * 1000.0) as u64))
+    };
+
+    loop {
+        if !HTTP_JOBS.lock().unwrap().jobs.contains_key(&request.id) {
+            return Qnil;
+        }
+        if let Some(response) = handle_events(&request, Some(Duration::from_millis(HTTP_POLL_MS))) {
+            return response;
+        }
+        if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+            return Qnil;
+        }
+        unsafe { maybe_quit() };
+    }
+}
+
+/// Cancel the request HANDLE.
+/// Its callback will not be called, and nothing more is inserted in its
+/// buffer.  Return t if the request wasn't finished.
+#[lisp_fn]
+pub fn http_request_cancel(handle: LispObject) -> bool {
+    let request = parse_handle(handle);
+    // The thread sending the request stops once it finds that nobody
+    // listens to it anymore.
+    HTTP_JOBS.lock().unwrap().jobs.remove(&request.id).is_some()
+}
+
+include!(concat!(env!("OUT_DIR"), "/http_exports.rs"));
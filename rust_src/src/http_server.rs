@@ -0,0 +1,436 @@
+//! An HTTP server.
+//!
+//! `make-http-server' listens on a port with tiny_http on a separate
+//! thread, which parses the requests that arrive and reads their
+//! bodies.  Each request is handed to the handler of the server from a
+//! timer, as a plist, and what the handler returns is sent back as the
+//! response, so Lisp never parses HTTP.
+
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use libc::{c_char, c_void};
+
+use percent_encoding::percent_decode;
+use tiny_http::{Header, Request, Response, Server};
+use url::form_urlencoded;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::{plist_get, LispConsCircularChecks, LispConsEndChecks},
+    obarray::intern,
+    objects::Fidentity,
+    remacs_sys::{internal_catch_all, make_unibyte_string, memory_full, Fsignal},
+    remacs_sys::{EmacsDouble, EmacsInt, EmacsUint, Qnil},
+};
+
+/// The number of milliseconds between checks on the requests received,
+/// which is also how long the thread of a server waits for one before
+/// it checks whether the server was stopped.
+const HTTP_SERVER_POLL_MS: u64 = 50;
+
+lazy_static! {
+    /// The running servers, by number.
+    static ref HTTP_SERVERS: Mutex<HttpServers> = Mutex::new(HttpServers {
+        next_id: 0,
+        servers: HashMap::new(),
+    });
+}
+
+struct HttpServers {
+    next_id: EmacsInt,
+    servers: HashMap<EmacsInt, RunningServer>,
+}
+
+/// A server, as the main thread sees it.  Dropping it stops the thread
+/// of the server, which closes its port.
+struct RunningServer {
+    requests: Receiver<Incoming>,
+    _stop: Sender<()>,
+    port: u16,
+}
+
+/// A request the thread of a server received, with its body.
+struct Incoming {
+    request: Request,
+    body: Vec<u8>,
+}
+
+/// Hand the requests SERVER receives to REQUESTS, with their bodies,
+/// until STOP is dropped.
+fn run_server(server: Server, requests: &Sender<Incoming>, stop: &Receiver<()>) {
+    let timeout = Duration::from_millis(HTTP_SERVER_POLL_MS);
+    while let Err(TryRecvError::Empty) = stop.try_recv() {
+        let mut request = match server.recv_timeout(timeout) {
+            Ok(Some(request)) => request,
+            Ok(None) => continue,
+            Err(_) => return,
+        };
+        let mut body = Vec::new();
+        if request.as_reader().read_to_end(&mut body).is_err() {
+            let _ = request.respond(Response::empty(400));
+            continue;
+        }
+        if requests.send(Incoming { request, body }).is_err() {
+            return;
+        }
+    }
+}
+
+fn unibyte_string(bytes: &[u8]) -> LispObject {
+    unsafe { make_unibyte_string(bytes.as_ptr() as *const c_char, bytes.len() as isize) }
+}
+
+/// A server, as Lisp sees it: a list (http-server ID HANDLER).
+struct Handle {
+    id: EmacsInt,
+    handler: LispObject,
+}
+
+fn is_handle(object: LispObject) -> bool {
+    object.as_cons().map_or(false, |cons| {
+        cons.car().eq(intern("http-server").into())
+            && cons
+                .cdr()
+                .as_cons()
+                .map_or(false, |id| id.car().is_fixnum())
+    })
+}
+
+fn parse_handle(server: LispObject) -> Handle {
+    let fields: Vec<LispObject> = if is_handle(server) {
+        server
+            .iter_cars(LispConsEndChecks::on, LispConsCircularChecks::safe)
+            .collect()
+    } else {
+        Vec::new()
+    };
+    if fields.len() != 3 {
+        wrong_type!(intern("http-server-p").into(), server);
+    }
+    Handle {
+        id: fields[1].as_fixnum_or_error(),
+        handler: fields[2],
+    }
+}
+
+fn schedule_http_server_poll(server: LispObject) {
+    call!(
+        LispObject::from(intern("run-with-timer")),
+        LispObject::from_float(HTTP_SERVER_POLL_MS as EmacsDouble / 1000.0),
+        Qnil,
+        LispObject::from(intern("http-server--poll")),
+        server
+    );
+}
+
+/// Return the alist of the (NAME . VALUE) pairs of the query string
+/// QUERY, decoded.
+fn query_alist(query: &str) -> LispObject {
+    form_urlencoded::parse(query.as_bytes())
+        .map(|(name, value)| LispObject::cons(name.as_ref(), value.as_ref()))
+        .collect::<Vec<LispObject>>()
+        .into()
+}
+
+/// Return the request INCOMING as the plist the handler of a server is
+/// called with.
+fn request_plist(incoming: &Incoming) -> LispObject {
+    let request = &incoming.request;
+    let url = request.url();
+    let (path, query) = match url.find('?') {
+        Some(i) => (&url[..i], &url[i + 1..]),
+        None => (url, ""),
+    };
+    let path = percent_decode(path.as_bytes()).decode_utf8_lossy();
+    let headers: LispObject = request
+        .headers()
+        .iter()
+        .map(|header| {
+            LispObject::cons(
+                header.field.as_str().as_str().to_lowercase().as_str(),
+                header.value.as_str(),
+            )
+        })
+        .collect::<Vec<LispObject>>()
+        .into();
+    let remote = request.remote_addr().to_string();
+
+    list!(
+        LispObject::from(intern(":method")),
+        LispObject::from(request.method().as_str()),
+        LispObject::from(intern(":url")),
+        LispObject::from(url),
+        LispObject::from(intern(":path")),
+        LispObject::from(path.as_ref()),
+        LispObject::from(intern(":query")),
+        query_alist(query),
+        LispObject::from(intern(":headers")),
+        headers,
+        LispObject::from(intern(":body")),
+        unibyte_string(&incoming.body),
+        LispObject::from(intern(":remote")),
+        LispObject::from(remote.as_str())
+    )
+}
+
+/// Return the bytes of the string STRING, encoded in UTF-8 unless it is
+/// unibyte.
+fn string_bytes(string: LispObject) -> Vec<u8> {
+    let string = string.as_string_or_error();
+    if string.is_multibyte() {
+        string.to_string().into_bytes()
+    } else {
+        string.as_slice().to_vec()
+    }
+}
+
+/// Return the response to send for VALUE, what the handler of a server
+/// returned.
+fn make_response(value: LispObject) -> Response<std::io::Cursor<Vec<u8>>> {
+    let (status, headers, body) = if value.is_nil() {
+        (404, Qnil, LispObject::from("Not Found"))
+    } else if value.is_string() {
+        (200, Qnil, value)
+    } else {
+        let status = plist_get(value, intern(":status").into());
+        let body = plist_get(value, intern(":body").into());
+        (
+            if status.is_nil() {
+                200
+            } else {
+                status.as_fixnum_or_error()
+            },
+            plist_get(value, intern(":headers").into()),
+            if body.is_nil() {
+                LispObject::from("")
+            } else {
+                body
+            },
+        )
+    };
+    if status < 100 || status > 999 {
+        args_out_of_range!(
+            LispObject::from(status),
+            LispObject::from(100),
+            LispObject::from(999)
+        );
+    }
+
+    let mut response = Response::from_data(string_bytes(body)).with_status_code(status as u16);
+    let mut content_type = false;
+    for header in headers.iter_cars(LispConsEndChecks::on, LispConsCircularChecks::safe) {
+        let (name, value) = header.as_cons_or_error().as_tuple();
+        let name = string_bytes(name);
+        content_type |= name.eq_ignore_ascii_case(&b"content-type"[..]);
+        match Header::from_bytes(&name[..], string_bytes(value)) {
+            Ok(header) => response.add_header(header),
+            Err(()) => error!("Invalid HTTP header {}", String::from_utf8_lossy(&name)),
+        }
+    }
+    if !content_type {
+        response.add_header(
+            Header::from_bytes(&b"Content-Type"[..], &b"text/html; charset=utf-8"[..]).unwrap(),
+        );
+    }
+    response
+}
+
+/// A call of the handler of a server with a request, and the response
+/// to send for what it returned, once there is one.
+struct HandlerCall {
+    handler: LispObject,
+    request: LispObject,
+    response: Option<Response<std::io::Cursor<Vec<u8>>>>,
+}
+
+extern "C" fn handler_callback(data: *mut c_void) -> LispObject {
+    let call = unsafe { &mut *(data as *mut HandlerCall) };
+    call.response = Some(make_response(call!(call.handler, call.request)));
+    Qnil
+}
+
+/// Start an HTTP server on PORT, and return it.
+/// PORT 0 means a port the system picks, which `http-server-port'
+/// returns.  The server listens on the loopback interface only, unless
+/// OPTIONS says otherwise.
+///
+/// HANDLER is a function to call with each request, which is a plist:
+///
+/// (:method METHOD :url URL :path PATH :query QUERY :headers HEADERS
+///  :body BODY :remote REMOTE)
+///
+/// METHOD is a string like "GET", URL the URL requested, PATH its path,
+/// decoded, and QUERY an alist of the (NAME . VALUE) strings of its
+/// query string.  HEADERS is an alist of the (NAME . VALUE) strings of
+/// the headers, with the names in lower case.  BODY is the body of the
+/// request, as a unibyte string.  REMOTE is the address of the client,
+/// as "HOST:PORT".
+///
+/// What HANDLER returns is sent as the response.  A string is sent with
+/// status 200, encoded in UTF-8, as HTML.  nil is sent as 404 Not Found.
+/// Otherwise, it is a plist (:status STATUS :headers HEADERS :body
+/// BODY), with STATUS 200 by default, HEADERS an alist of (NAME .
+/// VALUE) strings, and BODY a string.  If HANDLER signals an error, or
+/// exits nonlocally otherwise, the response is 500 Internal Server
+/// Error, and the error is then signaled again.
+///
+/// OPTIONS is a property list of these properties:
+///
+/// :host is the address to listen on, "127.0.0.1" by default.
+///
+/// HANDLER is called from timers, as the requests arrive.
+/// usage: (make-http-server PORT HANDLER &rest OPTIONS)
+#[lisp_fn(min = "2")]
+pub fn make_http_server(args: &mut [LispObject]) -> LispObject {
+    let port = args[0].as_natnum_or_error();
+    if port > EmacsUint::from(u16::max_value()) {
+        args_out_of_range!(
+            args[0],
+            LispObject::from(0),
+            LispObject::from(EmacsInt::from(u16::max_value()))
+        );
+    }
+    let handler = args[1];
+    let options = LispObject::from(args[2..].to_vec());
+    let host = plist_get(options, intern(":host").into());
+    let host = if host.is_nil() {
+        "127.0.0.1".to_string()
+    } else {
+        host.as_string_or_error().to_string()
+    };
+
+    let server = match Server::http((host.as_str(), port as u16)) {
+        Ok(server) => server,
+        Err(error) => error!("Can't start HTTP server on {}:{}: {}", host, port, error),
+    };
+    let port = server.server_addr().port();
+
+    let (request_sender, requests) = channel();
+    let (stop, stop_receiver) = channel();
+    thread::spawn(move || run_server(server, &request_sender, &stop_receiver));
+    let id = {
+        let mut servers = HTTP_SERVERS.lock().unwrap();
+        servers.next_id += 1;
+        let id = servers.next_id;
+        servers.servers.insert(
+            id,
+            RunningServer {
+                requests,
+                _stop: stop,
+                port,
+            },
+        );
+        id
+    };
+
+    let server = list!(
+        LispObject::from(intern("http-server")),
+        LispObject::from(id),
+        handler
+    );
+    schedule_http_server_poll(server);
+    server
+}
+
+/// Return t if OBJECT is a server returned by `make-http-server'.
+#[lisp_fn]
+pub fn http_server_p(object: LispObject) -> bool {
+    is_handle(object)
+}
+
+/// Return t if SERVER is running.
+#[lisp_fn]
+pub fn http_server_live_p(server: LispObject) -> bool {
+    let id = parse_handle(server).id;
+    HTTP_SERVERS.lock().unwrap().servers.contains_key(&id)
+}
+
+/// Return the port SERVER listens on, or nil if it is stopped.
+#[lisp_fn]
+pub fn http_server_port(server: LispObject) -> Option<EmacsInt> {
+    let id = parse_handle(server).id;
+    HTTP_SERVERS
+        .lock()
+        .unwrap()
+        .servers
+        .get(&id)
+        .map(|server| EmacsInt::from(server.port))
+}
+
+/// Stop SERVER, and close its port.
+/// Requests it received but didn't handle yet get no response.
+#[lisp_fn]
+pub fn delete_http_server(server: LispObject) {
+    let id = parse_handle(server).id;
+    HTTP_SERVERS.lock().unwrap().servers.remove(&id);
+}
+
+/// Hand the requests SERVER received to its handler.
+#[lisp_fn(name = "http-server--poll")]
+pub fn http_server_poll(server: LispObject) {
+    let handle = parse_handle(server);
+    if !HTTP_SERVERS
+        .lock()
+        .unwrap()
+        .servers
+        .contains_key(&handle.id)
+    {
+        return;
+    }
+    // Check again even if a handler signals an error, which ends this
+    // check once its request is answered.
+    schedule_http_server_poll(server);
+
+    loop {
+        // Requests are taken one at a time, so that the server is not
+        // locked while the handler runs.
+        let incoming = {
+            let servers = HTTP_SERVERS.lock().unwrap();
+            let running = match servers.servers.get(&handle.id) {
+                Some(running) => running,
+                None => return,
+            };
+            match running.requests.try_recv() {
+                Ok(incoming) => incoming,
+                Err(_) => return,
+            }
+        };
+
+        // A nonlocal exit from the handler would skip answering the
+        // request, so any is caught, and turned back into a signal once
+        // the request is answered with 500 instead.
+        let mut call = HandlerCall {
+            handler: handle.handler,
+            request: request_plist(&incoming),
+            response: None,
+        };
+        let error = unsafe {
+            internal_catch_all(
+                Some(handler_callback),
+                &mut call as *mut HandlerCall as *mut c_void,
+                Some(Fidentity),
+            )
+        };
+        let response = call
+            .response
+            .unwrap_or_else(|| Response::from_data("Internal Server Error").with_status_code(500));
+        // Send the response from a thread of its own, so that a slow
+        // client doesn't hold up Emacs.
+        thread::spawn(move || incoming.request.respond(response));
+
+        if let Some((symbol, data)) = error.as_cons().map(|c| c.as_tuple()) {
+            unsafe { Fsignal(symbol, data) };
+        } else if error.is_not_nil() {
+            unsafe { memory_full(std::usize::MAX) };
+        }
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/http_server_exports.rs"));
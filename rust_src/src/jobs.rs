@@ -0,0 +1,259 @@
+//! Job control for subprocesses on ptys.
+//!
+//! A shell that `shell-mode' runs on a pty puts each job in a process
+//! group of its own, and gives the pty to the job in the foreground.
+//! The functions here find those groups, and signal a whole job as a
+//! terminal would, so that stopping and continuing jobs from a shell
+//! buffer reaches every process in them.
+
+use std::ffi::CString;
+#[cfg(target_os = "linux")]
+use std::fs;
+
+use libc::{c_char, c_int, pid_t};
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    lisp::defsubr,
+    lisp::LispObject,
+    obarray::intern,
+    process::{get_process, LispProcessRef},
+    remacs_sys::{abbr_to_signal, emacs_get_tty_pgrp, report_file_error, EmacsInt},
+    remacs_sys::{Qnil, Qreal, Qt},
+};
+
+/// A process group of the session of a subprocess.
+struct Job {
+    group: pid_t,
+    stopped: bool,
+    command: Option<String>,
+}
+
+/// Return the number of the signal SIGCODE, an integer or a symbol
+/// whose name is a signal name, as for `signal-process'.
+fn signal_number(sigcode: LispObject) -> c_int {
+    if let Some(signo) = sigcode.as_fixnum() {
+        return signo as c_int;
+    }
+    let name = sigcode
+        .as_symbol_or_error()
+        .symbol_name()
+        .as_string_or_error()
+        .to_string();
+    let signo = CString::new(name.as_str())
+        .map(|abbr| unsafe { abbr_to_signal(abbr.as_ptr()) })
+        .unwrap_or(-1);
+    if signo < 0 {
+        error!("Undefined signal name {}", name);
+    }
+    signo
+}
+
+/// Return the subprocess PROCESS designates, as `get-process' does, if it
+/// is running.
+fn running_subprocess(process: LispObject) -> LispProcessRef {
+    let process = get_process(process).as_process_or_error();
+    if !process.type_.eq(Qreal) {
+        error!(
+            "Process {} is not a subprocess",
+            process.name.as_string_or_error()
+        );
+    }
+    if process.infd < 0 || process.pid <= 0 {
+        error!(
+            "Process {} is not active",
+            process.name.as_string_or_error()
+        );
+    }
+    process
+}
+
+/// Return the process group in the foreground of the pty of PROCESS.
+fn foreground_group(mut process: LispProcessRef) -> Option<pid_t> {
+    if process.tty_name.is_nil() {
+        return None;
+    }
+    match unsafe { emacs_get_tty_pgrp(process.as_mut()) } {
+        -1 => None,
+        group => Some(group),
+    }
+}
+
+/// Return the process groups of the session SESSION, by group, from the
+/// stat files of /proc.  A group is stopped if any process in it is.
+#[cfg(target_os = "linux")]
+fn session_jobs(session: pid_t) -> Vec<Job> {
+    let mut jobs: Vec<Job> = Vec::new();
+    let entries = match fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(_) => return jobs,
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let pid = match entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        {
+            Some(pid) => pid,
+            None => continue,
+        };
+        let stat = match fs::read_to_string(entry.path().join("stat")) {
+            Ok(stat) => stat,
+            Err(_) => continue,
+        };
+        // The command name is in parentheses, and may contain anything,
+        // so the other fields start after the last parenthesis.
+        let (command, fields) = match (stat.find('('), stat.rfind(')')) {
+            (Some(open), Some(close)) if open < close => {
+                (&stat[open + 1..close], &stat[close + 1..])
+            }
+            _ => continue,
+        };
+        let fields: Vec<&str> = fields.split_whitespace().collect();
+        if fields.len() < 4 || fields[3].parse::<pid_t>() != Ok(session) {
+            continue;
+        }
+        let stopped = fields[0] == "T" || fields[0] == "t";
+        let group: pid_t = match fields[2].parse() {
+            Ok(group) => group,
+            Err(_) => continue,
+        };
+
+        match jobs.iter_mut().find(|job| job.group == group) {
+            Some(job) => {
+                job.stopped |= stopped;
+                if pid == group {
+                    job.command = Some(command.to_string());
+                }
+            }
+            None => jobs.push(Job {
+                group,
+                stopped,
+                command: Some(command.to_string()),
+            }),
+        }
+    }
+    jobs.sort_by_key(|job| job.group);
+    jobs
+}
+
+#[cfg(not(target_os = "linux"))]
+fn session_jobs(_session: pid_t) -> Vec<Job> {
+    Vec::new()
+}
+
+/// Return the process group in the foreground of the terminal of PROCESS.
+/// This is the job a shell running on a pty has given the terminal to,
+/// or the group of the shell itself while it reads a command.  Return
+/// nil if PROCESS doesn't run on a pty, or the group can't be found.
+/// PROCESS may be a process, a buffer, the name of a process or buffer,
+/// or nil, indicating the current buffer's process.
+#[lisp_fn(min = "0")]
+pub fn process_foreground_group(process: LispObject) -> Option<EmacsInt> {
+    foreground_group(running_subprocess(process)).map(EmacsInt::from)
+}
+
+/// Return the jobs of the session of PROCESS, such as those of a shell.
+/// Each job is a process group, and is returned as (GROUP :foreground
+/// FOREGROUND :stopped STOPPED :command COMMAND), where FOREGROUND is
+/// non-nil for the job that has the terminal, STOPPED is non-nil if a
+/// process in the job is stopped, and COMMAND is the name of the
+/// program of the job, or nil if it isn't known.  The group of PROCESS
+/// itself is one of them.  PROCESS is as for
+/// `process-foreground-group'.
+///
+/// Systems other than GNU/Linux only tell which job is in the
+/// foreground, which is then the only one returned.
+#[lisp_fn(min = "0")]
+pub fn process_job_groups(process: LispObject) -> LispObject {
+    let process = running_subprocess(process);
+    let foreground = foreground_group(process);
+    let session = unsafe { libc::getsid(process.pid) };
+    let mut jobs = if session < 0 {
+        Vec::new()
+    } else {
+        session_jobs(session)
+    };
+    if jobs.is_empty() {
+        if let Some(group) = foreground {
+            jobs.push(Job {
+                group,
+                stopped: false,
+                command: None,
+            });
+        }
+    }
+
+    jobs.iter()
+        .map(|job| {
+            list!(
+                LispObject::from(EmacsInt::from(job.group)),
+                LispObject::from(intern(":foreground")),
+                LispObject::from(foreground == Some(job.group)),
+                LispObject::from(intern(":stopped")),
+                LispObject::from(job.stopped),
+                LispObject::from(intern(":command")),
+                job.command
+                    .as_ref()
+                    .map_or(Qnil, |command| LispObject::from(command.as_str()))
+            )
+        })
+        .collect::<Vec<LispObject>>()
+        .into()
+}
+
+/// Send the signal SIGCODE to a whole job of the session of PROCESS.
+/// GROUP nil means the job in the foreground of the terminal of PROCESS,
+/// or the group of PROCESS if that can't be found.  GROUP t means the
+/// group of PROCESS itself, such as a shell.  Otherwise, GROUP is the
+/// number of a process group of the session of PROCESS, as from
+/// `process-job-groups'.
+///
+/// SIGCODE may be an integer, or a symbol whose name is a signal name,
+/// as for `signal-process'.  For instance, SIGTSTP stops a job as
+/// `comint-stop-subjob' does, and SIGCONT continues a stopped job in
+/// the background, which the shell notices and reports.  PROCESS is as
+/// for `process-foreground-group'.  Return GROUP's number.
+#[lisp_fn(min = "2")]
+pub fn process_send_signal_group(
+    process: LispObject,
+    sigcode: LispObject,
+    group: LispObject,
+) -> EmacsInt {
+    let signo = signal_number(sigcode);
+    let process = running_subprocess(process);
+    let own_group = unsafe { libc::getpgid(process.pid) };
+    let target = if group.is_nil() {
+        foreground_group(process).unwrap_or(own_group)
+    } else if group.eq(Qt) {
+        own_group
+    } else {
+        let target = group.as_fixnum_or_error() as pid_t;
+        // Don't signal groups that aren't jobs of PROCESS.
+        let session = unsafe { libc::getsid(process.pid) };
+        let in_session = target > 0
+            && (unsafe { libc::getsid(target) } == session
+                || session_jobs(session).iter().any(|job| job.group == target));
+        if !in_session {
+            error!(
+                "Process group {} is not a job of {}",
+                target,
+                process.name.as_string_or_error()
+            );
+        }
+        target
+    };
+
+    if target <= 0 || unsafe { libc::killpg(target, signo) } < 0 {
+        unsafe {
+            report_file_error(
+                "Signaling process group\0".as_ptr() as *const c_char,
+                LispObject::from(EmacsInt::from(target)),
+            )
+        };
+    }
+    EmacsInt::from(target)
+}
+
+include!(concat!(env!("OUT_DIR"), "/jobs_exports.rs"));
@@ -0,0 +1,819 @@
+//! Native JSON parsing and serialization.
+
+use std::collections::HashMap;
+use std::io;
+use std::io::Read;
+use std::sync::Mutex;
+
+use libc::{c_char, c_void, ptrdiff_t};
+use serde::{Deserialize, Serialize};
+use serde_json::{
+    self,
+    error::Category,
+    map::Map,
+    ser::{CharEscape, CompactFormatter, Formatter, Serializer},
+    Number, Value,
+};
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    data::aref,
+    eval::{define_error, put},
+    hashtable::{puthash, LispHashTableRef},
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::{list, LispConsCircularChecks, LispConsEndChecks},
+    multibyte::LispStringRef,
+    numbers::{MOST_NEGATIVE_FIXNUM, MOST_POSITIVE_FIXNUM},
+    obarray::lisp_intern,
+    objects::Fidentity,
+    remacs_sys::{
+        buf_bytepos_to_charpos, code_convert_string, globals, insert1, internal_catch_all,
+        make_string, wrong_choice, EmacsInt, Fmake_finalizer, Fmake_hash_table, Fmake_vector,
+        Frecord, Fsignal,
+    },
+    remacs_sys::{
+        QCfalse, QCfalse_object, QCnull, QCnull_object, QCobject_type, QCsize, QCtest, Qalist,
+        Qequal, Qerror, Qhash_table, Qjson_end_of_file, Qjson_error, Qjson_object_too_deep,
+        Qjson_out_of_memory, Qjson_parse_error, Qjson_parse_string, Qjson_serialize,
+        Qjson_trailing_content, Qjson_value_p, Qnil, Qplist, Qplistp, Qpure, Qside_effect_free,
+        Qstring_without_embedded_nulls_p, Qt, Qutf_8_string_p, Qutf_8_unix,
+    },
+    remacs_sys::{Qclosure, Qjson__reader_release, Qjson_reader, Qjson_reader_p},
+    threads::ThreadState,
+    vectors::recordp,
+};
+
+/// How JSON objects are represented in Lisp.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ObjectType {
+    HashTable,
+    Alist,
+    Plist,
+}
+
+impl ObjectType {
+    /// Return the object type named by VALUE, the argument of an
+    /// `:object-type' keyword.
+    pub fn from_lisp(value: LispObject) -> Self {
+        if value == Qhash_table {
+            ObjectType::HashTable
+        } else if value == Qalist {
+            ObjectType::Alist
+        } else if value == Qplist {
+            ObjectType::Plist
+        } else {
+            unsafe { wrong_choice(list!(Qhash_table, Qalist, Qplist), value) }
+        }
+    }
+}
+
+/// The settings given by the keyword arguments of the JSON functions.
+#[derive(Clone, Copy)]
+pub struct JsonConfig {
+    object_type: ObjectType,
+    null_object: LispObject,
+    false_object: LispObject,
+}
+
+impl JsonConfig {
+    /// Parse the keyword arguments in ARGS.  :object-type is only
+    /// accepted when PARSING, since serialization accepts every
+    /// representation.
+    pub fn from_args(args: &[LispObject], parsing: bool) -> Self {
+        let mut config = JsonConfig {
+            object_type: ObjectType::HashTable,
+            null_object: QCnull,
+            false_object: QCfalse,
+        };
+
+        if args.len() % 2 != 0 {
+            wrong_type!(Qplistp, list(args));
+        }
+
+        for pair in args.chunks(2) {
+            let (key, value) = (pair[0], pair[1]);
+            if parsing && key == QCobject_type {
+                config.object_type = ObjectType::from_lisp(value);
+            } else if key == QCnull_object {
+                config.null_object = value;
+            } else if key == QCfalse_object {
+                config.false_object = value;
+            } else if parsing {
+                let choices = list!(QCobject_type, QCnull_object, QCfalse_object);
+                unsafe { wrong_choice(choices, key) };
+            } else {
+                unsafe { wrong_choice(list!(QCnull_object, QCfalse_object), key) };
+            }
+        }
+        config
+    }
+}
+
+/// Increment the Lisp evaluation depth before descending into a JSON
+/// array or object, so that cyclic structures are caught.  The depth is
+/// restored by `leave_nested` or, after a signal, by the handler.
+fn enter_nested() {
+    let mut current_thread = ThreadState::current_thread();
+    current_thread.m_lisp_eval_depth += 1;
+    if current_thread.m_lisp_eval_depth > unsafe { globals.max_lisp_eval_depth } {
+        xsignal!(Qjson_object_too_deep);
+    }
+}
+
+fn leave_nested() {
+    ThreadState::current_thread().m_lisp_eval_depth -= 1;
+}
+
+/// Return the UTF-8 representation of STRING, signaling an error if it
+/// isn't a sequence of Unicode scalar values.
+pub fn json_encode(string: LispStringRef) -> String {
+    let encoded = unsafe { code_convert_string(string.into(), Qutf_8_unix, Qt, true, true, true) };
+    match std::str::from_utf8(encoded.as_string_or_error().as_slice()) {
+        Ok(s) => s.to_string(),
+        Err(_) => wrong_type!(Qutf_8_string_p, encoded),
+    }
+}
+
+/// Create a Lisp string from the UTF-8 string S.
+pub fn json_make_string(s: &str) -> LispObject {
+    unsafe { make_string(s.as_ptr() as *const c_char, s.len() as ptrdiff_t) }
+}
+
+/// Convert the Lisp object LISP to a toplevel JSON value, which must be
+/// an array or an object.
+fn lisp_to_json_toplevel(lisp: LispObject, config: &JsonConfig) -> Value {
+    enter_nested();
+    let value = if let Some(vector) = lisp.as_vector() {
+        Value::Array(vector.iter().map(|v| lisp_to_json(v, config)).collect())
+    } else if lisp.is_hash_table() {
+        let table = lisp.as_hash_table_or_error();
+        let mut object = Map::new();
+        for (key, value) in table.iter() {
+            let key = json_encode(key.as_string_or_error());
+            // Reject duplicate keys.  These are possible if the hash
+            // table test is not `equal'.
+            if object.contains_key(&key) {
+                wrong_type!(Qjson_value_p, lisp);
+            }
+            object.insert(key, lisp_to_json(value, config));
+        }
+        Value::Object(object)
+    } else if lisp.is_nil() {
+        Value::Object(Map::new())
+    } else if let Some(cons) = lisp.as_cons() {
+        let mut object = Map::new();
+        if cons.car().is_symbol() {
+            // A plist.  Leading colons in keyword names are dropped.
+            let mut tails =
+                lisp.iter_tails_plist(LispConsEndChecks::on, LispConsCircularChecks::on);
+            while let Some(tail) = tails.next() {
+                let key = tail
+                    .car()
+                    .as_symbol_or_error()
+                    .symbol_name()
+                    .as_string_or_error();
+                let value = match tails.next() {
+                    Some(value_tail) => value_tail.car(),
+                    None => wrong_type!(Qplistp, lisp),
+                };
+                let mut key = json_encode(key);
+                if key.starts_with(':') {
+                    key.remove(0);
+                }
+                // Only add an element if the key is not already present.
+                if !object.contains_key(&key) {
+                    object.insert(key, lisp_to_json(value, config));
+                }
+            }
+        } else {
+            for pair in lisp.iter_cars(LispConsEndChecks::on, LispConsCircularChecks::on) {
+                let (key, value) = pair.as_cons_or_error().as_tuple();
+                let key = json_encode(key.as_symbol_or_error().symbol_name().as_string_or_error());
+                if !object.contains_key(&key) {
+                    object.insert(key, lisp_to_json(value, config));
+                }
+            }
+        }
+        Value::Object(object)
+    } else {
+        wrong_type!(Qjson_value_p, lisp);
+    };
+    leave_nested();
+    value
+}
+
+/// Convert the Lisp object LISP to any JSON value.
+pub fn lisp_to_json(lisp: LispObject, config: &JsonConfig) -> Value {
+    if lisp == config.null_object {
+        Value::Null
+    } else if lisp == config.false_object {
+        Value::Bool(false)
+    } else if lisp == Qt {
+        Value::Bool(true)
+    } else if let Some(n) = lisp.as_fixnum() {
+        Value::Number(Number::from(n as i64))
+    } else if let Some(f) = lisp.as_float() {
+        match Number::from_f64(f) {
+            Some(n) => Value::Number(n),
+            None => wrong_type!(Qjson_value_p, lisp),
+        }
+    } else if let Some(string) = lisp.as_string() {
+        Value::String(json_encode(string))
+    } else {
+        // LISP now must be a vector, hashtable, or alist.
+        lisp_to_json_toplevel(lisp, config)
+    }
+}
+
+/// A compact formatter which escapes control characters with upper
+/// case hex digits, as Jansson does.
+struct JsonFormatter(CompactFormatter);
+
+impl Formatter for JsonFormatter {
+    fn write_char_escape<W: ?Sized>(
+        &mut self,
+        writer: &mut W,
+        char_escape: CharEscape,
+    ) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        match char_escape {
+            CharEscape::AsciiControl(byte) => write!(writer, "\\u{:04X}", byte),
+            other => self.0.write_char_escape(writer, other),
+        }
+    }
+}
+
+/// Return the serialized form of VALUE.
+fn json_dump(value: &Value) -> String {
+    let mut output = Vec::new();
+    {
+        let mut serializer =
+            Serializer::with_formatter(&mut output, JsonFormatter(CompactFormatter));
+        if value.serialize(&mut serializer).is_err() {
+            wrong_type!(Qjson_value_p, Qnil);
+        }
+    }
+    // Serializing a `Value` can only produce valid UTF-8.
+    unsafe { String::from_utf8_unchecked(output) }
+}
+
+/// Return the JSON representation of OBJECT as a string.
+///
+/// OBJECT must be a vector, hashtable, alist, or plist and its elements
+/// can recursively contain the Lisp equivalents to the JSON null and
+/// false values, t, numbers, strings, or other vectors hashtables,
+/// alists or plists.  t will be converted to the JSON true value.
+/// Vectors will be converted to JSON arrays, whereas hashtables, alists
+/// and plists are converted to JSON objects.  Hashtable keys must be
+/// strings and must be unique within each object.  Alist and plist keys
+/// must be symbols; if a key is duplicate, the first instance is used.
+/// A leading colon in plist keys is elided.
+///
+/// The Lisp equivalents to the JSON null and false values are
+/// configurable in the arguments ARGS, a list of keyword/argument pairs:
+///
+/// The keyword argument `:null-object' specifies which object to use
+/// to represent a JSON null value.  It defaults to `:null'.
+///
+/// The keyword argument `:false-object' specifies which object to use to
+/// represent a JSON false value.  It defaults to `:false'.
+///
+/// In you specify the same value for `:null-object' and `:false-object',
+/// a potentially ambiguous situation, the JSON output will not contain
+/// any JSON false values.
+/// usage: (json-serialize OBJECT &rest ARGS)
+#[lisp_fn(min = "1")]
+pub fn json_serialize(args: &mut [LispObject]) -> LispObject {
+    let config = JsonConfig::from_args(&args[1..], false);
+    let json = lisp_to_json_toplevel(args[0], &config);
+    json_make_string(&json_dump(&json))
+}
+
+extern "C" fn json_insert_callback(data: *mut c_void) -> LispObject {
+    unsafe { insert1(*(data as *mut LispObject)) };
+    Qnil
+}
+
+/// Insert the JSON representation of OBJECT before point.
+/// This is the same as (insert (json-serialize OBJECT)), but potentially
+/// faster.  See the function `json-serialize' for allowed values of
+/// OBJECT.
+/// usage: (json-insert OBJECT &rest ARGS)
+#[lisp_fn(min = "1")]
+pub fn json_insert(args: &mut [LispObject]) {
+    let config = JsonConfig::from_args(&args[1..], false);
+    let json = lisp_to_json(args[0], &config);
+    let mut string = json_make_string(&json_dump(&json));
+
+    // Insertion runs the change hooks.  Any nonlocal exit from them is
+    // turned into a signal, as was the case with the Jansson callbacks.
+    let error = unsafe {
+        internal_catch_all(
+            Some(json_insert_callback),
+            &mut string as *mut LispObject as *mut c_void,
+            Some(Fidentity),
+        )
+    };
+    if let Some((symbol, data)) = error.as_cons().map(|c| c.as_tuple()) {
+        unsafe { Fsignal(symbol, data) };
+    } else if error.is_not_nil() {
+        xsignal!(Qjson_out_of_memory);
+    }
+}
+
+/// Signal the Lisp error corresponding to the parse error ERROR.
+fn json_parse_error(error: &serde_json::Error) -> ! {
+    let symbol = match error.classify() {
+        Category::Eof => Qjson_end_of_file,
+        _ => Qjson_parse_error,
+    };
+    xsignal!(
+        symbol,
+        json_make_string(&error.to_string()),
+        LispObject::from(error.line()),
+        LispObject::from(error.column())
+    );
+}
+
+/// Convert the JSON value JSON to a Lisp object.
+pub fn json_to_lisp(json: Value, config: &JsonConfig) -> LispObject {
+    match json {
+        Value::Null => config.null_object,
+        Value::Bool(false) => config.false_object,
+        Value::Bool(true) => Qt,
+        Value::Number(n) => {
+            // Return an integer if possible, a floating-point number
+            // otherwise.  This loses precision for integers with large
+            // magnitude; however, such integers tend to be nonportable
+            // anyway because many JSON implementations use only 64-bit
+            // floating-point numbers with 53 mantissa bits.
+            match n.as_i64() {
+                Some(i) if MOST_NEGATIVE_FIXNUM as i64 <= i && i <= MOST_POSITIVE_FIXNUM as i64 => {
+                    LispObject::from(i as EmacsInt)
+                }
+                _ => LispObject::from_float(n.as_f64().unwrap_or(0.0)),
+            }
+        }
+        Value::String(s) => json_make_string(&s),
+        Value::Array(elements) => {
+            enter_nested();
+            let result = unsafe { Fmake_vector(LispObject::from(elements.len()), Qnil) };
+            let mut vector = result.as_vector_or_error();
+            for (i, element) in elements.into_iter().enumerate() {
+                vector.set(i, json_to_lisp(element, config));
+            }
+            leave_nested();
+            result
+        }
+        Value::Object(members) => {
+            enter_nested();
+            let members = members
+                .into_iter()
+                .map(|(key, value)| (key, json_to_lisp(value, config)))
+                .collect();
+            let result = make_lisp_object(members, config.object_type);
+            leave_nested();
+            result
+        }
+    }
+}
+
+/// Return the Lisp representation of an object with MEMBERS, a list of
+/// keys and the already converted values, as given by OBJECT_TYPE.
+pub fn make_lisp_object(members: Vec<(String, LispObject)>, object_type: ObjectType) -> LispObject {
+    match object_type {
+        ObjectType::HashTable => {
+            let mut args = [QCtest, Qequal, QCsize, LispObject::from(members.len())];
+            let table = unsafe { Fmake_hash_table(args.len() as ptrdiff_t, args.as_mut_ptr()) };
+            let hash_table: LispHashTableRef = table.into();
+            for (key, value) in members {
+                puthash(json_make_string(&key), value, hash_table);
+            }
+            table
+        }
+        ObjectType::Alist => {
+            let pairs: Vec<LispObject> = members
+                .into_iter()
+                .map(|(key, value)| {
+                    let key = lisp_intern(json_make_string(&key), Qnil);
+                    LispObject::cons(key, value)
+                })
+                .collect();
+            list(&pairs)
+        }
+        ObjectType::Plist => {
+            let mut elements = Vec::with_capacity(2 * members.len());
+            for (key, value) in members {
+                elements.push(lisp_intern(json_make_string(&format!(":{}", key)), Qnil));
+                elements.push(value);
+            }
+            list(&elements)
+        }
+    }
+}
+
+/// Parse the JSON STRING into a Lisp object.
+/// This is essentially the reverse operation of `json-serialize', which
+/// see.  The returned object will be a vector, hashtable, alist, or
+/// plist.  Its elements will be the JSON null value, the JSON false
+/// value, t, numbers, strings, or further vectors, hashtables, alists,
+/// or plists.  If there are duplicate keys in an object, all but the
+/// last one are ignored.  If STRING doesn't contain a valid JSON object,
+/// or arrays and objects are nested in it more than 127 levels deep, an
+/// error of type `json-parse-error' is signaled.  The arguments ARGS are
+/// a list of keyword/argument pairs:
+///
+/// The keyword argument `:object-type' specifies which Lisp type is used
+/// to represent objects; it can be `hash-table', `alist' or `plist'.
+///
+/// The keyword argument `:null-object' specifies which object to use
+/// to represent a JSON null value.  It defaults to `:null'.
+///
+/// The keyword argument `:false-object' specifies which object to use to
+/// represent a JSON false value.  It defaults to `:false'.
+/// usage: (json-parse-string STRING &rest ARGS)
+#[lisp_fn(min = "1")]
+pub fn json_parse_string(args: &mut [LispObject]) -> LispObject {
+    let string = json_encode(args[0].as_string_or_error());
+    if string.contains('\0') {
+        wrong_type!(Qstring_without_embedded_nulls_p, args[0]);
+    }
+    let config = JsonConfig::from_args(&args[1..], true);
+
+    let mut deserializer = serde_json::Deserializer::from_str(&string);
+    let json = match Value::deserialize(&mut deserializer) {
+        Ok(json) => json,
+        Err(error) => json_parse_error(&error),
+    };
+    if let Err(error) = deserializer.end() {
+        xsignal!(
+            Qjson_trailing_content,
+            json_make_string(&error.to_string()),
+            LispObject::from(error.line()),
+            LispObject::from(error.column())
+        );
+    }
+    json_to_lisp(json, &config)
+}
+
+/// Read JSON object from current buffer starting at point.
+/// Move point after the end of the object if parsing was successful.
+/// On error, don't move point.
+///
+/// The returned object will be a vector, list, hashtable, alist, or
+/// plist.  Its elements will be the JSON null value, the JSON false
+/// value, t, numbers, strings, or further vectors, lists, hashtables,
+/// alists, or plists.  If there are duplicate keys in an object, all
+/// but the last one are ignored.
+///
+/// If the current buffer doesn't contain a valid JSON object, the
+/// function signals an error of type `json-parse-error'.
+///
+/// The arguments ARGS are a list of keyword/argument pairs, as for
+/// `json-parse-string'.
+/// usage: (json-parse-buffer &rest args)
+#[lisp_fn]
+pub fn json_parse_buffer(args: &mut [LispObject]) -> LispObject {
+    let config = JsonConfig::from_args(args, true);
+
+    let mut buffer = ThreadState::current_buffer();
+    let point = buffer.pt_byte;
+    // Read the text through the gap rather than copying it.
+    let (before_gap, after_gap) = buffer.region_slices(point, buffer.zv_byte);
+    let reader = before_gap.chain(after_gap);
+
+    let mut stream = serde_json::Deserializer::from_reader(reader).into_iter::<Value>();
+    let json = match stream.next() {
+        Some(Ok(json)) => json,
+        Some(Err(error)) => json_parse_error(&error),
+        None => xsignal!(
+            Qjson_end_of_file,
+            json_make_string("EOF while parsing a value")
+        ),
+    };
+
+    // Convert and then move point only if everything succeeded.
+    let lisp = json_to_lisp(json, &config);
+
+    // Adjust point by how much we just read.
+    let end = point + stream.byte_offset() as ptrdiff_t;
+    let end_char = unsafe { buf_bytepos_to_charpos(buffer.as_mut(), end) };
+    buffer.set_pt_both(end_char, end);
+    lisp
+}
+
+/// How far an incremental reader has got through the value it is
+/// currently reading.
+#[derive(Default)]
+struct JsonScanner {
+    started: bool,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    scalar: bool,
+}
+
+/// The state of an incremental reader: the input that has been fed to
+/// it but not yet returned as complete values.
+#[derive(Default)]
+struct JsonReader {
+    pending: Vec<u8>,
+    /// How many bytes of `pending` the scanner has already looked at.
+    scanned: usize,
+    scanner: JsonScanner,
+}
+
+/// Return true if BYTE ends a top-level number or literal.
+fn is_json_delimiter(byte: u8) -> bool {
+    match byte {
+        b' ' | b'\t' | b'\n' | b'\r' | b'[' | b']' | b'{' | b'}' | b',' | b':' | b'"' => true,
+        _ => false,
+    }
+}
+
+impl JsonReader {
+    /// Append CHUNK to the pending input and return the top-level values
+    /// it completes.  The scanner only tracks nesting and strings, so
+    /// each byte is looked at once no matter how the input is split;
+    /// a value is handed to the parser once its end has been seen.  On a
+    /// parse error all pending input is dropped.
+    fn feed(&mut self, chunk: &[u8]) -> Result<Vec<Value>, serde_json::Error> {
+        self.pending.extend_from_slice(chunk);
+
+        let mut values = Vec::new();
+        let mut start = 0;
+        let mut i = self.scanned;
+        while i < self.pending.len() {
+            let byte = self.pending[i];
+            let scanner = &mut self.scanner;
+            let mut end = None;
+
+            if !scanner.started {
+                match byte {
+                    b' ' | b'\t' | b'\n' | b'\r' => start = i + 1,
+                    b'{' | b'[' => {
+                        scanner.started = true;
+                        scanner.depth = 1;
+                    }
+                    b'"' => {
+                        scanner.started = true;
+                        scanner.in_string = true;
+                    }
+                    // Let the parser report the stray delimiter.
+                    b'}' | b']' | b',' | b':' => end = Some(i + 1),
+                    _ => {
+                        scanner.started = true;
+                        scanner.scalar = true;
+                    }
+                }
+            } else if scanner.in_string {
+                if scanner.escaped {
+                    scanner.escaped = false;
+                } else if byte == b'\\' {
+                    scanner.escaped = true;
+                } else if byte == b'"' {
+                    scanner.in_string = false;
+                    if scanner.depth == 0 {
+                        end = Some(i + 1);
+                    }
+                }
+            } else if scanner.scalar {
+                // The delimiter is not part of the value, so it is
+                // scanned again below.
+                if is_json_delimiter(byte) {
+                    end = Some(i);
+                }
+            } else {
+                match byte {
+                    b'"' => scanner.in_string = true,
+                    b'{' | b'[' => scanner.depth += 1,
+                    b'}' | b']' => {
+                        scanner.depth -= 1;
+                        if scanner.depth == 0 {
+                            end = Some(i + 1);
+                        }
+                    }
+                    _ => (),
+                }
+            }
+
+            match end {
+                Some(end) => {
+                    self.scanner = JsonScanner::default();
+                    match serde_json::from_slice(&self.pending[start..end]) {
+                        Ok(value) => values.push(value),
+                        Err(error) => {
+                            self.pending.clear();
+                            self.scanned = 0;
+                            return Err(error);
+                        }
+                    }
+                    start = end;
+                    i = end;
+                }
+                None => i += 1,
+            }
+        }
+
+        self.pending.drain(..start);
+        self.scanned = self.pending.len();
+        Ok(values)
+    }
+}
+
+struct JsonReaders {
+    next_id: EmacsInt,
+    readers: HashMap<EmacsInt, JsonReader>,
+}
+
+lazy_static! {
+    /// The state of the open incremental readers, by reader number.
+    static ref JSON_READERS: Mutex<JsonReaders> = Mutex::new(JsonReaders {
+        next_id: 0,
+        readers: HashMap::new(),
+    });
+}
+
+/// Return the number of the incremental reader READER.
+fn json_reader_id(reader: LispObject) -> EmacsInt {
+    if !json_reader_p(reader) {
+        wrong_type!(Qjson_reader_p, reader);
+    }
+    aref(reader, 1).as_fixnum_or_error()
+}
+
+/// Return a new reader which parses a stream of JSON values incrementally.
+/// Text is given to the reader with `json-reader-feed', in chunks of
+/// any size; this is meant to be called from a process filter, so that
+/// large messages need not be accumulated in a string before they are
+/// parsed.  The stream consists of JSON values separated by optional
+/// whitespace.
+///
+/// The arguments ARGS are a list of keyword/argument pairs which say
+/// how values are represented, as for `json-parse-string'.
+///
+/// The reader holds on to the incomplete input it has been given until
+/// it is closed with `json-reader-close' or garbage collected.
+/// usage: (json-make-reader &rest ARGS)
+#[lisp_fn]
+pub fn json_make_reader(args: &mut [LispObject]) -> LispObject {
+    // Check the arguments now rather than on the first chunk.
+    JsonConfig::from_args(args, true);
+
+    let id = {
+        let mut readers = JSON_READERS.lock().unwrap();
+        readers.next_id += 1;
+        let id = readers.next_id;
+        readers.readers.insert(id, JsonReader::default());
+        id
+    };
+
+    // Release the reader's state once the reader becomes garbage.
+    let release = list!(
+        Qclosure,
+        list!(Qt),
+        Qnil,
+        list!(Qjson__reader_release, LispObject::from(id))
+    );
+    let finalizer = unsafe { Fmake_finalizer(release) };
+    callN_raw!(
+        Frecord,
+        Qjson_reader,
+        LispObject::from(id),
+        list(args),
+        finalizer
+    )
+}
+
+/// Return t if OBJECT is a JSON reader made by `json-make-reader'.
+#[lisp_fn]
+pub fn json_reader_p(object: LispObject) -> bool {
+    recordp(object) && aref(object, 0) == Qjson_reader
+}
+
+/// Give the text CHUNK to the incremental JSON reader READER.
+/// Return a list of the top-level values completed by CHUNK, in the
+/// order they appeared, which is nil if CHUNK doesn't complete any.  A
+/// number or literal at the end of the stream isn't returned until the
+/// text following it shows where it ends.
+///
+/// If CHUNK is multibyte it is encoded as UTF-8; a unibyte CHUNK is
+/// taken as UTF-8 bytes, which may end in the middle of a character.
+/// If a complete value is invalid, an error of type `json-parse-error'
+/// is signaled and the input buffered by READER is discarded.
+#[lisp_fn]
+pub fn json_reader_feed(reader: LispObject, chunk: LispStringRef) -> LispObject {
+    let id = json_reader_id(reader);
+    let mut args: Vec<LispObject> = aref(reader, 2)
+        .iter_cars(LispConsEndChecks::on, LispConsCircularChecks::on)
+        .collect();
+    let config = JsonConfig::from_args(&args, true);
+
+    let encoded;
+    let bytes = if chunk.is_multibyte() {
+        encoded = json_encode(chunk);
+        encoded.as_bytes()
+    } else {
+        chunk.as_slice()
+    };
+
+    // Release the lock before anything can signal.
+    let result = JSON_READERS
+        .lock()
+        .unwrap()
+        .readers
+        .get_mut(&id)
+        .map(|state| state.feed(bytes));
+    match result {
+        None => error!("JSON reader has been closed"),
+        Some(Err(error)) => json_parse_error(&error),
+        Some(Ok(values)) => {
+            let values: Vec<LispObject> = values
+                .into_iter()
+                .map(|value| json_to_lisp(value, &config))
+                .collect();
+            list(&values)
+        }
+    }
+}
+
+/// Close the incremental JSON reader READER, discarding any input it
+/// has buffered.  Return t if READER was open, nil if it was already
+/// closed.
+#[lisp_fn]
+pub fn json_reader_close(reader: LispObject) -> bool {
+    json_reader_release(json_reader_id(reader))
+}
+
+/// Forget the state of the JSON reader numbered ID.
+/// This is for internal use by the finalizer of JSON readers.
+#[lisp_fn(name = "json--reader-release")]
+pub fn json_reader_release(id: EmacsInt) -> bool {
+    JSON_READERS.lock().unwrap().readers.remove(&id).is_some()
+}
+
+#[no_mangle]
+pub extern "C" fn syms_of_json() {
+    def_lisp_sym!(QCnull, ":null");
+    def_lisp_sym!(QCfalse, ":false");
+
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    def_lisp_sym!(Qstring_without_embedded_nulls_p, "string-without-embedded-nulls-p");
+    def_lisp_sym!(Qjson_value_p, "json-value-p");
+    def_lisp_sym!(Qutf_8_string_p, "utf-8-string-p");
+
+    def_lisp_sym!(Qjson_error, "json-error");
+    def_lisp_sym!(Qjson_out_of_memory, "json-out-of-memory");
+    def_lisp_sym!(Qjson_parse_error, "json-parse-error");
+    def_lisp_sym!(Qjson_end_of_file, "json-end-of-file");
+    def_lisp_sym!(Qjson_trailing_content, "json-trailing-content");
+    def_lisp_sym!(Qjson_object_too_deep, "json-object-too-deep");
+    define_error(Qjson_error, "generic JSON error", Qerror);
+    define_error(
+        Qjson_out_of_memory,
+        "not enough memory for creating JSON object",
+        Qjson_error,
+    );
+    define_error(
+        Qjson_parse_error,
+        "could not parse JSON stream",
+        Qjson_error,
+    );
+    define_error(Qjson_end_of_file, "end of JSON stream", Qjson_parse_error);
+    define_error(
+        Qjson_trailing_content,
+        "trailing content after JSON stream",
+        Qjson_parse_error,
+    );
+    define_error(
+        Qjson_object_too_deep,
+        "object cyclic or Lisp evaluation too deep",
+        Qjson_error,
+    );
+
+    def_lisp_sym!(Qpure, "pure");
+    def_lisp_sym!(Qside_effect_free, "side-effect-free");
+
+    def_lisp_sym!(Qjson_serialize, "json-serialize");
+    def_lisp_sym!(Qjson_parse_string, "json-parse-string");
+    put(Qjson_serialize.into(), Qpure, Qt);
+    put(Qjson_serialize.into(), Qside_effect_free, Qt);
+    put(Qjson_parse_string.into(), Qpure, Qt);
+    put(Qjson_parse_string.into(), Qside_effect_free, Qt);
+
+    def_lisp_sym!(QCobject_type, ":object-type");
+    def_lisp_sym!(QCnull_object, ":null-object");
+    def_lisp_sym!(QCfalse_object, ":false-object");
+    def_lisp_sym!(Qjson_reader, "json-reader");
+    def_lisp_sym!(Qjson_reader_p, "json-reader-p");
+    def_lisp_sym!(Qjson__reader_release, "json--reader-release");
+
+    def_lisp_sym!(Qalist, "alist");
+    def_lisp_sym!(Qplist, "plist");
+}
+
+include!(concat!(env!("OUT_DIR"), "/json_exports.rs"));
@@ -0,0 +1,303 @@
+//! The kill ring.
+//!
+//! `kill-new' and `current-kill' in simple.el deal with the selections of
+//! the window system, and leave the bookkeeping of the ring itself to the
+//! functions here.  The ring stays an ordinary list in `kill-ring', which
+//! Lisp code may still inspect and change directly.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+use libc::{c_char, ptrdiff_t};
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    fileio::encoded_file_path,
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::{nthcdr, setcar, setcdr, LispConsCircularChecks, LispConsEndChecks},
+    multibyte::LispStringRef,
+    objects::equal_including_properties,
+    remacs_sys::{encode_file_name, globals, make_specified_string, report_file_error},
+    remacs_sys::{EmacsInt, Fexpand_file_name},
+    remacs_sys::{Qall, Qkill_do_not_save_duplicates, Qkill_ring_max, Qnil},
+    symbols::symbol_value,
+    threads::ThreadState,
+    vectors::length,
+};
+
+/// The first line of a file written by `kill-ring-write-file'.
+const KILL_RING_FILE_HEADER: &[u8] = b";;; Emacs kill ring, format 1\n";
+
+/// Return the number of kills to keep in the ring, from `kill-ring-max'.
+/// At least the latest kill is always kept.
+fn kill_ring_max() -> usize {
+    symbol_value(Qkill_ring_max.into())
+        .as_fixnum()
+        .map_or(1, |max| max.max(1) as usize)
+}
+
+/// Return true if STRING is too large to be kept in the kill ring,
+/// according to `kill-ring-entry-max-bytes'.
+fn too_large_to_keep(string: LispStringRef) -> bool {
+    unsafe { globals.Vkill_ring_entry_max_bytes }
+        .as_fixnum()
+        .map_or(false, |max| string.len_bytes() as EmacsInt > max)
+}
+
+/// Drop the kills in RING beyond the first MAX.
+fn truncate_ring(ring: LispObject, max: usize) {
+    let tail = nthcdr(max as EmacsInt - 1, ring);
+    if let Some(cons) = tail.as_cons() {
+        setcdr(cons, Qnil);
+    }
+}
+
+/// Remove from the tail of RING after its first element the elements
+/// which are `equal-including-properties' to STRING.
+fn remove_older_duplicates(ring: LispObject, string: LispObject) {
+    let mut tails = ring.iter_tails(LispConsEndChecks::off, LispConsCircularChecks::safe);
+    let mut prev = match tails.next() {
+        Some(prev) => prev,
+        None => return,
+    };
+    for tail in tails {
+        if equal_including_properties(tail.car(), string) {
+            setcdr(prev, tail.cdr());
+        } else {
+            prev = tail;
+        }
+    }
+}
+
+/// Make STRING the latest kill in the kill ring, and return the ring.
+/// This does the bookkeeping of `kill-new', without calling
+/// `interprogram-cut-function'.  Optional second argument REPLACE
+/// non-nil means that STRING replaces the front of the kill ring, rather
+/// than being added to it.
+///
+/// If `kill-do-not-save-duplicates' is non-nil and STRING duplicates the
+/// latest kill, the ring is left alone; if it is `all', older duplicates
+/// of STRING are also removed from the ring.  A STRING longer than
+/// `kill-ring-entry-max-bytes' is not added.  The ring is then cut down
+/// to `kill-ring-max' kills, and `kill-ring-yank-pointer' is set to it.
+#[lisp_fn(min = "1")]
+pub fn kill_ring_push(string: LispStringRef, replace: bool) -> LispObject {
+    let new: LispObject = string.into();
+    let ring = unsafe { globals.Vkill_ring };
+    let duplicates = symbol_value(Qkill_do_not_save_duplicates.into());
+    let duplicate = duplicates.is_not_nil()
+        && ring
+            .as_cons()
+            .map_or(false, |cons| equal_including_properties(new, cons.car()));
+
+    if !duplicate && !too_large_to_keep(string) {
+        let ring = match ring.as_cons() {
+            Some(cons) if replace => {
+                setcar(cons, new);
+                ring
+            }
+            _ => LispObject::cons(new, ring),
+        };
+        if duplicates.eq(Qall) {
+            remove_older_duplicates(ring, new);
+        }
+        truncate_ring(ring, kill_ring_max());
+        unsafe { globals.Vkill_ring = ring };
+    }
+
+    unsafe {
+        globals.Vkill_ring_yank_pointer = globals.Vkill_ring;
+        globals.Vkill_ring
+    }
+}
+
+/// Rotate the yanking point by N places, and then return that kill.
+/// This does the bookkeeping of `current-kill', without consulting
+/// `interprogram-paste-function'.  If optional arg DO-NOT-MOVE is
+/// non-nil, then don't actually move the yanking point; just return the
+/// Nth kill forward.
+#[lisp_fn(min = "1")]
+pub fn kill_ring_rotate(n: EmacsInt, do_not_move: bool) -> LispObject {
+    let ring = unsafe { globals.Vkill_ring };
+    if ring.is_nil() {
+        error!("Kill ring is empty");
+    }
+    let ring_length = length(ring) as EmacsInt;
+    let pointer_length = length(unsafe { globals.Vkill_ring_yank_pointer }) as EmacsInt;
+    // The modulus is taken towards negative infinity, as by `mod'.
+    let index = ((n - pointer_length) % ring_length + ring_length) % ring_length;
+
+    let kill = nthcdr(index, ring);
+    if !do_not_move {
+        unsafe { globals.Vkill_ring_yank_pointer = kill };
+    }
+    kill.as_cons_or_error().car()
+}
+
+/// Return the bytes of a file holding the kills ENTRIES, each a flag
+/// telling whether it is multibyte and the bytes of its text.
+fn serialize_kills<'a, I>(entries: I) -> Vec<u8>
+where
+    I: Iterator<Item = (bool, &'a [u8])>,
+{
+    let mut data = KILL_RING_FILE_HEADER.to_vec();
+    for (multibyte, bytes) in entries {
+        data.extend(format!("{} {}\n", multibyte as u8, bytes.len()).as_bytes());
+        data.extend(bytes);
+        data.push(b'\n');
+    }
+    data
+}
+
+/// Return the kills in DATA, the contents of a file written by
+/// `serialize_kills', or None if DATA isn't in that format.
+fn deserialize_kills(data: &[u8]) -> Option<Vec<(bool, &[u8])>> {
+    if !data.starts_with(KILL_RING_FILE_HEADER) {
+        return None;
+    }
+    let mut rest = &data[KILL_RING_FILE_HEADER.len()..];
+    let mut kills = Vec::new();
+    while !rest.is_empty() {
+        let newline = rest.iter().position(|&b| b == b'\n')?;
+        let line = std::str::from_utf8(&rest[..newline]).ok()?;
+        let mut fields = line.splitn(2, ' ');
+        let multibyte = match fields.next()? {
+            "0" => false,
+            "1" => true,
+            _ => return None,
+        };
+        let len: usize = fields.next()?.parse().ok()?;
+        let start = newline + 1;
+        if rest.len() < start + len + 1 || rest[start + len] != b'\n' {
+            return None;
+        }
+        kills.push((multibyte, &rest[start..start + len]));
+        rest = &rest[start + len + 1..];
+    }
+    Some(kills)
+}
+
+/// Return the name of FILE, expanded, and its encoded form for opening.
+fn kill_ring_file_name(file: LispStringRef) -> (LispObject, LispObject) {
+    let directory = ThreadState::current_buffer().directory_;
+    let file = unsafe { Fexpand_file_name(file.into(), directory) };
+    (file, unsafe { encode_file_name(file) })
+}
+
+/// Write the kill ring to FILE, so that `kill-ring-read-file' can read
+/// it back in another session.  Only the text of the kills is written,
+/// without its properties.  The file is readable only by its owner,
+/// since kills often hold passwords.  Return the number of kills written.
+#[lisp_fn]
+pub fn kill_ring_write_file(file: LispStringRef) -> EmacsInt {
+    let kills: Vec<LispStringRef> = unsafe { globals.Vkill_ring }
+        .iter_cars(LispConsEndChecks::off, LispConsCircularChecks::safe)
+        .filter_map(|kill| kill.as_string())
+        .collect();
+    let data = serialize_kills(
+        kills
+            .iter()
+            .map(|kill| (kill.is_multibyte(), kill.as_slice())),
+    );
+
+    let (file, encoded) = kill_ring_file_name(file);
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let written = options
+        .open(encoded_file_path(encoded))
+        .and_then(|mut output| output.write_all(&data));
+    if written.is_err() {
+        unsafe { report_file_error("Writing kill ring\0".as_ptr() as *const c_char, file) };
+    }
+    kills.len() as EmacsInt
+}
+
+/// Replace the kill ring with the kills saved in FILE by
+/// `kill-ring-write-file', keeping at most `kill-ring-max' of them, and
+/// return the number of kills read.
+#[lisp_fn]
+pub fn kill_ring_read_file(file: LispStringRef) -> EmacsInt {
+    let (file, encoded) = kill_ring_file_name(file);
+    let mut data = Vec::new();
+    if File::open(encoded_file_path(encoded))
+        .and_then(|mut input| input.read_to_end(&mut data))
+        .is_err()
+    {
+        unsafe { report_file_error("Reading kill ring\0".as_ptr() as *const c_char, file) };
+    }
+
+    let kills = match deserialize_kills(&data) {
+        Some(kills) => kills,
+        None => error!("Invalid kill ring file: {}", file.as_string_or_error()),
+    };
+    let kills: Vec<LispObject> = kills
+        .into_iter()
+        .take(kill_ring_max())
+        .map(|(multibyte, bytes)| unsafe {
+            make_specified_string(
+                bytes.as_ptr() as *const c_char,
+                -1,
+                bytes.len() as ptrdiff_t,
+                multibyte,
+            )
+        })
+        .collect();
+
+    let ring = kills
+        .iter()
+        .rev()
+        .fold(Qnil, |ring, &kill| LispObject::cons(kill, ring));
+    unsafe {
+        globals.Vkill_ring = ring;
+        globals.Vkill_ring_yank_pointer = ring;
+    }
+    kills.len() as EmacsInt
+}
+
+#[no_mangle]
+pub extern "C" fn syms_of_kill_ring() {
+    def_lisp_sym!(Qall, "all");
+    def_lisp_sym!(Qkill_do_not_save_duplicates, "kill-do-not-save-duplicates");
+    def_lisp_sym!(Qkill_ring_max, "kill-ring-max");
+
+    /// List of killed text sequences.
+    /// Since the kill ring is supposed to interact nicely with cut-and-paste
+    /// facilities offered by window systems, use of this variable should
+    /// interact nicely with `interprogram-cut-function' and
+    /// `interprogram-paste-function'.  The functions `kill-new',
+    /// `kill-append', and `current-kill' are supposed to implement this
+    /// interaction; you may want to use them instead of manipulating the kill
+    /// ring directly.
+    defvar_lisp!(Vkill_ring, "kill-ring", Qnil);
+
+    /// The tail of the kill ring whose car is the last thing yanked.
+    defvar_lisp!(Vkill_ring_yank_pointer, "kill-ring-yank-pointer", Qnil);
+
+    /// Maximum size in bytes of a kill that is kept in the kill ring.
+    /// Larger kills are still passed to `interprogram-cut-function', but
+    /// not added to `kill-ring', so that killing a huge region by accident
+    /// doesn't keep its text alive.  If nil, there is no limit.
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    defvar_lisp!(Vkill_ring_entry_max_bytes, "kill-ring-entry-max-bytes", Qnil);
+}
+
+#[test]
+fn test_kill_ring_file_round_trip() {
+    let kills = vec![
+        (true, "κ\n1".as_bytes()),
+        (false, &b"\xff\x00"[..]),
+        (true, &b""[..]),
+    ];
+    let data = serialize_kills(kills.iter().cloned());
+    assert_eq!(deserialize_kills(&data), Some(kills));
+    assert_eq!(deserialize_kills(b"1 3\nabc\n"), None);
+    assert_eq!(deserialize_kills(&data[..data.len() - 1]), None);
+}
+
+include!(concat!(env!("OUT_DIR"), "/kill_ring_exports.rs"));
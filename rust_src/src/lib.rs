@@ -26,15 +26,68 @@ extern crate if_chain;
 #[macro_use]
 extern crate lazy_static;
 
+extern crate aho_corasick;
 extern crate base64 as base64_crate;
+extern crate blake2;
+extern crate brotli_decompressor;
+#[cfg(any(target_os = "macos", windows))]
+extern crate clipboard;
+#[cfg(feature = "dbusnotify")]
+extern crate dbus;
+extern crate fancy_regex;
+extern crate fs2;
+extern crate grep_matcher;
+extern crate grep_regex;
+extern crate grep_searcher;
+extern crate ignore;
+extern crate im;
 extern crate libc;
+#[cfg(target_os = "macos")]
+extern crate mac_notification_sys;
 extern crate md5;
+extern crate memmap;
+#[cfg(feature = "rustnotify")]
+extern crate notify;
+extern crate percent_encoding;
+extern crate png;
 extern crate rand;
+extern crate rayon;
+extern crate regex;
+extern crate reqwest;
+extern crate rustls;
+extern crate ryu;
+extern crate serde;
+extern crate serde_cbor;
+extern crate serde_json;
+extern crate serde_yaml;
 extern crate sha1;
 extern crate sha2;
+extern crate sha3;
+extern crate ssh2;
+extern crate tar;
+extern crate tiny_http;
+extern crate trust_dns_resolver;
+extern crate tungstenite;
+extern crate ucd;
+extern crate unicode_bidi;
+extern crate unicode_normalization;
+extern crate unicode_segmentation;
+extern crate url;
+extern crate webpki;
+extern crate webpki_roots;
+#[cfg(windows)]
+extern crate winrt_notification;
+#[cfg(all(unix, not(target_os = "macos")))]
+extern crate x11_clipboard;
+#[cfg(unix)]
+extern crate xattr as xattr_crate;
+extern crate xi_unicode;
+extern crate zip;
 
 extern crate field_offset;
 extern crate flate2;
+extern crate xz2;
+extern crate zstd;
 
 extern crate core;
 
@@ -61,7 +114,11 @@ mod vector_macros;
 mod str2sig;
 
 mod alloc;
+mod archive;
 mod base64;
+mod bidi;
+mod binary_serialization;
+mod bitset;
 mod buffers;
 mod bytecode;
 mod callint;
@@ -70,47 +127,83 @@ mod casefiddle;
 mod casetab;
 mod category;
 mod character;
+mod charprop;
+mod chars;
 mod charset;
 mod chartable;
 mod cmds;
 mod coding;
+mod collections;
 mod crypto;
+mod csv;
 mod data;
 mod decompress;
+mod diff;
 mod dired;
 #[cfg(unix)]
 mod dired_unix;
 #[cfg(windows)]
 mod dired_windows;
 mod dispnew;
+mod distance;
+mod dns;
 mod editfns;
 mod emacs;
 mod eval;
 mod ffi;
 mod fileio;
+mod filelock;
+mod filenotify;
+mod fill;
 mod floatfns;
 mod fns;
 mod fonts;
+mod format;
+mod fuzzy;
+mod grep;
+mod http;
+mod http_server;
 mod hashtable;
 mod indent;
 mod interactive;
+mod jobs;
+mod json;
 mod keyboard;
 mod keymap;
+mod kill_ring;
 mod libm;
+mod line_index;
 mod lists;
 mod lread;
+mod mapped_text;
 mod marker;
 mod math;
 mod minibuf;
 mod multibyte;
+mod notifications;
 mod numbers;
 mod obarray;
 mod objects;
+mod paragraphs;
+mod pcre;
+mod persistent;
+mod pipeline;
 mod process;
 mod profiler;
+mod pty;
+mod rect;
 #[allow(clippy::all)]
 mod remacs_sys;
 mod search;
+mod selection;
+mod seq;
+#[cfg(unix)]
+mod sigbus;
+mod sockets;
+mod sort;
+#[cfg(unix)]
+mod spawn;
+mod ssh;
 mod strings;
 mod symbols;
 mod syntax;
@@ -118,11 +211,22 @@ mod terminal;
 mod textprop;
 mod threads;
 mod time;
+mod tls;
+mod toml;
+mod trash;
+mod typed_vector;
+mod undo;
+mod utf8;
 mod util;
 mod vectors;
+mod weak;
+mod websocket;
+mod whitespace;
 mod window_configuration;
 mod windows;
+mod xattr;
 mod xml;
+mod yaml;
 
 #[cfg(all(not(test), target_os = "macos"))]
 use alloc_unexecmacosx::OsxUnexecAlloc;
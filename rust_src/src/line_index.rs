@@ -0,0 +1,196 @@
+//! An index of the lines of a buffer.
+//!
+//! `line-number-at-pos-fast' and `position-of-line' look lines up in a
+//! sorted vector of the byte positions of the newlines of a buffer, so a
+//! query takes O(log n) time instead of a scan for newlines.  The index
+//! is built when the text of a buffer is first queried, and rebuilt after
+//! the text changes.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use libc::ptrdiff_t;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    buffers::{LispBufferRef, BEG_BYTE},
+    lisp::defsubr,
+    lisp::LispObject,
+    marker::{buf_bytepos_to_charpos, buf_charpos_to_bytepos},
+    remacs_sys::{EmacsInt, Lisp_Buffer},
+    threads::ThreadState,
+};
+
+/// What the newlines of a buffer depend on: the storage of its text,
+/// which `buffer-swap-text' exchanges with another buffer, its size in
+/// characters and bytes, and the modification count of its characters.
+type TextState = (usize, ptrdiff_t, ptrdiff_t, EmacsInt);
+
+fn text_state(buffer: LispBufferRef) -> TextState {
+    (
+        buffer.beg_addr() as usize,
+        buffer.z(),
+        buffer.z_byte(),
+        buffer.char_modifications(),
+    )
+}
+
+struct LineIndex {
+    /// The state of the text when the index was built.
+    state: TextState,
+    /// The byte positions of the newlines, in increasing order.
+    newlines: Vec<ptrdiff_t>,
+}
+
+impl LineIndex {
+    fn new(buffer: LispBufferRef) -> Self {
+        LineIndex {
+            state: text_state(buffer),
+            newlines: newline_positions(BEG_BYTE, buffer.byte_chunks(BEG_BYTE, buffer.z_byte())),
+        }
+    }
+
+    /// Return the number of newlines before byte position BYTEPOS.
+    fn newlines_before(&self, bytepos: ptrdiff_t) -> usize {
+        match self.newlines.binary_search(&bytepos) {
+            Ok(i) | Err(i) => i,
+        }
+    }
+}
+
+/// Return the byte positions of the newlines in CHUNKS, consecutive
+/// slices of text starting at byte position START.
+fn newline_positions<'a, I>(start: ptrdiff_t, chunks: I) -> Vec<ptrdiff_t>
+where
+    I: Iterator<Item = &'a [u8]>,
+{
+    let mut newlines = Vec::new();
+    let mut chunk_start = start;
+    for chunk in chunks {
+        newlines.extend(
+            chunk
+                .iter()
+                .enumerate()
+                .filter(|&(_, &b)| b == b'\n')
+                .map(|(i, _)| chunk_start + i as ptrdiff_t),
+        );
+        chunk_start += chunk.len() as ptrdiff_t;
+    }
+    newlines
+}
+
+lazy_static! {
+    /// The line indexes of the buffer texts which have been queried, by
+    /// the addresses of the texts, which indirect buffers share.
+    static ref LINE_INDEXES: Mutex<HashMap<usize, LineIndex>> = Mutex::new(HashMap::new());
+}
+
+/// Call F with the line index of the text of BUFFER, which is built or
+/// rebuilt first if it doesn't describe the text as it is now.
+fn with_line_index<F, T>(buffer: LispBufferRef, f: F) -> T
+where
+    F: FnOnce(&LineIndex) -> T,
+{
+    let mut indexes = LINE_INDEXES.lock().unwrap();
+    let key = buffer.text as usize;
+    let state = text_state(buffer);
+    if !indexes
+        .get(&key)
+        .map_or(false, |index| index.state == state)
+    {
+        indexes.insert(key, LineIndex::new(buffer));
+    }
+    f(&indexes[&key])
+}
+
+/// Forget the line index of the text of the buffer B, whose text is
+/// being freed.
+#[no_mangle]
+pub extern "C" fn forget_line_index(b: *mut Lisp_Buffer) {
+    let text = unsafe { (*b).text };
+    LINE_INDEXES.lock().unwrap().remove(&(text as usize));
+}
+
+/// Return the bounds of the part of BUFFER that lines are counted in, in
+/// bytes: the accessible portion, or the whole buffer if ABSOLUTE.
+fn line_bounds(buffer: LispBufferRef, absolute: bool) -> (ptrdiff_t, ptrdiff_t) {
+    if absolute {
+        (BEG_BYTE, buffer.z_byte())
+    } else {
+        (buffer.begv_byte, buffer.zv_byte)
+    }
+}
+
+/// Return the line number at position POS.
+/// If POS is nil, use the current buffer location.
+/// Counting starts at (point-min), so the value refers to the contents
+/// of the accessible portion of the (potentially narrowed) buffer.  If
+/// ABSOLUTE is non-nil, ignore any narrowing and return the absolute
+/// line number.
+///
+/// This is like `line-number-at-pos', but looks the line up in an index
+/// of the newlines of the buffer, which is updated after the buffer
+/// changes, so it takes logarithmic time in the size of the buffer.
+#[lisp_fn(min = "0")]
+pub fn line_number_at_pos_fast(pos: LispObject, absolute: bool) -> EmacsInt {
+    let mut buffer = ThreadState::current_buffer();
+    let (start, end) = line_bounds(buffer, absolute);
+    let pos = if pos.is_nil() {
+        buffer.pt_byte
+    } else {
+        let pos = pos.as_number_coerce_marker_or_error().to_fixnum() as ptrdiff_t;
+        let (beg, end) = if absolute {
+            (buffer.beg(), buffer.z())
+        } else {
+            (buffer.begv, buffer.zv)
+        };
+        // Like `goto-char', move positions outside the bounds to them.
+        unsafe { buf_charpos_to_bytepos(buffer.as_mut(), pos.max(beg).min(end)) }
+    };
+    debug_assert!(start <= pos && pos <= end);
+
+    with_line_index(buffer, |index| {
+        (index.newlines_before(pos) - index.newlines_before(start) + 1) as EmacsInt
+    })
+}
+
+/// Return the position of the beginning of line number LINE.
+/// Counting starts at (point-min), as in `goto-line', unless ABSOLUTE is
+/// non-nil, in which case lines are counted from the beginning of the
+/// buffer, ignoring any narrowing.  Return nil if there are fewer than
+/// LINE lines.
+///
+/// The line is looked up in an index of the newlines of the buffer, so
+/// this takes logarithmic time in the size of the buffer.
+#[lisp_fn(min = "1")]
+pub fn position_of_line(line: EmacsInt, absolute: bool) -> Option<EmacsInt> {
+    if line < 1 {
+        args_out_of_range!(LispObject::from(line));
+    }
+
+    let mut buffer = ThreadState::current_buffer();
+    let (start, end) = line_bounds(buffer, absolute);
+    let bytepos = if line == 1 {
+        start
+    } else {
+        let newline = with_line_index(buffer, |index| {
+            let i = index.newlines_before(start) + (line - 2) as usize;
+            index.newlines.get(i).cloned()
+        });
+        match newline {
+            Some(newline) if newline < end => newline + 1,
+            _ => return None,
+        }
+    };
+    Some(unsafe { buf_bytepos_to_charpos(buffer.as_mut(), bytepos) } as EmacsInt)
+}
+
+#[test]
+fn test_newline_positions() {
+    let chunks = vec![&b"ab\nc"[..], &b"\n\nd"[..], &b""[..], &b"e\n"[..]];
+    assert_eq!(newline_positions(1, chunks.into_iter()), vec![3, 5, 6, 9]);
+    assert_eq!(newline_positions(1, vec![&b"abc"[..]].into_iter()), vec![]);
+}
+
+include!(concat!(env!("OUT_DIR"), "/line_index_exports.rs"));
@@ -3,6 +3,7 @@
 
 use libc::{c_char, c_void, intptr_t, uintptr_t};
 use std::ffi::CString;
+use std::iter::{FromIterator, Map};
 
 use std::convert::From;
 use std::fmt::{Debug, Error, Formatter};
@@ -13,14 +14,16 @@ use std::slice;
 use crate::{
     buffers::LispBufferRef,
     eval::FUNCTIONP,
-    lists::{list, CarIter, LispConsCircularChecks, LispConsEndChecks},
+    lists::{list, CarIter, LispCons, LispConsCircularChecks, LispConsEndChecks},
+    multibyte::{multibyte_char_at, Codepoint, LispStringRef},
     process::LispProcessRef,
     remacs_sys::{build_string, internal_equal, make_float},
     remacs_sys::{
         equal_kind, pvec_type, EmacsDouble, EmacsInt, EmacsUint, Lisp_Bits, USE_LSB_TAG, VALMASK,
     },
     remacs_sys::{Lisp_Misc_Any, Lisp_Misc_Type, Lisp_Subr, Lisp_Type},
-    remacs_sys::{Qautoload, Qnil, Qsubrp, Qt, Vbuffer_alist, Vprocess_alist},
+    remacs_sys::{Qautoload, Qnil, Qsequencep, Qsubrp, Qt, Vbuffer_alist, Vprocess_alist},
+    vectors::{LispBoolVecRef, LispVectorRef},
 };
 
 // TODO: tweak Makefile to rebuild C files if this changes.
@@ -271,6 +274,26 @@ where
     }
 }
 
+/// Collect the items of an iterator into a new list, in order.
+impl<T> FromIterator<T> for LispObject
+where
+    LispObject: From<T>,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> LispObject {
+        let mut head = Qnil;
+        let mut tail: Option<LispCons> = None;
+        for item in iter {
+            let cons = LispObject::cons(LispObject::from(item), Qnil);
+            match tail {
+                Some(tail) => tail.set_cdr(cons),
+                None => head = cons,
+            }
+            tail = cons.as_cons();
+        }
+        head
+    }
+}
+
 impl From<LispObject> for bool {
     fn from(o: LispObject) -> Self {
         o.is_not_nil()
@@ -506,6 +529,89 @@ macro_rules! impl_alistval_iter {
 impl_alistval_iter! {LiveBufferIter, LispBufferRef, unsafe { Vbuffer_alist }}
 impl_alistval_iter! {ProcessIter, LispProcessRef, unsafe { Vprocess_alist }}
 
+impl LispObject {
+    /// Iterate over the elements of the list self.  Signal an error if
+    /// self is not a proper list, or is circular.
+    pub fn iter(self) -> CarIter {
+        self.iter_cars(LispConsEndChecks::on, LispConsCircularChecks::on)
+    }
+
+    /// Iterate over the elements of the list self, as by `iter`,
+    /// converting each to T.  The conversion signals an error for an
+    /// element of the wrong type, as the `From<LispObject>` conversions
+    /// of `LispStringRef`, `LispSymbolRef` and the like do.
+    pub fn iter_as<T: From<LispObject>>(self) -> Map<CarIter, fn(LispObject) -> T> {
+        self.iter().map(T::from as fn(LispObject) -> T)
+    }
+
+    /// Iterate over the elements of the sequence self: a list, a vector,
+    /// a bool-vector or a string.  The elements of a bool-vector are t
+    /// and nil, and those of a string are its characters.
+    pub fn iter_sequence(self) -> SequenceIter {
+        if self.is_list() {
+            SequenceIter::List(self.iter())
+        } else if let Some(vector) = self.as_vector() {
+            SequenceIter::Vector(vector, 0)
+        } else if let Some(bool_vector) = self.as_bool_vector() {
+            SequenceIter::BoolVector(bool_vector, 0)
+        } else if let Some(string) = self.as_string() {
+            SequenceIter::String(string, 0)
+        } else {
+            wrong_type!(Qsequencep, self);
+        }
+    }
+}
+
+/// An iterator over the elements of a sequence, made by
+/// `LispObject::iter_sequence`.  Vectors and strings are indexed afresh
+/// for each element, so the iterator stays safe if they are changed
+/// while it runs.
+pub enum SequenceIter {
+    List(CarIter),
+    Vector(LispVectorRef, usize),
+    BoolVector(LispBoolVecRef, usize),
+    String(LispStringRef, usize),
+}
+
+impl Iterator for SequenceIter {
+    type Item = LispObject;
+
+    fn next(&mut self) -> Option<LispObject> {
+        match self {
+            SequenceIter::List(cars) => cars.next(),
+            SequenceIter::Vector(vector, index) => {
+                if *index >= vector.len() {
+                    return None;
+                }
+                *index += 1;
+                Some(unsafe { vector.get_unchecked(*index - 1) })
+            }
+            SequenceIter::BoolVector(bool_vector, index) => {
+                if *index >= bool_vector.len() {
+                    return None;
+                }
+                *index += 1;
+                Some(unsafe { bool_vector.get_unchecked(*index - 1) })
+            }
+            SequenceIter::String(string, pos) => {
+                let bytes = string.as_slice();
+                if *pos >= bytes.len() {
+                    return None;
+                }
+                let c = if string.is_multibyte() {
+                    let (c, len) = multibyte_char_at(&bytes[*pos..]);
+                    *pos += len;
+                    c
+                } else {
+                    *pos += 1;
+                    Codepoint::from(bytes[*pos - 1])
+                };
+                Some(LispObject::from(EmacsInt::from(c)))
+            }
+        }
+    }
+}
+
 pub fn is_autoload(function: LispObject) -> bool {
     function
         .as_cons()
@@ -697,3 +803,93 @@ fn test_lisp_misc_any_size() {
     // Should be 32 bits, which is 4 bytes.
     assert!(mem::size_of::<Lisp_Misc_Any>() == 4);
 }
+
+#[test]
+fn test_iter_list() {
+    let numbers: Vec<EmacsInt> = vec![1, 2, 3];
+    let list: LispObject = numbers.iter().cloned().collect();
+    assert!(list.iter().eq(numbers.iter().map(|&n| LispObject::from(n))));
+    assert_eq!(list.iter_as::<EmacsInt>().collect::<Vec<_>>(), numbers);
+    assert_eq!(
+        list.iter_sequence().map(EmacsInt::from).collect::<Vec<_>>(),
+        numbers
+    );
+
+    let empty: LispObject = Vec::<EmacsInt>::new().into_iter().collect();
+    assert!(empty.is_nil());
+    assert_eq!(empty.iter().count(), 0);
+    assert_eq!(empty.iter_sequence().count(), 0);
+}
+
+#[test]
+fn test_iter_vector() {
+    let elements = vec![LispObject::from_fixnum(4), Qnil, Qt];
+    let vector = mock_vector![elements[0], elements[1], elements[2]];
+    assert!(vector.iter_sequence().eq(elements.into_iter()));
+    assert_eq!(mock_vector![].iter_sequence().count(), 0);
+}
+
+#[test]
+fn test_iter_bool_vector() {
+    let bool_vector = mock_bool_vector!(&[true, false, false, true]);
+    assert_eq!(
+        bool_vector
+            .iter_sequence()
+            .map(bool::from)
+            .collect::<Vec<_>>(),
+        vec![true, false, false, true]
+    );
+    assert_eq!(mock_bool_vector!(&[]).iter_sequence().count(), 0);
+
+    // Bits past the first word.
+    let bits = (0..70).map(|i| i % 3 == 0).collect::<Vec<_>>();
+    assert_eq!(
+        mock_bool_vector!(&bits)
+            .iter_sequence()
+            .map(bool::from)
+            .collect::<Vec<_>>(),
+        bits
+    );
+}
+
+#[test]
+fn test_iter_string() {
+    let unibyte = mock_unibyte_string!(&b"a\xffb"[..]);
+    assert_eq!(
+        unibyte
+            .iter_sequence()
+            .map(EmacsInt::from)
+            .collect::<Vec<_>>(),
+        vec![0x61, 0xff, 0x62]
+    );
+    let multibyte = mock_multibyte_string!("aéλ");
+    assert_eq!(
+        multibyte
+            .iter_sequence()
+            .map(EmacsInt::from)
+            .collect::<Vec<_>>(),
+        vec![0x61, 0xe9, 0x3bb]
+    );
+}
+
+#[test]
+fn test_iter_string_while_mutating() {
+    let string = mock_multibyte_string!("abcd");
+    let mut chars = string.iter_sequence().map(EmacsInt::from);
+    assert_eq!(chars.next(), Some(0x61));
+
+    let mut contents = string.as_string().unwrap();
+    contents.set_byte(1, b'x');
+    assert_eq!(chars.next(), Some(0x78));
+
+    // Replacing a character with a wider one gives the string new data,
+    // which is read from where the iterator got to.
+    let replaced = mock_multibyte_string!("abé").as_string().unwrap();
+    unsafe {
+        contents.u.s.data = replaced.u.s.data;
+        contents.u.s.size = replaced.u.s.size;
+        contents.u.s.size_byte = replaced.u.s.size_byte;
+    }
+    assert_eq!(chars.next(), Some(0xe9));
+    assert_eq!(chars.next(), None);
+}
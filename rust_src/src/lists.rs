@@ -748,6 +748,19 @@ pub fn put(mut symbol: LispSymbolRef, propname: LispObject, value: LispObject) -
     value
 }
 
+/// Return the elements of SEQUENCE, a proper list or a vector, in
+/// order, or None if SEQUENCE is another kind of sequence.  A dotted
+/// or circular list signals an error rather than being walked forever.
+pub fn list_or_vector_elements(sequence: LispObject) -> Option<Vec<LispObject>> {
+    if sequence.is_list() {
+        Some(sequence.iter().collect())
+    } else {
+        sequence
+            .as_vector()
+            .map(|vector| vector.as_slice().to_vec())
+    }
+}
+
 /// Return a newly created list with specified arguments as elements.
 /// Any number of arguments, even zero arguments, are allowed.
 /// usage: (fn &rest OBJECTS)
@@ -774,24 +787,7 @@ pub fn safe_length(list: LispObject) -> usize {
         .count()
 }
 
-// Used by sort() in vectors.rs.
-
-pub fn sort_list(list: LispObject, pred: LispObject) -> LispObject {
-    let length = safe_length(list) as EmacsInt;
-    if length < 2 {
-        return list;
-    }
-
-    let item = nthcdr(length / 2 - 1, list);
-    let back = cdr(item);
-    setcdr(item.as_cons_or_error(), Qnil);
-
-    let front = sort_list(list, pred);
-    let back = sort_list(back, pred);
-    merge(front, back, pred)
-}
-
-// also needed by vectors.rs
+/// Return true if A may come before B, as PRED orders them.
 pub fn inorder(pred: LispObject, a: LispObject, b: LispObject) -> bool {
     call!(pred, b, a).is_nil()
 }
@@ -0,0 +1,203 @@
+//! Buffer text served from files mapped into memory.
+//!
+//! `insert-file-contents-mapped' makes the text of a unibyte buffer a
+//! private mapping of the file it visits, rather than a copy of it.  The
+//! pages of the file which are never looked at are never read, and those
+//! which are can be dropped and read again by the kernel, so browsing
+//! many large files takes little memory.  The mapping is followed by
+//! anonymous memory for the gap and the anchor byte after it.
+//!
+//! Buffer text which is mapped can be edited in place, but it can't
+//! grow or shrink; when it has to, buffer.c copies it to ordinary
+//! memory and releases the mapping with `unmap_buffer_text'.  The
+//! `mapped_size' of the `buffer_text' tells buffer.c which text is
+//! mapped.  Mappings are guarded against the file being truncated, so
+//! that what it loses reads as zeros.  Files are only mapped on Unix.
+
+use std::fs::File;
+use std::io;
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+#[cfg(unix)]
+use std::ptr;
+
+use libc::{c_char, c_uchar, ptrdiff_t};
+
+use remacs_macros::lisp_fn;
+
+#[cfg(unix)]
+use crate::sigbus;
+use crate::{
+    buffers::{BEG, BEG_BYTE},
+    fileio::encoded_file_path,
+    filelock::unlock_file,
+    lisp::defsubr,
+    lisp::LispObject,
+    multibyte::LispStringRef,
+    numbers::MOST_POSITIVE_FIXNUM,
+    remacs_sys::{encode_file_name, free_buffer_text, insert_from_gap, report_file_error},
+    remacs_sys::{EmacsInt, Qinsert_file_contents, Qnil},
+    remacs_sys::{Fexpand_file_name, Ffind_file_name_handler, Fset_visited_file_modtime},
+    threads::ThreadState,
+};
+
+/// The size of the gap after mapped text, which is as small as
+/// `compact-buffer' leaves gaps, so that it doesn't copy the text.
+const MAPPED_GAP_SIZE: usize = 20;
+
+/// Map the SIZE bytes of FILE into memory, followed by at least
+/// MAPPED_GAP_SIZE + 1 bytes of zeros, and guard the mapping.  Return
+/// the address and length of the mapping.
+#[cfg(unix)]
+fn map_file(file: &File, size: usize) -> io::Result<(*mut c_uchar, usize)> {
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let len = (size + MAPPED_GAP_SIZE + 1 + page_size - 1) / page_size * page_size;
+
+    // Reserve the whole length with anonymous memory first, so that the
+    // bytes after the end of the file are zeros, whatever page they are
+    // in, and then map the file over the start of it.
+    let addr = unsafe {
+        libc::mmap(
+            ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+    };
+    if addr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    let mapped = unsafe {
+        libc::mmap(
+            addr,
+            size,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_FIXED,
+            file.as_raw_fd(),
+            0,
+        )
+    };
+    if mapped == libc::MAP_FAILED {
+        let error = io::Error::last_os_error();
+        unsafe { libc::munmap(addr, len) };
+        return Err(error);
+    }
+
+    sigbus::guard_mapping(addr as *const u8, len);
+    Ok((addr as *mut c_uchar, len))
+}
+
+#[cfg(windows)]
+fn map_file(_file: &File, _size: usize) -> io::Result<(*mut c_uchar, usize)> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "files can't be mapped into buffers on this system",
+    ))
+}
+
+/// Release the mapping at BEG, of LEN bytes, which is no longer the text
+/// of a buffer.
+#[no_mangle]
+pub extern "C" fn unmap_buffer_text(beg: *mut c_uchar, len: ptrdiff_t) {
+    #[cfg(unix)]
+    {
+        sigbus::unguard_mapping(beg);
+        unsafe { libc::munmap(beg as *mut libc::c_void, len as usize) };
+    }
+    #[cfg(windows)]
+    {
+        let _ = (beg, len);
+    }
+}
+
+/// Visit FILENAME in the current buffer, mapping the file into memory.
+/// This is like `insert-file-contents-literally' with VISIT non-nil,
+/// except that the text of the buffer is the file itself, mapped into
+/// memory privately, instead of a copy of it.  The buffer must be empty
+/// and unibyte.  Return a list of the absolute file name and the number
+/// of characters inserted, as `insert-file-contents' does, or nil if
+/// FILENAME has a file name handler or isn't a regular file with some
+/// text in it, or if files can't be mapped on this system.
+///
+/// The text stays mapped until it is changed in a way that makes it grow
+/// or shrink, when it is copied into memory.  If another program
+/// truncates the file while it is mapped, the text it loses reads as
+/// zeros.
+#[lisp_fn]
+pub fn insert_file_contents_mapped(filename: LispStringRef) -> LispObject {
+    let mut buffer = ThreadState::current_buffer();
+    if buffer.base_buffer().is_some() || buffer.indirections > 0 {
+        error!("Cannot map a file into a buffer which shares its text");
+    }
+    if buffer.multibyte_characters_enabled() || buffer.z() > BEG {
+        error!("Cannot map a file into a non-empty or multibyte buffer");
+    }
+
+    let absname = unsafe { Fexpand_file_name(filename.into(), Qnil) };
+    let handler = unsafe { Ffind_file_name_handler(absname, Qinsert_file_contents) };
+    if handler.is_not_nil() {
+        return Qnil;
+    }
+
+    let encoded = unsafe { encode_file_name(absname) };
+    let file = match File::open(encoded_file_path(encoded)) {
+        Ok(file) => file,
+        Err(_) => unsafe {
+            report_file_error(
+                "Opening input file\0".as_ptr() as *const c_char,
+                filename.into(),
+            )
+        },
+    };
+    let size = match file.metadata() {
+        Ok(ref metadata) if metadata.is_file() && metadata.len() > 0 => metadata.len(),
+        _ => return Qnil,
+    };
+    if size > MOST_POSITIVE_FIXNUM as u64 {
+        error!("Maximum buffer size exceeded");
+    }
+    let size = size as usize;
+    let (addr, len) = match map_file(&file, size) {
+        Ok(mapping) => mapping,
+        Err(_) => return Qnil,
+    };
+
+    // As `insert-file-contents' does when visiting, don't run the change
+    // hooks, and leave the undo list empty if it was.
+    let empty_undo_list = buffer.undo_list_.is_nil();
+    unsafe {
+        free_buffer_text(buffer.as_mut());
+        let text = &mut *buffer.text;
+        text.beg = addr;
+        text.mapped_size = len as ptrdiff_t;
+        text.gpt = BEG;
+        text.gpt_byte = BEG_BYTE;
+        text.z = BEG;
+        text.z_byte = BEG_BYTE;
+        text.gap_size = (size + MAPPED_GAP_SIZE) as isize;
+        insert_from_gap(size as isize, size as isize, false);
+    }
+    if empty_undo_list {
+        buffer.undo_list_ = Qnil;
+    }
+
+    let truename = buffer.truename();
+    buffer.filename_ = absname;
+    unsafe {
+        Fset_visited_file_modtime(Qnil);
+        let text = &mut *buffer.text;
+        text.save_modiff = text.modiff;
+        buffer.auto_save_modified = text.modiff;
+    }
+    buffer.save_length_ = LispObject::from(size as EmacsInt);
+    if truename.is_not_nil() {
+        unlock_file(truename);
+    }
+    unlock_file(absname);
+
+    list!(absname, LispObject::from(size as EmacsInt))
+}
+
+include!(concat!(env!("OUT_DIR"), "/mapped_text_exports.rs"));
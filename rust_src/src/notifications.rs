@@ -0,0 +1,476 @@
+//! Desktop notifications.
+//!
+//! `notify-send' shows a notification with the means of the desktop:
+//! the notification server of a freedesktop.org desktop, over D-Bus,
+//! the notification center of macOS, or a toast on MS-Windows.  The
+//! notification server tells a thread when the user clicks an action
+//! button of a notification, or closes it.  The thread queues that and
+//! writes a byte to a pipe, which Emacs reads in its event loop, turning
+//! what was queued into `notification-event' events, which run the
+//! callbacks of the notifications.
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::{assq, delq, plist_get},
+    obarray::intern,
+    remacs_sys::{EmacsInt, Qnil, Qnotification_event},
+};
+
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+use std::cell::RefCell;
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+use std::collections::HashMap;
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+use std::mem;
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+use std::ptr;
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+use std::sync::atomic::AtomicIsize;
+#[cfg(any(target_os = "macos", windows))]
+use std::sync::atomic::AtomicUsize;
+#[cfg(any(target_os = "macos", windows, all(unix, feature = "dbusnotify")))]
+use std::sync::atomic::Ordering;
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+use std::sync::mpsc::channel;
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+use std::sync::Mutex;
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+use std::thread;
+
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+use dbus::{arg::Variant, BusType, Connection, ConnectionItem, Message};
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+use libc::{c_int, c_void};
+#[cfg(windows)]
+use winrt_notification::Toast;
+
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+use crate::remacs_sys::{add_read_fd, event_kind, input_event, kbd_buffer_store_event};
+
+// An alist of (ID ON-ACTION . ON-CLOSE) for the notifications with
+// callbacks, which keeps the callbacks alive until the notifications
+// are closed.
+declare_GC_protected_static!(notification_callbacks, Qnil);
+
+/// The name, path and interface of the notification server of
+/// freedesktop.org desktops.
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+const NOTIFICATIONS_SERVICE: &str = "org.freedesktop.Notifications";
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+
+/// Whether the callbacks of notifications are called on this system.
+const CALLBACKS_SUPPORTED: bool = cfg!(all(unix, not(target_os = "macos"), feature = "dbusnotify"));
+
+/// The identifier of the next notification, on the systems where Emacs
+/// numbers them itself.
+#[cfg(any(target_os = "macos", windows))]
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// The write end of the pipe which wakes Emacs up when something
+/// happened to a notification, or -1 before it is made.
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+static WAKEUP_FD: AtomicIsize = AtomicIsize::new(-1);
+
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+lazy_static! {
+    /// What happened to notifications since Emacs last looked, in order.
+    static ref PENDING: Mutex<Vec<(u32, Outcome)>> = Mutex::new(Vec::new());
+}
+
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+thread_local! {
+    /// The connection to the session bus notifications are shown over,
+    /// made for the first notification.  The signals of the
+    /// notification server are heard on another connection, by a thread
+    /// of its own.
+    static SESSION: RefCell<Option<Connection>> = RefCell::new(None);
+}
+
+/// A notification to show.  Not every system has a use for all of it.
+#[cfg_attr(
+    not(all(unix, not(target_os = "macos"), feature = "dbusnotify")),
+    allow(dead_code)
+)]
+struct Notification {
+    app_name: String,
+    title: String,
+    body: String,
+    icon: Option<String>,
+    urgency: u8,
+    timeout: i32,
+    actions: Vec<(String, String)>,
+    replaces_id: u32,
+}
+
+/// What happened to a notification: the user invoked the action with
+/// a key, or it was closed, for a reason.
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+enum Outcome {
+    Action(String),
+    Closed(&'static str),
+}
+
+/// Queue what happened to the notification ID, and wake Emacs up to
+/// handle it.  The pipe is non-blocking, and a full pipe already means
+/// that Emacs will look at the queue.
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+fn report(id: u32, outcome: Outcome) {
+    PENDING.lock().unwrap().push((id, outcome));
+    let fd = WAKEUP_FD.load(Ordering::SeqCst) as c_int;
+    unsafe { libc::write(fd, b"\0".as_ptr() as *const c_void, 1) };
+}
+
+/// Make the pipe which wakes Emacs up when something happened to a
+/// notification, and start reading it, unless that was already done.
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+fn make_wakeup_pipe() {
+    if WAKEUP_FD.load(Ordering::SeqCst) >= 0 {
+        return;
+    }
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        error!("Could not create notification pipe");
+    }
+    for &fd in &fds {
+        unsafe {
+            libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK);
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+    }
+    unsafe { add_read_fd(fds[0], Some(notifications_callback), ptr::null_mut()) };
+    WAKEUP_FD.store(fds[1] as isize, Ordering::SeqCst);
+}
+
+/// Turn what happened to notifications into `notification-event'
+/// events.  This is called when the pipe written to by the threads
+/// hearing from the notification servers is readable.
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+extern "C" fn notifications_callback(fd: c_int, _data: *mut c_void) {
+    let mut buffer = [0u8; 64];
+    while unsafe { libc::read(fd, buffer.as_mut_ptr() as *mut c_void, buffer.len()) } > 0 {}
+
+    let pending = mem::replace(&mut *PENDING.lock().unwrap(), Vec::new());
+    for (id, outcome) in pending {
+        let id = LispObject::from(EmacsInt::from(id));
+        // Notifications of other programs are reported too.
+        let entry = assq(id, unsafe { notification_callbacks });
+        let callbacks = match entry.as_cons() {
+            Some(entry) => entry.cdr().as_cons_or_error(),
+            None => continue,
+        };
+        let (callback, arg) = match outcome {
+            Outcome::Action(key) => (callbacks.car(), LispObject::from(key.as_str())),
+            Outcome::Closed(reason) => {
+                unsafe { notification_callbacks = delq(entry, notification_callbacks) };
+                (callbacks.cdr(), LispObject::from(intern(reason)))
+            }
+        };
+        if callback.is_nil() {
+            continue;
+        }
+
+        let mut event: input_event = unsafe { mem::zeroed() };
+        event.set_kind(event_kind::NOTIFICATION_EVENT);
+        event.arg = list!(callback, id, arg);
+        unsafe { kbd_buffer_store_event(&mut event) };
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+fn dbus_error(error: &dbus::Error) -> String {
+    error
+        .message()
+        .or_else(|| error.name())
+        .unwrap_or("D-Bus error")
+        .to_string()
+}
+
+/// Report the signal MESSAGE of the notification server, if it says
+/// that the user invoked an action of a notification, or that one was
+/// closed.
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+fn report_signal(message: &Message) {
+    let member = match message.member() {
+        Some(member) => member,
+        None => return,
+    };
+    match &*member {
+        "ActionInvoked" => {
+            if let (Some(id), Some(key)) = message.get2::<u32, String>() {
+                report(id, Outcome::Action(key));
+            }
+        }
+        "NotificationClosed" => {
+            if let (Some(id), Some(reason)) = message.get2::<u32, u32>() {
+                let reason = match reason {
+                    1 => "expired",
+                    2 => "dismissed",
+                    3 => "close-notification",
+                    _ => "undefined",
+                };
+                report(id, Outcome::Closed(reason));
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Start a thread listening to the signals of the notification server
+/// on a connection of its own.  Return once it listens, so that no
+/// signal about a notification shown after that is missed.
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+fn listen() -> Result<(), String> {
+    make_wakeup_pipe();
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        let rule = format!(
+            "type='signal',interface='{}',path='{}'",
+            NOTIFICATIONS_SERVICE, NOTIFICATIONS_PATH
+        );
+        let connection = match Connection::get_private(BusType::Session)
+            .and_then(|connection| connection.add_match(&rule).map(|()| connection))
+        {
+            Ok(connection) => connection,
+            Err(error) => {
+                let _ = sender.send(Err(dbus_error(&error)));
+                return;
+            }
+        };
+        let _ = sender.send(Ok(()));
+        // This only ends if the bus goes away.
+        for item in connection.iter(-1) {
+            if let ConnectionItem::Signal(message) = item {
+                report_signal(&message);
+            }
+        }
+    });
+    receiver
+        .recv()
+        .unwrap_or_else(|_| Err("D-Bus thread exited unexpectedly".to_string()))
+}
+
+/// Call the method MESSAGE on the session bus, connecting to it, and
+/// starting to listen to the signals of the notification server, if
+/// this is the first notification.
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+fn session_call(message: Message) -> Result<Message, String> {
+    SESSION.with(|session| {
+        let mut session = session.borrow_mut();
+        if session.is_none() {
+            listen()?;
+            let connection =
+                Connection::get_private(BusType::Session).map_err(|error| dbus_error(&error))?;
+            *session = Some(connection);
+        }
+        let connection = session.as_ref().unwrap();
+        // -1 is the default timeout of libdbus.
+        connection
+            .send_with_reply_and_block(message, -1)
+            .map_err(|error| dbus_error(&error))
+    })
+}
+
+#[cfg(all(unix, not(target_os = "macos"), feature = "dbusnotify"))]
+fn show(notification: Notification) -> Result<u32, String> {
+    let actions: Vec<&str> = notification
+        .actions
+        .iter()
+        .flat_map(|(key, title)| vec![key.as_str(), title.as_str()])
+        .collect();
+    let mut hints: HashMap<&str, Variant<u8>> = HashMap::new();
+    hints.insert("urgency", Variant(notification.urgency));
+    let message = Message::new_method_call(
+        NOTIFICATIONS_SERVICE,
+        NOTIFICATIONS_PATH,
+        NOTIFICATIONS_SERVICE,
+        "Notify",
+    )?
+    .append3(
+        notification.app_name.as_str(),
+        notification.replaces_id,
+        notification.icon.as_ref().map_or("", String::as_str),
+    )
+    .append3(
+        notification.title.as_str(),
+        notification.body.as_str(),
+        actions,
+    )
+    .append2(hints, notification.timeout);
+    session_call(message)?
+        .get1::<u32>()
+        .ok_or_else(|| "Invalid reply from the notification server".to_string())
+}
+
+/// The notification center shows the title and the body of a
+/// notification, but not its action buttons, and Emacs doesn't hear
+/// about clicks on it.
+#[cfg(target_os = "macos")]
+fn show(notification: Notification) -> Result<u32, String> {
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst) as u32;
+    // This fails after the first notification, as the application is
+    // already set.
+    let _ = mac_notification_sys::set_application("org.gnu.Emacs");
+    mac_notification_sys::send_notification(&notification.title, &None, &notification.body, &None)
+        .map_err(|error| error.to_string())?;
+    Ok(id)
+}
+
+/// A toast shows the title and the body of a notification, but not its
+/// action buttons.
+#[cfg(windows)]
+fn show(notification: Notification) -> Result<u32, String> {
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst) as u32;
+    Toast::new(Toast::POWERSHELL_APP_ID)
+        .title(&notification.title)
+        .text1(&notification.body)
+        .show()
+        .map_err(|error| format!("{:?}", error))?;
+    Ok(id)
+}
+
+#[cfg(not(any(target_os = "macos", windows, all(unix, feature = "dbusnotify"))))]
+fn show(_notification: Notification) -> Result<u32, String> {
+    error!("Emacs was built without support for desktop notifications");
+}
+
+/// Return the string OBJECT, or nil, as a Rust string.
+fn string_option(object: LispObject) -> Option<String> {
+    if object.is_nil() {
+        None
+    } else {
+        Some(object.as_string_or_error().to_string())
+    }
+}
+
+/// Show a desktop notification with TITLE and BODY, and return its id.
+/// The notification is shown with the means of the desktop: the
+/// notification server of freedesktop.org desktops, over D-Bus, the
+/// notification center of macOS, or a toast on MS-Windows.  BODY may be
+/// nil.
+///
+/// The arguments OPTIONS are a list of keyword/argument pairs.
+///
+/// :app-name NAME -- NAME is the name of the application, "Emacs" by
+/// default.
+///
+/// :icon ICON -- ICON is the name of an icon, or the file name of an
+/// image, to show in the notification.
+///
+/// :urgency URGENCY -- URGENCY is `low', `normal' or `critical'.
+///
+/// :timeout TIMEOUT -- TIMEOUT is how long the notification is shown,
+/// in milliseconds.  0 means until the user closes it, and -1, the
+/// default, means as long as the desktop shows notifications.
+///
+/// :actions ACTIONS -- ACTIONS is a list of action keys and titles,
+/// alternating, like ("ok" "Accept" "cancel" "Reject").  Each action
+/// is a button with its title.  The action "default" is invoked by
+/// clicking the notification itself.
+///
+/// :replaces-id ID -- ID is the id of a notification that this one
+/// replaces.
+///
+/// :on-action FUNCTION -- FUNCTION is called with the id of the
+/// notification and the key of the action, when the user invokes it.
+///
+/// :on-close FUNCTION -- FUNCTION is called with the id of the
+/// notification and the reason it was closed, `expired', `dismissed',
+/// `close-notification' or `undefined'.
+///
+/// The functions are called from `notification-event' events, so they
+/// run as Emacs waits for input.  They are only called for notifications
+/// shown over D-Bus; the notification center of macOS and the toasts of
+/// MS-Windows show neither action buttons nor tell Emacs about clicks.
+/// usage: (notify-send TITLE BODY &rest OPTIONS)
+#[lisp_fn(min = "2")]
+pub fn notify_send(args: &mut [LispObject]) -> EmacsInt {
+    let options = LispObject::from(args[2..].to_vec());
+    let option = |name| plist_get(options, intern(name).into());
+
+    let urgency = option(":urgency");
+    let urgency = if urgency.is_nil() || urgency.eq(intern("normal").into()) {
+        1
+    } else if urgency.eq(intern("low").into()) {
+        0
+    } else if urgency.eq(intern("critical").into()) {
+        2
+    } else {
+        error!("Invalid urgency: should be `low', `normal' or `critical'");
+    };
+    let timeout = option(":timeout");
+    let timeout = if timeout.is_nil() {
+        -1
+    } else {
+        timeout
+            .as_fixnum_or_error()
+            .max(-1)
+            .min(EmacsInt::from(i32::max_value())) as i32
+    };
+
+    let mut actions = Vec::new();
+    let mut list = option(":actions");
+    while list.is_not_nil() {
+        let (key, rest) = list.as_cons_or_error().as_tuple();
+        let (title, rest) = rest.as_cons_or_error().as_tuple();
+        actions.push((
+            key.as_string_or_error().to_string(),
+            title.as_string_or_error().to_string(),
+        ));
+        list = rest;
+    }
+
+    let replaces_id = option(":replaces-id");
+    let notification = Notification {
+        app_name: string_option(option(":app-name")).unwrap_or_else(|| "Emacs".to_string()),
+        title: args[0].as_string_or_error().to_string(),
+        body: string_option(args[1]).unwrap_or_default(),
+        icon: string_option(option(":icon")),
+        urgency,
+        timeout,
+        actions,
+        replaces_id: if replaces_id.is_nil() {
+            0
+        } else {
+            replaces_id.as_natnum_or_error() as u32
+        },
+    };
+    let id = match show(notification) {
+        Ok(id) => EmacsInt::from(id),
+        Err(message) => error!("Cannot show notification: {}", message),
+    };
+
+    let on_action = option(":on-action");
+    let on_close = option(":on-close");
+    unsafe {
+        let old = assq(LispObject::from(id), notification_callbacks);
+        notification_callbacks = delq(old, notification_callbacks);
+        if CALLBACKS_SUPPORTED && (on_action.is_not_nil() || on_close.is_not_nil()) {
+            notification_callbacks = LispObject::cons(
+                LispObject::cons(LispObject::from(id), LispObject::cons(on_action, on_close)),
+                notification_callbacks,
+            );
+        }
+    }
+    id
+}
+
+/// Handle a desktop notification event EVENT.
+/// EVENT is (notification-event FUNCTION ID ARG), and this calls
+/// FUNCTION with ID and ARG, as described in `notify-send'.
+#[lisp_fn(intspec = "e")]
+pub fn notify_send_handle_event(event: LispObject) -> LispObject {
+    let (kind, rest) = event.as_cons_or_error().as_tuple();
+    if !kind.eq(Qnotification_event) {
+        error!("Not a valid notification event");
+    }
+    let (function, rest) = rest.as_cons_or_error().as_tuple();
+    let (id, rest) = rest.as_cons_or_error().as_tuple();
+    let arg = rest.as_cons_or_error().car();
+    call!(function, id, arg)
+}
+
+include!(concat!(env!("OUT_DIR"), "/notifications_exports.rs"));
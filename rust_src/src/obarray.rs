@@ -10,7 +10,7 @@ use crate::{
         fatal_error_in_progress, globals, initial_obarray, initialized, intern_sym,
         make_pure_c_string, make_unibyte_string, oblookup,
     },
-    remacs_sys::{Fmake_symbol, Fpurecopy},
+    remacs_sys::{Fmake_symbol, Fmake_vector, Fpurecopy},
     remacs_sys::{Qnil, Qvectorp},
     symbols::LispSymbolRef,
 };
@@ -212,6 +212,17 @@ pub extern "C" fn intern_driver(
 #[lisp_fn(min = "1")]
 pub fn intern_soft(name: LispObject, obarray: Option<LispObarrayRef>) -> LispObject {
     let obarray = obarray.unwrap_or_else(LispObarrayRef::global);
+
+    // A symbol interned in the initial obarray is the canonical symbol
+    // with its name there, so it needn't be looked up.
+    if let Some(sym) = name.as_symbol() {
+        if sym.is_interned_in_initial_obarray()
+            && LispObject::from(&obarray).eq(unsafe { initial_obarray })
+        {
+            return name;
+        }
+    }
+
     let tem = obarray.lookup(name);
 
     if tem.is_integer() || (name.is_symbol() && !name.eq(tem)) {
@@ -236,6 +247,30 @@ pub fn lisp_intern(string: LispObject, obarray: LispObject) -> LispObject {
     obarray_ref.intern(string)
 }
 
+/// Intern each of the strings in the vector NAMES, and return a vector
+/// of the symbols, in the same order.
+/// This is like calling `intern' on each element of NAMES, but faster
+/// for the large tables of names read when loading autoloads or
+/// completion tables.  If an element of NAMES is not a string, nothing
+/// is interned.
+/// A second optional argument specifies the obarray to use;
+/// it defaults to the value of `obarray'.
+#[lisp_fn(min = "1")]
+pub fn intern_bulk(names: LispObject, obarray: Option<LispObarrayRef>) -> LispObject {
+    let names = names.as_vector_or_error();
+    let obarray = obarray.unwrap_or_else(LispObarrayRef::global);
+    for name in names.iter() {
+        name.as_string_or_error();
+    }
+
+    let result = unsafe { Fmake_vector(LispObject::from(names.len()), Qnil) };
+    let mut symbols = result.as_vector_or_error();
+    for (i, name) in names.iter().enumerate() {
+        symbols.set(i, obarray.intern(name));
+    }
+    result
+}
+
 extern "C" fn mapatoms_1(sym: LispObject, function: LispObject) {
     call!(function, sym);
 }
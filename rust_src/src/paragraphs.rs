@@ -0,0 +1,414 @@
+//! Scanning text by words, sentences and paragraphs.
+//!
+//! The paragraph and sentence motion commands of paragraphs.el, and the
+//! counting behind `count-words', which modeline widgets and the like call
+//! over whole buffers.  Paragraphs and sentences are still described by
+//! the regexps and margins of paragraphs.el and indent.el.
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    buffers::narrow_to_region,
+    cmds::{beginning_of_line, end_of_line, forward_char, forward_line},
+    editfns::{
+        bobp, bolp, constrain_to_field, eobp, goto_char, point, point_max, point_min,
+        save_excursion_save,
+    },
+    eval::unbind_to,
+    fns::concat,
+    lisp::defsubr,
+    lisp::LispObject,
+    numbers::LispNumber,
+    remacs_sys::{
+        current_column, record_unwind_protect, save_excursion_restore, save_restriction_restore,
+        save_restriction_save, scan_words, specbind,
+    },
+    remacs_sys::{EmacsInt, Fmake_char_table, Fregexp_quote, Fsubstring},
+    remacs_sys::{
+        Qcurrent_left_margin, Qend_of_paragraph_text, Qfill_prefix,
+        Qfind_word_boundary_function_table, Qhard, Qmove_to_left_margin, Qnil,
+        Qparagraph_ignore_fill_prefix, Qparagraph_separate, Qparagraph_start, Qsentence_end,
+        Qstart_of_paragraph_text, Qt, Quse_hard_newlines,
+    },
+    search::{looking_at, match_beginning, match_end, re_search_backward, re_search_forward},
+    symbols::symbol_value,
+    syntax::skip_chars_backward,
+    textprop::get_text_property,
+    threads::c_specpdl_index,
+};
+
+/// Return the number of words between START and END.
+/// Words are as `forward-word-strictly' finds them, by the syntax table
+/// alone, so `find-word-boundary-function-table' is ignored, and so are
+/// field boundaries.  A word which straddles START or END counts.  This
+/// is the work of `count-words', in a single pass over the text.
+#[lisp_fn]
+pub fn count_words_fast(start: LispObject, end: LispObject) -> EmacsInt {
+    let count = c_specpdl_index();
+    unsafe {
+        record_unwind_protect(Some(save_restriction_restore), save_restriction_save());
+        specbind(
+            Qfind_word_boundary_function_table,
+            Fmake_char_table(Qnil, Qnil),
+        );
+    }
+    narrow_to_region(start, end);
+
+    let mut words = 0;
+    let mut pos = point_min() as isize;
+    loop {
+        match unsafe { scan_words(pos, 1) } {
+            0 => break,
+            next => {
+                words += 1;
+                pos = next;
+            }
+        }
+    }
+
+    unbind_to(count, Qnil);
+    words
+}
+
+/// Call F with point saved, as `save-excursion' does.
+fn save_excursion<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    let count = c_specpdl_index();
+    unsafe { record_unwind_protect(Some(save_excursion_restore), save_excursion_save()) };
+    let result = f();
+    unbind_to(count, Qnil);
+    result
+}
+
+fn looking(regexp: LispObject) -> bool {
+    looking_at(regexp).is_not_nil()
+}
+
+fn move_to_left_margin() {
+    call!(Qmove_to_left_margin);
+}
+
+/// Move to the left margin, and return true if that isn't the end of
+/// the buffer.
+fn left_margin_before_eob() -> bool {
+    move_to_left_margin();
+    !eobp()
+}
+
+fn hard_newline_before(pos: EmacsInt) -> bool {
+    get_text_property((pos - 1).into(), Qhard, Qnil).is_not_nil()
+}
+
+/// Return REGEXP without a leading `^', since the paragraph regexps are
+/// matched at the left margin rather than at the beginning of the line.
+fn unanchored(regexp: LispObject) -> LispObject {
+    let string = regexp.as_string_or_error();
+    if string.len_bytes() > 0 && string.byte_at(0) == b'^' {
+        unsafe { Fsubstring(regexp, LispObject::from(1), Qnil) }
+    } else {
+        regexp
+    }
+}
+
+/// The regexps which say where paragraphs start and end.
+struct ParagraphRegexps {
+    /// The quoted `fill-prefix', if it overrides `paragraph-start'.
+    fill_prefix: Option<LispObject>,
+    /// `paragraph-start', unanchored.
+    start: LispObject,
+    /// `paragraph-separate', unanchored, or lines of just the fill prefix.
+    separate: LispObject,
+    /// Lines which start or separate paragraphs, for searching.
+    start_or_separate: LispObject,
+}
+
+impl ParagraphRegexps {
+    fn new() -> Self {
+        let prefix = symbol_value(Qfill_prefix.into());
+        let fill_prefix = if prefix.as_string().map_or(false, |s| s.len_chars() > 0)
+            && symbol_value(Qparagraph_ignore_fill_prefix.into()).is_nil()
+        {
+            Some(unsafe { Fregexp_quote(prefix) })
+        } else {
+            None
+        };
+        let start = unanchored(symbol_value(Qparagraph_start.into()));
+        let separate = unanchored(symbol_value(Qparagraph_separate.into()));
+        let separate = match fill_prefix {
+            Some(prefix) => concat(&mut [
+                separate,
+                LispObject::from("\\|"),
+                prefix,
+                LispObject::from("[ \t]*$"),
+            ]),
+            None => separate,
+        };
+        let start_or_separate = concat(&mut [
+            LispObject::from("^[ \t]*\\(?:"),
+            start,
+            LispObject::from("\\|"),
+            separate,
+            LispObject::from("\\)"),
+        ]);
+        ParagraphRegexps {
+            fill_prefix,
+            start,
+            separate,
+            start_or_separate,
+        }
+    }
+
+    /// Move to the left margin, and return true if the line separates
+    /// paragraphs.
+    fn at_separator(&self) -> bool {
+        move_to_left_margin();
+        looking(self.separate)
+    }
+}
+
+/// Move back over one paragraph, as `forward-paragraph' with a negative
+/// argument does, and return true if there was one.
+///
+/// FOUND_START is whether the last search found a line which starts or
+/// separates paragraphs, which `forward-paragraph' remembers from one
+/// paragraph to the next.
+fn backward_one_paragraph(
+    regexps: &ParagraphRegexps,
+    use_hard_newlines: bool,
+    found_start: &mut bool,
+) -> bool {
+    let bound = LispObject::from((point() - 1).max(point_min()));
+    if !looking(regexps.separate)
+        && re_search_backward(LispObject::from("^\n"), bound, Qt, Qnil).is_not_nil()
+        && looking(regexps.separate)
+    {
+        return true;
+    }
+
+    // Move back over paragraph-separating lines.
+    forward_char(LispObject::from(-1));
+    beginning_of_line(None);
+    while !bobp() && regexps.at_separator() {
+        forward_line(Some(-1));
+    }
+    if bobp() {
+        return false;
+    }
+
+    // Go to the end of the previous (non-separating) line, and search back
+    // for a line that starts or separates paragraphs.
+    end_of_line(None);
+    let found = match regexps.fill_prefix {
+        // There is a fill prefix; it overrides `paragraph-start'.
+        Some(prefix) => {
+            loop {
+                beginning_of_line(None);
+                if bobp() || regexps.at_separator() || !looking(prefix) {
+                    break;
+                }
+                forward_line(Some(-1));
+            }
+            move_to_left_margin();
+            !bobp()
+        }
+        None => {
+            while re_search_backward(regexps.start_or_separate, Qnil, LispObject::from(1), Qnil)
+                .is_not_nil()
+            {
+                *found_start = true;
+                // Found a candidate, but need to check if it is a real
+                // start.
+                let start = point();
+                if regexps.at_separator()
+                    || (looking(regexps.start)
+                        && (!use_hard_newlines || bobp() || hard_newline_before(start)))
+                {
+                    break;
+                }
+                *found_start = false;
+                goto_char(start.into());
+            }
+            *found_start
+        }
+    };
+
+    if found {
+        // Move forward over paragraph separators.  This cannot reach the
+        // place we started, because we moved back over a non-separator.
+        while !eobp() && regexps.at_separator() {
+            forward_line(Some(1));
+        }
+        // If the line before the paragraph is just margin, back up to it.
+        end_of_line(Some(0));
+        if unsafe { current_column() } as EmacsInt > current_left_margin() {
+            forward_char(LispObject::from(1));
+        } else {
+            skip_chars_backward(LispObject::from(" \t"), Qnil);
+            if !bolp() {
+                forward_line(Some(1));
+            }
+        }
+    } else {
+        // No starter or separator line means the paragraph starts at the
+        // beginning of the buffer.
+        goto_char(point_min().into());
+    }
+    true
+}
+
+/// Move forward over one paragraph, as `forward-paragraph' does, and
+/// return true if there was one.
+fn forward_one_paragraph(regexps: &ParagraphRegexps, use_hard_newlines: bool) -> bool {
+    // Move forward over separator lines...
+    while !eobp() && left_margin_before_eob() && looking(regexps.separate) {
+        forward_line(Some(1));
+    }
+    let moved = !eobp();
+    // ... and one more line.
+    forward_line(Some(1));
+
+    match regexps.fill_prefix {
+        // There is a fill prefix; it overrides `paragraph-start'.
+        Some(prefix) => {
+            while !eobp()
+                && left_margin_before_eob()
+                && !looking(regexps.separate)
+                && looking(prefix)
+            {
+                forward_line(Some(1));
+            }
+        }
+        None => {
+            let mut start = point();
+            while re_search_forward(regexps.start_or_separate, Qnil, LispObject::from(1), Qnil)
+                .is_not_nil()
+            {
+                start = match_beginning(LispObject::from(0)).as_fixnum_or_error();
+                goto_char(start.into());
+                if eobp()
+                    || regexps.at_separator()
+                    || (looking(regexps.start)
+                        && (!use_hard_newlines || hard_newline_before(start)))
+                {
+                    break;
+                }
+                forward_char(LispObject::from(1));
+            }
+            if point() < point_max() {
+                goto_char(start.into());
+            }
+        }
+    }
+    moved
+}
+
+fn current_left_margin() -> EmacsInt {
+    call!(Qcurrent_left_margin).as_fixnum_or_error()
+}
+
+/// Move forward to end of paragraph.
+/// With argument ARG, do it ARG times;
+/// a negative argument ARG = -N means move backward N paragraphs.
+///
+/// A line which `paragraph-start' matches either separates paragraphs
+/// (if `paragraph-separate' matches it also) or is the first line of a paragraph.
+/// A paragraph end is the beginning of a line which is not part of the paragraph
+/// to which the end of the previous line belongs, or the end of the buffer.
+/// Returns the count of paragraphs left to move.
+#[lisp_fn(min = "0", intspec = "^p")]
+pub fn forward_paragraph(arg: Option<EmacsInt>) -> EmacsInt {
+    let mut arg = arg.unwrap_or(1);
+    let opoint = point();
+    let regexps = ParagraphRegexps::new();
+    let use_hard_newlines = symbol_value(Quse_hard_newlines.into()).is_not_nil();
+    let mut found_start = false;
+
+    while arg < 0 && !bobp() {
+        if backward_one_paragraph(&regexps, use_hard_newlines, &mut found_start) {
+            arg += 1;
+        }
+    }
+    while arg > 0 && !eobp() {
+        if forward_one_paragraph(&regexps, use_hard_newlines) {
+            arg -= 1;
+        }
+    }
+
+    constrain_to_field(None, LispNumber::Fixnum(opoint), true, false, Qnil);
+    // Return the number of steps that could not be done.
+    arg
+}
+
+/// Move forward to next end of sentence.  With argument, repeat.
+/// With negative argument, move backward repeatedly to start of sentence.
+///
+/// The variable `sentence-end' is a regular expression that matches ends of
+/// sentences.  Also, every paragraph boundary terminates sentences as well.
+#[lisp_fn(min = "0", intspec = "^p")]
+pub fn forward_sentence(arg: Option<EmacsInt>) -> EmacsInt {
+    let mut arg = arg.unwrap_or(1);
+    let opoint = point();
+    let sentence_end = call!(Qsentence_end);
+
+    while arg < 0 {
+        let pos = point();
+        let (par_beg, par_text_beg) = save_excursion(|| {
+            call!(Qstart_of_paragraph_text);
+            // The start of the real text of the paragraph is where we move
+            // back to if there is no sentence end.
+            let par_text_beg = point();
+            // The start of the first line of the paragraph is the limit of
+            // the search, so that `sentence-end' may match if it is
+            // anchored at the beginning of a line and the paragraph starts
+            // indented.
+            beginning_of_line(None);
+            (point(), par_text_beg)
+        });
+        let par_beg = LispObject::from(par_beg);
+        if re_search_backward(sentence_end, par_beg, Qt, Qnil).is_not_nil()
+            && (match_end(LispObject::from(0)).as_fixnum_or_error() < pos
+                || re_search_backward(sentence_end, par_beg, Qt, Qnil).is_not_nil())
+        {
+            goto_char(match_end(LispObject::from(0)));
+        } else {
+            goto_char(par_text_beg.into());
+        }
+        arg += 1;
+    }
+
+    while arg > 0 {
+        let par_end = save_excursion(|| {
+            call!(Qend_of_paragraph_text);
+            point()
+        });
+        if re_search_forward(sentence_end, par_end.into(), Qt, Qnil).is_not_nil() {
+            skip_chars_backward(LispObject::from(" \t\n"), Qnil);
+        } else {
+            goto_char(par_end.into());
+        }
+        arg -= 1;
+    }
+
+    constrain_to_field(None, LispNumber::Fixnum(opoint), true, false, Qnil)
+}
+
+#[no_mangle]
+pub extern "C" fn syms_of_paragraphs() {
+    def_lisp_sym!(Qcurrent_left_margin, "current-left-margin");
+    def_lisp_sym!(Qend_of_paragraph_text, "end-of-paragraph-text");
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    def_lisp_sym!(Qfind_word_boundary_function_table, "find-word-boundary-function-table");
+    def_lisp_sym!(Qhard, "hard");
+    def_lisp_sym!(Qmove_to_left_margin, "move-to-left-margin");
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    def_lisp_sym!(Qparagraph_ignore_fill_prefix, "paragraph-ignore-fill-prefix");
+    def_lisp_sym!(Qparagraph_separate, "paragraph-separate");
+    def_lisp_sym!(Qparagraph_start, "paragraph-start");
+    def_lisp_sym!(Qsentence_end, "sentence-end");
+    def_lisp_sym!(Qstart_of_paragraph_text, "start-of-paragraph-text");
+    def_lisp_sym!(Quse_hard_newlines, "use-hard-newlines");
+}
+
+include!(concat!(env!("OUT_DIR"), "/paragraphs_exports.rs"));
@@ -0,0 +1,297 @@
+//! Perl-compatible regular expressions.
+//!
+//! These functions take patterns in the syntax of PCRE rather than of
+//! Emacs, including lookahead, lookbehind and back-references, and set
+//! the match data like their Emacs counterparts.
+
+use std::sync::{Arc, Mutex};
+
+use fancy_regex::{Captures, Regex};
+use libc::ptrdiff_t;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    buffers::{current_buffer_text, validate_region},
+    json::json_make_string,
+    lisp::defsubr,
+    lisp::LispObject,
+    marker::point_marker,
+    multibyte::LispStringRef,
+    remacs_sys::{buf_bytepos_to_charpos, buf_charpos_to_bytepos, unchain_marker, EmacsInt},
+    remacs_sys::{Freplace_match, Qinvalid_regexp, Qnil, Qutf_8_string_p},
+    search::{changing_match_data, search_failed, search_limit, set_match_groups},
+    threads::ThreadState,
+};
+
+/// The number of compiled patterns kept by `compile_pcre'.
+const PCRE_CACHE_SIZE: usize = 20;
+
+lazy_static! {
+    /// The most recently used patterns, most recent first, by pattern
+    /// and whether case is ignored.
+    static ref PCRE_CACHE: Mutex<Vec<(String, bool, Arc<Regex>)>> =
+        Mutex::new(Vec::with_capacity(PCRE_CACHE_SIZE));
+}
+
+/// Compile the PCRE pattern REGEXP, ignoring case if `case-fold-search'
+/// is non-nil, signaling `invalid-regexp' if it isn't valid.
+fn compile_pcre(regexp: LispStringRef) -> Arc<Regex> {
+    let pattern = pcre_text(regexp);
+    let case_fold = ThreadState::current_buffer()
+        .case_fold_search()
+        .is_not_nil();
+
+    let mut cache = PCRE_CACHE.lock().unwrap();
+    if let Some(i) = cache
+        .iter()
+        .position(|(p, fold, _)| *p == pattern && *fold == case_fold)
+    {
+        let entry = cache.remove(i);
+        let regex = Arc::clone(&entry.2);
+        cache.insert(0, entry);
+        return regex;
+    }
+
+    let source = if case_fold {
+        format!("(?i){}", pattern)
+    } else {
+        pattern.clone()
+    };
+    let regex = match Regex::new(&source) {
+        Ok(regex) => Arc::new(regex),
+        Err(error) => {
+            drop(cache);
+            xsignal!(Qinvalid_regexp, json_make_string(&format!("{:?}", error)));
+        }
+    };
+    cache.truncate(PCRE_CACHE_SIZE - 1);
+    cache.insert(0, (pattern, case_fold, Arc::clone(&regex)));
+    regex
+}
+
+/// Return the text of STRING.  Each byte of a unibyte string is a
+/// character; a multibyte string must not contain raw bytes.
+fn pcre_text(string: LispStringRef) -> String {
+    if string.is_multibyte() {
+        match std::str::from_utf8(string.as_slice()) {
+            Ok(text) => text.to_string(),
+            Err(_) => wrong_type!(Qutf_8_string_p, LispObject::from(string)),
+        }
+    } else {
+        string.as_slice().iter().map(|&b| char::from(b)).collect()
+    }
+}
+
+/// Return the captures of the first match of REGEX in TEXT which starts
+/// at or after the byte offset START.
+fn pcre_captures<'t>(regex: &Regex, text: &'t str, start: usize) -> Option<Captures<'t>> {
+    match regex.captures_from_pos(text, start) {
+        Ok(captures) => captures,
+        Err(error) => error!("PCRE matching failed: {:?}", error),
+    }
+}
+
+/// Return the text of the current buffer between the byte positions
+/// START and END.
+fn pcre_buffer_text<'a>(start: ptrdiff_t, end: ptrdiff_t) -> &'a str {
+    if !ThreadState::current_buffer().multibyte_characters_enabled() {
+        error!("PCRE functions can be called only in multibyte buffers");
+    }
+    match std::str::from_utf8(current_buffer_text(start, end)) {
+        Ok(text) => text,
+        Err(_) => error!("Buffer text contains raw bytes"),
+    }
+}
+
+/// Return index of start of first match for the PCRE pattern REGEXP in
+/// STRING, or nil.
+/// This is like `string-match', except that REGEXP uses the syntax of
+/// Perl-compatible regular expressions, which includes lookahead,
+/// lookbehind and non-greedy operators, and named groups.  Matching
+/// ignores case if `case-fold-search' is non-nil.
+/// If third arg START is non-nil, start search at that index in STRING;
+/// lookbehind assertions still see the text before START.
+///
+/// `match-beginning', `match-end' and `match-string' give the indices
+/// of the substrings matched by the groups of REGEXP, numbered in the
+/// order of their opening parentheses.
+#[lisp_fn(min = "2")]
+pub fn pcre_match_string(
+    regexp: LispStringRef,
+    string: LispStringRef,
+    start: Option<EmacsInt>,
+) -> Option<EmacsInt> {
+    let regex = compile_pcre(regexp);
+    let text = pcre_text(string);
+
+    let nchars = text.chars().count() as EmacsInt;
+    let start_char = match start {
+        None => 0,
+        Some(start) if start < 0 => nchars + start,
+        Some(start) => start,
+    };
+    if start_char < 0 || start_char > nchars {
+        args_out_of_range!(
+            LispObject::from(string),
+            start.map_or(Qnil, LispObject::from)
+        );
+    }
+    let start_byte = text
+        .char_indices()
+        .nth(start_char as usize)
+        .map_or(text.len(), |(i, _)| i);
+
+    let captures = pcre_captures(&regex, &text, start_byte)?;
+    let char_index = |offset: usize| text[..offset].chars().count() as ptrdiff_t;
+    if changing_match_data() {
+        set_match_groups(
+            (0..captures.len())
+                .map(|i| captures.get(i).map(|(s, e)| (char_index(s), char_index(e)))),
+        );
+    }
+    captures.get(0).map(|(s, _)| char_index(s) as EmacsInt)
+}
+
+/// Search forward from point for the PCRE pattern REGEXP.
+/// Set point to the end of the occurrence found, and return point.
+/// This is like `re-search-forward', except that REGEXP uses the syntax
+/// of Perl-compatible regular expressions; see `pcre-match-string'.
+/// The optional second argument BOUND is a buffer position that bounds
+///   the search.  The match found must not end after that position, and
+///   lookahead assertions don't see the text after it.  A value of nil
+///   means search to the end of the accessible portion of the buffer.
+/// The optional third argument NOERROR indicates how errors are handled
+///   when the search fails, as for `re-search-forward'.
+///
+/// The buffer must be multibyte, and the text from the start of its
+/// accessible portion to BOUND must not contain raw bytes.
+#[lisp_fn(min = "1")]
+pub fn pcre_search_forward(
+    regexp: LispStringRef,
+    bound: LispObject,
+    noerror: LispObject,
+) -> LispObject {
+    let regex = compile_pcre(regexp);
+
+    let mut buffer = ThreadState::current_buffer();
+    let (lim, lim_byte) = search_limit(bound);
+
+    let begv_byte = buffer.begv_byte;
+    let text = pcre_buffer_text(begv_byte, lim_byte);
+    let start = (buffer.pt_byte - begv_byte) as usize;
+    let captures = match pcre_captures(&regex, text, start) {
+        Some(captures) => captures,
+        None => return search_failed(LispObject::from(regexp), noerror, lim, lim_byte),
+    };
+
+    let mut charpos = |offset: usize| unsafe {
+        buf_bytepos_to_charpos(buffer.as_mut(), begv_byte + offset as ptrdiff_t)
+    };
+    if changing_match_data() {
+        set_match_groups(
+            (0..captures.len()).map(|i| captures.get(i).map(|(s, e)| (charpos(s), charpos(e)))),
+        );
+    }
+    let end_byte = begv_byte + captures.get(0).unwrap().1 as ptrdiff_t;
+    let end = charpos(captures.get(0).unwrap().1);
+    buffer.set_pt_both(end, end_byte);
+    LispObject::from(end)
+}
+
+/// Replace every match for the PCRE pattern REGEXP with REPLACEMENT.
+/// Only the matches between START and END are replaced, which default
+/// to point and the end of the accessible portion of the buffer.
+/// Return the number of replacements made.
+///
+/// REPLACEMENT is interpreted as by `replace-match', so that `\\&'
+/// stands for the whole match and `\\N' for the Nth group of REGEXP,
+/// and the case of the replacement follows that of the match if
+/// `case-fold-search' is non-nil.  Matches don't overlap, and an empty
+/// match is not replaced right after a previous match.  As with
+/// `replace-regexp', point is left at the end of the last replacement.
+#[lisp_fn(min = "2")]
+pub fn pcre_replace_regexp(
+    regexp: LispStringRef,
+    replacement: LispStringRef,
+    start: LispObject,
+    end: LispObject,
+) -> EmacsInt {
+    let regex = compile_pcre(regexp);
+
+    let mut buffer = ThreadState::current_buffer();
+    let mut start = if start.is_nil() {
+        LispObject::from(buffer.pt)
+    } else {
+        start
+    };
+    let mut end = if end.is_nil() {
+        LispObject::from(buffer.zv)
+    } else {
+        end
+    };
+    unsafe { validate_region(&mut start, &mut end) };
+    let start = start.as_fixnum_or_error() as ptrdiff_t;
+    let end = end.as_fixnum_or_error() as ptrdiff_t;
+    let start_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), start) };
+    let end_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), end) };
+
+    // Find all the matches before changing the buffer.
+    let begv_byte = buffer.begv_byte;
+    let text = pcre_buffer_text(begv_byte, end_byte);
+    let mut matches = Vec::new();
+    let mut pos = (start_byte - begv_byte) as usize;
+    let mut last_end = None;
+    while pos <= text.len() {
+        let captures = match pcre_captures(&regex, text, pos) {
+            Some(captures) => captures,
+            None => break,
+        };
+        let (match_start, match_end) = captures.get(0).unwrap();
+        if match_start == match_end {
+            // Step over the next character after an empty match.
+            pos = match_end + text[match_end..].chars().next().map_or(1, char::len_utf8);
+            if last_end == Some(match_end) {
+                continue;
+            }
+        } else {
+            pos = match_end;
+        }
+        last_end = Some(match_end);
+        let groups: Vec<_> = (0..captures.len()).map(|i| captures.get(i)).collect();
+        matches.push(groups);
+    }
+
+    let mut charpos = |offset: usize| unsafe {
+        buf_bytepos_to_charpos(buffer.as_mut(), begv_byte + offset as ptrdiff_t)
+    };
+    let matches: Vec<Vec<_>> = matches
+        .into_iter()
+        .map(|groups| {
+            groups
+                .into_iter()
+                .map(|group| group.map(|(s, e)| (charpos(s), charpos(e))))
+                .collect()
+        })
+        .collect();
+
+    // Replace from the end, so that the positions of earlier matches
+    // remain valid.  A marker keeps track of the end of the last
+    // replacement.
+    let count = matches.len();
+    let mut last_replacement = None;
+    for groups in matches.into_iter().rev() {
+        set_match_groups(groups);
+        unsafe { Freplace_match(LispObject::from(replacement), Qnil, Qnil, Qnil, Qnil) };
+        if last_replacement.is_none() {
+            last_replacement = Some(point_marker().as_marker_or_error());
+        }
+    }
+    if let Some(mut marker) = last_replacement {
+        buffer.set_pt_both(marker.charpos_or_error(), marker.bytepos_or_error());
+        unsafe { unchain_marker(marker.as_mut()) };
+    }
+    count as EmacsInt
+}
+
+include!(concat!(env!("OUT_DIR"), "/pcre_exports.rs"));
@@ -0,0 +1,324 @@
+//! Persistent vectors and maps, over the im crate.
+//!
+//! A persistent vector or map is never changed in place: the functions
+//! that "change" one return a new one, which shares most of its
+//! structure with the old.  Both are records holding the number of a
+//! collection on the Rust side, which a finalizer drops once the record
+//! is garbage.  The Lisp objects in the collections are marked by
+//! `mark_persistent', which the garbage collector calls with its other
+//! roots, so a collection that holds its own record is never freed.
+
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+
+use im::{HashMap as PMap, Vector as PVector};
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    data::aref,
+    lisp::defsubr,
+    lisp::LispObject,
+    remacs_sys::{mark_object, sxhash, EmacsInt, Fmake_finalizer, Frecord},
+    remacs_sys::{Qclosure, Qnil, Qt},
+    remacs_sys::{Qpersistent__release, Qpmap, Qpmap_p, Qpvector, Qpvector_p},
+    vectors::recordp,
+};
+
+/// A key of a persistent map.  Keys are compared with `equal'.
+#[derive(Clone, Copy)]
+struct MapKey(LispObject);
+
+impl Hash for MapKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(unsafe { sxhash(self.0, 0) });
+    }
+}
+
+impl PartialEq for MapKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.equal(other.0)
+    }
+}
+
+impl Eq for MapKey {}
+
+enum Collection {
+    Vector(PVector<LispObject>),
+    Map(PMap<MapKey, LispObject>),
+}
+
+struct Collections {
+    next_id: EmacsInt,
+    collections: HashMap<EmacsInt, Collection>,
+}
+
+lazy_static! {
+    /// The live persistent collections, by number.
+    static ref PERSISTENT: Mutex<Collections> = Mutex::new(Collections {
+        next_id: 0,
+        collections: HashMap::new(),
+    });
+}
+
+/// Mark the Lisp objects held by the persistent collections.
+#[no_mangle]
+pub extern "C" fn mark_persistent() {
+    let persistent = PERSISTENT.lock().unwrap();
+    for collection in persistent.collections.values() {
+        match collection {
+            Collection::Vector(vector) => {
+                for &object in vector.iter() {
+                    unsafe { mark_object(object) };
+                }
+            }
+            Collection::Map(map) => {
+                for &(key, value) in map.iter() {
+                    unsafe {
+                        mark_object(key.0);
+                        mark_object(value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Return a new record of type TYPE for COLLECTION.
+fn make_persistent(type_: LispObject, collection: Collection) -> LispObject {
+    let id = {
+        let mut persistent = PERSISTENT.lock().unwrap();
+        persistent.next_id += 1;
+        let id = persistent.next_id;
+        persistent.collections.insert(id, collection);
+        id
+    };
+
+    // Drop the collection once the record becomes garbage.
+    let release = list!(
+        Qclosure,
+        list!(Qt),
+        Qnil,
+        list!(Qpersistent__release, LispObject::from(id))
+    );
+    let finalizer = unsafe { Fmake_finalizer(release) };
+    callN_raw!(Frecord, type_, LispObject::from(id), finalizer)
+}
+
+/// Return a copy of the collection of the record OBJECT.  Copies share
+/// their structure, so this is cheap, and lets the collection be used
+/// without holding the lock while `equal' may signal.
+fn persistent_collection(object: LispObject) -> Option<Collection> {
+    let id = aref(object, 1).as_fixnum_or_error();
+    let persistent = PERSISTENT.lock().unwrap();
+    persistent
+        .collections
+        .get(&id)
+        .map(|collection| match collection {
+            Collection::Vector(vector) => Collection::Vector(vector.clone()),
+            Collection::Map(map) => Collection::Map(map.clone()),
+        })
+}
+
+/// Return t if OBJECT is a persistent vector.
+#[lisp_fn]
+pub fn pvector_p(object: LispObject) -> bool {
+    recordp(object) && aref(object, 0) == Qpvector
+}
+
+fn pvector_value(pvector: LispObject) -> PVector<LispObject> {
+    if !pvector_p(pvector) {
+        wrong_type!(Qpvector_p, pvector);
+    }
+    match persistent_collection(pvector) {
+        Some(Collection::Vector(vector)) => vector,
+        _ => error!("Persistent vector has been released"),
+    }
+}
+
+fn make_pvector(vector: PVector<LispObject>) -> LispObject {
+    make_persistent(Qpvector, Collection::Vector(vector))
+}
+
+/// Return the index N of the persistent vector PVECTOR, which has
+/// LENGTH elements, signaling an error unless N < LENGTH, or N <=
+/// LENGTH if END is true.
+fn pvector_index(pvector: LispObject, n: EmacsInt, length: usize, end: bool) -> usize {
+    let limit = if end { length + 1 } else { length };
+    if n < 0 || n as usize >= limit {
+        args_out_of_range!(pvector, LispObject::from(n));
+    }
+    n as usize
+}
+
+/// Return a new persistent vector with the elements OBJECTS.
+///
+/// A persistent vector is never changed: `pvector-set', `pvector-push'
+/// and the like return a new vector, which shares most of its storage
+/// with the old one, and take time logarithmic in its length.
+/// usage: (pvector &rest OBJECTS)
+#[lisp_fn]
+pub fn pvector(args: &mut [LispObject]) -> LispObject {
+    make_pvector(args.iter().cloned().collect())
+}
+
+/// Return the number of elements of the persistent vector PVECTOR.
+#[lisp_fn]
+pub fn pvector_length(pvector: LispObject) -> EmacsInt {
+    pvector_value(pvector).len() as EmacsInt
+}
+
+/// Return the element of the persistent vector PVECTOR at index N.
+#[lisp_fn]
+pub fn pvector_ref(pvector: LispObject, n: EmacsInt) -> LispObject {
+    let vector = pvector_value(pvector);
+    vector[pvector_index(pvector, n, vector.len(), false)]
+}
+
+/// Return a persistent vector like PVECTOR, but with OBJECT at index N.
+#[lisp_fn]
+pub fn pvector_set(pvector: LispObject, n: EmacsInt, object: LispObject) -> LispObject {
+    let vector = pvector_value(pvector);
+    let index = pvector_index(pvector, n, vector.len(), false);
+    make_pvector(vector.update(index, object))
+}
+
+/// Return a persistent vector like PVECTOR, with OBJECT added at its
+/// end.
+#[lisp_fn]
+pub fn pvector_push(pvector: LispObject, object: LispObject) -> LispObject {
+    let mut vector = pvector_value(pvector);
+    vector.push_back(object);
+    make_pvector(vector)
+}
+
+/// Return a persistent vector of the elements of the persistent vector
+/// PVECTOR followed by those of the persistent vector OTHER.
+#[lisp_fn]
+pub fn pvector_append(pvector: LispObject, other: LispObject) -> LispObject {
+    let mut vector = pvector_value(pvector);
+    vector.append(pvector_value(other));
+    make_pvector(vector)
+}
+
+/// Return a persistent vector of the elements of the persistent vector
+/// PVECTOR from index FROM up to, but not including, index TO.  If TO
+/// is nil, it defaults to the length of PVECTOR.
+#[lisp_fn(min = "2")]
+pub fn pvector_slice(pvector: LispObject, from: EmacsInt, to: LispObject) -> LispObject {
+    let mut vector = pvector_value(pvector);
+    let length = vector.len();
+    let start = pvector_index(pvector, from, length, true);
+    let end = if to.is_nil() {
+        length
+    } else {
+        pvector_index(pvector, to.as_fixnum_or_error(), length, true)
+    };
+    if end < start {
+        args_out_of_range!(pvector, LispObject::from(from), to);
+    }
+    make_pvector(vector.slice(start..end))
+}
+
+/// Return a list of the elements of the persistent vector PVECTOR.
+#[lisp_fn]
+pub fn pvector_to_list(pvector: LispObject) -> LispObject {
+    pvector_value(pvector).iter().cloned().collect()
+}
+
+/// Return t if OBJECT is a persistent map.
+#[lisp_fn]
+pub fn pmap_p(object: LispObject) -> bool {
+    recordp(object) && aref(object, 0) == Qpmap
+}
+
+fn pmap_value(pmap: LispObject) -> PMap<MapKey, LispObject> {
+    if !pmap_p(pmap) {
+        wrong_type!(Qpmap_p, pmap);
+    }
+    match persistent_collection(pmap) {
+        Some(Collection::Map(map)) => map,
+        _ => error!("Persistent map has been released"),
+    }
+}
+
+fn make_pmap(map: PMap<MapKey, LispObject>) -> LispObject {
+    make_persistent(Qpmap, Collection::Map(map))
+}
+
+/// Return a new persistent map of the keys and values
+/// KEYS-AND-VALUES, which alternate.  Keys are compared with `equal'.
+///
+/// A persistent map is never changed: `pmap-put' and `pmap-remove'
+/// return a new map, which shares most of its storage with the old
+/// one.
+/// usage: (pmap &rest KEYS-AND-VALUES)
+#[lisp_fn]
+pub fn pmap(args: &mut [LispObject]) -> LispObject {
+    if args.len() % 2 != 0 {
+        error!("Odd number of arguments to `pmap'");
+    }
+    let map = args
+        .chunks(2)
+        .map(|pair| (MapKey(pair[0]), pair[1]))
+        .collect();
+    make_pmap(map)
+}
+
+/// Return the number of entries in the persistent map PMAP.
+#[lisp_fn]
+pub fn pmap_count(pmap: LispObject) -> EmacsInt {
+    pmap_value(pmap).len() as EmacsInt
+}
+
+/// Look up KEY in the persistent map PMAP and return its value.
+/// If KEY is not found, return DEFAULT which defaults to nil.
+#[lisp_fn(min = "2")]
+pub fn pmap_get(key: LispObject, pmap: LispObject, default: LispObject) -> LispObject {
+    pmap_value(pmap)
+        .get(&MapKey(key))
+        .cloned()
+        .unwrap_or(default)
+}
+
+/// Return a persistent map like PMAP, but with KEY associated with
+/// VALUE.
+#[lisp_fn]
+pub fn pmap_put(key: LispObject, value: LispObject, pmap: LispObject) -> LispObject {
+    make_pmap(pmap_value(pmap).update(MapKey(key), value))
+}
+
+/// Return a persistent map like PMAP, but without KEY.
+#[lisp_fn]
+pub fn pmap_remove(key: LispObject, pmap: LispObject) -> LispObject {
+    make_pmap(pmap_value(pmap).without(&MapKey(key)))
+}
+
+/// Call FUNCTION for all the entries of the persistent map PMAP, in no
+/// particular order.  FUNCTION is called with two arguments, KEY and
+/// VALUE.  Return nil.
+#[lisp_fn]
+pub fn pmap_map(function: LispObject, pmap: LispObject) {
+    for &(key, value) in pmap_value(pmap).iter() {
+        call!(function, key.0, value);
+    }
+}
+
+/// Drop the persistent collection numbered ID.
+/// This is for internal use by the finalizer of persistent collections.
+#[lisp_fn(name = "persistent--release")]
+pub fn persistent_release(id: EmacsInt) -> bool {
+    PERSISTENT.lock().unwrap().collections.remove(&id).is_some()
+}
+
+#[no_mangle]
+pub extern "C" fn syms_of_persistent() {
+    def_lisp_sym!(Qpvector, "pvector");
+    def_lisp_sym!(Qpvector_p, "pvector-p");
+    def_lisp_sym!(Qpmap, "pmap");
+    def_lisp_sym!(Qpmap_p, "pmap-p");
+    def_lisp_sym!(Qpersistent__release, "persistent--release");
+}
+
+include!(concat!(env!("OUT_DIR"), "/persistent_exports.rs"));
@@ -0,0 +1,135 @@
+//! Pipelines of subprocesses.
+//!
+//! `make-process-pipeline' starts the processes of a pipeline, like
+//! find | xargs grep | sort, and connects the standard output of each
+//! to the standard input of the next with a pipe of their own, so that
+//! the output they pass on never goes through Emacs.
+
+use libc::{c_char, c_int};
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    eval::unbind_to,
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::{list, plist_get, LispConsCircularChecks, LispConsEndChecks},
+    obarray::intern,
+    remacs_sys::{
+        close_file_unwind, emacs_pipe, make_pipeline_process, record_unwind_protect,
+        record_unwind_protect_int, report_file_error, Fdelete_process, Qnil, Qpipe,
+    },
+    threads::c_specpdl_index,
+};
+
+/// The options of `make-process-pipeline' that every process gets.
+const SHARED_OPTIONS: [&str; 3] = [":buffer", ":coding", ":noquery"];
+
+/// The options of `make-process-pipeline' that only the last process
+/// gets.
+const LAST_OPTIONS: [&str; 2] = [":filter", ":sentinel"];
+
+/// Delete the processes in the car of CELL, the ones a pipeline had
+/// started when starting the next one failed.
+#[no_mangle]
+pub extern "C" fn delete_pipeline_unwind(cell: LispObject) {
+    let processes = cell.as_cons_or_error().car();
+    for process in processes.iter_cars(LispConsEndChecks::off, LispConsCircularChecks::off) {
+        unsafe { Fdelete_process(process) };
+    }
+}
+
+/// Return a new pipe, whose descriptors are closed on unwinding.
+fn make_pipe() -> [c_int; 2] {
+    let mut fds = [-1; 2];
+    if unsafe { emacs_pipe(fds.as_mut_ptr()) } != 0 {
+        unsafe { report_file_error("Creating pipe\0".as_ptr() as *const c_char, Qnil) };
+    }
+    for &fd in fds.iter() {
+        unsafe { record_unwind_protect_int(Some(close_file_unwind), fd) };
+    }
+    fds
+}
+
+/// Start the processes of a pipeline, and return them, first to last.
+/// The standard output of each process is the standard input of the
+/// next, through a pipe that Emacs makes for them but doesn't read
+/// from, so the output they pass on doesn't go through Emacs however
+/// large it is.  Emacs writes to the first process with
+/// `process-send-string', and reads the output of the last one as from
+/// `make-process'.  It reads the error output of the other processes,
+/// which goes to the same buffer.
+///
+/// The arguments ARGS are a list of keyword/argument pairs.
+///
+/// :name NAME -- NAME is the name of the processes, made unique as by
+/// `make-process'.
+///
+/// :commands COMMANDS -- COMMANDS is a list of the commands of the
+/// processes, each a list of a program and its arguments, as for the
+/// :command argument of `make-process'.
+///
+/// :buffer, :coding and :noquery -- As for `make-process', for every
+/// process.
+///
+/// :filter and :sentinel -- As for `make-process', for the last process.
+///
+/// The processes always talk over pipes, never a pty.  If one of them
+/// can't be started, the ones started before it are deleted.
+/// usage: (make-process-pipeline &rest ARGS)
+#[lisp_fn(min = "0")]
+pub fn make_process_pipeline(args: &mut [LispObject]) -> LispObject {
+    let options = LispObject::from(args.to_vec());
+    let option = |name| plist_get(options, intern(name).into());
+    let commands: Vec<LispObject> = option(":commands")
+        .iter_cars(LispConsEndChecks::on, LispConsCircularChecks::safe)
+        .collect();
+    if commands.is_empty() {
+        error!("A pipeline needs at least one command");
+    }
+
+    let count = c_specpdl_index();
+    let pipes: Vec<[c_int; 2]> = commands[1..].iter().map(|_| make_pipe()).collect();
+    let started = LispObject::cons(Qnil, Qnil);
+    unsafe { record_unwind_protect(Some(delete_pipeline_unwind), started) };
+
+    let last = commands.len() - 1;
+    let mut processes = Vec::with_capacity(commands.len());
+    for (i, &command) in commands.iter().enumerate() {
+        let mut process_args = vec![
+            LispObject::from(intern(":name")),
+            option(":name"),
+            LispObject::from(intern(":command")),
+            command,
+            LispObject::from(intern(":connection-type")),
+            Qpipe,
+        ];
+        let last_options: &[&str] = if i == last { &LAST_OPTIONS } else { &[] };
+        for &name in SHARED_OPTIONS.iter().chain(last_options) {
+            process_args.push(LispObject::from(intern(name)));
+            process_args.push(option(name));
+        }
+
+        let stdin_fd = if i == 0 { -1 } else { pipes[i - 1][0] };
+        let stdout_fd = if i == last { -1 } else { pipes[i][1] };
+        let process = unsafe {
+            make_pipeline_process(
+                process_args.len() as isize,
+                process_args.as_mut_ptr(),
+                stdin_fd,
+                stdout_fd,
+            )
+        };
+        let started = started.as_cons_or_error();
+        started.set_car(LispObject::cons(process, started.car()));
+        processes.push(process);
+    }
+
+    // Every process started, so unwinding only closes the pipes, whose
+    // ends the processes have their own copies of.
+    started.as_cons_or_error().set_car(Qnil);
+    unbind_to(count, Qnil);
+    list(&processes)
+}
+
+include!(concat!(env!("OUT_DIR"), "/pipeline_exports.rs"));
@@ -528,4 +528,147 @@ pub fn process_running_child_p(mut process: LispObject) -> LispObject {
     }
 }
 
+/// The number of bytes read from a process at a time by default, as
+/// DEFAULT_READ_CHUNK_SIZE in process.h.
+const DEFAULT_READ_CHUNK_SIZE: EmacsInt = 4096;
+
+/// The largest number of bytes read from a process at a time.
+const MAX_READ_CHUNK_SIZE: EmacsInt = 16 * 1024 * 1024;
+
+/// Return the number of bytes PROCESS reads at a time.
+/// See `set-process-read-chunk-size'.
+#[lisp_fn]
+pub fn process_read_chunk_size(process: LispProcessRef) -> EmacsInt {
+    process.read_chunk_size as EmacsInt
+}
+
+/// Make PROCESS read its output SIZE bytes at a time.
+/// This is the most output its filter gets at once, so a filter that
+/// parses large amounts of output runs less often with a larger SIZE.
+/// SIZE nil means 4096 bytes, the default.  Return SIZE.
+#[lisp_fn]
+pub fn set_process_read_chunk_size(mut process: LispProcessRef, size: LispObject) -> LispObject {
+    let chunk_size = if size.is_nil() {
+        DEFAULT_READ_CHUNK_SIZE
+    } else {
+        size.as_fixnum_or_error()
+    };
+    if chunk_size < 1 || chunk_size > MAX_READ_CHUNK_SIZE {
+        args_out_of_range!(
+            size,
+            LispObject::from(1),
+            LispObject::from(MAX_READ_CHUNK_SIZE)
+        );
+    }
+    process.read_chunk_size = chunk_size as isize;
+    size
+}
+
+/// Return the size in bytes the buffer of PROCESS may grow to.
+/// See `set-process-max-buffered-output'.
+#[lisp_fn]
+pub fn process_max_buffered_output(process: LispProcessRef) -> Option<EmacsInt> {
+    if process.max_buffered_output > 0 {
+        Some(process.max_buffered_output as EmacsInt)
+    } else {
+        None
+    }
+}
+
+/// Stop reading output from PROCESS while its buffer is SIZE bytes or larger.
+/// Emacs reads from PROCESS again once the buffer is smaller, for
+/// instance after `comint-truncate-buffer', or after it is killed.
+/// Meanwhile, PROCESS goes on running until the pipe or pty it writes
+/// to is full, and then waits, so that a process that writes without
+/// end can't make Emacs use up memory.  SIZE nil or 0 means no limit.
+/// Return SIZE.
+#[lisp_fn]
+pub fn set_process_max_buffered_output(
+    mut process: LispProcessRef,
+    size: LispObject,
+) -> LispObject {
+    process.max_buffered_output = if size.is_nil() {
+        0
+    } else {
+        size.as_natnum_or_error() as isize
+    };
+    size
+}
+
+/// Return t if Emacs doesn't read output from PROCESS for now, because
+/// of `process-pause-output' or `set-process-max-buffered-output'.
+#[lisp_fn]
+pub fn process_output_paused_p(process: LispProcessRef) -> bool {
+    process.output_paused() || process.output_full()
+}
+
+/// Stop reading output from PROCESS until `process-resume-output'.
+/// Unlike `stop-process', this doesn't stop PROCESS itself, which goes
+/// on running until the pipe or pty it writes to is full, and then
+/// waits for Emacs to read from it again.  Its filter isn't called
+/// meanwhile, so a filter can pause PROCESS while it is busy with
+/// output it already got.
+#[lisp_fn]
+pub fn process_pause_output(mut process: LispProcessRef) {
+    if process.infd >= 0 {
+        unsafe { delete_read_fd(process.infd) };
+    }
+    process.set_output_paused(true);
+}
+
+/// Read output from PROCESS again, after `process-pause-output'.
+/// Emacs still doesn't read from PROCESS while its buffer is as large as
+/// `set-process-max-buffered-output' allows.
+#[lisp_fn]
+pub fn process_resume_output(mut process: LispProcessRef) {
+    process.set_output_paused(false);
+    if !process.output_full() {
+        start_reading_output(process);
+    }
+}
+
+fn start_reading_output(process: LispProcessRef) {
+    // Stopped network, serial and pipe processes and processes whose
+    // filter is t aren't read from either.
+    if process.infd >= 0 && !process.command.eq(Qt) && !process.filter.eq(Qt) {
+        unsafe { add_process_read_fd(process.infd) };
+    }
+}
+
+/// Return true if the buffer of PROCESS is as large as its
+/// max_buffered_output allows.
+fn output_buffer_full(process: LispProcessRef) -> bool {
+    process.max_buffered_output > 0
+        && process.buffer.as_live_buffer().map_or(false, |buffer| {
+            buffer.z_byte() - buffer.beg_byte() >= process.max_buffered_output
+        })
+}
+
+/// Stop reading output from the process P if its buffer is full.
+#[no_mangle]
+pub extern "C" fn check_process_output_limit(p: *mut Lisp_Process) {
+    let mut process = LispProcessRef::new(p);
+    if !process.output_full() && output_buffer_full(process) {
+        process.set_output_full(true);
+        if process.infd >= 0 {
+            unsafe { delete_read_fd(process.infd) };
+        }
+    }
+}
+
+/// Read output again from the processes whose buffers were full, if
+/// they no longer are.
+#[no_mangle]
+pub extern "C" fn resume_drained_processes() {
+    for_each_process!(process => {
+        if process.output_full() && !output_buffer_full(process) {
+            let mut process = process;
+            process.set_output_full(false);
+            if !process.output_paused() {
+                start_reading_output(process);
+            }
+        }
+    });
+}
+
 include!(concat!(env!("OUT_DIR"), "/process_exports.rs"));
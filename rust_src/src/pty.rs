@@ -0,0 +1,211 @@
+//! Ptys of subprocesses.
+//!
+//! `open_pty' allocates the ptys `make-process' runs programs on, with
+//! posix_openpt.  The functions here tell the program on a pty the size
+//! of its window, for term.el and other terminal emulators to resize it,
+//! and turn output flow control with C-s and C-q on or off.
+
+use std::ffi::CStr;
+use std::io;
+use std::mem;
+use std::ptr;
+
+use errno::{set_errno, Errno};
+use libc::{c_char, c_int};
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    lisp::defsubr,
+    lisp::LispObject,
+    process::LispProcessRef,
+    remacs_sys::{EmacsInt, Qnetwork, Qnil},
+};
+
+/// Run F with SIGCHLD blocked, since grantpt may start a helper program
+/// whose exit Emacs mustn't take for that of a subprocess.
+fn with_sigchld_blocked<T, F: FnOnce() -> T>(f: F) -> T {
+    unsafe {
+        let mut blocked: libc::sigset_t = mem::zeroed();
+        let mut oldset: libc::sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut blocked);
+        libc::sigaddset(&mut blocked, libc::SIGCHLD);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &blocked, &mut oldset);
+        let result = f();
+        libc::pthread_sigmask(libc::SIG_SETMASK, &oldset, ptr::null_mut());
+        result
+    }
+}
+
+/// Return the file name of the slave side of the pty whose master side
+/// is FD.
+#[cfg(target_os = "linux")]
+fn slave_name(fd: c_int) -> io::Result<Vec<u8>> {
+    let mut name = [0 as c_char; 128];
+    match unsafe { libc::ptsname_r(fd, name.as_mut_ptr(), name.len()) } {
+        0 => Ok(unsafe { CStr::from_ptr(name.as_ptr()) }.to_bytes().to_vec()),
+        error => Err(io::Error::from_raw_os_error(error)),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn slave_name(fd: c_int) -> io::Result<Vec<u8>> {
+    let name = unsafe { libc::ptsname(fd) };
+    if name.is_null() {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(unsafe { CStr::from_ptr(name) }.to_bytes().to_vec())
+    }
+}
+
+/// Grant access to the slave side of the pty whose master side is FD,
+/// unlock it, and return its file name.
+fn open_slave(fd: c_int) -> io::Result<Vec<u8>> {
+    with_sigchld_blocked(|| {
+        if unsafe { libc::grantpt(fd) } < 0 || unsafe { libc::unlockpt(fd) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        slave_name(fd)
+    })
+}
+
+/// Open a new pty, store the file name of its slave side in NAME, which
+/// has room for SIZE bytes, and return the descriptor of its master
+/// side, which is close-on-exec.  Return -1 with errno set if there is
+/// no pty to be had.
+#[no_mangle]
+pub extern "C" fn open_pty(name: *mut c_char, size: usize) -> c_int {
+    let mut fd = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_CLOEXEC | libc::O_NOCTTY) };
+    if fd < 0 && io::Error::last_os_error().raw_os_error() == Some(libc::EINVAL) {
+        // POSIX doesn't require O_CLOEXEC to be supported here.
+        fd = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) };
+    }
+    if fd < 0 {
+        return -1;
+    }
+    unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+
+    let slave = match open_slave(fd) {
+        Ok(ref slave) if slave.len() >= size => {
+            Err(io::Error::from_raw_os_error(libc::ENAMETOOLONG))
+        }
+        result => result,
+    };
+    match slave {
+        Ok(slave) => unsafe {
+            ptr::copy_nonoverlapping(slave.as_ptr() as *const c_char, name, slave.len());
+            *name.add(slave.len()) = 0;
+            fd
+        },
+        Err(error) => {
+            unsafe { libc::close(fd) };
+            set_errno(Errno(error.raw_os_error().unwrap_or(libc::EIO)));
+            -1
+        }
+    }
+}
+
+/// Return the descriptor of the pty PROCESS runs on, if it runs on one.
+fn pty_fd(process: LispProcessRef) -> Option<c_int> {
+    if process.type_.eq(Qnetwork) || process.tty_name.is_nil() || process.infd < 0 {
+        None
+    } else {
+        Some(process.infd)
+    }
+}
+
+/// Check that SIZE fits in a window size, which all known platforms
+/// store as an unsigned short.
+fn window_size_value(size: EmacsInt) -> u16 {
+    let max = EmacsInt::from(u16::max_value());
+    if size < 0 || size > max {
+        args_out_of_range!(
+            LispObject::from(size),
+            LispObject::from(0),
+            LispObject::from(max)
+        );
+    }
+    size as u16
+}
+
+/// Tell PROCESS that it has logical window size WIDTH by HEIGHT.
+/// Value is t if PROCESS was successfully told about the window size,
+/// nil otherwise.
+#[lisp_fn]
+pub fn set_process_window_size(process: LispProcessRef, height: EmacsInt, width: EmacsInt) -> bool {
+    let height = window_size_value(height);
+    let width = window_size_value(width);
+    let fd = match pty_fd(process) {
+        Some(fd) => fd,
+        None => return false,
+    };
+    let size = libc::winsize {
+        ws_row: height,
+        ws_col: width,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    unsafe { libc::ioctl(fd, libc::TIOCSWINSZ, &size) == 0 }
+}
+
+/// Return the logical window size of PROCESS, as (HEIGHT . WIDTH).
+/// This is the size `set-process-window-size' last told it, or the
+/// program running on the pty of PROCESS set itself.  Value is nil if
+/// PROCESS doesn't run on a pty.
+#[lisp_fn]
+pub fn process_window_size(process: LispProcessRef) -> LispObject {
+    let fd = match pty_fd(process) {
+        Some(fd) => fd,
+        None => return Qnil,
+    };
+    let mut size: libc::winsize = unsafe { mem::zeroed() };
+    if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } < 0 {
+        return Qnil;
+    }
+    LispObject::cons(EmacsInt::from(size.ws_row), EmacsInt::from(size.ws_col))
+}
+
+/// Return the terminal attributes of the pty FD.
+fn pty_attributes(fd: c_int) -> Option<libc::termios> {
+    let mut attributes: libc::termios = unsafe { mem::zeroed() };
+    if unsafe { libc::tcgetattr(fd, &mut attributes) } < 0 {
+        None
+    } else {
+        Some(attributes)
+    }
+}
+
+/// Turn flow control on the pty of PROCESS on if FLAG is non-nil, and
+/// off otherwise.  With flow control on, C-s sent to PROCESS stops its
+/// output and C-q restarts it, as on a terminal, and the program gets
+/// neither.  Terminal emulators turn it off to send C-s and C-q to
+/// programs like Emacs.  Value is t if flow control was set, and nil if
+/// PROCESS doesn't run on a pty.
+#[lisp_fn]
+pub fn set_process_flow_control(process: LispProcessRef, flag: bool) -> bool {
+    let fd = match pty_fd(process) {
+        Some(fd) => fd,
+        None => return false,
+    };
+    let mut attributes = match pty_attributes(fd) {
+        Some(attributes) => attributes,
+        None => return false,
+    };
+    if flag {
+        attributes.c_iflag |= libc::IXON | libc::IXOFF;
+    } else {
+        attributes.c_iflag &= !(libc::IXON | libc::IXOFF);
+    }
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &attributes) == 0 }
+}
+
+/// Return t if flow control is on for the pty of PROCESS.
+/// See `set-process-flow-control'.
+#[lisp_fn]
+pub fn process_flow_control(process: LispProcessRef) -> bool {
+    pty_fd(process)
+        .and_then(pty_attributes)
+        .map_or(false, |attributes| attributes.c_iflag & libc::IXON != 0)
+}
+
+include!(concat!(env!("OUT_DIR"), "/pty_exports.rs"));
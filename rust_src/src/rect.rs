@@ -0,0 +1,280 @@
+//! Operations on rectangles of text.
+//!
+//! rect.el works out the columns of a rectangle, which depend on where
+//! `rectangle-mark-mode' put point and the mark, and calls the functions
+//! here to go over its lines.
+
+use libc::ptrdiff_t;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    cmds::{forward_char, forward_line},
+    editfns::{
+        bolp, buffer_substring, delete_region, goto_char, insert_char, line_beginning_position,
+        line_end_position, point, save_excursion_save,
+    },
+    eval::unbind_to,
+    fns::concat,
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::{list, LispConsCircularChecks, LispConsEndChecks},
+    marker::{
+        buf_bytepos_to_charpos, buf_charpos_to_bytepos, copy_marker, marker_position, set_marker,
+    },
+    multibyte::{Codepoint, LispStringRef},
+    obarray::intern,
+    remacs_sys::{current_column, record_unwind_protect, save_excursion_restore},
+    remacs_sys::{EmacsInt, Finsert, Fmake_string, Fmove_to_column, Freplace_match, Qnil, Qt},
+    search::{match_beginning, match_end, re_search_forward},
+    threads::{c_specpdl_index, ThreadState},
+};
+
+/// Move point to COLUMN in the current line, as `move-to-column' does
+/// with FORCE, and return the column reached.
+fn move_to_column(column: EmacsInt, force: LispObject) -> EmacsInt {
+    unsafe { Fmove_to_column(column.into(), force) }.as_fixnum_or_error()
+}
+
+fn column() -> EmacsInt {
+    unsafe { current_column() as EmacsInt }
+}
+
+/// Return a string of N spaces.
+fn spaces(n: EmacsInt) -> LispObject {
+    unsafe { Fmake_string(n.into(), EmacsInt::from(b' ').into(), Qnil) }
+}
+
+/// Return STARTCOL and ENDCOL with the left one first.
+fn ordered_columns(startcol: EmacsInt, endcol: EmacsInt) -> (EmacsInt, EmacsInt) {
+    if endcol < startcol {
+        (endcol, startcol)
+    } else {
+        (startcol, endcol)
+    }
+}
+
+/// Call F with point at the beginning of each line of the rectangle
+/// with corners at START and END, like `apply-on-rectangle', and return
+/// the position of point after the last call.  Point is restored
+/// afterwards.
+fn apply_on_rectangle<F>(start: LispObject, end: LispObject, mut f: F) -> EmacsInt
+where
+    F: FnMut(),
+{
+    let count = c_specpdl_index();
+    unsafe { record_unwind_protect(Some(save_excursion_restore), save_excursion_save()) };
+
+    goto_char(start);
+    let startpt = line_beginning_position(None);
+    goto_char(end);
+    // The end moves as the lines before it are edited.
+    let endpt = copy_marker(line_end_position(None).into(), Qnil);
+
+    goto_char(startpt.into());
+    let final_point = loop {
+        f();
+        let final_point = point();
+        if forward_line(Some(1)) != 0 || !bolp() || point() > marker_position(endpt) as EmacsInt {
+            break final_point;
+        }
+    };
+
+    set_marker(endpt.as_marker_or_error(), Qnil, Qnil);
+    unbind_to(count, Qnil);
+    final_point
+}
+
+/// Return the positions of the tabs between START and END.
+fn tab_positions(start: EmacsInt, end: EmacsInt) -> Vec<ptrdiff_t> {
+    let mut buffer = ThreadState::current_buffer();
+    let start_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), start as ptrdiff_t) };
+    let end_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), end as ptrdiff_t) };
+
+    // A tab is a single byte in either representation of the text.
+    let mut tabs = Vec::new();
+    let mut chunk_start = start_byte;
+    for chunk in buffer.byte_chunks(start_byte, end_byte) {
+        tabs.extend(
+            chunk
+                .iter()
+                .enumerate()
+                .filter(|&(_, &b)| b == b'\t')
+                .map(|(i, _)| chunk_start + i as ptrdiff_t),
+        );
+        chunk_start += chunk.len() as ptrdiff_t;
+    }
+    tabs.into_iter()
+        .map(|bytepos| unsafe { buf_bytepos_to_charpos(buffer.as_mut(), bytepos) })
+        .collect()
+}
+
+/// Return the text of the current line between STARTCOL and ENDCOL, with
+/// its tabs replaced by spaces, and padded with spaces to the columns.
+fn extract_rectangle_line(startcol: EmacsInt, endcol: EmacsInt) -> LispObject {
+    move_to_column(startcol, Qnil);
+    let start = point();
+    let mut begextra = column() - startcol;
+    move_to_column(endcol, Qnil);
+    let end = point();
+    let mut endextra = endcol - column();
+    if begextra < 0 {
+        endextra += begextra;
+        begextra = 0;
+    }
+    let endextra = endextra.max(0);
+
+    let mut pieces = vec![spaces(begextra)];
+    let mut piece_start = start;
+    for tab in tab_positions(start, end) {
+        let tab = tab as EmacsInt;
+        goto_char(tab.into());
+        let tab_column = column();
+        goto_char((tab + 1).into());
+        pieces.push(buffer_substring(piece_start.into(), tab.into()));
+        pieces.push(spaces(column() - tab_column));
+        piece_start = tab + 1;
+    }
+    pieces.push(buffer_substring(piece_start.into(), end.into()));
+    pieces.push(spaces(endextra));
+    concat(&mut pieces)
+}
+
+/// Return the contents of the rectangle with corners at START and END,
+/// between columns STARTCOL and ENDCOL, as a list of strings, one for
+/// each line of the rectangle.  Tabs are replaced by spaces, and lines
+/// which end before ENDCOL are padded with spaces.  This is the work of
+/// `extract-rectangle', which see.
+#[lisp_fn]
+pub fn extract_rectangle_columns(
+    start: LispObject,
+    end: LispObject,
+    startcol: EmacsInt,
+    endcol: EmacsInt,
+) -> LispObject {
+    let (startcol, endcol) = ordered_columns(startcol, endcol);
+    let mut lines = Vec::new();
+    apply_on_rectangle(start, end, || {
+        lines.push(extract_rectangle_line(startcol, endcol))
+    });
+    list(&lines)
+}
+
+/// Delete the text of the current line between STARTCOL and ENDCOL, if
+/// the line reaches STARTCOL, like `delete-rectangle-line' without FILL.
+fn delete_rectangle_line(startcol: EmacsInt, endcol: EmacsInt) {
+    let coerce = LispObject::from(intern("coerce"));
+    if move_to_column(startcol, coerce) == startcol {
+        let start = point();
+        move_to_column(endcol, coerce);
+        delete_region(start.into(), point().into());
+    }
+}
+
+/// Insert STRING at column STARTCOL of each line of the rectangle with
+/// corners at START and END, replacing the text up to column ENDCOL if
+/// DELETE is non-nil, and return the position after the last STRING
+/// inserted.  Lines which end before STARTCOL are extended with
+/// whitespace.  This is the work of `string-rectangle' and
+/// `string-insert-rectangle', which see.
+#[lisp_fn]
+pub fn string_rectangle_columns(
+    start: LispObject,
+    end: LispObject,
+    startcol: EmacsInt,
+    endcol: EmacsInt,
+    string: LispStringRef,
+    delete: bool,
+) -> EmacsInt {
+    let (startcol, endcol) = ordered_columns(startcol, endcol);
+    apply_on_rectangle(start, end, || {
+        move_to_column(startcol, Qt);
+        if delete {
+            delete_rectangle_line(startcol, endcol);
+        }
+        callN_raw!(Finsert, LispObject::from(string));
+    })
+}
+
+/// Insert the lines of RECTANGLE, a list of strings, at COLUMN of the
+/// current line and the lines after it, as `insert-for-yank' does.
+/// Lines are added at the end of the buffer when it runs out, and lines
+/// which end before COLUMN are extended with whitespace.  Point is left
+/// after the last string.  This is the work of `insert-rectangle'.
+#[lisp_fn]
+pub fn insert_rectangle_lines(rectangle: LispObject, column: EmacsInt) {
+    let insert_for_yank = LispObject::from(intern("insert-for-yank"));
+    let lines = rectangle.iter_cars(LispConsEndChecks::on, LispConsCircularChecks::on);
+    for (i, line) in lines.enumerate() {
+        if i > 0 {
+            forward_line(Some(1));
+            if !bolp() {
+                insert_char(Codepoint::from(b'\n'), None, false);
+            }
+            move_to_column(column, Qt);
+        }
+        call!(insert_for_yank, line);
+    }
+}
+
+/// Replace the matches for REGEXP in the current line between the
+/// positions FROM and TO with REPLACEMENT, and return their number.
+fn replace_regexp_in_line(
+    from: EmacsInt,
+    to: EmacsInt,
+    regexp: LispObject,
+    replacement: LispObject,
+) -> EmacsInt {
+    // The end moves past the replacements before it, including one right
+    // at the end.
+    let bound = copy_marker(to.into(), Qt);
+    goto_char(from.into());
+
+    let mut replaced = 0;
+    while point() <= marker_position(bound) as EmacsInt
+        && re_search_forward(regexp, bound, Qt, Qnil).is_not_nil()
+    {
+        let empty = match_beginning(LispObject::from(0)).eq(match_end(LispObject::from(0)));
+        unsafe { Freplace_match(replacement, Qnil, Qnil, Qnil, Qnil) };
+        replaced += 1;
+        if empty {
+            // Step over the next character after an empty match.
+            if point() >= marker_position(bound) as EmacsInt {
+                break;
+            }
+            forward_char(LispObject::from(1));
+        }
+    }
+
+    set_marker(bound.as_marker_or_error(), Qnil, Qnil);
+    replaced
+}
+
+/// Replace the matches for REGEXP with REPLACEMENT in the rectangle with
+/// corners at START and END, between columns STARTCOL and ENDCOL, and
+/// return the number of replacements.  Each line is searched separately,
+/// and matches must lie between the columns.  REPLACEMENT is as for
+/// `replace-match', and may refer to the groups of REGEXP.  This is the
+/// work of `replace-rectangle-regexp'.
+#[lisp_fn]
+pub fn replace_rectangle_regexp_columns(
+    start: LispObject,
+    end: LispObject,
+    startcol: EmacsInt,
+    endcol: EmacsInt,
+    regexp: LispStringRef,
+    replacement: LispStringRef,
+) -> EmacsInt {
+    let (startcol, endcol) = ordered_columns(startcol, endcol);
+    let mut replaced = 0;
+    apply_on_rectangle(start, end, || {
+        move_to_column(startcol, Qnil);
+        let from = point();
+        move_to_column(endcol, Qnil);
+        let to = point();
+        replaced += replace_regexp_in_line(from, to, regexp.into(), replacement.into());
+    });
+    replaced
+}
+
+include!(concat!(env!("OUT_DIR"), "/rect_exports.rs"));
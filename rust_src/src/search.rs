@@ -1,11 +1,36 @@
 //! String search routines
 
+use std::cmp::min;
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::io::Read;
+use std::mem;
+use std::sync::Mutex;
+
+use aho_corasick::{AcAutomaton, Automaton, Match};
+use libc::{c_int, c_void, ptrdiff_t};
+use rayon::prelude::*;
+use regex::bytes::Regex;
+
 use remacs_macros::lisp_fn;
 
 use crate::{
+    buffers::{current_buffer_text, validate_region},
+    character::char_head_p,
+    editfns::save_excursion_save,
+    eval::unbind_to,
     lisp::defsubr,
     lisp::LispObject,
-    remacs_sys::{looking_at_1, match_limit, search_command, string_match_1},
+    lists::{list, LispConsCircularChecks, LispConsEndChecks},
+    multibyte::{multibyte_char_at, multibyte_length_by_head, Codepoint, LispStringRef},
+    remacs_sys::{
+        buf_bytepos_to_charpos, buf_charpos_to_bytepos, downcase, globals, looking_at_1,
+        match_limit, record_unwind_protect, record_unwind_save_match_data, regoff_t,
+        save_excursion_restore, search_command, specbind, string_match_1, xrealloc,
+    },
+    remacs_sys::{EmacsInt, Qinhibit_changing_match_data, Qnil, Qsearch_failed, Qt},
+    strings::{string_to_multibyte, string_to_unibyte},
+    threads::{c_specpdl_index, ThreadState},
 };
 
 /// Return t if text after point matches regular expression REGEXP.
@@ -151,6 +176,9 @@ pub fn re_search_backward(
 ///
 /// See also the functions `match-beginning', `match-end', `match-string',
 /// and `replace-match'.
+///
+/// If `re-search-rust-engine' is non-nil, REGEXP may be matched by the
+/// Rust regex engine instead; see that variable.
 #[lisp_fn(min = "1", intspec = "sRE search: ")]
 pub fn re_search_forward(
     regexp: LispObject,
@@ -158,6 +186,11 @@ pub fn re_search_forward(
     noerror: LispObject,
     count: LispObject,
 ) -> LispObject {
+    if unsafe { globals.re_search_rust_engine } {
+        if let Some(result) = rust_re_search_forward(regexp, bound, noerror, count) {
+            return result;
+        }
+    }
     unsafe { search_command(regexp, bound, noerror, count, 1, 1, false) }
 }
 
@@ -249,4 +282,1243 @@ pub fn match_end(subexp: LispObject) -> LispObject {
     unsafe { match_limit(subexp, false) }
 }
 
+/// An Emacs regexp translated into the syntax of the regex crate.
+#[derive(Debug, PartialEq)]
+pub struct TranslatedRegexp {
+    pub source: String,
+    /// Whether the regexp can only match at the end of a line or of the
+    /// buffer, which depends on the text after the search bound.
+    pub anchors_end: bool,
+}
+
+/// Return the regex crate equivalent of the character class NAME, as
+/// the contents of a bracket expression, or None if its meaning depends
+/// on the syntax or case table.  Letters are the general categories
+/// which `alphabeticp' accepts.
+fn translate_char_class(name: &str) -> Option<&'static str> {
+    match name {
+        "alpha" => Some(r"\pL\p{Mn}\p{Mc}\p{Me}\p{Nl}"),
+        "alnum" => Some(r"\pL\p{Mn}\p{Mc}\p{Me}\p{Nl}\p{Nd}"),
+        "digit" => Some("0-9"),
+        "xdigit" => Some("0-9A-Fa-f"),
+        "ascii" => Some(r"\x00-\x7F"),
+        "nonascii" => Some(r"\x{80}-\x{10FFFF}"),
+        "blank" => Some(r"\t\p{Zs}"),
+        "cntrl" => Some(r"\x00-\x1F"),
+        _ => None,
+    }
+}
+
+/// Translate the bracket expression at the start of CHARS, just after
+/// its opening bracket, appending it to OUT.  Return false if it can't
+/// be translated.
+fn translate_bracket(chars: &mut std::iter::Peekable<std::str::Chars>, out: &mut String) -> bool {
+    let mut items = String::new();
+    let negated = chars.peek() == Some(&'^');
+    if negated {
+        chars.next();
+    }
+    // A close bracket in the first position is an ordinary character.
+    let mut first = true;
+    loop {
+        let c = match chars.next() {
+            Some(c) => c,
+            None => return false,
+        };
+        if c == ']' && !first {
+            break;
+        }
+        first = false;
+        if c == '[' && chars.peek() == Some(&':') {
+            let rest: String = chars.clone().collect();
+            let end = match rest[1..].find(":]") {
+                Some(end) => end + 1,
+                None => return false,
+            };
+            match translate_char_class(&rest[1..end]) {
+                Some(class) => items.push_str(class),
+                None => return false,
+            }
+            for _ in 0..rest[..end + 2].chars().count() {
+                chars.next();
+            }
+            continue;
+        }
+        let mut lookahead = chars.clone();
+        if lookahead.next() == Some('-') {
+            if let Some(to) = lookahead.next().filter(|&to| to != ']') {
+                chars.next();
+                chars.next();
+                // Emacs treats a reversed range as empty.
+                if c <= to {
+                    items.push_str(&regex::escape(&c.to_string()));
+                    items.push('-');
+                    items.push_str(&regex::escape(&to.to_string()));
+                }
+                continue;
+            }
+        }
+        items.push_str(&regex::escape(&c.to_string()));
+    }
+    if items.is_empty() {
+        return false;
+    }
+    out.push('[');
+    if negated {
+        out.push('^');
+    }
+    out.push_str(&items);
+    out.push(']');
+    true
+}
+
+/// Translate the Emacs regexp PATTERN into the syntax of the regex
+/// crate.  Return None if PATTERN uses a construct which the regex crate
+/// lacks or whose meaning depends on the syntax table, such as
+/// back-references, `\w' or `\_<'.
+pub fn translate_regexp(pattern: &str) -> Option<TranslatedRegexp> {
+    let mut out = String::with_capacity(pattern.len() + 8);
+    let mut anchors_end = false;
+    let mut chars = pattern.chars().peekable();
+    // Whether we are where `^' is an anchor and a repetition operator
+    // is an ordinary character: at the start of the pattern or of a
+    // group or alternative.
+    let mut at_start = true;
+    // Whether there is something for a repetition operator to repeat,
+    // and whether that is itself a repetition.
+    let mut can_repeat = false;
+    let mut repeated = false;
+
+    while let Some(c) = chars.next() {
+        let mut next_at_start = false;
+        let mut next_can_repeat = true;
+        match c {
+            '^' if at_start => {
+                out.push('^');
+                // A repetition operator after a leading `^' is ordinary.
+                next_at_start = true;
+                next_can_repeat = false;
+            }
+            '$' => {
+                let rest = chars.clone().collect::<String>();
+                if rest.is_empty() || rest.starts_with("\\)") || rest.starts_with("\\|") {
+                    out.push('$');
+                    anchors_end = true;
+                    next_can_repeat = false;
+                } else {
+                    out.push_str(r"\$");
+                }
+            }
+            '*' | '+' | '?' if !at_start => {
+                if !can_repeat || repeated {
+                    return None;
+                }
+                out.push(c);
+                if chars.peek() == Some(&'?') {
+                    chars.next();
+                    out.push('?');
+                }
+                repeated = true;
+                at_start = false;
+                can_repeat = true;
+                continue;
+            }
+            '.' => out.push('.'),
+            '[' => {
+                if !translate_bracket(&mut chars, &mut out) {
+                    return None;
+                }
+            }
+            '\\' => match chars.next()? {
+                '(' => {
+                    if chars.peek() == Some(&'?') {
+                        chars.next();
+                        // Explicitly numbered groups are not supported.
+                        if chars.next()? != ':' {
+                            return None;
+                        }
+                        out.push_str("(?:");
+                    } else {
+                        out.push('(');
+                    }
+                    next_at_start = true;
+                    next_can_repeat = false;
+                }
+                ')' => out.push(')'),
+                '|' => {
+                    out.push('|');
+                    next_at_start = true;
+                    next_can_repeat = false;
+                }
+                '{' => {
+                    if !can_repeat || repeated {
+                        return None;
+                    }
+                    let mut interval = String::new();
+                    loop {
+                        match chars.next()? {
+                            '\\' => break,
+                            c @ '0'..='9' | c @ ',' => interval.push(c),
+                            _ => return None,
+                        }
+                    }
+                    if chars.next()? != '}' || chars.peek() == Some(&'?') {
+                        return None;
+                    }
+                    let mut bounds = interval.splitn(2, ',');
+                    let min = bounds.next().unwrap_or("");
+                    let max = bounds.next();
+                    let min_count = if min.is_empty() {
+                        Some(0)
+                    } else {
+                        min.parse::<u32>().ok()
+                    };
+                    match (min_count, max) {
+                        (None, _) => return None,
+                        (Some(min_count), Some(max)) if !max.is_empty() => {
+                            if max.parse::<u32>().ok()? < min_count {
+                                return None;
+                            }
+                        }
+                        _ => (),
+                    }
+                    out.push('{');
+                    out.push_str(if min.is_empty() { "0" } else { min });
+                    if let Some(max) = max {
+                        out.push(',');
+                        out.push_str(max);
+                    }
+                    out.push('}');
+                    repeated = true;
+                    at_start = false;
+                    can_repeat = true;
+                    continue;
+                }
+                '`' => {
+                    out.push_str(r"\A");
+                    next_can_repeat = false;
+                }
+                '\'' => {
+                    out.push_str(r"\z");
+                    anchors_end = true;
+                    next_can_repeat = false;
+                }
+                '1'..='9'
+                | 'w'
+                | 'W'
+                | 's'
+                | 'S'
+                | 'c'
+                | 'C'
+                | 'b'
+                | 'B'
+                | '<'
+                | '>'
+                | '_'
+                | '=' => return None,
+                c => out.push_str(&regex::escape(&c.to_string())),
+            },
+            c => out.push_str(&regex::escape(&c.to_string())),
+        }
+        at_start = next_at_start;
+        can_repeat = next_can_repeat;
+        repeated = false;
+    }
+    Some(TranslatedRegexp {
+        source: out,
+        anchors_end,
+    })
+}
+
+/// Return the position and byte position of the limit of a forward
+/// search from point bounded by BOUND, as `search_command' does.
+pub fn search_limit(bound: LispObject) -> (ptrdiff_t, ptrdiff_t) {
+    let mut buffer = ThreadState::current_buffer();
+    if bound.is_nil() {
+        return (buffer.zv, buffer.zv_byte);
+    }
+    let lim = bound.as_number_coerce_marker_or_error().to_fixnum() as ptrdiff_t;
+    if lim < buffer.pt {
+        error!("Invalid search bound (wrong side of point)");
+    }
+    if lim >= buffer.zv {
+        (buffer.zv, buffer.zv_byte)
+    } else {
+        (lim, unsafe { buf_charpos_to_bytepos(buffer.as_mut(), lim) })
+    }
+}
+
+/// Handle the failure of a search for STRING as `search_command' does:
+/// signal `search-failed' if NOERROR is nil, and move to the limit of
+/// the search, LIM and LIM_BYTE, unless NOERROR is t.  Return nil.
+pub fn search_failed(
+    string: LispObject,
+    noerror: LispObject,
+    lim: ptrdiff_t,
+    lim_byte: ptrdiff_t,
+) -> LispObject {
+    if noerror.is_nil() {
+        xsignal!(Qsearch_failed, string);
+    }
+    if noerror != Qt {
+        ThreadState::current_buffer().set_pt_both(lim, lim_byte);
+    }
+    Qnil
+}
+
+/// Return true if searches should set the match data, which they
+/// shouldn't while `inhibit-changing-match-data' is non-nil.
+pub fn changing_match_data() -> bool {
+    unsafe { globals.Vinhibit_changing_match_data }.is_nil()
+}
+
+/// Set the match data to GROUPS, the start and end positions of each
+/// group of a match in the current buffer in order, or None for groups
+/// which didn't match.  The registers are filled as the C searches fill
+/// them, so that `match-data' returns markers into the buffer.
+pub fn set_match_groups<I>(groups: I)
+where
+    I: IntoIterator<Item = Option<(ptrdiff_t, ptrdiff_t)>>,
+{
+    let groups: Vec<_> = groups.into_iter().collect();
+    let mut thread = ThreadState::current_thread();
+    let regs = &mut thread.m_search_regs;
+    if (regs.num_regs as usize) < groups.len() {
+        let size = groups.len() * mem::size_of::<regoff_t>();
+        unsafe {
+            regs.start = xrealloc(regs.start as *mut c_void, size) as *mut regoff_t;
+            regs.end = xrealloc(regs.end as *mut c_void, size) as *mut regoff_t;
+        }
+        regs.num_regs = groups.len() as u32;
+    }
+    // Registers past the groups are cleared.
+    for i in 0..regs.num_regs as usize {
+        let (start, end) = match groups.get(i) {
+            Some(Some((start, end))) => (*start as regoff_t, *end as regoff_t),
+            _ => (-1, -1),
+        };
+        unsafe {
+            *regs.start.add(i) = start;
+            *regs.end.add(i) = end;
+        }
+    }
+    thread.m_last_thing_searched = ThreadState::current_buffer().into();
+}
+
+/// The number of compiled regexps kept by `compile_rust_regexp'.
+const RUST_REGEXP_CACHE_SIZE: usize = 20;
+
+/// A compiled regexp, with whether it needs the text after the search
+/// bound.  The regexp is None if the pattern can't be translated.
+type CompiledRegexp = Option<(Regex, bool)>;
+
+lazy_static! {
+    /// The most recently used regexps, most recent first, by pattern and
+    /// whether case is ignored.
+    static ref RUST_REGEXP_CACHE: Mutex<Vec<(String, bool, CompiledRegexp)>> =
+        Mutex::new(Vec::with_capacity(RUST_REGEXP_CACHE_SIZE));
+}
+
+/// Return the regex crate equivalent of the Emacs regexp PATTERN,
+/// ignoring case if CASE_FOLD.
+fn compile_rust_regexp(pattern: &str, case_fold: bool) -> CompiledRegexp {
+    let mut cache = RUST_REGEXP_CACHE.lock().unwrap();
+    if let Some(i) = cache
+        .iter()
+        .position(|(p, fold, _)| p == pattern && *fold == case_fold)
+    {
+        let entry = cache.remove(i);
+        let compiled = entry.2.clone();
+        cache.insert(0, entry);
+        return compiled;
+    }
+
+    // The case table of the buffer only agrees with Unicode case
+    // folding for ASCII.
+    let compiled = if case_fold && !pattern.is_ascii() {
+        None
+    } else {
+        translate_regexp(pattern).and_then(|translated| {
+            let flags = if case_fold { "(?mi)" } else { "(?m)" };
+            Regex::new(&format!("{}{}", flags, translated.source))
+                .ok()
+                .map(|regex| (regex, translated.anchors_end))
+        })
+    };
+    cache.truncate(RUST_REGEXP_CACHE_SIZE - 1);
+    cache.insert(0, (pattern.to_string(), case_fold, compiled.clone()));
+    compiled
+}
+
+/// Return the regex crate equivalent of REGEXP for searching the current
+/// buffer, or None if the search must be left to the C engine, because
+/// of the buffer or the regexp.
+fn buffer_rust_regexp(regexp: LispObject) -> CompiledRegexp {
+    // Spaces in the pattern would have to be replaced.
+    if unsafe { globals.Vsearch_spaces_regexp }.is_not_nil() {
+        return None;
+    }
+    let buffer = ThreadState::current_buffer();
+    if !buffer.multibyte_characters_enabled() {
+        return None;
+    }
+    // A multibyte pattern is in UTF-8 unless it contains raw bytes.
+    let string = regexp.as_string()?;
+    let pattern = std::str::from_utf8(string.as_slice())
+        .ok()
+        .filter(|pattern| string.is_multibyte() || pattern.is_ascii())?;
+    compile_rust_regexp(pattern, buffer.case_fold_search().is_not_nil())
+}
+
+/// Return true if TEXT, the text of a multibyte buffer, is all Unicode,
+/// so that the regex crate sees the characters Emacs does.  Raw bytes
+/// and characters beyond Unicode aren't valid UTF-8, and leave the
+/// search to the C engine.
+fn plain_unicode(text: &[u8]) -> bool {
+    std::str::from_utf8(text).is_ok()
+}
+
+/// Search forward for REGEXP as `re-search-forward' does, using the
+/// Rust regex engine.  Return None if the search must be left to the C
+/// engine, because of the arguments, the buffer or the regexp.
+fn rust_re_search_forward(
+    regexp: LispObject,
+    bound: LispObject,
+    noerror: LispObject,
+    count: LispObject,
+) -> Option<LispObject> {
+    if count.is_not_nil() && count.as_fixnum() != Some(1) {
+        return None;
+    }
+    let mut buffer = ThreadState::current_buffer();
+    let (regex, anchors_end) = buffer_rust_regexp(regexp)?;
+
+    let (lim, lim_byte) = search_limit(bound);
+    // Anchors at the bound look at the text after it, which the regex
+    // engine doesn't see.
+    if anchors_end && lim < buffer.zv {
+        return None;
+    }
+
+    // Match against everything from the start of the accessible portion,
+    // so that anchors at point see the text before it.
+    let text = current_buffer_text(buffer.begv_byte, lim_byte);
+    if !plain_unicode(text) {
+        return None;
+    }
+    let start = (buffer.pt_byte - buffer.begv_byte) as usize;
+
+    let mut locations = regex.capture_locations();
+    if regex
+        .captures_read_at(&mut locations, text, start)
+        .is_none()
+    {
+        return Some(search_failed(regexp, noerror, lim, lim_byte));
+    }
+
+    let mut charpos = |offset: usize| {
+        let bytepos = buffer.begv_byte + offset as ptrdiff_t;
+        (
+            unsafe { buf_bytepos_to_charpos(buffer.as_mut(), bytepos) },
+            bytepos,
+        )
+    };
+    if changing_match_data() {
+        set_match_groups((0..locations.len()).map(|i| {
+            locations
+                .get(i)
+                .map(|(start, end)| (charpos(start).0, charpos(end).0))
+        }));
+    }
+    let (end, end_byte) = charpos(locations.get(0).unwrap().1);
+    buffer.set_pt_both(end, end_byte);
+    Some(LispObject::from(end))
+}
+
+/// The automaton built by the last call of `search-forward-any', with
+/// the patterns and case folding it was built for.  When folding case,
+/// the automaton is built for the patterns in lower case.
+struct LiteralSearcher {
+    patterns: Vec<Vec<u8>>,
+    case_fold: bool,
+    automaton: AcAutomaton<Vec<u8>>,
+    longest: usize,
+}
+
+impl LiteralSearcher {
+    fn new(patterns: Vec<Vec<u8>>, case_fold: bool) -> Self {
+        let mut folded = patterns.clone();
+        if case_fold {
+            for pattern in &mut folded {
+                pattern.make_ascii_lowercase();
+            }
+        }
+        let longest = patterns.iter().map(Vec::len).max().unwrap_or(0);
+        Self {
+            patterns,
+            case_fold,
+            automaton: AcAutomaton::new(folded),
+            longest,
+        }
+    }
+
+    /// Return the occurrence of one of the patterns in TEXT which begins
+    /// first, the longest one if several begin there.
+    fn find(&self, text: &[u8]) -> Option<Match> {
+        if self.case_fold {
+            let matches = self
+                .automaton
+                .stream_find_overlapping(AsciiLowercase(text))
+                .filter_map(Result::ok);
+            leftmost_longest(matches, self.longest)
+        } else {
+            leftmost_longest(self.automaton.find_overlapping(text), self.longest)
+        }
+    }
+}
+
+/// Return the match among MATCHES, which are ordered by their ends,
+/// which begins first, the longest one if several begin there.  No
+/// match is longer than LONGEST, so once matches end far enough after
+/// the start of the best one, no better one can follow.
+fn leftmost_longest<I: Iterator<Item = Match>>(matches: I, longest: usize) -> Option<Match> {
+    let mut best: Option<Match> = None;
+    for found in matches {
+        if let Some(best) = best {
+            if found.end > best.start + longest {
+                break;
+            }
+            if found.start > best.start || (found.start == best.start && found.end <= best.end) {
+                continue;
+            }
+        }
+        best = Some(found);
+    }
+    best
+}
+
+/// A reader of text with its ASCII letters turned into lower case.
+/// Other characters are left alone, including the bytes of non-ASCII
+/// characters, which never look like ASCII ones.
+struct AsciiLowercase<'a>(&'a [u8]);
+
+impl<'a> Read for AsciiLowercase<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.0.read(buf)?;
+        buf[..n].make_ascii_lowercase();
+        Ok(n)
+    }
+}
+
+lazy_static! {
+    static ref LAST_LITERAL_SEARCHER: Mutex<Option<LiteralSearcher>> = Mutex::new(None);
+}
+
+/// Return the bytes of STRING in the representation of the text of the
+/// current buffer.
+fn buffer_representation(string: LispStringRef) -> Vec<u8> {
+    let multibyte = ThreadState::current_buffer().multibyte_characters_enabled();
+    let converted = if multibyte {
+        string_to_multibyte(string)
+    } else {
+        string_to_unibyte(string)
+    };
+    converted.as_string_or_error().as_slice().to_vec()
+}
+
+/// Search forward from point for any of the strings in PATTERNS.
+/// PATTERNS is a list or vector of strings.  If one of them is found,
+/// set point to the end of the occurrence found, and return a list
+/// (PATTERN BEG END) of the element of PATTERNS which matched, and the
+/// beginning and end of the occurrence.  The match data are set as by
+/// `search-forward'.
+///
+/// The occurrence found is the one which begins first; of the patterns
+/// which occur there, the longest is chosen.  All the patterns are
+/// looked for in a single pass over the text, so this is much faster
+/// than calling `search-forward' for each of them.
+///
+/// The optional second argument BOUND is a buffer position that bounds
+///   the search.  The match found must not end after that position.  A
+///   value of nil means search to the end of the accessible portion of
+///   the buffer.
+/// The optional third argument NOERROR indicates how errors are handled
+///   when the search fails, as for `search-forward'.
+///
+/// If `case-fold-search' is non-nil, the case of ASCII letters is
+/// ignored; other characters must match exactly.
+#[lisp_fn(min = "1")]
+pub fn search_forward_any(
+    patterns: LispObject,
+    bound: LispObject,
+    noerror: LispObject,
+) -> LispObject {
+    let elements: Vec<LispObject> = match patterns.as_vector() {
+        Some(vector) => vector.iter().collect(),
+        None => patterns
+            .iter_cars(LispConsEndChecks::on, LispConsCircularChecks::on)
+            .collect(),
+    };
+    let strings: Vec<Vec<u8>> = elements
+        .iter()
+        .map(|element| buffer_representation(element.as_string_or_error()))
+        .collect();
+
+    let mut buffer = ThreadState::current_buffer();
+    let case_fold = buffer.case_fold_search().is_not_nil();
+    let (lim, lim_byte) = search_limit(bound);
+    let text = current_buffer_text(buffer.pt_byte, lim_byte);
+
+    let found = {
+        let mut last = LAST_LITERAL_SEARCHER.lock().unwrap();
+        let reusable = last
+            .as_ref()
+            .map_or(false, |s| s.case_fold == case_fold && s.patterns == strings);
+        if !reusable {
+            *last = Some(LiteralSearcher::new(strings, case_fold));
+        }
+        last.as_ref().unwrap().find(text)
+    };
+    let found = match found {
+        Some(found) => found,
+        None => return search_failed(patterns, noerror, lim, lim_byte),
+    };
+
+    let pt_byte = buffer.pt_byte;
+    let mut charpos = |offset: usize| {
+        let bytepos = pt_byte + offset as ptrdiff_t;
+        (
+            unsafe { buf_bytepos_to_charpos(buffer.as_mut(), bytepos) },
+            bytepos,
+        )
+    };
+    let (beg, _) = charpos(found.start);
+    let (end, end_byte) = charpos(found.end);
+    if changing_match_data() {
+        set_match_groups(vec![Some((beg, end))]);
+    }
+    buffer.set_pt_both(end, end_byte);
+    list!(
+        elements[found.pati],
+        LispObject::from(beg),
+        LispObject::from(end)
+    )
+}
+
+/// The length of text from which `re-seq-all-matches' scans in parallel.
+const PARALLEL_SCAN_THRESHOLD: usize = 1 << 20;
+
+/// Return the first match for REGEX in TEXT which starts at or after
+/// the byte offset POS, as its start and end offsets.  An empty match at
+/// LAST_END, the end of the previous match, is skipped, as
+/// `Regex::find_iter' does.
+fn next_rust_match(
+    regex: &Regex,
+    text: &[u8],
+    mut pos: usize,
+    last_end: Option<usize>,
+) -> Option<(usize, usize)> {
+    loop {
+        let found = regex.find_at(text, pos)?;
+        if found.start() != found.end() || Some(found.end()) != last_end {
+            return Some((found.start(), found.end()));
+        }
+        if found.end() >= text.len() {
+            return None;
+        }
+        pos = found.end() + multibyte_length_by_head(text[found.end()]);
+    }
+}
+
+/// Return the successive matches for REGEX in TEXT which start from the
+/// byte offset FROM and before TO, the previous match having ended at
+/// LAST_END.
+fn rust_matches(
+    regex: &Regex,
+    text: &[u8],
+    from: usize,
+    to: usize,
+    mut last_end: Option<usize>,
+) -> Vec<(usize, usize)> {
+    let mut matches = Vec::new();
+    let mut pos = from;
+    while let Some((start, end)) = next_rust_match(regex, text, pos, last_end) {
+        if start >= to {
+            break;
+        }
+        matches.push((start, end));
+        pos = end;
+        last_end = Some(end);
+    }
+    matches
+}
+
+/// Return the same matches as `rust_matches' with no bound and no
+/// previous match, scanning about NCHUNKS chunks of TEXT in parallel.
+fn parallel_rust_matches(
+    regex: &Regex,
+    text: &[u8],
+    from: usize,
+    nchunks: usize,
+) -> Vec<(usize, usize)> {
+    // Each chunk holds the matches which start in it, found by scanning
+    // the whole text from its start.
+    let mut bounds = vec![from];
+    for i in 1..nchunks {
+        let mut bound = from + (text.len() - from) * i / nchunks;
+        while bound < text.len() && !char_head_p(text[bound]) {
+            bound += 1;
+        }
+        if bound > *bounds.last().unwrap() && bound < text.len() {
+            bounds.push(bound);
+        }
+    }
+    bounds.push(text.len() + 1);
+    let chunks: Vec<_> = bounds
+        .par_windows(2)
+        .map(|w| (w[0], w[1], rust_matches(regex, text, w[0], w[1], None)))
+        .collect();
+
+    let mut matches: Vec<(usize, usize)> = Vec::new();
+    for (chunk_start, chunk_end, chunk) in chunks {
+        let last_end = matches.last().map(|&(_, end)| end);
+        let pos = last_end.unwrap_or(from);
+        if pos <= chunk_start {
+            // A match at the start of the chunk is right, unless it is
+            // empty and adjoins the previous match.
+            let skip = chunk
+                .first()
+                .map_or(false, |&(start, end)| start == end && Some(end) == last_end);
+            matches.extend(chunk.into_iter().skip(skip as usize));
+            continue;
+        }
+        // The previous match overlaps the chunk, so scan from its end
+        // until reaching one of the matches found for the chunk, after
+        // which the scans agree.
+        let (mut pos, mut last_end) = (pos, last_end);
+        while let Some(found) = next_rust_match(regex, text, pos, last_end) {
+            if found.0 >= chunk_end {
+                break;
+            }
+            matches.push(found);
+            if let Ok(i) = chunk.binary_search(&found) {
+                matches.extend_from_slice(&chunk[i + 1..]);
+                break;
+            }
+            pos = found.1;
+            last_end = Some(found.1);
+        }
+    }
+    matches
+}
+
+/// Return the matches for REGEXP between START and END with the Rust
+/// regex engine, as their start and end positions, or None if they must
+/// be found by the C engine.
+fn rust_all_matches(
+    regexp: LispObject,
+    start: ptrdiff_t,
+    end: ptrdiff_t,
+) -> Option<Vec<(ptrdiff_t, ptrdiff_t)>> {
+    let mut buffer = ThreadState::current_buffer();
+    let (regex, anchors_end) = buffer_rust_regexp(regexp)?;
+    if anchors_end && end < buffer.zv {
+        return None;
+    }
+
+    let begv_byte = buffer.begv_byte;
+    let start_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), start) };
+    let end_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), end) };
+    let text = current_buffer_text(begv_byte, end_byte);
+    if !plain_unicode(text) {
+        return None;
+    }
+    let from = (start_byte - begv_byte) as usize;
+    let matches = if text.len() - from >= PARALLEL_SCAN_THRESHOLD {
+        parallel_rust_matches(&regex, text, from, rayon::current_num_threads())
+    } else {
+        rust_matches(&regex, text, from, text.len() + 1, None)
+    };
+
+    // The offsets never decrease, so count the characters up to each
+    // from the previous one.
+    let (mut offset, mut charpos) = (from, start);
+    let mut to_charpos = |next: usize| {
+        charpos += text[offset..next]
+            .iter()
+            .filter(|&&byte| char_head_p(byte))
+            .count() as ptrdiff_t;
+        offset = next;
+        charpos
+    };
+    Some(
+        matches
+            .into_iter()
+            .map(|(s, e)| (to_charpos(s), to_charpos(e)))
+            .collect(),
+    )
+}
+
+/// Return the matches for REGEXP between START and END found by
+/// repeating `re-search-forward', skipping empty matches as
+/// `rust_matches' does.
+fn emacs_all_matches(
+    regexp: LispObject,
+    start: ptrdiff_t,
+    end: ptrdiff_t,
+) -> Vec<(ptrdiff_t, ptrdiff_t)> {
+    let count = c_specpdl_index();
+    unsafe {
+        record_unwind_protect(Some(save_excursion_restore), save_excursion_save());
+        record_unwind_save_match_data();
+        specbind(Qinhibit_changing_match_data, Qnil);
+    }
+
+    let mut buffer = ThreadState::current_buffer();
+    let start_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), start) };
+    buffer.set_pt_both(start, start_byte);
+
+    let mut matches = Vec::new();
+    let mut last_end = None;
+    let bound = LispObject::from(end);
+    while unsafe { search_command(regexp, bound, Qt, Qnil, 1, 1, false) }.is_not_nil() {
+        let match_start = match_beginning(LispObject::from(0)).as_fixnum_or_error() as ptrdiff_t;
+        let match_end = buffer.pt;
+        if match_start == match_end {
+            if last_end != Some(match_end) {
+                matches.push((match_start, match_end));
+            }
+            // Step over the next character after an empty match.
+            if match_end >= end {
+                break;
+            }
+            let pt_byte = buffer.inc_pos(buffer.pt_byte);
+            buffer.set_pt_both(match_end + 1, pt_byte);
+        } else {
+            matches.push((match_start, match_end));
+        }
+        last_end = Some(match_end);
+    }
+
+    unbind_to(count, Qnil);
+    matches
+}
+
+/// Return a list of the positions of all the matches for REGEXP.
+/// Each element is a cons (BEG . END) of the beginning and end of a
+/// match.  The matches are those found by searching repeatedly with
+/// `re-search-forward' from START to END, which default to the
+/// beginning and end of the accessible portion of the buffer: they
+/// don't overlap, and an empty match right after the previous match
+/// is skipped.  Point and the match data are not changed.
+///
+/// Regexps which the Rust regex engine can match (see
+/// `re-search-rust-engine') are matched in a single pass over the text,
+/// which is divided among several threads when it is long; other
+/// regexps are matched by the Emacs engine.
+#[lisp_fn(min = "1")]
+pub fn re_seq_all_matches(regexp: LispStringRef, start: LispObject, end: LispObject) -> LispObject {
+    let buffer = ThreadState::current_buffer();
+    let mut start = if start.is_nil() {
+        LispObject::from(buffer.begv)
+    } else {
+        start
+    };
+    let mut end = if end.is_nil() {
+        LispObject::from(buffer.zv)
+    } else {
+        end
+    };
+    unsafe { validate_region(&mut start, &mut end) };
+    let start = start.as_fixnum_or_error() as ptrdiff_t;
+    let end = end.as_fixnum_or_error() as ptrdiff_t;
+
+    let regexp = LispObject::from(regexp);
+    let matches = rust_all_matches(regexp, start, end)
+        .unwrap_or_else(|| emacs_all_matches(regexp, start, end));
+    let conses: Vec<LispObject> = matches
+        .into_iter()
+        .map(|(beg, end)| LispObject::cons(beg, end))
+        .collect();
+    list(&conses)
+}
+
+/// Return the number of matches for REGEXP between START and END found
+/// by the Rust regex engine, counted as `re-count-matches' counts them,
+/// or None if they must be counted by the C engine.
+fn rust_count_matches(regexp: LispObject, start: ptrdiff_t, end: ptrdiff_t) -> Option<EmacsInt> {
+    let mut buffer = ThreadState::current_buffer();
+    let (regex, anchors_end) = buffer_rust_regexp(regexp)?;
+    if anchors_end && end < buffer.zv {
+        return None;
+    }
+
+    let begv_byte = buffer.begv_byte;
+    let start_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), start) };
+    let end_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), end) };
+    let text = current_buffer_text(begv_byte, end_byte);
+    if !plain_unicode(text) {
+        return None;
+    }
+    let mut pos = (start_byte - begv_byte) as usize;
+    let mut count = 0;
+    while pos < text.len() {
+        let found = match regex.find_at(text, pos) {
+            Some(found) => found,
+            None => break,
+        };
+        if found.end() == pos {
+            pos += multibyte_length_by_head(text[pos]);
+        } else {
+            count += 1;
+            pos = found.end();
+        }
+    }
+    Some(count)
+}
+
+/// Return the number of matches for REGEXP between START and END found
+/// by repeating `re-search-forward', counted as `re-count-matches'
+/// counts them.
+fn emacs_count_matches(regexp: LispObject, start: ptrdiff_t, end: ptrdiff_t) -> EmacsInt {
+    let count = c_specpdl_index();
+    unsafe {
+        record_unwind_protect(Some(save_excursion_restore), save_excursion_save());
+        specbind(Qinhibit_changing_match_data, Qt);
+    }
+
+    let mut buffer = ThreadState::current_buffer();
+    let start_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), start) };
+    buffer.set_pt_both(start, start_byte);
+
+    let mut matches = 0;
+    let bound = LispObject::from(end);
+    while buffer.pt < end {
+        let opoint = buffer.pt;
+        if unsafe { search_command(regexp, bound, Qt, Qnil, 1, 1, false) }.is_nil() {
+            break;
+        }
+        if buffer.pt == opoint {
+            // Step over the next character after an empty match.
+            let pt_byte = buffer.inc_pos(buffer.pt_byte);
+            buffer.set_pt_both(opoint + 1, pt_byte);
+        } else {
+            matches += 1;
+        }
+    }
+
+    unbind_to(count, Qnil);
+    matches
+}
+
+/// Return the number of matches for REGEXP between START and END.
+/// START and END default to point and the end of the accessible portion
+/// of the buffer.  The matches are counted as `how-many' counts them:
+/// each search starts at the end of the previous match, so overlapping
+/// matches are ignored, and an empty match where a search starts is
+/// not counted.  Point and the match data are not changed.
+///
+/// Regexps which the Rust regex engine can match (see
+/// `re-search-rust-engine') are counted in a single pass over the text;
+/// other regexps are matched by the Emacs engine.
+#[lisp_fn(min = "1")]
+pub fn re_count_matches(regexp: LispStringRef, start: LispObject, end: LispObject) -> EmacsInt {
+    let buffer = ThreadState::current_buffer();
+    let mut start = if start.is_nil() {
+        LispObject::from(buffer.pt)
+    } else {
+        start
+    };
+    let mut end = if end.is_nil() {
+        LispObject::from(buffer.zv)
+    } else {
+        end
+    };
+    unsafe { validate_region(&mut start, &mut end) };
+    let start = start.as_fixnum_or_error() as ptrdiff_t;
+    let end = end.as_fixnum_or_error() as ptrdiff_t;
+
+    let regexp = LispObject::from(regexp);
+    rust_count_matches(regexp, start, end)
+        .unwrap_or_else(|| emacs_count_matches(regexp, start, end))
+}
+
+/// The longest pattern, in characters, for `approx-search-forward'.
+const APPROX_PATTERN_MAX: usize = 64;
+
+/// An iterator over the characters of text in the representation of the
+/// text of the current buffer, with the byte offset of each.
+struct BufferChars<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    multibyte: bool,
+    /// Whether to ignore case, by returning the characters downcased.
+    case_fold: bool,
+}
+
+impl<'a> Iterator for BufferChars<'a> {
+    type Item = (usize, Codepoint);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.bytes.len() {
+            return None;
+        }
+        let (c, len) = if self.multibyte {
+            multibyte_char_at(&self.bytes[self.offset..])
+        } else {
+            (Codepoint::from(self.bytes[self.offset]), 1)
+        };
+        let c = if self.case_fold && (self.multibyte || c < 0x80) {
+            unsafe { downcase(c as c_int) as Codepoint }
+        } else {
+            c
+        };
+        self.offset += len;
+        Some((self.offset - len, c))
+    }
+}
+
+/// Return the characters of BYTES, which are in the representation of
+/// the text of the current buffer, ignoring case if CASE_FOLD.
+fn buffer_chars(bytes: &[u8], case_fold: bool) -> BufferChars {
+    BufferChars {
+        bytes,
+        offset: 0,
+        multibyte: ThreadState::current_buffer().multibyte_characters_enabled(),
+        case_fold,
+    }
+}
+
+/// Return the number of characters of TEXT read before the end of the
+/// first occurrence of PATTERN with at most MAX_ERRORS insertions,
+/// deletions and substitutions, found with the bitap algorithm, or None
+/// if there is none.  PATTERN has at most 64 characters.
+fn bitap_first_end<I>(pattern: &[Codepoint], max_errors: usize, text: I) -> Option<usize>
+where
+    I: Iterator<Item = Codepoint>,
+{
+    let m = pattern.len();
+    if m <= max_errors {
+        return Some(0);
+    }
+    let mut masks: HashMap<Codepoint, u64> = HashMap::new();
+    for (i, &c) in pattern.iter().enumerate() {
+        *masks.entry(c).or_insert(0) |= 1 << i;
+    }
+    let found = 1 << (m - 1);
+
+    // Bit I of STATES[D] is set if the first I + 1 characters of PATTERN
+    // match the text just read with at most D errors.
+    let mut states: Vec<u64> = (0..=max_errors).map(|d| (1 << d) - 1).collect();
+    for (n, c) in text.enumerate() {
+        let mask = masks.get(&c).cloned().unwrap_or(0);
+        let mut previous = states[0];
+        states[0] = ((previous << 1) | 1) & mask;
+        for d in 1..=max_errors {
+            let old = states[d];
+            states[d] = (((old << 1) | 1) & mask) // match
+                | previous // insertion
+                | ((previous << 1) | 1) // substitution
+                | ((states[d - 1] << 1) | 1); // deletion
+            previous = old;
+        }
+        if states[max_errors] & found != 0 {
+            return Some(n + 1);
+        }
+    }
+    None
+}
+
+/// Return the best occurrence of PATTERN in TEXT which starts first,
+/// as its start and end offsets, among those which start no later than
+/// LAST_START and have at most MAX_ERRORS errors.  The best occurrence
+/// has the fewest errors and then the most characters.
+fn approx_best_match(
+    pattern: &[Codepoint],
+    max_errors: usize,
+    text: &[Codepoint],
+    last_start: usize,
+) -> Option<(usize, usize)> {
+    let m = pattern.len();
+    for start in 0..=last_start {
+        // COLUMN[I] is the distance between the first I characters of
+        // PATTERN and the text from START read so far.
+        let mut column: Vec<usize> = (0..=m).collect();
+        let mut best = (column[m], 0);
+        for (j, &c) in text[start..].iter().take(m + max_errors).enumerate() {
+            let mut diagonal = column[0];
+            column[0] = j + 1;
+            for i in 1..=m {
+                let cost = if pattern[i - 1] == c { 0 } else { 1 };
+                let distance = min(min(column[i] + 1, column[i - 1] + 1), diagonal + cost);
+                diagonal = column[i];
+                column[i] = distance;
+            }
+            if column[m] <= best.0 {
+                best = (column[m], j + 1);
+            }
+        }
+        if best.0 <= max_errors {
+            return Some((start, start + best.1));
+        }
+    }
+    None
+}
+
+/// Search forward from point for an approximate occurrence of STRING.
+/// An occurrence may differ from STRING by at most MAX-ERRORS edits,
+/// each of which is the insertion, deletion or substitution of a
+/// character.  Set point to the end of the occurrence found, and return
+/// point.  The match data are set as by `search-forward'.
+///
+/// The occurrence found is the one which begins first.  Of the
+/// occurrences which begin there, the one with the fewest errors is
+/// chosen, and of those the longest.  STRING can have at most 64
+/// characters, and the text is scanned in a single pass with the bitap
+/// algorithm.
+///
+/// The optional third argument BOUND is a buffer position that bounds
+///   the search.  The match found must not end after that position.  A
+///   value of nil means search to the end of the accessible portion of
+///   the buffer.
+/// The optional fourth argument NOERROR indicates how errors are handled
+///   when the search fails, as for `search-forward'.
+///
+/// Search case-sensitivity is determined by the value of the variable
+/// `case-fold-search', which see.
+#[lisp_fn(min = "2")]
+pub fn approx_search_forward(
+    string: LispStringRef,
+    max_errors: EmacsInt,
+    bound: LispObject,
+    noerror: LispObject,
+) -> LispObject {
+    if max_errors < 0 {
+        args_out_of_range!(LispObject::from(string), LispObject::from(max_errors));
+    }
+    let max_errors = max_errors as usize;
+
+    let mut buffer = ThreadState::current_buffer();
+    let case_fold = buffer.case_fold_search().is_not_nil();
+    let pattern_bytes = buffer_representation(string);
+    let pattern: Vec<Codepoint> = buffer_chars(&pattern_bytes, case_fold)
+        .map(|(_, c)| c)
+        .collect();
+    if pattern.len() > APPROX_PATTERN_MAX {
+        error!(
+            "Approximate search pattern is longer than {} characters",
+            APPROX_PATTERN_MAX
+        );
+    }
+
+    let (lim, lim_byte) = search_limit(bound);
+    let text = current_buffer_text(buffer.pt_byte, lim_byte);
+
+    // Find where the first occurrence ends, keeping the characters
+    // which may belong to the best one.  Every occurrence is at least
+    // M - K and at most M + K characters long, so the best one starts
+    // at most M + K characters before that end, and ends at most 2K
+    // characters after it.
+    let m = pattern.len();
+    let mut chars = buffer_chars(text, case_fold);
+    let mut window: VecDeque<(usize, Codepoint)> = VecDeque::new();
+    let mut skipped = 0;
+    let first_end = bitap_first_end(
+        &pattern,
+        max_errors,
+        chars.by_ref().map(|(offset, c)| {
+            if window.len() == m + max_errors {
+                window.pop_front();
+                skipped += 1;
+            }
+            window.push_back((offset, c));
+            c
+        }),
+    );
+    if first_end.is_none() {
+        return search_failed(LispObject::from(string), noerror, lim, lim_byte);
+    }
+    let last_start = (window.len() + max_errors)
+        .saturating_sub(m)
+        .min(window.len());
+    window.extend(chars.take(2 * max_errors));
+
+    let window_chars: Vec<Codepoint> = window.iter().map(|&(_, c)| c).collect();
+    let (start, end) = approx_best_match(&pattern, max_errors, &window_chars, last_start)
+        .expect("bitap and edit distances disagree");
+
+    let byte_offset = |i: usize| window.get(i).map_or(text.len(), |&(offset, _)| offset);
+    let beg = buffer.pt + (skipped + start) as ptrdiff_t;
+    let end_char = buffer.pt + (skipped + end) as ptrdiff_t;
+    let end_byte = buffer.pt_byte + byte_offset(end) as ptrdiff_t;
+    if changing_match_data() {
+        set_match_groups(vec![Some((beg, end_char))]);
+    }
+    buffer.set_pt_both(end_char, end_byte);
+    LispObject::from(end_char)
+}
+
+#[no_mangle]
+pub extern "C" fn rust_syms_of_search() {
+    /// Non-nil means `re-search-forward' may use the Rust regex engine.
+    /// Regexps which only use constructs that the Rust engine shares with
+    /// Emacs, and whose meaning doesn't depend on the syntax table, are
+    /// then matched by that engine, which is much faster on long lines.
+    /// Back-references, syntax and category classes, word and symbol
+    /// boundaries, and searches backward or for more than one occurrence
+    /// are still handled by the Emacs engine, as are all searches in
+    /// unibyte buffers, through raw bytes, or while `search-spaces-regexp'
+    /// is non-nil.
+    defvar_bool!(re_search_rust_engine, "re-search-rust-engine", false);
+}
+
+#[test]
+fn test_translate_regexp() {
+    let translate = |pattern| translate_regexp(pattern).map(|t| (t.source, t.anchors_end));
+    assert_eq!(translate("^a*?b$"), Some(("^a*?b$".to_string(), true)));
+    assert_eq!(translate("*a^b$c"), Some((r"\*a\^b\$c".to_string(), false)));
+    assert_eq!(
+        translate(r"\(?:x\|^y\)\{2,\}\'"),
+        Some((r"(?:x|^y){2,}\z".to_string(), true))
+    );
+    assert_eq!(
+        translate("[]a-z[:digit:]][^z-a-]"),
+        Some((r"[\]a-z0-9][^\-]".to_string(), false))
+    );
+    assert_eq!(
+        translate("(a)|{b}"),
+        Some((r"\(a\)\|\{b\}".to_string(), false))
+    );
+}
+
+#[test]
+fn test_translate_regexp_unsupported() {
+    for pattern in &[
+        r"\(a\)\1",
+        r"\w+",
+        r"\_<a",
+        r"\bfoo",
+        r"\s-",
+        r"\(?1:a\)",
+        "[[:space:]]",
+        "a**",
+        r"a\{3,2\}",
+    ] {
+        assert_eq!(translate_regexp(pattern), None);
+    }
+}
+
+#[test]
+fn test_parallel_rust_matches() {
+    let text = "aab ab  b\u{e9}ab aaab\nb".repeat(5);
+    let text = text.as_bytes();
+    for pattern in &["a*b?", "a+b", "b|", " *", "(?m)$", "ab a"] {
+        let regex = Regex::new(pattern).unwrap();
+        let expected = rust_matches(&regex, text, 2, text.len() + 1, None);
+        for nchunks in 1..20 {
+            assert_eq!(parallel_rust_matches(&regex, text, 2, nchunks), expected);
+        }
+    }
+}
+
 include!(concat!(env!("OUT_DIR"), "/search_exports.rs"));
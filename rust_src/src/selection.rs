@@ -0,0 +1,294 @@
+//! The system clipboard, and the primary selection.
+//!
+//! On X11, the selections are read and written with x11-clipboard,
+//! which keeps a thread of its own that hands what Emacs put on a
+//! selection to the programs asking for it, and plain text, HTML and
+//! images can be exchanged.  On macOS and MS-Windows, the clipboard
+//! crate only exchanges plain text.  The functions here work whatever
+//! the window system of the frames, so they work on a text terminal too
+//! as long as the display can be reached.
+
+use std::cell::RefCell;
+#[cfg(all(unix, not(target_os = "macos")))]
+use std::time::Duration;
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+use clipboard::{ClipboardContext as Clipboard, ClipboardProvider};
+use libc::c_char;
+#[cfg(all(unix, not(target_os = "macos")))]
+use x11_clipboard::{error::Error as X11Error, xcb, Clipboard, Context};
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    lisp::defsubr,
+    lisp::LispObject,
+    multibyte::LispStringRef,
+    obarray::intern,
+    remacs_sys::{make_unibyte_string, Qnil},
+};
+
+thread_local! {
+    /// The connection to the clipboard.  It is kept for the whole
+    /// session, since on X11 what Emacs puts on the clipboard is lost
+    /// when it is closed.
+    static CLIPBOARD: RefCell<Option<Clipboard>> = RefCell::new(None);
+}
+
+/// How long to wait for the owner of an X11 selection to hand over its
+/// contents.
+#[cfg(all(unix, not(target_os = "macos")))]
+const LOAD_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The kind of data to read from or write to the clipboard.
+#[derive(Clone, Copy)]
+enum DataType {
+    Text,
+    Html,
+    Image,
+}
+
+impl DataType {
+    fn from_lisp(object: LispObject) -> DataType {
+        let data_type = if object.eq(intern("text").into()) {
+            DataType::Text
+        } else if object.eq(intern("html").into()) {
+            DataType::Html
+        } else if object.eq(intern("image").into()) {
+            DataType::Image
+        } else {
+            error!("Invalid clipboard data type: should be `text', `html' or `image'");
+        };
+        check_data_type(data_type);
+        data_type
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn check_data_type(_data_type: DataType) {}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn check_data_type(data_type: DataType) {
+    match data_type {
+        DataType::Text => (),
+        _ => error!("This system only has text on the clipboard"),
+    }
+}
+
+/// An X11 selection.
+#[cfg(all(unix, not(target_os = "macos")))]
+#[derive(Clone, Copy)]
+enum Selection {
+    Clipboard,
+    Primary,
+    Secondary,
+}
+
+/// Which selection to use, as the symbol SELECTION names it: nil or
+/// `CLIPBOARD' for the clipboard, or `PRIMARY' or `SECONDARY'.  Only X11
+/// has the last two.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn selection_kind(selection: LispObject) -> Selection {
+    if selection.is_nil() || selection.eq(intern("CLIPBOARD").into()) {
+        Selection::Clipboard
+    } else if selection.eq(intern("PRIMARY").into()) {
+        Selection::Primary
+    } else if selection.eq(intern("SECONDARY").into()) {
+        Selection::Secondary
+    } else {
+        error!("Invalid selection: should be `CLIPBOARD', `PRIMARY' or `SECONDARY'");
+    }
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn selection_kind(selection: LispObject) {
+    if !selection.is_nil() && !selection.eq(intern("CLIPBOARD").into()) {
+        error!("This system only has the `CLIPBOARD' selection");
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn selection_atom(context: &Context, selection: Selection) -> xcb::Atom {
+    match selection {
+        Selection::Clipboard => context.atoms.clipboard,
+        Selection::Primary => context.atoms.primary,
+        Selection::Secondary => xcb::ATOM_SECONDARY,
+    }
+}
+
+/// Return the X11 target of the data of type DATA_TYPE.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn target_atom(context: &Context, data_type: DataType) -> Result<xcb::Atom, X11Error> {
+    match data_type {
+        DataType::Text => Ok(context.atoms.utf8_string),
+        DataType::Html => context.get_atom("text/html"),
+        DataType::Image => context.get_atom("image/png"),
+    }
+}
+
+/// Return the contents of type DATA_TYPE of SELECTION, or None if it has
+/// none of that type.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn load(
+    clipboard: &mut Clipboard,
+    selection: Selection,
+    data_type: DataType,
+) -> Result<Option<Vec<u8>>, String> {
+    let context = &clipboard.getter;
+    let selection = selection_atom(context, selection);
+    let owner = xcb::get_selection_owner(&context.connection, selection)
+        .get_reply()
+        .map_err(|error| X11Error::from(error).to_string())?
+        .owner();
+    if owner == xcb::NONE {
+        return Ok(None);
+    }
+    let target = target_atom(context, data_type).map_err(|error| error.to_string())?;
+    match clipboard.load(selection, target, context.atoms.property, LOAD_TIMEOUT) {
+        Ok(data) => Ok(Some(data)),
+        // The owner refuses targets it doesn't have, which is only
+        // noticed by waiting in vain.
+        Err(X11Error::Timeout) => Ok(None),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn load(
+    clipboard: &mut Clipboard,
+    _selection: (),
+    _data_type: DataType,
+) -> Result<Option<Vec<u8>>, String> {
+    match clipboard.get_contents() {
+        Ok(text) => Ok(Some(text.into_bytes())),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+/// Put DATA on SELECTION, as contents of type DATA_TYPE.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn store(
+    clipboard: &mut Clipboard,
+    selection: Selection,
+    data_type: DataType,
+    data: Vec<u8>,
+) -> Result<(), String> {
+    let context = &*clipboard.setter;
+    let selection = selection_atom(context, selection);
+    let target = target_atom(context, data_type).map_err(|error| error.to_string())?;
+    clipboard
+        .store(selection, target, data)
+        .map_err(|error| error.to_string())
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn store(
+    clipboard: &mut Clipboard,
+    _selection: (),
+    _data_type: DataType,
+    data: Vec<u8>,
+) -> Result<(), String> {
+    // Only text gets here, which was a Lisp string.
+    let text = String::from_utf8(data).map_err(|error| error.to_string())?;
+    clipboard
+        .set_contents(text)
+        .map_err(|error| error.to_string())
+}
+
+/// Call F with the connection to the clipboard, making it first if this
+/// is the first time.  F must not signal, which would leave the
+/// connection borrowed.
+fn with_clipboard<T, F>(f: F) -> Result<T, String>
+where
+    F: FnOnce(&mut Clipboard) -> Result<T, String>,
+{
+    CLIPBOARD.with(|cell| {
+        let mut clipboard = cell.borrow_mut();
+        if clipboard.is_none() {
+            *clipboard = Some(Clipboard::new().map_err(|error| error.to_string())?);
+        }
+        f(clipboard.as_mut().unwrap())
+    })
+}
+
+fn clipboard_error(message: String) -> ! {
+    error!("Clipboard error: {}", message);
+}
+
+fn unibyte_string(bytes: &[u8]) -> LispObject {
+    unsafe { make_unibyte_string(bytes.as_ptr() as *const c_char, bytes.len() as isize) }
+}
+
+/// Check that DATA is a whole PNG image, so that no program is handed
+/// a broken one from the clipboard.
+fn check_png(data: &[u8]) -> Result<(), png::DecodingError> {
+    let (info, mut reader) = png::Decoder::new(data).read_info()?;
+    let mut buffer = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buffer)
+}
+
+/// Return the contents of the clipboard of type TYPE, or nil if it has
+/// none of that type.
+///
+/// TYPE is `text' for plain text, `html' for HTML, or `image' for an
+/// image, which is returned as a unibyte string of PNG data.  HTML and
+/// images are only exchanged on X11; macOS and MS-Windows only have
+/// `text'.
+///
+/// SELECTION is nil or `CLIPBOARD' for the clipboard.  On X11, it can
+/// also be `PRIMARY', for the primary selection, or `SECONDARY'.
+///
+/// This reads the clipboard of the system directly, whatever the window
+/// system of the selected frame, so it also works on a text terminal
+/// whose display can be reached.
+#[lisp_fn(min = "1")]
+pub fn clipboard_get(type_: LispObject, selection: LispObject) -> LispObject {
+    let data_type = DataType::from_lisp(type_);
+    let kind = selection_kind(selection);
+    match with_clipboard(|clipboard| load(clipboard, kind, data_type)) {
+        Ok(Some(data)) => match data_type {
+            DataType::Image => unibyte_string(&data),
+            _ => LispObject::from(String::from_utf8_lossy(&data).as_ref()),
+        },
+        Ok(None) => Qnil,
+        Err(message) => clipboard_error(message),
+    }
+}
+
+/// Put DATA on the clipboard, as contents of type TYPE, and return DATA.
+///
+/// TYPE is `text' for plain text, `html' for HTML, or `image' for an
+/// image, as for `clipboard-get'.  DATA is a string, which for an image
+/// is a unibyte string of PNG data, as `clipboard-get' returns.  DATA
+/// is only offered as TYPE; HTML is not offered as plain text too.
+///
+/// SELECTION is as for `clipboard-get'.  The clipboard of X11 only keeps
+/// what Emacs puts on it for as long as Emacs runs, unless a clipboard
+/// manager takes it over.
+#[lisp_fn(min = "2")]
+pub fn clipboard_set(
+    type_: LispObject,
+    data: LispStringRef,
+    selection: LispObject,
+) -> LispStringRef {
+    let data_type = DataType::from_lisp(type_);
+    let kind = selection_kind(selection);
+    let bytes = match data_type {
+        DataType::Image => {
+            if data.is_multibyte() {
+                error!("Image data must be a unibyte string");
+            }
+            if let Err(error) = check_png(data.as_slice()) {
+                error!("Invalid PNG image: {}", error);
+            }
+            data.as_slice().to_vec()
+        }
+        _ => data.to_string().into_bytes(),
+    };
+    if let Err(message) = with_clipboard(|clipboard| store(clipboard, kind, data_type, bytes)) {
+        clipboard_error(message);
+    }
+    data
+}
+
+include!(concat!(env!("OUT_DIR"), "/selection_exports.rs"));
@@ -0,0 +1,157 @@
+//! Fast paths for seq.el.
+//!
+//! seq.el's generic functions work on any sequence, one element at a
+//! time, and find elements with `seq-contains', so `seq-uniq',
+//! `seq-difference' and `seq-group-by' take time quadratic in the
+//! length of their input.  For lists and vectors whose elements are
+//! compared with `eq', `eql' or `equal', seq.el calls the functions
+//! here instead, which use a hash table.
+
+use libc::ptrdiff_t;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    data::{aref, aset},
+    hashtable::{HashLookupResult::Found, HashLookupResult::Missing, LispHashTableRef},
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::{list, list_or_vector_elements, LispConsCircularChecks, LispConsEndChecks},
+    remacs_sys::{EmacsInt, Fmake_hash_table, Fmake_vector},
+    remacs_sys::{QCsize, QCtest, Qequal, Qnil, Qsequencep},
+    sort::stable_sort,
+};
+
+/// Return the elements of SEQUENCE, which must be a list or a vector.
+fn elements(sequence: LispObject) -> Vec<LispObject> {
+    match list_or_vector_elements(sequence) {
+        Some(elements) => elements,
+        None => wrong_type!(Qsequencep, sequence),
+    }
+}
+
+/// Return a new hash table whose test is TESTFN, or `equal' if TESTFN
+/// is nil.
+fn make_table(testfn: LispObject, size: usize) -> LispHashTableRef {
+    let test = if testfn.is_nil() { Qequal } else { testfn };
+    let mut args = [QCtest, test, QCsize, LispObject::from(size)];
+    unsafe { Fmake_hash_table(args.len() as ptrdiff_t, args.as_mut_ptr()) }.as_hash_table_or_error()
+}
+
+/// Return a list of the elements of SEQUENCE without duplicates,
+/// keeping the first of each.  SEQUENCE is a list or a vector, and
+/// TESTFN is nil, `eq', `eql' or `equal', as for `seq-uniq'.
+#[lisp_fn(min = "1", name = "seq--uniq")]
+pub fn seq_uniq(sequence: LispObject, testfn: LispObject) -> LispObject {
+    let elements = elements(sequence);
+    let table = make_table(testfn, elements.len());
+    let mut result = Vec::new();
+    for elt in elements {
+        if let Missing(hash) = table.lookup(elt) {
+            table.put(elt, Qnil, hash);
+            result.push(elt);
+        }
+    }
+    list(&result)
+}
+
+/// Return a list of the elements of SEQUENCE1 which are not in
+/// SEQUENCE2.  The sequences are lists or vectors, and TESTFN is nil,
+/// `eq', `eql' or `equal', as for `seq-difference'.
+#[lisp_fn(min = "2", name = "seq--difference")]
+pub fn seq_difference(
+    sequence1: LispObject,
+    sequence2: LispObject,
+    testfn: LispObject,
+) -> LispObject {
+    let elements1 = elements(sequence1);
+    let elements2 = elements(sequence2);
+    let table = make_table(testfn, elements2.len());
+    for elt in elements2 {
+        if let Missing(hash) = table.lookup(elt) {
+            table.put(elt, Qnil, hash);
+        }
+    }
+    elements1
+        .into_iter()
+        .filter(|&elt| !contains(table, elt))
+        .collect()
+}
+
+fn contains(table: LispHashTableRef, key: LispObject) -> bool {
+    match table.lookup(key) {
+        Found(_) => true,
+        Missing(_) => false,
+    }
+}
+
+/// Group the elements of SEQUENCE, a list or a vector, by the result of
+/// calling FUNCTION on them, as `seq-group-by' does.
+#[lisp_fn(name = "seq--group-by")]
+pub fn seq_group_by(function: LispObject, sequence: LispObject) -> LispObject {
+    let mut elements = elements(sequence);
+    let table = make_table(Qequal, 0);
+    // Walk a list of the elements rather than ELEMENTS, so that they
+    // stay visible to the garbage collector whatever FUNCTION does.
+    // Like `seq-group-by', this goes from the last element to the first.
+    elements.reverse();
+    let reversed = list(&elements);
+    let mut groups = Qnil;
+    for elt in reversed.iter_cars(LispConsEndChecks::off, LispConsCircularChecks::off) {
+        let key = call!(function, elt);
+        match table.lookup(key) {
+            Found(idx) => {
+                let group = table.get_hash_value(idx).as_cons_or_error();
+                group.set_cdr(LispObject::cons(elt, group.cdr()));
+            }
+            Missing(hash) => {
+                let group = list(&[key, elt]);
+                table.put(key, group, hash);
+                groups = LispObject::cons(group, groups);
+            }
+        }
+    }
+    groups
+}
+
+/// Sort SEQUENCE, a list or a vector, by the results of calling
+/// FUNCTION on its elements, compared with PRED, as `seq-sort-by'
+/// does.  FUNCTION is called once for each element, rather than twice
+/// for each comparison.  The sort is stable.
+#[lisp_fn(name = "seq--sort-by")]
+pub fn seq_sort_by(function: LispObject, pred: LispObject, sequence: LispObject) -> LispObject {
+    let elements = elements(sequence);
+    let length = elements.len() as EmacsInt;
+
+    // Keep each element with its key in a Lisp vector, where the
+    // garbage collector sees them while FUNCTION and PRED run.
+    let pairs = unsafe { Fmake_vector(LispObject::from(length), Qnil) };
+    for (i, &elt) in elements.iter().enumerate() {
+        aset(pairs, i as EmacsInt, LispObject::cons(Qnil, elt));
+    }
+    for i in 0..length {
+        let pair = aref(pairs, i).as_cons_or_error();
+        pair.set_car(call!(function, pair.cdr()));
+    }
+
+    let mut sorted: Vec<LispObject> = (0..length).map(|i| aref(pairs, i)).collect();
+    stable_sort(&mut sorted, |a, b| {
+        let (a, b) = (a.as_cons_or_error(), b.as_cons_or_error());
+        call!(pred, a.car(), b.car()).is_not_nil()
+    });
+
+    let result: Vec<LispObject> = sorted
+        .into_iter()
+        .map(|pair| pair.as_cons_or_error().cdr())
+        .collect();
+    if sequence.is_vector() {
+        for (i, &elt) in result.iter().enumerate() {
+            aset(pairs, i as EmacsInt, elt);
+        }
+        pairs
+    } else {
+        list(&result)
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/seq_exports.rs"));
@@ -0,0 +1,144 @@
+//! Surviving files which shrink while they are mapped into memory.
+//!
+//! Reading a page of a mapping which is past the end of the file it
+//! maps raises SIGBUS, which would kill Emacs if another program
+//! truncated a file while it was mapped.  The ranges of the mappings of
+//! files are guarded instead: a fault in one of them maps a page of
+//! zeros over the lost page, and marks the range so that its owner can
+//! tell that some of what it read wasn't in the file.  Faults anywhere
+//! else are left to the handler Emacs installed.
+
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{self, AtomicUsize, Ordering};
+use std::sync::{Mutex, Once, TryLockError, ONCE_INIT};
+
+use libc::{c_int, c_void};
+
+/// A guarded range of addresses, with whether any of its pages were
+/// lost.
+struct GuardedRange {
+    start: usize,
+    end: usize,
+    intact: bool,
+}
+
+lazy_static! {
+    static ref GUARDED_RANGES: Mutex<Vec<GuardedRange>> = Mutex::new(Vec::new());
+}
+
+static INSTALL_HANDLER: Once = ONCE_INIT;
+
+/// The handler of SIGBUS before `handle_sigbus'.
+static mut PREVIOUS_ACTION: Option<libc::sigaction> = None;
+
+/// The size of a page, which the handler can't ask for.
+static PAGE_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// Return the address whose reading raised the signal described by
+/// INFO.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+unsafe fn fault_address(info: *const libc::siginfo_t) -> usize {
+    // The libc crate hides the union after the code, where the address
+    // comes first for faults.
+    #[repr(C)]
+    struct FaultInfo {
+        si_signo: c_int,
+        si_errno: c_int,
+        si_code: c_int,
+        si_addr: *mut c_void,
+    }
+    (*(info as *const FaultInfo)).si_addr as usize
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+unsafe fn fault_address(info: *const libc::siginfo_t) -> usize {
+    (*info).si_addr as usize
+}
+
+/// Map a page of zeros over the page at ADDR, if it is in a guarded
+/// range, and mark the range.  Return false if ADDR isn't guarded.
+fn replace_lost_page(addr: usize) -> bool {
+    // The lock is only ever held briefly by another thread, since the
+    // thread which faulted can't hold it.
+    let mut ranges = loop {
+        match GUARDED_RANGES.try_lock() {
+            Ok(ranges) => break ranges,
+            Err(TryLockError::Poisoned(error)) => break error.into_inner(),
+            Err(TryLockError::WouldBlock) => atomic::spin_loop_hint(),
+        }
+    };
+    let range = match ranges
+        .iter_mut()
+        .find(|range| range.start <= addr && addr < range.end)
+    {
+        Some(range) => range,
+        None => return false,
+    };
+    let page_size = PAGE_SIZE.load(Ordering::Relaxed);
+    let zeros = unsafe {
+        libc::mmap(
+            (addr & !(page_size - 1)) as *mut c_void,
+            page_size,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_FIXED,
+            -1,
+            0,
+        )
+    };
+    if zeros == libc::MAP_FAILED {
+        return false;
+    }
+    range.intact = false;
+    true
+}
+
+extern "C" fn handle_sigbus(_sig: c_int, info: *mut libc::siginfo_t, _context: *mut c_void) {
+    if !replace_lost_page(unsafe { fault_address(info) }) {
+        // Let the previous handler have the fault when it happens
+        // again, as soon as this returns.
+        unsafe {
+            if let Some(ref previous) = PREVIOUS_ACTION {
+                libc::sigaction(libc::SIGBUS, previous, ptr::null_mut());
+            }
+        }
+    }
+}
+
+fn install_handler() {
+    PAGE_SIZE.store(
+        unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize,
+        Ordering::Relaxed,
+    );
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = handle_sigbus as usize;
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+        libc::sigemptyset(&mut action.sa_mask);
+        let mut previous: libc::sigaction = mem::zeroed();
+        if libc::sigaction(libc::SIGBUS, &action, &mut previous) == 0 {
+            PREVIOUS_ACTION = Some(previous);
+        }
+    }
+}
+
+/// Guard the LEN bytes at ADDR, which are mapped from a file, so that
+/// reading the pages of them that the file loses reads zeros.
+pub fn guard_mapping(addr: *const u8, len: usize) {
+    INSTALL_HANDLER.call_once(install_handler);
+    GUARDED_RANGES.lock().unwrap().push(GuardedRange {
+        start: addr as usize,
+        end: addr as usize + len,
+        intact: true,
+    });
+}
+
+/// Stop guarding the mapping at ADDR, before it is unmapped.  Return
+/// false if some of its pages were lost, and read as zeros.
+pub fn unguard_mapping(addr: *const u8) -> bool {
+    let mut ranges = GUARDED_RANGES.lock().unwrap();
+    match ranges.iter().position(|range| range.start == addr as usize) {
+        Some(i) => ranges.swap_remove(i).intact,
+        None => true,
+    }
+}
@@ -0,0 +1,696 @@
+//! Sockets of network processes.
+//!
+//! `set_ip_socket_option' handles the options of `make-network-process'
+//! and `set-network-process-option' that the table in process.c doesn't
+//! have: joining and leaving multicast groups, the other multicast
+//! options, and the type of service, which service discovery with mDNS
+//! or SSDP needs.  With `:ancillary', process.c reads datagrams with
+//! `recv_datagram_ancillary', which uses recvmsg to learn where each
+//! datagram was sent to and how it arrived, for
+//! `process-datagram-ancillary'.
+//!
+//! Over local sockets, `peer_credentials' tells process.c who is at the
+//! other end, for the `:peer-credentials' of the process, and
+//! `process-send-fd' passes file descriptors to the other end, the way
+//! emacsclient could pass its terminal.
+
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::io;
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::ptr;
+use std::sync::Mutex;
+
+use errno::{errno, set_errno, Errno};
+use libc::{c_char, c_int, c_uint, c_void, socklen_t};
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    eval::unbind_to,
+    lisp::defsubr,
+    lisp::LispObject,
+    obarray::intern,
+    process::LispProcessRef,
+    remacs_sys::{close_file_unwind, emacs_open, encode_file_name, maybe_quit},
+    remacs_sys::{record_unwind_protect_int, report_file_error, Fexpand_file_name},
+    remacs_sys::{EmacsInt, Qnetwork, Qnil, Qstringp},
+    threads::{c_specpdl_index, ThreadState},
+};
+
+/// The type of the values of IP_MULTICAST_TTL and IP_MULTICAST_LOOP,
+/// which is an int on GNU/Linux, but must be an unsigned char on BSD.
+#[cfg(target_os = "linux")]
+type MulticastByte = c_int;
+#[cfg(not(target_os = "linux"))]
+type MulticastByte = u8;
+
+#[cfg(target_os = "linux")]
+const IPV6_JOIN_GROUP: c_int = libc::IPV6_ADD_MEMBERSHIP;
+#[cfg(target_os = "linux")]
+const IPV6_LEAVE_GROUP: c_int = libc::IPV6_DROP_MEMBERSHIP;
+#[cfg(not(target_os = "linux"))]
+const IPV6_JOIN_GROUP: c_int = libc::IPV6_JOIN_GROUP;
+#[cfg(not(target_os = "linux"))]
+const IPV6_LEAVE_GROUP: c_int = libc::IPV6_LEAVE_GROUP;
+
+/// The number of words of the buffer for the control messages of a
+/// datagram, which is plenty for those `:ancillary' asks for.
+const CONTROL_WORDS: usize = 32;
+
+/// How long to wait for a local socket to take more data at a time, in
+/// milliseconds, before checking for quits.
+const SEND_POLL_MS: c_int = 100;
+
+/// Don't raise SIGPIPE when the other end is gone, which Emacs only
+/// ignores when it isn't in batch mode.
+#[cfg(target_os = "linux")]
+const SEND_FLAGS: c_int = libc::MSG_NOSIGNAL;
+#[cfg(not(target_os = "linux"))]
+const SEND_FLAGS: c_int = 0;
+
+lazy_static! {
+    /// The sockets `:ancillary' is set for, with what the last datagram
+    /// read from each came with.
+    static ref DATAGRAM_ANCILLARY: Mutex<HashMap<c_int, Option<Ancillary>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// What a datagram came with, as far as the system tells.
+#[derive(Clone, Default)]
+struct Ancillary {
+    destination: Option<IpAddr>,
+    interface: Option<c_uint>,
+    tos: Option<u8>,
+    ttl: Option<c_int>,
+}
+
+impl Ancillary {
+    fn to_lisp(&self) -> LispObject {
+        let mut plist = Qnil;
+        {
+            let mut put = |key: &str, value: LispObject| {
+                plist = LispObject::cons(intern(key), LispObject::cons(value, plist));
+            };
+            if let Some(ttl) = self.ttl {
+                put(":ttl", LispObject::from(EmacsInt::from(ttl)));
+            }
+            if let Some(tos) = self.tos {
+                put(":tos", LispObject::from(EmacsInt::from(tos)));
+            }
+            if let Some(interface) = self.interface {
+                put(":interface", interface_name(interface));
+            }
+            if let Some(destination) = self.destination {
+                put(
+                    ":destination",
+                    LispObject::from(destination.to_string().as_str()),
+                );
+            }
+        }
+        plist
+    }
+}
+
+/// Return the name of the network interface INDEX, or INDEX if it has
+/// none.
+fn interface_name(index: c_uint) -> LispObject {
+    let mut name = [0 as c_char; libc::IF_NAMESIZE];
+    if unsafe { libc::if_indextoname(index, name.as_mut_ptr()) }.is_null() {
+        LispObject::from(EmacsInt::from(index))
+    } else {
+        let name = unsafe { CStr::from_ptr(name.as_ptr()) };
+        LispObject::from(&*name.to_string_lossy())
+    }
+}
+
+fn bad_value(option: &str) -> ! {
+    error!("Bad option value for {}", option)
+}
+
+/// Return the IP address VALUE, a string like "224.0.0.251" or
+/// "ff02::fb", for OPTION.
+fn address_value(option: &str, value: LispObject) -> IpAddr {
+    value
+        .as_string()
+        .and_then(|string| string.to_string().parse().ok())
+        .unwrap_or_else(|| bad_value(option))
+}
+
+/// Return the IPv4 address of the interface VALUE for OPTION, where nil
+/// lets the system choose.
+fn ipv4_interface_value(option: &str, value: LispObject) -> Ipv4Addr {
+    if value.is_nil() {
+        return Ipv4Addr::UNSPECIFIED;
+    }
+    match address_value(option, value) {
+        IpAddr::V4(address) => address,
+        IpAddr::V6(_) => bad_value(option),
+    }
+}
+
+/// Return the index of the interface VALUE, a name or an index, for
+/// OPTION, where nil lets the system choose.
+fn interface_index_value(option: &str, value: LispObject) -> c_uint {
+    if value.is_nil() {
+        return 0;
+    }
+    if let Some(index) = value.as_fixnum() {
+        if index >= 0 && index <= EmacsInt::from(c_uint::max_value()) {
+            return index as c_uint;
+        }
+    } else if let Some(name) = value.as_string() {
+        if let Ok(name) = CString::new(name.as_slice()) {
+            match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+                0 => error!("No such network interface: {}", value.as_string_or_error()),
+                index => return index,
+            }
+        }
+    }
+    bad_value(option)
+}
+
+/// Return the integer VALUE for OPTION, if it is between MIN and MAX.
+fn int_value(option: &str, value: LispObject, min: c_int, max: c_int) -> c_int {
+    match value.as_fixnum() {
+        Some(n) if n >= EmacsInt::from(min) && n <= EmacsInt::from(max) => n as c_int,
+        _ => bad_value(option),
+    }
+}
+
+fn in_addr(address: Ipv4Addr) -> libc::in_addr {
+    libc::in_addr {
+        s_addr: u32::from(address).to_be(),
+    }
+}
+
+fn in6_addr(address: Ipv6Addr) -> libc::in6_addr {
+    let mut addr: libc::in6_addr = unsafe { mem::zeroed() };
+    addr.s6_addr = address.octets();
+    addr
+}
+
+/// Set the option NAME at LEVEL of SOCKET to VALUE.  Return false, with
+/// errno set, if that fails.
+fn setsockopt<T>(socket: c_int, level: c_int, name: c_int, value: &T) -> bool {
+    unsafe {
+        libc::setsockopt(
+            socket,
+            level,
+            name,
+            value as *const T as *const c_void,
+            mem::size_of::<T>() as socklen_t,
+        ) == 0
+    }
+}
+
+/// Return the address family of SOCKET, or AF_UNSPEC if the system
+/// doesn't tell.
+fn socket_family(socket: c_int) -> c_int {
+    let mut address: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut len = mem::size_of_val(&address) as socklen_t;
+    let address_ptr = &mut address as *mut libc::sockaddr_storage as *mut libc::sockaddr;
+    if unsafe { libc::getsockname(socket, address_ptr, &mut len) } == 0 {
+        c_int::from(address.ss_family)
+    } else {
+        libc::AF_UNSPEC
+    }
+}
+
+/// Return true if SOCKET is an IPv6 socket.
+fn ipv6_socket_p(socket: c_int) -> bool {
+    socket_family(socket) == libc::AF_INET6
+}
+
+/// Join the multicast group VALUE on SOCKET, or leave it unless JOIN.
+/// VALUE is the address of the group, or a list (GROUP INTERFACE).
+fn set_membership(socket: c_int, option: &str, value: LispObject, join: bool) -> bool {
+    let (group, interface) = match value.as_cons() {
+        Some(cons) => (
+            cons.car(),
+            cons.cdr().as_cons().map_or(Qnil, |cdr| cdr.car()),
+        ),
+        None => (value, Qnil),
+    };
+    match address_value(option, group) {
+        IpAddr::V4(group) => {
+            let request = libc::ip_mreq {
+                imr_multiaddr: in_addr(group),
+                imr_interface: in_addr(ipv4_interface_value(option, interface)),
+            };
+            let name = if join {
+                libc::IP_ADD_MEMBERSHIP
+            } else {
+                libc::IP_DROP_MEMBERSHIP
+            };
+            setsockopt(socket, libc::IPPROTO_IP, name, &request)
+        }
+        IpAddr::V6(group) => {
+            let request = libc::ipv6_mreq {
+                ipv6mr_multiaddr: in6_addr(group),
+                ipv6mr_interface: interface_index_value(option, interface),
+            };
+            let name = if join {
+                IPV6_JOIN_GROUP
+            } else {
+                IPV6_LEAVE_GROUP
+            };
+            setsockopt(socket, libc::IPPROTO_IPV6, name, &request)
+        }
+    }
+}
+
+/// Return the options that make the system tell what datagrams came
+/// with, for IPv6 sockets if IPV6 and IPv4 sockets otherwise.
+#[cfg(target_os = "linux")]
+fn ancillary_options(ipv6: bool) -> Option<&'static [(c_int, c_int)]> {
+    const IPV4: [(c_int, c_int); 3] = [
+        (libc::IPPROTO_IP, libc::IP_PKTINFO),
+        (libc::IPPROTO_IP, libc::IP_RECVTOS),
+        (libc::IPPROTO_IP, libc::IP_RECVTTL),
+    ];
+    const IPV6: [(c_int, c_int); 3] = [
+        (libc::IPPROTO_IPV6, libc::IPV6_RECVPKTINFO),
+        (libc::IPPROTO_IPV6, libc::IPV6_RECVTCLASS),
+        (libc::IPPROTO_IPV6, libc::IPV6_RECVHOPLIMIT),
+    ];
+    Some(if ipv6 { &IPV6 } else { &IPV4 })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn ancillary_options(_ipv6: bool) -> Option<&'static [(c_int, c_int)]> {
+    None
+}
+
+/// Read what a datagram came with from the control messages of MESSAGE,
+/// as recvmsg returned it.
+#[cfg(target_os = "linux")]
+unsafe fn read_ancillary(message: &libc::msghdr) -> Ancillary {
+    use std::ptr::read_unaligned;
+
+    let mut ancillary = Ancillary::default();
+    let mut header = libc::CMSG_FIRSTHDR(message);
+    while !header.is_null() {
+        let data = libc::CMSG_DATA(header);
+        match ((*header).cmsg_level, (*header).cmsg_type) {
+            (libc::IPPROTO_IP, libc::IP_PKTINFO) => {
+                let info = read_unaligned(data as *const libc::in_pktinfo);
+                let address = Ipv4Addr::from(u32::from_be(info.ipi_addr.s_addr));
+                ancillary.destination = Some(IpAddr::V4(address));
+                ancillary.interface = Some(info.ipi_ifindex as c_uint);
+            }
+            (libc::IPPROTO_IP, libc::IP_TOS) => ancillary.tos = Some(*data),
+            (libc::IPPROTO_IP, libc::IP_TTL) => {
+                ancillary.ttl = Some(read_unaligned(data as *const c_int));
+            }
+            (libc::IPPROTO_IPV6, libc::IPV6_PKTINFO) => {
+                let info = read_unaligned(data as *const libc::in6_pktinfo);
+                let address = Ipv6Addr::from(info.ipi6_addr.s6_addr);
+                ancillary.destination = Some(IpAddr::V6(address));
+                ancillary.interface = Some(info.ipi6_ifindex as c_uint);
+            }
+            (libc::IPPROTO_IPV6, libc::IPV6_TCLASS) => {
+                ancillary.tos = Some(read_unaligned(data as *const c_int) as u8);
+            }
+            (libc::IPPROTO_IPV6, libc::IPV6_HOPLIMIT) => {
+                ancillary.ttl = Some(read_unaligned(data as *const c_int));
+            }
+            _ => (),
+        }
+        header = libc::CMSG_NXTHDR(message, header);
+    }
+    ancillary
+}
+
+#[cfg(not(target_os = "linux"))]
+unsafe fn read_ancillary(_message: &libc::msghdr) -> Ancillary {
+    Ancillary::default()
+}
+
+/// Make the system tell what the datagrams read from SOCKET come with if
+/// ENABLE, and stop it otherwise.  Return None if the system can't.
+fn set_ancillary(socket: c_int, enable: bool) -> Option<bool> {
+    let options = ancillary_options(ipv6_socket_p(socket))?;
+    let value = c_int::from(enable);
+    if !options
+        .iter()
+        .all(|&(level, name)| setsockopt(socket, level, name, &value))
+    {
+        return Some(false);
+    }
+
+    let mut sockets = DATAGRAM_ANCILLARY.lock().unwrap();
+    if enable {
+        sockets.entry(socket).or_insert(None);
+    } else {
+        sockets.remove(&socket);
+    }
+    Some(true)
+}
+
+/// Set the option OPT of the IP socket SOCKET to VAL, if it is one of
+/// the options process.c leaves to this file.  Return false if OPT is
+/// not, and signal an error if setting it fails.
+#[no_mangle]
+pub extern "C" fn set_ip_socket_option(socket: c_int, opt: LispObject, val: LispObject) -> bool {
+    let name = opt
+        .as_symbol_or_error()
+        .symbol_name()
+        .as_string_or_error()
+        .to_string();
+    let option = name.as_str();
+    let set = match option {
+        ":multicast-join" => set_membership(socket, option, val, true),
+        ":multicast-leave" => set_membership(socket, option, val, false),
+        ":multicast-loop" => {
+            let enable = c_int::from(val.is_not_nil());
+            if ipv6_socket_p(socket) {
+                setsockopt(
+                    socket,
+                    libc::IPPROTO_IPV6,
+                    libc::IPV6_MULTICAST_LOOP,
+                    &enable,
+                )
+            } else {
+                let enable = enable as MulticastByte;
+                setsockopt(socket, libc::IPPROTO_IP, libc::IP_MULTICAST_LOOP, &enable)
+            }
+        }
+        ":multicast-ttl" => {
+            let ttl = int_value(option, val, 0, 255);
+            if ipv6_socket_p(socket) {
+                setsockopt(socket, libc::IPPROTO_IPV6, libc::IPV6_MULTICAST_HOPS, &ttl)
+            } else {
+                let ttl = ttl as MulticastByte;
+                setsockopt(socket, libc::IPPROTO_IP, libc::IP_MULTICAST_TTL, &ttl)
+            }
+        }
+        ":multicast-interface" => {
+            if ipv6_socket_p(socket) {
+                let index = interface_index_value(option, val);
+                setsockopt(socket, libc::IPPROTO_IPV6, libc::IPV6_MULTICAST_IF, &index)
+            } else {
+                let address = in_addr(ipv4_interface_value(option, val));
+                setsockopt(socket, libc::IPPROTO_IP, libc::IP_MULTICAST_IF, &address)
+            }
+        }
+        ":tos" => {
+            let tos = int_value(option, val, 0, 255);
+            if ipv6_socket_p(socket) {
+                setsockopt(socket, libc::IPPROTO_IPV6, libc::IPV6_TCLASS, &tos)
+            } else {
+                setsockopt(socket, libc::IPPROTO_IP, libc::IP_TOS, &tos)
+            }
+        }
+        ":ancillary" => match set_ancillary(socket, val.is_not_nil()) {
+            Some(set) => set,
+            None => return false,
+        },
+        _ => return false,
+    };
+
+    if !set {
+        unsafe {
+            report_file_error(
+                "Cannot set network option\0".as_ptr() as *const c_char,
+                list!(opt, val),
+            )
+        }
+    }
+    true
+}
+
+/// Return true if `:ancillary' is set for SOCKET, so that its datagrams
+/// are to be read with `recv_datagram_ancillary'.
+#[no_mangle]
+pub extern "C" fn datagram_ancillary_p(socket: c_int) -> bool {
+    DATAGRAM_ANCILLARY.lock().unwrap().contains_key(&socket)
+}
+
+/// Forget about SOCKET, which is being closed.
+#[no_mangle]
+pub extern "C" fn forget_datagram_ancillary(socket: c_int) {
+    DATAGRAM_ANCILLARY.lock().unwrap().remove(&socket);
+}
+
+/// Read a datagram of at most NBYTE bytes from SOCKET into BUF, and its
+/// sender into ADDRESS and LEN, like recvfrom, and record what it came
+/// with for `process-datagram-ancillary'.
+#[no_mangle]
+pub extern "C" fn recv_datagram_ancillary(
+    socket: c_int,
+    buf: *mut c_char,
+    nbyte: isize,
+    address: *mut libc::sockaddr,
+    len: *mut socklen_t,
+) -> isize {
+    let mut iov = libc::iovec {
+        iov_base: buf as *mut c_void,
+        iov_len: nbyte as usize,
+    };
+    let mut control = [0u64; CONTROL_WORDS];
+    let mut message: libc::msghdr = unsafe { mem::zeroed() };
+    message.msg_name = address as *mut c_void;
+    message.msg_namelen = unsafe { *len };
+    message.msg_iov = &mut iov;
+    message.msg_iovlen = 1;
+    message.msg_control = control.as_mut_ptr() as *mut c_void;
+    message.msg_controllen = mem::size_of_val(&control) as _;
+
+    let nread = unsafe { libc::recvmsg(socket, &mut message, 0) };
+    if nread >= 0 {
+        unsafe { *len = message.msg_namelen };
+        let ancillary = unsafe { read_ancillary(&message) };
+        if let Some(last) = DATAGRAM_ANCILLARY.lock().unwrap().get_mut(&socket) {
+            *last = Some(ancillary);
+        }
+    }
+    nread as isize
+}
+
+/// Return what the last datagram PROCESS received came with.
+/// PROCESS is a datagram network process created with `:ancillary' set,
+/// see `make-network-process'.  The value is a plist with these
+/// properties, each of which is left out if the system didn't tell it:
+///
+/// :destination ADDRESS -- The address the datagram was sent to, as a
+///     string, which for a multicast datagram is the address of the group.
+/// :interface INTERFACE -- The name of the network interface the datagram
+///     arrived on, or its index if it has no name.
+/// :tos TOS -- The type of service, or traffic class with IPv6.
+/// :ttl TTL -- The time to live, or hop limit with IPv6.
+///
+/// Return nil if `:ancillary' isn't set for PROCESS, or it hasn't
+/// received a datagram since.
+#[lisp_fn]
+pub fn process_datagram_ancillary(process: LispProcessRef) -> LispObject {
+    if process.infd < 0 {
+        return Qnil;
+    }
+    let ancillary = DATAGRAM_ANCILLARY
+        .lock()
+        .unwrap()
+        .get(&process.infd)
+        .and_then(|last| last.clone());
+    ancillary.map_or(Qnil, |ancillary| ancillary.to_lisp())
+}
+
+/// Return the process ID, user ID and group ID of the process at the
+/// other end of the local socket SOCKET.
+#[cfg(target_os = "linux")]
+fn socket_peer_credentials(
+    socket: c_int,
+) -> Option<(Option<libc::pid_t>, libc::uid_t, libc::gid_t)> {
+    let mut credentials: libc::ucred = unsafe { mem::zeroed() };
+    let mut len = mem::size_of_val(&credentials) as socklen_t;
+    let credentials_ptr = &mut credentials as *mut libc::ucred as *mut c_void;
+    let got = unsafe {
+        libc::getsockopt(
+            socket,
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            credentials_ptr,
+            &mut len,
+        )
+    };
+    if got == 0 {
+        Some((Some(credentials.pid), credentials.uid, credentials.gid))
+    } else {
+        None
+    }
+}
+
+/// Return the user ID and group ID of the process at the other end of
+/// the local socket SOCKET.  The BSDs don't tell its process ID.
+#[cfg(not(target_os = "linux"))]
+fn socket_peer_credentials(
+    socket: c_int,
+) -> Option<(Option<libc::pid_t>, libc::uid_t, libc::gid_t)> {
+    let mut uid = 0;
+    let mut gid = 0;
+    if unsafe { libc::getpeereid(socket, &mut uid, &mut gid) } == 0 {
+        Some((None, uid, gid))
+    } else {
+        None
+    }
+}
+
+/// Return the credentials of the process at the other end of SOCKET as a
+/// list (PID UID GID), for the `:peer-credentials' of a network process,
+/// or nil if SOCKET is not a connected local socket.  PID is nil where
+/// the system doesn't tell it.
+#[no_mangle]
+pub extern "C" fn peer_credentials(socket: c_int) -> LispObject {
+    if socket_family(socket) != libc::AF_UNIX {
+        return Qnil;
+    }
+    socket_peer_credentials(socket).map_or(Qnil, |(pid, uid, gid)| {
+        list!(
+            pid.map_or(Qnil, |pid| LispObject::from(EmacsInt::from(pid))),
+            LispObject::from(EmacsInt::from(uid)),
+            LispObject::from(EmacsInt::from(gid))
+        )
+    })
+}
+
+/// Return the descriptor SOURCE stands for in `process-send-fd'.  If a
+/// file has to be opened for it, it is closed when the current binding
+/// context is unwound.
+fn fd_source(source: LispObject) -> c_int {
+    if let Some(fd) = source.as_fixnum() {
+        if fd < 0 || fd > EmacsInt::from(c_int::max_value()) {
+            error!("Invalid file descriptor: {}", fd);
+        }
+        return fd as c_int;
+    }
+    if let Some(process) = source.as_process() {
+        if process.infd < 0 {
+            error!(
+                "Process {} is not running",
+                process.name.as_string_or_error()
+            );
+        }
+        return process.infd;
+    }
+    if !source.is_string() {
+        wrong_type!(Qstringp, source);
+    }
+
+    let directory = ThreadState::current_buffer().directory_;
+    let filename = unsafe { Fexpand_file_name(source, directory) };
+    let encoded = unsafe { encode_file_name(filename) };
+    let name = encoded.as_string_or_error().const_data_ptr() as *const c_char;
+    let mut fd = unsafe { emacs_open(name, libc::O_RDWR | libc::O_NOCTTY, 0) };
+    if fd < 0 {
+        let Errno(open_errno) = errno();
+        if open_errno == libc::EACCES || open_errno == libc::EISDIR || open_errno == libc::EROFS {
+            fd = unsafe { emacs_open(name, libc::O_RDONLY | libc::O_NOCTTY, 0) };
+        }
+    }
+    if fd < 0 {
+        unsafe { report_file_error("Opening file to pass\0".as_ptr() as *const c_char, filename) }
+    }
+    unsafe { record_unwind_protect_int(Some(close_file_unwind), fd) };
+    fd
+}
+
+/// Send the descriptor FD over SOCKET with SCM_RIGHTS, along with DATA,
+/// waiting until SOCKET has taken all of DATA.
+fn send_fd(socket: c_int, fd: c_int, data: &[u8]) -> io::Result<()> {
+    let mut control = [0u64; CONTROL_WORDS];
+    let mut sent = 0;
+    while sent < data.len() {
+        let mut iov = libc::iovec {
+            iov_base: data[sent..].as_ptr() as *mut c_void,
+            iov_len: data.len() - sent,
+        };
+        let mut message: libc::msghdr = unsafe { mem::zeroed() };
+        message.msg_iov = &mut iov;
+        message.msg_iovlen = 1;
+        if sent == 0 {
+            // The descriptor goes along with the first byte.
+            let fd_len = mem::size_of::<c_int>() as c_uint;
+            unsafe {
+                message.msg_control = control.as_mut_ptr() as *mut c_void;
+                message.msg_controllen = libc::CMSG_SPACE(fd_len) as _;
+                let header = libc::CMSG_FIRSTHDR(&message);
+                (*header).cmsg_level = libc::SOL_SOCKET;
+                (*header).cmsg_type = libc::SCM_RIGHTS;
+                (*header).cmsg_len = libc::CMSG_LEN(fd_len) as _;
+                ptr::write_unaligned(libc::CMSG_DATA(header) as *mut c_int, fd);
+            }
+        }
+
+        match unsafe { libc::sendmsg(socket, &message, SEND_FLAGS) } {
+            -1 => {
+                let error = io::Error::last_os_error();
+                match error.kind() {
+                    io::ErrorKind::WouldBlock => {
+                        let mut pollfd = libc::pollfd {
+                            fd: socket,
+                            events: libc::POLLOUT,
+                            revents: 0,
+                        };
+                        unsafe { libc::poll(&mut pollfd, 1, SEND_POLL_MS) };
+                        unsafe { maybe_quit() };
+                    }
+                    io::ErrorKind::Interrupted => unsafe { maybe_quit() },
+                    _ => return Err(error),
+                }
+            }
+            nsent => sent += nsent as usize,
+        }
+    }
+    Ok(())
+}
+
+/// Pass a file descriptor to the process at the other end of PROCESS.
+/// PROCESS is a network process connected over a local socket, and the
+/// descriptor is passed with SCM_RIGHTS, so that the other end gets a
+/// descriptor of its own for the same open file.  SOURCE says what to
+/// pass:
+///
+/// A string is the name of a file, which is opened for reading and
+/// writing, or only for reading if it can't be written, and closed
+/// again once it has been passed.
+/// A process is the socket or pipe Emacs reads that process from.
+/// An integer is a descriptor of Emacs itself, like 0 for its standard
+/// input.
+///
+/// DATA is a unibyte string sent along with the descriptor, by default
+/// a single NUL byte, since at least one byte has to be.  Return t.
+#[lisp_fn(min = "2")]
+pub fn process_send_fd(process: LispProcessRef, source: LispObject, data: LispObject) -> bool {
+    let socket = process.outfd;
+    if !process.type_.eq(Qnetwork) || socket < 0 || socket_family(socket) != libc::AF_UNIX {
+        error!(
+            "Process {} is not connected over a local socket",
+            process.name.as_string_or_error()
+        );
+    }
+    let data = if data.is_nil() {
+        vec![0]
+    } else {
+        data.as_string_or_error().as_slice().to_vec()
+    };
+    if data.is_empty() {
+        error!("Data sent with a file descriptor must not be empty");
+    }
+
+    let count = c_specpdl_index();
+    let fd = fd_source(source);
+    if let Err(error) = send_fd(socket, fd, &data) {
+        set_errno(Errno(error.raw_os_error().unwrap_or(libc::EIO)));
+        unsafe {
+            report_file_error(
+                "Passing file descriptor\0".as_ptr() as *const c_char,
+                source,
+            )
+        }
+    }
+    unbind_to(count, Qnil);
+    true
+}
+
+include!(concat!(env!("OUT_DIR"), "/sockets_exports.rs"));
@@ -0,0 +1,857 @@
+//! Sorting the lines of a region.
+//!
+//! `sort-subr' in sort.el makes a list of the records of a region, each
+//! with its key copied out as a string, and calls back into Lisp for
+//! every comparison.  `sort-lines-by' copies the text of the region once,
+//! finds its lines and their keys as spans of that copy, sorts the spans
+//! stably, and rebuilds the region from the lines in their new order.
+//!
+//! `sort' itself is a natural merge sort, as timsort is, without
+//! recursion.  When its predicate is `<', `>' or `string<' and the
+//! elements are all of the types it takes, the comparison is made here
+//! rather than through `funcall', and large vectors are sorted with
+//! rayon.
+
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::mem;
+
+use rayon::prelude::*;
+
+use libc::ptrdiff_t;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    buffers::validate_region,
+    editfns::{
+        buffer_substring, delete_region, goto_char, insert_buffer_substring, save_excursion_save,
+    },
+    eval::unbind_to,
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::{LispConsCircularChecks, LispConsEndChecks},
+    marker::buf_charpos_to_bytepos,
+    math::{arithcompare, ArithComparison},
+    multibyte::{multibyte_char_at, Codepoint},
+    numbers::LispNumber,
+    remacs_sys::{record_unwind_protect, save_excursion_restore},
+    remacs_sys::{EmacsInt, Qnatural, Qnil, Qnumeric, Qsort_fold_case, Qsort_numeric_base, Qt},
+    remacs_sys::{Fmake_vector, Qgtr, Qlss, Qsequencep, Qstring_less, Qstring_lessp},
+    search::{match_beginning, match_end, re_search_forward},
+    strings::string_lessp,
+    symbols::{boundp, symbol_value},
+    threads::{c_specpdl_index, ThreadState},
+};
+
+/// How the keys of lines are compared.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Order {
+    /// Character by character, as `string<' compares.
+    Text,
+    /// By the numbers at the start of the keys, as `sort-numeric-fields'
+    /// reads them.
+    Numeric,
+    /// Character by character, except that runs of digits are compared
+    /// by their values, so that "a9" sorts before "a10".
+    Natural,
+}
+
+impl Order {
+    fn from_symbol(order: LispObject) -> Self {
+        if order.is_nil() {
+            Order::Text
+        } else if order.eq(Qnumeric) {
+            Order::Numeric
+        } else if order.eq(Qnatural) {
+            Order::Natural
+        } else {
+            error!("Invalid sort order")
+        }
+    }
+}
+
+/// A line of the region being sorted, as the byte offsets of its text,
+/// without its newline, and of its key in the copy of the region, and the
+/// character offsets of its text from the start of the region.
+#[derive(Debug, PartialEq)]
+struct Line {
+    start: usize,
+    end: usize,
+    key_start: usize,
+    key_end: usize,
+    char_start: usize,
+    char_end: usize,
+}
+
+/// Return the lines of TEXT, the text of a region, with their keys
+/// spanning the whole line.  A newline at the end of TEXT doesn't start
+/// another line.
+fn lines(text: &[u8], multibyte: bool) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    let mut chars = 0;
+    let mut char_start = 0;
+    for (i, &b) in text.iter().enumerate() {
+        if b == b'\n' {
+            lines.push(Line {
+                start,
+                end: i,
+                key_start: start,
+                key_end: i,
+                char_start,
+                char_end: chars,
+            });
+            start = i + 1;
+            char_start = chars + 1;
+        }
+        // Only the first byte of a multibyte character is outside this
+        // range.
+        if !multibyte || b & 0xC0 != 0x80 {
+            chars += 1;
+        }
+    }
+    if start < text.len() {
+        lines.push(Line {
+            start,
+            end: text.len(),
+            key_start: start,
+            key_end: text.len(),
+            char_start,
+            char_end: chars,
+        });
+    }
+    lines
+}
+
+fn is_blank(b: u8) -> bool {
+    b == b' ' || b == b'\t'
+}
+
+/// Return the bounds of field N of LINE, as `sort-skip-fields' finds it:
+/// fields are separated by spaces and tabs, and a negative N counts from
+/// the end of the line.  Return None if LINE has too few fields.
+fn field_bounds(line: &[u8], n: EmacsInt) -> Option<(usize, usize)> {
+    let skip_forward = |mut i: usize, blank: bool| {
+        while i < line.len() && is_blank(line[i]) == blank {
+            i += 1;
+        }
+        i
+    };
+    let skip_backward = |mut i: usize, blank: bool| {
+        while i > 0 && is_blank(line[i - 1]) == blank {
+            i -= 1;
+        }
+        i
+    };
+
+    let start = if n > 0 {
+        let mut i = 0;
+        for _ in 1..n {
+            i = skip_forward(skip_forward(i, true), false);
+        }
+        i = skip_forward(i, true);
+        if i == line.len() {
+            return None;
+        }
+        i
+    } else {
+        let mut i = line.len();
+        for _ in 1..-n {
+            i = skip_backward(skip_backward(i, true), false);
+        }
+        i = skip_backward(i, true);
+        if i == 0 {
+            return None;
+        }
+        skip_backward(i, false)
+    };
+    Some((start, skip_forward(start, false)))
+}
+
+/// The characters of a key, in the representation of the buffer.
+struct KeyChars<'a> {
+    bytes: &'a [u8],
+    multibyte: bool,
+}
+
+impl<'a> Iterator for KeyChars<'a> {
+    type Item = Codepoint;
+
+    fn next(&mut self) -> Option<Codepoint> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+        let (c, len) = if self.multibyte {
+            multibyte_char_at(self.bytes)
+        } else {
+            (Codepoint::from(self.bytes[0]), 1)
+        };
+        self.bytes = &self.bytes[len..];
+        Some(c)
+    }
+}
+
+fn is_digit(c: Codepoint) -> bool {
+    c >= Codepoint::from(b'0') && c <= Codepoint::from(b'9')
+}
+
+/// Take the run of digits at the start of CHARS, and return it without
+/// its leading zeros.
+fn digit_run<I>(chars: &mut Peekable<I>) -> Vec<Codepoint>
+where
+    I: Iterator<Item = Codepoint>,
+{
+    let mut run = Vec::new();
+    while let Some(&c) = chars.peek() {
+        if !is_digit(c) {
+            break;
+        }
+        if !(run.is_empty() && c == Codepoint::from(b'0')) {
+            run.push(c);
+        }
+        chars.next();
+    }
+    run
+}
+
+/// Compare the characters A and B, which CANON maps to their canonical
+/// case, so that runs of digits compare by their values if NATURAL.
+fn compare_chars<A, B, F>(a: A, b: B, natural: bool, canon: F) -> Ordering
+where
+    A: Iterator<Item = Codepoint>,
+    B: Iterator<Item = Codepoint>,
+    F: Fn(Codepoint) -> Codepoint,
+{
+    let mut a = a.peekable();
+    let mut b = b.peekable();
+    loop {
+        match (a.peek().cloned(), b.peek().cloned()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if natural && is_digit(x) && is_digit(y) => {
+                let (x, y) = (digit_run(&mut a), digit_run(&mut b));
+                match x.len().cmp(&y.len()).then_with(|| x.cmp(&y)) {
+                    Ordering::Equal => {}
+                    ordering => return ordering,
+                }
+            }
+            (Some(x), Some(y)) => match canon(x).cmp(&canon(y)) {
+                Ordering::Equal => {
+                    a.next();
+                    b.next();
+                }
+                ordering => return ordering,
+            },
+        }
+    }
+}
+
+/// Return the decimal number at the start of S, as `string-to-number'
+/// reads it, or 0 if there is none.
+fn decimal_prefix(s: &[u8]) -> f64 {
+    let digits_from = |i: usize| s[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+    let mut len = digits_from(0);
+    if s.get(len) == Some(&b'.') {
+        let fraction = digits_from(len + 1);
+        if len + fraction > 0 {
+            len += 1 + fraction;
+        }
+    }
+    if len == 0 {
+        return 0.0;
+    }
+    if s.get(len).map_or(false, |&b| b == b'e' || b == b'E') {
+        let mut i = len + 1;
+        if s.get(i).map_or(false, |&b| b == b'+' || b == b'-') {
+            i += 1;
+        }
+        let exponent = digits_from(i);
+        if exponent > 0 {
+            len = i + exponent;
+        }
+    }
+    std::str::from_utf8(&s[..len])
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0.0)
+}
+
+/// Return the number at the start of KEY, as `sort-numeric-fields' reads
+/// it: in hexadecimal after "0x", in octal after "0", and otherwise in
+/// BASE.  A key which doesn't start with a number counts as 0.
+fn numeric_key(key: &[u8], base: u32) -> f64 {
+    let key = &key[key.iter().take_while(|&&b| is_blank(b)).count()..];
+    let is_digit_at = |i: usize, base: u32| {
+        key.get(i)
+            .map_or(false, |&b| (b as char).to_digit(base).is_some())
+    };
+    let (key, base) = if (key.starts_with(b"0x") || key.starts_with(b"0X")) && is_digit_at(2, 16) {
+        (&key[2..], 16)
+    } else if key.starts_with(b"0") && is_digit_at(1, 8) {
+        (&key[1..], 8)
+    } else {
+        (key, base)
+    };
+
+    let (negative, digits) = match key.first() {
+        Some(b'-') => (true, &key[1..]),
+        Some(b'+') => (false, &key[1..]),
+        _ => (false, key),
+    };
+    let value = if base == 10 {
+        decimal_prefix(digits)
+    } else {
+        digits
+            .iter()
+            .map(|&b| (b as char).to_digit(base))
+            .take_while(Option::is_some)
+            .fold(0.0, |value, digit| {
+                value * f64::from(base) + f64::from(digit.unwrap())
+            })
+    };
+    if negative {
+        -value
+    } else {
+        value
+    }
+}
+
+/// Return the value of the variable SYMBOL of sort.el, or nil if that
+/// hasn't been loaded.
+fn sort_variable(symbol: LispObject) -> LispObject {
+    if boundp(symbol.into()) {
+        symbol_value(symbol.into())
+    } else {
+        Qnil
+    }
+}
+
+/// Set the keys of LINES, the lines of the region which starts at BEG
+/// and byte BEG_BYTE, to the text matched by the first match in each line
+/// of the regexp of KEY, which is a regexp or a cons of a regexp and the
+/// number of the group to use.  Lines without a match get an empty key.
+fn find_regexp_keys(lines: &mut [Line], beg: EmacsInt, beg_byte: ptrdiff_t, key: LispObject) {
+    let (regexp, group) = match key.as_cons() {
+        Some(cons) => (cons.car(), cons.cdr()),
+        None => (key, LispObject::from(0)),
+    };
+    regexp.as_string_or_error();
+    group.as_fixnum_or_error();
+
+    let mut buffer = ThreadState::current_buffer();
+    let count = c_specpdl_index();
+    unsafe { record_unwind_protect(Some(save_excursion_restore), save_excursion_save()) };
+
+    for line in lines {
+        goto_char((beg + line.char_start as EmacsInt).into());
+        let bound = LispObject::from(beg + line.char_end as EmacsInt);
+        let bounds = if re_search_forward(regexp, bound, Qt, Qnil).is_not_nil() {
+            match_beginning(group)
+                .as_fixnum()
+                .and_then(|start| match_end(group).as_fixnum().map(|end| (start, end)))
+        } else {
+            None
+        };
+        let offset = |pos: EmacsInt| {
+            (unsafe { buf_charpos_to_bytepos(buffer.as_mut(), pos as ptrdiff_t) } - beg_byte)
+                as usize
+        };
+        match bounds {
+            Some((start, end)) => {
+                line.key_start = offset(start);
+                line.key_end = offset(end);
+            }
+            None => line.key_end = line.key_start,
+        }
+    }
+
+    unbind_to(count, Qnil);
+}
+
+/// Replace the LINES of the region from BEG to END with the lines in the
+/// order SORTED.  The newlines between the lines stay where they are, and
+/// the lines keep their text properties.
+fn reorder_lines(beg: EmacsInt, end: EmacsInt, lines: &[Line], sorted: &[usize]) {
+    let buffer = LispObject::from(ThreadState::current_buffer());
+    let count = c_specpdl_index();
+    unsafe { record_unwind_protect(Some(save_excursion_restore), save_excursion_save()) };
+
+    // The sorted text is inserted before the old text, which moves forward
+    // as it goes in, and is then deleted.  This leaves the markers before
+    // and after the region where they were.
+    goto_char(beg.into());
+    let mut inserted = 0;
+    let mut copy = |from: usize, to: usize| {
+        let from = beg + inserted + from as EmacsInt;
+        let to = beg + inserted + to as EmacsInt;
+        insert_buffer_substring(
+            buffer.into(),
+            Some(LispNumber::Fixnum(from)),
+            Some(LispNumber::Fixnum(to)),
+        );
+        inserted += to - from;
+    };
+    for (i, &j) in sorted.iter().enumerate() {
+        copy(lines[j].char_start, lines[j].char_end);
+        let next = lines
+            .get(i + 1)
+            .map_or((end - beg) as usize, |line| line.char_start);
+        copy(lines[i].char_end, next);
+    }
+    delete_region((beg + inserted).into(), (end + inserted).into());
+
+    unbind_to(count, Qnil);
+}
+
+/// Sort the lines of the region between BEG and END, stably.
+/// Optional third argument REVERSE non-nil means sort in descending
+/// order; lines with equal keys stay in the order they were in.
+///
+/// KEY says which part of each line to compare.  If it is nil, that is
+/// the whole line.  If it is a number N, it is the Nth field of the line,
+/// as in `sort-fields': fields are separated by whitespace and numbered
+/// from 1 up, and a negative N counts from the end of the line.  If it is
+/// a regexp, it is the text of the first match for the regexp in the
+/// line, and if it is a cons (REGEXP . GROUP), the text of that group of
+/// the match.  Lines without a match have an empty key.
+///
+/// ORDER says how keys are compared.  If it is nil, they are compared
+/// character by character.  If it is `numeric', they are compared by the
+/// numbers they start with, which are read as in `sort-numeric-fields'.
+/// If it is `natural', runs of digits are compared by their values, so
+/// that "file9" sorts before "file10".  The variable `sort-fold-case'
+/// determines whether alphabetic case affects the sort order.
+#[lisp_fn(min = "2")]
+pub fn sort_lines_by(
+    beg: LispObject,
+    end: LispObject,
+    reverse: bool,
+    key: LispObject,
+    order: LispObject,
+) {
+    let order = Order::from_symbol(order);
+    let (mut beg, mut end) = (beg, end);
+    unsafe { validate_region(&mut beg, &mut end) };
+    let (beg, end) = (beg.as_fixnum_or_error(), end.as_fixnum_or_error());
+
+    let mut buffer = ThreadState::current_buffer();
+    let beg_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), beg as ptrdiff_t) };
+    let end_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), end as ptrdiff_t) };
+    let mut text = Vec::new();
+    for chunk in buffer.byte_chunks(beg_byte, end_byte) {
+        text.extend_from_slice(chunk);
+    }
+    let multibyte = buffer.multibyte_characters_enabled();
+
+    let mut lines = lines(&text, multibyte);
+    if let Some(field) = key.as_fixnum() {
+        let field = if field == 0 { 1 } else { field };
+        for line in &mut lines {
+            match field_bounds(&text[line.start..line.end], field) {
+                Some((start, end)) => {
+                    line.key_start = line.start + start;
+                    line.key_end = line.start + end;
+                }
+                None => error!(
+                    "Line has too few fields: {}",
+                    buffer_substring(
+                        (beg + line.char_start as EmacsInt).into(),
+                        (beg + line.char_end as EmacsInt).into()
+                    )
+                    .as_string_or_error()
+                ),
+            }
+        }
+    } else if key.is_not_nil() {
+        find_regexp_keys(&mut lines, beg, beg_byte, key);
+    }
+
+    let table = if sort_variable(Qsort_fold_case).is_not_nil() {
+        buffer.case_canon_table_.as_char_table()
+    } else {
+        None
+    };
+    let canon = |c: Codepoint| {
+        table.map_or(c, |table| {
+            table
+                .get(c as isize)
+                .as_fixnum()
+                .map_or(c, |c| c as Codepoint)
+        })
+    };
+    let key_chars = |line: &Line| KeyChars {
+        bytes: &text[line.key_start..line.key_end],
+        multibyte,
+    };
+    let numbers: Vec<f64> = if order == Order::Numeric {
+        let base = sort_variable(Qsort_numeric_base)
+            .as_fixnum()
+            .filter(|&base| 2 <= base && base <= 16)
+            .map_or(10, |base| base as u32);
+        lines
+            .iter()
+            .map(|line| numeric_key(&text[line.key_start..line.key_end], base))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let mut sorted: Vec<usize> = (0..lines.len()).collect();
+    sorted.sort_by(|&a, &b| {
+        let ordering = match order {
+            Order::Numeric => numbers[a]
+                .partial_cmp(&numbers[b])
+                .unwrap_or(Ordering::Equal),
+            _ => compare_chars(
+                key_chars(&lines[a]),
+                key_chars(&lines[b]),
+                order == Order::Natural,
+                &canon,
+            ),
+        };
+        if reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+
+    if sorted.iter().enumerate().any(|(i, &j)| i != j) {
+        reorder_lines(beg, end, &lines, &sorted);
+    }
+}
+
+/// Runs shorter than this are lengthened by insertion sort before they
+/// are merged, as in timsort.
+const MIN_RUN: usize = 32;
+
+/// Vectors at least this long are sorted on several threads, when the
+/// predicate is one `sort' knows.
+const PARALLEL_SORT_THRESHOLD: usize = 1 << 14;
+
+/// Sort ITEMS stably, where LESS says whether its first argument comes
+/// before its second.  LESS need not be a total order, as a Lisp
+/// predicate need not be: it is only asked whether an element is less
+/// than one before it.
+pub fn stable_sort<T, F>(items: &mut [T], less: F)
+where
+    T: Copy,
+    F: FnMut(T, T) -> bool,
+{
+    let mut scratch = items.to_vec();
+    stable_sort_with(items, &mut scratch, less);
+}
+
+/// Sort ITEMS stably as `stable_sort' does, merging through SCRATCH,
+/// which is as long as ITEMS.
+///
+/// The runs already in order are found first, descending runs being
+/// reversed and short ones lengthened, and then merged bottom-up, so
+/// sorted or reversed input takes linear time.
+fn stable_sort_with<T, F>(items: &mut [T], scratch: &mut [T], mut less: F)
+where
+    T: Copy,
+    F: FnMut(T, T) -> bool,
+{
+    let length = items.len();
+    let mut bounds = Vec::new();
+    let mut start = 0;
+    while start < length {
+        let mut end = start + 1;
+        if end < length && less(items[end], items[start]) {
+            // Reversing a strictly descending run keeps the sort stable.
+            while end < length && less(items[end], items[end - 1]) {
+                end += 1;
+            }
+            items[start..end].reverse();
+        } else {
+            while end < length && !less(items[end], items[end - 1]) {
+                end += 1;
+            }
+        }
+
+        let run_end = (start + MIN_RUN).min(length).max(end);
+        for i in end..run_end {
+            let item = items[i];
+            let (mut low, mut high) = (start, i);
+            while low < high {
+                let middle = (low + high) / 2;
+                if less(item, items[middle]) {
+                    high = middle;
+                } else {
+                    low = middle + 1;
+                }
+            }
+            items[low..=i].rotate_right(1);
+        }
+        bounds.push(start);
+        start = run_end;
+    }
+    bounds.push(length);
+
+    // Each pass merges the runs of one of ITEMS and SCRATCH into the
+    // other.
+    let mut items_sorted = true;
+    let (mut items, mut merged) = (items, scratch);
+    while bounds.len() > 2 {
+        let mut next_bounds = Vec::with_capacity(bounds.len() / 2 + 1);
+        for pair in bounds.windows(3).step_by(2) {
+            let (start, middle, end) = (pair[0], pair[1], pair[2]);
+            let (mut left, mut right) = (start, middle);
+            for slot in &mut merged[start..end] {
+                if left < middle && (right >= end || !less(items[right], items[left])) {
+                    *slot = items[left];
+                    left += 1;
+                } else {
+                    *slot = items[right];
+                    right += 1;
+                }
+            }
+            next_bounds.push(start);
+        }
+        // An odd run out has nothing to merge with.
+        if bounds.len() % 2 == 0 {
+            let start = bounds[bounds.len() - 2];
+            merged[start..].copy_from_slice(&items[start..]);
+            next_bounds.push(start);
+        }
+        next_bounds.push(length);
+        mem::swap(&mut items, &mut merged);
+        items_sorted = !items_sorted;
+        bounds = next_bounds;
+    }
+    if !items_sorted {
+        merged.copy_from_slice(items);
+    }
+}
+
+/// A predicate which `sort' can compare elements with itself.
+#[derive(Clone, Copy)]
+enum KnownPredicate {
+    Less,
+    Greater,
+    StringLess,
+}
+
+impl KnownPredicate {
+    /// Return the predicate PREDICATE is, if it is one `sort' knows and
+    /// ITEMS are all of the types it takes.  NaNs, which are neither
+    /// less nor greater than anything, are left to PREDICATE.
+    fn recognize(predicate: LispObject, items: &[LispObject]) -> Option<KnownPredicate> {
+        let known = if predicate.eq(Qlss) {
+            KnownPredicate::Less
+        } else if predicate.eq(Qgtr) {
+            KnownPredicate::Greater
+        } else if predicate.eq(Qstring_lessp) || predicate.eq(Qstring_less) {
+            KnownPredicate::StringLess
+        } else {
+            return None;
+        };
+        let takes = |item: &LispObject| match known {
+            KnownPredicate::StringLess => item.is_string() || item.is_symbol(),
+            _ => item.is_fixnum() || item.as_float().map_or(false, |f| !f.is_nan()),
+        };
+        if items.iter().all(takes) {
+            Some(known)
+        } else {
+            None
+        }
+    }
+
+    fn less(self, a: LispObject, b: LispObject) -> bool {
+        match self {
+            KnownPredicate::Less => arithcompare(a, b, ArithComparison::Less),
+            KnownPredicate::Greater => arithcompare(a, b, ArithComparison::Grtr),
+            KnownPredicate::StringLess => string_lessp(a, b),
+        }
+    }
+
+    fn compare(self, a: LispObject, b: LispObject) -> Ordering {
+        if self.less(a, b) {
+            Ordering::Less
+        } else if self.less(b, a) {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }
+    }
+}
+
+/// Return a new Lisp vector of ITEMS, where the garbage collector sees
+/// them while a predicate runs.
+fn lisp_vector(items: &[LispObject]) -> LispObject {
+    let vector = unsafe { Fmake_vector(LispObject::from(items.len()), Qnil) };
+    vector
+        .as_vector_or_error()
+        .as_mut_slice()
+        .copy_from_slice(items);
+    vector
+}
+
+/// Sort the elements of a list or vector, ITEMS, by PREDICATE, merging
+/// through SCRATCH, which is as long as ITEMS.  Both are the contents of
+/// Lisp vectors, so that the garbage collector sees the elements while
+/// PREDICATE runs, as it does those of the vector `sort_vector' sorts in
+/// C.
+fn sort_items(items: &mut [LispObject], scratch: &mut [LispObject], predicate: LispObject) {
+    match KnownPredicate::recognize(predicate, items) {
+        // The elements can't be changed or collected while the main
+        // thread waits for the sort.
+        Some(known) if items.len() >= PARALLEL_SORT_THRESHOLD => {
+            items.par_sort_by(|&a, &b| known.compare(a, b))
+        }
+        Some(known) => stable_sort_with(items, scratch, |a, b| known.less(a, b)),
+        None => stable_sort_with(items, scratch, |a, b| call!(predicate, a, b).is_not_nil()),
+    }
+}
+
+/// Sort the list LIST by relinking its conses, and return its new
+/// first cons.
+fn sort_list(list: LispObject, predicate: LispObject) -> LispObject {
+    let tails: Vec<LispObject> = list
+        .iter_tails(LispConsEndChecks::on, LispConsCircularChecks::on)
+        .map(LispObject::from)
+        .collect();
+    // PREDICATE may unlink the conses from LIST, so they are kept in a
+    // Lisp vector while it runs.
+    let mut conses = lisp_vector(&tails).as_vector_or_error();
+    let mut scratch = lisp_vector(&tails).as_vector_or_error();
+    let conses = conses.as_mut_slice();
+    let cars: Vec<LispObject> = tails
+        .iter()
+        .map(|&cons| cons.as_cons_or_error().car())
+        .collect();
+    let car = |cons: LispObject| cons.as_cons_or_error().car();
+    match KnownPredicate::recognize(predicate, &cars) {
+        Some(known) => stable_sort_with(conses, scratch.as_mut_slice(), |a, b| {
+            known.less(car(a), car(b))
+        }),
+        None => stable_sort_with(conses, scratch.as_mut_slice(), |a, b| {
+            call!(predicate, car(a), car(b)).is_not_nil()
+        }),
+    }
+
+    // The conses are only relinked once PREDICATE can no longer signal.
+    let mut tail = Qnil;
+    for &cons in conses.iter().rev() {
+        cons.as_cons_or_error().set_cdr(tail);
+        tail = cons;
+    }
+    tail
+}
+
+/// Sort SEQ, stably, comparing elements using PREDICATE.
+/// Returns the sorted sequence.  SEQ should be a list or vector.  SEQ is
+/// modified by side effects.  PREDICATE is called with two elements of
+/// SEQ, and should return non-nil if the first element should sort before
+/// the second.
+///
+/// If PREDICATE is `<', `>' or `string<' and the elements of SEQ are all
+/// of the types it takes, the elements are compared without calling it,
+/// and a long vector is sorted on several threads.
+#[lisp_fn]
+pub fn sort(seq: LispObject, predicate: LispObject) -> LispObject {
+    if seq.is_cons() {
+        sort_list(seq, predicate)
+    } else if let Some(mut vector) = seq.as_vector() {
+        // Sort a copy, so that the vector is left alone if PREDICATE
+        // signals.
+        let mut items = lisp_vector(vector.as_slice()).as_vector_or_error();
+        let mut scratch = lisp_vector(vector.as_slice()).as_vector_or_error();
+        sort_items(items.as_mut_slice(), scratch.as_mut_slice(), predicate);
+        vector.as_mut_slice().copy_from_slice(items.as_slice());
+        seq
+    } else if seq.is_nil() {
+        seq
+    } else {
+        wrong_type!(Qsequencep, seq)
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn syms_of_sort() {
+    def_lisp_sym!(Qnatural, "natural");
+    def_lisp_sym!(Qnumeric, "numeric");
+    def_lisp_sym!(Qsort_fold_case, "sort-fold-case");
+    def_lisp_sym!(Qsort_numeric_base, "sort-numeric-base");
+    def_lisp_sym!(Qgtr, ">");
+    def_lisp_sym!(Qstring_less, "string<");
+}
+
+#[test]
+fn test_lines_and_fields() {
+    let text = "b 2\nä 1\n".as_bytes();
+    let found = lines(text, true);
+    assert_eq!(found.len(), 2);
+    assert_eq!((found[1].start, found[1].end), (4, 8));
+    assert_eq!((found[1].char_start, found[1].char_end), (4, 7));
+    assert_eq!(lines(b"a\n\nb", false).len(), 3);
+
+    assert_eq!(field_bounds(b"  ab\tcd  ef ", 2), Some((5, 7)));
+    assert_eq!(field_bounds(b"  ab\tcd  ef ", -1), Some((9, 11)));
+    assert_eq!(field_bounds(b"  ab\tcd  ef ", -3), Some((2, 4)));
+    assert_eq!(field_bounds(b"  ab  ", 2), None);
+    assert_eq!(field_bounds(b"  ab  ", -2), None);
+}
+
+#[test]
+fn test_compare_keys() {
+    let chars = |s: &'static str| s.chars().map(|c| c as Codepoint);
+    let same = |c| c;
+    assert_eq!(
+        compare_chars(chars("a10"), chars("a9"), false, same),
+        Ordering::Less
+    );
+    assert_eq!(
+        compare_chars(chars("a10"), chars("a9"), true, same),
+        Ordering::Greater
+    );
+    assert_eq!(
+        compare_chars(chars("a007b"), chars("a7b"), true, same),
+        Ordering::Equal
+    );
+    assert_eq!(
+        compare_chars(chars("ab"), chars("a"), true, same),
+        Ordering::Greater
+    );
+
+    assert_eq!(numeric_key(b" -12.5e1x", 10), -125.0);
+    assert_eq!(numeric_key(b"0x1F", 10), 31.0);
+    assert_eq!(numeric_key(b"017", 10), 15.0);
+    assert_eq!(numeric_key(b"ff", 16), 255.0);
+    assert_eq!(numeric_key(b"none", 10), 0.0);
+}
+
+#[test]
+fn test_stable_sort() {
+    let pairs = |v: &[(i32, i32)]| v.to_vec();
+    let mut items = pairs(&[(3, 0), (1, 1), (2, 2), (1, 3), (3, 4), (0, 5)]);
+    stable_sort(&mut items, |a, b| a.0 < b.0);
+    assert_eq!(
+        items,
+        pairs(&[(0, 5), (1, 1), (1, 3), (2, 2), (3, 0), (3, 4)])
+    );
+
+    // Long runs in both directions, and equal keys within each.
+    let mut items: Vec<(usize, usize)> = (0..1000).map(|i| ((i / 3) % 250, i)).collect();
+    items.extend((0..500).rev().map(|i| (i / 2, 1000 + i)));
+    let mut expected = items.clone();
+    expected.sort_by_key(|&(key, _)| key);
+    stable_sort(&mut items, |a, b| a.0 < b.0);
+    assert_eq!(items, expected);
+
+    // A single merge, which leaves the sorted items in the scratch space.
+    let mut items: Vec<(usize, usize)> = (0..64).map(|i| ((i * 7) % 10, i)).collect();
+    let mut expected = items.clone();
+    expected.sort_by_key(|&(key, _)| key);
+    stable_sort(&mut items, |a, b| a.0 < b.0);
+    assert_eq!(items, expected);
+
+    let mut empty: Vec<i32> = Vec::new();
+    stable_sort(&mut empty, |a, b| a < b);
+    assert!(empty.is_empty());
+}
+
+include!(concat!(env!("OUT_DIR"), "/sort_exports.rs"));
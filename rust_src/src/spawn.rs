@@ -0,0 +1,134 @@
+//! Starting subprocesses with posix_spawn.
+//!
+//! `create_process' starts a subprocess that talks to Emacs over pipes
+//! with `spawn_child', rather than with vfork and `child_setup', when
+//! `process-use-posix-spawn' is non-nil.  The C library implements
+//! posix_spawn with a clone that shares the memory of Emacs, like vfork,
+//! but without running any of Emacs in the child, so starting a
+//! compiler from Flymake costs the same however large Emacs has grown.
+//! The child is reaped by the SIGCHLD handler, as any other.
+//!
+//! posix_spawn can only start the child in the directory it should run
+//! in with posix_spawn_file_actions_addchdir_np, which glibc has since
+//! 2.29 and macOS since 10.15.  configure looks for it, and enables the
+//! `posix-spawn-chdir' feature if the C library has it.
+
+#[cfg(feature = "posix-spawn-chdir")]
+use std::mem;
+
+use libc::{c_char, c_int, pid_t, sigset_t};
+#[cfg(feature = "posix-spawn-chdir")]
+use libc::{posix_spawn_file_actions_t, posix_spawnattr_t};
+
+/// The signals Emacs handles or ignores that the child should get with
+/// their default actions.
+#[cfg(feature = "posix-spawn-chdir")]
+const DEFAULT_SIGNALS: [c_int; 4] = [libc::SIGINT, libc::SIGQUIT, libc::SIGPROF, libc::SIGPIPE];
+
+#[cfg(feature = "posix-spawn-chdir")]
+extern "C" {
+    /// Make the child change to the directory PATH.
+    fn posix_spawn_file_actions_addchdir_np(
+        actions: *mut posix_spawn_file_actions_t,
+        path: *const c_char,
+    ) -> c_int;
+}
+
+/// Return Err with the error number ERROR if it is one.
+#[cfg(feature = "posix-spawn-chdir")]
+fn check(error: c_int) -> Result<(), c_int> {
+    match error {
+        0 => Ok(()),
+        error => Err(error),
+    }
+}
+
+/// Set up ACTIONS and ATTR to start a child as `child_setup' would: in
+/// the directory DIR, with STDIN, STDOUT and STDERR as its standard
+/// descriptors, in a process group of its own, with the signal mask
+/// SIGMASK, and with the signals Emacs handles set to their default.
+#[cfg(feature = "posix-spawn-chdir")]
+unsafe fn setup_child(
+    actions: &mut posix_spawn_file_actions_t,
+    attr: &mut posix_spawnattr_t,
+    dir: *const c_char,
+    stdin: c_int,
+    stdout: c_int,
+    stderr: c_int,
+    sigmask: *const sigset_t,
+) -> Result<(), c_int> {
+    // The descriptors of Emacs are close-on-exec, and these three are
+    // the only ones the child needs.
+    check(libc::posix_spawn_file_actions_adddup2(
+        actions,
+        stdin,
+        libc::STDIN_FILENO,
+    ))?;
+    check(libc::posix_spawn_file_actions_adddup2(
+        actions,
+        stdout,
+        libc::STDOUT_FILENO,
+    ))?;
+    check(libc::posix_spawn_file_actions_adddup2(
+        actions,
+        stderr,
+        libc::STDERR_FILENO,
+    ))?;
+    check(posix_spawn_file_actions_addchdir_np(actions, dir))?;
+
+    let mut default_signals: sigset_t = mem::zeroed();
+    libc::sigemptyset(&mut default_signals);
+    for &signal in DEFAULT_SIGNALS.iter() {
+        libc::sigaddset(&mut default_signals, signal);
+    }
+    let flags =
+        libc::POSIX_SPAWN_SETPGROUP | libc::POSIX_SPAWN_SETSIGMASK | libc::POSIX_SPAWN_SETSIGDEF;
+    check(libc::posix_spawnattr_setflags(attr, flags as _))?;
+    check(libc::posix_spawnattr_setpgroup(attr, 0))?;
+    check(libc::posix_spawnattr_setsigmask(attr, sigmask))?;
+    check(libc::posix_spawnattr_setsigdefault(attr, &default_signals))
+}
+
+/// Start the program ARGV[0] with the arguments ARGV and the environment
+/// ENV, both null-terminated, in the directory DIR, with STDIN, STDOUT
+/// and STDERR as its standard descriptors, and the signal mask SIGMASK.
+/// Store its process ID in PID.  Return 0 if it started, ENOSYS if it
+/// has to be started with vfork instead, because the C library can't
+/// make it change directories, and the error number of why it couldn't
+/// otherwise.
+#[no_mangle]
+pub extern "C" fn spawn_child(
+    argv: *const *mut c_char,
+    env: *const *mut c_char,
+    dir: *const c_char,
+    stdin: c_int,
+    stdout: c_int,
+    stderr: c_int,
+    sigmask: *const sigset_t,
+    pid: *mut pid_t,
+) -> c_int {
+    #[cfg(feature = "posix-spawn-chdir")]
+    unsafe {
+        let mut actions: posix_spawn_file_actions_t = mem::zeroed();
+        let mut attr: posix_spawnattr_t = mem::zeroed();
+        if let Err(error) = check(libc::posix_spawn_file_actions_init(&mut actions)) {
+            return error;
+        }
+        if let Err(error) = check(libc::posix_spawnattr_init(&mut attr)) {
+            libc::posix_spawn_file_actions_destroy(&mut actions);
+            return error;
+        }
+
+        let result = setup_child(&mut actions, &mut attr, dir, stdin, stdout, stderr, sigmask)
+            .and_then(|()| check(libc::posix_spawn(pid, *argv, &actions, &attr, argv, env)));
+
+        libc::posix_spawnattr_destroy(&mut attr);
+        libc::posix_spawn_file_actions_destroy(&mut actions);
+        result.err().unwrap_or(0)
+    }
+    #[cfg(not(feature = "posix-spawn-chdir"))]
+    {
+        let _ = (argv, env, dir, stdin, stdout, stderr, sigmask, pid);
+        libc::ENOSYS
+    }
+}
@@ -0,0 +1,665 @@
+//! SSH connections.
+//!
+//! `make-ssh-connection' opens a connection to an SSH server with ssh2,
+//! which stays open for the commands run with `ssh-exec' and the file
+//! operations done over SFTP, each on a channel of its own.  These are
+//! the primitives for TRAMP to drive instead of starting ssh for every
+//! operation.  A libssh2 session must only be used by one thread at a
+//! time, so each connection has a thread of its own which does all its
+//! operations, and Emacs waits for each of them so that it can be
+//! interrupted.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use libc::{c_char, c_int};
+use ssh2::{CheckResult, ExitSignal, KnownHostFileKind, OpenType, Session, Sftp};
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::{plist_get, plist_member, LispConsCircularChecks, LispConsEndChecks},
+    multibyte::LispStringRef,
+    obarray::intern,
+    remacs_sys::{make_unibyte_string, maybe_quit, EmacsInt, Qnil},
+};
+
+/// The number of milliseconds between checks for quitting while waiting
+/// for an SSH operation.
+const SSH_POLL_MS: u64 = 50;
+
+/// The private keys tried when neither :identity nor an agent gives one
+/// that works, in ~/.ssh.
+const DEFAULT_IDENTITIES: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// The code of the SFTP errors which say that there is no such file,
+/// SSH_FX_NO_SUCH_FILE.
+const SFTP_NO_SUCH_FILE: c_int = 2;
+
+lazy_static! {
+    /// The open connections, by number.
+    static ref SSH_CONNECTIONS: Mutex<SshConnections> = Mutex::new(SshConnections {
+        next_id: 0,
+        connections: HashMap::new(),
+    });
+}
+
+struct SshConnections {
+    next_id: EmacsInt,
+    connections: HashMap<EmacsInt, Sender<Job>>,
+}
+
+/// What the thread of a connection has to work with.
+struct Worker {
+    session: Session,
+    stream: TcpStream,
+}
+
+/// An operation for the thread of a connection to do, with its session
+/// and its SFTP session, which is started by the first operation that
+/// needs it.
+type Job = Box<dyn for<'w> FnMut(&'w Worker, &mut Option<Sftp<'w>>) + Send>;
+
+fn unibyte_string(bytes: &[u8]) -> LispObject {
+    unsafe { make_unibyte_string(bytes.as_ptr() as *const c_char, bytes.len() as isize) }
+}
+
+/// Return what ERROR says went wrong.
+fn failure(error: ssh2::Error) -> String {
+    error.message().to_string()
+}
+
+/// Wait for the result of an operation from RECEIVER, quitting if the
+/// user asks to.  Signal an error with what it failed with, after
+/// DOING.
+fn wait_for<T>(doing: &str, receiver: &Receiver<Result<T, String>>) -> T {
+    let result = loop {
+        match receiver.recv_timeout(Duration::from_millis(SSH_POLL_MS)) {
+            Ok(result) => break result,
+            Err(RecvTimeoutError::Timeout) => unsafe { maybe_quit() },
+            Err(RecvTimeoutError::Disconnected) => {
+                break Err("SSH thread exited unexpectedly".to_string())
+            }
+        }
+    };
+    match result {
+        Ok(value) => value,
+        Err(message) => error!("{}: {}", doing, message),
+    }
+}
+
+fn is_connection(object: LispObject) -> bool {
+    object.as_cons().map_or(false, |cons| {
+        cons.car().eq(intern("ssh-connection").into())
+            && cons
+                .cdr()
+                .as_cons()
+                .map_or(false, |id| id.car().is_fixnum())
+    })
+}
+
+/// Return the number of the connection CONNECTION, as returned by
+/// `make-ssh-connection'.
+fn connection_id(connection: LispObject) -> EmacsInt {
+    let fields: Vec<LispObject> = if is_connection(connection) {
+        connection
+            .iter_cars(LispConsEndChecks::on, LispConsCircularChecks::safe)
+            .collect()
+    } else {
+        Vec::new()
+    };
+    if fields.len() != 5 {
+        wrong_type!(intern("ssh-connection-p").into(), connection);
+    }
+    fields[1].as_fixnum_or_error()
+}
+
+/// Return the queue of jobs of the open connection CONNECTION.
+fn connection_jobs(connection: LispObject) -> Sender<Job> {
+    let id = connection_id(connection);
+    SSH_CONNECTIONS
+        .lock()
+        .unwrap()
+        .connections
+        .get(&id)
+        .cloned()
+        .unwrap_or_else(|| error!("SSH connection is not open"))
+}
+
+/// Have the thread of a connection, with the queue of jobs JOBS, do
+/// OPERATION, and return its result.  Signal an error saying it failed
+/// after DOING.
+fn run<T, O>(jobs: &Sender<Job>, doing: &str, operation: O) -> T
+where
+    T: Send + 'static,
+    O: for<'w> FnOnce(&'w Worker, &mut Option<Sftp<'w>>) -> Result<T, String> + Send + 'static,
+{
+    let (sender, receiver) = channel();
+    let mut operation = Some(operation);
+    let job: Job = Box::new(move |worker, sftp| {
+        if let Some(operation) = operation.take() {
+            // Nobody may be listening anymore if the wait was quit.
+            let _ = sender.send(operation(worker, sftp));
+        }
+    });
+    if jobs.send(job).is_err() {
+        error!("SSH connection is not open");
+    }
+    wait_for(doing, &receiver)
+}
+
+/// Return true if the server of WORKER hasn't closed the connection.
+/// This only looks at the socket, without reading from it.  It is only
+/// called on the thread of the connection, so nothing else uses the
+/// socket while it is nonblocking.
+fn is_open(worker: &Worker) -> bool {
+    let mut byte = [0u8];
+    if worker.stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let peeked = worker.stream.peek(&mut byte);
+    let _ = worker.stream.set_nonblocking(false);
+    match peeked {
+        Ok(0) => false,
+        Ok(_) => true,
+        Err(ref error) => error.kind() == io::ErrorKind::WouldBlock,
+    }
+}
+
+/// The ways `make-ssh-connection' was told to log in.
+struct Login {
+    user: String,
+    password: Option<String>,
+    identities: Vec<String>,
+}
+
+/// Try logging in to SESSION with the keys of the SSH agent, if there
+/// is one.
+fn authenticate_with_agent(session: &Session, user: &str) -> bool {
+    let mut agent = match session.agent() {
+        Ok(agent) => agent,
+        Err(_) => return false,
+    };
+    if agent.connect().is_err() || agent.list_identities().is_err() {
+        return false;
+    }
+    let accepted = agent
+        .identities()
+        .filter_map(Result::ok)
+        .any(|key| agent.userauth(user, &key).is_ok());
+    let _ = agent.disconnect();
+    accepted
+}
+
+/// Try logging in to SESSION with the key pair in the file FILE.
+fn authenticate_with_file(session: &Session, user: &str, file: &str) -> bool {
+    session
+        .userauth_pubkey_file(user, None, Path::new(file), None)
+        .is_ok()
+}
+
+/// Log in to SESSION as LOGIN says: with the password if there is one,
+/// else with its identity files if there are any, else with the agent
+/// and then the usual identity files.
+fn authenticate(session: &Session, login: &Login) -> Result<(), String> {
+    let user = login.user.as_str();
+    if let Some(ref password) = login.password {
+        return session
+            .userauth_password(user, password)
+            .map_err(|_| "Password rejected".to_string());
+    }
+
+    if !login.identities.is_empty() {
+        for file in &login.identities {
+            if !Path::new(file).exists() {
+                return Err(format!("Can't load {}", file));
+            }
+            if authenticate_with_file(session, user, file) {
+                return Ok(());
+            }
+        }
+        return Err("Keys rejected".to_string());
+    }
+
+    if authenticate_with_agent(session, user) {
+        return Ok(());
+    }
+    if let Ok(home) = std::env::var("HOME") {
+        for name in DEFAULT_IDENTITIES.iter() {
+            let file = format!("{}/.ssh/{}", home, name);
+            if Path::new(&file).exists() && authenticate_with_file(session, user, &file) {
+                return Ok(());
+            }
+        }
+    }
+    Err("No key was accepted".to_string())
+}
+
+/// Check the key of the server of SESSION, HOST at PORT, against
+/// ~/.ssh/known_hosts.
+fn verify_host_key(session: &Session, host: &str, port: u16) -> Result<(), String> {
+    let key = match session.host_key() {
+        Some((key, _)) => key,
+        None => return Err("The server has no host key".to_string()),
+    };
+    let mut known_hosts = session.known_hosts().map_err(failure)?;
+    if let Ok(home) = std::env::var("HOME") {
+        let file = PathBuf::from(home).join(".ssh/known_hosts");
+        // Without the file, no key is known.
+        let _ = known_hosts.read_file(&file, KnownHostFileKind::OpenSSH);
+    }
+    match known_hosts.check_port(host, port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::Mismatch => Err(format!("The host key of {} has changed", host)),
+        _ => Err(format!("The host key of {} is unknown", host)),
+    }
+}
+
+/// Connect to HOST at PORT and log in as LOGIN says, checking the key
+/// of the server if VERIFY.
+fn connect(host: &str, port: u16, verify: bool, login: &Login) -> Result<Worker, String> {
+    let stream = TcpStream::connect((host, port)).map_err(|error| error.to_string())?;
+    let mut session = match Session::new() {
+        Some(session) => session,
+        None => return Err("Can't start an SSH session".to_string()),
+    };
+    session.handshake(&stream).map_err(failure)?;
+    if verify {
+        verify_host_key(&session, host, port)?;
+    }
+    authenticate(&session, login)?;
+    Ok(Worker { session, stream })
+}
+
+/// Do the JOBS of a connection with WORKER, until the connection is
+/// closed.
+fn work(worker: &Worker, jobs: &Receiver<Job>) {
+    let mut sftp = None;
+    for mut job in jobs.iter() {
+        job(worker, &mut sftp);
+    }
+}
+
+/// Open a connection to the SSH server HOST, and return it.
+/// The connection stays open until `ssh-connection-close', for any
+/// number of commands run with `ssh-exec' and file operations over SFTP
+/// to share it.  The connection is a list (ssh-connection ID USER HOST
+/// PORT).
+///
+/// The arguments OPTIONS are a list of keyword/argument pairs:
+///
+/// :port PORT -- The port of the server, 22 by default.
+///
+/// :user USER -- The user to log in as, `user-login-name' by default.
+///
+/// :password PASSWORD -- Log in with PASSWORD.
+///
+/// :identity FILES -- Log in with a private key from the file, or the
+/// list of files, FILES.
+///
+/// :verify VERIFY -- If nil, trust any key the server has.  Otherwise,
+/// the server must have the key ~/.ssh/known_hosts has for it, and the
+/// connection fails if it is unknown or different.
+///
+/// Without a password or identity files, the keys of the SSH agent are
+/// tried, then the private keys ~/.ssh/id_ed25519, ~/.ssh/id_ecdsa and
+/// ~/.ssh/id_rsa, which must not be encrypted.
+/// usage: (make-ssh-connection HOST &rest OPTIONS)
+#[lisp_fn(min = "1")]
+pub fn make_ssh_connection(args: &mut [LispObject]) -> LispObject {
+    let host = args[0].as_string_or_error().to_string();
+    let options = LispObject::from(args[1..].to_vec());
+    let option = |name| plist_get(options, intern(name).into());
+
+    let port = option(":port");
+    let port = if port.is_nil() {
+        22
+    } else {
+        port.as_fixnum_or_error() as u16
+    };
+    let user = option(":user");
+    let user = if user.is_nil() {
+        call!(LispObject::from(intern("user-login-name")))
+    } else {
+        user
+    };
+    let identity = option(":identity");
+    let identity = if identity.is_string() {
+        list!(identity)
+    } else {
+        identity
+    };
+    let identities = identity
+        .iter_cars(LispConsEndChecks::on, LispConsCircularChecks::safe)
+        .map(|file| call!(LispObject::from(intern("expand-file-name")), file))
+        .map(|file| file.as_string_or_error().to_string())
+        .collect();
+    let password = option(":password");
+    let login = Login {
+        user: user.as_string_or_error().to_string(),
+        password: if password.is_nil() {
+            None
+        } else {
+            Some(password.as_string_or_error().to_string())
+        },
+        identities,
+    };
+    let verify =
+        plist_member(options, intern(":verify").into()).is_none() || option(":verify").is_not_nil();
+
+    let (sender, receiver) = channel();
+    let (jobs, job_receiver) = channel();
+    let address = host.clone();
+    thread::Builder::new()
+        .name("ssh".to_string())
+        .spawn(move || match connect(&address, port, verify, &login) {
+            Ok(worker) => {
+                let _ = sender.send(Ok(()));
+                work(&worker, &job_receiver);
+            }
+            Err(message) => {
+                let _ = sender.send(Err(message));
+            }
+        })
+        .unwrap_or_else(|error| error!("Can't start SSH: {}", error));
+    wait_for("SSH connection failed", &receiver);
+
+    let mut connections = SSH_CONNECTIONS.lock().unwrap();
+    connections.next_id += 1;
+    let id = connections.next_id;
+    connections.connections.insert(id, jobs);
+    list!(
+        LispObject::from(intern("ssh-connection")),
+        LispObject::from(id),
+        user,
+        LispObject::from(host.as_str()),
+        LispObject::from(EmacsInt::from(port))
+    )
+}
+
+/// Return t if OBJECT is a connection returned by `make-ssh-connection'.
+#[lisp_fn]
+pub fn ssh_connection_p(object: LispObject) -> bool {
+    is_connection(object)
+}
+
+/// Return t if the SSH connection CONNECTION is open.
+#[lisp_fn]
+pub fn ssh_connection_live_p(connection: LispObject) -> bool {
+    let id = connection_id(connection);
+    let jobs = SSH_CONNECTIONS
+        .lock()
+        .unwrap()
+        .connections
+        .get(&id)
+        .cloned();
+    jobs.map_or(false, |jobs| {
+        run(&jobs, "Checking SSH connection failed", |worker, _| {
+            Ok(is_open(worker))
+        })
+    })
+}
+
+/// Close the SSH connection CONNECTION.
+#[lisp_fn]
+pub fn ssh_connection_close(connection: LispObject) {
+    let id = connection_id(connection);
+    let jobs = SSH_CONNECTIONS.lock().unwrap().connections.remove(&id);
+    // Once it has done this, the thread of the connection exits, as
+    // there are no more jobs for it.
+    if let Some(jobs) = jobs {
+        run(&jobs, "Closing SSH connection failed", |worker, sftp| {
+            // The SFTP session has to be shut down while the session is
+            // still connected.
+            sftp.take();
+            worker.session.disconnect(None, "", None).map_err(failure)
+        });
+    }
+}
+
+/// Run COMMAND on the server of the SSH connection CONNECTION.
+/// COMMAND is a string the shell of the user runs.  If INPUT is non-nil,
+/// the bytes of the string INPUT are its standard input.  Wait for it to
+/// finish, and return (STATUS STDOUT STDERR), where STATUS is its exit
+/// status, or nil if it was killed by a signal, and STDOUT and STDERR
+/// are what it wrote to its standard output and error, as unibyte
+/// strings.
+#[lisp_fn(min = "2")]
+pub fn ssh_exec(connection: LispObject, command: LispStringRef, input: LispObject) -> LispObject {
+    let command = command.to_string();
+    let input = if input.is_nil() {
+        None
+    } else {
+        Some(input.as_string_or_error().as_slice().to_vec())
+    };
+
+    let jobs = connection_jobs(connection);
+    let (status, stdout, stderr) = run(&jobs, "SSH command failed", move |worker, _| {
+        let io_failure = |error: io::Error| error.to_string();
+        let mut channel = worker.session.channel_session().map_err(failure)?;
+        channel.exec(&command).map_err(failure)?;
+        if let Some(input) = input {
+            channel.write_all(&input).map_err(io_failure)?;
+        }
+        channel.send_eof().map_err(failure)?;
+
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        channel.read_to_end(&mut stdout).map_err(io_failure)?;
+        channel
+            .stderr()
+            .read_to_end(&mut stderr)
+            .map_err(io_failure)?;
+        channel.wait_close().map_err(failure)?;
+        let status = match channel.exit_signal() {
+            Ok(ExitSignal {
+                exit_signal: Some(_),
+                ..
+            }) => None,
+            _ => Some(channel.exit_status().map_err(failure)?),
+        };
+        Ok((status, stdout, stderr))
+    });
+
+    list!(
+        status.map_or(Qnil, |status| LispObject::from(EmacsInt::from(status))),
+        unibyte_string(&stdout),
+        unibyte_string(&stderr)
+    )
+}
+
+/// Do OPERATION with the SFTP session of CONNECTION, starting it the
+/// first time, and return its result.  Signal an error saying it failed
+/// after DOING.
+fn with_sftp<T, O>(connection: LispObject, doing: &str, operation: O) -> T
+where
+    T: Send + 'static,
+    O: FnOnce(&Sftp) -> Result<T, String> + Send + 'static,
+{
+    run(&connection_jobs(connection), doing, move |worker, sftp| {
+        if sftp.is_none() {
+            *sftp = Some(worker.session.sftp().map_err(failure)?);
+        }
+        operation(sftp.as_ref().unwrap())
+    })
+}
+
+/// Return the contents of the file FILE on the server of CONNECTION, as
+/// a unibyte string.  This is read over SFTP, as are the files of the
+/// other ssh- functions.
+#[lisp_fn]
+pub fn ssh_file_contents(connection: LispObject, file: LispStringRef) -> LispObject {
+    let file = file.to_string();
+    let contents = with_sftp(connection, &format!("Reading {}", file), move |sftp| {
+        let mut contents = Vec::new();
+        sftp.open(Path::new(&file))
+            .map_err(failure)?
+            .read_to_end(&mut contents)
+            .map_err(|error| error.to_string())?;
+        Ok(contents)
+    });
+    unibyte_string(&contents)
+}
+
+/// Write the bytes of the string DATA to the file FILE on the server of
+/// CONNECTION, replacing what it had.
+#[lisp_fn]
+pub fn ssh_write_file(connection: LispObject, file: LispStringRef, data: LispStringRef) {
+    let file = file.to_string();
+    let data = data.as_slice().to_vec();
+    with_sftp(connection, &format!("Writing {}", file), move |sftp| {
+        sftp.open_mode(
+            Path::new(&file),
+            ssh2::WRITE | ssh2::CREATE | ssh2::TRUNCATE,
+            0o644,
+            OpenType::File,
+        )
+        .map_err(failure)?
+        .write_all(&data)
+        .map_err(|error| error.to_string())
+    });
+}
+
+/// Return the attributes of the file FILE on the server of CONNECTION.
+/// They are a plist (:type TYPE :size SIZE :modes MODES :uid UID :gid
+/// GID :mtime MTIME), where TYPE is `directory', `symlink' or `file',
+/// MODES are the permission bits as for `file-modes', and MTIME is in
+/// seconds since the epoch.  Attributes the server doesn't tell are
+/// nil.  A symbolic link is followed unless NOFOLLOW is non-nil.  Return
+/// nil if FILE doesn't exist.
+#[lisp_fn(min = "2")]
+pub fn ssh_file_attributes(
+    connection: LispObject,
+    file: LispStringRef,
+    nofollow: bool,
+) -> LispObject {
+    let file = file.to_string();
+    let doing = format!("Getting attributes of {}", file);
+    let stat = with_sftp(connection, &doing, move |sftp| {
+        let stat = if nofollow {
+            sftp.lstat(Path::new(&file))
+        } else {
+            sftp.stat(Path::new(&file))
+        };
+        match stat {
+            Ok(stat) => Ok(Some(stat)),
+            Err(ref error) if error.code() == SFTP_NO_SUCH_FILE => Ok(None),
+            Err(error) => Err(failure(error)),
+        }
+    });
+    let stat = match stat {
+        Some(stat) => stat,
+        None => return Qnil,
+    };
+
+    let file_type = stat.file_type();
+    let file_type = if file_type.is_dir() {
+        "directory"
+    } else if file_type.is_symlink() {
+        "symlink"
+    } else {
+        "file"
+    };
+    let int = |value: Option<u64>| value.map_or(Qnil, |value| LispObject::from(value as EmacsInt));
+    list!(
+        LispObject::from(intern(":type")),
+        LispObject::from(intern(file_type)),
+        LispObject::from(intern(":size")),
+        int(stat.size),
+        LispObject::from(intern(":modes")),
+        int(stat.perm.map(|modes| u64::from(modes & 0o7777))),
+        LispObject::from(intern(":uid")),
+        int(stat.uid.map(u64::from)),
+        LispObject::from(intern(":gid")),
+        int(stat.gid.map(u64::from)),
+        LispObject::from(intern(":mtime")),
+        int(stat.mtime)
+    )
+}
+
+/// Return the names of the files in the directory DIRECTORY on the
+/// server of CONNECTION, sorted, without "." and "..".
+#[lisp_fn]
+pub fn ssh_directory_files(connection: LispObject, directory: LispStringRef) -> LispObject {
+    let directory = directory.to_string();
+    let mut names = with_sftp(connection, &format!("Listing {}", directory), move |sftp| {
+        // The entries come without "." and "..".
+        let entries = sftp.readdir(Path::new(&directory)).map_err(failure)?;
+        Ok(entries
+            .iter()
+            .filter_map(|(path, _)| path.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect::<Vec<String>>())
+    });
+    names.sort();
+    names
+        .iter()
+        .map(|name| LispObject::from(name.as_str()))
+        .collect::<Vec<LispObject>>()
+        .into()
+}
+
+/// Delete the file FILE on the server of CONNECTION.
+#[lisp_fn]
+pub fn ssh_delete_file(connection: LispObject, file: LispStringRef) {
+    let file = file.to_string();
+    with_sftp(connection, &format!("Deleting {}", file), move |sftp| {
+        sftp.unlink(Path::new(&file)).map_err(failure)
+    });
+}
+
+/// Make the directory DIRECTORY on the server of CONNECTION.
+#[lisp_fn]
+pub fn ssh_make_directory(connection: LispObject, directory: LispStringRef) {
+    let directory = directory.to_string();
+    with_sftp(
+        connection,
+        &format!("Making directory {}", directory),
+        move |sftp| sftp.mkdir(Path::new(&directory), 0o777).map_err(failure),
+    );
+}
+
+/// Delete the empty directory DIRECTORY on the server of CONNECTION.
+#[lisp_fn]
+pub fn ssh_delete_directory(connection: LispObject, directory: LispStringRef) {
+    let directory = directory.to_string();
+    with_sftp(
+        connection,
+        &format!("Deleting directory {}", directory),
+        move |sftp| sftp.rmdir(Path::new(&directory)).map_err(failure),
+    );
+}
+
+/// Rename the file FILE on the server of CONNECTION to NEWNAME.
+#[lisp_fn]
+pub fn ssh_rename_file(connection: LispObject, file: LispStringRef, newname: LispStringRef) {
+    let file = file.to_string();
+    let newname = newname.to_string();
+    with_sftp(connection, &format!("Renaming {}", file), move |sftp| {
+        sftp.rename(Path::new(&file), Path::new(&newname), None)
+            .map_err(failure)
+    });
+}
+
+/// Return the absolute name of the file FILE on the server of
+/// CONNECTION, with symbolic links resolved.  FILE is relative to the
+/// home directory of the user there, so "." is that directory.
+#[lisp_fn]
+pub fn ssh_file_truename(connection: LispObject, file: LispStringRef) -> LispObject {
+    let file = file.to_string();
+    let name = with_sftp(connection, &format!("Resolving {}", file), move |sftp| {
+        sftp.realpath(Path::new(&file))
+            .map(|name| name.to_string_lossy().into_owned())
+            .map_err(failure)
+    });
+    LispObject::from(name.as_str())
+}
+
+include!(concat!(env!("OUT_DIR"), "/ssh_exports.rs"));
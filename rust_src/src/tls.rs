@@ -0,0 +1,531 @@
+//! TLS for network processes, implemented with rustls.
+//!
+//! `rustls-boot' negotiates TLS on the socket of a network process, and
+//! from then on process.c reads and writes the process through
+//! `rustls_read' and `rustls_write', which decrypt and encrypt what goes
+//! over the socket.  Unlike GnuTLS, this needs no library besides Emacs
+//! itself, so static builds have TLS too.
+//! `network-stream-tls-backend' chooses it for `open-network-stream'.
+//!
+//! Sockets are read and written with the system calls, so rustls is only
+//! available on Unix.  On MS-Windows, where Emacs has sockets of its own
+//! making, `rustls-boot' always fails.
+
+#[cfg(unix)]
+use std::cmp;
+use std::collections::HashMap;
+use std::fs;
+#[cfg(unix)]
+use std::io::Write;
+use std::io::{self, Read};
+#[cfg(unix)]
+use std::slice;
+use std::sync::{Arc, Mutex};
+
+#[cfg(unix)]
+use errno::{set_errno, Errno};
+#[cfg(unix)]
+use libc::c_void;
+use libc::{c_char, c_int};
+
+use rustls::internal::pemfile;
+use rustls::{Certificate, ClientConfig, ClientSession, PrivateKey, RootCertStore};
+use rustls::{ServerCertVerified, ServerCertVerifier, Session, TLSError};
+use webpki::DNSNameRef;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    fileio::encoded_file_path,
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::{plist_get, plist_member, LispConsCircularChecks, LispConsEndChecks},
+    multibyte::LispStringRef,
+    obarray::intern,
+    remacs_sys::{encode_file_name, maybe_quit, Fexpand_file_name},
+    remacs_sys::{Qnetwork, Qnil},
+};
+
+/// How long to wait for the socket at a time, in milliseconds, while
+/// negotiating, before checking for quits.
+#[cfg(unix)]
+const HANDSHAKE_POLL_MS: c_int = 100;
+
+lazy_static! {
+    /// The TLS connections of network processes, by their sockets.
+    static ref TLS_STREAMS: Mutex<HashMap<c_int, TlsStream>> = Mutex::new(HashMap::new());
+}
+
+/// A TLS connection over a socket, with the plaintext it has decrypted
+/// which hasn't been read yet, and whether the server has closed it.
+struct TlsStream {
+    session: ClientSession,
+    plaintext: Vec<u8>,
+    closed: bool,
+}
+
+impl TlsStream {
+    /// Move the plaintext the session has decrypted to the stream, where
+    /// `rustls_pending_p' can see it.
+    fn take_plaintext(&mut self) {
+        let mut chunk = [0; 4096];
+        loop {
+            match self.session.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => self.plaintext.extend_from_slice(&chunk[..n]),
+                // rustls tells of the close_notify alert this way.
+                Err(_) => {
+                    self.closed = true;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// A socket, read and written with the system calls, so that it stays
+/// nonblocking if it is.
+#[cfg(unix)]
+struct Socket(c_int);
+
+#[cfg(unix)]
+impl Read for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match unsafe { libc::read(self.0, buf.as_mut_ptr() as *mut c_void, buf.len()) } {
+            -1 => Err(io::Error::last_os_error()),
+            n => Ok(n as usize),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Write for Socket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match unsafe { libc::write(self.0, buf.as_ptr() as *const c_void, buf.len()) } {
+            -1 => Err(io::Error::last_os_error()),
+            n => Ok(n as usize),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Wait up to TIMEOUT milliseconds for SOCKET to be ready for EVENTS.
+#[cfg(unix)]
+fn poll_socket(socket: c_int, events: libc::c_short, timeout: c_int) {
+    let mut pollfd = libc::pollfd {
+        fd: socket,
+        events,
+        revents: 0,
+    };
+    unsafe { libc::poll(&mut pollfd, 1, timeout) };
+}
+
+fn tls_error(error: TLSError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn pem_error(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid {}", what))
+}
+
+/// A verifier which accepts any certificate, for `:verify nil'.
+struct NoVerification;
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _roots: &RootCertStore,
+        _presented_certs: &[Certificate],
+        _dns_name: DNSNameRef,
+        _ocsp_response: &[u8],
+    ) -> Result<ServerCertVerified, TLSError> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Return the contents of FILENAME, a file of PEM certificates or keys.
+fn read_pem_file(filename: LispObject) -> io::Result<Vec<u8>> {
+    let absname = unsafe { Fexpand_file_name(filename, Qnil) };
+    let encoded = unsafe { encode_file_name(absname) };
+    fs::read(encoded_file_path(encoded))
+}
+
+fn read_certificates(filename: LispObject) -> io::Result<Vec<Certificate>> {
+    let pem = read_pem_file(filename)?;
+    pemfile::certs(&mut pem.as_slice()).map_err(|()| pem_error("certificate"))
+}
+
+/// Return the first private key in FILENAME, in PKCS #8 or PKCS #1
+/// format.
+fn read_private_key(filename: LispObject) -> io::Result<PrivateKey> {
+    let pem = read_pem_file(filename)?;
+    let mut keys =
+        pemfile::pkcs8_private_keys(&mut pem.as_slice()).map_err(|()| pem_error("private key"))?;
+    if keys.is_empty() {
+        keys = pemfile::rsa_private_keys(&mut pem.as_slice())
+            .map_err(|()| pem_error("private key"))?;
+    }
+    match keys.into_iter().next() {
+        Some(key) => Ok(key),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "no private key found",
+        )),
+    }
+}
+
+/// Return the certificate authorities in the files TRUSTFILES, or the
+/// ones Mozilla trusts if TRUSTFILES is nil.
+fn root_certificates(trustfiles: LispObject) -> io::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    if trustfiles.is_nil() {
+        roots.add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+        return Ok(roots);
+    }
+    for file in trustfiles.iter_cars(LispConsEndChecks::on, LispConsCircularChecks::safe) {
+        for certificate in read_certificates(file)? {
+            roots
+                .add(&certificate)
+                .map_err(|error| tls_error(TLSError::WebPKIError(error)))?;
+        }
+    }
+    Ok(roots)
+}
+
+/// Return the configuration of TLS connections which PARAMS asks for.
+fn client_config(params: LispObject) -> io::Result<ClientConfig> {
+    let param = |name| plist_get(params, intern(name).into());
+    let mut config = ClientConfig::new();
+    config.root_store = root_certificates(param(":trustfiles"))?;
+
+    // Only the first key and certificate are used, as rustls can't
+    // choose between them.
+    if let Some(pair) = param(":keylist").as_cons() {
+        let (key, rest) = pair.car().as_cons_or_error().as_tuple();
+        let certificates = read_certificates(rest.as_cons_or_error().car())?;
+        config.set_single_client_cert(certificates, read_private_key(key)?);
+    }
+
+    config.alpn_protocols = param(":alpn-protocols")
+        .iter_cars(LispConsEndChecks::on, LispConsCircularChecks::safe)
+        .map(|protocol| protocol.as_string_or_error().to_string())
+        .collect();
+
+    // Certificates are verified unless :verify is there and nil.
+    let verify =
+        plist_member(params, intern(":verify").into()).is_none() || param(":verify").is_not_nil();
+    if !verify {
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(NoVerification));
+    }
+    Ok(config)
+}
+
+/// Carry on the handshake of SESSION over SOCKET until it is done,
+/// waiting for the socket as needed.
+#[cfg(unix)]
+fn handshake(session: &mut ClientSession, socket: c_int) -> io::Result<()> {
+    let mut io = Socket(socket);
+    while session.is_handshaking() || session.wants_write() {
+        let result = if session.wants_write() {
+            session.write_tls(&mut io).map(|_| ())
+        } else {
+            match session.read_tls(&mut io) {
+                Ok(0) => Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(_) => session.process_new_packets().map_err(|error| {
+                    // Let the server know why, if it can still hear.
+                    let _ = session.write_tls(&mut io);
+                    tls_error(error)
+                }),
+                Err(error) => Err(error),
+            }
+        };
+        match result {
+            Ok(()) => (),
+            Err(ref error) if error.kind() == io::ErrorKind::WouldBlock => {
+                let events = if session.wants_write() {
+                    libc::POLLOUT
+                } else {
+                    libc::POLLIN
+                };
+                poll_socket(socket, events, HANDSHAKE_POLL_MS);
+                unsafe { maybe_quit() };
+            }
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn handshake(_session: &mut ClientSession, _socket: c_int) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "rustls is not available on MS-Windows",
+    ))
+}
+
+/// Negotiate TLS on the network process PROCESS, connected to HOSTNAME.
+/// Once this returns, what is sent to PROCESS is encrypted, and what is
+/// received from it decrypted, with rustls.  HOSTNAME is the name the
+/// server's certificate must be for, and which is sent to it with SNI.
+///
+/// PARAMS is a property list of these properties:
+///
+/// :verify, if nil, accepts any certificate the server has.  By default
+/// the certificate is verified against the trusted authorities.
+///
+/// :trustfiles is a list of files of PEM certificates of the trusted
+/// authorities.  By default the authorities Mozilla trusts are.
+///
+/// :keylist is a list of (KEY-FILE CERT-FILE) pairs of PEM files of the
+/// private key and certificates to identify with, as for
+/// `gnutls-boot'.  Only the first pair is used.
+///
+/// :alpn-protocols is a list of the names of the protocols to offer the
+/// server with ALPN, like \"h2\".
+///
+/// Return t.  Signal an error if the handshake fails, leaving PROCESS
+/// as it is, for the caller to delete.
+#[lisp_fn(min = "2")]
+pub fn rustls_boot(process: LispObject, hostname: LispStringRef, params: LispObject) -> bool {
+    let proc_ref = process.as_process_or_error();
+    if !proc_ref.type_.eq(Qnetwork) || proc_ref.infd < 0 {
+        error!(
+            "Process {} is not an open network connection",
+            proc_ref.name.as_string_or_error()
+        );
+    }
+    let socket = proc_ref.infd;
+    if TLS_STREAMS.lock().unwrap().contains_key(&socket) {
+        error!(
+            "Process {} already has TLS",
+            proc_ref.name.as_string_or_error()
+        );
+    }
+
+    let hostname = hostname.to_string();
+    let server_name = match DNSNameRef::try_from_ascii_str(&hostname) {
+        Ok(server_name) => server_name,
+        Err(()) => error!("Invalid TLS host name: {}", hostname),
+    };
+    let config = match client_config(params) {
+        Ok(config) => config,
+        Err(error) => error!("Invalid TLS parameters: {}", error),
+    };
+    let mut session = ClientSession::new(&Arc::new(config), server_name);
+    if let Err(error) = handshake(&mut session, socket) {
+        error!("TLS handshake with {} failed: {}", hostname, error);
+    }
+
+    // The server may have sent data along with the end of the handshake.
+    let mut stream = TlsStream {
+        session,
+        plaintext: Vec::new(),
+        closed: false,
+    };
+    stream.take_plaintext();
+    TLS_STREAMS.lock().unwrap().insert(socket, stream);
+    true
+}
+
+/// Return a description of the TLS connection of PROCESS.
+/// The value is a property list with the properties :protocol, the
+/// version of TLS, like \"TLSv1_3\", :cipher, the cipher suite, and
+/// :alpn, the protocol the server chose with ALPN, or nil.  Return nil
+/// if PROCESS has no connection made with `rustls-boot'.
+#[lisp_fn]
+pub fn rustls_peer_status(process: LispObject) -> LispObject {
+    let socket = process.as_process_or_error().infd;
+    // Lisp objects are only made once the connection is unlocked.
+    let (protocol, cipher, alpn) = match TLS_STREAMS.lock().unwrap().get(&socket) {
+        Some(stream) => (
+            stream
+                .session
+                .get_protocol_version()
+                .map(|version| format!("{:?}", version)),
+            stream
+                .session
+                .get_negotiated_ciphersuite()
+                .map(|suite| format!("{:?}", suite.suite)),
+            stream.session.get_alpn_protocol().map(str::to_owned),
+        ),
+        None => return Qnil,
+    };
+    let string = |name: Option<String>| name.map_or(Qnil, |name| LispObject::from(name.as_str()));
+    let protocol = string(protocol);
+    let cipher = string(cipher);
+    let alpn = string(alpn);
+    list!(
+        LispObject::from(intern(":protocol")),
+        protocol,
+        LispObject::from(intern(":cipher")),
+        cipher,
+        LispObject::from(intern(":alpn")),
+        alpn
+    )
+}
+
+/// Return true if the socket SOCKET has a connection made by
+/// `rustls-boot'.
+#[no_mangle]
+pub extern "C" fn rustls_stream_p(socket: c_int) -> bool {
+    TLS_STREAMS.lock().unwrap().contains_key(&socket)
+}
+
+/// Return true if the connection on SOCKET has decrypted data which
+/// hasn't been read.  select can't tell that there is.
+#[no_mangle]
+pub extern "C" fn rustls_pending_p(socket: c_int) -> bool {
+    TLS_STREAMS
+        .lock()
+        .unwrap()
+        .get(&socket)
+        .map_or(false, |stream| !stream.plaintext.is_empty())
+}
+
+#[cfg(unix)]
+fn set_io_errno(error: &io::Error) {
+    set_errno(Errno(error.raw_os_error().unwrap_or(libc::EPROTO)));
+}
+
+/// Read and decrypt up to NBYTE bytes from SOCKET into BUF, as
+/// emacs_read does.  Return the number of bytes read, 0 at the end of
+/// the stream, or -1 with errno set, to EAGAIN if nothing can be read
+/// yet.  No socket has such a connection on MS-Windows, so this is never
+/// called there.
+#[no_mangle]
+pub extern "C" fn rustls_read(socket: c_int, buf: *mut c_char, nbyte: isize) -> isize {
+    #[cfg(unix)]
+    {
+        read_stream(socket, buf, nbyte)
+    }
+    #[cfg(windows)]
+    {
+        let _ = (socket, buf, nbyte);
+        -1
+    }
+}
+
+#[cfg(unix)]
+fn read_stream(socket: c_int, buf: *mut c_char, nbyte: isize) -> isize {
+    let mut streams = TLS_STREAMS.lock().unwrap();
+    let stream = match streams.get_mut(&socket) {
+        Some(stream) => stream,
+        None => {
+            set_errno(Errno(libc::EBADF));
+            return -1;
+        }
+    };
+    let buf = unsafe { slice::from_raw_parts_mut(buf as *mut u8, nbyte as usize) };
+    let mut io = Socket(socket);
+    loop {
+        if !stream.plaintext.is_empty() {
+            let n = cmp::min(buf.len(), stream.plaintext.len());
+            buf[..n].copy_from_slice(&stream.plaintext[..n]);
+            stream.plaintext.drain(..n);
+            return n as isize;
+        }
+        if stream.closed {
+            return 0;
+        }
+        match stream.session.read_tls(&mut io) {
+            Ok(0) => return 0,
+            Ok(_) => match stream.session.process_new_packets() {
+                Ok(()) => stream.take_plaintext(),
+                Err(_) => {
+                    let _ = stream.session.write_tls(&mut io);
+                    set_errno(Errno(libc::EPROTO));
+                    return -1;
+                }
+            },
+            Err(error) => {
+                set_io_errno(&error);
+                return -1;
+            }
+        }
+        // Answer what the server asked for after the handshake, like a
+        // new key.
+        while stream.session.wants_write() {
+            if stream.session.write_tls(&mut io).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Encrypt the NBYTE bytes at BUF and send them over SOCKET, as
+/// emacs_write_sig does.  Return the number of bytes sent, or 0 with
+/// errno set.  This is never called on MS-Windows, like `rustls_read'.
+#[no_mangle]
+pub extern "C" fn rustls_write(socket: c_int, buf: *const c_char, nbyte: isize) -> isize {
+    #[cfg(unix)]
+    {
+        write_stream(socket, buf, nbyte)
+    }
+    #[cfg(windows)]
+    {
+        let _ = (socket, buf, nbyte);
+        0
+    }
+}
+
+#[cfg(unix)]
+fn write_stream(socket: c_int, buf: *const c_char, nbyte: isize) -> isize {
+    let mut streams = TLS_STREAMS.lock().unwrap();
+    let stream = match streams.get_mut(&socket) {
+        Some(stream) => stream,
+        None => {
+            set_errno(Errno(libc::EBADF));
+            return 0;
+        }
+    };
+    let buf = unsafe { slice::from_raw_parts(buf as *const u8, nbyte as usize) };
+    let written = match stream.session.write(buf) {
+        Ok(written) => written,
+        Err(error) => {
+            set_io_errno(&error);
+            return 0;
+        }
+    };
+
+    // The encrypted records are sent now, since nothing else would send
+    // them.  A signal may interrupt this, but quits are not handled with
+    // the connection locked.
+    let mut io = Socket(socket);
+    while stream.session.wants_write() {
+        match stream.session.write_tls(&mut io) {
+            Ok(_) => (),
+            Err(ref error)
+                if error.kind() == io::ErrorKind::WouldBlock
+                    || error.kind() == io::ErrorKind::Interrupted =>
+            {
+                poll_socket(socket, libc::POLLOUT, -1)
+            }
+            Err(error) => {
+                set_io_errno(&error);
+                return 0;
+            }
+        }
+    }
+    written as isize
+}
+
+/// Close the TLS connection on SOCKET, if it has one, as the socket is
+/// about to be closed.
+#[no_mangle]
+pub extern "C" fn rustls_close(socket: c_int) {
+    if let Some(mut stream) = TLS_STREAMS.lock().unwrap().remove(&socket) {
+        stream.session.send_close_notify();
+        #[cfg(unix)]
+        {
+            let _ = stream.session.write_tls(&mut Socket(socket));
+        }
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/tls_exports.rs"));
@@ -0,0 +1,206 @@
+//! TOML parsing.
+//!
+//! Tables are represented like JSON objects, so the `:object-type'
+//! and `:false-object' keyword arguments are those of the JSON
+//! functions.  TOML has no null value.
+
+use ::toml::Value;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    eval::define_error,
+    json::{json_encode, json_make_string, make_lisp_object, ObjectType},
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::list,
+    numbers::{MOST_NEGATIVE_FIXNUM, MOST_POSITIVE_FIXNUM},
+    remacs_sys::{buf_bytepos_to_charpos, wrong_choice, EmacsInt, Fmake_vector},
+    remacs_sys::{
+        QCdatetime_function, QCfalse, QCfalse_object, QCobject_type, Qerror, Qnil, Qplistp,
+        Qstring_without_embedded_nulls_p, Qt, Qtoml_error, Qtoml_parse_error,
+    },
+    threads::ThreadState,
+};
+
+/// The settings given by the keyword arguments of the TOML functions.
+struct TomlConfig {
+    object_type: ObjectType,
+    false_object: LispObject,
+    datetime_function: LispObject,
+}
+
+impl TomlConfig {
+    /// Parse the keyword arguments in ARGS.
+    fn from_args(args: &[LispObject]) -> Self {
+        let mut config = TomlConfig {
+            object_type: ObjectType::HashTable,
+            false_object: QCfalse,
+            datetime_function: Qnil,
+        };
+
+        if args.len() % 2 != 0 {
+            wrong_type!(Qplistp, list(args));
+        }
+
+        for pair in args.chunks(2) {
+            let (key, value) = (pair[0], pair[1]);
+            if key == QCobject_type {
+                config.object_type = ObjectType::from_lisp(value);
+            } else if key == QCfalse_object {
+                config.false_object = value;
+            } else if key == QCdatetime_function {
+                config.datetime_function = value;
+            } else {
+                let choices = list!(QCobject_type, QCfalse_object, QCdatetime_function);
+                unsafe { wrong_choice(choices, key) };
+            }
+        }
+        config
+    }
+}
+
+/// Convert the TOML value TOML to a Lisp object.
+fn toml_to_lisp(toml: Value, config: &TomlConfig) -> LispObject {
+    match toml {
+        Value::String(s) => json_make_string(&s),
+        Value::Integer(i) => {
+            // Integers which don't fit in a fixnum become floats, as
+            // for JSON.
+            if MOST_NEGATIVE_FIXNUM as i64 <= i && i <= MOST_POSITIVE_FIXNUM as i64 {
+                LispObject::from(i as EmacsInt)
+            } else {
+                LispObject::from_float(i as f64)
+            }
+        }
+        Value::Float(f) => LispObject::from_float(f),
+        Value::Boolean(false) => config.false_object,
+        Value::Boolean(true) => Qt,
+        Value::Datetime(datetime) => {
+            let string = json_make_string(&datetime.to_string());
+            if config.datetime_function.is_nil() {
+                string
+            } else {
+                call!(config.datetime_function, string)
+            }
+        }
+        Value::Array(elements) => {
+            let result = unsafe { Fmake_vector(LispObject::from(elements.len()), Qnil) };
+            let mut vector = result.as_vector_or_error();
+            for (i, element) in elements.into_iter().enumerate() {
+                vector.set(i, toml_to_lisp(element, config));
+            }
+            result
+        }
+        Value::Table(members) => {
+            let members = members
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_lisp(value, config)))
+                .collect();
+            make_lisp_object(members, config.object_type)
+        }
+    }
+}
+
+/// Parse the TOML document TEXT, signaling `toml-parse-error' if it
+/// isn't valid.
+fn toml_parse(text: &str) -> Value {
+    match text.parse::<Value>() {
+        Ok(value) => value,
+        Err(error) => {
+            // The parser counts lines and columns from zero.
+            let (line, column) = error
+                .line_col()
+                .map_or((0, 0), |(line, column)| (line + 1, column + 1));
+            xsignal!(
+                Qtoml_parse_error,
+                json_make_string(&error.to_string()),
+                LispObject::from(line),
+                LispObject::from(column)
+            );
+        }
+    }
+}
+
+/// Parse the TOML STRING into a Lisp object.
+/// STRING must contain a complete TOML document, whose top-level table
+/// is returned.  Tables, including arrays of tables, are represented as
+/// hashtables, alists or plists, and arrays as vectors.  Integers which
+/// don't fit in a fixnum are returned as floats.  Offset and local
+/// datetimes, dates and times are returned as strings in RFC 3339 form,
+/// unless the keyword argument `:datetime-function' is given.  If
+/// STRING isn't a valid TOML document, an error of type
+/// `toml-parse-error' is signaled, whose data are the message, line and
+/// column reported by the parser.  The arguments ARGS are a list of
+/// keyword/argument pairs:
+///
+/// The keyword argument `:object-type' specifies which Lisp type is used
+/// to represent tables; it can be `hash-table', `alist' or `plist'.
+///
+/// The keyword argument `:false-object' specifies which object to use to
+/// represent a TOML false value.  It defaults to `:false'.
+///
+/// The keyword argument `:datetime-function' specifies a function which
+/// is called with the RFC 3339 string of each datetime, and whose value
+/// represents it; `iso8601-parse' is a suitable choice.
+/// usage: (toml-parse-string STRING &rest ARGS)
+#[lisp_fn(min = "1")]
+pub fn toml_parse_string(args: &mut [LispObject]) -> LispObject {
+    let string = json_encode(args[0].as_string_or_error());
+    if string.contains('\0') {
+        wrong_type!(Qstring_without_embedded_nulls_p, args[0]);
+    }
+    let config = TomlConfig::from_args(&args[1..]);
+    toml_to_lisp(toml_parse(&string), &config)
+}
+
+/// Read a TOML document from the current buffer, from point to the end
+/// of the accessible portion.
+/// Move point to the end of the accessible portion if parsing was
+/// successful.  On error, don't move point.
+///
+/// The result is the same as that of `toml-parse-string', which see,
+/// and the arguments ARGS are a list of keyword/argument pairs, as for
+/// that function.
+/// usage: (toml-parse-buffer &rest ARGS)
+#[lisp_fn]
+pub fn toml_parse_buffer(args: &mut [LispObject]) -> LispObject {
+    let config = TomlConfig::from_args(args);
+
+    let mut buffer = ThreadState::current_buffer();
+    let (point, end) = (buffer.pt_byte, buffer.zv_byte);
+    let (before_gap, after_gap) = buffer.region_slices(point, end);
+    let mut text = Vec::with_capacity((end - point) as usize);
+    text.extend_from_slice(before_gap);
+    text.extend_from_slice(after_gap);
+    let text = match String::from_utf8(text) {
+        Ok(text) => text,
+        Err(_) => xsignal!(
+            Qtoml_parse_error,
+            json_make_string("buffer text isn't valid UTF-8")
+        ),
+    };
+
+    // Convert and then move point only if everything succeeded.
+    let lisp = toml_to_lisp(toml_parse(&text), &config);
+
+    let end_char = unsafe { buf_bytepos_to_charpos(buffer.as_mut(), end) };
+    buffer.set_pt_both(end_char, end);
+    lisp
+}
+
+#[no_mangle]
+pub extern "C" fn syms_of_toml() {
+    def_lisp_sym!(QCdatetime_function, ":datetime-function");
+
+    def_lisp_sym!(Qtoml_error, "toml-error");
+    def_lisp_sym!(Qtoml_parse_error, "toml-parse-error");
+    define_error(Qtoml_error, "generic TOML error", Qerror);
+    define_error(
+        Qtoml_parse_error,
+        "could not parse TOML document",
+        Qtoml_error,
+    );
+}
+
+include!(concat!(env!("OUT_DIR"), "/toml_exports.rs"));
@@ -0,0 +1,577 @@
+//! Moving files to the trash, and getting them back.
+//!
+//! On MS-Windows the trash is the Recycle Bin, which w32fns.c moves
+//! files to.  Elsewhere it is the trash of the freedesktop.org trash
+//! specification, as used by the GNOME, KDE and XFCE desktops: files on
+//! the volume of the home directory go to the home trash, and files on
+//! other volumes to the trash directory at the top of their volume.
+//! The trash can only be listed, and files restored from it, on systems
+//! using the freedesktop.org trash.
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    lisp::defsubr,
+    lisp::LispObject,
+    multibyte::LispStringRef,
+    remacs_sys::{Fdirectory_file_name, Fexpand_file_name, Ffile_directory_p, Ffile_symlink_p},
+    remacs_sys::{Ffind_file_name_handler, Qdelete_directory, Qdelete_file, Qnil},
+};
+
+#[cfg(unix)]
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    fs::{self, DirBuilder, OpenOptions},
+    io::{self, Write},
+    mem,
+    os::unix::ffi::{OsStrExt, OsStringExt},
+    os::unix::fs::{DirBuilderExt, MetadataExt},
+    path::{Path, PathBuf},
+};
+
+#[cfg(unix)]
+use libc::{c_char, c_int};
+
+#[cfg(unix)]
+use crate::{
+    remacs_sys::{decode_file_name, encode_file_name, make_unibyte_string, report_file_errno},
+    remacs_sys::{EmacsInt, Qfile_already_exists, Qfile_error, Qfile_missing},
+    strings::string_equal,
+};
+
+#[cfg(windows)]
+extern "C" {
+    /// Move FILENAME, an absolute file name, to the Recycle Bin.
+    fn w32_move_file_to_trash(filename: LispObject);
+}
+
+/// Return the name of the file FILENAME, an absolute file name, as the
+/// system sees it.
+#[cfg(unix)]
+fn system_path(filename: LispObject) -> PathBuf {
+    let encoded = unsafe { encode_file_name(filename) };
+    PathBuf::from(OsStr::from_bytes(encoded.as_string_or_error().as_slice()))
+}
+
+/// Return the Lisp file name of PATH, a file name from the system.
+#[cfg(unix)]
+fn lisp_file_name(path: &Path) -> LispObject {
+    let name = path.as_os_str().as_bytes();
+    unsafe {
+        decode_file_name(make_unibyte_string(
+            name.as_ptr() as *const c_char,
+            name.len() as isize,
+        ))
+    }
+}
+
+/// Signal a file error for FILENAME, saying what went wrong while doing
+/// what MESSAGE says, a null-terminated string.
+#[cfg(unix)]
+fn trash_error(message: &str, error: &io::Error, filename: LispObject) -> ! {
+    match error.raw_os_error() {
+        Some(errno) => unsafe {
+            report_file_errno(message.as_ptr() as *const c_char, filename, errno as c_int)
+        },
+        None => xsignal!(
+            Qfile_error,
+            LispObject::from(message.trim_end_matches('\0')),
+            LispObject::from(error.to_string().as_str()),
+            filename
+        ),
+    }
+}
+
+/// A trash directory, with the `files' subdirectory holding the trashed
+/// files and `info' their original names and deletion times.
+#[cfg(unix)]
+#[derive(Clone)]
+struct TrashDir {
+    path: PathBuf,
+    /// The top directory of the volume for the trash directory of a
+    /// volume, which the original names of its files are relative to.
+    topdir: Option<PathBuf>,
+}
+
+/// A file in the trash.
+#[cfg(unix)]
+struct TrashItem {
+    /// The name of the file in the `files' directory, which is that of
+    /// its information file without the `.trashinfo' extension.
+    name: OsString,
+    /// The absolute name of the file before it was trashed.
+    original_path: PathBuf,
+    /// The time the file was trashed, in seconds since the epoch.
+    time_deleted: i64,
+}
+
+#[cfg(unix)]
+impl TrashDir {
+    /// Return the home trash, in `$XDG_DATA_HOME/Trash'.
+    fn home() -> Self {
+        let data_home = match env::var_os("XDG_DATA_HOME") {
+            Some(ref dir) if Path::new(dir).is_absolute() => PathBuf::from(dir),
+            _ => {
+                let home = env::var_os("HOME").unwrap_or_default();
+                Path::new(&home).join(".local/share")
+            }
+        };
+        TrashDir {
+            path: data_home.join("Trash"),
+            topdir: None,
+        }
+    }
+
+    /// Return the trash directory of the volume whose top directory is
+    /// TOPDIR: `.Trash/$uid' if the administrator made a `.Trash'
+    /// directory with the sticky bit set there, or else `.Trash-$uid'.
+    fn of_volume(topdir: &Path) -> Self {
+        let uid = unsafe { libc::getuid() };
+        let shared = topdir.join(".Trash");
+        let path = match fs::symlink_metadata(&shared) {
+            Ok(ref metadata) if metadata.is_dir() && metadata.mode() & 0o1000 != 0 => {
+                shared.join(uid.to_string())
+            }
+            _ => topdir.join(format!(".Trash-{}", uid)),
+        };
+        TrashDir {
+            path,
+            topdir: Some(topdir.to_path_buf()),
+        }
+    }
+
+    /// Return the trash directory which PATH, an absolute file name,
+    /// goes to.
+    fn for_path(path: &Path) -> io::Result<Self> {
+        let home = Self::home();
+        let device = fs::symlink_metadata(path)?.dev();
+        if device == existing_ancestor_device(&home.path)? {
+            return Ok(home);
+        }
+        let mut topdir = path;
+        while let Some(parent) = topdir.parent() {
+            if fs::metadata(parent)?.dev() != device {
+                break;
+            }
+            topdir = parent;
+        }
+        Ok(Self::of_volume(topdir))
+    }
+
+    /// Return the trash directories whose files can be listed: the home
+    /// trash, and those of the mounted volumes which have one.
+    fn all() -> Vec<Self> {
+        let mut dirs = vec![Self::home()];
+        for topdir in mount_points() {
+            let dir = Self::of_volume(&topdir);
+            if dir.path.is_dir() && !dirs.iter().any(|d| d.path == dir.path) {
+                dirs.push(dir);
+            }
+        }
+        dirs
+    }
+
+    fn files(&self) -> PathBuf {
+        self.path.join("files")
+    }
+
+    fn info(&self) -> PathBuf {
+        self.path.join("info")
+    }
+
+    fn info_file(&self, name: &OsStr) -> PathBuf {
+        let mut file = name.to_os_string();
+        file.push(".trashinfo");
+        self.info().join(file)
+    }
+
+    /// Move the file PATH, an absolute file name, to the trash.
+    fn move_file(&self, path: &Path) -> io::Result<()> {
+        if self.path.starts_with(path) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The trash directory is inside the file",
+            ));
+        }
+        for dir in &[self.files(), self.info()] {
+            DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+        }
+
+        let original = match self.topdir {
+            Some(ref topdir) => path.strip_prefix(topdir).unwrap_or(path),
+            None => path,
+        };
+        let contents = format!(
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            percent_encode(original.as_os_str().as_bytes()),
+            deletion_date()
+        );
+
+        // Reserve a name by creating the information file, which the
+        // specification requires to be done atomically.
+        let base = path.file_name().unwrap_or_else(|| OsStr::new("file"));
+        let mut attempt = 0;
+        let (name, info_file) = loop {
+            let mut name = base.to_os_string();
+            if attempt > 0 {
+                name.push(format!(".{}", attempt));
+            }
+            attempt += 1;
+            if fs::symlink_metadata(self.files().join(&name)).is_ok() {
+                continue;
+            }
+            let info_file = self.info_file(&name);
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&info_file)
+            {
+                Ok(mut file) => {
+                    file.write_all(contents.as_bytes())?;
+                    break (name, info_file);
+                }
+                Err(ref error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(error) => return Err(error),
+            }
+        };
+
+        if let Err(error) = fs::rename(path, self.files().join(&name)) {
+            let _ = fs::remove_file(&info_file);
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    /// Return the files in the trash directory.  Information files which
+    /// can't be parsed are ignored.
+    fn items(&self) -> Vec<TrashItem> {
+        let entries = match fs::read_dir(self.info()) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+        entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension() != Some(OsStr::new("trashinfo")) {
+                    return None;
+                }
+                let contents = fs::read(&path).ok()?;
+                let (original, time_deleted) = parse_trash_info(&contents)?;
+                let original_path = match self.topdir {
+                    Some(ref topdir) => topdir.join(original),
+                    None => original,
+                };
+                Some(TrashItem {
+                    name: path.file_stem()?.to_os_string(),
+                    original_path,
+                    time_deleted,
+                })
+            })
+            .collect()
+    }
+
+    /// Move ITEM back to where it was before it was trashed.
+    fn restore(&self, item: &TrashItem) -> io::Result<()> {
+        if let Some(parent) = item.original_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(self.files().join(&item.name), &item.original_path)?;
+        fs::remove_file(self.info_file(&item.name))
+    }
+}
+
+/// Return the device of PATH, or of its closest ancestor which exists.
+#[cfg(unix)]
+fn existing_ancestor_device(path: &Path) -> io::Result<u64> {
+    let mut error = None;
+    for ancestor in path.ancestors() {
+        match fs::metadata(ancestor) {
+            Ok(metadata) => return Ok(metadata.dev()),
+            Err(e) => error = Some(e),
+        }
+    }
+    Err(error.unwrap_or_else(|| io::Error::from(io::ErrorKind::NotFound)))
+}
+
+/// Return the mount points of the system, as /proc/mounts lists them.
+#[cfg(unix)]
+fn mount_points() -> Vec<PathBuf> {
+    let mounts = fs::read("/proc/mounts").unwrap_or_default();
+    mounts
+        .split(|&b| b == b'\n')
+        .filter_map(|line| line.split(|&b| b == b' ').nth(1))
+        .map(|field| PathBuf::from(OsString::from_vec(unescape_mount_field(field))))
+        .collect()
+}
+
+/// Decode the octal escapes, such as `\040' for a space, of FIELD, a
+/// field of /proc/mounts.
+#[cfg(unix)]
+fn unescape_mount_field(field: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(field.len());
+    let mut i = 0;
+    while i < field.len() {
+        let octal = field[i] == b'\\'
+            && field
+                .get(i + 1..i + 4)
+                .map_or(false, |d| d.iter().all(|d| b'0' <= *d && *d <= b'7'));
+        if octal {
+            let digits = &field[i + 1..i + 4];
+            decoded.push(digits.iter().fold(0, |n, d| n * 8 + (d - b'0')));
+            i += 4;
+        } else {
+            decoded.push(field[i]);
+            i += 1;
+        }
+    }
+    decoded
+}
+
+/// Return BYTES with the bytes other than unreserved URI characters and
+/// slashes percent-encoded, as the `Path' key of an information file
+/// has them.
+#[cfg(unix)]
+fn percent_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len());
+    for &b in bytes {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(b as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+/// Decode the percent-encoded bytes of S, or return None if it has a
+/// malformed escape.
+#[cfg(unix)]
+fn percent_decode(s: &str) -> Option<Vec<u8>> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Some(decoded)
+}
+
+/// Return the current local time in the format of the `DeletionDate'
+/// key of an information file, such as `2018-10-29T14:08:01'.
+#[cfg(unix)]
+fn deletion_date() -> String {
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        libc::localtime_r(&now, &mut tm);
+    }
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
+
+/// Return the time of DATE, a local time in the format of the
+/// `DeletionDate' key, in seconds since the epoch.
+#[cfg(unix)]
+fn parse_deletion_date(date: &str) -> Option<i64> {
+    let field = |range: std::ops::Range<usize>| date.get(range)?.parse::<c_int>().ok();
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    tm.tm_year = field(0..4)? - 1900;
+    tm.tm_mon = field(5..7)? - 1;
+    tm.tm_mday = field(8..10)?;
+    tm.tm_hour = field(11..13)?;
+    tm.tm_min = field(14..16)?;
+    tm.tm_sec = field(17..19)?;
+    tm.tm_isdst = -1;
+    match unsafe { libc::mktime(&mut tm) } {
+        -1 => None,
+        time => Some(i64::from(time)),
+    }
+}
+
+/// Return the original name and the deletion time of the information
+/// file whose contents are CONTENTS, or None if it lacks either.
+#[cfg(unix)]
+fn parse_trash_info(contents: &[u8]) -> Option<(PathBuf, i64)> {
+    let contents = std::str::from_utf8(contents).ok()?;
+    let mut lines = contents.lines().map(str::trim);
+    if lines.next() != Some("[Trash Info]") {
+        return None;
+    }
+    let (mut path, mut time) = (None, None);
+    for line in lines {
+        if line.starts_with("Path=") {
+            path = percent_decode(&line[5..]).map(|p| PathBuf::from(OsString::from_vec(p)));
+        } else if line.starts_with("DeletionDate=") {
+            time = parse_deletion_date(&line[13..]);
+        } else if line.starts_with('[') {
+            break;
+        }
+    }
+    Some((path?, time?))
+}
+
+/// Move file or directory named FILENAME to the trash.
+/// This is the trash of the system: the freedesktop.org trash of the
+/// volume FILENAME is on, or the Recycle Bin on MS-Windows.
+/// `move-file-to-trash' calls this function, unless `trash-directory'
+/// is non-nil.
+#[lisp_fn]
+pub fn system_move_file_to_trash(filename: LispStringRef) -> LispObject {
+    let mut filename = LispObject::from(filename);
+    let mut operation = Qdelete_file;
+    unsafe {
+        if Ffile_directory_p(filename).is_not_nil() && Ffile_symlink_p(filename).is_nil() {
+            operation = Qdelete_directory;
+            filename = Fdirectory_file_name(filename);
+        }
+        filename = Fexpand_file_name(filename, Qnil);
+    }
+
+    let handler = unsafe { Ffind_file_name_handler(filename, operation) };
+    if handler.is_not_nil() {
+        return call!(handler, operation, filename);
+    }
+
+    #[cfg(unix)]
+    {
+        let path = system_path(filename);
+        if let Err(error) = TrashDir::for_path(&path).and_then(|trash| trash.move_file(&path)) {
+            trash_error("Moving file to trash\0", &error, filename);
+        }
+    }
+    #[cfg(windows)]
+    unsafe {
+        w32_move_file_to_trash(filename);
+    }
+    Qnil
+}
+
+/// Return the files in the trash, with the trash directory of each.
+#[cfg(unix)]
+fn trash_items() -> Vec<(TrashDir, TrashItem)> {
+    let mut items = Vec::new();
+    for dir in TrashDir::all() {
+        for item in dir.items() {
+            items.push((dir.clone(), item));
+        }
+    }
+    items
+}
+
+/// Return a list of the files and directories in the trash.
+/// Each element has the form (FILENAME DELETION-TIME), where FILENAME is
+/// the absolute name the file had before it was moved to the trash, and
+/// DELETION-TIME is the time it was moved there, in seconds since the
+/// epoch.  The most recently trashed files come first.  On MS-Windows,
+/// the contents of the Recycle Bin can't be listed, and this signals an
+/// error.
+#[lisp_fn]
+pub fn trash_list() -> LispObject {
+    #[cfg(unix)]
+    {
+        let mut items = trash_items();
+        items.sort_by_key(|&(_, ref item)| -item.time_deleted);
+        items
+            .into_iter()
+            .map(|(_, item)| {
+                list!(
+                    lisp_file_name(&item.original_path),
+                    LispObject::from(item.time_deleted as EmacsInt)
+                )
+            })
+            .collect::<Vec<LispObject>>()
+            .into()
+    }
+    #[cfg(windows)]
+    {
+        error!("Listing the trash is not supported on this system");
+    }
+}
+
+/// Restore FILENAME from the trash, where it was moved to.
+/// FILENAME is the name of the file before it was moved to the trash, as
+/// `trash-list' has it.  If the file was moved to the trash more than
+/// once, the version moved there last is restored.  Signal an error if
+/// FILENAME isn't in the trash, or if a file named FILENAME exists.  On
+/// MS-Windows, files can't be restored from the Recycle Bin, and this
+/// signals an error.
+#[lisp_fn]
+pub fn trash_restore(filename: LispStringRef) {
+    #[cfg(unix)]
+    {
+        let filename = unsafe { Fexpand_file_name(filename.into(), Qnil) };
+        let found = trash_items()
+            .into_iter()
+            .filter(|&(_, ref item)| string_equal(lisp_file_name(&item.original_path), filename))
+            .max_by_key(|&(_, ref item)| item.time_deleted);
+        let (dir, item) = match found {
+            Some(found) => found,
+            None => xsignal!(
+                Qfile_missing,
+                LispObject::from("No such file in the trash"),
+                filename
+            ),
+        };
+        if fs::symlink_metadata(&item.original_path).is_ok() {
+            xsignal!(
+                Qfile_already_exists,
+                LispObject::from("File already exists"),
+                filename
+            );
+        }
+        if let Err(error) = dir.restore(&item) {
+            trash_error("Restoring file from trash\0", &error, filename);
+        }
+    }
+    #[cfg(windows)]
+    {
+        let _ = filename;
+        error!("Restoring files from the trash is not supported on this system");
+    }
+}
+
+#[test]
+fn test_percent_encoding() {
+    let name = "/home/me/a file/é%.txt".as_bytes();
+    let encoded = percent_encode(name);
+    assert_eq!(encoded, "/home/me/a%20file/%C3%A9%25.txt");
+    assert_eq!(percent_decode(&encoded).unwrap(), name);
+    assert!(percent_decode("%4").is_none());
+}
+
+#[test]
+fn test_parse_trash_info() {
+    let info = b"[Trash Info]\nPath=foo/bar%20baz\nDeletionDate=2018-10-29T14:08:01\n";
+    let (path, time) = parse_trash_info(info).unwrap();
+    assert_eq!(path, PathBuf::from("foo/bar baz"));
+    assert_eq!(Some(time), parse_deletion_date("2018-10-29T14:08:01"));
+    assert!(parse_trash_info(b"[Trash Info]\nPath=foo\n").is_none());
+    assert!(parse_trash_info(b"Path=foo\nDeletionDate=2018-10-29T14:08:01\n").is_none());
+}
+
+#[test]
+fn test_unescape_mount_field() {
+    assert_eq!(
+        unescape_mount_field(b"/media/my\\040disk"),
+        b"/media/my disk"
+    );
+    assert_eq!(unescape_mount_field(b"/mnt\\"), b"/mnt\\");
+}
+
+include!(concat!(env!("OUT_DIR"), "/trash_exports.rs"));
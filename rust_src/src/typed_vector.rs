@@ -0,0 +1,560 @@
+//! Typed vectors: vectors of unboxed numbers of a single type.
+//!
+//! A typed vector keeps bytes (`u8'), 32-bit integers (`i32') or double
+//! floats (`f64') in a contiguous Rust vector, rather than a Lisp object
+//! for each element, so numeric code can hold large arrays of floats
+//! without a float object per element, and fill, copy and combine them
+//! without leaving Rust.  The Lisp object is a pseudovector pointing at
+//! the Rust vector, which alloc.c frees through `free_typed_vector'
+//! when it sweeps the pseudovector.
+
+use std::ptr;
+
+use libc::c_void;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    lisp::defsubr,
+    lisp::{ExternalPtr, LispObject},
+    obarray::intern,
+    remacs_sys::{pvec_type, EmacsInt, Lisp_Type, Lisp_Typed_Vector},
+    remacs_sys::{Qf64, Qi32, Qnil, Qtyped_vector_p, Qu8},
+};
+
+pub type LispTypedVectorRef = ExternalPtr<Lisp_Typed_Vector>;
+
+/// The type of the elements of a typed vector.
+#[derive(Clone, Copy, PartialEq)]
+enum ElementType {
+    U8,
+    I32,
+    F64,
+}
+
+impl ElementType {
+    fn from_lisp(type_: LispObject) -> ElementType {
+        if type_.eq(Qu8) {
+            ElementType::U8
+        } else if type_.eq(Qi32) {
+            ElementType::I32
+        } else if type_.eq(Qf64) {
+            ElementType::F64
+        } else {
+            error!("Invalid typed vector type: should be `u8', `i32' or `f64'");
+        }
+    }
+
+    fn to_lisp(self) -> LispObject {
+        match self {
+            ElementType::U8 => Qu8,
+            ElementType::I32 => Qi32,
+            ElementType::F64 => Qf64,
+        }
+    }
+
+    /// Return VALUE as an element of this type, signaling an error if
+    /// it isn't a number that fits.
+    fn check(self, value: LispObject) -> Number {
+        let (min, max) = match self {
+            ElementType::U8 => (0, EmacsInt::from(u8::max_value())),
+            ElementType::I32 => (
+                EmacsInt::from(i32::min_value()),
+                EmacsInt::from(i32::max_value()),
+            ),
+            ElementType::F64 => return Number::Float(value.any_to_float_or_error()),
+        };
+        let n = value.as_fixnum_or_error();
+        if n < min || n > max {
+            args_out_of_range!(value, LispObject::from(min), LispObject::from(max));
+        }
+        Number::Int(n)
+    }
+}
+
+/// An element of a typed vector, out of the vector.
+#[derive(Clone, Copy)]
+enum Number {
+    Int(EmacsInt),
+    Float(f64),
+}
+
+impl Number {
+    fn to_lisp(self) -> LispObject {
+        match self {
+            Number::Int(n) => LispObject::from(n),
+            Number::Float(x) => LispObject::from_float(x),
+        }
+    }
+}
+
+/// The elements of a typed vector.
+#[derive(Clone)]
+enum Elements {
+    U8(Vec<u8>),
+    I32(Vec<i32>),
+    F64(Vec<f64>),
+}
+
+impl Elements {
+    fn new(type_: ElementType, length: usize, init: Number) -> Elements {
+        let mut elements = match type_ {
+            ElementType::U8 => Elements::U8(vec![0; length]),
+            ElementType::I32 => Elements::I32(vec![0; length]),
+            ElementType::F64 => Elements::F64(vec![0.0; length]),
+        };
+        elements.fill(0, length, init);
+        elements
+    }
+
+    fn element_type(&self) -> ElementType {
+        match self {
+            Elements::U8(_) => ElementType::U8,
+            Elements::I32(_) => ElementType::I32,
+            Elements::F64(_) => ElementType::F64,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Elements::U8(v) => v.len(),
+            Elements::I32(v) => v.len(),
+            Elements::F64(v) => v.len(),
+        }
+    }
+
+    fn get(&self, index: usize) -> Number {
+        match self {
+            Elements::U8(v) => Number::Int(EmacsInt::from(v[index])),
+            Elements::I32(v) => Number::Int(EmacsInt::from(v[index])),
+            Elements::F64(v) => Number::Float(v[index]),
+        }
+    }
+
+    /// Set the element at INDEX to N, which must be of the type of
+    /// these elements.  Integers are truncated to the width of the type.
+    fn set(&mut self, index: usize, n: Number) {
+        match (self, n) {
+            (Elements::U8(v), Number::Int(n)) => v[index] = n as u8,
+            (Elements::I32(v), Number::Int(n)) => v[index] = n as i32,
+            (Elements::F64(v), Number::Float(x)) => v[index] = x,
+            _ => panic!("Typed vector element of the wrong type"),
+        }
+    }
+
+    fn fill(&mut self, start: usize, end: usize, n: Number) {
+        match (self, n) {
+            (Elements::U8(v), Number::Int(n)) => {
+                for elt in &mut v[start..end] {
+                    *elt = n as u8;
+                }
+            }
+            (Elements::I32(v), Number::Int(n)) => {
+                for elt in &mut v[start..end] {
+                    *elt = n as i32;
+                }
+            }
+            (Elements::F64(v), Number::Float(x)) => {
+                for elt in &mut v[start..end] {
+                    *elt = x;
+                }
+            }
+            _ => panic!("Typed vector element of the wrong type"),
+        }
+    }
+
+    fn slice(&self, start: usize, end: usize) -> Elements {
+        match self {
+            Elements::U8(v) => Elements::U8(v[start..end].to_vec()),
+            Elements::I32(v) => Elements::I32(v[start..end].to_vec()),
+            Elements::F64(v) => Elements::F64(v[start..end].to_vec()),
+        }
+    }
+
+    /// Copy the elements of FROM, which are of the same type, to these
+    /// elements from index START on.
+    fn replace(&mut self, start: usize, from: &Elements) {
+        let end = start + from.len();
+        match (self, from) {
+            (Elements::U8(v), Elements::U8(from)) => v[start..end].copy_from_slice(from),
+            (Elements::I32(v), Elements::I32(from)) => v[start..end].copy_from_slice(from),
+            (Elements::F64(v), Elements::F64(from)) => v[start..end].copy_from_slice(from),
+            _ => panic!("Typed vectors of different types"),
+        }
+    }
+}
+
+/// An arithmetic operation which `typed-vector-map' does without
+/// calling Lisp.
+#[derive(Clone, Copy)]
+enum Operation {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Min,
+    Max,
+}
+
+impl Operation {
+    fn from_lisp(function: LispObject) -> Option<Operation> {
+        let operations = [
+            ("+", Operation::Add),
+            ("-", Operation::Subtract),
+            ("*", Operation::Multiply),
+            ("/", Operation::Divide),
+            ("min", Operation::Min),
+            ("max", Operation::Max),
+        ];
+        operations
+            .iter()
+            .find(|&&(name, _)| function.eq(intern(name).into()))
+            .map(|&(_, operation)| operation)
+    }
+
+    /// Return the result of this operation on A and B, which are of the
+    /// type TYPE_, or None for an integer division by zero.  Integer
+    /// results wrap around to fit TYPE_.
+    fn apply(self, type_: ElementType, a: Number, b: Number) -> Option<Number> {
+        match (a, b) {
+            (Number::Int(a), Number::Int(b)) => {
+                let n = match self {
+                    Operation::Add => a.wrapping_add(b),
+                    Operation::Subtract => a.wrapping_sub(b),
+                    Operation::Multiply => a.wrapping_mul(b),
+                    Operation::Divide if b == 0 => return None,
+                    Operation::Divide => a.wrapping_div(b),
+                    Operation::Min => a.min(b),
+                    Operation::Max => a.max(b),
+                };
+                Some(Number::Int(match type_ {
+                    ElementType::U8 => EmacsInt::from(n as u8),
+                    _ => EmacsInt::from(n as i32),
+                }))
+            }
+            (Number::Float(a), Number::Float(b)) => Some(Number::Float(match self {
+                Operation::Add => a + b,
+                Operation::Subtract => a - b,
+                Operation::Multiply => a * b,
+                Operation::Divide => a / b,
+                Operation::Min => a.min(b),
+                Operation::Max => a.max(b),
+            })),
+            _ => panic!("Typed vector element of the wrong type"),
+        }
+    }
+}
+
+impl LispTypedVectorRef {
+    fn allocate(elements: Elements) -> LispTypedVectorRef {
+        let mut vector = LispTypedVectorRef::new(allocate_pseudovector!(
+            Lisp_Typed_Vector,
+            data,
+            pvec_type::PVEC_TYPED_VECTOR
+        ));
+        vector.data = Box::into_raw(Box::new(elements)) as *mut c_void;
+        vector
+    }
+
+    fn elements(&self) -> &Elements {
+        unsafe { &*(self.data as *const Elements) }
+    }
+
+    fn elements_mut(&mut self) -> &mut Elements {
+        unsafe { &mut *(self.data as *mut Elements) }
+    }
+
+    /// Return INDEX as an index of this vector, signaling an error
+    /// unless it is one.
+    fn index(self, index: EmacsInt) -> usize {
+        if index < 0 || index as usize >= self.elements().len() {
+            args_out_of_range!(LispObject::from(self), LispObject::from(index));
+        }
+        index as usize
+    }
+
+    /// Return the range of indices from START to END, which default to
+    /// the start and the end of this vector.
+    fn range(self, start: LispObject, end: LispObject) -> (usize, usize) {
+        let length = self.elements().len() as EmacsInt;
+        let from = if start.is_nil() {
+            0
+        } else {
+            start.as_fixnum_or_error()
+        };
+        let to = if end.is_nil() {
+            length
+        } else {
+            end.as_fixnum_or_error()
+        };
+        if from < 0 || from > to || to > length {
+            args_out_of_range!(LispObject::from(self), start, end);
+        }
+        (from as usize, to as usize)
+    }
+}
+
+impl LispObject {
+    pub fn is_typed_vector(self) -> bool {
+        self.as_vectorlike()
+            .map_or(false, |v| v.is_pseudovector(pvec_type::PVEC_TYPED_VECTOR))
+    }
+
+    pub fn as_typed_vector(self) -> Option<LispTypedVectorRef> {
+        if self.is_typed_vector() {
+            Some(LispTypedVectorRef::new(
+                self.get_untaggedptr() as *mut Lisp_Typed_Vector
+            ))
+        } else {
+            None
+        }
+    }
+
+    pub fn as_typed_vector_or_error(self) -> LispTypedVectorRef {
+        self.as_typed_vector()
+            .unwrap_or_else(|| wrong_type!(Qtyped_vector_p, self))
+    }
+}
+
+impl From<LispObject> for LispTypedVectorRef {
+    fn from(o: LispObject) -> Self {
+        o.as_typed_vector_or_error()
+    }
+}
+
+impl From<LispTypedVectorRef> for LispObject {
+    fn from(v: LispTypedVectorRef) -> Self {
+        LispObject::tag_ptr(v, Lisp_Type::Lisp_Vectorlike)
+    }
+}
+
+/// Free the elements of the typed vector VECTOR, which the garbage
+/// collector is freeing.
+#[no_mangle]
+pub extern "C" fn free_typed_vector(vector: *mut Lisp_Typed_Vector) {
+    unsafe {
+        let data = (*vector).data;
+        if !data.is_null() {
+            drop(Box::from_raw(data as *mut Elements));
+            (*vector).data = ptr::null_mut();
+        }
+    }
+}
+
+/// Return a new typed vector of LENGTH elements of type TYPE, all INIT.
+///
+/// TYPE is `u8' for bytes, `i32' for 32-bit signed integers, or `f64'
+/// for floats.  A typed vector keeps its elements unboxed, one after
+/// the other, and takes a number of the right type for each of them.
+/// INIT defaults to 0.
+///
+/// `aref' and `aset' work on typed vectors, as do `typed-vector-fill',
+/// `typed-vector-copy', `typed-vector-replace' and `typed-vector-map',
+/// which work on many elements at once.
+#[lisp_fn(min = "2")]
+pub fn make_typed_vector(type_: LispObject, length: EmacsInt, init: LispObject) -> LispObject {
+    let element_type = ElementType::from_lisp(type_);
+    if length < 0 {
+        args_out_of_range!(LispObject::from(length), Qnil);
+    }
+    let init = element_type.check(if init.is_nil() {
+        LispObject::from(0)
+    } else {
+        init
+    });
+    LispTypedVectorRef::allocate(Elements::new(element_type, length as usize, init)).into()
+}
+
+/// Return a new typed vector of type TYPE with the elements ELEMENTS.
+/// See `make-typed-vector' for the types.
+/// usage: (typed-vector TYPE &rest ELEMENTS)
+#[lisp_fn(min = "1")]
+pub fn typed_vector(args: &mut [LispObject]) -> LispObject {
+    let element_type = ElementType::from_lisp(args[0]);
+    let numbers = &args[1..];
+    let mut elements = Elements::new(
+        element_type,
+        numbers.len(),
+        element_type.check(LispObject::from(0)),
+    );
+    for (i, &n) in numbers.iter().enumerate() {
+        elements.set(i, element_type.check(n));
+    }
+    LispTypedVectorRef::allocate(elements).into()
+}
+
+/// Return t if OBJECT is a typed vector.
+#[lisp_fn]
+pub fn typed_vector_p(object: LispObject) -> bool {
+    object.is_typed_vector()
+}
+
+/// Return the type of the elements of the typed vector VECTOR: `u8',
+/// `i32' or `f64'.
+#[lisp_fn]
+pub fn typed_vector_type(vector: LispTypedVectorRef) -> LispObject {
+    vector.elements().element_type().to_lisp()
+}
+
+/// Return the number of elements of the typed vector VECTOR.
+#[lisp_fn]
+pub fn typed_vector_length(vector: LispTypedVectorRef) -> EmacsInt {
+    vector.elements().len() as EmacsInt
+}
+
+/// Return the element of the typed vector VECTOR at index INDEX.
+#[lisp_fn]
+pub fn typed_vector_ref(vector: LispTypedVectorRef, index: EmacsInt) -> LispObject {
+    let index = vector.index(index);
+    vector.elements().get(index).to_lisp()
+}
+
+/// Set the element of the typed vector VECTOR at index INDEX to VALUE,
+/// and return VALUE.
+#[lisp_fn]
+pub fn typed_vector_set(
+    mut vector: LispTypedVectorRef,
+    index: EmacsInt,
+    value: LispObject,
+) -> LispObject {
+    let index = vector.index(index);
+    let n = vector.elements().element_type().check(value);
+    vector.elements_mut().set(index, n);
+    value
+}
+
+/// Set the elements of the typed vector VECTOR from index START up to
+/// index END to VALUE, and return VECTOR.  START and END default to
+/// the start and the end of VECTOR.
+#[lisp_fn(min = "2")]
+pub fn typed_vector_fill(
+    mut vector: LispTypedVectorRef,
+    value: LispObject,
+    start: LispObject,
+    end: LispObject,
+) -> LispTypedVectorRef {
+    let (start, end) = vector.range(start, end);
+    let n = vector.elements().element_type().check(value);
+    vector.elements_mut().fill(start, end, n);
+    vector
+}
+
+/// Return a new typed vector of the elements of the typed vector
+/// VECTOR from index START up to index END, which default to the start
+/// and the end of VECTOR.
+#[lisp_fn(min = "1")]
+pub fn typed_vector_copy(
+    vector: LispTypedVectorRef,
+    start: LispObject,
+    end: LispObject,
+) -> LispObject {
+    let (start, end) = vector.range(start, end);
+    LispTypedVectorRef::allocate(vector.elements().slice(start, end)).into()
+}
+
+/// Copy the elements of the typed vector FROM into the typed vector TO,
+/// from index START of TO on, and return TO.  START defaults to 0.
+/// FROM and TO must have elements of the same type, and FROM must fit
+/// in TO from START on.
+#[lisp_fn(min = "2")]
+pub fn typed_vector_replace(
+    mut to: LispTypedVectorRef,
+    from: LispTypedVectorRef,
+    start: LispObject,
+) -> LispTypedVectorRef {
+    if to.elements().element_type() != from.elements().element_type() {
+        error!("Typed vectors with different element types");
+    }
+    let (index, _) = to.range(start, Qnil);
+    if index + from.elements().len() > to.elements().len() {
+        args_out_of_range!(LispObject::from(to), LispObject::from(from), start);
+    }
+    // Copy FROM first, in case it is TO.
+    let from = from.elements().clone();
+    to.elements_mut().replace(start, &from);
+    to
+}
+
+/// Return a new typed vector of the results of calling FUNCTION on the
+/// elements of the typed vector VECTOR, of the type of VECTOR.
+///
+/// If OTHER is non-nil, FUNCTION is called with two arguments: each
+/// element of VECTOR and OTHER, if it is a number, or else the element
+/// at the same index of OTHER, a typed vector of the same type and
+/// length as VECTOR.
+///
+/// If FUNCTION is `+', `-', `*', `/', `min' or `max' and OTHER is
+/// non-nil, the elements are computed without calling FUNCTION.  Then
+/// the results for `u8' and `i32' vectors wrap around on overflow.
+#[lisp_fn(min = "2")]
+pub fn typed_vector_map(
+    function: LispObject,
+    vector: LispTypedVectorRef,
+    other: LispObject,
+) -> LispObject {
+    let element_type = vector.elements().element_type();
+    let length = vector.elements().len();
+    let other_vector = other.as_typed_vector();
+    let scalar = match other_vector {
+        Some(other_vector) => {
+            if other_vector.elements().element_type() != element_type
+                || other_vector.elements().len() != length
+            {
+                error!("Typed vectors with different element types or lengths");
+            }
+            None
+        }
+        None if other.is_nil() => None,
+        None => Some(element_type.check(other)),
+    };
+    let operand = |i: usize| match other_vector {
+        Some(other_vector) => other_vector.elements().get(i),
+        None => scalar.unwrap(),
+    };
+
+    let mut result = vector.elements().clone();
+    match Operation::from_lisp(function) {
+        Some(operation) if other.is_not_nil() => {
+            for i in 0..length {
+                match operation.apply(element_type, result.get(i), operand(i)) {
+                    Some(n) => result.set(i, n),
+                    None => arith_error!(),
+                }
+            }
+        }
+        _ => {
+            // FUNCTION may change VECTOR or OTHER, so look at their
+            // elements afresh for each call.
+            for i in 0..length {
+                let elt = vector.elements().get(i).to_lisp();
+                let value = if other.is_nil() {
+                    call!(function, elt)
+                } else {
+                    call!(function, elt, operand(i).to_lisp())
+                };
+                result.set(i, element_type.check(value));
+            }
+        }
+    }
+    LispTypedVectorRef::allocate(result).into()
+}
+
+/// Return a list of the elements of the typed vector VECTOR.
+#[lisp_fn]
+pub fn typed_vector_to_list(vector: LispTypedVectorRef) -> LispObject {
+    let elements = vector.elements();
+    (0..elements.len())
+        .map(|i| elements.get(i).to_lisp())
+        .collect()
+}
+
+#[no_mangle]
+pub extern "C" fn syms_of_typed_vector() {
+    def_lisp_sym!(Qtyped_vector, "typed-vector");
+    def_lisp_sym!(Qtyped_vector_p, "typed-vector-p");
+    def_lisp_sym!(Qu8, "u8");
+    def_lisp_sym!(Qi32, "i32");
+    def_lisp_sym!(Qf64, "f64");
+}
+
+include!(concat!(env!("OUT_DIR"), "/typed_vector_exports.rs"));
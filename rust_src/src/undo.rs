@@ -0,0 +1,440 @@
+//! Recording changes for undo.
+//!
+//! Changes to the current buffer are recorded in `buffer-undo-list', in
+//! the form which `primitive-undo' reads.  The elements of the list are
+//! examined as `UndoEntry' values, to combine consecutive insertions
+//! and to measure the list when it is truncated.
+
+use std::mem;
+
+use libc::ptrdiff_t;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    buffers::LispBufferRef,
+    data::set,
+    eval::unbind_to,
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::{LispCons, LispConsCircularChecks, LispConsEndChecks},
+    multibyte::LispStringRef,
+    remacs_sys::{
+        buffer_before_last_command_or_undo, globals, inhibit_garbage_collection,
+        make_buffer_string, point_before_last_command_or_undo, record_unwind_current_buffer,
+        set_buffer_internal,
+    },
+    remacs_sys::{EmacsInt, Fvisited_file_modtime, Lisp_Buffer, Lisp_Cons, Lisp_String},
+    remacs_sys::{Qexplicit, Qnil, Qt, Qundo_auto__last_boundary_cause},
+    threads::ThreadState,
+};
+
+// The first time a command records something for undo, it also
+// allocates the cons cell for the undo boundary which is added at the
+// end of the command, so that there's always memory for it.
+declare_GC_protected_static!(pending_boundary, Qnil);
+
+/// An element of `buffer-undo-list'.
+#[derive(Clone, Copy)]
+enum UndoEntry {
+    /// nil, the boundary between the changes of two commands.
+    Boundary,
+    /// (BEG . END), for text inserted between BEG and END.
+    Insertion(EmacsInt, EmacsInt),
+    /// (TEXT . POSITION), for TEXT deleted at POSITION.
+    Deletion(LispStringRef),
+    /// (MARKER . ADJUSTMENT), for a marker in the text of the deletion
+    /// recorded just before.
+    MarkerAdjustment,
+    /// Any other element, such as a position of point or a change of a
+    /// text property.
+    Other(LispObject),
+}
+
+impl UndoEntry {
+    fn from_lisp(elt: LispObject) -> Self {
+        let (car, cdr) = match elt.as_cons() {
+            Some(cons) => cons.as_tuple(),
+            None if elt.is_nil() => return UndoEntry::Boundary,
+            None => return UndoEntry::Other(elt),
+        };
+        if let (Some(beg), Some(end)) = (car.as_fixnum(), cdr.as_fixnum()) {
+            UndoEntry::Insertion(beg, end)
+        } else if let Some(text) = car.as_string() {
+            UndoEntry::Deletion(text)
+        } else if car.is_marker() {
+            UndoEntry::MarkerAdjustment
+        } else {
+            UndoEntry::Other(elt)
+        }
+    }
+
+    /// Return the number of bytes the entry and its link in the list
+    /// occupy, counting the text of a deletion.
+    fn size(self) -> EmacsInt {
+        let cons = mem::size_of::<Lisp_Cons>() as EmacsInt;
+        match self {
+            UndoEntry::Boundary => cons,
+            UndoEntry::Insertion(..) | UndoEntry::MarkerAdjustment => 2 * cons,
+            UndoEntry::Deletion(text) => {
+                2 * cons + mem::size_of::<Lisp_String>() as EmacsInt - 1
+                    + text.len_chars() as EmacsInt
+            }
+            UndoEntry::Other(elt) => {
+                if elt.is_cons() {
+                    2 * cons
+                } else {
+                    cons
+                }
+            }
+        }
+    }
+}
+
+/// Push ELT onto the undo list of the current buffer.
+fn push_undo_entry(elt: LispObject) {
+    let mut buffer = ThreadState::current_buffer();
+    buffer.undo_list_ = LispObject::cons(elt, buffer.undo_list_);
+}
+
+/// Return true if undo information isn't recorded in the current buffer.
+fn undo_disabled() -> bool {
+    ThreadState::current_buffer().undo_list_.eq(Qt)
+}
+
+/// Return true if the current buffer hasn't been changed since it was
+/// last saved.
+fn unmodified_since_save() -> bool {
+    let buffer = ThreadState::current_buffer();
+    buffer.modifications() <= buffer.modifications_since_save()
+}
+
+/// Prepare the undo information for recording a change.
+fn prepare_record() {
+    unsafe {
+        if pending_boundary.is_nil() {
+            pending_boundary = LispObject::cons(Qnil, Qnil);
+        }
+    }
+}
+
+/// Record point as it was at the beginning of the command, if necessary.
+/// BEG is the position of point after undoing the change about to be
+/// recorded.
+fn record_point(beg: ptrdiff_t) {
+    if unsafe { globals.undo_inhibit_record_point } {
+        return;
+    }
+
+    // Check whether we are at a boundary before recording the first
+    // change since the buffer was saved.
+    let mut buffer = ThreadState::current_buffer();
+    let at_boundary = buffer
+        .undo_list_
+        .as_cons()
+        .map_or(true, |list| list.car().is_nil());
+
+    if unmodified_since_save() {
+        record_first_change();
+    }
+
+    // Point is recorded after a boundary so that undo restores it,
+    // unless undoing the change moves it there anyway.  If another
+    // buffer was changed since the last command, the value of point we
+    // have is that buffer's.
+    let point = unsafe { point_before_last_command_or_undo };
+    if at_boundary
+        && point != beg
+        && unsafe { buffer_before_last_command_or_undo } == buffer.as_mut()
+    {
+        push_undo_entry(LispObject::from(point as EmacsInt));
+    }
+}
+
+/// Record an insertion of LENGTH characters at BEG, which has just
+/// happened or is about to happen.  An insertion in the text inserted
+/// by the insertion recorded last, or at either end of it, is combined
+/// with it.
+#[no_mangle]
+pub extern "C" fn record_insert(beg: ptrdiff_t, length: ptrdiff_t) {
+    if undo_disabled() {
+        return;
+    }
+
+    prepare_record();
+    record_point(beg);
+
+    let (beg, length) = (beg as EmacsInt, length as EmacsInt);
+    if let Some(list) = ThreadState::current_buffer().undo_list_.as_cons() {
+        let last = list.car();
+        if let UndoEntry::Insertion(last_beg, last_end) = UndoEntry::from_lisp(last) {
+            if last_beg <= beg && beg <= last_end {
+                last.as_cons_or_error()
+                    .set_cdr(LispObject::from(last_end + length));
+                return;
+            }
+        }
+    }
+
+    push_undo_entry(LispObject::cons(
+        LispObject::from(beg),
+        LispObject::from(beg + length),
+    ));
+}
+
+/// Record the adjustments of the markers between FROM and TO, which are
+/// about to be moved by deleting the text between them.  Only these
+/// markers need them, since the other adjustments are undone by undoing
+/// the deletion.
+fn record_marker_adjustments(from: ptrdiff_t, to: ptrdiff_t) {
+    prepare_record();
+
+    let buffer = ThreadState::current_buffer();
+    let markers = match buffer.markers() {
+        Some(markers) => markers,
+        None => return,
+    };
+    for marker in markers.iter() {
+        let charpos = marker.charpos;
+        debug_assert!(charpos <= buffer.z());
+        if from <= charpos && charpos <= to {
+            // Reinserting the deleted text leaves markers with insertion
+            // type nil at its beginning and those with insertion type t
+            // at its end, so they are moved back from there.
+            let adjustment = if marker.insertion_type() { to } else { from } - charpos;
+            if adjustment != 0 {
+                push_undo_entry(LispObject::cons(
+                    LispObject::from(marker),
+                    LispObject::from(adjustment as EmacsInt),
+                ));
+            }
+        }
+    }
+}
+
+/// Record the deletion of the characters of STRING at BEG, which is about
+/// to happen.  If RECORD_MARKERS, also record the adjustments of the
+/// markers in the text STRING occupies.
+#[no_mangle]
+pub extern "C" fn record_delete(beg: ptrdiff_t, string: LispObject, record_markers: bool) {
+    if undo_disabled() {
+        return;
+    }
+
+    prepare_record();
+    record_point(beg);
+
+    let end = beg + string.as_string_or_error().len_chars();
+    // A negative position means point was at the end of the text.
+    let position = if ThreadState::current_buffer().pt == end {
+        -beg
+    } else {
+        beg
+    };
+
+    // `primitive-undo' expects the marker adjustments to be recorded
+    // right before the deletion (Bug#16818).
+    if record_markers {
+        record_marker_adjustments(beg, end);
+    }
+
+    push_undo_entry(LispObject::cons(
+        string,
+        LispObject::from(position as EmacsInt),
+    ));
+}
+
+/// Record the replacement of LENGTH characters at BEG, which is about to
+/// happen.  The replacement must not change the number of characters.
+#[no_mangle]
+pub extern "C" fn record_change(beg: ptrdiff_t, length: ptrdiff_t) {
+    let string = unsafe { make_buffer_string(beg, beg + length, true) };
+    record_delete(beg, string, false);
+    record_insert(beg, length);
+}
+
+/// Record that an unmodified buffer is about to be changed, with the
+/// modification time of the visited file, so that undoing the change
+/// can tell whether the file was saved again since.
+#[no_mangle]
+pub extern "C" fn record_first_change() {
+    if undo_disabled() {
+        return;
+    }
+
+    push_undo_entry(LispObject::cons(Qt, unsafe { Fvisited_file_modtime() }));
+}
+
+/// Record the change of the property PROP, whose old value was VALUE,
+/// for LENGTH characters at BEG in BUFFER.
+#[no_mangle]
+pub extern "C" fn record_property_change(
+    beg: ptrdiff_t,
+    length: ptrdiff_t,
+    prop: LispObject,
+    value: LispObject,
+    buffer: LispObject,
+) {
+    if buffer.as_buffer_or_error().undo_list_.eq(Qt) {
+        return;
+    }
+
+    prepare_record();
+
+    if unmodified_since_save() {
+        record_first_change();
+    }
+
+    let (beg, end) = (beg as EmacsInt, (beg + length) as EmacsInt);
+    let range = LispObject::cons(LispObject::from(beg), LispObject::from(end));
+    let entry = LispObject::cons(Qnil, LispObject::cons(prop, LispObject::cons(value, range)));
+    push_undo_entry(entry);
+}
+
+/// Mark a boundary between units of undo.
+/// An undo command will stop at this point,
+/// but another undo command will undo to the previous boundary.
+#[lisp_fn]
+pub fn undo_boundary() {
+    let mut buffer = ThreadState::current_buffer();
+    if buffer.undo_list_.eq(Qt) {
+        return;
+    }
+
+    if buffer
+        .undo_list_
+        .as_cons()
+        .map_or(false, |list| list.car().is_not_nil())
+    {
+        // Use the cons cell allocated for the boundary, if there is one.
+        let boundary = unsafe { mem::replace(&mut pending_boundary, Qnil) };
+        match boundary.as_cons() {
+            Some(cons) => {
+                cons.set_cdr(buffer.undo_list_);
+                buffer.undo_list_ = boundary;
+            }
+            None => push_undo_entry(Qnil),
+        }
+    }
+
+    set(Qundo_auto__last_boundary_cause.into(), Qexplicit);
+    unsafe {
+        point_before_last_command_or_undo = buffer.pt;
+        buffer_before_last_command_or_undo = buffer.as_mut();
+    }
+}
+
+/// Truncate the most recent change group of an undo list to LIMIT bytes,
+/// keeping its newest entries.  GROUP holds the links of the group, from
+/// the newest, with their entries and the size of the list up to them.
+fn trim_change_group(
+    mut buffer: LispBufferRef,
+    group: &[(LispCons, UndoEntry, EmacsInt)],
+    limit: EmacsInt,
+) {
+    // The marker adjustments of a deletion are kept with it.
+    let last_kept = group
+        .iter()
+        .enumerate()
+        .take_while(|&(_, &(_, _, size))| size <= limit)
+        .filter(|&(i, _)| match group.get(i + 1) {
+            Some((_, UndoEntry::MarkerAdjustment, _)) => false,
+            _ => true,
+        })
+        .last();
+    match last_kept {
+        Some((_, &(link, _, _))) => link.set_cdr(Qnil),
+        None => buffer.undo_list_ = Qnil,
+    }
+}
+
+/// At garbage collection time, make the undo list of B shorter at the
+/// end.  Older change groups are discarded as `undo-limit' and
+/// `undo-strong-limit' say.  If the most recent one is bigger than
+/// `undo-outer-limit', `undo-outer-limit-function' is called, and unless
+/// it deals with it, the oldest entries of the group are discarded to
+/// bring it within the limit.
+#[no_mangle]
+pub extern "C" fn truncate_undo_list(b: *mut Lisp_Buffer) {
+    // Make sure that calling `undo-outer-limit-function' won't cause
+    // another GC.
+    let count = unsafe { inhibit_garbage_collection() };
+
+    // Make the buffer current to get its local values of variables such
+    // as `undo-limit', and so that `undo-outer-limit-function' can tell
+    // which buffer to operate on.
+    unsafe {
+        record_unwind_current_buffer();
+        set_buffer_internal(b);
+    }
+    let mut buffer = LispBufferRef::new(b);
+
+    let mut tails = buffer
+        .undo_list_
+        .iter_tails(LispConsEndChecks::off, LispConsCircularChecks::off)
+        .peekable();
+    let mut size = 0;
+    let mut prev = None;
+
+    // Always keep the most recent change group, after the boundary at
+    // the head of the list if there is one, unless it is horribly big.
+    let mut group = Vec::new();
+    while let Some(&tail) = tails.peek() {
+        let entry = UndoEntry::from_lisp(tail.car());
+        if let UndoEntry::Boundary = entry {
+            if !group.is_empty() {
+                break;
+            }
+        }
+        size += entry.size();
+        group.push((tail, entry, size));
+        prev = Some(tail);
+        tails.next();
+    }
+
+    if let Some(outer_limit) = unsafe { globals.Vundo_outer_limit }.as_fixnum() {
+        if size > outer_limit {
+            // Normally the function is `undo-outer-limit-truncate'.
+            let function = unsafe { globals.Vundo_outer_limit_function };
+            if function.is_nil() || call!(function, LispObject::from(size)).is_nil() {
+                trim_change_group(buffer, &group, outer_limit);
+            }
+            unbind_to(count, Qnil);
+            return;
+        }
+    }
+
+    // Keep older change groups while they fit: the group which takes the
+    // size past `undo-limit' is the last one kept, unless it also takes
+    // it past `undo-strong-limit'.
+    let (undo_limit, strong_limit) = unsafe { (globals.undo_limit, globals.undo_strong_limit) };
+    let mut last_boundary = if tails.peek().is_some() { prev } else { None };
+    let mut scanned_all = true;
+    for tail in tails {
+        let entry = UndoEntry::from_lisp(tail.car());
+        if let UndoEntry::Boundary = entry {
+            if size > strong_limit {
+                scanned_all = false;
+                break;
+            }
+            last_boundary = prev;
+            if size > undo_limit {
+                scanned_all = false;
+                break;
+            }
+        }
+        size += entry.size();
+        prev = Some(tail);
+    }
+
+    if !scanned_all {
+        match last_boundary {
+            Some(link) => link.set_cdr(Qnil),
+            None => buffer.undo_list_ = Qnil,
+        }
+    }
+
+    unbind_to(count, Qnil);
+}
+
+include!(concat!(env!("OUT_DIR"), "/undo_exports.rs"));
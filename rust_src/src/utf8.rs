@@ -0,0 +1,231 @@
+//! UTF-8 validation and repair.
+//!
+//! Text is valid UTF-8 if the bytes it stands for are: each raw-byte
+//! (`eight-bit') character of multibyte text stands for its byte, and
+//! characters outside Unicode are never valid.
+
+use libc::{c_char, ptrdiff_t};
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    buffers::validate_region,
+    json::json_make_string,
+    lisp::defsubr,
+    lisp::LispObject,
+    multibyte::{char_byte8_p, multibyte_char_at, raw_byte_from_codepoint, Codepoint},
+    remacs_sys::{buf_charpos_to_bytepos, make_unibyte_string, replace_range, EmacsInt},
+    remacs_sys::{string_char_to_byte, validate_subarray, Qbuffer_or_string_p},
+    threads::ThreadState,
+};
+
+/// The UTF-8 encoding of U+FFFD REPLACEMENT CHARACTER.
+const REPLACEMENT: &[u8] = b"\xEF\xBF\xBD";
+
+/// Return true if the multibyte character C is a Unicode scalar value.
+pub fn unicode_char_p(c: Codepoint) -> bool {
+    c <= 0x10_FFFF && (c < 0xD800 || c > 0xDFFF)
+}
+
+/// Return the number of invalid sequences in BYTES, as
+/// `String::from_utf8_lossy' replaces them: each maximal prefix of a
+/// valid sequence, or byte which begins none, is one invalid sequence.
+fn invalid_sequences(mut bytes: &[u8]) -> usize {
+    let mut count = 0;
+    while let Err(error) = std::str::from_utf8(bytes) {
+        count += 1;
+        let invalid = error
+            .error_len()
+            .unwrap_or(bytes.len() - error.valid_up_to());
+        bytes = &bytes[error.valid_up_to() + invalid..];
+    }
+    count
+}
+
+/// Return the repaired text for RUN, a run of multibyte characters which
+/// are raw bytes or, for None, outside Unicode, and the number of
+/// invalid sequences in it.
+fn repair_run(run: &[Option<u8>]) -> (String, usize) {
+    let mut repaired = String::new();
+    let mut count = 0;
+    // The groups of raw bytes are separated by non-Unicode characters.
+    for (i, group) in run.split(Option::is_none).enumerate() {
+        if i > 0 {
+            repaired.push('\u{FFFD}');
+            count += 1;
+        }
+        let bytes: Vec<u8> = group.iter().flatten().cloned().collect();
+        count += invalid_sequences(&bytes);
+        repaired.push_str(&String::from_utf8_lossy(&bytes));
+    }
+    (repaired, count)
+}
+
+/// Return true if TEXT, in Emacs's internal representation, is valid
+/// UTF-8; it is multibyte if MULTIBYTE.
+fn utf8_valid(text: &[u8], multibyte: bool) -> bool {
+    // Raw bytes aren't valid UTF-8 in the internal representation, so
+    // this is enough for most text.
+    if std::str::from_utf8(text).is_ok() {
+        return true;
+    }
+    if !multibyte {
+        return false;
+    }
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        let (c, len) = multibyte_char_at(&text[i..]);
+        if char_byte8_p(c) {
+            bytes.push(raw_byte_from_codepoint(c));
+        } else if unicode_char_p(c) {
+            bytes.extend_from_slice(&text[i..i + len]);
+        } else {
+            return false;
+        }
+        i += len;
+    }
+    std::str::from_utf8(&bytes).is_ok()
+}
+
+/// Return the text of OBJECT, a string or buffer, between START and END,
+/// in Emacs's internal representation, and whether it is multibyte.
+fn utf8_input(object: LispObject, start: LispObject, end: LispObject) -> (Vec<u8>, bool) {
+    if let Some(string) = object.as_string() {
+        let (mut from, mut to) = (0, 0);
+        unsafe { validate_subarray(object, start, end, string.len_chars(), &mut from, &mut to) };
+        let from_byte = unsafe { string_char_to_byte(object, from) } as usize;
+        let to_byte = unsafe { string_char_to_byte(object, to) } as usize;
+        (
+            string.as_slice()[from_byte..to_byte].to_vec(),
+            string.is_multibyte(),
+        )
+    } else if let Some(mut buffer) = object.as_live_buffer() {
+        let position = |pos: LispObject, default| {
+            if pos.is_nil() {
+                default
+            } else {
+                pos.as_number_coerce_marker_or_error().to_fixnum() as ptrdiff_t
+            }
+        };
+        let (mut from, mut to) = (position(start, buffer.begv), position(end, buffer.zv));
+        if from > to {
+            std::mem::swap(&mut from, &mut to);
+        }
+        if !(buffer.begv <= from && to <= buffer.zv) {
+            args_out_of_range!(start, end);
+        }
+        let from_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), from) };
+        let to_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), to) };
+        let (before_gap, after_gap) = buffer.region_slices(from_byte, to_byte);
+        let mut text = before_gap.to_vec();
+        text.extend_from_slice(after_gap);
+        (text, buffer.multibyte_characters_enabled())
+    } else {
+        wrong_type!(Qbuffer_or_string_p, object)
+    }
+}
+
+/// Return t if OBJECT, a buffer or string, is valid UTF-8.
+/// The text of a unibyte string or buffer is valid if its bytes are.
+/// Multibyte text is valid if it would be encoded as valid UTF-8 by the
+/// `utf-8' coding system: it must contain only Unicode characters and
+/// raw bytes which together form valid UTF-8 sequences.
+///
+/// The two optional arguments START and END are positions specifying
+/// which part of OBJECT to check.  If nil or omitted, uses the whole
+/// OBJECT, or the accessible portion of a buffer.
+#[lisp_fn(min = "1")]
+pub fn utf8_valid_p(object: LispObject, start: LispObject, end: LispObject) -> bool {
+    let (text, multibyte) = utf8_input(object, start, end);
+    utf8_valid(&text, multibyte)
+}
+
+/// Replace the invalid UTF-8 sequences in the region with U+FFFD.
+/// Return the number of sequences replaced, which is 0 if the text
+/// between START and END is valid according to `utf8-valid-p'.
+///
+/// In a unibyte buffer, each invalid byte sequence is replaced by the
+/// bytes of the UTF-8 encoding of U+FFFD.  In a multibyte buffer, raw
+/// bytes which together form a valid sequence are replaced by the
+/// character it encodes, other raw bytes are replaced by U+FFFD as in
+/// a unibyte buffer, and each character outside Unicode is replaced by
+/// U+FFFD.
+#[lisp_fn]
+pub fn utf8_repair_region(mut start: LispObject, mut end: LispObject) -> EmacsInt {
+    unsafe { validate_region(&mut start, &mut end) };
+    let start = start.as_fixnum_or_error() as ptrdiff_t;
+    let end = end.as_fixnum_or_error() as ptrdiff_t;
+    let buffer = ThreadState::current_buffer();
+    let (text, multibyte) = utf8_input(buffer.into(), start.into(), end.into());
+    if utf8_valid(&text, multibyte) {
+        return 0;
+    }
+
+    // Find the replacements, as the character positions they replace
+    // and the replacement text.
+    let mut edits = Vec::new();
+    let mut count = 0;
+    if multibyte {
+        // Each run of raw bytes and non-Unicode characters is replaced
+        // at once, since the raw bytes may form valid sequences.
+        let mut run = Vec::new();
+        let (mut i, mut pos) = (0, start);
+        while i <= text.len() {
+            let (c, len) = if i < text.len() {
+                multibyte_char_at(&text[i..])
+            } else {
+                (0, 1)
+            };
+            if i < text.len() && char_byte8_p(c) {
+                run.push(Some(raw_byte_from_codepoint(c)));
+            } else if i < text.len() && !unicode_char_p(c) {
+                run.push(None);
+            } else if !run.is_empty() {
+                let (replacement, replaced) = repair_run(&run);
+                let from = pos - run.len() as ptrdiff_t;
+                edits.push((from, pos, json_make_string(&replacement)));
+                count += replaced;
+                run.clear();
+            }
+            i += len;
+            pos += 1;
+        }
+    } else {
+        let mut rest: &[u8] = &text;
+        let mut pos = start;
+        while let Err(error) = std::str::from_utf8(rest) {
+            let valid = error.valid_up_to();
+            let invalid = error.error_len().unwrap_or(rest.len() - valid);
+            let from = pos + valid as ptrdiff_t;
+            let replacement = unsafe {
+                make_unibyte_string(
+                    REPLACEMENT.as_ptr() as *const c_char,
+                    REPLACEMENT.len() as ptrdiff_t,
+                )
+            };
+            edits.push((from, from + invalid as ptrdiff_t, replacement));
+            count += 1;
+            rest = &rest[valid + invalid..];
+            pos = from + invalid as ptrdiff_t;
+        }
+    }
+
+    // Replace from the end, so that the earlier positions stay valid.
+    for (from, to, replacement) in edits.into_iter().rev() {
+        unsafe { replace_range(from, to, replacement, true, false, true, false) };
+    }
+    count as EmacsInt
+}
+
+#[test]
+fn test_invalid_sequences() {
+    assert_eq!(invalid_sequences(b"abc \xC3\xA9"), 0);
+    assert_eq!(invalid_sequences(b"\xFF"), 1);
+    // A truncated sequence is a single invalid sequence.
+    assert_eq!(invalid_sequences(b"a\xE2\x82b\xE2\x82"), 2);
+    assert_eq!(invalid_sequences(b"\xED\xA0\x80"), 3);
+    assert_eq!(invalid_sequences(b"\xC0\x80"), 2);
+}
+
+include!(concat!(env!("OUT_DIR"), "/utf8_exports.rs"));
@@ -1,6 +1,5 @@
 //! Functions operating on vector(like)s, and general sequences.
 
-use std::cmp::Ordering;
 use std::mem;
 use std::ptr;
 
@@ -15,7 +14,7 @@ use crate::{
     frames::LispFrameRef,
     lisp::defsubr,
     lisp::{ExternalPtr, LispObject, LispSubrRef},
-    lists::{inorder, nth, sort_list},
+    lists::nth,
     multibyte::MAX_CHAR,
     process::LispProcessRef,
     remacs_sys::{
@@ -496,37 +495,6 @@ pub fn elt(sequence: LispObject, n: EmacsInt) -> LispObject {
     }
 }
 
-/// Sort SEQ, stably, comparing elements using PREDICATE.
-/// Returns the sorted sequence.  SEQ should be a list or vector.  SEQ is
-/// modified by side effects.  PREDICATE is called with two elements of
-/// SEQ, and should return non-nil if the first element should sort before
-/// the second.
-#[lisp_fn]
-pub fn sort(seq: LispObject, predicate: LispObject) -> LispObject {
-    if seq.is_cons() {
-        sort_list(seq, predicate)
-    } else if let Some(mut vec) = seq.as_vectorlike().and_then(|v| v.as_vector()) {
-        vec.as_mut_slice().sort_by(|&a, &b| {
-            // XXX: since the `sort' predicate is a two-outcome comparison
-            // Less/!Less, and slice::sort_by() uses Greater/!Greater
-            // (which is not guaranteed anyway), this requires two calls
-            // instead of one in some cases.
-            if !inorder(predicate, a, b) {
-                Ordering::Greater
-            } else if inorder(predicate, b, a) {
-                Ordering::Equal
-            } else {
-                Ordering::Less
-            }
-        });
-        seq
-    } else if seq.is_nil() {
-        seq
-    } else {
-        wrong_type!(Qsequencep, seq)
-    }
-}
-
 /// Return t if OBJECT is a vector.
 #[lisp_fn]
 pub fn vectorp(object: LispObject) -> bool {
@@ -0,0 +1,129 @@
+//! Weak references and ephemerons.
+//!
+//! A weak reference holds an object without keeping it from being
+//! garbage collected; once the object is garbage, the reference holds
+//! nil.  An ephemeron holds a key weakly, as a weak reference does,
+//! and a value which is kept only as long as the key is: the value is
+//! not marked through the ephemeron unless the key survives by other
+//! means, even if the value refers to the key.  Both are pseudovectors
+//! which the garbage collector in alloc.c treats specially.
+
+use std::ptr;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    lisp::defsubr,
+    lisp::{ExternalPtr, LispObject},
+    remacs_sys::{pvec_type, Lisp_Type, Lisp_Weak_Ref},
+    remacs_sys::{Qephemeron_p, Qnil, Qweak_ref_p},
+};
+
+pub type LispWeakRefRef = ExternalPtr<Lisp_Weak_Ref>;
+
+impl LispWeakRefRef {
+    fn allocate(type_: pvec_type, key: LispObject, value: LispObject) -> LispWeakRefRef {
+        let mut weak_ref =
+            LispWeakRefRef::new(allocate_pseudovector!(Lisp_Weak_Ref, next_weak, type_));
+        weak_ref.key = key;
+        weak_ref.value = value;
+        weak_ref
+    }
+}
+
+impl LispObject {
+    pub fn is_weak_ref(self) -> bool {
+        self.as_vectorlike()
+            .map_or(false, |v| v.is_pseudovector(pvec_type::PVEC_WEAK_REF))
+    }
+
+    pub fn is_ephemeron(self) -> bool {
+        self.as_vectorlike()
+            .map_or(false, |v| v.is_pseudovector(pvec_type::PVEC_EPHEMERON))
+    }
+
+    pub fn as_weak_ref_or_error(self) -> LispWeakRefRef {
+        if self.is_weak_ref() {
+            LispWeakRefRef::new(self.get_untaggedptr() as *mut Lisp_Weak_Ref)
+        } else {
+            wrong_type!(Qweak_ref_p, self);
+        }
+    }
+
+    pub fn as_ephemeron_or_error(self) -> LispWeakRefRef {
+        if self.is_ephemeron() {
+            LispWeakRefRef::new(self.get_untaggedptr() as *mut Lisp_Weak_Ref)
+        } else {
+            wrong_type!(Qephemeron_p, self);
+        }
+    }
+}
+
+impl From<LispWeakRefRef> for LispObject {
+    fn from(w: LispWeakRefRef) -> Self {
+        LispObject::tag_ptr(w, Lisp_Type::Lisp_Vectorlike)
+    }
+}
+
+/// Return a new weak reference to OBJECT.
+/// The weak reference does not keep OBJECT from being garbage
+/// collected.  Once it is, `weak-ref-get' returns nil.
+#[lisp_fn]
+pub fn make_weak_ref(object: LispObject) -> LispObject {
+    LispWeakRefRef::allocate(pvec_type::PVEC_WEAK_REF, object, Qnil).into()
+}
+
+/// Return t if OBJECT is a weak reference.
+#[lisp_fn]
+pub fn weak_ref_p(object: LispObject) -> bool {
+    object.is_weak_ref()
+}
+
+/// Return the object WEAK-REF refers to, or nil if it has been garbage
+/// collected.
+#[lisp_fn]
+pub fn weak_ref_get(weak_ref: LispObject) -> LispObject {
+    weak_ref.as_weak_ref_or_error().key
+}
+
+/// Return a new ephemeron with key KEY and value VALUE.
+///
+/// The ephemeron keeps VALUE from being garbage collected for only as
+/// long as KEY is reachable other than through the ephemeron, even if
+/// VALUE refers to KEY.  Once KEY is garbage collected, both
+/// `ephemeron-key' and `ephemeron-value' return nil.  This is what a
+/// hash table with `:weakness key' does for each of its entries.
+#[lisp_fn]
+pub fn make_ephemeron(key: LispObject, value: LispObject) -> LispObject {
+    LispWeakRefRef::allocate(pvec_type::PVEC_EPHEMERON, key, value).into()
+}
+
+/// Return t if OBJECT is an ephemeron.
+#[lisp_fn]
+pub fn ephemeron_p(object: LispObject) -> bool {
+    object.is_ephemeron()
+}
+
+/// Return the key of EPHEMERON, or nil if it has been garbage
+/// collected.
+#[lisp_fn]
+pub fn ephemeron_key(ephemeron: LispObject) -> LispObject {
+    ephemeron.as_ephemeron_or_error().key
+}
+
+/// Return the value of EPHEMERON, or nil if its key has been garbage
+/// collected.
+#[lisp_fn]
+pub fn ephemeron_value(ephemeron: LispObject) -> LispObject {
+    ephemeron.as_ephemeron_or_error().value
+}
+
+#[no_mangle]
+pub extern "C" fn syms_of_weak() {
+    def_lisp_sym!(Qweak_ref, "weak-ref");
+    def_lisp_sym!(Qweak_ref_p, "weak-ref-p");
+    def_lisp_sym!(Qephemeron, "ephemeron");
+    def_lisp_sym!(Qephemeron_p, "ephemeron-p");
+}
+
+include!(concat!(env!("OUT_DIR"), "/weak_exports.rs"));
@@ -0,0 +1,466 @@
+//! A WebSocket client.
+//!
+//! `websocket-open' connects to a server with tungstenite on a separate
+//! thread, which then parses the frames which arrive and sends the
+//! messages it is given.  The messages received are handed to their
+//! callback from a timer, so Lisp never sees a frame, only whole
+//! messages.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use libc::c_char;
+
+use tungstenite::client::AutoStream;
+use tungstenite::handshake::client::Request;
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::protocol::CloseFrame;
+use tungstenite::stream::Stream;
+use tungstenite::{Message, WebSocket};
+use url::Url;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    lisp::defsubr,
+    lisp::LispObject,
+    lists::{plist_get, LispConsCircularChecks, LispConsEndChecks},
+    obarray::intern,
+    remacs_sys::{make_unibyte_string, EmacsDouble, EmacsInt, Qnil},
+};
+
+/// The number of milliseconds between checks on the messages received,
+/// which is also how long the thread of a connection waits for one
+/// before it looks for messages to send.
+const WEBSOCKET_POLL_MS: u64 = 50;
+
+lazy_static! {
+    /// The open connections, by number.
+    static ref WEBSOCKETS: Mutex<WebSockets> = Mutex::new(WebSockets {
+        next_id: 0,
+        connections: HashMap::new(),
+    });
+}
+
+struct WebSockets {
+    next_id: EmacsInt,
+    connections: HashMap<EmacsInt, Connection>,
+}
+
+/// A connection, as the main thread sees it.
+struct Connection {
+    events: Receiver<WebSocketEvent>,
+    commands: Sender<Command>,
+    open: bool,
+}
+
+/// What the thread of a connection is asked to do.
+enum Command {
+    Send(Message),
+    Close(CloseFrame<'static>),
+}
+
+/// What the thread of a connection reports.
+enum WebSocketEvent {
+    Open,
+    Message(Message),
+    Closed(Option<u16>, String),
+    Failed(String),
+}
+
+type Socket = WebSocket<AutoStream>;
+
+/// Make reads from SOCKET time out, so that its thread gets to send
+/// messages while no message arrives.
+fn set_read_timeout(socket: &Socket) -> io::Result<()> {
+    let timeout = Some(Duration::from_millis(WEBSOCKET_POLL_MS));
+    match socket.get_ref() {
+        Stream::Plain(stream) => stream.set_read_timeout(timeout),
+        Stream::Tls(stream) => stream.get_ref().set_read_timeout(timeout),
+    }
+}
+
+fn is_timeout(error: &tungstenite::Error) -> bool {
+    match error {
+        tungstenite::Error::Io(error) => {
+            error.kind() == io::ErrorKind::WouldBlock || error.kind() == io::ErrorKind::TimedOut
+        }
+        _ => false,
+    }
+}
+
+/// Talk to the server on SOCKET until the connection is closed: report
+/// what arrives through EVENTS, and send what arrives through COMMANDS.
+/// Return the code and reason the connection was closed with.
+fn run_connection(
+    mut socket: Socket,
+    events: &Sender<WebSocketEvent>,
+    commands: &Receiver<Command>,
+) -> tungstenite::Result<(Option<u16>, String)> {
+    loop {
+        loop {
+            match commands.try_recv() {
+                Ok(Command::Send(message)) => socket.write_message(message)?,
+                Ok(Command::Close(frame)) => socket.close(Some(frame))?,
+                Err(TryRecvError::Empty) => break,
+                // Nobody listens anymore; hang up.
+                Err(TryRecvError::Disconnected) => {
+                    socket.close(None)?;
+                    return Ok((None, String::new()));
+                }
+            }
+        }
+
+        match socket.read_message() {
+            // Pings are answered by tungstenite.
+            Ok(Message::Ping(_)) | Ok(Message::Pong(_)) => (),
+            Ok(message) => {
+                if events.send(WebSocketEvent::Message(message)).is_err() {
+                    return Ok((None, String::new()));
+                }
+            }
+            Err(ref error) if is_timeout(error) => (),
+            // tungstenite answers the close frame of the server, and
+            // reports it once the server has hung up.
+            Err(tungstenite::Error::ConnectionClosed(frame)) => {
+                return Ok(frame.map_or((None, String::new()), |frame| {
+                    (Some(frame.code.into()), frame.reason.into_owned())
+                }));
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Return the request for a connection to URL, sending HEADERS and
+/// offering PROTOCOLS.
+fn client_request(
+    url: &str,
+    headers: Vec<(String, String)>,
+    protocols: Vec<String>,
+) -> Result<Request<'static>, String> {
+    let url = Url::parse(url).map_err(|error| error.to_string())?;
+    let mut request = Request::from(url);
+    for (name, value) in headers {
+        // tungstenite sends the headers as they are.
+        if name.contains(|c| c == '\r' || c == '\n' || c == ':')
+            || value.contains(|c| c == '\r' || c == '\n')
+        {
+            return Err(format!("invalid header {}", name));
+        }
+        request.add_header(Cow::from(name), Cow::from(value));
+    }
+    if !protocols.is_empty() {
+        request.add_protocol(Cow::from(protocols.join(", ")));
+    }
+    Ok(request)
+}
+
+fn unibyte_string(bytes: &[u8]) -> LispObject {
+    unsafe { make_unibyte_string(bytes.as_ptr() as *const c_char, bytes.len() as isize) }
+}
+
+/// A connection, as Lisp sees it: a list (websocket ID ON-OPEN
+/// ON-MESSAGE ON-CLOSE).
+struct Handle {
+    id: EmacsInt,
+    on_open: LispObject,
+    on_message: LispObject,
+    on_close: LispObject,
+}
+
+fn is_handle(object: LispObject) -> bool {
+    object.as_cons().map_or(false, |cons| {
+        cons.car().eq(intern("websocket").into())
+            && cons
+                .cdr()
+                .as_cons()
+                .map_or(false, |id| id.car().is_fixnum())
+    })
+}
+
+fn parse_handle(websocket: LispObject) -> Handle {
+    let fields: Vec<LispObject> = if is_handle(websocket) {
+        websocket
+            .iter_cars(LispConsEndChecks::on, LispConsCircularChecks::safe)
+            .collect()
+    } else {
+        Vec::new()
+    };
+    if fields.len() != 5 {
+        wrong_type!(intern("websocketp").into(), websocket);
+    }
+    Handle {
+        id: fields[1].as_fixnum_or_error(),
+        on_open: fields[2],
+        on_message: fields[3],
+        on_close: fields[4],
+    }
+}
+
+fn schedule_websocket_poll(websocket: LispObject) {
+    call!(
+        LispObject::from(intern("run-with-timer")),
+        LispObject::from_float(WEBSOCKET_POLL_MS as EmacsDouble / 1000.0),
+        Qnil,
+        LispObject::from(intern("websocket--poll")),
+        websocket
+    );
+}
+
+/// Open a WebSocket connection to URL, a ws: or wss: URL.
+/// Return the connection at once; it is made on a separate thread.
+/// OPTIONS is a property list of these properties:
+///
+/// :on-open is a function to call with the connection when it is open.
+///
+/// :on-message is a function to call with the connection and each
+/// message received: the text of a text message, decoded from UTF-8, or
+/// the bytes of a binary one, as a unibyte string.
+///
+/// :on-close is a function to call with the connection and the code and
+/// reason it was closed with, when it is closed.  The code is nil if the
+/// server gave none, or if the connection failed, and the reason is
+/// then a message saying why.
+///
+/// :headers is an alist of the (NAME . VALUE) strings of extra headers
+/// to send with the opening handshake.
+///
+/// :protocols is a list of the subprotocols to offer the server.
+///
+/// The callbacks are called from timers, as the messages arrive.
+/// usage: (websocket-open URL &rest OPTIONS)
+#[lisp_fn(min = "1")]
+pub fn websocket_open(args: &mut [LispObject]) -> LispObject {
+    let url = args[0].as_string_or_error().to_string();
+    let options = LispObject::from(args[1..].to_vec());
+    let option = |name| plist_get(options, intern(name).into());
+
+    let headers: Vec<(String, String)> = option(":headers")
+        .iter_cars(LispConsEndChecks::on, LispConsCircularChecks::safe)
+        .map(|header| {
+            let (name, value) = header.as_cons_or_error().as_tuple();
+            (
+                name.as_string_or_error().to_string(),
+                value.as_string_or_error().to_string(),
+            )
+        })
+        .collect();
+    let protocols: Vec<String> = option(":protocols")
+        .iter_cars(LispConsEndChecks::on, LispConsCircularChecks::safe)
+        .map(|protocol| protocol.as_string_or_error().to_string())
+        .collect();
+    let request = match client_request(&url, headers, protocols) {
+        Ok(request) => request,
+        Err(error) => error!("Invalid WebSocket request: {}", error),
+    };
+
+    let (event_sender, events) = channel();
+    let (commands, command_receiver) = channel();
+    let id = {
+        let mut websockets = WEBSOCKETS.lock().unwrap();
+        websockets.next_id += 1;
+        let id = websockets.next_id;
+        websockets.connections.insert(
+            id,
+            Connection {
+                events,
+                commands,
+                open: false,
+            },
+        );
+        id
+    };
+    thread::spawn(move || {
+        let result = tungstenite::connect(request).and_then(|(socket, _response)| {
+            set_read_timeout(&socket)?;
+            let _ = event_sender.send(WebSocketEvent::Open);
+            run_connection(socket, &event_sender, &command_receiver)
+        });
+        let event = match result {
+            Ok((code, reason)) => WebSocketEvent::Closed(code, reason),
+            Err(error) => WebSocketEvent::Failed(error.to_string()),
+        };
+        let _ = event_sender.send(event);
+    });
+
+    let websocket = list!(
+        LispObject::from(intern("websocket")),
+        LispObject::from(id),
+        option(":on-open"),
+        option(":on-message"),
+        option(":on-close")
+    );
+    schedule_websocket_poll(websocket);
+    websocket
+}
+
+/// Return t if OBJECT is a connection returned by `websocket-open'.
+#[lisp_fn]
+pub fn websocketp(object: LispObject) -> bool {
+    is_handle(object)
+}
+
+/// Return t if the connection WEBSOCKET is open.
+/// It isn't until its :on-open callback is called, nor once it is
+/// closed.
+#[lisp_fn]
+pub fn websocket_openp(websocket: LispObject) -> bool {
+    let id = parse_handle(websocket).id;
+    WEBSOCKETS
+        .lock()
+        .unwrap()
+        .connections
+        .get(&id)
+        .map_or(false, |connection| connection.open)
+}
+
+/// Hand what the connection WEBSOCKET received to its callbacks.
+#[lisp_fn(name = "websocket--poll")]
+pub fn websocket_poll(websocket: LispObject) {
+    let handle = parse_handle(websocket);
+    if !WEBSOCKETS
+        .lock()
+        .unwrap()
+        .connections
+        .contains_key(&handle.id)
+    {
+        return;
+    }
+    // Check again even if a callback signals an error.
+    schedule_websocket_poll(websocket);
+
+    loop {
+        // Events are taken one at a time, so that none is lost if a
+        // callback signals an error, and the connection is not locked
+        // while callbacks run.
+        let event = {
+            let mut websockets = WEBSOCKETS.lock().unwrap();
+            let connection = match websockets.connections.get_mut(&handle.id) {
+                Some(connection) => connection,
+                None => return,
+            };
+            match connection.events.try_recv() {
+                Ok(event) => {
+                    match event {
+                        WebSocketEvent::Open => connection.open = true,
+                        WebSocketEvent::Closed(..) | WebSocketEvent::Failed(_) => {
+                            websockets.connections.remove(&handle.id);
+                        }
+                        WebSocketEvent::Message(_) => (),
+                    }
+                    event
+                }
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    websockets.connections.remove(&handle.id);
+                    WebSocketEvent::Failed("Connection thread exited".to_string())
+                }
+            }
+        };
+
+        match event {
+            WebSocketEvent::Open => {
+                if handle.on_open.is_not_nil() {
+                    call!(handle.on_open, websocket);
+                }
+            }
+            WebSocketEvent::Message(message) => {
+                let data = match message {
+                    Message::Text(ref text) => LispObject::from(text.as_str()),
+                    message => unibyte_string(&message.into_data()),
+                };
+                if handle.on_message.is_not_nil() {
+                    call!(handle.on_message, websocket, data);
+                }
+            }
+            WebSocketEvent::Closed(code, reason) => {
+                if handle.on_close.is_not_nil() {
+                    call!(
+                        handle.on_close,
+                        websocket,
+                        code.map_or(Qnil, |code| LispObject::from(EmacsInt::from(code))),
+                        LispObject::from(reason.as_str())
+                    );
+                }
+                return;
+            }
+            WebSocketEvent::Failed(error) => {
+                if handle.on_close.is_not_nil() {
+                    call!(
+                        handle.on_close,
+                        websocket,
+                        Qnil,
+                        LispObject::from(error.as_str())
+                    );
+                }
+                return;
+            }
+        }
+    }
+}
+
+/// Queue COMMAND for the thread of the connection WEBSOCKET.
+fn send_command(websocket: LispObject, command: Command) {
+    let id = parse_handle(websocket).id;
+    let sent = WEBSOCKETS
+        .lock()
+        .unwrap()
+        .connections
+        .get(&id)
+        .filter(|connection| connection.open)
+        .map_or(false, |connection| {
+            connection.commands.send(command).is_ok()
+        });
+    if !sent {
+        error!("WebSocket connection is not open");
+    }
+}
+
+/// Send DATA, a string, over the connection WEBSOCKET.
+/// It is sent as a text message, unless BINARY is non-nil, when it is
+/// sent as a binary message of the bytes of DATA.  Signal an error if
+/// WEBSOCKET is not open.
+#[lisp_fn(min = "2")]
+pub fn websocket_send(websocket: LispObject, data: LispObject, binary: bool) {
+    let data = data.as_string_or_error();
+    let message = if binary {
+        Message::Binary(data.as_slice().to_vec())
+    } else {
+        Message::Text(data.to_string())
+    };
+    send_command(websocket, Command::Send(message));
+}
+
+/// Close the connection WEBSOCKET, with CODE and REASON.
+/// CODE is the status code to send, 1000, normal closure, by default,
+/// and REASON a string explaining it.  The :on-close callback of
+/// WEBSOCKET is called once the server has answered.  Signal an error
+/// if WEBSOCKET is not open.
+#[lisp_fn(min = "1")]
+pub fn websocket_close(websocket: LispObject, code: LispObject, reason: LispObject) {
+    let code = if code.is_nil() {
+        CloseCode::Normal
+    } else {
+        CloseCode::from(code.as_fixnum_or_error() as u16)
+    };
+    let reason = if reason.is_nil() {
+        String::new()
+    } else {
+        reason.as_string_or_error().to_string()
+    };
+    send_command(
+        websocket,
+        Command::Close(CloseFrame {
+            code,
+            reason: reason.into(),
+        }),
+    );
+}
+
+include!(concat!(env!("OUT_DIR"), "/websocket_exports.rs"));
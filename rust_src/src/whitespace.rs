@@ -0,0 +1,355 @@
+//! Normalizing whitespace.
+//!
+//! `delete-trailing-whitespace', `tabify' and `untabify' search the
+//! region with regexps and edit it match by match.  The functions here
+//! find all the changes to make to a region in one pass over its text,
+//! and then make them from the end of the region backwards, so that the
+//! positions they found stay valid.  This is fast enough to use on large
+//! files in `before-save-hook'.
+
+use libc::ptrdiff_t;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    buffers::validate_region,
+    editfns::{
+        buffer_substring_no_properties, delete_region, goto_char, insert_char, save_excursion_save,
+    },
+    eval::unbind_to,
+    lisp::defsubr,
+    lisp::LispObject,
+    marker::buf_charpos_to_bytepos,
+    multibyte::{multibyte_char_at, Codepoint},
+    remacs_sys::{current_column, record_unwind_protect, save_excursion_restore},
+    remacs_sys::{syntax_property, syntaxcode, EmacsInt, Fchar_width, Qnil},
+    threads::{c_specpdl_index, ThreadState},
+};
+
+const TAB: Codepoint = b'\t' as Codepoint;
+const SPACE: Codepoint = b' ' as Codepoint;
+const NEWLINE: Codepoint = b'\n' as Codepoint;
+const FORMFEED: Codepoint = 0x0C;
+
+/// A change to the whitespace of a text: the characters from START to
+/// END, counted from the start of the text, are replaced by TABS tabs
+/// followed by SPACES spaces.
+#[derive(Debug, PartialEq)]
+struct Respace {
+    start: usize,
+    end: usize,
+    tabs: usize,
+    spaces: usize,
+}
+
+impl Respace {
+    fn deletion(start: usize, end: usize) -> Self {
+        Respace {
+            start,
+            end,
+            tabs: 0,
+            spaces: 0,
+        }
+    }
+}
+
+/// Return the column after the character C, which starts at COLUMN.
+/// WIDTH gives the width of characters other than tabs and newlines.
+fn next_column<W>(column: usize, c: Codepoint, tab_width: usize, width: &W) -> usize
+where
+    W: Fn(Codepoint) -> usize,
+{
+    match c {
+        TAB => (column / tab_width + 1) * tab_width,
+        NEWLINE => 0,
+        _ => column + width(c),
+    }
+}
+
+/// Return the deletions of the whitespace at the ends of the lines of
+/// TEXT, where IS_WHITESPACE tells which characters are whitespace.  The
+/// last line of TEXT only counts if END_AT_EOL, that is, if TEXT is
+/// followed by the end of a line.
+fn trailing_whitespace<F>(text: &[Codepoint], end_at_eol: bool, is_whitespace: F) -> Vec<Respace>
+where
+    F: Fn(Codepoint) -> bool,
+{
+    let mut eols: Vec<usize> = text
+        .iter()
+        .enumerate()
+        .filter(|&(_, &c)| c == NEWLINE)
+        .map(|(i, _)| i)
+        .collect();
+    if end_at_eol {
+        eols.push(text.len());
+    }
+
+    let mut deletions = Vec::new();
+    let mut bol = 0;
+    for eol in eols {
+        let start = text[bol..eol]
+            .iter()
+            .rposition(|&c| !is_whitespace(c))
+            .map_or(bol, |i| bol + i + 1);
+        if start < eol {
+            deletions.push(Respace::deletion(start, eol));
+        }
+        bol = eol + 1;
+    }
+    deletions
+}
+
+/// Return the replacements of the tabs in TEXT by spaces, which take up
+/// the same columns.  TEXT starts at START_COLUMN, and WIDTH gives the
+/// width of characters other than tabs and newlines.
+fn untabify_changes<W>(
+    text: &[Codepoint],
+    start_column: usize,
+    tab_width: usize,
+    width: W,
+) -> Vec<Respace>
+where
+    W: Fn(Codepoint) -> usize,
+{
+    let mut changes = Vec::new();
+    let mut column = start_column;
+    let mut i = 0;
+    while i < text.len() {
+        if text[i] != TAB {
+            column = next_column(column, text[i], tab_width, &width);
+            i += 1;
+            continue;
+        }
+        let (start, start_column) = (i, column);
+        while i < text.len() && text[i] == TAB {
+            column = next_column(column, TAB, tab_width, &width);
+            i += 1;
+        }
+        changes.push(Respace {
+            start,
+            end: i,
+            tabs: 0,
+            spaces: column - start_column,
+        });
+    }
+    changes
+}
+
+/// Return the replacements of the runs of whitespace in TEXT by tabs
+/// and spaces which take up the same columns, as `tabify' makes them:
+/// a run is a space followed by spaces and tabs, and it is only replaced
+/// if it reaches a tab stop.  TEXT starts at START_COLUMN, and WIDTH
+/// gives the width of characters other than tabs and newlines.
+fn tabify_changes<W>(
+    text: &[Codepoint],
+    start_column: usize,
+    tab_width: usize,
+    width: W,
+) -> Vec<Respace>
+where
+    W: Fn(Codepoint) -> usize,
+{
+    let mut changes = Vec::new();
+    let mut column = start_column;
+    let mut i = 0;
+    while i < text.len() {
+        if text[i] != SPACE {
+            column = next_column(column, text[i], tab_width, &width);
+            i += 1;
+            continue;
+        }
+        let (start, start_column) = (i, column);
+        while i < text.len() && (text[i] == SPACE || text[i] == TAB) {
+            column = next_column(column, text[i], tab_width, &width);
+            i += 1;
+        }
+        if i - start >= 2 && column / tab_width != start_column / tab_width {
+            changes.push(Respace {
+                start,
+                end: i,
+                tabs: column / tab_width - start_column / tab_width,
+                spaces: column % tab_width,
+            });
+        }
+    }
+    changes
+}
+
+/// Return the width of tabs in the current buffer, as `tab-width' gives
+/// it, or 8 if that is unreasonable.
+fn tab_width() -> usize {
+    ThreadState::current_buffer()
+        .tab_width_
+        .as_fixnum()
+        .filter(|&width| 0 < width && width <= 1000)
+        .map_or(8, |width| width as usize)
+}
+
+/// Return the number of columns the character C takes up in the current
+/// buffer.  Text properties and overlays are not taken into account.
+fn char_width(c: Codepoint) -> usize {
+    if c >= SPACE && c < 0x7F {
+        1
+    } else {
+        unsafe { Fchar_width(LispObject::from(c)) }.as_fixnum_or_error() as usize
+    }
+}
+
+/// Return the bounds of the region between START and END, in order, and
+/// its text.  The text is read from the buffer without making a string.
+fn region_text(start: LispObject, end: LispObject) -> (EmacsInt, EmacsInt, Vec<Codepoint>) {
+    let (mut start, mut end) = (start, end);
+    unsafe { validate_region(&mut start, &mut end) };
+    let (start, end) = (start.as_fixnum_or_error(), end.as_fixnum_or_error());
+
+    let mut buffer = ThreadState::current_buffer();
+    let start_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), start as ptrdiff_t) };
+    let end_byte = unsafe { buf_charpos_to_bytepos(buffer.as_mut(), end as ptrdiff_t) };
+    let bytes = buffer.text_between(start_byte, end_byte);
+    let mut text = Vec::with_capacity((end - start) as usize);
+    if buffer.multibyte_characters_enabled() {
+        let mut i = 0;
+        while i < bytes.len() {
+            let (c, len) = multibyte_char_at(&bytes[i..]);
+            text.push(c);
+            i += len;
+        }
+    } else {
+        text.extend(bytes.iter().map(|&b| Codepoint::from(b)));
+    }
+    (start, end, text)
+}
+
+/// Return the column of position POS in the current buffer.
+fn column_at(pos: EmacsInt) -> usize {
+    goto_char(pos.into());
+    unsafe { current_column() as usize }
+}
+
+/// Make the CHANGES to the text which starts at position START, saving
+/// point.  The changes must be in order and must not overlap.
+fn apply_changes(start: EmacsInt, changes: &[Respace]) {
+    let count = c_specpdl_index();
+    unsafe { record_unwind_protect(Some(save_excursion_restore), save_excursion_save()) };
+
+    for change in changes.iter().rev() {
+        let from = start + change.start as EmacsInt;
+        delete_region(from.into(), (start + change.end as EmacsInt).into());
+        goto_char(from.into());
+        if change.tabs > 0 {
+            insert_char(TAB, Some(change.tabs as EmacsInt), false);
+        }
+        if change.spaces > 0 {
+            insert_char(SPACE, Some(change.spaces as EmacsInt), false);
+        }
+    }
+
+    unbind_to(count, Qnil);
+}
+
+/// Delete the whitespace at the ends of the lines between START and END,
+/// and return the number of lines it was deleted from.
+/// Whitespace is as the syntax table has it, except that form feeds are
+/// kept, as `delete-trailing-whitespace' keeps them.  Whitespace before
+/// END is only deleted if END is at the end of a line.  Unlike
+/// `delete-trailing-whitespace', this makes a single pass over the region,
+/// and never deletes lines at the end of the buffer.
+#[lisp_fn]
+pub fn delete_trailing_whitespace_region(start: LispObject, end: LispObject) -> EmacsInt {
+    let (start, end, text) = region_text(start, end);
+    let buffer = ThreadState::current_buffer();
+    let end_at_eol = end == buffer.zv as EmacsInt
+        || buffer_substring_no_properties(end.into(), (end + 1).into())
+            .as_string_or_error()
+            .chars()
+            .next()
+            == Some(NEWLINE);
+    let changes = trailing_whitespace(&text, end_at_eol, |c| {
+        c != FORMFEED && unsafe { syntax_property(c as i32, false) } == syntaxcode::Swhitespace
+    });
+    apply_changes(start, &changes);
+    changes.len() as EmacsInt
+}
+
+/// Convert all tabs in the region to multiple spaces, preserving columns.
+/// This does the work of `untabify' in a single pass over the region
+/// between START and END.  Columns are worked out from the characters
+/// alone, as `char-width' gives them, ignoring invisible text and
+/// `display' properties.
+#[lisp_fn]
+pub fn untabify_region_fast(start: LispObject, end: LispObject) {
+    let (start, _, text) = region_text(start, end);
+    let changes = untabify_changes(&text, column_at(start), tab_width(), char_width);
+    apply_changes(start, &changes);
+}
+
+/// Convert multiple spaces in the region to tabs when possible.
+/// This does the work of `tabify' with its default `tabify-regexp', in a
+/// single pass over the region between START and END: a run of
+/// whitespace which starts with a space is replaced by tabs and spaces if
+/// it reaches a tab stop.  Columns are worked out from the characters
+/// alone, as `char-width' gives them, ignoring invisible text and
+/// `display' properties.
+#[lisp_fn]
+pub fn tabify_region_fast(start: LispObject, end: LispObject) {
+    let (start, _, text) = region_text(start, end);
+    let changes = tabify_changes(&text, column_at(start), tab_width(), char_width);
+    apply_changes(start, &changes);
+}
+
+#[cfg(test)]
+fn codepoints(s: &str) -> Vec<Codepoint> {
+    s.chars().map(|c| c as Codepoint).collect()
+}
+
+#[test]
+fn test_trailing_whitespace() {
+    let is_blank = |c| c == SPACE || c == TAB;
+    let text = codepoints("a \t\nb\n  \nc  ");
+    assert_eq!(
+        trailing_whitespace(&text, false, is_blank),
+        vec![Respace::deletion(1, 3), Respace::deletion(6, 8)]
+    );
+    assert_eq!(
+        trailing_whitespace(&text, true, is_blank).last(),
+        Some(&Respace::deletion(10, 12))
+    );
+}
+
+#[test]
+fn test_untabify_and_tabify_changes() {
+    let width = |_| 1;
+    let text = codepoints("ab\t\tc\n\tx");
+    assert_eq!(
+        untabify_changes(&text, 0, 4, width),
+        vec![
+            Respace {
+                start: 2,
+                end: 4,
+                tabs: 0,
+                spaces: 6
+            },
+            Respace {
+                start: 6,
+                end: 7,
+                tabs: 0,
+                spaces: 4
+            },
+        ]
+    );
+
+    // The first run ends before a tab stop, and the second is a single
+    // space.
+    let text = codepoints("a  b      c d\t");
+    assert_eq!(
+        tabify_changes(&text, 0, 4, width),
+        vec![Respace {
+            start: 4,
+            end: 10,
+            tabs: 1,
+            spaces: 2
+        }]
+    );
+}
+
+include!(concat!(env!("OUT_DIR"), "/whitespace_exports.rs"));
@@ -0,0 +1,383 @@
+//! Extended attributes and access control lists of files.
+//!
+//! Extended attributes carry metadata which isn't in `file-attributes',
+//! like the quarantine flag of files downloaded on macOS or the SELinux
+//! context of a file.  `file-extended-attributes' includes them, so that
+//! backups and copies made by Emacs keep them.  Files only have them
+//! on Unix; elsewhere, the functions here act as on a file system
+//! without them.
+
+#[cfg(unix)]
+use std::ffi::OsStr;
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::io;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+#[cfg(unix)]
+use std::path::PathBuf;
+
+#[cfg(unix)]
+use errno::{set_errno, Errno};
+use libc::c_char;
+
+use remacs_macros::lisp_fn;
+
+#[cfg(unix)]
+use crate::remacs_sys::{encode_file_name, report_file_error};
+use crate::{
+    lisp::defsubr,
+    lisp::LispObject,
+    multibyte::LispStringRef,
+    obarray::intern,
+    remacs_sys::{make_unibyte_string, EmacsInt},
+    remacs_sys::{Fexpand_file_name, Ffind_file_name_handler, Qnil},
+    threads::ThreadState,
+};
+
+/// Expand FILENAME, and return it with the handler for OPERATION on it,
+/// if it has one.
+fn expand_with_handler(filename: LispStringRef, operation: &str) -> (LispObject, LispObject) {
+    let directory = ThreadState::current_buffer().directory_;
+    let absname = unsafe { Fexpand_file_name(filename.into(), directory) };
+    let handler = unsafe { Ffind_file_name_handler(absname, intern(operation).into()) };
+    (absname, handler)
+}
+
+#[cfg(unix)]
+fn system_path(absname: LispObject) -> PathBuf {
+    let encoded = unsafe { encode_file_name(absname) };
+    PathBuf::from(OsStr::from_bytes(encoded.as_string_or_error().as_slice()))
+}
+
+fn unibyte_string(bytes: &[u8]) -> LispObject {
+    unsafe { make_unibyte_string(bytes.as_ptr() as *const c_char, bytes.len() as isize) }
+}
+
+/// Return true if ERROR means the file system has no extended attributes.
+#[cfg(unix)]
+fn unsupported(error: &io::Error) -> bool {
+    !xattr_crate::SUPPORTED_PLATFORM || error.raw_os_error() == Some(libc::ENOTSUP)
+}
+
+/// Return the value of the extended attribute NAME of file FILENAME.
+/// The value is a unibyte string.  Return nil if FILENAME has no such
+/// attribute, if it does not exist or is not accessible, or if its file
+/// system has no extended attributes.
+#[lisp_fn]
+pub fn file_xattr(filename: LispStringRef, name: LispStringRef) -> LispObject {
+    let (absname, handler) = expand_with_handler(filename, "file-xattr");
+    if handler.is_not_nil() {
+        return call!(
+            handler,
+            LispObject::from(intern("file-xattr")),
+            absname,
+            name.into()
+        );
+    }
+
+    #[cfg(unix)]
+    {
+        let name = OsStr::from_bytes(name.as_slice());
+        match xattr_crate::get(system_path(absname), name) {
+            Ok(Some(value)) => unibyte_string(&value),
+            _ => Qnil,
+        }
+    }
+    #[cfg(windows)]
+    {
+        Qnil
+    }
+}
+
+/// Return the names of the extended attributes of file FILENAME.
+/// Return nil if FILENAME does not exist or is not accessible, or if its
+/// file system has no extended attributes.
+#[lisp_fn]
+pub fn file_xattrs(filename: LispStringRef) -> LispObject {
+    let (absname, handler) = expand_with_handler(filename, "file-xattrs");
+    if handler.is_not_nil() {
+        return call!(handler, LispObject::from(intern("file-xattrs")), absname);
+    }
+
+    #[cfg(unix)]
+    {
+        match xattr_crate::list(system_path(absname)) {
+            Ok(names) => names
+                .map(|name| unibyte_string(name.as_bytes()))
+                .collect::<Vec<LispObject>>()
+                .into(),
+            Err(_) => Qnil,
+        }
+    }
+    #[cfg(windows)]
+    {
+        Qnil
+    }
+}
+
+/// Set the extended attribute NAME of file FILENAME to VALUE.
+/// VALUE should be a unibyte string; if it is nil, the attribute is
+/// removed.  Value is t if the attribute was set or removed, and nil if
+/// the file system of FILENAME has no extended attributes.  Signal an
+/// error if the attribute can't be set, for instance because setting
+/// attributes outside the `user' namespace needs privileges.
+#[lisp_fn]
+pub fn set_file_xattr(filename: LispStringRef, name: LispStringRef, value: LispObject) -> bool {
+    let (absname, handler) = expand_with_handler(filename, "set-file-xattr");
+    if handler.is_not_nil() {
+        return call!(
+            handler,
+            LispObject::from(intern("set-file-xattr")),
+            absname,
+            name.into(),
+            value
+        )
+        .is_not_nil();
+    }
+
+    #[cfg(unix)]
+    {
+        let path = system_path(absname);
+        let name = OsStr::from_bytes(name.as_slice());
+        let result = if value.is_nil() {
+            match xattr_crate::remove(&path, name) {
+                // An attribute which isn't there is as good as removed.
+                Err(_) if xattr_crate::get(&path, name).ok() == Some(None) => Ok(()),
+                result => result,
+            }
+        } else {
+            xattr_crate::set(&path, name, value.as_string_or_error().as_slice())
+        };
+        match result {
+            Ok(()) => true,
+            Err(ref error) if unsupported(error) => false,
+            Err(error) => unsafe {
+                set_errno(Errno(error.raw_os_error().unwrap_or(libc::EIO)));
+                report_file_error(
+                    "Setting extended attribute\0".as_ptr() as *const c_char,
+                    absname,
+                )
+            },
+        }
+    }
+    #[cfg(windows)]
+    {
+        false
+    }
+}
+
+/// The extended attributes holding the POSIX ACLs of files on
+/// GNU/Linux.
+#[cfg(unix)]
+const ACL_ACCESS_XATTR: &str = "system.posix_acl_access";
+#[cfg(unix)]
+const ACL_DEFAULT_XATTR: &str = "system.posix_acl_default";
+
+/// The version of the format of ACLs in extended attributes.
+#[cfg(unix)]
+const ACL_XATTR_VERSION: u32 = 2;
+
+/// The kinds of ACL entries, as `acl.h' has them.
+const ACL_USER_OBJ: u16 = 0x01;
+const ACL_USER: u16 = 0x02;
+const ACL_GROUP_OBJ: u16 = 0x04;
+const ACL_GROUP: u16 = 0x08;
+const ACL_MASK: u16 = 0x10;
+const ACL_OTHER: u16 = 0x20;
+
+/// An entry of a POSIX ACL: who it is for, their user or group ID if it
+/// is for a named user or group, and the permissions it gives, as `rwx'
+/// bits.
+#[derive(Debug, PartialEq)]
+struct AclEntry {
+    tag: u16,
+    id: Option<u32>,
+    perms: u16,
+}
+
+impl AclEntry {
+    fn to_lisp(&self) -> LispObject {
+        let tag = match self.tag {
+            ACL_USER_OBJ | ACL_USER => "user",
+            ACL_GROUP_OBJ | ACL_GROUP => "group",
+            ACL_MASK => "mask",
+            _ => "other",
+        };
+        let mut perms = *b"---";
+        for (i, (&bit, &c)) in [4, 2, 1].iter().zip(b"rwx").enumerate() {
+            if self.perms & bit != 0 {
+                perms[i] = c;
+            }
+        }
+        list!(
+            LispObject::from(intern(tag)),
+            self.id
+                .map_or(Qnil, |id| LispObject::from(EmacsInt::from(id))),
+            unibyte_string(&perms)
+        )
+    }
+}
+
+#[cfg(unix)]
+fn read_u16(bytes: &[u8]) -> u16 {
+    u16::from(bytes[0]) | u16::from(bytes[1]) << 8
+}
+
+#[cfg(unix)]
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from(read_u16(bytes)) | u32::from(read_u16(&bytes[2..])) << 16
+}
+
+/// Parse the ACL VALUE of a POSIX ACL extended attribute, which is a
+/// version number followed by entries of a tag, permissions and an ID,
+/// in little-endian order.  Return None if it isn't in that format.
+#[cfg(unix)]
+fn parse_acl_xattr(value: &[u8]) -> Option<Vec<AclEntry>> {
+    if value.len() < 4 || (value.len() - 4) % 8 != 0 || read_u32(value) != ACL_XATTR_VERSION {
+        return None;
+    }
+    let entries = value[4..]
+        .chunks(8)
+        .map(|entry| {
+            let tag = read_u16(entry);
+            AclEntry {
+                tag,
+                id: match tag {
+                    ACL_USER | ACL_GROUP => Some(read_u32(&entry[4..])),
+                    _ => None,
+                },
+                perms: read_u16(&entry[2..]),
+            }
+        })
+        .collect();
+    Some(entries)
+}
+
+/// Return the ACL which is the same as the permissions MODE of a file.
+#[cfg(unix)]
+fn acl_from_mode(mode: u32) -> Vec<AclEntry> {
+    [(ACL_USER_OBJ, 6), (ACL_GROUP_OBJ, 3), (ACL_OTHER, 0)]
+        .iter()
+        .map(|&(tag, shift)| AclEntry {
+            tag,
+            id: None,
+            perms: (mode >> shift) as u16 & 0o7,
+        })
+        .collect()
+}
+
+/// Return the entries of the ACL of file FILENAME, as a list.
+/// Each entry has the form (TAG ID PERMISSIONS), where TAG is one of
+/// `user', `group', `mask' and `other', ID is the user or group ID the
+/// entry is for, or nil for the owner and group of the file and for the
+/// other entries, and PERMISSIONS is a string like \"rw-\".  A file with
+/// no ACL beyond its permissions has just the entries of its owner,
+/// group and others.
+///
+/// If DEFAULT is non-nil, return the default ACL of FILENAME instead,
+/// which files created in it inherit, if it is a directory with one.
+/// Return nil if FILENAME does not exist or is not accessible, if it has
+/// no default ACL and DEFAULT is non-nil, or if the ACL can't be read,
+/// which is always the case on systems other than GNU/Linux.
+#[lisp_fn(min = "1")]
+pub fn file_acl_entries(filename: LispStringRef, default: bool) -> LispObject {
+    let (absname, handler) = expand_with_handler(filename, "file-acl-entries");
+    if handler.is_not_nil() {
+        return call!(
+            handler,
+            LispObject::from(intern("file-acl-entries")),
+            absname,
+            LispObject::from(default)
+        );
+    }
+
+    match acl_entries(absname, default) {
+        Some(entries) => entries
+            .iter()
+            .map(AclEntry::to_lisp)
+            .collect::<Vec<LispObject>>()
+            .into(),
+        None => Qnil,
+    }
+}
+
+/// Return the entries of the ACL of the file ABSNAME, or of its default
+/// ACL if DEFAULT is true, or None if it can't be read.
+#[cfg(unix)]
+fn acl_entries(absname: LispObject, default: bool) -> Option<Vec<AclEntry>> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    let path = system_path(absname);
+    let name = if default {
+        ACL_DEFAULT_XATTR
+    } else {
+        ACL_ACCESS_XATTR
+    };
+    let value = match xattr_crate::get(&path, name) {
+        Ok(value) => value,
+        Err(ref error) if unsupported(error) => None,
+        Err(_) => return None,
+    };
+    match value {
+        Some(value) => parse_acl_xattr(&value),
+        // Without an extended ACL, the access ACL is just the permissions
+        // of the file.
+        None if !default => fs::metadata(&path)
+            .ok()
+            .map(|metadata| acl_from_mode(metadata.mode())),
+        None => None,
+    }
+}
+
+#[cfg(windows)]
+fn acl_entries(_absname: LispObject, _default: bool) -> Option<Vec<AclEntry>> {
+    None
+}
+
+#[cfg(unix)]
+#[test]
+fn test_parse_acl_xattr() {
+    let mut value = vec![2, 0, 0, 0];
+    for &(tag, perms, id) in &[
+        (ACL_USER_OBJ, 6u16, u32::max_value()),
+        (ACL_USER, 4, 1000),
+        (ACL_MASK, 4, u32::max_value()),
+    ] {
+        value.extend_from_slice(&[tag as u8, (tag >> 8) as u8, perms as u8, 0]);
+        value.extend_from_slice(&[
+            id as u8,
+            (id >> 8) as u8,
+            (id >> 16) as u8,
+            (id >> 24) as u8,
+        ]);
+    }
+    assert_eq!(
+        parse_acl_xattr(&value),
+        Some(vec![
+            AclEntry {
+                tag: ACL_USER_OBJ,
+                id: None,
+                perms: 6
+            },
+            AclEntry {
+                tag: ACL_USER,
+                id: Some(1000),
+                perms: 4
+            },
+            AclEntry {
+                tag: ACL_MASK,
+                id: None,
+                perms: 4
+            },
+        ])
+    );
+    assert_eq!(parse_acl_xattr(&value[..7]), None);
+    assert_eq!(acl_from_mode(0o640)[1].perms, 4);
+}
+
+include!(concat!(env!("OUT_DIR"), "/xattr_exports.rs"));
@@ -0,0 +1,102 @@
+//! YAML parsing and serialization.
+//!
+//! YAML documents are converted through the same value representation
+//! as JSON, so the keyword arguments and the Lisp representation of
+//! the data are those of the JSON functions.
+
+use serde_json::Value;
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    eval::{define_error, put},
+    json::{json_encode, json_make_string, json_to_lisp, lisp_to_json, JsonConfig},
+    lisp::defsubr,
+    lisp::LispObject,
+    remacs_sys::{
+        Qerror, Qpure, Qside_effect_free, Qstring_without_embedded_nulls_p, Qt, Qyaml_error,
+        Qyaml_parse_error, Qyaml_parse_string, Qyaml_serialize, Qyaml_value_p,
+    },
+};
+
+/// Return the YAML representation of OBJECT as a string.
+///
+/// OBJECT can be any value accepted by `json-serialize', or a single
+/// scalar: the Lisp equivalents to the null and false values, t, a
+/// number or a string.  Vectors become YAML sequences, whereas
+/// hashtables, alists and plists become YAML mappings.  The result is
+/// a complete YAML document, beginning with the "---" marker.
+///
+/// The arguments ARGS are a list of keyword/argument pairs, as for
+/// `json-serialize'.
+/// usage: (yaml-serialize OBJECT &rest ARGS)
+#[lisp_fn(min = "1")]
+pub fn yaml_serialize(args: &mut [LispObject]) -> LispObject {
+    let config = JsonConfig::from_args(&args[1..], false);
+    let value = lisp_to_json(args[0], &config);
+    match serde_yaml::to_string(&value) {
+        Ok(yaml) => json_make_string(&yaml),
+        Err(_) => wrong_type!(Qyaml_value_p, args[0]),
+    }
+}
+
+/// Parse the YAML STRING into a Lisp object.
+/// This is essentially the reverse operation of `yaml-serialize', which
+/// see.  STRING must contain a single YAML document.  Sequences are
+/// returned as vectors and mappings as hashtables, alists, or plists;
+/// keys which aren't strings in the document, such as numbers, are
+/// converted to their textual form, and aliases are replaced by the
+/// node they refer to.  If STRING isn't a valid YAML document, an error
+/// of type `yaml-parse-error' is signaled, whose data are the message,
+/// line and column reported by the parser.
+///
+/// The arguments ARGS are a list of keyword/argument pairs, as for
+/// `json-parse-string', including `:object-type'.
+/// usage: (yaml-parse-string STRING &rest ARGS)
+#[lisp_fn(min = "1")]
+pub fn yaml_parse_string(args: &mut [LispObject]) -> LispObject {
+    let string = json_encode(args[0].as_string_or_error());
+    if string.contains('\0') {
+        wrong_type!(Qstring_without_embedded_nulls_p, args[0]);
+    }
+    let config = JsonConfig::from_args(&args[1..], true);
+
+    let value: Value = match serde_yaml::from_str(&string) {
+        Ok(value) => value,
+        Err(error) => {
+            let (line, column) = error
+                .location()
+                .map_or((0, 0), |location| (location.line(), location.column()));
+            xsignal!(
+                Qyaml_parse_error,
+                json_make_string(&error.to_string()),
+                LispObject::from(line),
+                LispObject::from(column)
+            );
+        }
+    };
+    json_to_lisp(value, &config)
+}
+
+#[no_mangle]
+pub extern "C" fn syms_of_yaml() {
+    def_lisp_sym!(Qyaml_value_p, "yaml-value-p");
+
+    def_lisp_sym!(Qyaml_error, "yaml-error");
+    def_lisp_sym!(Qyaml_parse_error, "yaml-parse-error");
+    define_error(Qyaml_error, "generic YAML error", Qerror);
+    define_error(
+        Qyaml_parse_error,
+        "could not parse YAML document",
+        Qyaml_error,
+    );
+
+    def_lisp_sym!(Qyaml_serialize, "yaml-serialize");
+    def_lisp_sym!(Qyaml_parse_string, "yaml-parse-string");
+    put(Qyaml_serialize.into(), Qpure, Qt);
+    put(Qyaml_serialize.into(), Qside_effect_free, Qt);
+    put(Qyaml_parse_string.into(), Qpure, Qt);
+    put(Qyaml_parse_string.into(), Qside_effect_free, Qt);
+}
+
+include!(concat!(env!("OUT_DIR"), "/yaml_exports.rs"));
@@ -559,6 +559,10 @@ static struct Lisp_Finalizer finalizers;
    running finalizers.  */
 static struct Lisp_Finalizer doomed_finalizers;
 
+/* Head of the list of weak references and ephemerons marked during
+   the current GC, chained through their next_weak fields.  */
+static struct Lisp_Weak_Ref *weak_refs;
+
 
 /************************************************************************
 				Malloc
@@ -3136,6 +3140,8 @@ cleanup_vector (struct Lisp_Vector *vector)
     finalize_one_mutex ((struct Lisp_Mutex *) vector);
   else if (PSEUDOVECTOR_TYPEP (&vector->header, PVEC_CONDVAR))
     finalize_one_condvar ((struct Lisp_CondVar *) vector);
+  else if (PSEUDOVECTOR_TYPEP (&vector->header, PVEC_TYPED_VECTOR))
+    free_typed_vector ((struct Lisp_Typed_Vector *) vector);
 }
 
 /* Reclaim space used by unmarked vectors.  */
@@ -5889,6 +5895,7 @@ garbage_collect_1 (void *end)
   mark_terminals ();
   mark_kboards ();
   mark_threads ();
+  mark_persistent ();
 
 #ifdef USE_GTK
   xg_mark_data ();
@@ -6498,6 +6505,19 @@ mark_object (Lisp_Object arg)
 	    }
 	    break;
 
+	  case PVEC_WEAK_REF:
+	  case PVEC_EPHEMERON:
+	    {
+	      struct Lisp_Weak_Ref *w = (struct Lisp_Weak_Ref *) ptr;
+
+	      /* Mark neither the key nor the value here; see
+		 mark_ephemerons and sweep_weak_refs.  */
+	      VECTOR_MARK (ptr);
+	      w->next_weak = weak_refs;
+	      weak_refs = w;
+	    }
+	    break;
+
 	  case PVEC_CHAR_TABLE:
 	  case PVEC_SUB_CHAR_TABLE:
 	    mark_char_table (ptr, (enum pvec_type) pvectype);
@@ -6709,6 +6729,44 @@ survives_gc_p (Lisp_Object obj)
   return survives_p || PURE_P (XPNTR (obj));
 }
 
+/* Mark the values of the ephemerons marked so far whose keys survive
+   the current GC.  Value is true if anything was marked, in which case
+   more keys may survive, so this is called until it returns false,
+   together with the marking of weak hash tables.  */
+
+bool
+mark_ephemerons (void)
+{
+  bool marked = false;
+
+  for (struct Lisp_Weak_Ref *w = weak_refs; w; w = w->next_weak)
+    if (PSEUDOVECTOR_TYPEP (&w->header, PVEC_EPHEMERON)
+	&& survives_gc_p (w->key) && !survives_gc_p (w->value))
+      {
+	mark_object (w->value);
+	marked = true;
+      }
+
+  return marked;
+}
+
+/* Clear the weak references and ephemerons whose keys don't survive
+   the current GC.  Called from gc_sweep, after mark_ephemerons and
+   before any object is unmarked.  */
+
+void
+sweep_weak_refs (void)
+{
+  for (struct Lisp_Weak_Ref *w = weak_refs; w; w = w->next_weak)
+    if (!survives_gc_p (w->key))
+      {
+	w->key = Qnil;
+	w->value = Qnil;
+      }
+
+  weak_refs = NULL;
+}
+
 
 
 
@@ -7063,6 +7121,7 @@ gc_sweep (void)
   /* Remove or mark entries in weak hash tables.
      This must be done before any object is unmarked.  */
   sweep_weak_hash_tables ();
+  sweep_weak_refs ();
 
   sweep_strings ();
   check_string_bytes (!noninteractive);
@@ -113,7 +113,6 @@ static void call_overlay_mod_hooks (Lisp_Object list, Lisp_Object overlay,
                                     bool after, Lisp_Object arg1,
                                     Lisp_Object arg2, Lisp_Object arg3);
 static void swap_out_buffer_local_variables (struct buffer *b);
-static void reset_buffer_local_variables (struct buffer *, bool);
 
 extern void drop_overlay (struct buffer *, struct Lisp_Overlay *);
 void unchain_both (struct buffer *, Lisp_Object);
@@ -126,7 +125,6 @@ Lisp_Object Vbuffer_alist;
 static Lisp_Object QSFundamental;	/* A string "Fundamental".  */
 
 static void alloc_buffer_text (struct buffer *, ptrdiff_t);
-static void free_buffer_text (struct buffer *b);
 extern struct Lisp_Overlay * copy_overlays (struct buffer *, struct Lisp_Overlay *);
 static Lisp_Object buffer_lisp_local_variables (struct buffer *, bool);
 
@@ -438,6 +436,7 @@ even if it is dead.  The return value is never nil.  */)
   BUF_END_UNCHANGED (b) = 0;
   BUF_BEG_UNCHANGED (b) = 0;
   *(BUF_GPT_ADDR (b)) = *(BUF_Z_ADDR (b)) = 0; /* Put an anchor '\0'.  */
+  b->text->mapped_size = 0;
   b->text->inhibit_shrinking = false;
   b->text->redisplay = false;
 
@@ -496,7 +495,7 @@ set_buffer_overlays_after (struct buffer *b, struct Lisp_Overlay *o)
    are copied and made to refer to TO, and (3) overlay lists are
    copied.  */
 
-static void
+void
 clone_per_buffer_values (struct buffer *from, struct buffer *to)
 {
   int offset;
@@ -537,137 +536,6 @@ void record_buffer_markers (struct buffer *b);
 void fetch_buffer_markers (struct buffer *b);
 
 
-DEFUN ("make-indirect-buffer", Fmake_indirect_buffer, Smake_indirect_buffer,
-       2, 3,
-       "bMake indirect buffer (to buffer): \nBName of indirect buffer: ",
-       doc: /* Create and return an indirect buffer for buffer BASE-BUFFER, named NAME.
-BASE-BUFFER should be a live buffer, or the name of an existing buffer.
-NAME should be a string which is not the name of an existing buffer.
-Optional argument CLONE non-nil means preserve BASE-BUFFER's state,
-such as major and minor modes, in the indirect buffer.
-CLONE nil means the indirect buffer's state is reset to default values.  */)
-  (Lisp_Object base_buffer, Lisp_Object name, Lisp_Object clone)
-{
-  Lisp_Object buf, tem;
-  struct buffer *b;
-
-  CHECK_STRING (name);
-  buf = Fget_buffer (name);
-  if (!NILP (buf))
-    error ("Buffer name `%s' is in use", SDATA (name));
-
-  tem = base_buffer;
-  base_buffer = Fget_buffer (base_buffer);
-  if (NILP (base_buffer))
-    error ("No such buffer: `%s'", SDATA (tem));
-  if (!BUFFER_LIVE_P (XBUFFER (base_buffer)))
-    error ("Base buffer has been killed");
-
-  if (SCHARS (name) == 0)
-    error ("Empty string for buffer name is not allowed");
-
-  b = allocate_buffer ();
-
-  /* No double indirection - if base buffer is indirect,
-     new buffer becomes an indirect to base's base.  */
-  b->base_buffer = (XBUFFER (base_buffer)->base_buffer
-		    ? XBUFFER (base_buffer)->base_buffer
-		    : XBUFFER (base_buffer));
-
-  /* Use the base buffer's text object.  */
-  b->text = b->base_buffer->text;
-  /* We have no own text.  */
-  b->indirections = -1;
-  /* Notify base buffer that we share the text now.  */
-  b->base_buffer->indirections++;
-  /* Always -1 for an indirect buffer.  */
-  b->window_count = -1;
-
-  b->pt = b->base_buffer->pt;
-  b->begv = b->base_buffer->begv;
-  b->zv = b->base_buffer->zv;
-  b->pt_byte = b->base_buffer->pt_byte;
-  b->begv_byte = b->base_buffer->begv_byte;
-  b->zv_byte = b->base_buffer->zv_byte;
-
-  b->newline_cache = 0;
-  b->width_run_cache = 0;
-  b->bidi_paragraph_cache = 0;
-  bset_width_table (b, Qnil);
-
-  name = Fcopy_sequence (name);
-  set_string_intervals (name, NULL);
-  bset_name (b, name);
-
-  /* An indirect buffer shares undo list of its base (Bug#18180).  */
-  bset_undo_list (b, BVAR (b->base_buffer, undo_list));
-
-  reset_buffer (b);
-  reset_buffer_local_variables (b, 1);
-
-  /* Put this in the alist of all live buffers.  */
-  XSETBUFFER (buf, b);
-  Vbuffer_alist = nconc2 (Vbuffer_alist, list1 (Fcons (name, buf)));
-
-  bset_mark (b, Fmake_marker ());
-
-  /* The multibyte status belongs to the base buffer.  */
-  bset_enable_multibyte_characters
-    (b, BVAR (b->base_buffer, enable_multibyte_characters));
-
-  /* Make sure the base buffer has markers for its narrowing.  */
-  if (NILP (BVAR (b->base_buffer, pt_marker)))
-    {
-      eassert (NILP (BVAR (b->base_buffer, begv_marker)));
-      eassert (NILP (BVAR (b->base_buffer, zv_marker)));
-
-      bset_pt_marker (b->base_buffer,
-		      build_marker (b->base_buffer, b->base_buffer->pt,
-				    b->base_buffer->pt_byte));
-
-      bset_begv_marker (b->base_buffer,
-			build_marker (b->base_buffer, b->base_buffer->begv,
-				      b->base_buffer->begv_byte));
-
-      bset_zv_marker (b->base_buffer,
-		      build_marker (b->base_buffer, b->base_buffer->zv,
-				    b->base_buffer->zv_byte));
-
-      XMARKER (BVAR (b->base_buffer, zv_marker))->insertion_type = 1;
-    }
-
-  if (NILP (clone))
-    {
-      /* Give the indirect buffer markers for its narrowing.  */
-      bset_pt_marker (b, build_marker (b, b->pt, b->pt_byte));
-      bset_begv_marker (b, build_marker (b, b->begv, b->begv_byte));
-      bset_zv_marker (b, build_marker (b, b->zv, b->zv_byte));
-      XMARKER (BVAR (b, zv_marker))->insertion_type = 1;
-    }
-  else
-    {
-      struct buffer *old_b = current_buffer;
-
-      clone_per_buffer_values (b->base_buffer, b);
-      bset_filename (b, Qnil);
-      bset_file_truename (b, Qnil);
-      bset_display_count (b, make_number (0));
-      bset_backed_up (b, Qnil);
-      bset_auto_save_file_name (b, Qnil);
-      set_buffer_internal_1 (b);
-      Fset (intern ("buffer-save-without-query"), Qnil);
-      Fset (intern ("buffer-file-number"), Qnil);
-      Fset (intern ("buffer-stale-function"), Qnil);
-      set_buffer_internal_1 (old_b);
-    }
-
-  /* Run buffer-list-update-hook.  */
-  if (!NILP (Vrun_hooks))
-    call1 (Vrun_hooks, Qbuffer_list_update_hook);
-
-  return buf;
-}
-
 /* Delete all overlays of B and reset its overlay lists.  */
 
 void
@@ -746,7 +614,7 @@ reset_buffer (register struct buffer *b)
    If PERMANENT_TOO, reset permanent buffer-local variables.
    If not, preserve those.  */
 
-static void
+void
 reset_buffer_local_variables (struct buffer *b, bool permanent_too)
 {
   int offset, i;
@@ -4475,6 +4343,21 @@ enlarge_buffer_text (struct buffer *b, ptrdiff_t delta)
   void *p;
   ptrdiff_t nbytes = (BUF_Z_BYTE (b) - BUF_BEG_BYTE (b) + BUF_GAP_SIZE (b) + 1
 		      + delta);
+
+  /* Text mapped from a file can't be resized, so copy it into memory
+     allocated as usual first.  */
+  if (b->text->mapped_size > 0)
+    {
+      unsigned char *mapped = b->text->beg;
+      ptrdiff_t mapped_size = b->text->mapped_size;
+      b->text->beg = NULL;
+      b->text->mapped_size = 0;
+      alloc_buffer_text (b, nbytes);
+      memcpy (b->text->beg, mapped, min (nbytes, nbytes - delta));
+      unmap_buffer_text (mapped, mapped_size);
+      return;
+    }
+
   block_input ();
 #if defined USE_MMAP_FOR_BUFFERS
   p = mmap_realloc ((void **) &b->text->beg, nbytes);
@@ -4497,21 +4380,31 @@ enlarge_buffer_text (struct buffer *b, ptrdiff_t delta)
 
 /* Free buffer B's text buffer.  */
 
-static void
+void
 free_buffer_text (struct buffer *b)
 {
   block_input ();
 
+  if (b->text->mapped_size > 0)
+    {
+      unmap_buffer_text (b->text->beg, b->text->mapped_size);
+      b->text->mapped_size = 0;
+    }
+  else
+    {
 #if defined USE_MMAP_FOR_BUFFERS
-  mmap_free ((void **) &b->text->beg);
+      mmap_free ((void **) &b->text->beg);
 #elif defined REL_ALLOC
-  r_alloc_free ((void **) &b->text->beg);
+      r_alloc_free ((void **) &b->text->beg);
 #else
-  xfree (b->text->beg);
+      xfree (b->text->beg);
 #endif
+    }
 
   BUF_BEG_ADDR (b) = NULL;
   unblock_input ();
+
+  forget_line_index (b);
 }
 
 
@@ -5701,7 +5594,6 @@ Functions running this hook are, `get-buffer-create',
   DEFSYM (Qbuffer_list_update_hook, "buffer-list-update-hook");
 
   defsubr (&Sget_buffer_create);
-  defsubr (&Smake_indirect_buffer);
   defsubr (&Sbuffer_local_variables);
   defsubr (&Sset_buffer_modified_p);
   defsubr (&Srename_buffer);
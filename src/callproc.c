@@ -1023,6 +1023,123 @@ exec_failed (char const *name, int err)
 
 #endif
 
+/* Return the number of strings in the environment of a subprocess,
+   not counting PWD, and set *DISPLAY to the DISPLAY it is to get if
+   `process-environment' has none, or to nil.  */
+
+static ptrdiff_t
+environment_length (Lisp_Object *display)
+{
+  Lisp_Object tem;
+  ptrdiff_t new_length = 0;
+
+  *display = Qnil;
+
+  for (tem = Vprocess_environment;
+       CONSP (tem) && STRINGP (XCAR (tem));
+       tem = XCDR (tem))
+    {
+      if (strncmp (SSDATA (XCAR (tem)), "DISPLAY", 7) == 0
+	  && (SDATA (XCAR (tem)) [7] == '\0'
+	      || SDATA (XCAR (tem)) [7] == '='))
+	/* DISPLAY is specified in process-environment.  */
+	*display = Qt;
+      new_length++;
+    }
+
+  /* If not provided yet, use the frame's DISPLAY.  */
+  if (NILP (*display))
+    {
+      Lisp_Object tmp = Fframe_parameter (selected_frame, Qdisplay);
+      if (!STRINGP (tmp) && CONSP (Vinitial_environment))
+	/* If still not found, Look for DISPLAY in Vinitial_environment.  */
+	tmp = Fgetenv_internal (build_string ("DISPLAY"),
+				Vinitial_environment);
+      if (STRINGP (tmp))
+	{
+	  *display = tmp;
+	  new_length++;
+	}
+    }
+
+  return new_length;
+}
+
+/* Store the environment of a subprocess in ENV, which has room for the
+   strings environment_length counts, PWD and a terminating null
+   pointer.  PWD_VAR is "PWD=" followed by the directory of the
+   subprocess.  DISPLAY_VAR is "DISPLAY=" followed by the display
+   environment_length found, or null.  */
+
+static void
+fill_environment (char **env, char *pwd_var, char *display_var)
+{
+  Lisp_Object tem;
+  char **new_env = env;
+  char **p, **q;
+
+  /* If we have a PWD envvar, pass one down,
+     but with corrected value.  */
+  if (egetenv ("PWD"))
+    *new_env++ = pwd_var;
+
+  if (display_var)
+    new_env = add_env (env, new_env, display_var);
+
+  /* Overrides.  */
+  for (tem = Vprocess_environment;
+       CONSP (tem) && STRINGP (XCAR (tem));
+       tem = XCDR (tem))
+    new_env = add_env (env, new_env, SSDATA (XCAR (tem)));
+
+  *new_env = 0;
+
+  /* Remove variable names without values.  */
+  p = q = env;
+  while (*p != 0)
+    {
+      while (*q != 0 && strchr (*q, '=') == NULL)
+	q++;
+      *p = *q++;
+      if (*p != 0)
+	p++;
+    }
+}
+
+/* Return the environment child_setup gives a subprocess that runs in
+   CURRENT_DIR, for starting one without child_setup.  Its strings
+   point into Lisp strings, so it must be used before Emacs can collect
+   garbage.  Free it with xfree.  */
+
+char **
+make_environment_block (Lisp_Object current_dir)
+{
+  Lisp_Object display;
+  ptrdiff_t nstrings = environment_length (&display) + 2;
+  ptrdiff_t dirlen = SBYTES (current_dir);
+  ptrdiff_t displaylen = (STRINGP (display)
+			  ? sizeof "DISPLAY=" + SBYTES (display) : 0);
+  char **env = xmalloc (nstrings * sizeof *env
+			+ sizeof "PWD=" + dirlen + displaylen);
+  char *pwd_var = (char *) (env + nstrings);
+  char *dir = pwd_var + 4;
+  char *display_var = NULL;
+
+  lispstpcpy (stpcpy (pwd_var, "PWD="), current_dir);
+  /* Strip trailing slashes for PWD, but leave "/" and "//" alone.  */
+  while (dirlen > 2 && IS_DIRECTORY_SEP (dir[dirlen - 1]))
+    dir[--dirlen] = 0;
+
+  if (STRINGP (display))
+    {
+      display_var = pwd_var + sizeof "PWD=" + SBYTES (current_dir);
+      lispstpcpy (stpcpy (display_var, "DISPLAY="), display);
+    }
+
+  fill_environment (env, pwd_var, display_var);
+  return env;
+}
+
 /* This is the last thing run in a newly forked inferior
    either synchronous or asynchronous.
    Copy descriptors IN, OUT and ERR as descriptors 0, 1 and 2.
@@ -1095,80 +1212,26 @@ child_setup (int in, int out, int err, char **new_argv, bool set_pgrp,
 
   /* Set `env' to a vector of the strings in the environment.  */
   {
-    register Lisp_Object tem;
-    register char **new_env;
-    char **p, **q;
-    register int new_length;
-    Lisp_Object display = Qnil;
-
-    new_length = 0;
-
-    for (tem = Vprocess_environment;
-	 CONSP (tem) && STRINGP (XCAR (tem));
-	 tem = XCDR (tem))
-      {
-	if (strncmp (SSDATA (XCAR (tem)), "DISPLAY", 7) == 0
-	    && (SDATA (XCAR (tem)) [7] == '\0'
-		|| SDATA (XCAR (tem)) [7] == '='))
-	  /* DISPLAY is specified in process-environment.  */
-	  display = Qt;
-	new_length++;
-      }
-
-    /* If not provided yet, use the frame's DISPLAY.  */
-    if (NILP (display))
-      {
-	Lisp_Object tmp = Fframe_parameter (selected_frame, Qdisplay);
-	if (!STRINGP (tmp) && CONSP (Vinitial_environment))
-	  /* If still not found, Look for DISPLAY in Vinitial_environment.  */
-	  tmp = Fgetenv_internal (build_string ("DISPLAY"),
-				  Vinitial_environment);
-	if (STRINGP (tmp))
-	  {
-	    display = tmp;
-	    new_length++;
-	  }
-      }
+    Lisp_Object display;
+    ptrdiff_t new_length = environment_length (&display);
+    char *display_var = NULL;
 
     /* new_length + 2 to include PWD and terminating 0.  */
     if (MAX_ALLOCA / sizeof *env - 2 < new_length)
       exec_failed (new_argv[0], ENOMEM);
-    env = new_env = alloca ((new_length + 2) * sizeof *env);
-    /* If we have a PWD envvar, pass one down,
-       but with corrected value.  */
-    if (egetenv ("PWD"))
-      *new_env++ = pwd_var;
+    env = alloca ((new_length + 2) * sizeof *env);
 
     if (STRINGP (display))
       {
 	if (MAX_ALLOCA - sizeof "DISPLAY=" < SBYTES (display))
 	  exec_failed (new_argv[0], ENOMEM);
-	char *vdata = alloca (sizeof "DISPLAY=" + SBYTES (display));
-	lispstpcpy (stpcpy (vdata, "DISPLAY="), display);
-	new_env = add_env (env, new_env, vdata);
+	display_var = alloca (sizeof "DISPLAY=" + SBYTES (display));
+	lispstpcpy (stpcpy (display_var, "DISPLAY="), display);
       }
 
-    /* Overrides.  */
-    for (tem = Vprocess_environment;
-	 CONSP (tem) && STRINGP (XCAR (tem));
-	 tem = XCDR (tem))
-      new_env = add_env (env, new_env, SSDATA (XCAR (tem)));
-
-    *new_env = 0;
-
-    /* Remove variable names without values.  */
-    p = q = env;
-    while (*p != 0)
-      {
-	while (*q != 0 && strchr (*q, '=') == NULL)
-	  q++;
-	*p = *q++;
-	if (*p != 0)
-	  p++;
-      }
+    fill_environment (env, pwd_var, display_var);
   }
 
-
 #ifdef WINDOWSNT
   prepare_standard_handles (in, out, err, handles);
   set_process_dir (SSDATA (current_dir));
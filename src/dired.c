@@ -1075,6 +1075,9 @@ The value may be nil if not supported on this platform.  */)
   return groups;
 }
 
+/* Defined in dired.rs.  */
+extern void rust_syms_of_dired (void);
+
 void
 syms_of_dired (void)
 {
@@ -1098,4 +1101,6 @@ these strings or when displaying a list of completions.
 It ignores directory names if they match any string in this list which
 ends in a slash.  */);
   Vcompletion_ignored_extensions = Qnil;
+
+  rust_syms_of_dired ();
 }
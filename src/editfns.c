@@ -2318,43 +2318,6 @@ It returns the number of characters changed.  */)
 }
 
 
-DEFUN ("narrow-to-region", Fnarrow_to_region, Snarrow_to_region, 2, 2, "r",
-       doc: /* Restrict editing in this buffer to the current region.
-The rest of the text becomes temporarily invisible and untouchable
-but is not deleted; if you save the buffer in a file, the invisible
-text is included in the file.  \\[widen] makes all visible again.
-See also `save-restriction'.
-
-When calling from a program, pass two arguments; positions (integers
-or markers) bounding the text that should remain visible.  */)
-  (register Lisp_Object start, Lisp_Object end)
-{
-  CHECK_NUMBER_COERCE_MARKER (start);
-  CHECK_NUMBER_COERCE_MARKER (end);
-
-  if (XINT (start) > XINT (end))
-    {
-      Lisp_Object tem;
-      tem = start; start = end; end = tem;
-    }
-
-  if (!(BEG <= XINT (start) && XINT (start) <= XINT (end) && XINT (end) <= Z))
-    args_out_of_range (start, end);
-
-  if (BEGV != XFASTINT (start) || ZV != XFASTINT (end))
-    current_buffer->clip_changed = 1;
-
-  SET_BUF_BEGV (current_buffer, XFASTINT (start));
-  SET_BUF_ZV (current_buffer, XFASTINT (end));
-  if (PT < XFASTINT (start))
-    SET_PT (XFASTINT (start));
-  if (PT > XFASTINT (end))
-    SET_PT (XFASTINT (end));
-  /* Changing the buffer bounds invalidates any recorded current column.  */
-  invalidate_current_column ();
-  return Qnil;
-}
-
 Lisp_Object
 save_restriction_save (void)
 {
@@ -2449,852 +2412,6 @@ save_restriction_restore (Lisp_Object data)
 }
 
 
-/* Convert the prefix of STR from ASCII decimal digits to a number.
-   Set *STR_END to the address of the first non-digit.  Return the
-   number, or PTRDIFF_MAX on overflow.  Return 0 if there is no number.
-   This is like strtol for ptrdiff_t and base 10 and C locale,
-   except without negative numbers or errno.  */
-
-static ptrdiff_t
-str2num (char *str, char **str_end)
-{
-  ptrdiff_t n = 0;
-  for (; c_isdigit (*str); str++)
-    if (INT_MULTIPLY_WRAPV (n, 10, &n) || INT_ADD_WRAPV (n, *str - '0', &n))
-      n = PTRDIFF_MAX;
-  *str_end = str;
-  return n;
-}
-
-/* Implement ‘format-message’ if MESSAGE is true, ‘format’ otherwise.  */
-
-Lisp_Object
-styled_format (ptrdiff_t nargs, Lisp_Object *args, bool message)
-{
-  ptrdiff_t n;		/* The number of the next arg to substitute.  */
-  char initial_buffer[4000];
-  char *buf = initial_buffer;
-  ptrdiff_t bufsize = sizeof initial_buffer;
-  ptrdiff_t max_bufsize = STRING_BYTES_BOUND + 1;
-  char *p;
-  ptrdiff_t buf_save_value_index UNINIT;
-  char *format, *end;
-  ptrdiff_t nchars;
-  /* When we make a multibyte string, we must pay attention to the
-     byte combining problem, i.e., a byte may be combined with a
-     multibyte character of the previous string.  This flag tells if we
-     must consider such a situation or not.  */
-  bool maybe_combine_byte;
-  Lisp_Object val;
-  bool arg_intervals = false;
-  USE_SAFE_ALLOCA;
-  sa_avail -= sizeof initial_buffer;
-
-  /* Information recorded for each format spec.  */
-  struct info
-  {
-    /* The corresponding argument, converted to string if conversion
-       was needed.  */
-    Lisp_Object argument;
-
-    /* The start and end bytepos in the output string.  */
-    ptrdiff_t start, end;
-
-    /* Whether the argument is a string with intervals.  */
-    bool_bf intervals : 1;
-  } *info;
-
-  CHECK_STRING (args[0]);
-  char *format_start = SSDATA (args[0]);
-  bool multibyte_format = STRING_MULTIBYTE (args[0]);
-  ptrdiff_t formatlen = SBYTES (args[0]);
-
-  /* Upper bound on number of format specs.  Each uses at least 2 chars.  */
-  ptrdiff_t nspec_bound = SCHARS (args[0]) >> 1;
-
-  /* Allocate the info and discarded tables.  */
-  ptrdiff_t info_size, alloca_size;
-  if (INT_MULTIPLY_WRAPV (nspec_bound, sizeof *info, &info_size)
-      || INT_ADD_WRAPV (formatlen, info_size, &alloca_size)
-      || SIZE_MAX < alloca_size)
-    memory_full (SIZE_MAX);
-  info = SAFE_ALLOCA (alloca_size);
-  /* discarded[I] is 1 if byte I of the format
-     string was not copied into the output.
-     It is 2 if byte I was not the first byte of its character.  */
-  char *discarded = (char *) &info[nspec_bound];
-  info = ptr_bounds_clip (info, info_size);
-  discarded = ptr_bounds_clip (discarded, formatlen);
-  memset (discarded, 0, formatlen);
-
-  /* Try to determine whether the result should be multibyte.
-     This is not always right; sometimes the result needs to be multibyte
-     because of an object that we will pass through prin1.
-     or because a grave accent or apostrophe is requoted,
-     and in that case, we won't know it here.  */
-
-  /* True if the output should be a multibyte string,
-     which is true if any of the inputs is one.  */
-  bool multibyte = multibyte_format;
-  for (ptrdiff_t i = 1; !multibyte && i < nargs; i++)
-    if (STRINGP (args[i]) && STRING_MULTIBYTE (args[i]))
-      multibyte = true;
-
-  int quoting_style = message ? text_quoting_style () : -1;
-
-  ptrdiff_t ispec;
-  ptrdiff_t nspec = 0;
-
-  /* True if a string needs to be allocated to hold the result.  */
-  bool new_result = false;
-
-  /* If we start out planning a unibyte result,
-     then discover it has to be multibyte, we jump back to retry.  */
- retry:
-
-  p = buf;
-  nchars = 0;
-
-  /* N is the argument index, ISPEC is the specification index.  */
-  n = 0;
-  ispec = 0;
-
-  /* Scan the format and store result in BUF.  */
-  format = format_start;
-  end = format + formatlen;
-  maybe_combine_byte = false;
-
-  while (format != end)
-    {
-      /* The values of N, ISPEC, and FORMAT when the loop body is
-         entered.  */
-      ptrdiff_t n0 = n;
-      ptrdiff_t ispec0 = ispec;
-      char *format0 = format;
-      char const *convsrc = format;
-      unsigned char format_char = *format++;
-
-      /* Bytes needed to represent the output of this conversion.  */
-      ptrdiff_t convbytes = 1;
-
-      if (format_char == '%')
-	{
-	  /* General format specifications look like
-
-	     '%' [field-number] [flags] [field-width] [precision] format
-
-	     where
-
-             field-number ::= [0-9]+ '$'
-	     flags ::= [-+0# ]+
-	     field-width ::= [0-9]+
-	     precision ::= '.' [0-9]*
-
-	     If present, a field-number specifies the argument number
-	     to substitute.  Otherwise, the next argument is taken.
-
-	     If a field-width is specified, it specifies to which width
-	     the output should be padded with blanks, if the output
-	     string is shorter than field-width.
-
-	     If precision is specified, it specifies the number of
-	     digits to print after the '.' for floats, or the max.
-	     number of chars to print from a string.  */
-
-	  ptrdiff_t num;
-	  char *num_end;
-	  if (c_isdigit (*format))
-	    {
-	      num = str2num (format, &num_end);
-	      if (*num_end == '$')
-		{
-		  n = num - 1;
-		  format = num_end + 1;
-		}
-	    }
-
-	  bool minus_flag = false;
-	  bool  plus_flag = false;
-	  bool space_flag = false;
-	  bool sharp_flag = false;
-	  bool  zero_flag = false;
-
-	  for (; ; format++)
-	    {
-	      switch (*format)
-		{
-		case '-': minus_flag = true; continue;
-		case '+':  plus_flag = true; continue;
-		case ' ': space_flag = true; continue;
-		case '#': sharp_flag = true; continue;
-		case '0':  zero_flag = true; continue;
-		}
-	      break;
-	    }
-
-	  /* Ignore flags when sprintf ignores them.  */
-	  space_flag &= ! plus_flag;
-	  zero_flag &= ! minus_flag;
-
-	  num = str2num (format, &num_end);
-	  if (max_bufsize <= num)
-	    string_overflow ();
-	  ptrdiff_t field_width = num;
-
-	  bool precision_given = *num_end == '.';
-	  ptrdiff_t precision = (precision_given
-				 ? str2num (num_end + 1, &num_end)
-				 : PTRDIFF_MAX);
-	  format = num_end;
-
-	  if (format == end)
-	    error ("Format string ends in middle of format specifier");
-
-	  char conversion = *format++;
-	  memset (&discarded[format0 - format_start], 1,
-		  format - format0 - (conversion == '%'));
-	  if (conversion == '%')
-	    {
-	      new_result = true;
-	      goto copy_char;
-	    }
-
-	  ++n;
-	  if (! (n < nargs))
-	    error ("Not enough arguments for format string");
-
-	  struct info *spec = &info[ispec++];
-	  if (nspec < ispec)
-	    {
-	      spec->argument = args[n];
-	      spec->intervals = false;
-	      nspec = ispec;
-	    }
-	  Lisp_Object arg = spec->argument;
-
-	  /* For 'S', prin1 the argument, and then treat like 's'.
-	     For 's', princ any argument that is not a string or
-	     symbol.  But don't do this conversion twice, which might
-	     happen after retrying.  */
-	  if ((conversion == 'S'
-	       || (conversion == 's'
-		   && ! STRINGP (arg) && ! SYMBOLP (arg))))
-	    {
-	      if (EQ (arg, args[n]))
-		{
-		  Lisp_Object noescape = conversion == 'S' ? Qnil : Qt;
-		  spec->argument = arg = Fprin1_to_string (arg, noescape);
-		  if (STRING_MULTIBYTE (arg) && ! multibyte)
-		    {
-		      multibyte = true;
-		      goto retry;
-		    }
-		}
-	      conversion = 's';
-	    }
-	  else if (conversion == 'c')
-	    {
-	      if (INTEGERP (arg) && ! ASCII_CHAR_P (XINT (arg)))
-		{
-		  if (!multibyte)
-		    {
-		      multibyte = true;
-		      goto retry;
-		    }
-		  spec->argument = arg = Fchar_to_string (arg);
-		}
-
-	      if (!EQ (arg, args[n]))
-		conversion = 's';
-	      zero_flag = false;
-	    }
-
-	  if (SYMBOLP (arg))
-	    {
-	      spec->argument = arg = SYMBOL_NAME (arg);
-	      if (STRING_MULTIBYTE (arg) && ! multibyte)
-		{
-		  multibyte = true;
-		  goto retry;
-		}
-	    }
-
-	  bool float_conversion
-	    = conversion == 'e' || conversion == 'f' || conversion == 'g';
-
-	  if (conversion == 's')
-	    {
-	      if (format == end && format - format_start == 2
-		  && ! string_intervals (args[0]))
-		{
-		  val = arg;
-		  goto return_val;
-		}
-
-	      /* handle case (precision[n] >= 0) */
-
-	      ptrdiff_t prec = -1;
-	      if (precision_given)
-		prec = precision;
-
-	      /* lisp_string_width ignores a precision of 0, but GNU
-		 libc functions print 0 characters when the precision
-		 is 0.  Imitate libc behavior here.  Changing
-		 lisp_string_width is the right thing, and will be
-		 done, but meanwhile we work with it. */
-
-	      ptrdiff_t width, nbytes;
-	      ptrdiff_t nchars_string;
-	      if (prec == 0)
-		width = nchars_string = nbytes = 0;
-	      else
-		{
-		  ptrdiff_t nch, nby;
-		  width = lisp_string_width (arg, prec, &nch, &nby);
-		  if (prec < 0)
-		    {
-		      nchars_string = SCHARS (arg);
-		      nbytes = SBYTES (arg);
-		    }
-		  else
-		    {
-		      nchars_string = nch;
-		      nbytes = nby;
-		    }
-		}
-
-	      convbytes = nbytes;
-	      if (convbytes && multibyte && ! STRING_MULTIBYTE (arg))
-		convbytes = count_size_as_multibyte (SDATA (arg), nbytes);
-
-	      ptrdiff_t padding
-		= width < field_width ? field_width - width : 0;
-
-	      if (max_bufsize - padding <= convbytes)
-		string_overflow ();
-	      convbytes += padding;
-	      if (convbytes <= buf + bufsize - p)
-		{
-		  if (! minus_flag)
-		    {
-		      memset (p, ' ', padding);
-		      p += padding;
-		      nchars += padding;
-		    }
-		  spec->start = nchars;
-
-		  if (p > buf
-		      && multibyte
-		      && !ASCII_CHAR_P (*((unsigned char *) p - 1))
-		      && STRING_MULTIBYTE (arg)
-		      && !CHAR_HEAD_P (SREF (arg, 0)))
-		    maybe_combine_byte = true;
-
-		  p += copy_text (SDATA (arg), (unsigned char *) p,
-				  nbytes,
-				  STRING_MULTIBYTE (arg), multibyte);
-
-		  nchars += nchars_string;
-
-		  if (minus_flag)
-		    {
-		      memset (p, ' ', padding);
-		      p += padding;
-		      nchars += padding;
-		    }
-		  spec->end = nchars;
-
-		  /* If this argument has text properties, record where
-		     in the result string it appears.  */
-		  if (string_intervals (arg))
-		    spec->intervals = arg_intervals = true;
-
-		  new_result = true;
-		  continue;
-		}
-	    }
-	  else if (! (conversion == 'c' || conversion == 'd'
-		      || float_conversion || conversion == 'i'
-		      || conversion == 'o' || conversion == 'x'
-		      || conversion == 'X'))
-	    error ("Invalid format operation %%%c",
-		   STRING_CHAR ((unsigned char *) format - 1));
-	  else if (! (INTEGERP (arg) || (FLOATP (arg) && conversion != 'c')))
-	    error ("Format specifier doesn't match argument type");
-	  else
-	    {
-	      enum
-	      {
-		/* Lower bound on the number of bits per
-		   base-FLT_RADIX digit.  */
-		DIG_BITS_LBOUND = FLT_RADIX < 16 ? 1 : 4,
-
-		/* 1 if integers should be formatted as long doubles,
-		   because they may be so large that there is a rounding
-		   error when converting them to double, and long doubles
-		   are wider than doubles.  */
-		INT_AS_LDBL = (DIG_BITS_LBOUND * DBL_MANT_DIG < FIXNUM_BITS - 1
-			       && DBL_MANT_DIG < LDBL_MANT_DIG),
-
-		/* Maximum precision for a %f conversion such that the
-		   trailing output digit might be nonzero.  Any precision
-		   larger than this will not yield useful information.  */
-		USEFUL_PRECISION_MAX =
-		  ((1 - LDBL_MIN_EXP)
-		   * (FLT_RADIX == 2 || FLT_RADIX == 10 ? 1
-		      : FLT_RADIX == 16 ? 4
-		      : -1)),
-
-		/* Maximum number of bytes generated by any format, if
-		   precision is no more than USEFUL_PRECISION_MAX.
-		   On all practical hosts, %f is the worst case.  */
-		SPRINTF_BUFSIZE =
-		  sizeof "-." + (LDBL_MAX_10_EXP + 1) + USEFUL_PRECISION_MAX,
-
-		/* Length of pM (that is, of pMd without the
-		   trailing "d").  */
-		pMlen = sizeof pMd - 2
-	      };
-	      verify (USEFUL_PRECISION_MAX > 0);
-
-	      /* Avoid undefined behavior in underlying sprintf.  */
-	      if (conversion == 'd' || conversion == 'i')
-		sharp_flag = false;
-
-	      /* Create the copy of the conversion specification, with
-		 any width and precision removed, with ".*" inserted,
-		 with "L" possibly inserted for floating-point formats,
-		 and with pM inserted for integer formats.
-		 At most two flags F can be specified at once.  */
-	      char convspec[sizeof "%FF.*d" + max (INT_AS_LDBL, pMlen)];
-	      {
-		char *f = convspec;
-		*f++ = '%';
-		/* MINUS_FLAG and ZERO_FLAG are dealt with later.  */
-		*f = '+'; f +=  plus_flag;
-		*f = ' '; f += space_flag;
-		*f = '#'; f += sharp_flag;
-                *f++ = '.';
-                *f++ = '*';
-		if (float_conversion)
-		  {
-		    if (INT_AS_LDBL)
-		      {
-			*f = 'L';
-			f += INTEGERP (arg);
-		      }
-		  }
-		else if (conversion != 'c')
-		  {
-		    memcpy (f, pMd, pMlen);
-		    f += pMlen;
-		    zero_flag &= ! precision_given;
-		  }
-		*f++ = conversion;
-		*f = '\0';
-	      }
-
-	      int prec = -1;
-	      if (precision_given)
-		prec = min (precision, USEFUL_PRECISION_MAX);
-
-	      /* Use sprintf to format this number into sprintf_buf.  Omit
-		 padding and excess precision, though, because sprintf limits
-		 output length to INT_MAX.
-
-		 There are four types of conversion: double, unsigned
-		 char (passed as int), wide signed int, and wide
-		 unsigned int.  Treat them separately because the
-		 sprintf ABI is sensitive to which type is passed.  Be
-		 careful about integer overflow, NaNs, infinities, and
-		 conversions; for example, the min and max macros are
-		 not suitable here.  */
-	      char sprintf_buf[SPRINTF_BUFSIZE];
-	      ptrdiff_t sprintf_bytes;
-	      if (float_conversion)
-		{
-		  if (INT_AS_LDBL && INTEGERP (arg))
-		    {
-		      /* Although long double may have a rounding error if
-			 DIG_BITS_LBOUND * LDBL_MANT_DIG < FIXNUM_BITS - 1,
-			 it is more accurate than plain 'double'.  */
-		      long double x = XINT (arg);
-		      sprintf_bytes = sprintf (sprintf_buf, convspec, prec, x);
-		    }
-		  else
-		    sprintf_bytes = sprintf (sprintf_buf, convspec, prec,
-					     XFLOATINT (arg));
-		}
-	      else if (conversion == 'c')
-		{
-		  /* Don't use sprintf here, as it might mishandle prec.  */
-		  sprintf_buf[0] = XINT (arg);
-		  sprintf_bytes = prec != 0;
-		  sprintf_buf[sprintf_bytes] = '\0';
-		}
-	      else if (conversion == 'd' || conversion == 'i')
-		{
-		  /* For float, maybe we should use "%1.0f"
-		     instead so it also works for values outside
-		     the integer range.  */
-		  printmax_t x;
-		  if (INTEGERP (arg))
-		    x = XINT (arg);
-		  else
-		    {
-		      double d = XFLOAT_DATA (arg);
-		      if (d < 0)
-			{
-			  x = TYPE_MINIMUM (printmax_t);
-			  if (x < d)
-			    x = d;
-			}
-		      else
-			{
-			  x = TYPE_MAXIMUM (printmax_t);
-			  if (d < x)
-			    x = d;
-			}
-		    }
-		  sprintf_bytes = sprintf (sprintf_buf, convspec, prec, x);
-		}
-	      else
-		{
-		  /* Don't sign-extend for octal or hex printing.  */
-		  uprintmax_t x;
-		  if (INTEGERP (arg))
-		    x = XUINT (arg);
-		  else
-		    {
-		      double d = XFLOAT_DATA (arg);
-		      if (d < 0)
-			x = 0;
-		      else
-			{
-			  x = TYPE_MAXIMUM (uprintmax_t);
-			  if (d < x)
-			    x = d;
-			}
-		    }
-		  sprintf_bytes = sprintf (sprintf_buf, convspec, prec, x);
-		}
-
-	      /* Now the length of the formatted item is known, except it omits
-		 padding and excess precision.  Deal with excess precision
-		 first.  This happens only when the format specifies
-		 ridiculously large precision.  */
-	      ptrdiff_t excess_precision
-		= precision_given ? precision - prec : 0;
-	      ptrdiff_t leading_zeros = 0, trailing_zeros = 0;
-	      if (excess_precision)
-		{
-		  if (float_conversion)
-		    {
-		      if ((conversion == 'g' && ! sharp_flag)
-			  || ! ('0' <= sprintf_buf[sprintf_bytes - 1]
-				&& sprintf_buf[sprintf_bytes - 1] <= '9'))
-			excess_precision = 0;
-		      else
-			{
-			  if (conversion == 'g')
-			    {
-			      char *dot = strchr (sprintf_buf, '.');
-			      if (!dot)
-				excess_precision = 0;
-			    }
-			}
-		      trailing_zeros = excess_precision;
-		    }
-		  else
-		    leading_zeros = excess_precision;
-		}
-
-	      /* Compute the total bytes needed for this item, including
-		 excess precision and padding.  */
-	      ptrdiff_t numwidth;
-	      if (INT_ADD_WRAPV (sprintf_bytes, excess_precision, &numwidth))
-		numwidth = PTRDIFF_MAX;
-	      ptrdiff_t padding
-		= numwidth < field_width ? field_width - numwidth : 0;
-	      if (max_bufsize - sprintf_bytes <= excess_precision
-		  || max_bufsize - padding <= numwidth)
-		string_overflow ();
-	      convbytes = numwidth + padding;
-
-	      if (convbytes <= buf + bufsize - p)
-		{
-		  /* Copy the formatted item from sprintf_buf into buf,
-		     inserting padding and excess-precision zeros.  */
-
-                  char *src = sprintf_buf;
-		  char src0 = src[0];
-		  int exponent_bytes = 0;
-		  bool signedp = src0 == '-' || src0 == '+' || src0 == ' ';
-		  int prefix_bytes = (signedp
-				      + ((src[signedp] == '0'
-					  && (src[signedp + 1] == 'x'
-					      || src[signedp + 1] == 'X'))
-					 ? 2 : 0));
-		  if (zero_flag)
-		    {
-		      unsigned char after_prefix = src[prefix_bytes];
-		      if (0 <= char_hexdigit (after_prefix))
-			{
-			  leading_zeros += padding;
-			  padding = 0;
-			}
-		    }
-
-		  if (excess_precision
-		      && (conversion == 'e' || conversion == 'g'))
-		    {
-		      char *e = strchr (src, 'e');
-		      if (e)
-			exponent_bytes = src + sprintf_bytes - e;
-		    }
-
-		  spec->start = nchars;
-		  if (! minus_flag)
-		    {
-		      memset (p, ' ', padding);
-		      p += padding;
-		      nchars += padding;
-		    }
-
-		  memcpy (p, src, prefix_bytes);
-		  p += prefix_bytes;
-		  src += prefix_bytes;
-		  memset (p, '0', leading_zeros);
-		  p += leading_zeros;
-		  int significand_bytes
-		    = sprintf_bytes - prefix_bytes - exponent_bytes;
-		  memcpy (p, src, significand_bytes);
-                  p += significand_bytes;
-		  src += significand_bytes;
-		  memset (p, '0', trailing_zeros);
-		  p += trailing_zeros;
-		  memcpy (p, src, exponent_bytes);
-		  p += exponent_bytes;
-
-		  nchars += leading_zeros + sprintf_bytes + trailing_zeros;
-
-		  if (minus_flag)
-		    {
-		      memset (p, ' ', padding);
-		      p += padding;
-		      nchars += padding;
-		    }
-		  spec->end = nchars;
-
-		  new_result = true;
-		  continue;
-		}
-	    }
-	}
-      else
-	{
-	  unsigned char str[MAX_MULTIBYTE_LENGTH];
-
-	  if ((format_char == '`' || format_char == '\'')
-	      && quoting_style == CURVE_QUOTING_STYLE)
-	    {
-	      if (! multibyte)
-		{
-		  multibyte = true;
-		  goto retry;
-		}
-	      convsrc = format_char == '`' ? uLSQM : uRSQM;
-	      convbytes = 3;
-	      new_result = true;
-	    }
-	  else if (format_char == '`' && quoting_style == STRAIGHT_QUOTING_STYLE)
-	    {
-	      convsrc = "'";
-	      new_result = true;
-	    }
-	  else
-	    {
-	      /* Copy a single character from format to buf.  */
-	      if (multibyte_format)
-		{
-		  /* Copy a whole multibyte character.  */
-		  if (p > buf
-		      && !ASCII_CHAR_P (*((unsigned char *) p - 1))
-		      && !CHAR_HEAD_P (format_char))
-		    maybe_combine_byte = true;
-
-		  while (! CHAR_HEAD_P (*format))
-		    format++;
-
-		  convbytes = format - format0;
-		  memset (&discarded[format0 + 1 - format_start], 2,
-			  convbytes - 1);
-		}
-	      else if (multibyte && !ASCII_CHAR_P (format_char))
-		{
-		  int c = BYTE8_TO_CHAR (format_char);
-		  convbytes = CHAR_STRING (c, str);
-		  convsrc = (char *) str;
-		  new_result = true;
-		}
-	    }
-
-	copy_char:
-	  if (convbytes <= buf + bufsize - p)
-	    {
-	      memcpy (p, convsrc, convbytes);
-	      p += convbytes;
-	      nchars++;
-	      continue;
-	    }
-	}
-
-      /* There wasn't enough room to store this conversion or single
-	 character.  CONVBYTES says how much room is needed.  Allocate
-	 enough room (and then some) and do it again.  */
-
-      ptrdiff_t used = p - buf;
-      if (max_bufsize - used < convbytes)
-	string_overflow ();
-      bufsize = used + convbytes;
-      bufsize = bufsize < max_bufsize / 2 ? bufsize * 2 : max_bufsize;
-
-      if (buf == initial_buffer)
-	{
-	  buf = xmalloc (bufsize);
-	  sa_must_free = true;
-	  buf_save_value_index = SPECPDL_INDEX ();
-	  record_unwind_protect_ptr (xfree, buf);
-	  memcpy (buf, initial_buffer, used);
-	}
-      else
-	{
-	  buf = xrealloc (buf, bufsize);
-	  set_unwind_protect_ptr (buf_save_value_index, xfree, buf);
-	}
-
-      p = buf + used;
-      format = format0;
-      n = n0;
-      ispec = ispec0;
-    }
-
-  if (bufsize < p - buf)
-    emacs_abort ();
-
-  if (! new_result)
-    {
-      val = args[0];
-      goto return_val;
-    }
-
-  if (maybe_combine_byte)
-    nchars = multibyte_chars_in_text ((unsigned char *) buf, p - buf);
-  val = make_specified_string (buf, nchars, p - buf, multibyte);
-
-  /* If the format string has text properties, or any of the string
-     arguments has text properties, set up text properties of the
-     result string.  */
-
-  if (string_intervals (args[0]) || arg_intervals)
-    {
-      /* Add text properties from the format string.  */
-      Lisp_Object len = make_number (SCHARS (args[0]));
-      Lisp_Object props = text_property_list (args[0], make_number (0),
-					      len, Qnil);
-      if (CONSP (props))
-	{
-	  ptrdiff_t bytepos = 0, position = 0, translated = 0;
-	  ptrdiff_t fieldn = 0;
-
-	  /* Adjust the bounds of each text property
-	     to the proper start and end in the output string.  */
-
-	  /* Put the positions in PROPS in increasing order, so that
-	     we can do (effectively) one scan through the position
-	     space of the format string.  */
-	  props = Fnreverse (props);
-
-	  /* BYTEPOS is the byte position in the format string,
-	     POSITION is the untranslated char position in it,
-	     TRANSLATED is the translated char position in BUF,
-	     and ARGN is the number of the next arg we will come to.  */
-	  for (Lisp_Object list = props; CONSP (list); list = XCDR (list))
-	    {
-	      Lisp_Object item = XCAR (list);
-
-	      /* First adjust the property start position.  */
-	      ptrdiff_t pos = XINT (XCAR (item));
-
-	      /* Advance BYTEPOS, POSITION, TRANSLATED and ARGN
-		 up to this position.  */
-	      for (; position < pos; bytepos++)
-		{
-		  if (! discarded[bytepos])
-		    position++, translated++;
-		  else if (discarded[bytepos] == 1)
-		    {
-		      position++;
-		      if (fieldn < nspec && translated == info[fieldn].start)
-			{
-			  translated += info[fieldn].end - info[fieldn].start;
-			  fieldn++;
-			}
-		    }
-		}
-
-	      XSETCAR (item, make_number (translated));
-
-	      /* Likewise adjust the property end position.  */
-	      pos = XINT (XCAR (XCDR (item)));
-
-	      for (; position < pos; bytepos++)
-		{
-		  if (! discarded[bytepos])
-		    position++, translated++;
-		  else if (discarded[bytepos] == 1)
-		    {
-		      position++;
-		      if (fieldn < nspec && translated == info[fieldn].start)
-			{
-			  translated += info[fieldn].end - info[fieldn].start;
-			  fieldn++;
-			}
-		    }
-		}
-
-	      XSETCAR (XCDR (item), make_number (translated));
-	    }
-
-	  add_text_properties_from_list (val, props, make_number (0));
-	}
-
-      /* Add text properties from arguments.  */
-      if (arg_intervals)
-	for (ptrdiff_t i = 0; i < nspec; i++)
-	  if (info[i].intervals)
-	    {
-	      len = make_number (SCHARS (info[i].argument));
-	      Lisp_Object new_len = make_number (info[i].end - info[i].start);
-	      props = text_property_list (info[i].argument,
-                                          make_number (0), len, Qnil);
-	      props = extend_property_ranges (props, len, new_len);
-	      /* If successive arguments have properties, be sure that
-		 the value of `composition' property be the copy.  */
-	      if (1 < i && info[i - 1].end)
-		make_composition_value_copy (props);
-	      add_text_properties_from_list (val, props,
-					     make_number (info[i].start));
-	    }
-    }
-
- return_val:
-  /* If we allocated BUF or INFO with malloc, free it too.  */
-  SAFE_FREE ();
-
-  return val;
-}
-
 /* Transpose the markers in two regions of the current buffer, and
    adjust the ones between them if necessary (i.e.: if the regions
    differ in size).
@@ -3762,6 +2879,5 @@ functions if all the text being accessed has this property.  */);
   defsubr (&Sreplace_buffer_contents);
   defsubr (&Ssubst_char_in_region);
   defsubr (&Stranslate_region_internal);
-  defsubr (&Snarrow_to_region);
   defsubr (&Stranspose_regions);
 }
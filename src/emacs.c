@@ -1216,10 +1216,6 @@ Using an Emacs configured with --with-x-toolkit=lucid does not have this problem
   running_asynch_code = 0;
   init_random ();
 
-#if defined HAVE_JSON && !defined WINDOWSNT
-  init_json ();
-#endif
-
   no_loadup
     = argmatch (argv, argc, "-nl", "--no-loadup", 6, NULL, &skip_args);
 
@@ -1423,6 +1419,7 @@ Using an Emacs configured with --with-x-toolkit=lucid does not have this problem
       syms_of_print ();
       syms_of_eval ();
 
+      syms_of_bitset ();
       syms_of_buffer ();
       syms_of_bytecode ();
       syms_of_callint ();
@@ -1432,6 +1429,12 @@ Using an Emacs configured with --with-x-toolkit=lucid does not have this problem
       syms_of_ccl ();
       syms_of_character ();
       syms_of_cmds ();
+      syms_of_collections ();
+      syms_of_persistent ();
+      syms_of_weak ();
+      syms_of_typed_vector ();
+      syms_of_crypto ();
+      syms_of_decompress ();
       syms_of_dired ();
       syms_of_display ();
       syms_of_doc ();
@@ -1519,6 +1522,10 @@ Using an Emacs configured with --with-x-toolkit=lucid does not have this problem
 
       syms_of_gnutls ();
 
+#ifdef HAVE_RUSTNOTIFY
+      syms_of_filenotify ();
+#endif /* HAVE_RUSTNOTIFY */
+
 #ifdef HAVE_INOTIFY
       syms_of_inotify ();
 #endif /* HAVE_INOTIFY */
@@ -1545,9 +1552,18 @@ Using an Emacs configured with --with-x-toolkit=lucid does not have this problem
       syms_of_threads ();
       syms_of_profiler ();
 
-#ifdef HAVE_JSON
       syms_of_json ();
-#endif
+      syms_of_yaml ();
+      syms_of_toml ();
+      syms_of_binary_serialization ();
+      syms_of_csv ();
+      syms_of_diff ();
+      syms_of_chars ();
+      syms_of_charprop ();
+      syms_of_kill_ring ();
+      syms_of_fill ();
+      syms_of_sort ();
+      syms_of_paragraphs ();
 
       keys_of_casefiddle ();
       keys_of_cmds ();
@@ -56,11 +56,6 @@ along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.  */
 #include "region-cache.h"
 #include "frame.h"
 
-#ifdef HAVE_LINUX_FS_H
-# include <sys/ioctl.h>
-# include <linux/fs.h>
-#endif
-
 #ifdef WINDOWSNT
 #define NOMINMAX 1
 #include <windows.h>
@@ -1790,15 +1785,9 @@ barf_or_query_if_file_exists (Lisp_Object absname, bool known_to_exist,
 }
 
 #ifndef WINDOWSNT
-/* Copy data to DEST from SOURCE if possible.  Return true if OK.  */
-static bool
-clone_file (int dest, int source)
-{
-#ifdef FICLONE
-  return ioctl (dest, FICLONE, source) == 0;
-#endif
-  return false;
-}
+/* Defined in fileio.rs.  */
+extern off_t copy_file_contents (int, int, off_t);
+extern int copy_file_xattrs (int, int);
 #endif
 
 DEFUN ("copy-file", Fcopy_file, Scopy_file, 2, 6,
@@ -1828,10 +1817,14 @@ If PRESERVE-UID-GID is non-nil, try to transfer the uid and gid of
 FILE to NEWNAME.
 
 If PRESERVE-PERMISSIONS is non-nil, copy permissions of FILE to NEWNAME;
-this includes the file modes, along with ACL entries and SELinux
-context if present.  Otherwise, if NEWNAME is created its file
-permission bits are those of FILE, masked by the default file
-permissions.  */)
+this includes the file modes, along with ACL entries, SELinux context
+and user extended attributes if present.  Otherwise, if NEWNAME is
+created its file permission bits are those of FILE, masked by the
+default file permissions.
+
+The contents of FILE are shared with NEWNAME or copied by the kernel
+where the file system allows it.  `copy-file-progress-function' is
+called as they are copied.  */)
   (Lisp_Object file, Lisp_Object newname, Lisp_Object ok_if_already_exists,
    Lisp_Object keep_time, Lisp_Object preserve_uid_gid,
    Lisp_Object preserve_permissions)
@@ -1951,19 +1944,11 @@ permissions.  */)
 
   maybe_quit ();
 
-  if (clone_file (ofd, ifd))
-    newsize = st.st_size;
-  else
-    {
-      char buf[MAX_ALLOCA];
-      ptrdiff_t n;
-      for (newsize = 0; 0 < (n = emacs_read_quit (ifd, buf, sizeof buf));
-	   newsize += n)
-	if (emacs_write_quit (ofd, buf, n) != n)
-	  report_file_error ("Write error", newname);
-      if (n < 0)
-	report_file_error ("Read error", file);
-    }
+  newsize = copy_file_contents (ifd, ofd, st.st_size);
+  if (newsize == -2)
+    report_file_error ("Write error", newname);
+  if (newsize < 0)
+    report_file_error ("Read error", file);
 
   /* Truncate any existing output file after writing the data.  This
      is more likely to work than truncation before writing, if the
@@ -2013,6 +1998,13 @@ permissions.  */)
       case -2: report_file_error ("Copying permissions from", file);
       case -1: report_file_error ("Copying permissions to", newname);
       }
+
+    if (!NILP (preserve_permissions))
+      switch (copy_file_xattrs (ifd, ofd))
+	{
+	case -2: report_file_error ("Copying extended attributes from", file);
+	case -1: report_file_error ("Copying extended attributes to", newname);
+	}
   }
 
 #if HAVE_LIBSELINUX
@@ -3216,6 +3208,18 @@ read_non_regular (Lisp_Object state)
 }
 
 
+/* Defined in fileio.rs.  */
+extern bool read_file_mapped_p (off_t);
+extern unsigned char *map_file_for_read (int, off_t, ptrdiff_t);
+extern bool unmap_file_for_read (unsigned char *);
+extern void rust_syms_of_fileio (void);
+
+static void
+unmap_file_for_read_unwind (void *addr)
+{
+  unmap_file_for_read (addr);
+}
+
 /* Condition-case handler used when reading from non-regular files
    in insert-file-contents.  */
 
@@ -3377,6 +3381,11 @@ by calling `format-decode', which see.  */)
   Lisp_Object old_Vdeactivate_mark = Vdeactivate_mark;
   bool we_locked_file = false;
   ptrdiff_t fd_index;
+  /* Whether to decode the file straight from a mapping of it, and the
+     mapping.  */
+  bool map_file = false;
+  unsigned char *mapped_text = NULL;
+  ptrdiff_t mapped_index UNINIT;
   Lisp_Object window_markers = Qnil;
   /* same_at_start and same_at_end count bytes, because file access counts
      bytes and BEG and END count bytes.  */
@@ -3521,6 +3530,11 @@ by calling `format-decode', which see.  */)
 	  if (buf_growth_max < likely_growth)
 	    buffer_overflow ();
 	}
+
+      /* A large file is decoded straight from a mapping of it, so
+	 that it is never copied into the buffer undecoded.  */
+      map_file = (NILP (replace)
+		  && read_file_mapped_p (likely_end - beg_offset));
     }
 
   /* Prevent redisplay optimizations.  */
@@ -3533,11 +3547,12 @@ by calling `format-decode', which see.  */)
       /* Ensure we set Vlast_coding_system_used.  */
       set_coding_system = true;
     }
-  else if (BEG < Z)
+  else if (BEG < Z || map_file)
     {
       /* Decide the coding system to use for reading the file now
          because we can't use an optimized method for handling
-         `coding:' tag if the current buffer is not empty.  */
+         `coding:' tag if the current buffer is not empty, or if the
+         file will not be read into it before being decoded.  */
       if (!NILP (Vcoding_system_for_read))
 	coding_system = Vcoding_system_for_read;
       else
@@ -4022,8 +4037,20 @@ by calling `format-decode', which see.  */)
       prepare_to_modify_buffer (PT, PT, NULL);
     }
 
+  /* A file to decode from a mapping of it is not read into the gap,
+     unless it can't be mapped.  */
+  if (map_file)
+    {
+      mapped_text = map_file_for_read (fd, beg_offset, total);
+      if (mapped_text)
+	{
+	  mapped_index = SPECPDL_INDEX ();
+	  record_unwind_protect_ptr (unmap_file_for_read_unwind, mapped_text);
+	}
+    }
+
   move_gap_both (PT, PT_BYTE);
-  if (GAP_SIZE < total)
+  if (GAP_SIZE < total && ! mapped_text)
     make_gap (total - GAP_SIZE);
 
   if (beg_offset != 0 || !NILP (replace))
@@ -4035,8 +4062,8 @@ by calling `format-decode', which see.  */)
   /* In the following loop, HOW_MUCH contains the total bytes read so
      far for a regular file, and not changed for a special file.  But,
      before exiting the loop, it is set to a negative value if I/O
-     error occurs.  */
-  how_much = 0;
+     error occurs.  A mapped file is not read at all.  */
+  how_much = mapped_text ? total : 0;
 
   /* Total bytes inserted.  */
   inserted = 0;
@@ -4113,7 +4140,7 @@ by calling `format-decode', which see.  */)
      or stop reading on I/O error or quit.  If nothing was
      read, undo marking the buffer modified.  */
 
-  if (inserted == 0)
+  if (inserted == 0 && ! mapped_text)
     {
       if (we_locked_file)
 	unlock_file (BVAR (current_buffer, file_truename));
@@ -4222,8 +4249,29 @@ by calling `format-decode', which see.  */)
     }
 
   coding.dst_multibyte = ! NILP (BVAR (current_buffer, enable_multibyte_characters));
-  if (CODING_MAY_REQUIRE_DECODING (&coding)
-      && (inserted > 0 || CODING_REQUIRE_FLUSHING (&coding)))
+  if (mapped_text)
+    {
+      /* Decode the file into the buffer at point, as decode_coding_gap
+	 does, but reading the mapping, whose pages are only read from
+	 the file as the decoder gets to them.  */
+      decode_coding_c_string (&coding, mapped_text, total,
+			      Fcurrent_buffer ());
+      inserted = coding.produced_char;
+      coding_system = CODING_ID_NAME (coding.id);
+      clear_unwind_protect (mapped_index);
+      if (! unmap_file_for_read (mapped_text))
+	{
+	  /* Some of the text was zeros standing for what the file lost
+	     while it was decoded.  */
+	  del_range_both (PT, PT_BYTE, PT + inserted,
+			  PT_BYTE + coding.produced, false);
+	  xsignal2 (Qfile_error,
+		    build_string ("file shrank while being read"),
+		    orig_filename);
+	}
+    }
+  else if (CODING_MAY_REQUIRE_DECODING (&coding)
+	   && (inserted > 0 || CODING_REQUIRE_FLUSHING (&coding)))
     {
       move_gap_both (PT, PT_BYTE);
       GAP_SIZE += inserted;
@@ -4624,6 +4672,18 @@ This calls `write-region-annotate-functions' at the start, and
 		       -1);
 }
 
+extern int atomic_write_open (Lisp_Object, int);
+extern bool atomic_write_commit (int);
+extern void atomic_write_discard (int);
+
+/* Close the temporary file FD of an atomic write, and remove it.  */
+static void
+close_atomic_file_unwind (int fd)
+{
+  emacs_close (fd);
+  atomic_write_discard (fd);
+}
+
 /* Like Fwrite_region, except that if DESC is nonnegative, it is a file
    descriptor for FILENAME, so do not open or close FILENAME.  */
 
@@ -4650,6 +4710,7 @@ write_region (Lisp_Object start, Lisp_Object end, Lisp_Object filename,
   bool visiting = (EQ (visit, Qt) || STRINGP (visit));
   bool quietly = !NILP (visit);
   bool file_locked = 0;
+  bool atomic = false;
   struct buffer *given_buffer;
   struct coding_system coding;
 
@@ -4765,7 +4826,16 @@ write_region (Lisp_Object start, Lisp_Object end, Lisp_Object filename,
 
   if (open_and_close_file)
     {
-      desc = emacs_open (fn, open_flags, mode);
+      /* Write a temporary file to rename over FILENAME, if asked to and
+	 if FILENAME can be replaced that way.  */
+      if (write_region_atomic && !auto_saving && NILP (append)
+	  && !EQ (mustbenew, Qexcl))
+	{
+	  desc = atomic_write_open (encoded_filename, mode);
+	  atomic = desc >= 0;
+	}
+      if (!atomic)
+	desc = emacs_open (fn, open_flags, mode);
       if (desc < 0)
 	{
 	  int open_errno = errno;
@@ -4775,7 +4845,9 @@ write_region (Lisp_Object start, Lisp_Object end, Lisp_Object filename,
 	}
 
       count1 = SPECPDL_INDEX ();
-      record_unwind_protect_int (close_file_unwind, desc);
+      record_unwind_protect_int (atomic ? close_atomic_file_unwind
+				 : close_file_unwind,
+				 desc);
     }
 
   if (NUMBERP (append))
@@ -4814,13 +4886,16 @@ write_region (Lisp_Object start, Lisp_Object end, Lisp_Object filename,
 
   /* fsync is not crucial for temporary files.  Nor for auto-save
      files, since they might lose some work anyway.  */
-  if (open_and_close_file && !auto_saving && !write_region_inhibit_fsync)
+  if (open_and_close_file && !auto_saving && !write_region_inhibit_fsync
+      && !EQ (Vwrite_region_sync_mode, Qnone))
     {
       /* Transfer data and metadata to disk, retrying if interrupted.
+	 Only transfer data if `write-region-sync-mode' says so.
 	 fsync can report a write failure here, e.g., due to disk full
 	 under NFS.  But ignore EINVAL, which means fsync is not
 	 supported on this file.  */
-      while (fsync (desc) != 0)
+      while ((EQ (Vwrite_region_sync_mode, Qdata)
+	      ? fdatasync (desc) : fsync (desc)) != 0)
 	if (errno != EINTR)
 	  {
 	    if (errno != EINVAL)
@@ -4844,6 +4919,15 @@ write_region (Lisp_Object start, Lisp_Object end, Lisp_Object filename,
       if (emacs_close (desc) < 0)
 	ok = 0, save_errno = errno;
 
+      /* Only replace FILENAME once all of the text is safely written.  */
+      if (atomic)
+	{
+	  if (!ok)
+	    atomic_write_discard (desc);
+	  else if (!atomic_write_commit (desc))
+	    ok = 0, save_errno = errno;
+	}
+
       /* Discard the unwind protect for close_file_unwind.  */
       specpdl_ptr = specpdl + count1;
     }
@@ -6068,4 +6152,6 @@ This includes interactive calls to `delete-file' and
 #ifdef HAVE_SYNC
   defsubr (&Sunix_sync);
 #endif
+
+  rust_syms_of_fileio ();
 }
@@ -25,16 +25,8 @@ along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.  */
 #include <config.h>
 #include <sys/types.h>
 #include <sys/stat.h>
-#include <signal.h>
 #include <stdio.h>
 #include <stdlib.h>
-
-#ifdef HAVE_PWD_H
-#include <pwd.h>
-#endif
-
-#include <sys/file.h>
-#include <fcntl.h>
 #include <unistd.h>
 
 #ifdef __FreeBSD__
@@ -43,23 +35,22 @@ along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.  */
 
 #include <errno.h>
 
-#include <c-ctype.h>
-
 #include "lisp.h"
-#include "buffer.h"
-#include "coding.h"
+
 #ifdef WINDOWSNT
+#include <c-ctype.h>
 #include <share.h>
 #include <sys/socket.h>	/* for fcntl */
+#include "buffer.h"
+#include "coding.h"
 #include "w32.h"	/* for dostounix_filename */
+#include "remacs-lib.h"
 #endif
 
 #ifdef HAVE_UTMP_H
 #include <utmp.h>
 #endif
 
-#include "remacs-lib.h"
-
 /* A file whose last-modified time is just after the most recent boot.
    Define this to be NULL to disable checking for this file.  */
 #ifndef BOOT_TIME_FILE
@@ -70,58 +61,11 @@ along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.  */
 #define WTMP_FILE "/var/log/wtmp"
 #endif
 
-/* Normally use a symbolic link to represent a lock.
-   The strategy: to lock a file FN, create a symlink .#FN in FN's
-   directory, with link data USER@HOST.PID:BOOT.  This avoids a single
-   mount (== failure) point for lock files.  The :BOOT is omitted if
-   the boot time is not available.
-
-   When the host in the lock data is the current host, we can check if
-   the pid is valid with kill.
-
-   Otherwise, we could look at a separate file that maps hostnames to
-   reboot times to see if the remote pid can possibly be valid, since we
-   don't want Emacs to have to communicate via pipes or sockets or
-   whatever to other processes, either locally or remotely; rms says
-   that's too unreliable.  Hence the separate file, which could
-   theoretically be updated by daemons running separately -- but this
-   whole idea is unimplemented; in practice, at least in our
-   environment, it seems such stale locks arise fairly infrequently, and
-   Emacs' standard methods of dealing with clashes suffice.
-
-   We use symlinks instead of normal files because (1) they can be
-   stored more efficiently on the filesystem, since the kernel knows
-   they will be small, and (2) all the info about the lock can be read
-   in a single system call (readlink).  Although we could use regular
-   files to be useful on old systems lacking symlinks, nowadays
-   virtually all such systems are probably single-user anyway, so it
-   didn't seem worth the complication.
-
-   Similarly, we don't worry about a possible 14-character limit on
-   file names, because those are all the same systems that don't have
-   symlinks.
-
-   This is compatible with the locking scheme used by Interleaf (which
-   has contributed this implementation for Emacs), and was designed by
-   Karl Berry, Ethan Jacobson, Kimbo Mundy, and others.
-
-   On some file systems, notably those of MS-Windows, symbolic links
-   do not work well, so instead of a symlink .#FN -> USER@HOST.PID:BOOT,
-   the lock is a regular file .#FN with contents USER@HOST.PID:BOOT.  To
-   establish a lock, a nonce file is created and then renamed to .#FN.
-   On MS-Windows this renaming is atomic unless the lock is forcibly
-   acquired.  On other systems the renaming is atomic if the lock is
-   forcibly acquired; if not, the renaming is done via hard links,
-   which is good enough for lock-file purposes.
-
-   To summarize, race conditions can occur with either:
-
-   * Forced locks on MS-Windows systems.
-
-   * Non-forced locks on non-MS-Windows systems that support neither
-     hard nor symbolic links.  */
+/* The lock files themselves are made in filelock.rs, which describes
+   them, except on MS-Windows, where they are made at the end of this
+   file.  The boot time recorded in their contents is found here.  */
+
 
-
 /* Return the time of the last system boot.  */
 
 static time_t boot_time;
@@ -131,7 +75,7 @@ static bool boot_time_initialized;
 static void get_boot_time_1 (const char *, bool);
 #endif
 
-static time_t
+time_t
 get_boot_time (void)
 {
 #if defined (BOOT_TIME)
@@ -277,6 +221,8 @@ get_boot_time_1 (const char *filename, bool newest)
 }
 #endif /* BOOT_TIME */
 
+#ifdef WINDOWSNT
+
 /* An arbitrary limit on lock contents length.  8 K should be plenty
    big enough in practice.  */
 enum { MAX_LFINFO = 8 * 1024 };
@@ -316,51 +262,13 @@ fill_in_lock_file_name (char *lockfile, Lisp_Object fn)
   strcpy (lockfile + dirlen + 2, base);
 }
 
-/* For some reason Linux kernels return EPERM on file systems that do
-   not support hard or symbolic links.  This symbol documents the quirk.
-   There is no way to tell whether a symlink call fails due to
-   permissions issues or because links are not supported, but luckily
-   the lock file code should work either way.  */
-enum { LINKS_MIGHT_NOT_WORK = EPERM };
-
 /* Rename OLD to NEW.  If FORCE, replace any existing NEW.
    It is OK if there are temporarily two hard links to OLD.
    Return 0 if successful, -1 (setting errno) otherwise.  */
 static int
 rename_lock_file (char const *old, char const *new, bool force)
 {
-#ifdef WINDOWSNT
   return sys_rename_replace (old, new, force);
-#else
-  if (! force)
-    {
-      struct stat st;
-
-      int r = renameat_noreplace (AT_FDCWD, old, AT_FDCWD, new);
-      if (! (r < 0 && errno == ENOSYS))
-	return r;
-      if (link (old, new) == 0)
-	return unlink (old) == 0 || errno == ENOENT ? 0 : -1;
-      if (errno != ENOSYS && errno != LINKS_MIGHT_NOT_WORK)
-	return -1;
-
-      /* 'link' does not work on this file system.  This can occur on
-	 a GNU/Linux host mounting a FAT32 file system.  Fall back on
-	 'rename' after checking that NEW does not exist.  There is a
-	 potential race condition since some other process may create
-	 NEW immediately after the existence check, but it's the best
-	 we can portably do here.  */
-      if (lstat (new, &st) == 0 || errno == EOVERFLOW)
-	{
-	  errno = EEXIST;
-	  return -1;
-	}
-      if (errno != ENOENT)
-	return -1;
-    }
-
-  return rename (old, new);
-#endif
 }
 
 /* Create the lock file LFNAME with contents LOCK_INFO_STR.  Return 0 if
@@ -370,57 +278,41 @@ rename_lock_file (char const *old, char const *new, bool force)
 static int
 create_lock_file (char *lfname, char *lock_info_str, bool force)
 {
-#ifdef WINDOWSNT
   /* Symlinks are supported only by later versions of Windows, and
      creating them is a privileged operation that often triggers
      User Account Control elevation prompts.  Avoid the problem by
-     pretending that 'symlink' does not work.  */
-  int err = ENOSYS;
-#else
-  int err = symlink (lock_info_str, lfname) == 0 ? 0 : errno;
-#endif
-
-  if (err == EEXIST && force)
-    {
-      unlink (lfname);
-      err = symlink (lock_info_str, lfname) == 0 ? 0 : errno;
-    }
-
-  if (err == ENOSYS || err == LINKS_MIGHT_NOT_WORK || err == ENAMETOOLONG)
+     always making a regular file.  */
+  static char const nonce_base[] = ".#-emacsXXXXXX";
+  char *last_slash = strrchr (lfname, '/');
+  ptrdiff_t lfdirlen = last_slash + 1 - lfname;
+  USE_SAFE_ALLOCA;
+  char *nonce = SAFE_ALLOCA (lfdirlen + sizeof nonce_base);
+  int fd, err;
+  memcpy (nonce, lfname, lfdirlen);
+  strcpy (nonce + lfdirlen, nonce_base);
+
+  fd = rust_make_temp (nonce, O_BINARY | O_CLOEXEC);
+  if (fd < 0)
+    err = errno;
+  else
     {
-      static char const nonce_base[] = ".#-emacsXXXXXX";
-      char *last_slash = strrchr (lfname, '/');
-      ptrdiff_t lfdirlen = last_slash + 1 - lfname;
-      USE_SAFE_ALLOCA;
-      char *nonce = SAFE_ALLOCA (lfdirlen + sizeof nonce_base);
-      int fd;
-      memcpy (nonce, lfname, lfdirlen);
-      strcpy (nonce + lfdirlen, nonce_base);
-
-      fd = rust_make_temp (nonce, O_BINARY | O_CLOEXEC);
-      if (fd < 0)
+      ptrdiff_t lock_info_len;
+      lock_info_len = strlen (lock_info_str);
+      err = 0;
+      if (emacs_write (fd, lock_info_str, lock_info_len) != lock_info_len
+	  || fchmod (fd, S_IRUSR | S_IRGRP | S_IROTH) != 0)
 	err = errno;
-      else
-	{
-	  ptrdiff_t lock_info_len;
-	  lock_info_len = strlen (lock_info_str);
-	  err = 0;
-	  if (emacs_write (fd, lock_info_str, lock_info_len) != lock_info_len
-	      || fchmod (fd, S_IRUSR | S_IRGRP | S_IROTH) != 0)
-	    err = errno;
-	  /* There is no need to call fsync here, as the contents of
-	     the lock file need not survive system crashes.  */
-	  if (emacs_close (fd) != 0)
-	    err = errno;
-	  if (!err && rename_lock_file (nonce, lfname, force) != 0)
-	    err = errno;
-	  if (err)
-	    unlink (nonce);
-	}
-
-      SAFE_FREE ();
+      /* There is no need to call fsync here, as the contents of
+	 the lock file need not survive system crashes.  */
+      if (emacs_close (fd) != 0)
+	err = errno;
+      if (!err && rename_lock_file (nonce, lfname, force) != 0)
+	err = errno;
+      if (err)
+	unlink (nonce);
     }
 
+  SAFE_FREE ();
   return err;
 }
 
@@ -671,12 +563,10 @@ lock_file (Lisp_Object fn)
 
   orig_fn = fn;
   fn = Fexpand_file_name (fn, Qnil);
-#ifdef WINDOWSNT
   /* Ensure we have only '/' separators, to avoid problems with
      looking (inside fill_in_lock_file_name) for backslashes in file
      names encoded by some DBCS codepage.  */
   dostounix_filename (SSDATA (fn));
-#endif
   encoded_fn = ENCODE_FILE (fn);
 
   /* See if this file is visited and has changed on disk since it was
@@ -754,38 +644,6 @@ unlock_all_files (void)
     }
 }
 
-DEFUN ("lock-buffer", Flock_buffer, Slock_buffer,
-       0, 1, 0,
-       doc: /* Lock FILE, if current buffer is modified.
-FILE defaults to current buffer's visited file,
-or else nothing is done if current buffer isn't visiting a file.
-
-If the option `create-lockfiles' is nil, this does nothing.  */)
-  (Lisp_Object file)
-{
-  if (NILP (file))
-    file = BVAR (current_buffer, file_truename);
-  else
-    CHECK_STRING (file);
-  if (SAVE_MODIFF < MODIFF
-      && !NILP (file))
-    lock_file (file);
-  return Qnil;
-}
-
-DEFUN ("unlock-buffer", Funlock_buffer, Sunlock_buffer,
-       0, 0, 0,
-       doc: /* Unlock the file visited in the current buffer.
-If the buffer is not modified, this does nothing because the file
-should not be locked in that case.  */)
-  (void)
-{
-  if (SAVE_MODIFF < MODIFF
-      && STRINGP (BVAR (current_buffer, file_truename)))
-    unlock_file (BVAR (current_buffer, file_truename));
-  return Qnil;
-}
-
 /* Unlock the file visited in buffer BUFFER.  */
 
 void
@@ -796,11 +654,13 @@ unlock_buffer (struct buffer *buffer)
     unlock_file (BVAR (buffer, file_truename));
 }
 
-DEFUN ("file-locked-p", Ffile_locked_p, Sfile_locked_p, 1, 1, 0,
-       doc: /* Return a value indicating whether FILENAME is locked.
-The value is nil if the FILENAME is not locked,
-t if it is locked by you, else a string saying which user has locked it.  */)
-  (Lisp_Object filename)
+/* Return a value indicating whether the file FILENAME, an absolute
+   file name, is locked, for `file-locked-p': nil if it is not locked,
+   t if it is locked by this Emacs, else the name of the user who has
+   locked it.  */
+
+Lisp_Object
+file_lock_owner (Lisp_Object filename)
 {
   Lisp_Object ret;
   char *lfname;
@@ -808,8 +668,6 @@ t if it is locked by you, else a string saying which user has locked it.  */)
   lock_info_type locker;
   USE_SAFE_ALLOCA;
 
-  filename = Fexpand_file_name (filename, Qnil);
-
   MAKE_LOCK_NAME (lfname, filename);
 
   owner = current_lock_owner (&locker, lfname);
@@ -824,6 +682,8 @@ t if it is locked by you, else a string saying which user has locked it.  */)
   return ret;
 }
 
+#endif /* WINDOWSNT */
+
 void
 syms_of_filelock (void)
 {
@@ -834,8 +694,4 @@ syms_of_filelock (void)
   DEFVAR_BOOL ("create-lockfiles", create_lockfiles,
 	       doc: /* Non-nil means use lockfiles to avoid editing collisions.  */);
   create_lockfiles = 1;
-
-  defsubr (&Sunlock_buffer);
-  defsubr (&Slock_buffer);
-  defsubr (&Sfile_locked_p);
 }
@@ -1815,136 +1815,6 @@ The data read from the system are decoded using `locale-coding-system'.  */)
 #endif	/* HAVE_LANGINFO_CODESET*/
   return Qnil;
 }
-
-/* base64 encode/decode functions (RFC 2045).
-   Based on code from GNU recode. */
-
-ptrdiff_t base64_encode_1 (const char *, ptrdiff_t, char *, ptrdiff_t, bool, bool);
-ptrdiff_t base64_decode_1 (const char *, ptrdiff_t, char *, ptrdiff_t, bool,
-                           ptrdiff_t *);
-ptrdiff_t pad_base64_size(ptrdiff_t len);
-ptrdiff_t compute_decode_size(ptrdiff_t len);
-ptrdiff_t compute_encode_size(ptrdiff_t len);
-
-DEFUN ("base64-encode-region", Fbase64_encode_region, Sbase64_encode_region,
-       2, 3, "r",
-       doc: /* Base64-encode the region between BEG and END.
-Return the length of the encoded text.
-Optional third argument NO-LINE-BREAK means do not break long lines
-into shorter lines.  */)
-  (Lisp_Object beg, Lisp_Object end, Lisp_Object no_line_break)
-{
-  char *encoded;
-  ptrdiff_t allength, length;
-  ptrdiff_t ibeg, iend, encoded_length;
-  ptrdiff_t old_pos = PT;
-  USE_SAFE_ALLOCA;
-
-  validate_region (&beg, &end);
-
-  ibeg = CHAR_TO_BYTE (XFASTINT (beg));
-  iend = CHAR_TO_BYTE (XFASTINT (end));
-  move_gap_both (XFASTINT (beg), ibeg);
-
-  /* We need to allocate enough room for encoding the text.
-     We need 33 1/3% more space, plus a newline every 76
-     characters, and then we round up. */
-  length = iend - ibeg;
-  allength = pad_base64_size(compute_encode_size(length));
-
-  encoded = SAFE_ALLOCA (allength);
-  encoded_length = base64_encode_1 ((char *) BYTE_POS_ADDR (ibeg), length,
-                                    encoded, allength,
-                                    NILP (no_line_break),
-				    !NILP (BVAR (current_buffer, enable_multibyte_characters)));
-
-  if (encoded_length < 0)
-    {
-      /* The encoding wasn't possible. */
-      SAFE_FREE ();
-      error ("Multibyte character in data for base64 encoding");
-    }
-
-  /* Now we have encoded the region, so we insert the new contents
-     and delete the old.  (Insert first in order to preserve markers.)  */
-  SET_PT_BOTH (XFASTINT (beg), ibeg);
-  insert (encoded, encoded_length);
-  SAFE_FREE ();
-  del_range_byte (ibeg + encoded_length, iend + encoded_length);
-
-  /* If point was outside of the region, restore it exactly; else just
-     move to the beginning of the region.  */
-  if (old_pos >= XFASTINT (end))
-    old_pos += encoded_length - (XFASTINT (end) - XFASTINT (beg));
-  else if (old_pos > XFASTINT (beg))
-    old_pos = XFASTINT (beg);
-  SET_PT (old_pos);
-
-  /* We return the length of the encoded text. */
-  return make_number (encoded_length);
-}
-
-DEFUN ("base64-decode-region", Fbase64_decode_region, Sbase64_decode_region,
-       2, 2, "r",
-       doc: /* Base64-decode the region between BEG and END.
-Return the length of the decoded text.
-If the region can't be decoded, signal an error and don't modify the buffer.  */)
-  (Lisp_Object beg, Lisp_Object end)
-{
-  ptrdiff_t ibeg, iend, length, allength;
-  char *decoded;
-  ptrdiff_t old_pos = PT;
-  ptrdiff_t decoded_length;
-  ptrdiff_t inserted_chars;
-  bool multibyte = !NILP (BVAR (current_buffer, enable_multibyte_characters));
-  USE_SAFE_ALLOCA;
-
-  validate_region (&beg, &end);
-
-  ibeg = CHAR_TO_BYTE (XFASTINT (beg));
-  iend = CHAR_TO_BYTE (XFASTINT (end));
-
-  length = iend - ibeg;
-
-  /* We need to allocate enough room for decoding the text.  If we are
-     working on a multibyte buffer, each decoded code may occupy at
-     most two bytes.  */
-  allength = compute_decode_size(multibyte ? length * 2 : length);
-  decoded = SAFE_ALLOCA (allength);
-
-  move_gap_both (XFASTINT (beg), ibeg);
-  decoded_length = base64_decode_1 ((char *) BYTE_POS_ADDR (ibeg), length,
-				    decoded, allength,
-				    multibyte, &inserted_chars);
-
-  if (decoded_length < 0)
-    {
-      /* The decoding wasn't possible. */
-      error ("Invalid base64 data");
-    }
-
-  /* Now we have decoded the region, so we insert the new contents
-     and delete the old.  (Insert first in order to preserve markers.)  */
-  TEMP_SET_PT_BOTH (XFASTINT (beg), ibeg);
-  insert_1_both (decoded, inserted_chars, decoded_length, 0, 1, 0);
-  signal_after_change (XFASTINT (beg), 0, inserted_chars);
-  SAFE_FREE ();
-
-  /* Delete the original text.  */
-  del_range_both (PT, PT_BYTE, XFASTINT (end) + inserted_chars,
-		  iend + decoded_length, 1);
-
-  /* If point was outside of the region, restore it exactly; else just
-     move to the beginning of the region.  */
-  if (old_pos >= XFASTINT (end))
-    old_pos += inserted_chars - (XFASTINT (end) - XFASTINT (beg));
-  else if (old_pos > XFASTINT (beg))
-    old_pos = XFASTINT (beg);
-  SET_PT (old_pos > ZV ? ZV : old_pos);
-
-  return make_number (inserted_chars);
-}
-
 
 
 /***********************************************************************
@@ -2641,7 +2511,8 @@ sweep_weak_hash_tables (void)
      value-weak table A containing an entry X -> Y, where Y is used in a
      key-weak table B, Z -> Y.  If B comes after A in the list of weak
      tables, X -> Y might be removed from A, although when looking at B
-     one finds that it shouldn't.  */
+     one finds that it shouldn't.  The values of ephemerons whose keys
+     survive are marked in the same loop, for the same reason.  */
   do
     {
       marked = 0;
@@ -2650,6 +2521,7 @@ sweep_weak_hash_tables (void)
 	  if (h->header.size & ARRAY_MARK_FLAG)
 	    marked |= sweep_weak_table (h, 0);
 	}
+      marked |= mark_ephemerons ();
     }
   while (marked);
 
@@ -3036,20 +2908,6 @@ DEFUN ("hash-table-rehash-size", Fhash_table_rehash_size,
     return make_float (rehash_size + 1);
 }
 
-DEFUN ("secure-hash-algorithms", Fsecure_hash_algorithms,
-       Ssecure_hash_algorithms, 0, 0, 0,
-       doc: /* Return a list of all the supported `secure_hash' algorithms. */)
-  (void)
-{
-  return listn (CONSTYPE_HEAP, 6,
-                Qmd5,
-                Qsha1,
-                Qsha224,
-                Qsha256,
-                Qsha384,
-                Qsha512);
-}
-
 /* Extract data from a string or a buffer. SPEC is a list of
 (BUFFER-OR-STRING-OR-SYMBOL START END CODING-SYSTEM NOERROR) which behave as
 specified with `secure-hash' and in Info node
@@ -3356,8 +3214,5 @@ this variable.  */);
   defsubr (&Swidget_put);
   defsubr (&Swidget_get);
   defsubr (&Swidget_apply);
-  defsubr (&Sbase64_encode_region);
-  defsubr (&Sbase64_decode_region);
-  defsubr (&Ssecure_hash_algorithms);
   defsubr (&Slocale_info);
 }
@@ -2784,6 +2784,7 @@ read_char (int commandflag, Lisp_Object map,
 #ifdef USE_FILE_NOTIFY
 	      || EQ (XCAR (c), Qfile_notify)
 #endif
+	      || EQ (XCAR (c), Qnotification_event)
 	      || EQ (XCAR (c), Qconfig_changed_event))
           && !end_time)
 	/* We stopped being idle for this event; undo that.  This
@@ -3842,6 +3843,7 @@ kbd_buffer_get_event (KBOARD **kbp,
 #ifdef HAVE_XWIDGETS
       case XWIDGET_EVENT:
 #endif
+      case NOTIFICATION_EVENT:
       case BUFFER_SWITCH_EVENT:
       case SAVE_SESSION_EVENT:
       case NO_EVENT:
@@ -5963,6 +5965,9 @@ make_lispy_event (struct input_event *event)
 #endif
 #endif /* USE_FILE_NOTIFY */
 
+    case NOTIFICATION_EVENT:
+      return Fcons (Qnotification_event, event->arg);
+
     case CONFIG_CHANGED_EVENT:
 	return list3 (Qconfig_changed_event,
 		      event->arg, event->frame_or_window);
@@ -10869,6 +10874,8 @@ syms_of_keyboard (void)
   DEFSYM (Qfile_notify, "file-notify");
 #endif /* USE_FILE_NOTIFY */
 
+  DEFSYM (Qnotification_event, "notification-event");
+
   /* Menu and tool bar item parts.  */
   DEFSYM (QCenable, ":enable");
   DEFSYM (QCvisible, ":visible");
@@ -11674,6 +11681,11 @@ keys_of_keyboard (void)
                             "file-notify-handle-event");
 #endif /* USE_FILE_NOTIFY */
 
+  /* Define a special event which is raised when a desktop notification
+     is acted on.  */
+  initial_define_lispy_key (Vspecial_event_map, "notification-event",
+			    "notify-send-handle-event");
+
   initial_define_lispy_key (Vspecial_event_map, "config-changed-event",
 			    "ignore");
 #if defined (WINDOWSNT)
@@ -969,6 +969,9 @@ enum pvec_type
   PVEC_MUTEX,
   PVEC_CONDVAR,
   PVEC_MODULE_FUNCTION,
+  PVEC_WEAK_REF,
+  PVEC_EPHEMERON,
+  PVEC_TYPED_VECTOR,
 
   /* These should be last, check internal_equal to see why.  */
   PVEC_COMPILED,
@@ -2258,6 +2261,46 @@ SXHASH_REDUCE (EMACS_UINT x)
   return (x ^ x >> (EMACS_INT_WIDTH - FIXNUM_BITS)) & INTMASK;
 }
 
+/* A weak reference (PVEC_WEAK_REF) or an ephemeron (PVEC_EPHEMERON).
+   The GC does not mark KEY through this object, and sets KEY and
+   VALUE to nil once KEY is garbage.  The VALUE of an ephemeron is
+   marked only if KEY survives by other means; a weak reference has
+   no value.  */
+struct Lisp_Weak_Ref
+{
+  union vectorlike_header header;
+
+  Lisp_Object key;
+  Lisp_Object value;
+
+  /* Next weak reference or ephemeron marked during this GC.  The
+     head of the list is in alloc.c.  */
+  struct Lisp_Weak_Ref *next_weak;
+};
+
+INLINE bool
+WEAK_REF_P (Lisp_Object a)
+{
+  return (PSEUDOVECTORP (a, PVEC_WEAK_REF)
+	  || PSEUDOVECTORP (a, PVEC_EPHEMERON));
+}
+
+INLINE struct Lisp_Weak_Ref *
+XWEAK_REF (Lisp_Object a)
+{
+  eassert (WEAK_REF_P (a));
+  return XUNTAG (a, Lisp_Vectorlike);
+}
+
+/* A vector of unboxed numbers of a single type.  DATA points to the
+   numbers, which typed_vector.rs owns; free_typed_vector frees them
+   when the vector is swept.  */
+struct Lisp_Typed_Vector
+{
+  union vectorlike_header header;
+  void *data;
+};
+
 /* These structures are used for various misc types.  */
 
 struct Lisp_Misc_Any		/* Supertype of all Misc types.  */
@@ -3504,6 +3547,29 @@ aset_multibyte_string(register Lisp_Object array, EMACS_INT idxval, int c);
 extern void syms_of_cmds (void);
 extern void keys_of_cmds (void);
 
+/* Defined in bitset.rs.  */
+extern void syms_of_bitset (void);
+
+/* Defined in collections.rs.  */
+extern void syms_of_collections (void);
+
+/* Defined in persistent.rs.  */
+extern void mark_persistent (void);
+extern void syms_of_persistent (void);
+
+/* Defined in weak.rs.  */
+extern void syms_of_weak (void);
+
+/* Defined in typed_vector.rs.  */
+extern void free_typed_vector (struct Lisp_Typed_Vector *);
+extern void syms_of_typed_vector (void);
+
+/* Defined in crypto.rs.  */
+extern void syms_of_crypto (void);
+
+/* Defined in decompress.rs.  */
+extern void syms_of_decompress (void);
+
 /* Defined in coding.c.  */
 extern Lisp_Object detect_coding_system (const unsigned char *, ptrdiff_t,
                                          ptrdiff_t, bool, bool, Lisp_Object);
@@ -3576,11 +3642,41 @@ extern int x_bitmap_mask (struct frame *, ptrdiff_t);
 extern void reset_image_types (void);
 extern void syms_of_image (void);
 
-#ifdef HAVE_JSON
-/* Defined in json.c.  */
-extern void init_json (void);
+/* Defined in json.rs.  */
 extern void syms_of_json (void);
-#endif
+
+/* Defined in yaml.rs.  */
+extern void syms_of_yaml (void);
+
+/* Defined in toml.rs.  */
+extern void syms_of_toml (void);
+
+/* Defined in binary_serialization.rs.  */
+extern void syms_of_binary_serialization (void);
+
+/* Defined in csv.rs.  */
+extern void syms_of_csv (void);
+
+/* Defined in diff.rs.  */
+extern void syms_of_diff (void);
+
+/* Defined in chars.rs.  */
+extern void syms_of_chars (void);
+
+/* Defined in charprop.rs.  */
+extern void syms_of_charprop (void);
+
+/* Defined in kill_ring.rs.  */
+extern void syms_of_kill_ring (void);
+
+/* Defined in fill.rs.  */
+extern void syms_of_fill (void);
+
+/* Defined in sort.rs.  */
+extern void syms_of_sort (void);
+
+/* Defined in paragraphs.rs.  */
+extern void syms_of_paragraphs (void);
 
 /* Defined in insdel.c.  */
 extern void move_gap_both (ptrdiff_t, ptrdiff_t);
@@ -3693,6 +3789,8 @@ extern _Noreturn void memory_full (size_t);
 extern _Noreturn void buffer_memory_full (ptrdiff_t);
 extern bool survives_gc_p (Lisp_Object);
 extern void mark_object (Lisp_Object);
+extern bool mark_ephemerons (void);
+extern void sweep_weak_refs (void);
 #if defined REL_ALLOC && !defined SYSTEM_MALLOC && !defined HYBRID_MALLOC
 extern void refill_memory_reserve (void);
 #endif
@@ -4113,7 +4211,6 @@ extern void syms_of_module (void);
 extern void mark_threads (void);
 
 /* Defined in editfns.c.  */
-extern Lisp_Object styled_format (ptrdiff_t, Lisp_Object *, bool);
 extern void insert1 (Lisp_Object);
 extern Lisp_Object save_excursion_save (void);
 extern Lisp_Object save_restriction_save (void);
@@ -4373,6 +4470,15 @@ extern void delete_gpm_wait_descriptor (int);
 extern void init_process_emacs (int);
 extern void syms_of_process (void);
 extern void setup_process_coding_systems (Lisp_Object);
+extern Lisp_Object make_pipeline_process (ptrdiff_t, Lisp_Object *, int, int);
+extern int abbr_to_signal (char const *);
+
+/* Defined in tls.rs.  */
+extern bool rustls_stream_p (int);
+extern bool rustls_pending_p (int);
+extern ptrdiff_t rustls_read (int, char *, ptrdiff_t);
+extern ptrdiff_t rustls_write (int, const char *, ptrdiff_t);
+extern void rustls_close (int);
 
 /* Defined in callproc.c.  */
 #ifndef DOS_NT
@@ -4381,6 +4487,7 @@ extern void setup_process_coding_systems (Lisp_Object);
 # define CHILD_SETUP_TYPE int
 #endif
 extern CHILD_SETUP_TYPE child_setup (int, int, int, char **, bool, Lisp_Object);
+extern char **make_environment_block (Lisp_Object);
 extern void init_callproc_1 (void);
 extern void init_callproc (void);
 extern void set_initial_environment (void);
@@ -4416,6 +4523,9 @@ extern void init_macros (void);
 extern void syms_of_macros (void);
 
 /* Defined in undo.c.  */
+extern void syms_of_undo (void);
+
+/* Defined in undo.rs.  */
 extern void truncate_undo_list (struct buffer *);
 extern void record_insert (ptrdiff_t, ptrdiff_t);
 extern void record_delete (ptrdiff_t, Lisp_Object, bool);
@@ -4424,7 +4534,6 @@ extern void record_change (ptrdiff_t, ptrdiff_t);
 extern void record_property_change (ptrdiff_t, ptrdiff_t,
 				    Lisp_Object, Lisp_Object,
                                     Lisp_Object);
-extern void syms_of_undo (void);
 
 /* Defined in textprop.c.  */
 extern void report_interval_modification (Lisp_Object, Lisp_Object);
@@ -4458,7 +4567,6 @@ extern void init_all_sys_modes (void);
 extern void reset_all_sys_modes (void);
 extern void child_setup_tty (int);
 extern void setup_pty (int);
-extern int set_window_size (int, int, int);
 extern EMACS_INT get_random (void);
 extern void seed_random (void *, ptrdiff_t);
 extern void init_random (void);
@@ -4477,11 +4585,18 @@ extern int renameat_noreplace (int, char const *, int, char const *);
 extern int str_collate (Lisp_Object, Lisp_Object, Lisp_Object, Lisp_Object);
 
 /* Defined in filelock.c.  */
+extern time_t get_boot_time (void);
+extern Lisp_Object file_lock_owner (Lisp_Object);
+extern void syms_of_filelock (void);
+
+/* Defined in filelock.rs, or in filelock.c on MS-Windows.  */
 extern void lock_file (Lisp_Object);
 extern void unlock_file (Lisp_Object);
 extern void unlock_all_files (void);
 extern void unlock_buffer (struct buffer *);
-extern void syms_of_filelock (void);
+
+/* Defined in mapped_text.rs.  */
+extern void unmap_buffer_text (unsigned char *, ptrdiff_t);
 
 /* Defined in sound.c.  */
 extern void syms_of_sound (void);
@@ -4518,6 +4633,11 @@ extern void init_font (void);
 extern void syms_of_fontset (void);
 #endif
 
+/* Defined in filenotify.rs */
+#ifdef HAVE_RUSTNOTIFY
+extern void syms_of_filenotify (void);
+#endif
+
 /* Defined in inotify.c */
 #ifdef HAVE_INOTIFY
 extern void syms_of_inotify (void);
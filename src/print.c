@@ -1615,6 +1615,24 @@ print_vectorlike (Lisp_Object obj, Lisp_Object printcharfun, bool escapeflag,
       printchar ('>', printcharfun);
       break;
 
+    case PVEC_WEAK_REF:
+    case PVEC_EPHEMERON:
+      print_c_string (PSEUDOVECTORP (obj, PVEC_WEAK_REF)
+		      ? "#<weak-ref " : "#<ephemeron ", printcharfun);
+      print_object (XWEAK_REF (obj)->key, printcharfun, escapeflag);
+      printchar ('>', printcharfun);
+      break;
+
+    case PVEC_TYPED_VECTOR:
+      {
+	print_c_string ("#<typed-vector ", printcharfun);
+	print_object (Ftyped_vector_type (obj), printcharfun, escapeflag);
+	int len = sprintf (buf, " %"pI"d>",
+			   XINT (Ftyped_vector_length (obj)));
+	strout (buf, len, len, printcharfun);
+      }
+      break;
+
     case PVEC_RECORD:
       {
 	ptrdiff_t size = PVSIZE (obj);
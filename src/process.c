@@ -256,7 +256,7 @@ static int process_output_delay_count;
 static bool process_output_skip;
 
 static void start_process_unwind (Lisp_Object);
-static void create_process (Lisp_Object, char **, Lisp_Object);
+static void create_process (Lisp_Object, char **, Lisp_Object, int, int);
 #ifdef USABLE_SIGIO
 static bool keyboard_bit_set (fd_set *);
 #endif
@@ -315,6 +315,29 @@ static struct sockaddr_and_len {
 #define DATAGRAM_CONN_P(proc)	(0)
 #endif
 
+#ifndef WINDOWSNT
+/* Defined in spawn.rs.  */
+extern int spawn_child (char **, char **, const char *, int, int, int,
+			const sigset_t *, pid_t *);
+#endif
+
+/* Defined in process.rs.  */
+extern void check_process_output_limit (struct Lisp_Process *);
+extern void resume_drained_processes (void);
+
+/* Defined in pty.rs.  */
+extern int open_pty (char *, size_t);
+
+/* Defined in sockets.rs.  */
+extern bool set_ip_socket_option (int, Lisp_Object, Lisp_Object);
+extern Lisp_Object peer_credentials (int);
+#ifdef DATAGRAM_SOCKETS
+extern bool datagram_ancillary_p (int);
+extern void forget_datagram_ancillary (int);
+extern ptrdiff_t recv_datagram_ancillary (int, char *, ptrdiff_t,
+					  struct sockaddr *, socklen_t *);
+#endif
+
 /* FOR_EACH_PROCESS (LIST_VAR, PROC_VAR) followed by a statement is
    a `for' loop which iterates over processes from Vprocess_alist.  */
 
@@ -790,6 +813,21 @@ enum { PTY_NAME_SIZE = 24 };
 static int
 allocate_pty (char pty_name[PTY_NAME_SIZE])
 {
+#if defined HAVE_PTYS && defined HAVE_POSIX_OPENPT
+  int fd = open_pty (pty_name, PTY_NAME_SIZE);
+  if (fd < 0)
+    return -1;
+
+  /* Check to make certain that both sides are available.
+     This avoids a nasty yet stupid bug in rlogins.  */
+  if (faccessat (AT_FDCWD, pty_name, R_OK | W_OK, AT_EACCESS) != 0)
+    {
+      emacs_close (fd);
+      return -1;
+    }
+  setup_pty (fd);
+  return fd;
+#else /* !HAVE_POSIX_OPENPT */
 #ifdef HAVE_PTYS
   int fd;
 
@@ -844,6 +882,7 @@ allocate_pty (char pty_name[PTY_NAME_SIZE])
       }
 #endif /* HAVE_PTYS */
   return -1;
+#endif /* !HAVE_POSIX_OPENPT */
 }
 
 /* Allocate basically initialized process.  */
@@ -870,6 +909,7 @@ make_process (Lisp_Object name)
   p->outfd = -1;
   for (int i = 0; i < PROCESS_OPEN_FDS; i++)
     p->open_fd[i] = -1;
+  p->read_chunk_size = DEFAULT_READ_CHUNK_SIZE;
 
 #ifdef HAVE_GNUTLS
   verify (GNUTLS_STAGE_EMPTY == 0);
@@ -1069,29 +1109,6 @@ If THREAD is nil, the process is unlocked.  */)
   return thread;
 }
 
-DEFUN ("set-process-window-size", Fset_process_window_size,
-       Sset_process_window_size, 3, 3, 0,
-       doc: /* Tell PROCESS that it has logical window size WIDTH by HEIGHT.
-Value is t if PROCESS was successfully told about the window size,
-nil otherwise.  */)
-  (Lisp_Object process, Lisp_Object height, Lisp_Object width)
-{
-  CHECK_PROCESS (process);
-
-  /* All known platforms store window sizes as 'unsigned short'.  */
-  CHECK_RANGED_INTEGER (height, 0, USHRT_MAX);
-  CHECK_RANGED_INTEGER (width, 0, USHRT_MAX);
-
-  if (NETCONN_P (process)
-      || XPROCESS (process)->infd < 0
-      || (set_window_size (XPROCESS (process)->infd,
-			   XINT (height), XINT (width))
-	  < 0))
-    return Qnil;
-  else
-    return Qt;
-}
-
 DEFUN ("set-process-inherit-coding-system-flag",
        Fset_process_inherit_coding_system_flag,
        Sset_process_inherit_coding_system_flag, 2, 2, 0,
@@ -1297,8 +1314,31 @@ to use a pty, or nil to use the default specified through
 to the standard error of subprocess.  Specifying this implies
 `:connection-type' is set to `pipe'.
 
+:read-chunk-size SIZE -- Read output from the process SIZE bytes at a
+time, which is the most the filter gets at once.  See
+`set-process-read-chunk-size'.
+
+:max-buffered-output SIZE -- Stop reading output from the process
+while its buffer is SIZE bytes or larger.  See
+`set-process-max-buffered-output'.
+
 usage: (make-process &rest ARGS)  */)
   (ptrdiff_t nargs, Lisp_Object *args)
+{
+  return make_pipeline_process (nargs, args, -1, -1);
+}
+
+/* Start a program in a subprocess as `make-process' does with ARGS.
+   If STDIN_FD is nonnegative, it is the standard input of the
+   subprocess instead of a pipe from Emacs.  If STDOUT_FD is
+   nonnegative, it is the standard output of the subprocess instead of
+   a pipe to Emacs, and Emacs reads the standard error of the subprocess
+   instead.  `make-process-pipeline' connects the processes of a
+   pipeline with these.  */
+
+Lisp_Object
+make_pipeline_process (ptrdiff_t nargs, Lisp_Object *args,
+		       int stdin_fd, int stdout_fd)
 {
   Lisp_Object buffer, name, command, program, proc, contact, current_dir, tem;
   Lisp_Object xstderr, stderrproc;
@@ -1387,6 +1427,10 @@ usage: (make-process &rest ARGS)  */)
       XPROCESS (proc)->pty_flag = false;
     }
 
+  /* The processes of a pipeline are connected by pipes.  */
+  if (0 <= stdin_fd || 0 <= stdout_fd)
+    XPROCESS (proc)->pty_flag = false;
+
 #ifdef HAVE_GNUTLS
   /* AKA GNUTLS_INITSTAGE(proc).  */
   verify (GNUTLS_STAGE_EMPTY == 0);
@@ -1398,6 +1442,11 @@ usage: (make-process &rest ARGS)  */)
     = (NILP (Vprocess_adaptive_read_buffering) ? 0
        : EQ (Vprocess_adaptive_read_buffering, Qt) ? 1 : 2);
 
+  if (tem = Fplist_get (contact, QCread_chunk_size), !NILP (tem))
+    Fset_process_read_chunk_size (proc, tem);
+  if (tem = Fplist_get (contact, QCmax_buffered_output), !NILP (tem))
+    Fset_process_max_buffered_output (proc, tem);
+
   /* Make the process marker point into the process buffer (if any).  */
   if (BUFFERP (buffer))
     set_marker_both (XPROCESS (proc)->mark, buffer,
@@ -1555,7 +1604,7 @@ usage: (make-process &rest ARGS)  */)
 	  tem = XCDR (tem);
 	}
 
-      create_process (proc, new_argv, current_dir);
+      create_process (proc, new_argv, current_dir, stdin_fd, stdout_fd);
     }
   else
     create_pty (proc);
@@ -1606,7 +1655,8 @@ enum
 verify (PROCESS_OPEN_FDS == EXEC_MONITOR_OUTPUT + 1);
 
 static void
-create_process (Lisp_Object process, char **new_argv, Lisp_Object current_dir)
+create_process (Lisp_Object process, char **new_argv, Lisp_Object current_dir,
+		int stdin_fd, int stdout_fd)
 {
   struct Lisp_Process *p = XPROCESS (process);
   int inchannel, outchannel;
@@ -1643,14 +1693,22 @@ create_process (Lisp_Object process, char **new_argv, Lisp_Object current_dir)
     }
   else
     {
-      if (emacs_pipe (p->open_fd + SUBPROCESS_STDIN) != 0
+      if ((stdin_fd < 0 && emacs_pipe (p->open_fd + SUBPROCESS_STDIN) != 0)
 	  || emacs_pipe (p->open_fd + READ_FROM_SUBPROCESS) != 0)
 	report_file_error ("Creating pipe", Qnil);
-      forkin = p->open_fd[SUBPROCESS_STDIN];
+      forkin = stdin_fd < 0 ? p->open_fd[SUBPROCESS_STDIN] : stdin_fd;
       outchannel = p->open_fd[WRITE_TO_SUBPROCESS];
       inchannel = p->open_fd[READ_FROM_SUBPROCESS];
       forkout = p->open_fd[SUBPROCESS_STDOUT];
 
+      /* In a pipeline, the output goes to the next process, and Emacs
+	 reads the error output instead.  */
+      if (0 <= stdout_fd)
+	{
+	  forkerr = forkout;
+	  forkout = stdout_fd;
+	}
+
       if (!NILP (p->stderrproc))
 	{
 	  struct Lisp_Process *pp = XPROCESS (p->stderrproc);
@@ -1669,7 +1727,8 @@ create_process (Lisp_Object process, char **new_argv, Lisp_Object current_dir)
 #endif
 
   fcntl (inchannel, F_SETFL, O_NONBLOCK);
-  fcntl (outchannel, F_SETFL, O_NONBLOCK);
+  if (0 <= outchannel)
+    fcntl (outchannel, F_SETFL, O_NONBLOCK);
 
   /* Record this as an active process, with its channels.  */
   chan_process[inchannel] = process;
@@ -1694,6 +1753,35 @@ create_process (Lisp_Object process, char **new_argv, Lisp_Object current_dir)
   block_child_signal (&oldset);
 
 #ifndef WINDOWSNT
+  /* A subprocess on pipes needs none of the setup a pty needs, so start
+     it with posix_spawn.  spawn_child returns ENOSYS if Emacs was built
+     without posix_spawn_file_actions_addchdir_np, which posix_spawn
+     needs to start it in its directory; it is then started with vfork
+     below.  */
+  bool use_spawn = process_use_posix_spawn && !pty_flag;
+#ifdef HAVE_SETRLIMIT
+  /* Only child_setup restores the limit on open files Emacs started
+     with.  */
+  if (FD_SETSIZE < nofile_limit.rlim_cur)
+    use_spawn = false;
+#endif
+  if (use_spawn)
+    {
+      char **env = make_environment_block (current_dir);
+      int spawn_errno = spawn_child (new_argv, env, SSDATA (current_dir),
+				     forkin, forkout,
+				     forkerr < 0 ? forkout : forkerr,
+				     &oldset, &pid);
+      xfree (env);
+      if (spawn_errno != ENOSYS)
+	{
+	  if (spawn_errno != 0)
+	    pid = -1;
+	  errno = spawn_errno;
+	  goto spawned;
+	}
+    }
+
   /* vfork, and prevent local vars from being clobbered by the vfork.  */
   Lisp_Object volatile current_dir_volatile = current_dir;
   Lisp_Object volatile lisp_pty_name_volatile = lisp_pty_name;
@@ -1839,6 +1927,9 @@ create_process (Lisp_Object process, char **new_argv, Lisp_Object current_dir)
 #endif /* not WINDOWSNT */
     }
 
+#ifndef WINDOWSNT
+ spawned:
+#endif
   /* Back in the parent process.  */
 
   vfork_errno = errno;
@@ -2502,7 +2593,9 @@ set_socket_option (int s, Lisp_Object opt, Lisp_Object val)
 #endif
 
     default:
-      return 0;
+      /* The multicast options, :tos and :ancillary are set in
+	 sockets.rs, which signals an error itself if that fails.  */
+      return set_ip_socket_option (s, opt, val) ? 1 << OPIX_MISC : 0;
     }
 
   if (ret < 0)
@@ -3032,11 +3125,6 @@ connect_network_socket (Lisp_Object proc, Lisp_Object addrinfos,
 	    }
 	}
 
-#ifdef DATAGRAM_SOCKETS
-      if (!p->is_server && p->socktype == SOCK_DGRAM)
-	break;
-#endif /* DATAGRAM_SOCKETS */
-
       /* Make us close S if quit.  */
       record_unwind_protect_int (close_file_unwind, s);
 
@@ -3056,6 +3144,13 @@ connect_network_socket (Lisp_Object proc, Lisp_Object addrinfos,
 	  }
       }
 
+#ifdef DATAGRAM_SOCKETS
+      /* A datagram client needs its options too, to :broadcast or
+	 join a multicast group, but it doesn't connect.  */
+      if (!p->is_server && p->socktype == SOCK_DGRAM)
+	break;
+#endif /* DATAGRAM_SOCKETS */
+
       if (p->is_server)
 	{
 	  /* Configure as a server socket.  */
@@ -3216,6 +3311,11 @@ connect_network_socket (Lisp_Object proc, Lisp_Object addrinfos,
 	    contact = Fplist_put (contact, QClocal,
 				  conv_sockaddr_to_lisp (psa1, len1));
 	}
+#endif
+#ifdef HAVE_LOCAL_SOCKETS
+      if (!p->is_server && family == AF_LOCAL)
+	contact = Fplist_put (contact, QCpeer_credentials,
+			      peer_credentials (s));
 #endif
     }
 
@@ -3442,6 +3542,19 @@ The following network options can be specified for this connection:
                       (this is allowed by default for a server process).
 :bindtodevice NAME -- bind to interface NAME.  Using this may require
                       special privileges on some systems.
+:multicast-join GROUP -- Join the multicast group with address GROUP, a
+                      string like \"224.0.0.251\" or \"ff02::fb\".  GROUP
+                      may also be a list (GROUP INTERFACE), where
+                      INTERFACE is the address of the interface to join
+                      on for IPv4, and its name or index for IPv6.
+:multicast-leave GROUP -- Leave the multicast group GROUP.
+:multicast-loop BOOL -- Receive the multicast datagrams sent from here.
+:multicast-ttl INT -- Set the time to live of sent multicast datagrams.
+:multicast-interface INTERFACE -- Send multicast datagrams on INTERFACE.
+:tos INT           -- Set the type of service, or the traffic class for
+                      IPv6, of sent packets.
+:ancillary BOOL    -- Record where each datagram received was sent to
+                      and how it arrived, for `process-datagram-ancillary'.
 :use-external-socket BOOL -- Use any pre-allocated sockets that have
                              been passed to Emacs.  If Emacs wasn't
                              passed a socket, this option is silently
@@ -3466,6 +3579,8 @@ NAME concatenated with the client identification string.
 inherited from the server process's TYPE, FILTER and SENTINEL.
 - The client process's contact info is set according to the client's
 addressing information (typically an IP address and a port number).
+For a local socket, its :peer-credentials are (PID UID GID) of the
+client, where PID is nil if the system doesn't tell it.
 - The client process's plist is initialized from the server's plist.
 
 Notice that the FILTER and SENTINEL args are never used directly by
@@ -3474,7 +3589,10 @@ the server process, but via the optional :log function, accepted (and
 failed) connections may be logged in the server process's buffer.
 
 The original argument list, modified with the actual connection
-information, is available via the `process-contact' function.
+information, is available via the `process-contact' function.  A client
+connected to a local socket also has the :peer-credentials of the
+server in it.  `process-send-fd' passes file descriptors over a local
+socket.
 
 usage: (make-network-process &rest ARGS)  */)
   (ptrdiff_t nargs, Lisp_Object *args)
@@ -4179,6 +4297,10 @@ deactivate_process (Lisp_Object proc)
   emacs_gnutls_deinit (proc);
 #endif /* HAVE_GNUTLS */
 
+  /* Delete the rustls connection of PROC, if any.  */
+  if (p->infd >= 0)
+    rustls_close (p->infd);
+
   if (p->read_output_delay > 0)
     {
       if (--process_output_delay_count < 0)
@@ -4203,6 +4325,7 @@ deactivate_process (Lisp_Object proc)
 	  xfree (datagram_address[inchannel].sa);
 	  datagram_address[inchannel].sa = 0;
 	  datagram_address[inchannel].len = 0;
+	  forget_datagram_ancillary (inchannel);
 	}
 #endif
       chan_process[inchannel] = Qnil;
@@ -4440,6 +4563,10 @@ server_accept_connection (Lisp_Object server, int channel)
     contact = Fplist_put (contact, QClocal,
 			  conv_sockaddr_to_lisp (&saddr.sa, len));
 #endif
+#ifdef HAVE_LOCAL_SOCKETS
+  if (saddr.sa.sa_family == AF_LOCAL)
+    contact = Fplist_put (contact, QCpeer_credentials, peer_credentials (s));
+#endif
 
   pset_childp (p, contact);
   pset_plist (p, Fcopy_sequence (ps->plist));
@@ -4699,6 +4826,9 @@ wait_reading_process_output (intmax_t time_limit, int nsecs, int read_kbd,
       if (! NILP (wait_for_cell) && ! NILP (XCAR (wait_for_cell)))
 	break;
 
+      /* Read again from processes whose buffers are no longer full.  */
+      resume_drained_processes ();
+
 #if defined HAVE_GETADDRINFO_A || defined HAVE_GNUTLS
       {
 	Lisp_Object process_list_head, aproc;
@@ -5086,6 +5216,28 @@ wait_reading_process_output (intmax_t time_limit, int nsecs, int read_kbd,
 		Available = tls_available;
 	    }
 #endif
+
+	  /* Rustls also keeps decrypted data which select can't see.  */
+	  if (nfds == 0)
+	    {
+	      fd_set tls_available;
+	      int set = 0;
+
+	      FD_ZERO (&tls_available);
+	      for (channel = 0; channel < FD_SETSIZE; ++channel)
+		if (! NILP (chan_process[channel])
+		    && (! wait_proc || wait_proc->infd == channel)
+		    && rustls_pending_p (channel))
+		  {
+		    FD_SET (channel, &tls_available);
+		    set++;
+		  }
+	      if (set)
+		{
+		  nfds = set;
+		  Available = tls_available;
+		}
+	    }
 	}
 
       xerrno = errno;
@@ -5454,7 +5606,8 @@ read_and_dispose_of_process_output (struct Lisp_Process *p, char *chars,
    starting with our buffered-ahead character if we have one.
    Yield number of decoded characters read.
 
-   This function reads at most 4096 characters.
+   This function reads at most the read chunk size of PROC, which is
+   4096 bytes unless `set-process-read-chunk-size' changed it.
    If you want to read all available subprocess output,
    you must call it repeatedly until it returns zero.
 
@@ -5468,10 +5621,12 @@ read_process_output (Lisp_Object proc, int channel)
   struct Lisp_Process *p = XPROCESS (proc);
   struct coding_system *coding = proc_decode_coding_system[channel];
   int carryover = p->decoding_carryover;
-  enum { readmax = 4096 };
+  ptrdiff_t readmax = p->read_chunk_size;
   ptrdiff_t count = SPECPDL_INDEX ();
   Lisp_Object odeactivate;
-  char chars[sizeof coding->carryover + readmax];
+  char *chars;
+  USE_SAFE_ALLOCA;
+  chars = SAFE_ALLOCA (sizeof coding->carryover + readmax);
 
   if (carryover)
     /* See the comment above.  */
@@ -5482,8 +5637,12 @@ read_process_output (Lisp_Object proc, int channel)
   if (DATAGRAM_CHAN_P (channel))
     {
       socklen_t len = datagram_address[channel].len;
-      nbytes = recvfrom (channel, chars + carryover, readmax,
-			 0, datagram_address[channel].sa, &len);
+      if (datagram_ancillary_p (channel))
+	nbytes = recv_datagram_ancillary (channel, chars + carryover, readmax,
+					  datagram_address[channel].sa, &len);
+      else
+	nbytes = recvfrom (channel, chars + carryover, readmax,
+			   0, datagram_address[channel].sa, &len);
     }
   else
 #endif
@@ -5500,6 +5659,10 @@ read_process_output (Lisp_Object proc, int channel)
 				    readmax - buffered);
       else
 #endif
+      if (rustls_stream_p (channel))
+	nbytes = rustls_read (channel, chars + carryover + buffered,
+			      readmax - buffered);
+      else
 	nbytes = emacs_read (channel, chars + carryover + buffered,
 			     readmax - buffered);
       if (nbytes > 0 && p->adaptive_read_buffering)
@@ -5538,7 +5701,10 @@ read_process_output (Lisp_Object proc, int channel)
   if (nbytes <= 0)
     {
       if (nbytes < 0 || coding->mode & CODING_MODE_LAST_BLOCK)
-	return nbytes;
+	{
+	  SAFE_FREE ();
+	  return nbytes;
+	}
       coding->mode |= CODING_MODE_LAST_BLOCK;
     }
 
@@ -5559,6 +5725,10 @@ read_process_output (Lisp_Object proc, int channel)
   /* Handling the process output should not deactivate the mark.  */
   Vdeactivate_mark = odeactivate;
 
+  /* Stop reading if the output filled the process buffer.  */
+  if (p->max_buffered_output > 0)
+    check_process_output_limit (p);
+
   unbind_to (count, Qnil);
   return nbytes;
 }
@@ -6000,6 +6170,9 @@ send_process (Lisp_Object proc, const char *buf, ptrdiff_t len,
 		written = emacs_gnutls_write (p, cur_buf, cur_len);
 	      else
 #endif
+	      if (rustls_stream_p (outfd))
+		written = rustls_write (outfd, cur_buf, cur_len);
+	      else
 		written = emacs_write_sig (outfd, cur_buf, cur_len);
 	      rv = (written ? 0 : -1);
 	      if (p->read_output_delay > 0
@@ -6397,7 +6570,7 @@ traffic.  */)
 
 /* Return the integer value of the signal whose abbreviation is ABBR,
    or a negative number if there is no such signal.  */
-static int
+int
 abbr_to_signal (char const *name)
 {
   int i, signo;
@@ -7112,7 +7285,7 @@ setup_process_coding_systems (Lisp_Object process)
   int outch = p->outfd;
   Lisp_Object coding_system;
 
-  if (inch < 0 || outch < 0)
+  if (inch < 0)
     return;
 
   if (!proc_decode_coding_system[inch])
@@ -7126,6 +7299,9 @@ setup_process_coding_systems (Lisp_Object process)
     }
   setup_coding_system (coding_system, proc_decode_coding_system[inch]);
 
+  /* The processes of a pipeline after the first have no OUTCH.  */
+  if (outch < 0)
+    return;
   if (!proc_encode_coding_system[outch])
     proc_encode_coding_system[outch] = xmalloc (sizeof (struct coding_system));
   setup_coding_system (p->encode_coding_system,
@@ -7420,6 +7596,7 @@ syms_of_process (void)
   DEFSYM (QCservice, ":service");
   DEFSYM (QClocal, ":local");
   DEFSYM (QCremote, ":remote");
+  DEFSYM (QCpeer_credentials, ":peer-credentials");
   DEFSYM (QCcoding, ":coding");
   DEFSYM (QCserver, ":server");
   DEFSYM (QCnowait, ":nowait");
@@ -7434,6 +7611,8 @@ syms_of_process (void)
   DEFSYM (QCcommand, ":command");
   DEFSYM (QCconnection_type, ":connection-type");
   DEFSYM (QCstderr, ":stderr");
+  DEFSYM (QCread_chunk_size, ":read-chunk-size");
+  DEFSYM (QCmax_buffered_output, ":max-buffered-output");
   DEFSYM (Qpty, "pty");
   DEFSYM (Qpipe, "pipe");
 
@@ -7487,6 +7666,15 @@ A value of nil means don't delete them until `list-processes' is run.  */);
 
   delete_exited_processes = 1;
 
+  DEFVAR_BOOL ("process-use-posix-spawn", process_use_posix_spawn,
+	       doc: /* Non-nil means start subprocesses with posix_spawn when possible.
+That is when a subprocess talks to Emacs over pipes rather than a pty,
+and the system can start it in its directory with posix_spawn, which is
+faster than how Emacs starts subprocesses otherwise, when Emacs uses a
+lot of memory.  A program that can't be executed is then reported with
+an error by `make-process', rather than by the process exiting.  */);
+  process_use_posix_spawn = 1;
+
   DEFVAR_LISP ("process-connection-type", Vprocess_connection_type,
 	       doc: /* Control type of device used to communicate with subprocesses.
 Values are nil to use a pipe, or t or `pty' to use a pty.
@@ -7524,7 +7712,6 @@ returns non-`nil'.  */);
 
   defsubr (&Sdelete_process);
   defsubr (&Sset_process_thread);
-  defsubr (&Sset_process_window_size);
   defsubr (&Sset_process_inherit_coding_system_flag);
   defsubr (&Sprocess_contact);
   defsubr (&Smake_process);
@@ -36,6 +36,10 @@ INLINE_HEADER_BEGIN
 
 enum { PROCESS_OPEN_FDS = 6 };
 
+/* The number of bytes read from a process at a time by default.  */
+
+enum { DEFAULT_READ_CHUNK_SIZE = 4096 };
+
 /* This structure records information about a subprocess
    or network connection.  */
 
@@ -149,12 +153,23 @@ struct Lisp_Process
        time.  Value is nanoseconds to delay reading output from
        this process.  Range is 0 .. 50 * 1000 * 1000.  */
     int read_output_delay;
+    /* Number of bytes to read from the process at a time, which is the
+       most its filter gets at once.  */
+    ptrdiff_t read_chunk_size;
+    /* Number of bytes the buffer of the process may grow to before
+       Emacs stops reading its output, or 0 for no limit.  */
+    ptrdiff_t max_buffered_output;
     /* Should we delay reading output from this process.
        Initialized from `Vprocess_adaptive_read_buffering'.
        0 = nil, 1 = t, 2 = other.  */
     unsigned int adaptive_read_buffering : 2;
     /* Skip reading this process on next read.  */
     bool_bf read_output_skip : 1;
+    /* True if Lisp paused reading the output of this process.  */
+    bool_bf output_paused : 1;
+    /* True if reading the output of this process stopped because its
+       buffer grew to `max_buffered_output'.  */
+    bool_bf output_full : 1;
     /* True means kill silently if Emacs is exited.
        This is the inverse of the `query-on-exit' flag.  */
     bool_bf kill_without_query : 1;
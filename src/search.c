@@ -3130,6 +3130,8 @@ the buffer.  If the buffer doesn't have a cache, the value is nil.  */)
   return val;
 }
 
+extern void rust_syms_of_search (void);
+
 void
 syms_of_search (void)
 {
@@ -3204,4 +3206,6 @@ is to bind it with `let' around a small expression.  */);
   defsubr (&Sset_match_data);
   defsubr (&Sregexp_quote);
   defsubr (&Snewline_cache_check);
+
+  rust_syms_of_search ();
 }
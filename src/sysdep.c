@@ -1362,37 +1362,6 @@ get_tty_size (int fd, int *widthp, int *heightp)
 #endif
 }
 
-/* Set the logical window size associated with descriptor FD
-   to HEIGHT and WIDTH.  This is used mainly with ptys.
-   Return a negative value on failure.  */
-
-int
-set_window_size (int fd, int height, int width)
-{
-#ifdef TIOCSWINSZ
-
-  /* BSD-style.  */
-  struct winsize size;
-  size.ws_row = height;
-  size.ws_col = width;
-
-  return ioctl (fd, TIOCSWINSZ, &size);
-
-#else
-#ifdef TIOCSSIZE
-
-  /* SunOS - style.  */
-  struct ttysize size;
-  size.ts_lines = height;
-  size.ts_cols = width;
-
-  return ioctl (fd, TIOCGSIZE, &size);
-#else
-  return -1;
-#endif /* not SunOS-style */
-#endif /* not BSD-style */
-}
-
 
 
 /* Prepare all terminal devices for exiting Emacs. */
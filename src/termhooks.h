@@ -257,6 +257,9 @@ enum event_kind
   , FILE_NOTIFY_EVENT
 #endif
 
+  /* A desktop notification was acted on, or closed.  */
+  , NOTIFICATION_EVENT
+
 };
 
 /* Bit width of an enum event_kind tag at the start of structs and unions.  */
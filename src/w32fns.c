@@ -7894,100 +7894,76 @@ DEFUN ("x-file-dialog", Fx_file_dialog, Sx_file_dialog, 2, 5, 0,
 
 
 #ifdef WINDOWSNT
-/* Moving files to the system recycle bin.
-   Used by `move-file-to-trash' instead of the default moving to ~/.Trash  */
-DEFUN ("system-move-file-to-trash", Fsystem_move_file_to_trash,
-       Ssystem_move_file_to_trash, 1, 1, 0,
-       doc: /* Move file or directory named FILENAME to the recycle bin.  */)
-  (Lisp_Object filename)
+/* Move the file or directory named FILENAME, an absolute file name, to
+   the system recycle bin.  Used by `system-move-file-to-trash'.  */
+void
+w32_move_file_to_trash (Lisp_Object filename)
 {
-  Lisp_Object handler;
   Lisp_Object encoded_file;
-  Lisp_Object operation;
+  const char * path;
+  int result;
 
-  operation = Qdelete_file;
-  if (!NILP (Ffile_directory_p (filename))
-      && NILP (Ffile_symlink_p (filename)))
-    {
-      operation = intern ("delete-directory");
-      filename = Fdirectory_file_name (filename);
-    }
+  encoded_file = ENCODE_FILE (filename);
 
-  /* Must have fully qualified file names for moving files to Recycle
-     Bin. */
-  filename = Fexpand_file_name (filename, Qnil);
+  path = map_w32_filename (SSDATA (encoded_file), NULL);
 
-  handler = Ffind_file_name_handler (filename, operation);
-  if (!NILP (handler))
-    return call2 (handler, operation, filename);
-  else
+  /* The Unicode version of SHFileOperation is not supported on
+     Windows 9X. */
+  if (w32_unicode_filenames && os_subtype != OS_9X)
     {
-      const char * path;
-      int result;
+      SHFILEOPSTRUCTW file_op_w;
+      /* We need one more element beyond MAX_PATH because this is
+	 a list of file names, with the last element double-null
+	 terminated. */
+      wchar_t tmp_path_w[MAX_PATH + 1];
 
-      encoded_file = ENCODE_FILE (filename);
+      memset (tmp_path_w, 0, sizeof (tmp_path_w));
+      filename_to_utf16 (path, tmp_path_w);
 
-      path = map_w32_filename (SSDATA (encoded_file), NULL);
+      /* On Windows, write permission is required to delete/move files.  */
+      _wchmod (tmp_path_w, 0666);
 
-      /* The Unicode version of SHFileOperation is not supported on
-	 Windows 9X. */
-      if (w32_unicode_filenames && os_subtype != OS_9X)
-	{
-	  SHFILEOPSTRUCTW file_op_w;
-	  /* We need one more element beyond MAX_PATH because this is
-	     a list of file names, with the last element double-null
-	     terminated. */
-	  wchar_t tmp_path_w[MAX_PATH + 1];
-
-	  memset (tmp_path_w, 0, sizeof (tmp_path_w));
-	  filename_to_utf16 (path, tmp_path_w);
-
-	  /* On Windows, write permission is required to delete/move files.  */
-	  _wchmod (tmp_path_w, 0666);
-
-	  memset (&file_op_w, 0, sizeof (file_op_w));
-	  file_op_w.hwnd = HWND_DESKTOP;
-	  file_op_w.wFunc = FO_DELETE;
-	  file_op_w.pFrom = tmp_path_w;
-	  file_op_w.fFlags = FOF_SILENT | FOF_NOCONFIRMATION | FOF_ALLOWUNDO
-	    | FOF_NOERRORUI | FOF_NO_CONNECTED_ELEMENTS;
-	  file_op_w.fAnyOperationsAborted = FALSE;
+      memset (&file_op_w, 0, sizeof (file_op_w));
+      file_op_w.hwnd = HWND_DESKTOP;
+      file_op_w.wFunc = FO_DELETE;
+      file_op_w.pFrom = tmp_path_w;
+      file_op_w.fFlags = FOF_SILENT | FOF_NOCONFIRMATION | FOF_ALLOWUNDO
+	| FOF_NOERRORUI | FOF_NO_CONNECTED_ELEMENTS;
+      file_op_w.fAnyOperationsAborted = FALSE;
 
-	  result = SHFileOperationW (&file_op_w);
-	}
+      result = SHFileOperationW (&file_op_w);
+    }
+  else
+    {
+      SHFILEOPSTRUCTA file_op_a;
+      char tmp_path_a[MAX_PATH + 1];
+
+      memset (tmp_path_a, 0, sizeof (tmp_path_a));
+      filename_to_ansi (path, tmp_path_a);
+
+      /* If a file cannot be represented in ANSI codepage, don't
+	 let them inadvertently delete other files because some
+	 characters are interpreted as a wildcards.  */
+      if (_mbspbrk ((unsigned char *)tmp_path_a,
+		    (const unsigned char *)"?*"))
+	result = ERROR_FILE_NOT_FOUND;
       else
 	{
-	  SHFILEOPSTRUCTA file_op_a;
-	  char tmp_path_a[MAX_PATH + 1];
-
-	  memset (tmp_path_a, 0, sizeof (tmp_path_a));
-	  filename_to_ansi (path, tmp_path_a);
-
-	  /* If a file cannot be represented in ANSI codepage, don't
-	     let them inadvertently delete other files because some
-	     characters are interpreted as a wildcards.  */
-	  if (_mbspbrk ((unsigned char *)tmp_path_a,
-			(const unsigned char *)"?*"))
-	    result = ERROR_FILE_NOT_FOUND;
-	  else
-	    {
-	      _chmod (tmp_path_a, 0666);
+	  _chmod (tmp_path_a, 0666);
 
-	      memset (&file_op_a, 0, sizeof (file_op_a));
-	      file_op_a.hwnd = HWND_DESKTOP;
-	      file_op_a.wFunc = FO_DELETE;
-	      file_op_a.pFrom = tmp_path_a;
-	      file_op_a.fFlags = FOF_SILENT | FOF_NOCONFIRMATION | FOF_ALLOWUNDO
-		| FOF_NOERRORUI | FOF_NO_CONNECTED_ELEMENTS;
-	      file_op_a.fAnyOperationsAborted = FALSE;
+	  memset (&file_op_a, 0, sizeof (file_op_a));
+	  file_op_a.hwnd = HWND_DESKTOP;
+	  file_op_a.wFunc = FO_DELETE;
+	  file_op_a.pFrom = tmp_path_a;
+	  file_op_a.fFlags = FOF_SILENT | FOF_NOCONFIRMATION | FOF_ALLOWUNDO
+	    | FOF_NOERRORUI | FOF_NO_CONNECTED_ELEMENTS;
+	  file_op_a.fAnyOperationsAborted = FALSE;
 
-	      result = SHFileOperationA (&file_op_a);
-	    }
+	  result = SHFileOperationA (&file_op_a);
 	}
-      if (result != 0)
-	report_file_error ("Removing old name", list1 (filename));
     }
-  return Qnil;
+  if (result != 0)
+    report_file_error ("Removing old name", list1 (filename));
 }
 
 #endif /* WINDOWSNT */
@@ -10154,7 +10130,6 @@ syms_of_w32fns (void)
   DEFSYM (Qserif, "serif");
   DEFSYM (Qzlib, "zlib");
   DEFSYM (Qlcms2, "lcms2");
-  DEFSYM (Qjson, "json");
 
   Fput (Qundefined_color, Qerror_conditions,
 	listn (CONSTYPE_PURE, 2, Qundefined_color, Qerror));
@@ -10524,9 +10499,6 @@ tip frame.  */);
   staticpro (&tip_last_parms);
 
   defsubr (&Sx_file_dialog);
-#ifdef WINDOWSNT
-  defsubr (&Ssystem_move_file_to_trash);
-#endif
 }
 
 
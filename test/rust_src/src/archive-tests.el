@@ -0,0 +1,71 @@
+;;; archive-tests.el --- tests for reading tar and zip archives
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(defmacro archive-tests--with-files (&rest body)
+  "Run BODY in a temporary directory holding some files to archive."
+  (declare (indent 0))
+  `(let ((default-directory (file-name-as-directory
+                             (make-temp-file "archive" t))))
+     (unwind-protect
+         (progn
+           (make-directory "dir")
+           (write-region "first\n" nil "dir/a.txt" nil 'silent)
+           (write-region "second\n" nil "dir/b.txt" nil 'silent)
+           ,@body)
+       (delete-directory default-directory t))))
+
+(defun archive-tests--check (archive)
+  (let ((entries (archive-entries archive)))
+    (should (assoc "dir/" entries))
+    (should (equal (nth 1 (assoc "dir/a.txt" entries)) 6))
+    (should (consp (nth 3 (assoc "dir/a.txt" entries)))))
+  (with-temp-buffer
+    (insert "<>")
+    (goto-char 2)
+    (should (= (archive-insert-entry archive "dir/b.txt") 7))
+    (should (equal (buffer-string) "<second\n>"))
+    (should (= (point) 2)))
+  (should-error (archive-insert-entry archive "dir/c.txt")))
+
+(ert-deftest archive-tar ()
+  (skip-unless (executable-find "tar"))
+  (archive-tests--with-files
+    (should (zerop (call-process "tar" nil nil nil "cf" "test.tar" "dir")))
+    (archive-tests--check "test.tar")))
+
+(ert-deftest archive-compressed-tar ()
+  (skip-unless (and (executable-find "tar") (executable-find "gzip")))
+  (archive-tests--with-files
+    (should (zerop (call-process "tar" nil nil nil "czf" "test.tar.gz" "dir")))
+    (archive-tests--check "test.tar.gz")))
+
+(ert-deftest archive-zip ()
+  (skip-unless (executable-find "zip"))
+  (archive-tests--with-files
+    (should (zerop (call-process "zip" nil nil nil "-qr" "test.zip" "dir")))
+    (archive-tests--check "test.zip")))
+
+(ert-deftest archive-errors ()
+  (should-error (archive-entries "/nonexistent/archive.tar") :type 'file-error))
+
+(provide 'archive-tests)
+
+;;; archive-tests.el ends here
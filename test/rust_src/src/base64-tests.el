@@ -18,3 +18,51 @@
         (encoded-without-break (apply 'concat (make-list 20 "eHh4"))))
     (should (string= encoded-with-break (base64-encode-string clear)))
     (should (string= encoded-without-break (base64-encode-string clear t)))))
+
+(ert-deftest base64-tests-url ()
+  (let ((clear "\373\377\277ab")
+        (encoded "-_-_YWI="))
+    (should (string= encoded (base64url-encode-string clear)))
+    (should (string= "-_-_YWI" (base64url-encode-string clear t)))
+    (should (string= clear (base64-decode-string encoded t)))
+    (should (string= clear (base64-decode-string "-_-_YWI" t)))
+    (should-error (base64-decode-string encoded))))
+
+(ert-deftest base64-tests-region ()
+  (with-temp-buffer
+    (insert "foo hello world bar")
+    (goto-char (point-max))
+    (should (= 16 (base64-encode-region 5 16)))
+    (should (string= "foo aGVsbG8gd29ybGQ= bar" (buffer-string)))
+    (should (= (point) (point-max)))
+    (should (= 11 (base64-decode-region 5 21)))
+    (should (string= "foo hello world bar" (buffer-string)))
+    (should (= (point) (point-max)))
+    (should (= 15 (base64url-encode-region 5 16 t)))
+    (should (string= "foo aGVsbG8gd29ybGQ bar" (buffer-string)))
+    (should (= 11 (base64-decode-region 5 20 t)))
+    (should (string= "foo hello world bar" (buffer-string))))
+  (with-temp-buffer
+    (insert "a!b=")
+    (should-error (base64-decode-region (point-min) (point-max)))
+    (should (string= "a!b=" (buffer-string)))))
+
+(ert-deftest base64-tests-region-across-gap ()
+  (with-temp-buffer
+    (insert "world bar")
+    (goto-char (point-min))
+    ;; Leave the gap in the middle of the region.
+    (insert "foo hello ")
+    (should (= 16 (base64-encode-region 5 16)))
+    (should (string= "foo aGVsbG8gd29ybGQ= bar" (buffer-string)))
+    (goto-char 13)
+    (insert "x")
+    (delete-char -1)
+    (should (= 11 (base64-decode-region 5 21)))
+    (should (string= "foo hello world bar" (buffer-string)))))
+
+(ert-deftest base64-tests-empty-buffer ()
+  (with-temp-buffer
+    (should (= 0 (base64-encode-region (point-min) (point-max))))
+    (should (= 0 (base64-decode-region (point-min) (point-max))))
+    (should (string= "" (buffer-string)))))
@@ -0,0 +1,55 @@
+;;; bidi-tests.el --- tests for bidirectional reordering
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest string-visual-runs ()
+  (should (equal (string-visual-runs "") nil))
+  (should (equal (string-visual-runs "abc") '((0 3 0))))
+  (should (equal (string-visual-runs "abc אבג")
+                 '((0 4 0) (4 7 1))))
+  (should (equal (string-visual-runs "אב abc")
+                 '((3 6 2) (0 3 1))))
+  (should (equal (string-visual-runs "אב abc" 'left-to-right)
+                 '((0 2 1) (2 6 0))))
+  (should (equal (string-visual-runs "abc" 'right-to-left) '((0 3 2))))
+  ;; Each paragraph has its own direction.
+  (should (equal (string-visual-runs "א\nabc") '((0 1 1) (1 2 1) (2 5 0))))
+  (should-error (string-visual-runs "abc" 'up)))
+
+(ert-deftest string-reorder-visual ()
+  (should (equal (string-reorder-visual "") ""))
+  (should (equal (string-reorder-visual "abc") "abc"))
+  (should (equal (string-reorder-visual "abc אבג")
+                 "abc גבא"))
+  (should (equal (string-reorder-visual "אב abc")
+                 "abc בא"))
+  (should (equal (string-reorder-visual "אב abc" 'left-to-right)
+                 "בא abc"))
+  (should (equal (string-reorder-visual "אב\nabc") "בא\nabc"))
+  ;; Combining marks stay after their base characters.
+  (should (equal (string-reorder-visual "אָב")
+                 "באָ"))
+  (let ((reordered (string-reorder-visual
+                    (concat "a" (propertize "אב" 'face 'bold)))))
+    (should (equal reordered "aבא"))
+    (should (eq (get-text-property 1 'face reordered) 'bold))
+    (should-not (get-text-property 0 'face reordered))))
+
+(provide 'bidi-tests)
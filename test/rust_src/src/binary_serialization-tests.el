@@ -0,0 +1,73 @@
+;;; binary_serialization-tests.el --- tests for CBOR and MessagePack support
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest cbor-serialize ()
+  (should (equal (cbor-serialize '((a . 1))) "\xa1\x61\x61\x01"))
+  (should (equal (cbor-serialize [1 2]) "\x82\x01\x02"))
+  (should (equal (cbor-serialize [t :false :null]) "\x83\xf5\xf4\xf6"))
+  (should-not (multibyte-string-p (cbor-serialize "α")))
+  (should-error (cbor-serialize '((a . 1)) :object-type 'alist)))
+
+(ert-deftest cbor-parse-string ()
+  (should (equal (cbor-parse-string "\xa1\x61\x61\x01" :object-type 'alist)
+                 '((a . 1))))
+  (should (equal (cbor-parse-string "\x83\xf5\xf4\xf6"
+                                    :false-object nil :null-object 'none)
+                 [t nil none]))
+  (let ((object '(:name "α" :ports [80 443] :ratio 1.5)))
+    (should (equal (cbor-parse-string (cbor-serialize object)
+                                      :object-type 'plist)
+                   object)))
+  (should-error (cbor-parse-string "\x82\x01") :type 'cbor-parse-error)
+  (should-error (cbor-parse-string "\x01\x02") :type 'cbor-error)
+  ;; Byte strings have no Lisp representation.
+  (should-error (cbor-parse-string "\x41\x00") :type 'cbor-parse-error))
+
+(ert-deftest msgpack-serialize ()
+  (should (equal (msgpack-serialize '((a . 1))) "\x81\xa1\x61\x01"))
+  (should (equal (msgpack-serialize [1 2]) "\x92\x01\x02"))
+  (should (equal (msgpack-serialize [t :false :null]) "\x93\xc3\xc2\xc0"))
+  (should-not (multibyte-string-p (msgpack-serialize "α")))
+  (should-error (msgpack-serialize '((a . 1)) :object-type 'alist)))
+
+(ert-deftest msgpack-parse-string ()
+  (should (equal (msgpack-parse-string "\x81\xa1\x61\x01" :object-type 'alist)
+                 '((a . 1))))
+  (should (equal (msgpack-parse-string "\x93\xc3\xc2\xc0"
+                                       :false-object nil :null-object 'none)
+                 [t nil none]))
+  (let ((object '(:name "α" :ports [80 443] :ratio 1.5)))
+    (should (equal (msgpack-parse-string (msgpack-serialize object)
+                                         :object-type 'plist)
+                   object)))
+  (should-error (msgpack-parse-string "\x92\x01") :type 'msgpack-parse-error)
+  (should-error (msgpack-parse-string "\x01\x02") :type 'msgpack-error)
+  (should-error (msgpack-parse-string "α")))
+
+(ert-deftest msgpack-parse-string/nesting-limit ()
+  (let ((nested (lambda (depth)
+                  (concat (apply #'unibyte-string (make-list (1- depth) #x91))
+                          "\x90"))))
+    (should (vectorp (msgpack-parse-string (funcall nested 128))))
+    (should-error (msgpack-parse-string (funcall nested 129))
+                  :type 'msgpack-parse-error)))
+
+(provide 'binary_serialization-tests)
@@ -0,0 +1,67 @@
+;;; bitset-tests.el --- tests for bitset.rs
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+
+(require 'ert)
+
+(ert-deftest bitset-add-and-remove ()
+  (let ((bitset (make-bitset 4)))
+    (should (bitset-p bitset))
+    (should-not (bitset-p (make-bool-vector 4 nil)))
+    (should (bitset-add bitset 3))
+    (should-not (bitset-add bitset 3))
+    (should (bitset-add bitset 1000))
+    (should (bitset-add bitset 64))
+    (should (bitset-member-p bitset 3))
+    (should (bitset-member-p bitset 1000))
+    (should-not (bitset-member-p bitset 2))
+    (should-not (bitset-member-p bitset 100000))
+    (should (= (bitset-count bitset) 3))
+    (should (equal (bitset-to-list bitset) '(3 64 1000)))
+    (should (bitset-remove bitset 64))
+    (should-not (bitset-remove bitset 64))
+    (should (equal (bitset-to-list bitset) '(3 1000)))
+    (bitset-clear bitset)
+    (should (= (bitset-count bitset) 0))
+    (should-error (bitset-add bitset -1) :type 'wrong-type-argument)))
+
+(ert-deftest bloom-filter-membership ()
+  (let ((filter (make-bloom-filter 1000)))
+    (should (bloom-filter-p filter))
+    (should-not (bloom-filter-p (make-bitset)))
+    (dotimes (i 1000)
+      (bloom-add filter (format "word%d" i)))
+    (dotimes (i 1000)
+      (should (bloom-member-p filter (format "word%d" i))))
+    (let ((false-positives 0))
+      (dotimes (i 1000)
+        (when (bloom-member-p filter (format "other%d" i))
+          (setq false-positives (1+ false-positives))))
+      (should (< false-positives 50)))))
+
+(ert-deftest bloom-filter-arguments ()
+  (should-error (make-bloom-filter 0) :type 'args-out-of-range)
+  (should-error (make-bloom-filter 10 1.5) :type 'args-out-of-range)
+  (let ((filter (make-bloom-filter 10 0.1)))
+    (should (bloom-add filter '(a b)))
+    (should-not (bloom-add filter (list 'a 'b)))))
+
+(provide 'bitset-tests)
+
+;;; bitset-tests.el ends here
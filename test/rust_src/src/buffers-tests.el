@@ -63,6 +63,55 @@
     (should (equal (delq nil (delete-dups the-buffers))
                    the-buffers))))
 
+;; narrow-to-region, widen and make-indirect-buffer
+
+(ert-deftest test-narrow-to-region ()
+  (with-temp-buffer
+    (insert "hello world")
+    (narrow-to-region 9 7)
+    (should (= (point-min) 7))
+    (should (= (point-max) 9))
+    (should (= (point) 9))
+    (should (string= (buffer-string) "wo"))
+    (should-error (narrow-to-region 0 3) :type 'args-out-of-range)
+    (widen)
+    (should (= (point-min) 1))
+    (should (= (point-max) 12))
+    (should (string= (buffer-string) "hello world"))))
+
+(ert-deftest test-make-indirect-buffer ()
+  (with-temp-buffer
+    (insert "shared text")
+    (let* ((base (current-buffer))
+           (indirect (make-indirect-buffer base "test-make-indirect-buffer")))
+      (unwind-protect
+          (progn
+            (should (eq (buffer-base-buffer indirect) base))
+            (should-error (make-indirect-buffer base "test-make-indirect-buffer"))
+            (should-error (make-indirect-buffer "no such buffer" "unused name"))
+            (should-error (make-indirect-buffer base ""))
+            (with-current-buffer indirect
+              (should (string= (buffer-string) "shared text"))
+              (narrow-to-region 1 7)
+              (goto-char (point-max))
+              (insert "and "))
+            (should (string= (buffer-string) "shared and text"))
+            (should (= (point-max) 16)))
+        (kill-buffer indirect)))))
+
+(ert-deftest test-make-indirect-buffer-clone ()
+  (with-temp-buffer
+    (emacs-lisp-mode)
+    (setq-local fill-column 42)
+    (let ((indirect (make-indirect-buffer (current-buffer)
+                                          "test-make-indirect-buffer-clone" t)))
+      (unwind-protect
+          (with-current-buffer indirect
+            (should (eq major-mode 'emacs-lisp-mode))
+            (should (= fill-column 42))
+            (should-not buffer-file-name))
+        (kill-buffer indirect)))))
+
 (provide 'buffers-tests)
 
 ;;; buffers-tests.el ends here
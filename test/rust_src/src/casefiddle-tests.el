@@ -0,0 +1,85 @@
+;;; casefiddle-tests.el --- tests for region case conversion
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest casefiddle-region-special-casing ()
+  (with-temp-buffer
+    (insert "straße ﬁx")
+    (upcase-region (point-min) (point-max))
+    (should (equal (buffer-string) "STRASSE FIX"))
+    (downcase-region (point-min) (point-max))
+    (should (equal (buffer-string) "strasse fix"))))
+
+(ert-deftest casefiddle-region-final-sigma ()
+  (with-temp-buffer
+    (insert "ΌΣΟΣ ΌΣΟΣ Σ")
+    (downcase-region (point-min) (point-max))
+    (should (equal (buffer-string) "όσος όσος σ"))))
+
+(ert-deftest casefiddle-region-turkic ()
+  (with-temp-buffer
+    (insert "istanbul IĞDIR")
+    (let ((case-conversion-locale "tr_TR.UTF-8"))
+      (upcase-region 1 9)
+      (downcase-region 10 (point-max)))
+    (should (equal (buffer-string) "İSTANBUL ığdır")))
+  (with-temp-buffer
+    (insert "istanbul")
+    (upcase-region (point-min) (point-max))
+    (should (equal (buffer-string) "ISTANBUL"))))
+
+(ert-deftest casefiddle-region-keeps-properties-and-markers ()
+  (with-temp-buffer
+    (insert (propertize "aß" 'face 'bold) "c")
+    (let ((marker (copy-marker (point-max))))
+      (goto-char 2)
+      (upcase-region (point-min) (point-max))
+      (should (equal (buffer-string) "ASSC"))
+      (should (= (point) 2))
+      (should (= marker (point-max)))
+      (should (eq (get-text-property 1 'face) 'bold))
+      (should-not (get-text-property 4 'face)))))
+
+(ert-deftest casefiddle-region-across-gap ()
+  (with-temp-buffer
+    (insert "abc déf")
+    (goto-char 4)
+    (insert "ß")
+    (upcase-region (point-min) (point-max))
+    (should (equal (buffer-string) "ABCSS DÉF"))))
+
+(ert-deftest casefiddle-region-unibyte ()
+  (with-temp-buffer
+    (set-buffer-multibyte nil)
+    (insert "abc\377")
+    (upcase-region (point-min) (point-max))
+    (should (equal (buffer-string) "ABC\377"))))
+
+(ert-deftest casefiddle-word-special-casing ()
+  (with-temp-buffer
+    (insert "maß maß")
+    (goto-char (point-min))
+    (upcase-word 1)
+    (should (equal (buffer-string) "MASS maß"))
+    (should (= (point) 5))
+    (downcase-word -1)
+    (should (equal (buffer-string) "mass maß"))))
+
+(provide 'casefiddle-tests)
@@ -0,0 +1,56 @@
+;;; charprop-tests.el --- tests for Unicode character properties
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest charprop-general-category ()
+  (should (eq (char-unicode-property ?A 'general-category) 'Lu))
+  (should (eq (char-unicode-property ?é 'general-category) 'Ll))
+  (should (eq (char-unicode-property ?5 'general-category) 'Nd))
+  (should (eq (char-unicode-property ?\s 'general-category) 'Zs))
+  (should (eq (char-unicode-property #x10FFFF 'general-category) 'Cn)))
+
+(ert-deftest charprop-script ()
+  (should (eq (char-unicode-property ?a 'script) 'latin))
+  (should (eq (char-unicode-property ?λ 'script) 'greek))
+  (should (eq (char-unicode-property ?漢 'script) 'han))
+  (should (eq (char-unicode-property ?. 'script) 'common)))
+
+(ert-deftest charprop-east-asian-width ()
+  (should (eq (char-unicode-property ?a 'east-asian-width) 'Na))
+  (should (eq (char-unicode-property ?漢 'east-asian-width) 'W))
+  (should (eq (char-unicode-property ?Ａ 'east-asian-width) 'F))
+  (should (eq (char-unicode-property ?ｱ 'east-asian-width) 'H)))
+
+(ert-deftest charprop-decimal-digit-value ()
+  (should (= (char-unicode-property ?7 'decimal-digit-value) 7))
+  (should (= (char-unicode-property ?٣ 'decimal-digit-value) 3))
+  (should-not (char-unicode-property ?½ 'decimal-digit-value))
+  (should-not (char-unicode-property ?x 'decimal-digit-value)))
+
+(ert-deftest charprop-non-unicode ()
+  (should-not (char-unicode-property (unibyte-char-to-multibyte #xff)
+                                     'general-category))
+  (should-not (char-unicode-property #x110000 'script)))
+
+(ert-deftest charprop-invalid-arguments ()
+  (should-error (char-unicode-property ?a 'bogus))
+  (should-error (char-unicode-property "a" 'script) :type 'wrong-type-argument))
+
+(provide 'charprop-tests)
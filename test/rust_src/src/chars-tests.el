@@ -0,0 +1,159 @@
+;;; chars-tests.el --- tests for Unicode normalization and segmentation
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest string-normalize ()
+  (should (equal (string-normalize "é" 'nfc) "é"))
+  (should (equal (string-normalize "é" 'nfd) "é"))
+  (should (equal (string-normalize "ﬁ" 'nfc) "ﬁ"))
+  (should (equal (string-normalize "ﬁ" 'nfkc) "fi"))
+  (should (equal (string-normalize "Ａ" 'nfkd) "A"))
+  ;; Combining marks are put in canonical order.
+  (should (equal (string-normalize "ạ́" 'nfd) "ạ́"))
+  ;; Conjoining Hangul jamo compose into a syllable.
+  (should (equal (string-normalize "한" 'nfc) "한"))
+  (should (equal (string-normalize "한" 'nfd) "한"))
+  (should (equal (string-normalize "" 'nfc) ""))
+  (should (equal (string-normalize "abc" 'nfc) "abc"))
+  (should-error (string-normalize "abc" 'nfx)))
+
+(ert-deftest string-normalize-raw-bytes ()
+  (let ((unibyte "caf\351"))
+    (should (equal (string-normalize unibyte 'nfc) unibyte))
+    (should-not (multibyte-string-p (string-normalize unibyte 'nfc))))
+  ;; Nothing combines across a raw byte.
+  (let ((string (concat "e" (string-to-multibyte "\377") "́")))
+    (should (equal (string-normalize string 'nfc) string)))
+  (should (equal (string-normalize (string #x110000 ?e #x301) 'nfc)
+                 (string #x110000 #xe9))))
+
+(ert-deftest normalize-region ()
+  (with-temp-buffer
+    (insert "café 한")
+    (let ((marker (copy-marker 2)))
+      (normalize-region (point-min) (point-max) 'nfc)
+      (should (equal (buffer-string) "café 한"))
+      (normalize-region (point-min) (point-max) 'nfd)
+      (should (equal (buffer-string) "café 한"))
+      ;; Markers in the unchanged text stay where they were.
+      (should (= marker 2)))))
+
+(ert-deftest normalize-region-partial ()
+  (with-temp-buffer
+    (insert "ééé")
+    (normalize-region 3 5 'nfc)
+    (should (equal (buffer-string) "ééé"))
+    (goto-char (point-max))
+    (normalize-region (point-max) (point-min) 'nfc)
+    (should (equal (buffer-string) "ééé"))
+    (should (= (point) (point-max)))))
+
+(ert-deftest normalize-region-unibyte ()
+  (with-temp-buffer
+    (set-buffer-multibyte nil)
+    (insert "caf\351")
+    (normalize-region (point-min) (point-max) 'nfkc)
+    (should (equal (buffer-string) "caf\351"))))
+
+(ert-deftest string-fold-diacritics ()
+  (should (equal (string-fold-diacritics "café") "cafe"))
+  (should (equal (string-fold-diacritics "Ångström") "Angstrom"))
+  (should (equal (string-fold-diacritics "ﬁＡ") "fiA"))
+  ;; Letters without a decomposition and Hangul syllables are kept.
+  (should (equal (string-fold-diacritics "ø 한") "ø 한"))
+  (should (equal (string-fold-diacritics "") ""))
+  (let ((unibyte "caf\351"))
+    (should (equal (string-fold-diacritics unibyte) unibyte))))
+
+(ert-deftest fold-diacritics-region ()
+  (with-temp-buffer
+    (insert "naïve café")
+    (let ((marker (copy-marker 2)))
+      (fold-diacritics-region (point-min) (point-max))
+      (should (equal (buffer-string) "naive cafe"))
+      (should (= marker 2)))))
+
+(ert-deftest string-grapheme-length ()
+  (should (= (string-grapheme-length "") 0))
+  (should (= (string-grapheme-length "abc") 3))
+  (should (= (string-grapheme-length "cafe\u0301") 4))
+  (should (= (string-grapheme-length "\u1112\u1161\u11ab") 1))
+  (should (= (string-grapheme-length "\U0001F468\u200d\U0001F469\u200d\U0001F467") 1))
+  (should (= (string-grapheme-length "\U0001F1EB\U0001F1F7\U0001F1E9\U0001F1EA") 2))
+  (should (= (string-grapheme-length "a\r\nb") 3))
+  (should (= (string-grapheme-length "caf\351") 4)))
+
+(ert-deftest grapheme-substring ()
+  (let ((string "e\u0301\U0001F468\u200d\U0001F469x"))
+    (should (equal (grapheme-substring string 0 1) "e\u0301"))
+    (should (equal (grapheme-substring string 1 2)
+                   "\U0001F468\u200d\U0001F469"))
+    (should (equal (grapheme-substring string 1) "\U0001F468\u200d\U0001F469x"))
+    (should (equal (grapheme-substring string -1) "x"))
+    (should (equal (grapheme-substring string 0 -1)
+                   "e\u0301\U0001F468\u200d\U0001F469"))
+    (should (equal (grapheme-substring string 3) ""))
+    (should-error (grapheme-substring string 4) :type 'args-out-of-range)
+    (should-error (grapheme-substring string 2 1) :type 'args-out-of-range))
+  (let ((string (propertize "ab" 'face 'bold)))
+    (should (equal (get-text-property 0 'face (grapheme-substring string 1))
+                   'bold))))
+
+(ert-deftest forward-grapheme ()
+  (with-temp-buffer
+    (insert "e\u0301\U0001F468\u200d\U0001F469\r\nx")
+    (goto-char (point-min))
+    (forward-grapheme)
+    (should (= (point) 3))
+    (forward-grapheme)
+    (should (= (point) 6))
+    (forward-grapheme)
+    (should (= (point) 8))
+    (forward-grapheme -2)
+    (should (= (point) 3))
+    (backward-grapheme)
+    (should (= (point) 1))
+    (should-error (backward-grapheme) :type 'beginning-of-buffer)
+    (should-error (forward-grapheme 10) :type 'end-of-buffer)
+    (should (= (point) (point-max)))
+    ;; Point in the middle of a cluster moves to its boundaries.
+    (goto-char 2)
+    (forward-grapheme)
+    (should (= (point) 3))
+    (goto-char 2)
+    (backward-grapheme)
+    (should (= (point) 1))))
+
+(ert-deftest unicode-word-boundaries ()
+  (with-temp-buffer
+    (insert "Don't panic, \u6f22\u5b57!")
+    (should (equal (unicode-word-boundaries (point-min) (point-max))
+                   '(1 6 7 12 13 14 15 16 17)))
+    (should (equal (unicode-word-boundaries 8 3) '(3 6 7 8)))
+    (should (equal (unicode-word-boundaries 4 4) '(4)))
+    (should-error (unicode-word-boundaries 1 100) :type 'args-out-of-range)))
+
+(ert-deftest unicode-sentence-boundaries ()
+  (with-temp-buffer
+    (insert "Hello there. How are you?  Fine\u3002\u597d")
+    (should (equal (unicode-sentence-boundaries (point-min) (point-max))
+                   '(1 14 28 33 34)))))
+
+(provide 'chars-tests)
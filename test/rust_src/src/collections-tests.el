@@ -0,0 +1,144 @@
+;;; collections-tests.el --- tests for collections.rs
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+
+(require 'ert)
+
+(defun collections-tests--entries (table)
+  "Return the entries of TABLE as visited by `maphash'."
+  (let ((entries nil))
+    (maphash (lambda (key value) (push (cons key value) entries)) table)
+    (nreverse entries)))
+
+(ert-deftest ordered-hash-table-keeps-insertion-order ()
+  (let ((table (make-ordered-hash-table :test 'equal)))
+    (should (ordered-hash-table-p table))
+    (should-not (ordered-hash-table-p (make-hash-table)))
+    (ordered-hash-table-put "b" 1 table)
+    (ordered-hash-table-put "a" 2 table)
+    (ordered-hash-table-put "c" 3 table)
+    (ordered-hash-table-put "b" 4 table)
+    (should (= (ordered-hash-table-count table) 3))
+    (should (= (ordered-hash-table-get "b" table) 4))
+    (should (eq (ordered-hash-table-get "d" table 'none) 'none))
+    (should (equal (collections-tests--entries table)
+                   '(("b" . 4) ("a" . 2) ("c" . 3))))))
+
+(ert-deftest ordered-hash-table-remove ()
+  (let ((table (make-ordered-hash-table)))
+    (dotimes (i 100)
+      (ordered-hash-table-put i (* i i) table))
+    (dotimes (i 100)
+      (when (= (% i 2) 1)
+        (ordered-hash-table-remove i table)))
+    (dotimes (i 100)
+      (ordered-hash-table-put (+ i 100) i table))
+    (should (= (ordered-hash-table-count table) 150))
+    (should-not (ordered-hash-table-get 1 table))
+    (should (= (ordered-hash-table-get 10 table) 100))
+    (let ((keys (mapcar #'car (collections-tests--entries table))))
+      (should (equal keys (append (number-sequence 0 98 2)
+                                  (number-sequence 100 199)))))
+    (ordered-hash-table-clear table)
+    (should (= (ordered-hash-table-count table) 0))
+    (should-not (collections-tests--entries table))))
+
+(ert-deftest ordered-hash-table-cannot-be-weak ()
+  (should-error (make-ordered-hash-table :weakness 'key))
+  (should-error (ordered-hash-table-get 1 (make-hash-table))
+                :type 'wrong-type-argument))
+
+(ert-deftest sorted-map-sorts-keys ()
+  (let ((map (make-sorted-map)))
+    (should (sorted-map-p map))
+    (should-not (sorted-map-p (make-hash-table)))
+    (dolist (key '(b "z" 3 1.5 "a" a -2))
+      (sorted-map-put key (format "%s" key) map))
+    (sorted-map-put 3 "three" map)
+    (should (= (sorted-map-count map) 7))
+    (should (equal (sorted-map-get 3 map) "three"))
+    (should (equal (mapcar #'car (collections-tests--entries map))
+                   '(-2 1.5 3 "a" "z" a b)))
+    (should (equal (sorted-map-first map) '(-2 . "-2")))
+    (should (equal (sorted-map-last map) '(b . "b")))
+    (sorted-map-remove "a" map)
+    (should-not (sorted-map-get "a" map))
+    (should (= (sorted-map-count map) 6))))
+
+(ert-deftest sorted-map-numbers ()
+  (let ((map (make-sorted-map)))
+    (sorted-map-put 1 'fixnum map)
+    (sorted-map-put 1.0 'float map)
+    (sorted-map-put 0.0 'zero map)
+    (sorted-map-put -0.0 'negative-zero map)
+    (should (= (sorted-map-count map) 3))
+    (should (eq (sorted-map-get 1 map) 'fixnum))
+    (should (eq (sorted-map-get 1.0 map) 'float))
+    (should (eq (sorted-map-get 0.0 map) 'negative-zero))
+    (should (eq (sorted-map-get -0.0 map) 'negative-zero))))
+
+(ert-deftest sorted-map-map-range ()
+  (let ((map (make-sorted-map))
+        (keys nil))
+    (dotimes (i 10)
+      (sorted-map-put i (* i i) map))
+    (sorted-map-map (lambda (key _value) (push key keys)) map 3 7)
+    (should (equal (nreverse keys) '(3 4 5 6)))
+    (setq keys nil)
+    (sorted-map-map (lambda (key _value) (push key keys)) map 7 3)
+    (should-not keys)
+    (sorted-map-clear map)
+    (should-not (sorted-map-first map))))
+
+(ert-deftest sorted-map-key-types ()
+  (should (sorted-map-key-p 1))
+  (should (sorted-map-key-p "a"))
+  (should (sorted-map-key-p 'a))
+  (should-not (sorted-map-key-p '(1 2)))
+  (should-error (sorted-map-put '(1 2) t (make-sorted-map))
+                :type 'wrong-type-argument))
+
+(ert-deftest heap-pops-smallest-first ()
+  (let ((heap (make-heap)))
+    (should (heap-p heap))
+    (should-not (heap-p [1 2 3]))
+    (dolist (n '(5 3 9 1 7 3 20 0 4 11 2))
+      (heap-push heap n))
+    (should (= (heap-count heap) 11))
+    (should (= (heap-peek heap) 0))
+    (let ((popped nil))
+      (while (> (heap-count heap) 0)
+        (push (heap-pop heap) popped))
+      (should (equal (nreverse popped) '(0 1 2 3 3 4 5 7 9 11 20))))
+    (should-not (heap-pop heap))
+    (should-not (heap-peek heap))))
+
+(ert-deftest heap-predicate ()
+  (let ((heap (make-heap (lambda (a b) (string< (car a) (car b))) 1)))
+    (heap-push heap '("b" . 2))
+    (heap-push heap '("c" . 3))
+    (heap-push heap '("a" . 1))
+    (should (equal (heap-pop heap) '("a" . 1)))
+    (should (equal (heap-pop heap) '("b" . 2)))
+    (heap-clear heap)
+    (should (= (heap-count heap) 0))))
+
+(provide 'collections-tests)
+
+;;; collections-tests.el ends here
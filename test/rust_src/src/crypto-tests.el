@@ -0,0 +1,188 @@
+;;; crypto-tests.el --- tests for secure hashing
+
+;; Copyright 2017-2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest crypto-secure-hash-algorithms ()
+  (dolist (algorithm '(md5 sha1 sha224 sha256 sha384 sha512
+                       blake2b blake2s sha3-224 sha3-256 sha3-384 sha3-512))
+    (should (memq algorithm (secure-hash-algorithms)))))
+
+(ert-deftest crypto-secure-hash-new-algorithms ()
+  (should (equal (secure-hash 'blake2b "foo")
+                 "ca002330e69d3e6b84a46a56a6533fd79d51d97a3bb7cad6c2ff43b354185d6dc1e723fb3db4ae0737e120378424c714bb982d9dc5bbd7a0ab318240ddd18f8d"))
+  (should (equal (secure-hash 'blake2s "foo")
+                 "08d6cad88075de8f192db097573d0e829411cd91eb6ec65e8fc16c017edfdb74"))
+  (should (equal (secure-hash 'sha3-224 "foo")
+                 "f4f6779e153c391bbd29c95e72b0708e39d9166c7cea51d1f10ef58a"))
+  (should (equal (secure-hash 'sha3-256 "foo")
+                 "76d3bc41c9f588f7fcd0d5bf4718f8f84b1c41b20882703100b9eb9413807c01"))
+  (should (equal (secure-hash 'sha3-384 "foo")
+                 "665551928d13b7d84ee02734502b018d896a0fb87eed5adb4c87ba91bbd6489410e11b0fbcc06ed7d0ebad559e5d3bb5"))
+  (should (equal (secure-hash 'sha3-512 "foo")
+                 "4bca2b137edc580fe50a88983ef860ebaca36c857b1f492839d6d7392452a63c82cbebc68e3b70a2a1480b4bb5d437a7cba6ecf9d89f9ff3ccd14cd6146ea7e7"))
+  (should (= (length (secure-hash 'blake2b "foo" nil nil t)) 64)))
+
+(ert-deftest crypto-secure-hash-invalid-algorithm ()
+  (should-error (secure-hash 'sha3 "foo")))
+
+(ert-deftest crypto-secure-hash-unibyte-buffer-across-gap ()
+  (with-temp-buffer
+    (set-buffer-multibyte nil)
+    (insert "bar")
+    (goto-char (point-min))
+    ;; Leave the gap between "foo" and "bar".
+    (insert "foo")
+    (dolist (algorithm (secure-hash-algorithms))
+      (should (equal (secure-hash algorithm (current-buffer))
+                     (secure-hash algorithm "foobar")))
+      (should (equal (secure-hash algorithm (current-buffer) 3 6)
+                     (secure-hash algorithm "oba")))
+      (should (equal (secure-hash algorithm (current-buffer) 5 2)
+                     (secure-hash algorithm "oob")))
+      (should (equal (secure-hash algorithm (current-buffer) 5 5)
+                     (secure-hash algorithm ""))))
+    (should-error (secure-hash 'sha1 (current-buffer) 1 10)
+                  :type 'args-out-of-range)))
+
+(ert-deftest crypto-secure-hash-empty-buffer ()
+  (with-temp-buffer
+    (dolist (algorithm (secure-hash-algorithms))
+      (should (equal (secure-hash algorithm (current-buffer))
+                     (secure-hash algorithm ""))))
+    (should (equal (buffer-hash) (sha1 "")))
+    (set-buffer-multibyte nil)
+    (should (equal (secure-hash 'sha256 (current-buffer))
+                   (secure-hash 'sha256 "")))))
+
+(ert-deftest crypto-buffer-hash ()
+  (with-temp-buffer
+    (insert "bar")
+    (goto-char (point-min))
+    (insert "foo")
+    (should (equal (buffer-hash) (sha1 "foobar")))))
+
+(ert-deftest crypto-buffer-hash-region ()
+  (with-temp-buffer
+    (insert "bar é")
+    (goto-char (point-min))
+    (insert "foo")
+    (dolist (algorithm (secure-hash-algorithms))
+      (should (equal (buffer-hash-region (point-min) (point-max) algorithm)
+                     (secure-hash algorithm
+                                  (encode-coding-string "foobar é" 'utf-8))))
+      (should (equal (buffer-hash-region 6 2 algorithm)
+                     (secure-hash algorithm "ooba")))
+      (should (equal (buffer-hash-region 3 3 algorithm t)
+                     (secure-hash algorithm "" nil nil t))))
+    (should (equal (buffer-hash-region (point-min) (point-max) 'sha1)
+                   (buffer-hash)))
+    (should-error (buffer-hash-region 1 20 'sha1) :type 'args-out-of-range)
+    (should-error (buffer-hash-region 1 2 'no-such-hash))))
+
+(defun crypto-tests--unhex (hex)
+  "Return the unibyte string encoded by the string HEX."
+  (apply #'unibyte-string
+         (mapcar (lambda (i) (string-to-number (substring hex i (+ i 2)) 16))
+                 (number-sequence 0 (1- (length hex)) 2))))
+
+(ert-deftest crypto-hmac ()
+  ;; RFC 4231, test case 2.
+  (should (equal (hmac 'sha256 "Jefe" "what do ya want for nothing?")
+                 "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"))
+  (should (equal (hmac 'sha256 "Jefe" "what do ya want for nothing?" t)
+                 (crypto-tests--unhex
+                  "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")))
+  ;; Keys longer than the block size are hashed first.
+  (should (equal (hmac 'sha3-256 (make-string 200 ?k) "msg")
+                 "4beeb04d637f2720b7472094d5aec0e1a6d78e52b0e4efc9870196be1593f4f2"))
+  (with-temp-buffer
+    (insert "what do ya want for nothing?")
+    (should (equal (hmac 'sha256 "Jefe" (current-buffer))
+                   "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")))
+  (should-error (hmac 'sha3 "Jefe" "foo")))
+
+(ert-deftest crypto-hkdf-expand ()
+  ;; RFC 5869, test case 1.
+  (let ((prk (crypto-tests--unhex
+              "077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5"))
+        (info (crypto-tests--unhex "f0f1f2f3f4f5f6f7f8f9")))
+    (should (equal (hkdf-expand 'sha256 prk info 42)
+                   (crypto-tests--unhex
+                    "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865")))
+    (should (equal (hkdf-expand 'sha256 prk nil 0) ""))
+    (should (= (length (hkdf-expand 'sha256 prk nil (* 255 32))) (* 255 32)))
+    (should-error (hkdf-expand 'sha256 prk nil (1+ (* 255 32)))
+                  :type 'args-out-of-range)
+    (should-error (hkdf-expand 'sha256 prk nil -1)
+                  :type 'args-out-of-range)))
+
+(ert-deftest crypto-string-equal-constant-time ()
+  (should (string-equal-constant-time "secret" "secret"))
+  (should (string-equal-constant-time "" ""))
+  (should-not (string-equal-constant-time "secret" "secreT"))
+  (should-not (string-equal-constant-time "secret" "secrets"))
+  (should (string-equal-constant-time "héllo" "héllo"))
+  (should (string-equal-constant-time "abc" (string-to-multibyte "abc")))
+  ;; Unibyte bytes only match raw bytes, not the equivalent Latin-1 characters.
+  (should (string-equal-constant-time "\377" (string-to-multibyte "\377")))
+  (should (string-equal-constant-time (string-to-multibyte "\377") "\377"))
+  (should-not (string-equal-constant-time "\377" "ÿ"))
+  (should (string-equal-constant-time (hmac 'sha256 "key" "msg" t)
+                                      (hmac 'sha256 "key" "msg" t)))
+  (should-error (string-equal-constant-time 'secret "secret")
+                :type 'wrong-type-argument))
+
+(ert-deftest crypto-verify-file-digest ()
+  (let ((file (make-temp-file "digest" nil nil "abc")))
+    (unwind-protect
+        (progn
+          (should (verify-file-digest
+                   file 'sha256
+                   "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"))
+          ;; Case and surrounding whitespace don't matter.
+          (should (verify-file-digest
+                   file 'md5 " 900150983CD24FB0D6963F7D28E17F72\n"))
+          (should-not (verify-file-digest
+                       file 'md5 "900150983cd24fb0d6963f7d28e17f73"))
+          (should-not (verify-file-digest file 'sha1 "a9993e36"))
+          (should-error (verify-file-digest file 'md5 "not hex"))
+          (should-error (verify-file-digest (concat file "-nonexistent")
+                                            'md5 "00")
+                        :type 'file-missing))
+      (delete-file file))))
+
+(ert-deftest crypto-verify-file-digest-callback ()
+  (let ((file (make-temp-file "digest" nil nil "abc"))
+        (result 'pending)
+        (deadline (+ (float-time) 10)))
+    (unwind-protect
+        (progn
+          (should (integerp
+                   (verify-file-digest
+                    file 'sha1 "a9993e364706816aba3e25717850c26c9cd0d89d"
+                    (lambda (name matches)
+                      (should (equal name file))
+                      (setq result matches)))))
+          (while (and (eq result 'pending) (< (float-time) deadline))
+            (accept-process-output nil 0.05))
+          (should (eq result t)))
+      (delete-file file))))
+
+(provide 'crypto-tests)
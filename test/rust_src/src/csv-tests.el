@@ -0,0 +1,52 @@
+;;; csv-tests.el --- tests for native CSV parsing
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest csv-parse-string-quoting ()
+  (should (equal (csv-parse-string "a,b,c\n1,2,3\n")
+                 [["a" "b" "c"] ["1" "2" "3"]]))
+  (should (equal (csv-parse-string "\"a,b\",\"say \"\"hi\"\"\"\r\nx,\"multi\nline\"")
+                 [["a,b" "say \"hi\""] ["x" "multi\nline"]]))
+  ;; Rows can have different lengths, and fields can be empty.
+  (should (equal (csv-parse-string "a\nb,,c\n") [["a"] ["b" "" "c"]]))
+  (should (equal (csv-parse-string "") [])))
+
+(ert-deftest csv-parse-string-options ()
+  (should (equal (csv-parse-string "a;b\n'x;y';z" :separator ?\; :quote ?')
+                 [["a" "b"] ["x;y" "z"]]))
+  (should (equal (csv-parse-string "\"a\"\tb" :separator ?\t :quote nil)
+                 [["\"a\"" "b"]]))
+  (should (equal (csv-parse-string "α,β") [["α" "β"]]))
+  (should-error (csv-parse-string "a" :separator ?α))
+  (should-error (csv-parse-string "a" :delimiter ?,))
+  (should-error (csv-parse-string "a" :separator)))
+
+(ert-deftest csv-parse-region ()
+  (with-temp-buffer
+    (insert "skip\nname,age\n\"Doe, J\",42\n")
+    (goto-char (point-min))
+    (forward-line)
+    (should (equal (csv-parse-region (point) (point-max))
+                   [["name" "age"] ["Doe, J" "42"]]))
+    (should (equal (csv-parse-region (point-max) (point))
+                   [["name" "age"] ["Doe, J" "42"]]))
+    (should-error (csv-parse-region 1 1000))))
+
+(provide 'csv-tests)
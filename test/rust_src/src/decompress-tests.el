@@ -62,6 +62,346 @@
                    (set-buffer-multibyte nil)
                    (zlib-decompress-region (point-min) (point-max)))))))
 
+(ert-deftest zlib--compress-roundtrip ()
+  "Test compressing a region and decompressing it again."
+  (when (and (fboundp 'zlib-available-p)
+	     (zlib-available-p))
+    (dolist (format '(nil gzip zlib deflate))
+      (should (string=
+	       (with-temp-buffer
+		 (set-buffer-multibyte nil)
+		 (insert (make-string 1000 ?a) "foo\n")
+		 (should (zlib-compress-region (point-min) (point-max) format))
+		 (should (< (buffer-size) 1000))
+		 (zlib-decompress-region (point-min) (point-max))
+		 (buffer-string))
+	       (concat (make-string 1000 ?a) "foo\n"))))))
+
+(ert-deftest zlib--compress-gzip-header ()
+  "Test that the default format produces a gzip header."
+  (when (and (fboundp 'zlib-available-p)
+	     (zlib-available-p))
+    (with-temp-buffer
+      (set-buffer-multibyte nil)
+      (insert "foo\n")
+      (zlib-compress-region (point-min) (point-max))
+      (should (eq (char-after 1) #x1f))
+      (should (eq (char-after 2) #x8b)))))
+
+(ert-deftest zlib--compress-invalid-format ()
+  "Test that an unknown format signals an error."
+  (when (and (fboundp 'zlib-available-p)
+	     (zlib-available-p))
+    (with-temp-buffer
+      (set-buffer-multibyte nil)
+      (insert "foo\n")
+      (should-error (zlib-compress-region (point-min) (point-max) 'bzip2)))))
+
+(ert-deftest zlib--decompress-multibyte ()
+  "Test decompressing into a multibyte buffer."
+  (when (and (fboundp 'zlib-available-p)
+	     (zlib-available-p))
+    (let ((compressed (with-temp-buffer
+			(set-buffer-multibyte nil)
+			(insert (encode-coding-string "f\u00f6\u00f6\n" 'utf-8))
+			(zlib-compress-region (point-min) (point-max))
+			(buffer-string))))
+      (with-temp-buffer
+	(setq buffer-file-coding-system 'utf-8)
+	(insert (string-to-multibyte compressed))
+	(should-error (zlib-decompress-region (point-min) (point-max)))
+	(should (zlib-decompress-region (point-min) (point-max) t))
+	(should (string= (buffer-string) "f\u00f6\u00f6\n"))
+	(should (= (point-max) 5))))))
+
+(ert-deftest zlib--decompress-zstd ()
+  "Test decompressing a Zstandard file."
+  (when (and (fboundp 'zlib-available-p)
+	     (zlib-available-p))
+    (should (string=
+	     (with-temp-buffer
+	       (set-buffer-multibyte nil)
+	       (insert-file-contents-literally
+		(expand-file-name "foo.zst" zlib-tests-data-directory))
+	       (zlib-decompress-region (point-min) (point-max))
+	       (buffer-string))
+	     "foo\n"))))
+
+(ert-deftest zlib--decompress-xz ()
+  "Test decompressing an XZ file."
+  (when (and (fboundp 'zlib-available-p)
+	     (zlib-available-p))
+    (should (string=
+	     (with-temp-buffer
+	       (set-buffer-multibyte nil)
+	       (insert-file-contents-literally
+		(expand-file-name "foo.xz" zlib-tests-data-directory))
+	       (zlib-decompress-region (point-min) (point-max))
+	       (buffer-string))
+	     "foo\n"))))
+
+(ert-deftest zlib--decompress-brotli ()
+  "Test decompressing a brotli file."
+  (when (fboundp 'brotli-decompress-region)
+    (should (string=
+	     (with-temp-buffer
+	       (set-buffer-multibyte nil)
+	       (insert-file-contents-literally
+		(expand-file-name "foo.br" zlib-tests-data-directory))
+	       (should (brotli-decompress-region (point-min) (point-max)))
+	       (buffer-string))
+	     "foo\n"))))
+
+(ert-deftest zlib--decompress-brotli-invalid ()
+  "Test that invalid brotli data is left in place."
+  (when (fboundp 'brotli-decompress-region)
+    (with-temp-buffer
+      (set-buffer-multibyte nil)
+      (insert "\xff\xff\xff\xff")
+      (should-not (brotli-decompress-region (point-min) (point-max)))
+      (should (string= (buffer-string) "\xff\xff\xff\xff")))))
+
+(ert-deftest zlib--decompress-file-into-buffer ()
+  "Test streaming a compressed file into a buffer."
+  (with-temp-buffer
+    (set-buffer-multibyte nil)
+    (insert "ab")
+    (goto-char 2)
+    (should (= (zlib-decompress-file-into-buffer
+		(expand-file-name "foo.gz" zlib-tests-data-directory))
+	       4))
+    (should (= (point) 2))
+    (should (string= (buffer-string) "afoo\nb"))))
+
+(ert-deftest zlib--decompress-file-into-buffer-missing ()
+  "Test that a missing file signals `file-missing'."
+  (with-temp-buffer
+    (set-buffer-multibyte nil)
+    (should-error (zlib-decompress-file-into-buffer
+		   (expand-file-name "nonexistent.gz" zlib-tests-data-directory))
+		  :type 'file-missing)))
+
+(ert-deftest zlib--last-gzip-header ()
+  "Test retrieving the header of a decompressed gzip stream."
+  (when (and (fboundp 'zlib-available-p)
+	     (zlib-available-p))
+    (with-temp-buffer
+      (set-buffer-multibyte nil)
+      (insert-file-contents-literally
+       (expand-file-name "foo.gz" zlib-tests-data-directory))
+      (zlib-decompress-region (point-min) (point-max))
+      (let ((header (zlib-last-gzip-header)))
+	(should (equal (plist-get header :name) "small"))
+	(should (= (plist-get header :mtime) 1376331724))
+	(should-not (plist-get header :comment)))
+      ;; The header is recorded in the buffer it was decompressed into.
+      (with-temp-buffer
+	(should-not (zlib-last-gzip-header))))
+    (with-temp-buffer
+      (set-buffer-multibyte nil)
+      (insert-file-contents-literally
+       (expand-file-name "foo.xz" zlib-tests-data-directory))
+      (zlib-decompress-region (point-min) (point-max))
+      (should-not (zlib-last-gzip-header)))))
+
+(ert-deftest zlib--decompress-strict ()
+  "Test that STRICT reports truncated and corrupt gzip data."
+  (when (and (fboundp 'zlib-available-p)
+	     (zlib-available-p))
+    (with-temp-buffer
+      (set-buffer-multibyte nil)
+      (insert-file-contents-literally
+       (expand-file-name "foo.gz" zlib-tests-data-directory))
+      (should (zlib-decompress-region (point-min) (point-max) nil t))
+      (should (string= (buffer-string) "foo\n")))
+    ;; Truncated: the length field of the trailer is missing.
+    (with-temp-buffer
+      (set-buffer-multibyte nil)
+      (insert-file-contents-literally
+       (expand-file-name "foo.gz" zlib-tests-data-directory))
+      (delete-region (- (point-max) 4) (point-max))
+      (let ((compressed (buffer-string)))
+	(should-not (zlib-decompress-region (point-min) (point-max)))
+	(should (eq (cadr (should-error
+			   (zlib-decompress-region (point-min) (point-max) nil t)
+			   :type 'zlib-error))
+		    'truncated))
+	(should (string= (buffer-string) compressed))))
+    ;; Corrupt: the CRC32 in the trailer does not match.
+    (with-temp-buffer
+      (set-buffer-multibyte nil)
+      (insert-file-contents-literally
+       (expand-file-name "foo.gz" zlib-tests-data-directory))
+      (goto-char (- (point-max) 8))
+      (delete-char 1)
+      (insert "\x00")
+      (let ((data (cdr (should-error
+			(zlib-decompress-region (point-min) (point-max) nil t)
+			:type 'zlib-error))))
+	(should (eq (nth 0 data) 'corrupt))
+	(should (integerp (nth 1 data)))
+	(should (stringp (nth 2 data)))))))
+
+(ert-deftest zlib--decompress-region-to-buffer ()
+  "Test decompressing a region into another buffer."
+  (when (and (fboundp 'zlib-available-p)
+	     (zlib-available-p))
+    (let ((target (generate-new-buffer " *zlib-target*")))
+      (unwind-protect
+	  (with-temp-buffer
+	    (set-buffer-multibyte nil)
+	    (insert-file-contents-literally
+	     (expand-file-name "foo.gz" zlib-tests-data-directory))
+	    (let ((compressed (buffer-string)))
+	      (should (zlib-decompress-region-to-buffer
+		       (point-min) (point-max) target))
+	      (should (string= (buffer-string) compressed))
+	      (should (eq (current-buffer)
+			  (progn (zlib-decompress-region-to-buffer
+				  (point-min) (point-max) (buffer-name target))
+				 (current-buffer)))))
+	    (with-current-buffer target
+	      (should (string= (buffer-string) "foo\nfoo\n"))))
+	(kill-buffer target)))))
+
+(ert-deftest zlib--decompress-multiple-members ()
+  "Test decompressing a gzip file made of several members."
+  (when (and (fboundp 'zlib-available-p)
+	     (zlib-available-p))
+    (should (string=
+	     (with-temp-buffer
+	       (set-buffer-multibyte nil)
+	       (insert-file-contents-literally
+		(expand-file-name "foo-bar.gz" zlib-tests-data-directory))
+	       (zlib-decompress-region (point-min) (point-max))
+	       (buffer-string))
+	     "foo\nbar\n"))))
+
+(ert-deftest zlib--decompress-region-async ()
+  "Test decompressing a region in the background."
+  (when (and (fboundp 'zlib-available-p)
+	     (zlib-available-p))
+    (with-temp-buffer
+      (set-buffer-multibyte nil)
+      (insert-file-contents-literally
+       (expand-file-name "32k-a.gz" zlib-tests-data-directory))
+      (let* ((compressed (buffer-string))
+	     (result 'pending)
+	     (deadline (+ (float-time) 10)))
+	(zlib-decompress-region-async (point-min) (point-max)
+				      (lambda (data) (setq result data)))
+	(should (string= (buffer-string) compressed))
+	(while (and (eq result 'pending) (< (float-time) deadline))
+	  (accept-process-output nil 0.05))
+	(should (string= result (make-string (* 32 1024) ?a)))))))
+
+(ert-deftest zlib--decompress-region-async-empty ()
+  "Test that decompressing an empty region in the background fails."
+  (when (and (fboundp 'zlib-available-p)
+	     (zlib-available-p))
+    (with-temp-buffer
+      (set-buffer-multibyte nil)
+      (let ((result 'pending)
+	    (deadline (+ (float-time) 10)))
+	(zlib-decompress-region-async (point-min) (point-max)
+				      (lambda (data) (setq result data)))
+	(while (and (eq result 'pending) (< (float-time) deadline))
+	  (accept-process-output nil 0.05))
+	(should-not result)))))
+
+(ert-deftest zlib--decompress-region-async-cancel ()
+  "Test cancelling a background decompression."
+  (when (and (fboundp 'zlib-available-p)
+	     (zlib-available-p))
+    (with-temp-buffer
+      (set-buffer-multibyte nil)
+      (insert-file-contents-literally
+       (expand-file-name "foo.gz" zlib-tests-data-directory))
+      (let* ((called nil)
+	     (job (zlib-decompress-region-async
+		   (point-min) (point-max)
+		   (lambda (_data) (setq called t)))))
+	(should (zlib-decompress-async-cancel job))
+	(should-not (zlib-decompress-async-cancel job))
+	(accept-process-output nil 0.2)
+	(should-not called)))))
+
+(ert-deftest zlib--decompress-dictionary ()
+  "Test decompressing data compressed with a preset dictionary."
+  (when (and (fboundp 'zlib-available-p)
+	     (zlib-available-p))
+    (let ((dictionary "foo bar baz "))
+      ;; Raw deflate.
+      (with-temp-buffer
+	(set-buffer-multibyte nil)
+	(insert "\x4b\xc3\xce\xe6\x02\x00")
+	(should (zlib-decompress-region (point-min) (point-max)
+					nil nil dictionary))
+	(should (string= (buffer-string) "foo bar baz foo bar baz\n")))
+      ;; Zlib, whose header identifies the dictionary.
+      (with-temp-buffer
+	(set-buffer-multibyte nil)
+	(insert "\x78\xf9\x1b\xd1\x04\x17\x4b\xc3\xce\xe6\x02\x00\x68\x94\x08\x17")
+	(should-not (zlib-decompress-region (point-min) (point-max)
+					    nil nil "wrong"))
+	(should (zlib-decompress-region (point-min) (point-max)
+					nil nil dictionary))
+	(should (string= (buffer-string) "foo bar baz foo bar baz\n"))))))
+
+(ert-deftest zlib--compress-string-roundtrip ()
+  "Test compressing and decompressing strings."
+  (when (and (fboundp 'zlib-available-p)
+	     (zlib-available-p))
+    (dolist (format '(nil gzip zlib deflate))
+      (let ((compressed (zlib-compress-string "foo\nbar\n" format)))
+	(should-not (multibyte-string-p compressed))
+	(should (string= (zlib-decompress-string compressed) "foo\nbar\n"))))
+    (should (string= (zlib-decompress-string
+		      (with-temp-buffer
+			(set-buffer-multibyte nil)
+			(insert-file-contents-literally
+			 (expand-file-name "foo.gz" zlib-tests-data-directory))
+			(buffer-string)))
+		     "foo\n"))
+    (should-not (zlib-decompress-string "\xff\xff\xff\xff"))
+    (should-error (zlib-compress-string "é"))))
+
+(ert-deftest zlib--decompress-chunk-size ()
+  "Test decompressing with a small `zlib-decompress-chunk-size'."
+  (when (and (fboundp 'zlib-available-p)
+	     (zlib-available-p))
+    (let ((zlib-decompress-chunk-size 100))
+      (should (string=
+	       (with-temp-buffer
+		 (set-buffer-multibyte nil)
+		 (insert-file-contents-literally
+		  (expand-file-name "32k-a.gz" zlib-tests-data-directory))
+		 (zlib-decompress-region (point-min) (point-max))
+		 (buffer-string))
+	       (make-string (* 32 1024) ?a))))))
+
+(ert-deftest zlib--decompress-max-output-bytes ()
+  "Test that MAX-OUTPUT-BYTES bounds the size of the result."
+  (when (and (fboundp 'zlib-available-p)
+	     (zlib-available-p))
+    (with-temp-buffer
+      (set-buffer-multibyte nil)
+      (insert-file-contents-literally
+       (expand-file-name "32k-a.gz" zlib-tests-data-directory))
+      (let ((compressed (buffer-string)))
+	(should-not (zlib-decompress-region (point-min) (point-max)
+					    nil nil nil 1000))
+	(should (string= (buffer-string) compressed))
+	(should (zlib-decompress-region (point-min) (point-max)
+					nil nil nil (* 32 1024)))
+	(should (= (buffer-size) (* 32 1024)))))
+    (with-temp-buffer
+      (set-buffer-multibyte nil)
+      (should-not (zlib-decompress-file-into-buffer
+		   (expand-file-name "32k-a.gz" zlib-tests-data-directory)
+		   1000))
+      (should (= (buffer-size) 0)))))
+
 (provide 'decompress-tests)
 
 ;;; decompress-tests.el ends here.
@@ -0,0 +1,76 @@
+;;; diff-tests.el --- tests for native diffing
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest diff-regions-strings ()
+  (should (equal (diff-regions "a\nb\nc\n" "a\nb\nc\n") nil))
+  (should (equal (diff-regions "a\nb\nc\n" "a\nx\nc\n") '((2 4 2 4))))
+  ;; Insertions and deletions have an empty part.
+  (should (equal (diff-regions "a\nc\n" "a\nb\nc\n") '((2 2 2 4))))
+  (should (equal (diff-regions "a\nb\nc" "a\nc") '((2 4 2 2))))
+  (should (equal (diff-regions "été" "etè" :granularity 'char)
+                 '((0 1 0 1) (2 3 2 3))))
+  (should-error (diff-regions "a" "b" :granularity 'word))
+  (should-error (diff-regions "a" "b" :algorithm 'magic))
+  (should-error (diff-regions "a" 'b) :type 'wrong-type-argument))
+
+(defun diff-tests--apply (old new hunks)
+  "Return the string OLD with the differences HUNKS from NEW applied."
+  (let ((pos 0)
+        (parts nil))
+    (dolist (hunk hunks)
+      (pcase-let ((`(,old-beg ,old-end ,new-beg ,new-end) hunk))
+        (push (substring old pos old-beg) parts)
+        (push (substring new new-beg new-end) parts)
+        (setq pos old-end)))
+    (push (substring old pos) parts)
+    (apply #'concat (nreverse parts))))
+
+(ert-deftest diff-regions-algorithms ()
+  (let ((old "int f() {\n  a();\n}\n\nint g() {\n  b();\n}\n")
+        (new "int g() {\n  b();\n}\n\nint h() {\n  a();\n}\n"))
+    (dolist (algorithm '(myers patience))
+      (dolist (granularity '(line char))
+        (let ((hunks (diff-regions old new :algorithm algorithm
+                                   :granularity granularity)))
+          (should hunks)
+          (should (equal (diff-tests--apply old new hunks) new)))))))
+
+(ert-deftest diff-regions-buffers ()
+  (let ((old (generate-new-buffer "old"))
+        (new (generate-new-buffer "new")))
+    (unwind-protect
+        (progn
+          (with-current-buffer old
+            (insert "header\none\ntwo\nthree\n"))
+          (with-current-buffer new
+            (insert "one\n2\nthree\n"))
+          (should (equal (diff-regions old new)
+                         '((1 8 1 1) (12 16 5 7))))
+          (should (equal (diff-regions (list old 8 nil) "one\n2\nthree\n")
+                         '((12 16 4 6))))
+          (should (equal (diff-regions (list old 8 12) new)
+                         '((12 12 5 13))))
+          (should-error (diff-regions (list old 0 5) new)
+                        :type 'args-out-of-range))
+      (kill-buffer old)
+      (kill-buffer new))))
+
+(provide 'diff-tests)
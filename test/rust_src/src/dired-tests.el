@@ -35,3 +35,34 @@
         (should (= (length (system-users)) 1)))
     (progn
       (should (>= (length (system-users)) 1)))))
+
+(ert-deftest test-directory-files-recursively-fast ()
+  (let ((dir (file-name-as-directory (make-temp-file "walk" t))))
+    (unwind-protect
+        (progn
+          (dolist (file '("a.el" "b.txt" "sub/c.el" "sub/deep/d.el"
+                          "skip/e.el" ".hidden.el"))
+            (let ((file (expand-file-name file dir)))
+              (make-directory (file-name-directory file) t)
+              (write-region "" nil file nil 'silent)))
+          (write-region "skip\n" nil (expand-file-name ".ignore" dir)
+                        nil 'silent)
+          (should (equal (directory-files-recursively-fast dir "\\.el\\'")
+                         (sort (directory-files-recursively dir "\\.el\\'")
+                               #'string-lessp)))
+          (should (equal (directory-files-recursively-fast
+                          dir "\\.el\\'" :max-depth 1 :ignore-files t)
+                         (list (expand-file-name "a.el" dir)
+                               (expand-file-name "sub/c.el" dir))))
+          (should (member (expand-file-name "sub" dir)
+                          (directory-files-recursively-fast
+                           dir "" :include-directories t)))
+          (let ((entry (car (directory-files-recursively-fast
+                             dir "\\`b" :attributes t))))
+            (should (equal (car entry) (expand-file-name "b.txt" dir)))
+            (should (equal (cdr entry)
+                           (file-attributes (car entry)))))
+          (should-error (directory-files-recursively-fast dir "" :depth 1))
+          (should-error (directory-files-recursively-fast
+                         (expand-file-name "a.el" dir) "")))
+      (delete-directory dir t))))
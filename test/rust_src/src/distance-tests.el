@@ -0,0 +1,54 @@
+;;; distance-tests.el --- tests for string distance functions
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest string-distance ()
+  (should (= (string-distance "kitten" "sitting") 3))
+  (should (= (string-distance "" "abc") 3))
+  (should (= (string-distance "same" "same") 0))
+  ;; Multibyte characters count as one, unless comparing bytes.
+  (should (= (string-distance "été" "ete") 2))
+  (should (= (string-distance "été" "ete" t) 4))
+  (should (= (string-distance "ab" "ba") 2)))
+
+(ert-deftest string-edit-distance ()
+  (should (= (string-edit-distance "kitten" "sitting") 3))
+  (should (= (string-edit-distance "ab" "ba" t) 1))
+  (should (= (string-edit-distance "réçu" "rçéu" t) 1))
+  (should (= (string-edit-distance "ca" "abc" t) 3))
+  (should (= (string-edit-distance "kitten" "sitting" nil 3) 3))
+  (should-not (string-edit-distance "kitten" "sitting" nil 2))
+  (should-not (string-edit-distance "a" "abcdef" nil 4))
+  (should-error (string-edit-distance "a" "b" nil -1) :type 'args-out-of-range))
+
+(ert-deftest string-distance-rank ()
+  (let ((candidates '("recieve" "receive" "deceive" "relieve" "rec")))
+    (should (equal (string-distance-rank "receive" candidates)
+                   '(("receive" . 0) ("deceive" . 1) ("recieve" . 2)
+                     ("relieve" . 3) ("rec" . 4))))
+    ;; Equal distances keep the order of the candidates.
+    (should (equal (string-distance-rank "receive" (vconcat candidates) t 1)
+                   '(("receive" . 0) ("recieve" . 1) ("deceive" . 1))))
+    (should (eq (car (car (string-distance-rank "rec" candidates)))
+                (nth 4 candidates)))
+    (should-error (string-distance-rank "a" '("a" b))
+                  :type 'wrong-type-argument)))
+
+(provide 'distance-tests)
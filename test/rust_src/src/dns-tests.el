@@ -0,0 +1,53 @@
+;;; dns-tests.el --- tests for DNS lookups
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+(require 'network-stream)
+
+(ert-deftest dns-resolve-types ()
+  (should-error (dns-resolve "localhost" 'HINFO))
+  (should-error (dns-resolve "localhost" "A") :type 'wrong-type-argument))
+
+(ert-deftest dns-resolve-localhost ()
+  (let ((addresses (ignore-errors (dns-resolve "localhost" 'A))))
+    (skip-unless addresses)
+    (should (member "127.0.0.1" addresses))))
+
+(ert-deftest dns-resolve-callback ()
+  (skip-unless (ignore-errors (dns-resolve "localhost" 'A)))
+  (let* ((result 'unset)
+         (job (dns-resolve "localhost" 'A
+                           (lambda (records) (setq result records)))))
+    (should (integerp job))
+    (with-timeout (10 (error "Lookup timed out"))
+      (while (eq result 'unset)
+        (accept-process-output nil 0.05)))
+    (should (member "127.0.0.1" result))))
+
+(ert-deftest dns-network-stream-host-address ()
+  (should (equal (network-stream-host-address "127.0.0.1") "127.0.0.1"))
+  (should (equal (network-stream-host-address "::1") "::1"))
+  (should (equal (network-stream-host-address "example.invalid" t)
+                 "example.invalid"))
+  (let ((network-stream-resolve-hosts nil))
+    (should (equal (network-stream-host-address "localhost") "localhost"))))
+
+(provide 'dns-tests)
+
+;;; dns-tests.el ends here
@@ -96,3 +96,52 @@
     (should (equal fixed-time (time-subtract fixed-time 0)))
     (should (equal more-time (time-add fixed-time '(0 10))))
     (should (equal less-time (time-subtract fixed-time '(0 20))))))
+
+(ert-deftest region-char-histogram ()
+  (with-temp-buffer
+    (insert "abracadabra αα")
+    (let ((histogram (region-char-histogram (point-min) (point-max))))
+      (should (= (hash-table-count histogram) 7))
+      (should (= (gethash ?a histogram) 5))
+      (should (= (gethash ?b histogram) 2))
+      (should (= (gethash ?α histogram) 2))
+      (should-not (gethash ?z histogram)))
+    (let ((histogram (region-char-histogram 5 1)))
+      (let (keys)
+        (maphash (lambda (key _count) (push key keys)) histogram)
+        (should (equal (nreverse keys) '(?a ?b ?r))))
+      (should (= (gethash ?a histogram) 2)))
+    (should (= (hash-table-count (region-char-histogram 3 3)) 0))
+    (should-error (region-char-histogram 1 100) :type 'args-out-of-range))
+  (with-temp-buffer
+    (set-buffer-multibyte nil)
+    (insert "\377\0\377")
+    (let ((histogram (region-char-histogram (point-min) (point-max))))
+      (should (= (gethash 255 histogram) 2))
+      (should (= (gethash 0 histogram) 1)))))
+
+(ert-deftest buffer-substring-literal ()
+  (with-temp-buffer
+    (insert (propertize "héllo" 'face 'bold) " wörld")
+    ;; Move the gap into the middle of the text.
+    (goto-char 4)
+    (insert "x")
+    (delete-char -1)
+    (let ((s (buffer-substring-literal 2 10)))
+      (should (equal s "éllo wö"))
+      (should-not (text-properties-at 0 s))
+      (should (multibyte-string-p s)))
+    (should (equal (buffer-substring-literal 10 2) "éllo wö"))
+    (narrow-to-region 2 4)
+    (should-error (buffer-substring-literal 1 3) :type 'args-out-of-range)
+    (let ((buffer (current-buffer)))
+      (with-temp-buffer
+        (should (equal (buffer-substring-literal 2 4 buffer) "él"))))))
+
+(ert-deftest buffer-substring-literal-unibyte ()
+  (with-temp-buffer
+    (set-buffer-multibyte nil)
+    (insert "a\377b")
+    (let ((s (buffer-substring-literal (point-min) (point-max))))
+      (should-not (multibyte-string-p s))
+      (should (equal s "a\377b")))))
@@ -39,3 +39,187 @@
       (should-not (file-name-case-insensitive-p file)))
     (when (eq system-type 'darwin)
       (should (file-name-case-insensitive-p file)))))
+
+(ert-deftest test-insert-file-contents-mapped ()
+  (let ((file (make-temp-file "mapped"))
+        (contents (concat "héllo\n" (make-string 10000 ?x) "\nwörld\n")))
+    (unwind-protect
+        (let ((coding-system-for-write 'utf-8-unix)
+              (coding-system-for-read 'utf-8-unix))
+          (write-region contents nil file nil 'silent)
+          (let ((insert-file-contents-mmap-threshold 0))
+            (with-temp-buffer
+              (insert "ab")
+              (goto-char 2)
+              (should (equal (insert-file-contents file)
+                             (list file (length contents))))
+              (should (equal (buffer-string) (concat "a" contents "b")))
+              (should (= (point) 2)))
+            ;; Only the part between BEG and END is mapped.
+            (with-temp-buffer
+              (insert-file-contents file nil 1 6)
+              (should (equal (buffer-string) "éllo")))
+            ;; The coding system is detected from the mapping too.
+            (let ((coding-system-for-read nil))
+              (with-temp-buffer
+                (insert-file-contents file)
+                (should (equal (buffer-string) contents))
+                (should (eq (coding-system-base last-coding-system-used)
+                            'utf-8)))))
+          ;; Files smaller than the threshold are read as usual.
+          (let ((insert-file-contents-mmap-threshold (* 1024 1024)))
+            (with-temp-buffer
+              (insert-file-contents file)
+              (should (equal (buffer-string) contents)))))
+      (delete-file file))))
+
+(ert-deftest test-insert-file-contents-decompressed ()
+  (let ((file (make-temp-file "compressed"))
+        (contents "héllo\nwörld\n"))
+    (unwind-protect
+        (let ((coding-system-for-write 'no-conversion))
+          (write-region (zlib-compress-string (encode-coding-string
+                                               contents 'utf-8)
+                                              'gzip)
+                        nil file nil 'silent)
+          (with-temp-buffer
+            (insert "ab")
+            (goto-char 2)
+            (should (equal (insert-file-contents-decompressed file)
+                           (string-bytes contents)))
+            ;; Point is left before the raw bytes, which decode as
+            ;; the original text.
+            (should (= (point) 2))
+            (decode-coding-region 2 (- (point-max) 1) 'utf-8)
+            (should (equal (buffer-string) (concat "a" contents "b"))))
+          ;; Files which aren't compressed are left alone.
+          (write-region contents nil file nil 'silent)
+          (with-temp-buffer
+            (should-not (insert-file-contents-decompressed file))
+            (should (= (buffer-size) 0))))
+      (delete-file file))
+    (should-error (insert-file-contents-decompressed file)
+                  :type 'file-missing)))
+
+(ert-deftest test-write-region-async ()
+  (let ((file (make-temp-file "async"))
+        (write-region-sync-mode 'data))
+    (unwind-protect
+        (let ((coding-system-for-write 'utf-8-unix)
+              (result 'pending)
+              (deadline (+ (float-time) 10)))
+          (with-temp-buffer
+            (insert "héllo\n")
+            ;; The second write is only started once the first is
+            ;; finished, since the two would race otherwise.
+            (write-region-async
+             nil nil file
+             (lambda (name error)
+               (should (equal name file))
+               (should-not error)
+               (write-region-async "wörld\n" nil file
+                                   (lambda (_name error)
+                                     (setq result error))
+                                   'append))))
+          (while (and (eq result 'pending) (< (float-time) deadline))
+            (accept-process-output nil 0.05))
+          (should-not result)
+          (with-temp-buffer
+            (let ((coding-system-for-read 'utf-8-unix))
+              (insert-file-contents file))
+            (should (equal (buffer-string) "héllo\nwörld\n"))))
+      (delete-file file))))
+
+(ert-deftest test-write-region-async-error ()
+  (let ((result 'pending)
+        (deadline (+ (float-time) 10)))
+    (write-region-async "x" nil "/nonexistent/directory/file"
+                        (lambda (_name error) (setq result error)))
+    (while (and (eq result 'pending) (< (float-time) deadline))
+      (accept-process-output nil 0.05))
+    (should (stringp result))))
+
+(ert-deftest test-copy-file-progress ()
+  (let* ((file (make-temp-file "copy"))
+         (newname (concat file "-copy"))
+         (size (* 3 1024 1024))
+         (calls nil)
+         (copy-file-progress-function
+          (lambda (copied total) (push (cons copied total) calls))))
+    (unwind-protect
+        (let ((coding-system-for-write 'no-conversion))
+          (with-temp-buffer
+            (set-buffer-multibyte nil)
+            (dotimes (i size)
+              (insert (% i 251)))
+            (write-region nil nil file nil 'silent))
+          (copy-file file newname nil t nil t)
+          (should (equal (file-attribute-size (file-attributes newname))
+                         size))
+          (should (equal (file-modes newname) (file-modes file)))
+          (should (equal (nth 5 (file-attributes newname))
+                         (nth 5 (file-attributes file))))
+          (with-temp-buffer
+            (set-buffer-multibyte nil)
+            (insert-file-contents-literally newname)
+            (should (= (char-after 1000) (% 999 251))))
+          ;; Progress is reported up to the whole file.
+          (should calls)
+          (should (equal (car calls) (cons size size))))
+      (delete-file file)
+      (when (file-exists-p newname)
+        (delete-file newname)))))
+
+(ert-deftest test-write-region-atomic ()
+  (let* ((dir (make-temp-file "atomic" t))
+         (file (expand-file-name "file" dir))
+         (write-region-atomic t)
+         (backup-by-copying nil))
+    (unwind-protect
+        (progn
+          (write-region "old" nil file nil 'silent)
+          (set-file-modes file #o640)
+          (let ((inode (file-attribute-inode-number (file-attributes file))))
+            (write-region "new" nil file nil 'silent)
+            ;; The file was replaced by another one, which kept its
+            ;; permissions, and no temporary file was left behind.
+            (should-not (equal (file-attribute-inode-number
+                                (file-attributes file))
+                               inode))
+            (should (= (file-modes file) #o640))
+            (should (equal (directory-files dir nil "\\`[^.]\\|\\`\\.emacs")
+                           '("file")))
+            (with-temp-buffer
+              (insert-file-contents file)
+              (should (equal (buffer-string) "new"))))
+          ;; Appending still writes in place.
+          (write-region "er" nil file t 'silent)
+          (with-temp-buffer
+            (insert-file-contents file)
+            (should (equal (buffer-string) "newer"))))
+      (delete-directory dir t))))
+
+(ert-deftest test-write-region-atomic-in-place ()
+  (let* ((dir (make-temp-file "atomic" t))
+         (file (expand-file-name "file" dir))
+         (link (expand-file-name "link" dir))
+         (write-region-atomic t)
+         (backup-by-copying nil))
+    (unwind-protect
+        (progn
+          (write-region "old" nil file nil 'silent)
+          (add-name-to-file file link)
+          ;; A file with several names is written in place, so that
+          ;; they all still name it.
+          (write-region "new" nil file nil 'silent)
+          (with-temp-buffer
+            (insert-file-contents link)
+            (should (equal (buffer-string) "new")))
+          (delete-file link)
+          (let ((inode (file-attribute-inode-number (file-attributes file)))
+                (backup-by-copying t))
+            (write-region "newer" nil file nil 'silent)
+            (should (equal (file-attribute-inode-number
+                            (file-attributes file))
+                           inode))))
+      (delete-directory dir t))))
@@ -0,0 +1,56 @@
+;;; filelock-tests.el --- tests for lock files and advisory locks
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest filelock-lock-buffer ()
+  (let ((file (make-temp-file "filelock"))
+        (create-lockfiles t))
+    (unwind-protect
+        (with-current-buffer (find-file-noselect file)
+          (should-not (file-locked-p file))
+          (insert "text")
+          (should (eq (file-locked-p file) t))
+          (should (file-symlink-p (expand-file-name
+                                   (concat ".#" (file-name-nondirectory file))
+                                   (file-name-directory file))))
+          (unlock-buffer)
+          (should-not (file-locked-p file))
+          (lock-buffer)
+          (should (eq (file-locked-p file) t))
+          (set-buffer-modified-p nil)
+          (kill-buffer))
+      (delete-file file))))
+
+(ert-deftest filelock-file-flock ()
+  (let ((file (make-temp-file "flock")))
+    (unwind-protect
+        (progn
+          (should (file-flock file))
+          ;; Locking again converts the lock instead of waiting for it.
+          (should (file-flock file t t))
+          (should (file-funlock file))
+          (should-not (file-funlock file))
+          (should-error (file-flock (concat file "-nonexistent"))
+                        :type 'file-missing))
+      (delete-file file))))
+
+(provide 'filelock-tests)
+
+;;; filelock-tests.el ends here
@@ -0,0 +1,49 @@
+;;; filenotify-tests.el --- tests for the Rust file notification backend
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest rustnotify-add-watch ()
+  (skip-unless (featurep 'rustnotify))
+  (let* ((dir (make-temp-file "rustnotify" t))
+         (file (expand-file-name "new-file" dir))
+         events
+         (desc (rustnotify-add-watch dir '(change)
+                                     (lambda (event) (push event events)))))
+    (unwind-protect
+        (progn
+          (should (rustnotify-valid-p desc))
+          (write-region "text" nil file nil 'silent)
+          (with-timeout (5 (ert-fail "No `created' event"))
+            (while (not (member (list desc 'created file) events))
+              (accept-process-output nil 0.1)))
+          (rustnotify-rm-watch desc)
+          (should-not (rustnotify-valid-p desc)))
+      (delete-directory dir t))))
+
+(ert-deftest rustnotify-add-watch-errors ()
+  (skip-unless (featurep 'rustnotify))
+  (should-error (rustnotify-add-watch "/nonexistent/directory" '(change)
+                                      #'ignore)
+                :type 'file-notify-error)
+  (should-not (rustnotify-valid-p 'not-a-descriptor)))
+
+(provide 'filenotify-tests)
+
+;;; filenotify-tests.el ends here
@@ -0,0 +1,83 @@
+;;; fill-tests.el --- tests for native filling
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest fill-region-natively ()
+  (with-temp-buffer
+    (setq fill-column 10)
+    (insert "aaa bbb ccc ddd eee\n\nfff  ggg\nhhh\n")
+    (let ((fill-prefix nil))
+      (fill-region-natively (point-min) (point-max)))
+    (should (equal (buffer-string)
+                   "aaa bbb\nccc ddd\neee\n\nfff ggg\nhhh\n"))))
+
+(ert-deftest fill-region-natively-keeps-properties ()
+  (with-temp-buffer
+    (setq fill-column 10)
+    (insert (propertize "aaaa" 'face 'bold) " bbbb cccc")
+    (let ((marker (copy-marker 8)))
+      (fill-region-natively (point-min) (point-max))
+      (should (equal (buffer-string) "aaaa bbbb\ncccc"))
+      (should (eq (get-text-property 1 'face) 'bold))
+      (should (= marker 8)))))
+
+(ert-deftest fill-region-natively-prefix ()
+  (with-temp-buffer
+    (setq fill-column 10)
+    (insert "  aaa bbb ccc")
+    (should (equal (fill-region-natively (point-min) (point-max)) "  "))
+    (should (equal (buffer-string) "  aaa bbb\n  ccc")))
+  (with-temp-buffer
+    (setq fill-column 10)
+    (insert ";; aaa bbb\n;; ccc")
+    (let ((fill-prefix ";; "))
+      (fill-region-natively (point-min) (point-max)))
+    (should (equal (buffer-string) ";; aaa bbb\n;; ccc"))))
+
+(ert-deftest fill-region-natively-cjk ()
+  (with-temp-buffer
+    (setq fill-column 8)
+    (insert "日本語の文章です\nそして")
+    (fill-region-natively (point-min) (point-max))
+    (should (equal (buffer-string) "日本語の\n文章です\nそして"))))
+
+(ert-deftest fill-region-natively-justify ()
+  (with-temp-buffer
+    (setq fill-column 6)
+    (insert "a b c dd")
+    (fill-region-natively (point-min) (point-max) 'full)
+    (should (equal (buffer-string) "a  b c\ndd")))
+  (with-temp-buffer
+    (setq fill-column 6)
+    (insert "ab")
+    (fill-region-natively (point-min) (point-max) 'right)
+    (should (equal (buffer-string) "    ab"))))
+
+(ert-deftest fill-natively-fill-paragraph ()
+  (with-temp-buffer
+    (setq fill-column 10)
+    (insert "aaa bbb ccc ddd")
+    (let ((fill-natively t))
+      (fill-paragraph))
+    (should (equal (buffer-string) "aaa bbb\nccc ddd"))))
+
+(provide 'fill-tests)
+
+;;; fill-tests.el ends here
@@ -0,0 +1,92 @@
+;;; format-tests.el --- tests for format and format-message
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest format-field-width-multibyte ()
+  ;; Widths count columns, so wide characters take up two.
+  (should (equal (format "%5s|" "日本") " 日本|"))
+  (should (equal (format "%-5s|" "日本") "日本 |"))
+  (should (equal (format "%.3s|" "日本語") "日|"))
+  (should (equal (format "%.1s|" "日本語") "|"))
+  (should (equal (format "%4s" "é") "   é")))
+
+(ert-deftest format-unibyte-and-multibyte ()
+  (should (multibyte-string-p (format "%s %s" "a" "é")))
+  (should-not (multibyte-string-p (format "%s %d" "a" 1)))
+  (should (equal (format "%s" (string-to-multibyte "\377"))
+                 (string-to-multibyte "\377")))
+  ;; An argument printed by `prin1' can make the result multibyte.
+  (should (equal (format "%d %S" 1 '("é")) "1 (\"é\")")))
+
+(ert-deftest format-reuses-parsed-format ()
+  (dotimes (i 3)
+    (should (equal (format "%03d:%-3s|" i 'ab) (format "00%d:ab |" i)))))
+
+(ert-deftest format-field-numbers ()
+  (should (equal (format "%2$s %1$s %s" "a" "b" "c") "b a b"))
+  (should (equal (format "%1$s%1$s" "x") "xx")))
+
+(ert-deftest format-floats ()
+  (should (equal (format "%s" 1.0) "1.0"))
+  (should (equal (format "%s" 0.1) "0.1"))
+  (should (equal (format "%s" (+ 0.1 0.2)) "0.30000000000000004"))
+  (should (equal (format "%s" 1e20) "1e+20"))
+  (should (equal (format "%S" -0.0) "-0.0"))
+  (should (equal (format "%s" 1.0e+INF) "1.0e+INF"))
+  (should (equal (format "%.2f|%e|%g" 3.14159 1.5 100.0)
+                 "3.14|1.500000e+00|100"))
+  (should (equal (format "%d %x" 2.7 255.0) "2 ff"))
+  (let ((float-output-format "%.2f"))
+    (should (equal (format "%s" 3.14159) "3.14"))))
+
+(ert-deftest format-integers-as-floats ()
+  (should (equal (format "%.2f|%e|%g" 3 15 100) "3.00|1.500000e+01|100"))
+  ;; Integers are formatted exactly, even those a double can't hold.
+  (should (equal (format "%.0f" most-positive-fixnum)
+                 (number-to-string most-positive-fixnum)))
+  (should (equal (format "%.0f" most-negative-fixnum)
+                 (number-to-string most-negative-fixnum)))
+  (should (equal (format "%.1e" 125) "1.2e+02")))
+
+(ert-deftest format-percent-and-errors ()
+  (should (equal (format "100%%") "100%"))
+  (should (equal (format "%-3%|") "%|"))
+  (should-error (format "%d") :type 'error)
+  (should-error (format "%d" "a") :type 'error)
+  (should-error (format "%q" 1) :type 'error)
+  (should-error (format "%5") :type 'error))
+
+(ert-deftest format-message-quotes ()
+  (let ((text-quoting-style 'curve))
+    (should (equal (format-message "`%s'" "x") "‘x’")))
+  (let ((text-quoting-style 'straight))
+    (should (equal (format-message "`%s'" "x") "'x'")))
+  (let ((text-quoting-style 'grave))
+    (should (equal (format-message "`%s'" "x") "`x'")))
+  (should (equal (format "`%s'" "x") "`x'")))
+
+(ert-deftest format-returns-argument-for-%s ()
+  (let ((s "abc"))
+    (should (eq (format "%s" s) s))
+    (should (eq (format s) s))))
+
+(provide 'format-tests)
+
+;;; format-tests.el ends here
@@ -0,0 +1,51 @@
+;;; fuzzy-tests.el --- tests for fuzzy matching
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest fuzzy-score ()
+  (should (integerp (fuzzy-score "find-file" "ff")))
+  (should-not (fuzzy-score "find-file" "fz"))
+  (should-not (fuzzy-score "ab" "ba"))
+  (should (eql (fuzzy-score "anything" "") 0))
+  ;; Word boundaries and consecutive characters score higher.
+  (should (> (fuzzy-score "find-file" "ff") (fuzzy-score "buffer" "ff")))
+  (should (> (fuzzy-score "switch-to-buffer" "buf")
+             (fuzzy-score "rebuild-fun" "buf")))
+  ;; Case is ignored unless the query contains upper-case letters.
+  (should (fuzzy-score "FindFile" "ff"))
+  (should-not (fuzzy-score "findfile" "FF"))
+  (should (fuzzy-score "größe" "gß"))
+  (should-error (fuzzy-score 'find-file "ff") :type 'wrong-type-argument))
+
+(ert-deftest fuzzy-filter ()
+  (let ((candidates '("buffer-list" "find-file" "bound-and-true-p"
+                      "find-file-other-window" "kill-buffer")))
+    (should (equal (fuzzy-filter candidates "ff")
+                   (sort (seq-filter (lambda (c) (fuzzy-score c "ff"))
+                                     (copy-sequence candidates))
+                         (lambda (a b)
+                           (> (fuzzy-score a "ff") (fuzzy-score b "ff"))))))
+    (should (equal (fuzzy-filter (vconcat candidates) "zzz") nil))
+    ;; An empty query keeps every candidate, in order.
+    (should (equal (fuzzy-filter candidates "") candidates))
+    (should (eq (car (fuzzy-filter candidates "kill")) (nth 4 candidates)))
+    (should-error (fuzzy-filter '("a" b) "a") :type 'wrong-type-argument)))
+
+(provide 'fuzzy-tests)
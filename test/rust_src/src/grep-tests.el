@@ -0,0 +1,72 @@
+;;; grep-tests.el --- tests for native directory search
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(defmacro grep-tests--with-tree (&rest body)
+  "Run BODY with `default-directory' bound to a new directory tree."
+  (declare (indent 0))
+  `(let ((default-directory
+           (file-name-as-directory (make-temp-file "grep-tests" t))))
+     (unwind-protect
+         (progn
+           (make-directory "sub")
+           (write-region "foo\nbar baz\n" nil "a.txt")
+           (write-region "xbar\n" nil "sub/b.txt")
+           (write-region "bar\n" nil "skipped.log")
+           (write-region "bar\n" nil ".hidden")
+           (write-region "*.log\n" nil ".ignore")
+           ,@body)
+       (delete-directory default-directory t))))
+
+(defun grep-tests--sort (results)
+  (sort results (lambda (a b) (string< (car a) (car b)))))
+
+(ert-deftest directory-search ()
+  (grep-tests--with-tree
+    (let ((expected `((,(expand-file-name "a.txt") 2 0 "bar baz")
+                      (,(expand-file-name "sub/b.txt") 1 1 "xbar"))))
+      (should (equal (grep-tests--sort (directory-search "ba[rz]" "."))
+                     expected))
+      (let ((case-fold-search t))
+        (should (equal (grep-tests--sort (directory-search "BAR" "."))
+                       expected)))
+      (let ((case-fold-search nil))
+        (should-not (directory-search "BAR" "."))))
+    (should-error (directory-search "\\(a\\)\\1" ".") :type 'invalid-regexp)
+    (should-error (directory-search "a" "a.txt"))))
+
+(ert-deftest directory-search-background ()
+  (grep-tests--with-tree
+    (let ((search (directory-search "bar" default-directory t))
+          (results nil)
+          (finished nil))
+      (should (integerp search))
+      (while (not finished)
+        (let ((state (directory-search-results search)))
+          (setq finished (car state))
+          (setq results (append results (cdr state))))
+        (sleep-for 0.01))
+      (should (equal (mapcar #'cadr (grep-tests--sort results)) '(2 1)))
+      (should-error (directory-search-results search)))
+    (let ((search (directory-search "bar" default-directory t)))
+      (directory-search-cancel search)
+      (should-error (directory-search-cancel search)))))
+
+(provide 'grep-tests)
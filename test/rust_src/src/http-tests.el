@@ -0,0 +1,94 @@
+;;; http-tests.el --- tests for the HTTP client
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(defun http-tests--serve (response)
+  "Start a local server answering every request with RESPONSE.
+Return the server process."
+  (make-network-process
+   :name "http-tests-server" :server t :host 'local :service t
+   :filter (lambda (process _string)
+             (process-send-string process response)
+             (delete-process process))))
+
+(defun http-tests--url (server)
+  (format "http://127.0.0.1:%d/" (process-contact server :service)))
+
+(defun http-tests--response (handle)
+  "Wait for HANDLE, running the server meanwhile, and return the response."
+  (with-timeout (10 (error "Request timed out"))
+    (while (not (nth 4 handle))
+      (accept-process-output nil 0.05)))
+  (http-request-wait handle))
+
+(ert-deftest http-request-handle ()
+  (should-not (http-request-p nil))
+  (should-not (http-request-p '(http-request)))
+  (should-error (http-request-wait 1) :type 'wrong-type-argument)
+  (should-error (http-request-cancel '(foo 1)) :type 'wrong-type-argument))
+
+(ert-deftest http-request-body ()
+  (let* ((server (http-tests--serve
+                  (concat "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n"
+                          "Connection: close\r\n\r\nhello")))
+         (called nil)
+         (handle (http-request (http-tests--url server)
+                               :callback (lambda (response)
+                                           (setq called response)))))
+    (unwind-protect
+        (let ((response (http-tests--response handle)))
+          (should (http-request-p handle))
+          (should (eq called response))
+          (should (equal (plist-get response :status) 200))
+          (should (equal (plist-get response :body) "hello"))
+          (should (equal (cdr (assoc "content-length"
+                                     (plist-get response :headers)))
+                         "5"))
+          (should-not (plist-get response :error))
+          ;; Finished requests can't be cancelled.
+          (should-not (http-request-cancel handle)))
+      (delete-process server))))
+
+(ert-deftest http-request-into-buffer ()
+  (let ((server (http-tests--serve
+                 (concat "HTTP/1.1 404 Not Found\r\nContent-Length: 7\r\n"
+                         "Connection: close\r\n\r\nmissing"))))
+    (unwind-protect
+        (with-temp-buffer
+          (insert "> ")
+          (let ((response (http-tests--response
+                           (http-request (http-tests--url server)
+                                         :method 'HEAD
+                                         :buffer (current-buffer)))))
+            (should (equal (plist-get response :status) 404))
+            (should-not (plist-get response :body))
+            ;; A HEAD request has no body.
+            (should (equal (buffer-string) "> "))))
+      (delete-process server))))
+
+(ert-deftest http-request-error ()
+  (let ((response (http-tests--response
+                   (http-request "http://127.0.0.1:1/"))))
+    (should-not (plist-get response :status))
+    (should (stringp (plist-get response :error)))))
+
+(provide 'http-tests)
+
+;;; http-tests.el ends here
@@ -0,0 +1,79 @@
+;;; http_server-tests.el --- tests for the HTTP server
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+(require 'url)
+
+(defun http-server-tests--fetch (server path &optional method data)
+  "Request PATH from SERVER, and return (STATUS . BODY)."
+  (let ((url-request-method (or method "GET"))
+        (url-request-data data))
+    (with-current-buffer
+        (url-retrieve-synchronously
+         (format "http://127.0.0.1:%d%s" (http-server-port server) path)
+         t t 10)
+      (prog1 (cons url-http-response-status
+                   (progn (goto-char (point-min))
+                          (re-search-forward "\n\n")
+                          (buffer-substring (point) (point-max))))
+        (kill-buffer)))))
+
+(ert-deftest http-server-handle ()
+  (should-not (http-server-p nil))
+  (should-not (http-server-p '(http-server)))
+  (should-error (http-server-port '(foo 1 ignore)) :type 'wrong-type-argument)
+  (should-error (make-http-server -1 #'ignore))
+  (should-error (make-http-server 70000 #'ignore)))
+
+(ert-deftest http-server-request ()
+  (let* ((requests nil)
+         (server (make-http-server
+                  0 (lambda (request)
+                      (push request requests)
+                      (pcase (plist-get request :path)
+                        ("/hello" "hello")
+                        ("/teapot" '(:status 418
+                                     :headers (("Content-Type" . "text/plain"))
+                                     :body "short and stout"))
+                        ("/error" (error "Oops")))))))
+    (unwind-protect
+        (progn
+          (should (http-server-live-p server))
+          (should (> (http-server-port server) 0))
+          (should (equal (http-server-tests--fetch server "/hello?a=1&b=x%20y")
+                         '(200 . "hello")))
+          (let ((request (car requests)))
+            (should (equal (plist-get request :method) "GET"))
+            (should (equal (plist-get request :url) "/hello?a=1&b=x%20y"))
+            (should (equal (plist-get request :query)
+                           '(("a" . "1") ("b" . "x y"))))
+            (should (assoc "host" (plist-get request :headers))))
+          (should (equal (http-server-tests--fetch server "/teapot")
+                         '(418 . "short and stout")))
+          (should (equal (car (http-server-tests--fetch server "/missing")) 404))
+          (should (equal (car (http-server-tests--fetch server "/error")) 500))
+          (http-server-tests--fetch server "/post" "POST" "data")
+          (should (equal (plist-get (car requests) :body) "data")))
+      (delete-http-server server))
+    (should-not (http-server-live-p server))
+    (should-not (http-server-port server))))
+
+(provide 'http_server-tests)
+
+;;; http_server-tests.el ends here
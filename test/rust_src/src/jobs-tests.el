@@ -0,0 +1,96 @@
+;;; jobs-tests.el --- tests for job control of subprocesses
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(defmacro jobs-tests--with-shell (process &rest body)
+  "Run an interactive shell on a pty as PROCESS while evaluating BODY."
+  (declare (indent 1))
+  `(let ((,process (make-process :name "jobs-test" :command '("sh" "-i")
+                                 :buffer (generate-new-buffer " *jobs-test*")
+                                 :connection-type 'pty
+                                 :sentinel #'ignore)))
+     (unwind-protect
+         (progn
+           (accept-process-output ,process 0.5)
+           ,@body)
+       (let ((buffer (process-buffer ,process)))
+         (delete-process ,process)
+         (kill-buffer buffer)))))
+
+(defun jobs-tests--job (process command)
+  "Return the job of PROCESS running COMMAND, waiting for it to start."
+  (with-timeout (10 (error "Job didn't start"))
+    (let (job)
+      (while (not (setq job (seq-find (lambda (job)
+                                        (equal (plist-get (cdr job) :command)
+                                               command))
+                                      (process-job-groups process))))
+        (accept-process-output process 0.05))
+      job)))
+
+(ert-deftest jobs-shell-groups ()
+  (skip-unless (and (eq system-type 'gnu/linux) (executable-find "sh")))
+  (jobs-tests--with-shell process
+    (should (eql (process-foreground-group process) (process-id process)))
+    (let ((shell (assq (process-id process) (process-job-groups process))))
+      (should shell)
+      (should (plist-get (cdr shell) :foreground))
+      (should-not (plist-get (cdr shell) :stopped)))))
+
+(ert-deftest jobs-stop-and-continue ()
+  (skip-unless (and (eq system-type 'gnu/linux) (executable-find "sh")
+                    (executable-find "sleep")))
+  (jobs-tests--with-shell process
+    (process-send-string process "sleep 30 &\n")
+    (let ((group (car (jobs-tests--job process "sleep"))))
+      (should-not (eql group (process-id process)))
+      (should (eql (process-send-signal-group process 'SIGTSTP group) group))
+      (with-timeout (10 (error "Job didn't stop"))
+        (while (not (plist-get (cdr (assq group (process-job-groups process)))
+                               :stopped))
+          (accept-process-output process 0.05)))
+      (process-send-signal-group process 'SIGCONT group)
+      (with-timeout (10 (error "Job didn't continue"))
+        (while (plist-get (cdr (assq group (process-job-groups process)))
+                          :stopped)
+          (accept-process-output process 0.05)))
+      (process-send-signal-group process 'SIGKILL group))))
+
+(ert-deftest jobs-foreign-group ()
+  (skip-unless (executable-find "sh"))
+  (jobs-tests--with-shell process
+    (should-error (process-send-signal-group process 'SIGCONT 1))))
+
+(ert-deftest jobs-bad-signal ()
+  (skip-unless (executable-find "sh"))
+  (jobs-tests--with-shell process
+    (should-error (process-send-signal-group process 'SIGNOTASIGNAL))))
+
+(ert-deftest jobs-pipe-process ()
+  (skip-unless (executable-find "sleep"))
+  (let ((process (make-process :name "jobs-test" :command '("sleep" "10")
+                               :connection-type 'pipe)))
+    (unwind-protect
+        (should-not (process-foreground-group process))
+      (delete-process process))))
+
+(provide 'jobs-tests)
+
+;;; jobs-tests.el ends here
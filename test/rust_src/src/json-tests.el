@@ -1,4 +1,4 @@
-;;; json-tests.el --- unit tests for json.c          -*- lexical-binding: t; -*-
+;;; json-tests.el --- tests for json.rs          -*- lexical-binding: t; -*-
 
 ;; Copyright (C) 2017-2018 Free Software Foundation, Inc.
 
@@ -19,10 +19,11 @@
 
 ;;; Commentary:
 
-;; Unit tests for src/json.c.
+;; Unit tests for rust_src/src/json.rs.
 
 ;;; Code:
 
+(require 'ert)
 (require 'cl-lib)
 (require 'map)
 
@@ -161,6 +162,14 @@ Test with both unibyte and multibyte strings."
   (skip-unless (fboundp 'json-parse-string))
   (should-error (json-parse-string "[123] [456]") :type 'json-trailing-content))
 
+(ert-deftest json-parse-string/nesting-limit ()
+  (skip-unless (fboundp 'json-parse-string))
+  (should (json-parse-string
+           (concat (make-string 127 ?\[) (make-string 127 ?\]))))
+  (should-error (json-parse-string
+                 (concat (make-string 128 ?\[) (make-string 128 ?\])))
+                :type 'json-parse-error))
+
 (ert-deftest json-parse-buffer/incomplete ()
   (skip-unless (fboundp 'json-parse-buffer))
   (with-temp-buffer
@@ -208,5 +217,113 @@ Test with both unibyte and multibyte strings."
        :type 'no-catch)
       (should (equal calls 1)))))
 
+(ert-deftest json-rust-parse-object-types ()
+  (let ((input "{\"a\": 1, \"b\": [true, false, null], \"c\": {\"d\": \"e\"}}"))
+    (should (equal (json-parse-string input :object-type 'alist)
+                   '((a . 1) (b . [t :false :null]) (c . ((d . "e"))))))
+    (should (equal (json-parse-string input :object-type 'plist)
+                   '(:a 1 :b [t :false :null] :c (:d "e"))))
+    (let ((table (json-parse-string input)))
+      (should (hash-table-p table))
+      (should (eq (hash-table-test table) 'equal))
+      (should (equal (gethash "a" table) 1))
+      (should (hash-table-p (gethash "c" table)))))
+  (should-error (json-parse-string "{}" :object-type 'vector) :type 'error)
+  (should-error (json-parse-string "{}" :foo 1) :type 'error)
+  (should-error (json-parse-string "{}" :object-type) :type 'wrong-type-argument))
+
+(ert-deftest json-rust-null-and-false-objects ()
+  (should (equal (json-parse-string "[null, false]" :null-object nil :false-object 'no)
+                 [nil no]))
+  (should (equal (json-serialize [nil no t] :null-object nil :false-object 'no)
+                 "[null,false,true]"))
+  (should (equal (json-serialize '((a . :null) (b . :false)))
+                 "{\"a\":null,\"b\":false}"))
+  (should-error (json-serialize [] :object-type 'alist) :type 'error))
+
+(ert-deftest json-rust-serialize-plist ()
+  (should (equal (json-serialize '(:a 1 :b [2] c "d"))
+                 "{\"a\":1,\"b\":[2],\"c\":\"d\"}"))
+  (should (equal (json-serialize '(:a 1 :a 2)) "{\"a\":1}"))
+  (should-error (json-serialize '(:a 1 :b)) :type 'wrong-type-argument))
+
+(ert-deftest json-rust-numbers ()
+  (should (equal (json-parse-string "[1.5, -0.25, 1e3]") [1.5 -0.25 1000.0]))
+  (should (floatp (aref (json-parse-string "[100000000000000000000000]") 0)))
+  (should (equal (json-serialize [0.5 42]) "[0.5,42]"))
+  (should-error (json-serialize [0.0e+NaN]) :type 'wrong-type-argument)
+  (should-error (json-serialize [1.0e+INF]) :type 'wrong-type-argument))
+
+(ert-deftest json-rust-too-deep ()
+  (let ((deep (make-string 100000 ?\[)))
+    (should-error (json-parse-string (concat deep (make-string 100000 ?\])))
+                  :type 'json-error)))
+
+(ert-deftest json-rust-parse-buffer-across-gap ()
+  (with-temp-buffer
+    (insert "  [1, \"two\"]  {\"a\": 3}")
+    ;; Leave the gap in the middle of the first value.
+    (goto-char 8)
+    (insert "x")
+    (delete-char -1)
+    (goto-char (point-min))
+    (should (equal (json-parse-buffer) [1 "two"]))
+    (should (= (point) 13))
+    (should (equal (json-parse-buffer :object-type 'alist) '((a . 3))))
+    (should (eobp))
+    (should-error (json-parse-buffer) :type 'json-end-of-file)))
+
+(ert-deftest json-rust-parse-buffer-multibyte ()
+  (with-temp-buffer
+    (insert "[\"αβγ\"] tail")
+    (goto-char (point-min))
+    (should (equal (json-parse-buffer) ["αβγ"]))
+    (should (looking-at-p " tail"))))
+
+(ert-deftest json-rust-error-data ()
+  (let ((err (should-error (json-parse-string "[1,\n 2") :type 'json-end-of-file)))
+    (should (stringp (nth 1 err)))
+    (should (equal (nth 2 err) 2))))
+
+(ert-deftest json-rust-reader-chunks ()
+  (let ((reader (json-make-reader :object-type 'alist)))
+    (should (json-reader-p reader))
+    (should-not (json-reader-p [json-reader 1]))
+    (should-not (json-reader-feed reader "{\"a\": [1, "))
+    (should-not (json-reader-feed reader "\"}]\\\""))
+    (should (equal (json-reader-feed reader "\"]} [true]\n {\"b\"")
+                   '(((a . [1 "}]\""])) [t])))
+    (should (equal (json-reader-feed reader ": null}") '(((b . :null)))))
+    (should (json-reader-close reader))
+    (should-not (json-reader-close reader))
+    (should-error (json-reader-feed reader "[]"))))
+
+(ert-deftest json-rust-reader-scalars ()
+  (let ((reader (json-make-reader)))
+    (should (equal (json-reader-feed reader "\"str\" 12") '("str")))
+    ;; The number could still continue.
+    (should (equal (json-reader-feed reader "3 false") '(123)))
+    (should (equal (json-reader-feed reader " ") '(:false)))
+    (json-reader-close reader)))
+
+(ert-deftest json-rust-reader-split-utf-8 ()
+  (let ((reader (json-make-reader))
+        (bytes (encode-coding-string "[\"αβγ\"]" 'utf-8)))
+    (should-not (json-reader-feed reader (substring bytes 0 3)))
+    (should (equal (json-reader-feed reader (substring bytes 3)) '(["αβγ"])))
+    (should (equal (json-reader-feed reader "[\"δ\"]") '(["δ"])))
+    (json-reader-close reader)))
+
+(ert-deftest json-rust-reader-errors ()
+  (let ((reader (json-make-reader)))
+    (should-error (json-reader-feed reader "[1 2]") :type 'json-parse-error)
+    ;; The reader recovers after the bad input is dropped.
+    (should (equal (json-reader-feed reader "[3]") '([3])))
+    (should-error (json-reader-feed reader "]") :type 'json-parse-error)
+    (json-reader-close reader))
+  (should-error (json-make-reader :object-type 'vector))
+  (should-error (json-reader-feed 'reader "[]") :type 'wrong-type-argument))
+
 (provide 'json-tests)
+
 ;;; json-tests.el ends here
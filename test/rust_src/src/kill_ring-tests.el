@@ -0,0 +1,125 @@
+;;; kill_ring-tests.el --- tests for the kill ring
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(defmacro kill-ring-tests--with-ring (&rest body)
+  "Run BODY with an empty kill ring and default settings."
+  (declare (indent 0) (debug t))
+  `(let ((kill-ring nil)
+         (kill-ring-yank-pointer nil)
+         (kill-ring-max 60)
+         (kill-ring-entry-max-bytes nil)
+         (kill-do-not-save-duplicates nil))
+     ,@body))
+
+(ert-deftest kill-ring-push ()
+  (kill-ring-tests--with-ring
+    (should (equal (kill-ring-push "a") '("a")))
+    (should (equal (kill-ring-push "b") '("b" "a")))
+    (should (eq kill-ring-yank-pointer kill-ring))
+    (kill-ring-push "c" t)
+    (should (equal kill-ring '("c" "a")))
+    (kill-ring-push "c")
+    (should (equal kill-ring '("c" "c" "a")))
+    (should-error (kill-ring-push 'c) :type 'wrong-type-argument)))
+
+(ert-deftest kill-ring-push-duplicates ()
+  (kill-ring-tests--with-ring
+    (setq kill-do-not-save-duplicates t)
+    (kill-ring-push "a")
+    (kill-ring-push "b")
+    (kill-ring-push "b")
+    (should (equal kill-ring '("b" "a")))
+    (kill-ring-push (propertize "b" 'face 'bold))
+    (should (= (length kill-ring) 3))
+    (kill-ring-push "a")
+    (should (equal kill-ring '("a" "b" "b" "a")))
+    (setq kill-do-not-save-duplicates 'all)
+    (kill-ring-push "b")
+    (should (equal-including-properties
+             kill-ring (list "b" "a" (propertize "b" 'face 'bold) "a")))))
+
+(ert-deftest kill-ring-push-limits ()
+  (kill-ring-tests--with-ring
+    (setq kill-ring-max 2)
+    (kill-ring-push "a")
+    (kill-ring-push "b")
+    (kill-ring-push "c")
+    (should (equal kill-ring '("c" "b")))
+    (setq kill-ring-entry-max-bytes 3)
+    (kill-ring-push "abcd")
+    (kill-ring-push "é")
+    (should (equal kill-ring '("é" "c")))
+    (kill-ring-push "éé")
+    (should (equal kill-ring '("é" "c")))))
+
+(ert-deftest kill-ring-rotate ()
+  (kill-ring-tests--with-ring
+    (should-error (kill-ring-rotate 0))
+    (kill-ring-push "a")
+    (kill-ring-push "b")
+    (kill-ring-push "c")
+    (should (equal (kill-ring-rotate 0) "c"))
+    (should (equal (kill-ring-rotate 1) "b"))
+    (should (equal (kill-ring-rotate 1 t) "a"))
+    (should (equal (kill-ring-rotate 0) "b"))
+    (should (equal (kill-ring-rotate 2) "c"))
+    (should (equal (kill-ring-rotate -1) "a"))
+    (should (equal (car kill-ring-yank-pointer) "a"))))
+
+(ert-deftest kill-ring-new-and-current-kill ()
+  (kill-ring-tests--with-ring
+    (let ((interprogram-cut-function nil)
+          (interprogram-paste-function nil))
+      (kill-new "a")
+      (kill-new "b")
+      (kill-append "c" nil)
+      (should (equal kill-ring '("bc" "a")))
+      (should (equal (current-kill 1) "a"))
+      (should (equal (current-kill 1) "bc")))))
+
+(ert-deftest kill-ring-file-round-trip ()
+  (let ((file (make-temp-file "kill-ring")))
+    (unwind-protect
+        (kill-ring-tests--with-ring
+          (kill-ring-push (string-to-unibyte "\377\0"))
+          (kill-ring-push "")
+          (kill-ring-push (propertize "κ\nλ" 'face 'bold))
+          (should (= (kill-ring-write-file file) 3))
+          (should (= (logand (file-modes file) #o077) 0))
+          (let ((saved kill-ring))
+            (setq kill-ring nil)
+            (should (= (kill-ring-read-file file) 3))
+            (should (equal kill-ring saved))
+            (should (eq kill-ring-yank-pointer kill-ring))
+            (should-not (text-properties-at 0 (car kill-ring)))
+            (should (multibyte-string-p (car kill-ring)))
+            (should-not (multibyte-string-p (nth 2 kill-ring))))
+          (setq kill-ring-max 1)
+          (should (= (kill-ring-read-file file) 1))
+          (should (equal kill-ring '("κ\nλ")))
+          (with-temp-file file
+            (insert "not a kill ring"))
+          (should-error (kill-ring-read-file file)))
+      (delete-file file))))
+
+(provide 'kill_ring-tests)
+
+;;; kill_ring-tests.el ends here
@@ -0,0 +1,83 @@
+;;; line_index-tests.el --- tests for the buffer line index
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest line-number-at-pos-fast ()
+  (with-temp-buffer
+    (insert "one\ntwo\n\nfour")
+    (should (= (line-number-at-pos-fast) 4))
+    (should (= (line-number-at-pos-fast 1) 1))
+    (should (= (line-number-at-pos-fast 4) 1))
+    (should (= (line-number-at-pos-fast 5) 2))
+    (should (= (line-number-at-pos-fast 10) 4))
+    (dotimes (pos (point-max))
+      (should (= (line-number-at-pos-fast (1+ pos))
+                 (line-number-at-pos (1+ pos)))))
+    (goto-char 1)
+    (insert "zero\n")
+    (should (= (line-number-at-pos-fast (point-max)) 5))
+    (narrow-to-region 10 (point-max))
+    (should (= (line-number-at-pos-fast 10) 1))
+    (should (= (line-number-at-pos-fast 10 t) 3))
+    (should (= (line-number-at-pos-fast (point-max)) 3))
+    (should (= (line-number-at-pos-fast 1) 1))))
+
+(ert-deftest line-number-at-pos-fast-multibyte ()
+  (with-temp-buffer
+    (insert "αβ\nγ\nδε")
+    (should (= (line-number-at-pos-fast 3) 1))
+    (should (= (line-number-at-pos-fast 4) 2))
+    (should (= (line-number-at-pos-fast (point-max)) 3))))
+
+(ert-deftest position-of-line ()
+  (with-temp-buffer
+    (insert "one\ntwo\n\nfour\n")
+    (should (= (position-of-line 1) 1))
+    (should (= (position-of-line 2) 5))
+    (should (= (position-of-line 4) 10))
+    (should (= (position-of-line 5) 15))
+    (should-not (position-of-line 6))
+    (should-error (position-of-line 0) :type 'args-out-of-range)
+    (delete-region 1 5)
+    (should (= (position-of-line 2) 5))
+    (narrow-to-region 5 (point-max))
+    (should (= (position-of-line 1) 5))
+    (should (= (position-of-line 2) 6))
+    (should (= (position-of-line 2 t) 5))
+    (should-not (position-of-line 4))))
+
+(ert-deftest line-index-indirect-buffer ()
+  (with-temp-buffer
+    (insert "a\nb\nc")
+    (let ((indirect (make-indirect-buffer (current-buffer)
+                                          "line-index-indirect-buffer")))
+      (unwind-protect
+          (progn
+            (should (= (line-number-at-pos-fast (point-max)) 3))
+            (with-current-buffer indirect
+              (goto-char (point-max))
+              (insert "\nd"))
+            (should (= (line-number-at-pos-fast (point-max)) 4))
+            (should (= (position-of-line 4) 7)))
+        (kill-buffer indirect)))))
+
+(provide 'line_index-tests)
+
+;;; line_index-tests.el ends here
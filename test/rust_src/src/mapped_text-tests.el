@@ -0,0 +1,78 @@
+;;; mapped_text-tests.el --- tests for buffer text mapped from files
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest insert-file-contents-mapped ()
+  (let ((file (make-temp-file "mapped" nil nil "line 1\nline 2\n"))
+        (empty (make-temp-file "mapped")))
+    (unwind-protect
+        (with-temp-buffer
+          (set-buffer-multibyte nil)
+          (should-not (insert-file-contents-mapped empty))
+          (should (equal (insert-file-contents-mapped file) (list file 14)))
+          (should (equal (buffer-string) "line 1\nline 2\n"))
+          (should (equal buffer-file-name file))
+          (should-not (buffer-modified-p))
+          (should (= (point) (point-min)))
+          (should (search-forward "2" nil t))
+          ;; Changing the text copies it into memory.
+          (goto-char (point-max))
+          (insert "line 3\n")
+          (should (equal (buffer-string) "line 1\nline 2\nline 3\n"))
+          (should (buffer-modified-p))
+          (set-buffer-modified-p nil)
+          (should-error (insert-file-contents-mapped file)))
+      (delete-file file)
+      (delete-file empty))))
+
+(ert-deftest insert-file-contents-mapped-truncated ()
+  (skip-unless (eq system-type 'gnu/linux))
+  (let ((file (make-temp-file "mapped" nil nil (make-string 10000 ?a))))
+    (unwind-protect
+        (with-temp-buffer
+          (set-buffer-multibyte nil)
+          (should (insert-file-contents-mapped file))
+          (let ((write-region-atomic nil))
+            (write-region "" nil file nil 'silent))
+          ;; The pages the file lost read as zeros, instead of crashing.
+          (should (= (char-after 1) 0))
+          (should (= (char-after 10000) 0))
+          (goto-char (point-max))
+          (insert "b")
+          (should (= (char-after 10000) 0)))
+      (delete-file file))))
+
+(ert-deftest find-file-literally-mmap ()
+  (let ((file (make-temp-file "mapped" nil nil "\xff\xfe text")))
+    (unwind-protect
+        (save-window-excursion
+          (let ((buffer (find-file-literally-mmap file)))
+            (unwind-protect
+                (with-current-buffer buffer
+                  (should buffer-read-only)
+                  (should find-file-literally)
+                  (should-not enable-multibyte-characters)
+                  (should (equal (buffer-string) "\xff\xfe text")))
+              (kill-buffer buffer))))
+      (delete-file file))))
+
+(provide 'mapped_text-tests)
+
+;;; mapped_text-tests.el ends here
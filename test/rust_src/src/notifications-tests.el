@@ -0,0 +1,39 @@
+;;; notifications-tests.el --- tests for desktop notifications
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+
+(require 'ert)
+
+(ert-deftest notify-send-handle-event ()
+  (let (args)
+    (notify-send-handle-event
+     (list 'notification-event (lambda (&rest rest) (setq args rest))
+           7 "default"))
+    (should (equal args '(7 "default"))))
+  (should-error (notify-send-handle-event '(file-notify ignore 7 "default"))))
+
+(ert-deftest notify-send-invalid-options ()
+  (should-error (notify-send "Title" "Body" :urgency 'extreme))
+  (should-error (notify-send "Title" "Body" :actions '("default")))
+  (should-error (notify-send "Title" "Body" :timeout "never"))
+  (should-error (notify-send 'title "Body")))
+
+(provide 'notifications-tests)
+
+;;; notifications-tests.el ends here
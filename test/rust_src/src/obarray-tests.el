@@ -11,6 +11,32 @@
    (intern "foo" 123)
    :type 'wrong-type-argument))
 
+(ert-deftest obarray-tests-intern-soft ()
+  (should (eq (intern-soft 'car) 'car))
+  (should (eq (intern-soft "car") 'car))
+  (should-not (intern-soft (make-symbol "car")))
+  (let ((my-obarray (make-vector 20 0)))
+    (should-not (intern-soft 'car my-obarray))
+    (intern "car" my-obarray)
+    (should-not (intern-soft 'car my-obarray))
+    (should (intern-soft "car" my-obarray))))
+
+(ert-deftest obarray-tests-intern-bulk ()
+  (let ((my-obarray (make-vector 20 0)))
+    (let ((symbols (intern-bulk ["foo" "bar" "foo"] my-obarray)))
+      (should (vectorp symbols))
+      (should (= (length symbols) 3))
+      (should (eq (aref symbols 0) (aref symbols 2)))
+      (should (eq (aref symbols 1) (intern-soft "bar" my-obarray)))
+      (should-not (intern-soft "bar")))
+    (should (equal (intern-bulk [] my-obarray) []))
+    ;; Nothing is interned if an element isn't a string.
+    (should-error (intern-bulk ["baz" 1] my-obarray)
+                  :type 'wrong-type-argument)
+    (should-not (intern-soft "baz" my-obarray)))
+  (should (eq (aref (intern-bulk ["car"]) 0) 'car))
+  (should-error (intern-bulk '("foo")) :type 'wrong-type-argument))
+
 (ert-deftest obarray-tests-mapatoms ()
   ;; We should have `let' in our global obarray.
   (let ((found-let-sym nil))
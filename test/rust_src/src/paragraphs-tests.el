@@ -0,0 +1,71 @@
+;;; paragraphs-tests.el --- tests for scanning words, sentences and paragraphs
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest count-words-fast ()
+  (with-temp-buffer
+    (insert "One two, three.\n\nFour-five  six\n")
+    (should (= (count-words-fast (point-min) (point-max)) 6))
+    (should (= (count-words-fast 1 6) 2))
+    (should (= (count-words-fast 5 5) 0))
+    (should (= (count-words (point-min) (point-max)) 6))))
+
+(ert-deftest count-words-fast-ignores-word-boundary-functions ()
+  (with-temp-buffer
+    (insert "fooBar bazQux")
+    (subword-mode 1)
+    (should (= (count-words-fast (point-min) (point-max)) 2))))
+
+(ert-deftest forward-paragraph-moves-over-paragraphs ()
+  (with-temp-buffer
+    (insert "First para\nline two\n\nSecond para\n\n\nThird\n")
+    (goto-char (point-min))
+    (should (= (forward-paragraph) 0))
+    (should (= (point) 21))
+    (should (= (forward-paragraph 2) 0))
+    (should (= (point) (point-max)))
+    (should (= (forward-paragraph) 1))
+    (should (= (forward-paragraph -1) 0))
+    (should (= (point) 35))
+    (should (= (forward-paragraph -5) -3))
+    (should (bobp))))
+
+(ert-deftest forward-paragraph-with-fill-prefix ()
+  (with-temp-buffer
+    (insert ";; one\n;; two\n;;\n;; three\n")
+    (let ((fill-prefix ";; "))
+      (goto-char (point-min))
+      (forward-paragraph)
+      (should (= (point) 15)))))
+
+(ert-deftest forward-sentence-moves-over-sentences ()
+  (with-temp-buffer
+    (insert "One.  Two?  Three\n\nFour.")
+    (goto-char (point-min))
+    (let ((sentence-end-double-space t))
+      (should (= (forward-sentence) 5))
+      (should (= (forward-sentence) 11))
+      (should (= (forward-sentence) 18))
+      (forward-sentence -1)
+      (should (= (point) 13)))))
+
+(provide 'paragraphs-tests)
+
+;;; paragraphs-tests.el ends here
@@ -0,0 +1,65 @@
+;;; pcre-tests.el --- tests for Perl-compatible regexps
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest pcre-match-string ()
+  (let ((case-fold-search nil))
+    (should (= (pcre-match-string "(?<=\\$)\\d+" "cost: $42") 7))
+    (should (equal (match-data) '(7 9)))
+    (should (= (pcre-match-string "(a+?)(b)?" "xaab") 1))
+    (should (equal (match-data) '(1 2 1 2)))
+    (should (= (pcre-match-string "é(?!t)" "étét é") 5))
+    (should (= (pcre-match-string "(\\w)\\1" "abccd") 2))
+    (should (= (pcre-match-string "a" "banana" 2) 3))
+    (should (= (pcre-match-string "a" "banana" -1) 5))
+    (should-not (pcre-match-string "A" "banana"))
+    (should-error (pcre-match-string "a" "banana" 7) :type 'args-out-of-range)
+    (should-error (pcre-match-string "(" "a") :type 'invalid-regexp))
+  (let ((case-fold-search t))
+    (should (= (pcre-match-string "A" "banana") 1))))
+
+(ert-deftest pcre-search-forward ()
+  (with-temp-buffer
+    (insert "foo=1 bar=22 baz=333")
+    (goto-char (point-min))
+    (should (= (pcre-search-forward "(?<=bar=)\\d+") 13))
+    (should (equal (match-string 0) "22"))
+    (should (= (point) 13))
+    (should-not (pcre-search-forward "foo" nil t))
+    (should (= (point) 13))
+    (should-not (pcre-search-forward "baz" 16 'move))
+    (should (= (point) 16))
+    (should-error (pcre-search-forward "qux") :type 'search-failed)
+    (should-error (pcre-search-forward "foo" 2))))
+
+(ert-deftest pcre-replace-regexp ()
+  (with-temp-buffer
+    (insert "one two three")
+    (goto-char (point-min))
+    (should (= (pcre-replace-regexp "(\\w+)(?= )" "<\\1>") 2))
+    (should (equal (buffer-string) "<one> <two> three"))
+    (should (= (point) 12))
+    (should (= (pcre-replace-regexp "t" "T" 1 8) 0))
+    (should (= (pcre-replace-regexp "e" "E" 1 (point-max)) 3))
+    (should (equal (buffer-string) "<onE> <two> thrEE"))
+    (goto-char (point-min))
+    (should (= (pcre-replace-regexp "x*" "-") 18))))
+
+(provide 'pcre-tests)
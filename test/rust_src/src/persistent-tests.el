@@ -0,0 +1,75 @@
+;;; persistent-tests.el --- tests for persistent.rs
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+
+(require 'ert)
+
+(ert-deftest pvector-is-persistent ()
+  (let* ((v1 (pvector 'a 'b 'c))
+         (v2 (pvector-set v1 1 'x))
+         (v3 (pvector-push v2 'd)))
+    (should (pvector-p v1))
+    (should-not (pvector-p [a b c]))
+    (should (equal (pvector-to-list v1) '(a b c)))
+    (should (equal (pvector-to-list v2) '(a x c)))
+    (should (equal (pvector-to-list v3) '(a x c d)))
+    (should (= (pvector-length v3) 4))
+    (should (eq (pvector-ref v3 3) 'd))))
+
+(ert-deftest pvector-slice-and-append ()
+  (let ((v (apply #'pvector (number-sequence 0 9))))
+    (should (equal (pvector-to-list (pvector-slice v 2 5)) '(2 3 4)))
+    (should (equal (pvector-to-list (pvector-slice v 8)) '(8 9)))
+    (should (equal (pvector-to-list (pvector-slice v 10)) nil))
+    (should (equal (pvector-to-list
+                    (pvector-append (pvector-slice v 0 2) (pvector-slice v 8)))
+                   '(0 1 8 9)))
+    (should-error (pvector-slice v 5 2) :type 'args-out-of-range)
+    (should-error (pvector-ref v 10) :type 'args-out-of-range)
+    (should-error (pvector-ref [1 2] 0) :type 'wrong-type-argument)))
+
+(ert-deftest pvector-survives-gc ()
+  (let ((v (pvector (copy-sequence "kept") (list 1 2))))
+    (garbage-collect)
+    (should (equal (pvector-ref v 0) "kept"))
+    (should (equal (pvector-ref v 1) '(1 2)))))
+
+(ert-deftest pmap-is-persistent ()
+  (let* ((m1 (pmap "a" 1 'b 2))
+         (m2 (pmap-put "c" 3 m1))
+         (m3 (pmap-remove "a" m2)))
+    (should (pmap-p m1))
+    (should-not (pmap-p (make-hash-table)))
+    (should (= (pmap-count m1) 2))
+    (should (= (pmap-count m2) 3))
+    (should (= (pmap-count m3) 2))
+    (should (= (pmap-get (copy-sequence "a") m1) 1))
+    (should-not (pmap-get "c" m1))
+    (should (eq (pmap-get "a" m3 'none) 'none))
+    (should-error (pmap 'a) :type 'error)))
+
+(ert-deftest pmap-maphash ()
+  (let ((map (pmap 'a 1 'b 2 'c 3))
+        (sum 0))
+    (maphash (lambda (_key value) (setq sum (+ sum value))) map)
+    (should (= sum 6))))
+
+(provide 'persistent-tests)
+
+;;; persistent-tests.el ends here
@@ -0,0 +1,80 @@
+;;; pipeline-tests.el --- tests for pipelines of subprocesses
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(defun pipeline-tests--run (&rest args)
+  "Start a pipeline with ARGS, and return its output once it exits."
+  (let* ((buffer (generate-new-buffer " *pipeline-test*"))
+         (processes (apply #'make-process-pipeline :name "pipeline-test"
+                           :buffer buffer args)))
+    (unwind-protect
+        (progn
+          (with-timeout (10 (error "Pipeline didn't exit"))
+            (while (seq-some #'process-live-p processes)
+              (accept-process-output nil 0.05)))
+          (with-current-buffer buffer
+            (buffer-string)))
+      (mapc #'delete-process processes)
+      (kill-buffer buffer))))
+
+(ert-deftest pipeline-output ()
+  (skip-unless (and (executable-find "printf") (executable-find "sort")
+                    (executable-find "tr")))
+  (should (equal (pipeline-tests--run
+                  :commands '(("printf" "b\\na\\nc\\n") ("sort") ("tr" "a-z" "A-Z")))
+                 "A\nB\nC\n")))
+
+(ert-deftest pipeline-single-command ()
+  (skip-unless (executable-find "echo"))
+  (should (equal (pipeline-tests--run :commands '(("echo" "one")))
+                 "one\n")))
+
+(ert-deftest pipeline-input ()
+  (skip-unless (and (executable-find "cat") (executable-find "wc")))
+  (let* ((buffer (generate-new-buffer " *pipeline-test*"))
+         (processes (make-process-pipeline :name "pipeline-test"
+                                           :buffer buffer
+                                           :commands '(("cat") ("wc" "-l")))))
+    (unwind-protect
+        (progn
+          (should (= (length processes) 2))
+          (process-send-string (car processes) "1\n2\n3\n")
+          (process-send-eof (car processes))
+          (with-timeout (10 (error "Pipeline didn't exit"))
+            (while (seq-some #'process-live-p processes)
+              (accept-process-output nil 0.05)))
+          (should (equal (string-trim (with-current-buffer buffer
+                                        (buffer-string)))
+                         "3")))
+      (mapc #'delete-process processes)
+      (kill-buffer buffer))))
+
+(ert-deftest pipeline-error-output ()
+  (skip-unless (and (executable-find "sh") (executable-find "cat")))
+  (should (equal (pipeline-tests--run
+                  :commands '(("sh" "-c" "echo err >&2") ("cat")))
+                 "err\n")))
+
+(ert-deftest pipeline-no-commands ()
+  (should-error (make-process-pipeline :name "pipeline-test" :commands nil)))
+
+(provide 'pipeline-tests)
+
+;;; pipeline-tests.el ends here
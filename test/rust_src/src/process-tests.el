@@ -26,3 +26,77 @@
     (delete-process network-proc)
     (delete-process pipe-proc)
     (delete-process buffer-proc)))
+
+(defun process-tests--chunks (&rest args)
+  "Run `printf' printing 100000 bytes with ARGS to `make-process'.
+Return the lengths of the strings its filter got."
+  (let* ((chunks nil)
+         (process (apply #'make-process :name "test-chunks"
+                         :command '("sh" "-c" "printf '%0100000d' 0")
+                         :connection-type 'pipe
+                         :filter (lambda (_process output)
+                                   (push (length output) chunks))
+                         :sentinel #'ignore
+                         args)))
+    (with-timeout (10 (error "Process didn't exit"))
+      (while (process-live-p process)
+        (accept-process-output process 0.05)))
+    chunks))
+
+(ert-deftest process-tests--read-chunk-size ()
+  (skip-unless (executable-find "sh"))
+  (let ((chunks (process-tests--chunks)))
+    (should (= (apply #'+ chunks) 100000))
+    (should (<= (apply #'max chunks) 4096)))
+  (let ((chunks (process-tests--chunks :read-chunk-size 65536)))
+    (should (= (apply #'+ chunks) 100000))
+    (should (<= (apply #'max chunks) 65536))
+    (should (< (length chunks) (/ 100000 4096)))))
+
+(ert-deftest process-tests--set-process-read-chunk-size ()
+  (let ((process (make-pipe-process :name "test-pipe")))
+    (unwind-protect
+        (progn
+          (should (= (process-read-chunk-size process) 4096))
+          (set-process-read-chunk-size process 100000)
+          (should (= (process-read-chunk-size process) 100000))
+          (set-process-read-chunk-size process nil)
+          (should (= (process-read-chunk-size process) 4096))
+          (should-error (set-process-read-chunk-size process 0)
+                        :type 'args-out-of-range))
+      (delete-process process))))
+
+(ert-deftest process-tests--pause-output ()
+  (let ((process (make-pipe-process :name "test-pipe")))
+    (unwind-protect
+        (progn
+          (should-not (process-output-paused-p process))
+          (process-pause-output process)
+          (should (process-output-paused-p process))
+          (process-resume-output process)
+          (should-not (process-output-paused-p process)))
+      (delete-process process))))
+
+(ert-deftest process-tests--max-buffered-output ()
+  (skip-unless (executable-find "yes"))
+  (let* ((buffer (generate-new-buffer " *test-max-buffered-output*"))
+         (process (make-process :name "test-max-buffered-output"
+                                :command '("yes")
+                                :buffer buffer
+                                :connection-type 'pipe
+                                :max-buffered-output 10000
+                                :sentinel #'ignore)))
+    (unwind-protect
+        (progn
+          (should (= (process-max-buffered-output process) 10000))
+          (accept-process-output process 0.5)
+          (should (process-live-p process))
+          (should (process-output-paused-p process))
+          (should (< (buffer-size buffer) (+ 10000 4096)))
+          (with-current-buffer buffer
+            (erase-buffer))
+          (accept-process-output process 0.5)
+          (should (> (buffer-size buffer) 0))
+          (should (< (buffer-size buffer) (+ 10000 4096))))
+      (delete-process process)
+      (kill-buffer buffer))))
@@ -0,0 +1,102 @@
+;;; pty-tests.el --- tests for ptys of subprocesses
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(defmacro pty-tests--with-process (process command &rest body)
+  "Run COMMAND on a pty as PROCESS while evaluating BODY."
+  (declare (indent 2))
+  `(let ((,process (make-process :name "pty-test" :command ,command
+                                 :buffer (generate-new-buffer " *pty-test*")
+                                 :connection-type 'pty
+                                 :sentinel #'ignore)))
+     (unwind-protect
+         (progn ,@body)
+       (let ((buffer (process-buffer ,process)))
+         (delete-process ,process)
+         (kill-buffer buffer)))))
+
+(defun pty-tests--wait (process)
+  "Wait for PROCESS to exit."
+  (with-timeout (10 (error "Process didn't exit"))
+    (while (process-live-p process)
+      (accept-process-output process 0.05))))
+
+(ert-deftest pty-window-size ()
+  (skip-unless (executable-find "sh"))
+  (pty-tests--with-process process '("sh" "-c" "read line; stty size")
+    (should (set-process-window-size process 37 101))
+    (should (equal (process-window-size process) '(37 . 101)))
+    (process-send-string process "\n")
+    (pty-tests--wait process)
+    (should (string-match-p "^37 101\r?$"
+                            (with-current-buffer (process-buffer process)
+                              (buffer-string))))))
+
+(ert-deftest pty-window-size-range ()
+  (skip-unless (executable-find "sh"))
+  (pty-tests--with-process process '("sh" "-c" "sleep 10")
+    (should-error (set-process-window-size process -1 80)
+                  :type 'args-out-of-range)
+    (should-error (set-process-window-size process 24 65536)
+                  :type 'args-out-of-range)))
+
+(ert-deftest pty-pipe-process ()
+  (let ((process (make-pipe-process :name "pty-test-pipe")))
+    (unwind-protect
+        (progn
+          (should-not (set-process-window-size process 24 80))
+          (should-not (process-window-size process))
+          (should-not (set-process-flow-control process nil))
+          (should-not (process-flow-control process)))
+      (delete-process process))))
+
+(ert-deftest pty-flow-control ()
+  (skip-unless (executable-find "sh"))
+  (pty-tests--with-process process '("sh" "-c" "sleep 10")
+    (should (set-process-flow-control process nil))
+    (should-not (process-flow-control process))
+    (should (set-process-flow-control process t))
+    (should (process-flow-control process))))
+
+(ert-deftest pty-multibyte-chunks ()
+  "Filters get whole characters, however the output is split."
+  (skip-unless (executable-find "sh"))
+  (let* ((text (make-string 5000 ?é))
+         (chunks nil)
+         (process (make-process :name "pty-test"
+                                :command (list "sh" "-c"
+                                               (concat "printf %s " text))
+                                :connection-type 'pty
+                                :coding 'utf-8-unix
+                                :filter (lambda (_process output)
+                                          (push output chunks))
+                                :sentinel #'ignore)))
+    (unwind-protect
+        (pty-tests--wait process)
+      (delete-process process))
+    (should (> (length chunks) 1))
+    (dolist (chunk chunks)
+      (should-not (seq-some (lambda (char) (eq (char-charset char) 'eight-bit))
+                            chunk)))
+    (should (equal (apply #'concat (nreverse chunks)) text))))
+
+(provide 'pty-tests)
+
+;;; pty-tests.el ends here
@@ -0,0 +1,80 @@
+;;; rect-tests.el --- tests for rectangle operations
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(require 'rect)
+
+(ert-deftest rect-extract-rectangle ()
+  (with-temp-buffer
+    (insert "abcdef\nab\nabcdef")
+    (should (equal (extract-rectangle-columns 2 (point-max) 1 4)
+                   '("bcd" "b  " "bcd")))
+    ;; The columns may be given in either order.
+    (should (equal (extract-rectangle-columns 2 (point-max) 4 1)
+                   '("bcd" "b  " "bcd")))
+    (should (equal (extract-rectangle 2 (point-max))
+                   '("bcdef" "b    " "bcdef")))
+    (should (= (point) (point-max))))
+  (with-temp-buffer
+    (insert "x\ty")
+    (should (equal (extract-rectangle-columns 1 (point-max) 0 10)
+                   '("x       y ")))))
+
+(ert-deftest rect-extract-rectangle-properties ()
+  (with-temp-buffer
+    (insert (propertize "abc" 'face 'bold) "\ndef")
+    (let ((lines (extract-rectangle-columns 1 (point-max) 1 2)))
+      (should (equal lines '("b" "e")))
+      (should (eq (get-text-property 0 'face (car lines)) 'bold)))))
+
+(ert-deftest rect-string-rectangle ()
+  (with-temp-buffer
+    (insert "abcdef\nabcdef\nab")
+    (string-rectangle 2 (point-max) "XY")
+    (should (equal (buffer-string) "aXYcdef\naXYcdef\naXY"))
+    (should (= (point) (point-max))))
+  (with-temp-buffer
+    (insert "abcdef\nab\nabcdef")
+    (string-insert-rectangle 4 (point-max) "|")
+    (should (equal (buffer-string) "abc|def\nab |\nabc|def"))))
+
+(ert-deftest rect-insert-rectangle ()
+  (with-temp-buffer
+    (insert "ab\ncd")
+    (goto-char 2)
+    (insert-rectangle '("1" "2" "3"))
+    (should (equal (buffer-string) "a1b\nc2d\n 3"))
+    (should (= (point) (point-max)))
+    (should (= (mark) 2))))
+
+(ert-deftest rect-replace-rectangle-regexp ()
+  (with-temp-buffer
+    (insert "aaaa\naaaa\nbbbb\naaaa")
+    (should (= (replace-rectangle-regexp 2 19 "a" "x") 6))
+    (should (equal (buffer-string) "axxa\naxxa\nbbbb\naxxa"))
+    (should (= (replace-rectangle-regexp 2 19 "\\(x+\\)" "<\\1>")
+               3))
+    (should (equal (buffer-string) "a<xx>a\na<xx>a\nbbbb\na<xx>a"))
+    (should (= (replace-rectangle-regexp 1 (point-max) "^" "-") 4))
+    (should (equal (buffer-string) "-a<xx>a\n-a<xx>a\n-bbbb\n-a<xx>a"))))
+
+(provide 'rect-tests)
+
+;;; rect-tests.el ends here
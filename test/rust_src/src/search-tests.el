@@ -0,0 +1,217 @@
+;;; search-tests.el --- tests for search.rs
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(defun search-tests--both-engines (regexp &optional bound noerror)
+  "Return the results of searching for REGEXP with both engines.
+Each result is a list of the value of `re-search-forward', point and
+the match data."
+  (mapcar (lambda (rust)
+            (save-excursion
+              (let ((re-search-rust-engine rust)
+                    (value (re-search-forward regexp bound noerror)))
+                (list value (point) (match-data t)))))
+          '(nil t)))
+
+(ert-deftest re-search-rust-engine-agrees ()
+  (with-temp-buffer
+    (insert "foo bar\nbaz qux 42 αβγ\n(a) [b] {c}\n")
+    (goto-char 2)
+    (dolist (regexp '("ba\\(r\\|z\\)" "^baz" "o*$" "[0-9]+" "[[:alpha:]]+γ"
+                      "\\(a\\)\\|\\(b\\)" "x?\\(?:q.\\)+" "z\\{1,2\\} q"
+                      "(a) \\[b\\] {c}" "\\`f" "[^a-z ]+"))
+      (let ((results (search-tests--both-engines regexp nil t)))
+        (should (equal (car results) (cadr results)))))
+    (let* ((case-fold-search t)
+           (results (search-tests--both-engines "BAZ Q" nil t)))
+      (should (car (car results)))
+      (should (equal (car results) (cadr results))))))
+
+(ert-deftest re-search-rust-engine-bound-and-errors ()
+  (with-temp-buffer
+    (insert "one two three")
+    (goto-char (point-min))
+    (let ((re-search-rust-engine t))
+      (should (equal (search-tests--both-engines "t[a-z]+" 8 t)
+                     '((8 8 (5 8)) (8 8 (5 8)))))
+      (should-not (re-search-forward "three" 8 t))
+      (should (= (point) 1))
+      (should-not (re-search-forward "three" 8 'move))
+      (should (= (point) 8))
+      (should-error (re-search-forward "four") :type 'search-failed)
+      (should-error (re-search-forward "one" 2)))))
+
+(ert-deftest re-search-rust-engine-fallback ()
+  "Constructs the Rust engine lacks are still matched."
+  (with-temp-buffer
+    (insert "abab foo-bar foo_bar")
+    (goto-char (point-min))
+    (let ((re-search-rust-engine t))
+      (should (= (re-search-forward "\\(ab\\)\\1") 5))
+      (should (= (re-search-forward "\\_<foo_bar\\_>") 21))
+      (should (= (re-search-backward "\\bfoo") 14))
+      (let ((case-fold-search t))
+        (should (= (re-search-forward "FOO-BAR" nil t -1) 6))))))
+
+(ert-deftest re-search-rust-engine-match-data ()
+  "The match data are markers into the buffer, as the C engine sets them."
+  (with-temp-buffer
+    (insert "foo bar")
+    (goto-char (point-min))
+    (let ((re-search-rust-engine t))
+      (re-search-forward "b\\(a\\)r")
+      (should (eq (marker-buffer (car (match-data))) (current-buffer)))
+      (should (equal (match-data t) '(5 8 6 7))))))
+
+(ert-deftest re-search-rust-engine-raw-bytes ()
+  "Raw bytes, which the Rust engine doesn't see, are still matched."
+  (with-temp-buffer
+    (insert "x a" (unibyte-string #xff) "b")
+    (goto-char (point-min))
+    (let ((results (search-tests--both-engines "a.b" nil t)))
+      (should (equal (car results) '(6 6 (3 6))))
+      (should (equal (car results) (cadr results))))))
+
+(ert-deftest search-forward-any ()
+  (with-temp-buffer
+    (insert "error: disk full; warning: low memory; ERROR again")
+    (goto-char (point-min))
+    (let ((case-fold-search nil)
+          (patterns '("warn" "warning" "error")))
+      (should (equal (search-forward-any patterns) '("error" 1 6)))
+      (should (equal (match-data t) '(1 6)))
+      (should (= (point) 6))
+      ;; The longest pattern wins at the same position.
+      (should (eq (car (search-forward-any patterns)) (nth 1 patterns)))
+      (should (= (point) 26))
+      (should-not (search-forward-any patterns nil t))
+      (should (= (point) 26))
+      (should-error (search-forward-any patterns) :type 'search-failed))
+    (goto-char (point-min))
+    (let ((case-fold-search t))
+      (should (equal (search-forward-any ["memory" "ERROR"] 5 'move) nil))
+      (should (= (point) 5))
+      (should (equal (search-forward-any ["memory" "ERROR"]) '("memory" 32 38)))
+      (should (equal (search-forward-any ["memory" "ERROR"]) '("ERROR" 40 45))))
+    (erase-buffer)
+    (insert "日本語のテキスト")
+    (goto-char (point-min))
+    (should (equal (search-forward-any '("テキスト" "本")) '("本" 2 3)))
+    (should-error (search-forward-any '(foo)) :type 'wrong-type-argument)))
+
+(defun search-tests--all-matches (regexp start end)
+  "Return the matches for REGEXP from START to END, found one by one."
+  (save-excursion
+    (goto-char start)
+    (let (matches last-end done)
+      (while (and (not done) (re-search-forward regexp end t))
+        (let ((beg (match-beginning 0)))
+          (unless (and (= beg (point)) (eql beg last-end))
+            (push (cons beg (point)) matches))
+          (setq last-end (point))
+          (when (= beg (point))
+            (if (< (point) end) (forward-char) (setq done t)))))
+      (nreverse matches))))
+
+(ert-deftest re-seq-all-matches ()
+  (with-temp-buffer
+    (insert "aab ab  béab aaab\nb αβ ab")
+    (goto-char 3)
+    (dolist (regexp '("a+b" "a*b?" "b\\|" " *" "$" "[[:alpha:]]+"
+                      "\\(a\\)\\1" "\\_<ab\\_>"))
+      (should (equal (re-seq-all-matches regexp)
+                     (search-tests--all-matches regexp (point-min) (point-max))))
+      (should (equal (re-seq-all-matches regexp 4 20)
+                     (search-tests--all-matches regexp 4 20))))
+    (should (equal (re-seq-all-matches "a+b" 12 1)
+                   '((1 . 4) (5 . 7))))
+    (should (equal (re-seq-all-matches "\\(a\\)\\1") '((1 . 3) (14 . 16))))
+    (should (= (point) 3))
+    (should-error (re-seq-all-matches "a" 0) :type 'args-out-of-range)
+    ;; Raw bytes are matched by the Emacs engine.
+    (goto-char (point-max))
+    (insert "a" (unibyte-string #xff) "b")
+    (should (equal (re-seq-all-matches "a.b" (- (point) 3))
+                   (list (cons (- (point) 3) (point)))))))
+
+(defun search-tests--count-matches (regexp start end)
+  "Count the matches for REGEXP from START to END as `how-many' did."
+  (save-excursion
+    (goto-char start)
+    (let ((count 0) opoint)
+      (while (and (< (point) end)
+                  (progn (setq opoint (point))
+                         (re-search-forward regexp end t)))
+        (if (= opoint (point))
+            (forward-char 1)
+          (setq count (1+ count))))
+      count)))
+
+(ert-deftest re-count-matches ()
+  (with-temp-buffer
+    (insert "aab ab  béab aaab\nb αβ ab")
+    (goto-char 3)
+    (set-match-data '(1 2))
+    (dolist (regexp '("a+b" "a*b?" "b\\|" " *" "^" "$" "[[:alpha:]]+"
+                      "\\(a\\)\\1" "\\_<ab\\_>"))
+      (should (= (re-count-matches regexp (point-min))
+                 (search-tests--count-matches regexp (point-min) (point-max))))
+      (should (= (re-count-matches regexp 4 20)
+                 (search-tests--count-matches regexp 4 20))))
+    (should (= (re-count-matches "a+b") 4))
+    (should (= (re-count-matches "a+b" 12 1) 2))
+    (should (= (point) 3))
+    (should (equal (match-data) '(1 2)))
+    (should-error (re-count-matches "a" 0) :type 'args-out-of-range)
+    ;; Raw bytes are matched by the Emacs engine.
+    (goto-char (point-max))
+    (insert "a" (unibyte-string #xff) "b")
+    (should (= (re-count-matches "a.b" (- (point) 3)) 1))))
+
+(ert-deftest approx-search-forward ()
+  (with-temp-buffer
+    (insert "We recieved the pacakge; the package was receieved.")
+    (goto-char (point-min))
+    (let ((case-fold-search nil))
+      (should (= (approx-search-forward "received" 2) 12))
+      (should (equal (match-data t) '(4 12)))
+      (should (= (approx-search-forward "package" 2) 24))
+      (should (equal (match-data t) '(17 24)))
+      (should (= (approx-search-forward "package" 0) 37))
+      (should-not (approx-search-forward "parcel" 1 nil t))
+      (should (= (point) 37))
+      (should-not (approx-search-forward "received" 0 nil 'move))
+      (should (= (point) 52))
+      (should-error (approx-search-forward "x" 0) :type 'search-failed))
+    (goto-char (point-min))
+    (let ((case-fold-search t))
+      (should (= (approx-search-forward "WE" 0) 3)))
+    (goto-char (point-min))
+    (should-not (approx-search-forward "pakage" 1 25 t))
+    (should (= (approx-search-forward "pakage" 2 25) 24))
+    (should-error (approx-search-forward "a" -1) :type 'args-out-of-range)
+    (should-error (approx-search-forward (make-string 65 ?a) 1))
+    (erase-buffer)
+    (insert "naïve café")
+    (goto-char (point-min))
+    (should (= (approx-search-forward "cafe" 1) 11))
+    (should (= (match-beginning 0) 7))))
+
+(provide 'search-tests)
@@ -0,0 +1,50 @@
+;;; selection-tests.el --- tests for the clipboard functions
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+
+(require 'ert)
+
+(defun selection-tests--clipboard-p ()
+  (and (or (getenv "DISPLAY") (memq system-type '(darwin windows-nt)))
+       (ignore-errors (clipboard-get 'text) t)))
+
+(defun selection-tests--x11-p ()
+  (and (not (memq system-type '(darwin windows-nt)))
+       (selection-tests--clipboard-p)))
+
+(ert-deftest clipboard-set-get-text ()
+  (skip-unless (selection-tests--clipboard-p))
+  (should (equal (clipboard-set 'text "héllo") "héllo"))
+  (should (equal (clipboard-get 'text) "héllo")))
+
+(ert-deftest clipboard-set-get-html ()
+  (skip-unless (selection-tests--x11-p))
+  (clipboard-set 'html "<b>bold</b> text")
+  (should (equal (clipboard-get 'html) "<b>bold</b> text")))
+
+(ert-deftest clipboard-invalid-arguments ()
+  (should-error (clipboard-get 'sound))
+  (should-error (clipboard-get 'text 'TERTIARY))
+  (should-error (clipboard-set 'text 42))
+  (should-error (clipboard-set 'image "not a PNG image"))
+  (should-error (clipboard-set 'image "\x89PNG héllo")))
+
+(provide 'selection-tests)
+
+;;; selection-tests.el ends here
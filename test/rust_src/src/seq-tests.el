@@ -0,0 +1,65 @@
+;;; seq-tests.el --- tests for seq.rs
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+
+(require 'ert)
+(require 'cl-lib)
+(require 'seq)
+
+(ert-deftest seq-uniq-native ()
+  (should (equal (seq--uniq '(1 2 1 "a" 3 "a" 2)) '(1 2 "a" 3)))
+  (should (equal (seq--uniq (list "a" (copy-sequence "a")) 'eq)
+                 '("a" "a")))
+  (should (equal (seq--uniq [b a b c a] 'eq) '(b a c)))
+  (should (equal (seq--uniq '(1 1.0 1) 'eql) '(1 1.0)))
+  (should (equal (seq-uniq '(x y x)) '(x y)))
+  (let ((circular (list 1 2)))
+    (setcdr (cdr circular) circular)
+    (should-error (seq--uniq circular) :type 'circular-list))
+  (should-error (seq--uniq "abc") :type 'wrong-type-argument))
+
+(ert-deftest seq-difference-native ()
+  (should (equal (seq--difference '(1 2 3 2 4) [2 5]) '(1 3 4)))
+  (should (equal (seq--difference '("a" "b") '("a")) '("b")))
+  (should (equal (seq-difference [1 2 3] '(3)) '(1 2))))
+
+(ert-deftest seq-group-by-native ()
+  (let ((generic (seq-reduce
+                  (lambda (acc elt)
+                    (let* ((key (cl-oddp elt))
+                           (cell (assoc key acc)))
+                      (if cell
+                          (setcdr cell (push elt (cdr cell)))
+                        (push (list key elt) acc))
+                      acc))
+                  (seq-reverse '(1 2 3 4 6 5))
+                  nil)))
+    (should (equal (seq--group-by #'cl-oddp '(1 2 3 4 6 5)) generic))
+    (should (equal (seq-group-by #'cl-oddp [1 2 3 4 6 5]) generic))))
+
+(ert-deftest seq-sort-by-native ()
+  (should (equal (seq--sort-by #'car #'< '((3 . a) (1 . b) (2 . c) (1 . d)))
+                 '((1 . b) (1 . d) (2 . c) (3 . a))))
+  (should (equal (seq-sort-by #'length #'> ["a" "ccc" "bb"])
+                 ["ccc" "bb" "a"]))
+  (should (equal (seq--sort-by #'identity #'< nil) nil)))
+
+(provide 'seq-tests)
+
+;;; seq-tests.el ends here
@@ -0,0 +1,166 @@
+;;; sockets-tests.el --- tests for sockets of network processes
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(defmacro sockets-tests--with-server (server &rest body)
+  "Run BODY with SERVER bound to a UDP server on the loopback interface."
+  (declare (indent 1))
+  `(let ((,server (make-network-process :name "sockets-test-server"
+                                        :server t :type 'datagram
+                                        :host "127.0.0.1" :service t
+                                        :family 'ipv4 :ancillary t)))
+     (unwind-protect
+         (progn ,@body)
+       (delete-process ,server))))
+
+(defun sockets-tests--send (server &rest options)
+  "Send a datagram to SERVER from a client made with OPTIONS.
+Return the datagram once SERVER has received it."
+  (let* ((received nil)
+         (client (apply #'make-network-process
+                        :name "sockets-test-client" :type 'datagram
+                        :host "127.0.0.1"
+                        :service (process-contact server :service)
+                        :family 'ipv4 options)))
+    (set-process-filter server (lambda (_proc string) (setq received string)))
+    (unwind-protect
+        (progn
+          (process-send-string client "ping")
+          (with-timeout (5 (error "Datagram not received"))
+            (while (not received)
+              (accept-process-output server 0.05)))
+          received)
+      (delete-process client))))
+
+(ert-deftest sockets-ancillary-destination ()
+  (sockets-tests--with-server server
+    (should-not (process-datagram-ancillary server))
+    (should (equal (sockets-tests--send server) "ping"))
+    (let ((ancillary (process-datagram-ancillary server)))
+      (should (equal (plist-get ancillary :destination) "127.0.0.1"))
+      (should (plist-get ancillary :interface))
+      (should (integerp (plist-get ancillary :ttl))))))
+
+(ert-deftest sockets-tos ()
+  (sockets-tests--with-server server
+    (sockets-tests--send server :tos 16)
+    (should (eq (plist-get (process-datagram-ancillary server) :tos) 16))))
+
+(ert-deftest sockets-client-options ()
+  ;; Datagram clients get their options, although they don't connect.
+  (sockets-tests--with-server server
+    (should (equal (sockets-tests--send server :broadcast t :multicast-ttl 4
+                                        :multicast-loop nil)
+                   "ping"))))
+
+(ert-deftest sockets-bad-values ()
+  (sockets-tests--with-server server
+    (should-error (set-network-process-option server :tos 256))
+    (should-error (set-network-process-option server :multicast-join
+                                              "not an address"))
+    (should-error (set-network-process-option server :multicast-ttl "4"))
+    (should (set-network-process-option server :multicast-ttl 2))
+    (should (set-network-process-option server :ancillary nil))
+    (should-not (process-datagram-ancillary server))
+    (should-not (set-network-process-option server :no-such-option t t))))
+
+(ert-deftest sockets-multicast-membership ()
+  (sockets-tests--with-server server
+    (skip-unless (ignore-errors
+                   (set-network-process-option server :multicast-join
+                                               "224.0.0.251")))
+    (should (set-network-process-option server :multicast-leave
+                                        "224.0.0.251"))
+    ;; Leaving a group twice fails.
+    (should-error (set-network-process-option server :multicast-leave
+                                              "224.0.0.251")
+                  :type 'file-error)))
+
+;;; Local sockets
+
+(defvar sockets-tests--received nil
+  "What the server of `sockets-tests--with-local-connection' received.")
+
+(defmacro sockets-tests--with-local-connection (vars &rest body)
+  "Run BODY with VARS, (SERVER CLIENT ACCEPTED), bound to processes.
+SERVER listens on a local socket, CLIENT is connected to it, and
+ACCEPTED is the server's process for CLIENT.  The strings ACCEPTED
+receives are collected in `sockets-tests--received'."
+  (declare (indent 1))
+  (let ((file (make-symbol "file")))
+    `(let* ((,file (make-temp-name
+                    (expand-file-name "sockets-test" temporary-file-directory)))
+            (sockets-tests--received "")
+            (,(car vars) nil)
+            (,(nth 1 vars) nil)
+            (,(nth 2 vars) nil))
+       (unwind-protect
+           (progn
+             (setq ,(car vars)
+                   (make-network-process
+                    :name "sockets-test-server" :server t :family 'local
+                    :service ,file
+                    :filter (lambda (_proc string)
+                              (setq sockets-tests--received
+                                    (concat sockets-tests--received string)))
+                    :log (lambda (_server client _message)
+                           (setq ,(nth 2 vars) client))))
+             (setq ,(nth 1 vars)
+                   (make-network-process :name "sockets-test-client"
+                                         :family 'local :service ,file))
+             (with-timeout (5 (error "Connection not accepted"))
+               (while (not ,(nth 2 vars))
+                 (accept-process-output nil 0.05)))
+             ,@body)
+         (dolist (process (list ,@vars))
+           (when process
+             (delete-process process)))
+         (ignore-errors (delete-file ,file))))))
+
+(ert-deftest sockets-peer-credentials ()
+  (sockets-tests--with-local-connection (server client accepted)
+    (let ((credentials (list (emacs-pid) (user-uid) (group-gid))))
+      (dolist (process (list client accepted))
+        (let ((peer (process-contact process :peer-credentials)))
+          (should (equal (cdr peer) (cdr credentials)))
+          (should (memq (car peer) (list nil (emacs-pid)))))))))
+
+(ert-deftest sockets-send-fd ()
+  (sockets-tests--with-local-connection (server client accepted)
+    (should (process-send-fd client 0))
+    (should (process-send-fd client null-device "passed"))
+    (with-timeout (5 (error "Data not received"))
+      (while (< (length sockets-tests--received) 7)
+        (accept-process-output nil 0.05)))
+    (should (equal sockets-tests--received "\0passed"))
+    (should-error (process-send-fd client 'foo) :type 'wrong-type-argument)
+    (should-error (process-send-fd client null-device ""))
+    (should-error (process-send-fd client (expand-file-name
+                                           "no-such-file"
+                                           temporary-file-directory))
+                  :type 'file-error)))
+
+(ert-deftest sockets-send-fd-not-local ()
+  (sockets-tests--with-server server
+    (should-error (process-send-fd server 0))))
+
+(provide 'sockets-tests)
+
+;;; sockets-tests.el ends here
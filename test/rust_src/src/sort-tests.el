@@ -0,0 +1,127 @@
+;;; sort-tests.el --- tests for sorting lines
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(defun sort-tests--sorted (text &rest args)
+  "Return TEXT with its lines sorted by `sort-lines-by' with ARGS."
+  (with-temp-buffer
+    (insert text)
+    (apply #'sort-lines-by (point-min) (point-max) args)
+    (buffer-string)))
+
+(ert-deftest sort-lines-by-text ()
+  (should (equal (sort-tests--sorted "c\na\nb\n") "a\nb\nc\n"))
+  (should (equal (sort-tests--sorted "c\na\nb") "a\nb\nc"))
+  (should (equal (sort-tests--sorted "a\nc\nb\n" t) "c\nb\na\n"))
+  (should (equal (sort-tests--sorted "") "")))
+
+(ert-deftest sort-lines-by-is-stable ()
+  (should (equal (sort-tests--sorted "b 1\na 2\nb 3\na 4\n" nil 1)
+                 "a 2\na 4\nb 1\nb 3\n"))
+  (should (equal (sort-tests--sorted "b 1\na 2\nb 3\na 4\n" t 1)
+                 "b 1\nb 3\na 2\na 4\n")))
+
+(ert-deftest sort-lines-by-field ()
+  (should (equal (sort-tests--sorted "x b\ny a\n" nil 2) "y a\nx b\n"))
+  (should (equal (sort-tests--sorted "x b z\ny a z\n" nil -2) "y a z\nx b z\n"))
+  (should-error (sort-tests--sorted "x b\ny\n" nil 2)))
+
+(ert-deftest sort-lines-by-numeric ()
+  (should (equal (sort-tests--sorted "a 10\nb 9\nc -1.5\nd 0x1f\n" nil 2 'numeric)
+                 "c -1.5\nb 9\na 10\nd 0x1f\n")))
+
+(ert-deftest sort-lines-by-natural ()
+  (should (equal (sort-tests--sorted "file10\nfile9\nfile009a\nfile1\n" nil nil 'natural)
+                 "file1\nfile9\nfile009a\nfile10\n")))
+
+(ert-deftest sort-lines-by-regexp ()
+  (should (equal (sort-tests--sorted "x id=3\ny id=1\nz\n" nil "id=[0-9]")
+                 "z\ny id=1\nx id=3\n"))
+  (should (equal (sort-tests--sorted "2:b\n1:c\n3:a\n" nil '(":\\(.\\)" . 1))
+                 "3:a\n2:b\n1:c\n")))
+
+(ert-deftest sort-lines-by-fold-case ()
+  (should (equal (let ((sort-fold-case t))
+                   (sort-tests--sorted "b\nA\na\nB\n"))
+                 "A\na\nb\nB\n")))
+
+(ert-deftest sort-lines-by-keeps-properties ()
+  (with-temp-buffer
+    (insert (propertize "b" 'face 'bold) "\na\n")
+    (sort-lines-by (point-min) (point-max))
+    (should (equal (buffer-string) "a\nb\n"))
+    (should (eq (get-text-property 3 'face) 'bold))))
+
+(ert-deftest sort-list-stable ()
+  (let ((list (list '(2 . a) '(1 . b) '(2 . c) '(1 . d) '(0 . e))))
+    (should (equal (sort list (lambda (a b) (< (car a) (car b))))
+                   '((0 . e) (1 . b) (1 . d) (2 . a) (2 . c))))))
+
+(ert-deftest sort-vector-in-place ()
+  (let ((vector (vector 3 1.5 2 -1 1.5)))
+    (should (eq (sort vector #'<) vector))
+    (should (equal vector [-1 1.5 1.5 2 3])))
+  (let ((vector (vector "b" 'a "c")))
+    (sort vector #'string<)
+    (should (equal vector [a "b" "c"])))
+  (should (equal (sort (vector 1 3 2) #'>) [3 2 1]))
+  (should-not (sort nil #'<))
+  (should-error (sort "abc" #'<) :type 'wrong-type-argument))
+
+(ert-deftest sort-long-sequences ()
+  (let* ((numbers (number-sequence 1 50000))
+         (shuffled (vconcat (mapcar (lambda (n) (% (* n 7919) 50000)) numbers))))
+    (should (equal (append (sort (copy-sequence shuffled) #'<) nil)
+                   (number-sequence 0 49999)))
+    (should (equal (sort (reverse numbers) #'<) numbers))
+    (should (equal (sort (vconcat (mapcar #'number-to-string numbers)) #'string<)
+                   (vconcat (sort (mapcar #'number-to-string numbers)
+                                  (lambda (a b) (string< a b))))))))
+
+(ert-deftest sort-predicate-error ()
+  (let ((vector (vector 3 'x 1)))
+    (should-error (sort vector #'<) :type 'wrong-type-argument)
+    (should (equal vector [3 x 1]))))
+
+;; PREDICATE drops the elements from the sequence and collects garbage,
+;; which mustn't free them while they are being sorted.
+(ert-deftest sort-predicate-collects-garbage ()
+  (let* ((strings (lambda ()
+                    (mapcar #'number-to-string (number-sequence 1 100))))
+         (expected (sort (funcall strings) #'string<))
+         (vector (vconcat (funcall strings)))
+         (list (funcall strings))
+         (collect (lambda (drop)
+                    (let ((collected nil))
+                      (lambda (a b)
+                        (unless collected
+                          (setq collected t)
+                          (funcall drop)
+                          (garbage-collect)
+                          (make-list 1000 (make-string 3 ?x)))
+                        (string< a b))))))
+    (sort vector (funcall collect (lambda () (fillarray vector nil))))
+    (should (equal vector (vconcat expected)))
+    (should (equal (sort list (funcall collect (lambda () (setcdr list nil))))
+                   expected))))
+
+(provide 'sort-tests)
+
+;;; sort-tests.el ends here
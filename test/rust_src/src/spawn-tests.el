@@ -0,0 +1,86 @@
+;;; spawn-tests.el --- tests for starting subprocesses with posix_spawn
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(defun spawn-tests--output (&rest command)
+  "Run COMMAND with `make-process' and return its output and status."
+  (let ((process (make-process :name "spawn-test" :command command
+                               :buffer (generate-new-buffer " *spawn-test*")
+                               :connection-type 'pipe
+                               :sentinel #'ignore)))
+    (unwind-protect
+        (progn
+          (with-timeout (10 (error "Process didn't exit"))
+            (while (process-live-p process)
+              (accept-process-output process 0.05)))
+          (cons (with-current-buffer (process-buffer process)
+                  (buffer-string))
+                (process-exit-status process)))
+      (kill-buffer (process-buffer process)))))
+
+(ert-deftest spawn-output-and-status ()
+  (skip-unless (executable-find "sh"))
+  (dolist (process-use-posix-spawn '(t nil))
+    (should (equal (spawn-tests--output
+                    "sh" "-c" "echo out; echo err >&2; exit 3")
+                   '("out\nerr\n" . 3)))))
+
+(ert-deftest spawn-directory-and-environment ()
+  (skip-unless (executable-find "sh"))
+  (let ((default-directory (file-name-as-directory temporary-file-directory))
+        (process-environment (cons "SPAWN_TEST=value" process-environment))
+        (directory (directory-file-name
+                    (file-truename temporary-file-directory))))
+    (dolist (process-use-posix-spawn '(t nil))
+      (should (equal (car (spawn-tests--output
+                           "sh" "-c" "pwd -P; echo $SPAWN_TEST"))
+                     (concat directory "\nvalue\n"))))))
+
+(ert-deftest spawn-removed-variable ()
+  (skip-unless (executable-find "sh"))
+  (let ((process-environment (cons "HOME" process-environment))
+        (process-use-posix-spawn t))
+    (should (equal (car (spawn-tests--output "sh" "-c" "echo ${HOME-unset}"))
+                   "unset\n"))))
+
+(ert-deftest spawn-stderr-process ()
+  (skip-unless (executable-find "sh"))
+  (let* ((process-use-posix-spawn t)
+         (stderr (make-pipe-process :name "spawn-test-stderr"
+                                    :buffer (generate-new-buffer
+                                             " *spawn-test-stderr*")))
+         (process (make-process :name "spawn-test"
+                                :command '("sh" "-c" "echo err >&2")
+                                :stderr stderr :sentinel #'ignore)))
+    (unwind-protect
+        (progn
+          (with-timeout (10 (error "Process didn't exit"))
+            (while (process-live-p process)
+              (accept-process-output process 0.05)))
+          (accept-process-output stderr 0.1)
+          (should (equal (with-current-buffer (process-buffer stderr)
+                           (buffer-string))
+                         "err\n")))
+      (kill-buffer (process-buffer stderr))
+      (delete-process stderr))))
+
+(provide 'spawn-tests)
+
+;;; spawn-tests.el ends here
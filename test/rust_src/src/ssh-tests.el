@@ -0,0 +1,75 @@
+;;; ssh-tests.el --- tests for SSH connections
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(defun ssh-tests--connect ()
+  "Connect to the server in $REMACS_SSH_TEST_HOST, or skip the test."
+  (let ((host (getenv "REMACS_SSH_TEST_HOST")))
+    (skip-unless host)
+    (make-ssh-connection host)))
+
+(ert-deftest ssh-connection-handle ()
+  (should-not (ssh-connection-p nil))
+  (should-not (ssh-connection-p '(ssh-connection)))
+  (should-error (ssh-connection-live-p '(foo 1)) :type 'wrong-type-argument)
+  (should-error (ssh-exec '(ssh-connection -1 "user" "host" 22) "true")))
+
+(ert-deftest ssh-connection-refused ()
+  (should-error (make-ssh-connection "127.0.0.1" :port 1 :password "")))
+
+(ert-deftest ssh-exec-output ()
+  (let ((connection (ssh-tests--connect)))
+    (unwind-protect
+        (progn
+          (should (equal (ssh-exec connection "echo hello; echo oops >&2")
+                         '(0 "hello\n" "oops\n")))
+          (should (equal (ssh-exec connection "cat" "input")
+                         '(0 "input" "")))
+          (should (equal (car (ssh-exec connection "exit 3")) 3)))
+      (ssh-connection-close connection))
+    (should-not (ssh-connection-live-p connection))))
+
+(ert-deftest ssh-sftp-files ()
+  (let* ((connection (ssh-tests--connect))
+         (directory (format "/tmp/ssh-tests-%d" (emacs-pid)))
+         (file (concat directory "/file")))
+    (unwind-protect
+        (progn
+          (ssh-make-directory connection directory)
+          (should (eq (plist-get (ssh-file-attributes connection directory)
+                                 :type)
+                      'directory))
+          (ssh-write-file connection file "contents")
+          (should (equal (ssh-file-contents connection file) "contents"))
+          (should (equal (plist-get (ssh-file-attributes connection file)
+                                    :size)
+                         8))
+          (should (equal (ssh-directory-files connection directory)
+                         '("file")))
+          (ssh-rename-file connection file (concat file "2"))
+          (should-not (ssh-file-attributes connection file))
+          (ssh-delete-file connection (concat file "2"))
+          (ssh-delete-directory connection directory)
+          (should-not (ssh-file-attributes connection directory)))
+      (ssh-connection-close connection))))
+
+(provide 'ssh-tests)
+
+;;; ssh-tests.el ends here
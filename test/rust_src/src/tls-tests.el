@@ -0,0 +1,39 @@
+;;; tls-tests.el --- tests for TLS with rustls
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+(require 'network-stream)
+
+(ert-deftest tls-boot-needs-network-process ()
+  (let ((process (make-pipe-process :name "tls-test")))
+    (unwind-protect
+        (progn
+          (should-error (rustls-boot process "localhost"))
+          (should-not (rustls-peer-status process)))
+      (delete-process process))))
+
+(ert-deftest tls-network-stream-backend ()
+  (skip-unless (not (memq system-type '(windows-nt ms-dos))))
+  (let ((network-stream-tls-backend 'rustls))
+    (should (network-stream-use-rustls-p))
+    (should (network-stream-builtin-tls-p))))
+
+(provide 'tls-tests)
+
+;;; tls-tests.el ends here
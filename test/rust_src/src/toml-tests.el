@@ -0,0 +1,73 @@
+;;; toml-tests.el --- tests for native TOML support
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest toml-parse-string-object-types ()
+  (let ((input "name = \"remacs\"\nversion = 1\n\n[dependencies]\nlibc = \"0.2\"\n"))
+    (should (equal (toml-parse-string input :object-type 'alist)
+                   '((name . "remacs") (version . 1)
+                     (dependencies (libc . "0.2")))))
+    (should (equal (toml-parse-string input :object-type 'plist)
+                   '(:name "remacs" :version 1 :dependencies (:libc "0.2"))))
+    (let ((table (toml-parse-string input)))
+      (should (hash-table-p table))
+      (should (equal (gethash "libc" (gethash "dependencies" table)) "0.2")))))
+
+(ert-deftest toml-parse-string-values ()
+  (should (equal (toml-parse-string "a = [true, false, 1.5, \"α\"]"
+                                    :object-type 'alist :false-object nil)
+                 '((a . [t nil 1.5 "α"]))))
+  ;; Arrays of tables are vectors of tables.
+  (should (equal (toml-parse-string "[[bin]]\nname = \"a\"\n[[bin]]\nname = \"b\"\n"
+                                    :object-type 'alist)
+                 '((bin . [((name . "a")) ((name . "b"))]))))
+  ;; Dotted keys and inline tables make nested tables.
+  (should (equal (toml-parse-string "a.b = 1\nc = { d = 2 }" :object-type 'alist)
+                 '((a (b . 1)) (c (d . 2))))))
+
+(ert-deftest toml-parse-string-datetimes ()
+  (let ((input "date = 1979-05-27T07:32:00Z"))
+    (should (equal (toml-parse-string input :object-type 'alist)
+                   '((date . "1979-05-27T07:32:00Z"))))
+    (should (equal (toml-parse-string input :object-type 'alist
+                                      :datetime-function #'list)
+                   '((date "1979-05-27T07:32:00Z"))))))
+
+(ert-deftest toml-parse-string-errors ()
+  (should-error (toml-parse-string "a = ") :type 'toml-parse-error)
+  (should-error (toml-parse-string "a = 1\na = 2") :type 'toml-error)
+  (should-error (toml-parse-string "a\0b") :type 'wrong-type-argument)
+  (should-error (toml-parse-string "a = 1" :null-object nil))
+  (should-error (toml-parse-string "a = 1" :object-type 'vector)))
+
+(ert-deftest toml-parse-buffer ()
+  (with-temp-buffer
+    (insert "[package]\nname = \"remacs\"\n")
+    (goto-char (point-min))
+    (should (equal (toml-parse-buffer :object-type 'alist)
+                   '((package (name . "remacs")))))
+    (should (eobp)))
+  (with-temp-buffer
+    (insert "a = [1, ")
+    (goto-char (point-min))
+    (should-error (toml-parse-buffer) :type 'toml-parse-error)
+    (should (bobp))))
+
+(provide 'toml-tests)
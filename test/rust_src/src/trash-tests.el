@@ -0,0 +1,71 @@
+;;; trash-tests.el --- tests for moving files to the trash
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest trash-move-list-and-restore ()
+  (skip-unless (not (eq system-type 'windows-nt)))
+  (let ((file (make-temp-file "trash" nil nil "trashed text")))
+    (unwind-protect
+        (progn
+          (system-move-file-to-trash file)
+          (should-not (file-exists-p file))
+          (let ((item (assoc file (trash-list))))
+            (should item)
+            (should (integerp (nth 1 item))))
+          (trash-restore file)
+          (should-not (assoc file (trash-list)))
+          (should (equal (with-temp-buffer
+                           (insert-file-contents file)
+                           (buffer-string))
+                         "trashed text")))
+      (when (file-exists-p file)
+        (delete-file file)))))
+
+(ert-deftest trash-restore-errors ()
+  (skip-unless (not (eq system-type 'windows-nt)))
+  (let ((file (make-temp-file "trash")))
+    (unwind-protect
+        (progn
+          (should-error (trash-restore file) :type 'file-missing)
+          (system-move-file-to-trash file)
+          (write-region "" nil file nil 'silent)
+          (should-error (trash-restore file) :type 'file-already-exists)
+          (delete-file file)
+          (trash-restore file)
+          (should (file-exists-p file)))
+      (when (file-exists-p file)
+        (delete-file file)))))
+
+(ert-deftest trash-move-file-to-trash ()
+  (skip-unless (not (eq system-type 'windows-nt)))
+  (let ((file (make-temp-file "trash"))
+        (trash-directory nil))
+    (unwind-protect
+        (progn
+          (move-file-to-trash file)
+          (should-not (file-exists-p file))
+          (should (assoc file (trash-list)))
+          (trash-restore file))
+      (when (file-exists-p file)
+        (delete-file file)))))
+
+(provide 'trash-tests)
+
+;;; trash-tests.el ends here
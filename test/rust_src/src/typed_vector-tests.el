@@ -0,0 +1,99 @@
+;;; typed_vector-tests.el --- tests for typed_vector.rs
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+
+(require 'ert)
+
+(ert-deftest typed-vector-make ()
+  (let ((v (make-typed-vector 'f64 4 1.5)))
+    (should (typed-vector-p v))
+    (should-not (typed-vector-p [1.5 1.5 1.5 1.5]))
+    (should (eq (type-of v) 'typed-vector))
+    (should (eq (typed-vector-type v) 'f64))
+    (should (= (typed-vector-length v) 4))
+    (should (equal (typed-vector-to-list v) '(1.5 1.5 1.5 1.5))))
+  (should (equal (typed-vector-to-list (make-typed-vector 'i32 3)) '(0 0 0)))
+  (should (equal (typed-vector-to-list (typed-vector 'u8 1 2 255))
+                 '(1 2 255)))
+  (should-error (make-typed-vector 'i64 3))
+  (should-error (make-typed-vector 'u8 -1) :type 'args-out-of-range))
+
+(ert-deftest typed-vector-aref-aset ()
+  (let ((v (make-typed-vector 'i32 3)))
+    (should (= (aset v 1 -7) -7))
+    (should (= (aref v 1) -7))
+    (should (= (typed-vector-ref v 1) -7))
+    (typed-vector-set v 2 2147483647)
+    (should (equal (typed-vector-to-list v) '(0 -7 2147483647)))
+    (should-error (aset v 0 2147483648) :type 'args-out-of-range)
+    (should-error (aset v 0 1.0) :type 'wrong-type-argument)
+    (should-error (aref v 3) :type 'args-out-of-range)
+    (should-error (aref v -1) :type 'args-out-of-range))
+  (let ((v (make-typed-vector 'u8 2)))
+    (should-error (aset v 0 256) :type 'args-out-of-range)
+    (should-error (aset v 0 -1) :type 'args-out-of-range))
+  (let ((v (make-typed-vector 'f64 2)))
+    (aset v 0 3)
+    (should (eql (aref v 0) 3.0))))
+
+(ert-deftest typed-vector-fill-copy-replace ()
+  (let ((v (make-typed-vector 'u8 6)))
+    (should (eq (typed-vector-fill v 9 2 4) v))
+    (should (equal (typed-vector-to-list v) '(0 0 9 9 0 0)))
+    (let ((copy (typed-vector-copy v 1 4)))
+      (should (equal (typed-vector-to-list copy) '(0 9 9)))
+      (aset copy 0 1)
+      (should (= (aref v 1) 0))
+      (typed-vector-replace v copy 3)
+      (should (equal (typed-vector-to-list v) '(0 0 9 1 9 9)))
+      (should-error (typed-vector-replace v copy 4)
+                    :type 'args-out-of-range))
+    (typed-vector-replace v v)
+    (should (equal (typed-vector-to-list v) '(0 0 9 1 9 9)))
+    (should-error (typed-vector-replace v (make-typed-vector 'i32 1)))
+    (should-error (typed-vector-fill v 1 4 2) :type 'args-out-of-range)))
+
+(ert-deftest typed-vector-map-arithmetic ()
+  (let ((a (typed-vector 'f64 1.0 2.0 3.0))
+        (b (typed-vector 'f64 0.5 0.5 4.0)))
+    (should (equal (typed-vector-to-list (typed-vector-map #'+ a b))
+                   '(1.5 2.5 7.0)))
+    (should (equal (typed-vector-to-list (typed-vector-map #'* a 2))
+                   '(2.0 4.0 6.0)))
+    (should (equal (typed-vector-to-list (typed-vector-map #'max a b))
+                   '(1.0 2.0 4.0)))
+    (should (equal (typed-vector-to-list (typed-vector-map #'1+ a))
+                   '(2.0 3.0 4.0)))
+    (should (equal (typed-vector-to-list
+                    (typed-vector-map (lambda (x y) (- x y)) a b))
+                   '(0.5 1.5 -1.0))))
+  (let ((v (typed-vector 'u8 250 10)))
+    (should (equal (typed-vector-to-list (typed-vector-map #'+ v 10))
+                   '(4 20)))
+    (should (equal (typed-vector-to-list (typed-vector-map #'/ v 3))
+                   '(83 3)))
+    (should-error (typed-vector-map #'/ v 0) :type 'arith-error)
+    (should-error (typed-vector-map #'+ v (typed-vector 'u8 1))))
+  (let ((v (typed-vector 'i32 2147483647)))
+    (should (equal (typed-vector-to-list (typed-vector-map #'+ v 1))
+                   '(-2147483648)))))
+
+(provide 'typed_vector-tests)
+
+;;; typed_vector-tests.el ends here
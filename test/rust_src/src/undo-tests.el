@@ -0,0 +1,79 @@
+;;; undo-tests.el --- tests for undo recording
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest undo-insertions-combine ()
+  (with-temp-buffer
+    (buffer-enable-undo)
+    (insert "world")
+    (goto-char 1)
+    (insert "hello ")
+    (goto-char 4)
+    (insert "--")
+    (should (equal (car buffer-undo-list) '(1 . 14)))
+    (undo-boundary)
+    (goto-char (point-max))
+    (insert "!")
+    (should (equal (car buffer-undo-list) '(15 . 16)))))
+
+(ert-deftest undo-deletion-records-text ()
+  (with-temp-buffer
+    (buffer-enable-undo)
+    (insert "hello")
+    (undo-boundary)
+    (delete-region 2 4)
+    (should (equal (car buffer-undo-list) '("el" . 2)))))
+
+(ert-deftest undo-outer-limit-trims-oldest ()
+  (with-temp-buffer
+    (buffer-enable-undo)
+    (dotimes (i 1000)
+      (goto-char (point-max))
+      (insert "x")
+      (goto-char (point-min))
+      (delete-char 1))
+    (let ((undo-outer-limit 2000)
+          (undo-outer-limit-function nil)
+          (newest (car buffer-undo-list)))
+      (garbage-collect)
+      (should buffer-undo-list)
+      (should (eq (car buffer-undo-list) newest))
+      (should (< (length buffer-undo-list) 1000)))))
+
+(ert-deftest undo-outer-limit-truncate-trims ()
+  (with-temp-buffer
+    (buffer-enable-undo)
+    (dotimes (i 1000)
+      (goto-char (point-max))
+      (insert "x")
+      (goto-char (point-min))
+      (delete-char 1))
+    (let ((undo-outer-limit 2000)
+          (undo-outer-limit-function 'undo-outer-limit-truncate)
+          (undo-ask-before-discard nil)
+          (warning-suppress-log-types '((undo discard-info)))
+          (warning-suppress-types '((undo discard-info)))
+          (newest (car buffer-undo-list)))
+      (garbage-collect)
+      (should buffer-undo-list)
+      (should (eq (car buffer-undo-list) newest))
+      (should (< (length buffer-undo-list) 1000)))))
+
+(provide 'undo-tests)
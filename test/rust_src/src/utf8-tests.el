@@ -0,0 +1,66 @@
+;;; utf8-tests.el --- tests for UTF-8 validation and repair
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest utf8-valid-p-strings ()
+  (should (utf8-valid-p "plain ASCII"))
+  (should (utf8-valid-p "naïve café"))
+  (should (utf8-valid-p "caf\303\251"))
+  (should-not (utf8-valid-p "caf\351"))
+  (should-not (utf8-valid-p (string-to-multibyte "caf\351")))
+  ;; Raw bytes which form a valid sequence are valid.
+  (should (utf8-valid-p (string-to-multibyte "caf\303\251")))
+  (should-not (utf8-valid-p (string #x110000)))
+  (should (utf8-valid-p "caf\351" 0 3))
+  (should-not (utf8-valid-p "caf\351" -1))
+  (should-error (utf8-valid-p 'foo) :type 'wrong-type-argument))
+
+(ert-deftest utf8-valid-p-buffers ()
+  (with-temp-buffer
+    (insert "abc" (string-to-multibyte "\377") "def")
+    (should-not (utf8-valid-p (current-buffer)))
+    (should (utf8-valid-p (current-buffer) 1 4))
+    (should (utf8-valid-p (current-buffer) 8 5))
+    (narrow-to-region 5 8)
+    (should (utf8-valid-p (current-buffer)))
+    (should-error (utf8-valid-p (current-buffer) 1 4)
+                  :type 'args-out-of-range)))
+
+(ert-deftest utf8-repair-region-multibyte ()
+  (with-temp-buffer
+    (insert "a" (string-to-multibyte "\342\202\254") "b"
+            (string-to-multibyte "\342\202") "c" (string #x110000) "d")
+    (goto-char (point-max))
+    (should (= (utf8-repair-region (point-min) (point-max)) 2))
+    (should (equal (buffer-string) "a€b�c�d"))
+    (should (= (point) (point-max)))
+    (should (utf8-valid-p (current-buffer)))
+    (should (= (utf8-repair-region (point-min) (point-max)) 0))))
+
+(ert-deftest utf8-repair-region-unibyte ()
+  (with-temp-buffer
+    (set-buffer-multibyte nil)
+    (insert "ok \303\251 bad \351 truncated \342\202")
+    (should (= (utf8-repair-region (point-min) (point-max)) 2))
+    (should (equal (buffer-string)
+                   "ok \303\251 bad \357\277\275 truncated \357\277\275"))
+    (should (utf8-valid-p (current-buffer)))))
+
+(provide 'utf8-tests)
@@ -0,0 +1,90 @@
+;;; weak-tests.el --- tests for weak.rs
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+
+(require 'ert)
+(require 'cl-lib)
+
+(ert-deftest weak-ref-basics ()
+  (let* ((object (list 1 2 3))
+         (ref (make-weak-ref object)))
+    (should (weak-ref-p ref))
+    (should-not (weak-ref-p object))
+    (should-not (ephemeron-p ref))
+    (should (eq (type-of ref) 'weak-ref))
+    (should (eq (weak-ref-get ref) object))
+    (garbage-collect)
+    (should (eq (weak-ref-get ref) object))
+    (should (eq (weak-ref-get (make-weak-ref 'foo)) 'foo))
+    (should-error (weak-ref-get object) :type 'wrong-type-argument)))
+
+(ert-deftest weak-ref-cleared-by-gc ()
+  ;; The stack is scanned conservatively, so some of the objects may
+  ;; survive; most of them don't.
+  (let ((refs (let (refs)
+                (dotimes (i 1000)
+                  (push (make-weak-ref (list i)) refs))
+                refs)))
+    (garbage-collect)
+    (should (> (cl-count-if-not #'weak-ref-get refs) 500))))
+
+(ert-deftest ephemeron-basics ()
+  (let* ((key (list 'key))
+         (value (list 'value))
+         (ephemeron (make-ephemeron key value)))
+    (should (ephemeron-p ephemeron))
+    (should-not (weak-ref-p ephemeron))
+    (should (eq (type-of ephemeron) 'ephemeron))
+    (should (eq (ephemeron-key ephemeron) key))
+    (should (eq (ephemeron-value ephemeron) value))
+    (setq value nil)
+    (garbage-collect)
+    (should (eq (ephemeron-key ephemeron) key))
+    (should (equal (ephemeron-value ephemeron) '(value)))
+    (should-error (ephemeron-key key) :type 'wrong-type-argument)))
+
+(ert-deftest ephemeron-value-does-not-keep-key ()
+  ;; Each value refers to its key, which must not keep the key alive.
+  (let ((ephemerons (let (ephemerons)
+                      (dotimes (i 1000)
+                        (let ((key (list i)))
+                          (push (make-ephemeron key (list 'value key))
+                                ephemerons)))
+                      ephemerons)))
+    (garbage-collect)
+    (should (> (cl-count-if-not #'ephemeron-key ephemerons) 500))
+    (dolist (ephemeron ephemerons)
+      (unless (ephemeron-key ephemeron)
+        (should-not (ephemeron-value ephemeron))))))
+
+(ert-deftest ephemeron-chain ()
+  ;; The key of the second ephemeron is reachable only through the value
+  ;; of the first, whose key is live.
+  (let* ((key (list 'key))
+         (second (let ((key2 (list 'key2)))
+                   (cons (make-ephemeron key (list key2))
+                         (make-ephemeron key2 (list 'value2))))))
+    (garbage-collect)
+    (should (ephemeron-key (cdr second)))
+    (should (equal (ephemeron-value (cdr second)) '(value2)))
+    (should (eq (ephemeron-key (car second)) key))))
+
+(provide 'weak-tests)
+
+;;; weak-tests.el ends here
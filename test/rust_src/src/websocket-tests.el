@@ -0,0 +1,46 @@
+;;; websocket-tests.el --- tests for the WebSocket client
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest websocket-handle ()
+  (should-not (websocketp nil))
+  (should-not (websocketp '(websocket)))
+  (should-error (websocket-openp '(foo 1)) :type 'wrong-type-argument)
+  (should-error (websocket-open "not a url")))
+
+(ert-deftest websocket-connection-refused ()
+  (let* ((closed nil)
+         (websocket (websocket-open
+                     "ws://127.0.0.1:1/"
+                     :on-close (lambda (ws code reason)
+                                 (setq closed (list ws code reason))))))
+    (should (websocketp websocket))
+    (with-timeout (10 (error "Connection attempt timed out"))
+      (while (not closed)
+        (accept-process-output nil 0.05)))
+    (should (eq (nth 0 closed) websocket))
+    (should-not (nth 1 closed))
+    (should (stringp (nth 2 closed)))
+    (should-not (websocket-openp websocket))
+    (should-error (websocket-send websocket "hello"))))
+
+(provide 'websocket-tests)
+
+;;; websocket-tests.el ends here
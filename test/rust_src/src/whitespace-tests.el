@@ -0,0 +1,67 @@
+;;; whitespace-tests.el --- tests for whitespace normalization
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest delete-trailing-whitespace-region ()
+  (with-temp-buffer
+    (insert "a \t\nb\n  \n\f\nc  \nd ")
+    (should (= (delete-trailing-whitespace-region (point-min) (point-max)) 4))
+    (should (equal (buffer-string) "a\nb\n\n\f\nc\nd"))
+    (should (= (delete-trailing-whitespace-region (point-min) (point-max)) 0))))
+
+(ert-deftest delete-trailing-whitespace-region-end-inside-line ()
+  (with-temp-buffer
+    (insert "a  \nb  c")
+    (should (= (delete-trailing-whitespace-region (point-min) 7) 1))
+    (should (equal (buffer-string) "a\nb  c"))))
+
+(ert-deftest untabify-region-fast ()
+  (dolist (text '("ab\t\tc\n\tx\n" "  \t x\t\ty" "é\t中\tz"))
+    (let ((expected (with-temp-buffer
+                      (insert text)
+                      (untabify (point-min) (point-max))
+                      (buffer-string))))
+      (with-temp-buffer
+        (insert text)
+        (goto-char 2)
+        (untabify-region-fast (point-min) (point-max))
+        (should (equal (buffer-string) expected))
+        (should (= (point) 2))))))
+
+(ert-deftest tabify-region-fast ()
+  (dolist (text '("a  b      c d\t" "\t    x\n        y" " \t  z" "中     q"))
+    (let ((expected (with-temp-buffer
+                      (insert text)
+                      (tabify (point-min) (point-max))
+                      (buffer-string))))
+      (with-temp-buffer
+        (insert text)
+        (tabify-region-fast (point-min) (point-max))
+        (should (equal (buffer-string) expected))))))
+
+(ert-deftest tabify-region-fast-inside-line ()
+  (with-temp-buffer
+    (insert "abc         d")
+    (tabify-region-fast 6 (point-max))
+    (should (equal (buffer-string) "abc  \t    d"))))
+
+(provide 'whitespace-tests)
+
+;;; whitespace-tests.el ends here
@@ -0,0 +1,64 @@
+;;; xattr-tests.el --- tests for extended attributes and ACLs
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest xattr-set-and-get ()
+  (let ((file (make-temp-file "xattr")))
+    (unwind-protect
+        (progn
+          (should-not (file-xattr file "user.emacs-test"))
+          (skip-unless (set-file-xattr file "user.emacs-test" "value\0"))
+          (should (equal (file-xattr file "user.emacs-test") "value\0"))
+          (should (member "user.emacs-test" (file-xattrs file)))
+          (should (equal (cdr (assq 'xattrs (file-extended-attributes file)))
+                         '(("user.emacs-test" . "value\0"))))
+          (should (set-file-xattr file "user.emacs-test" nil))
+          (should-not (file-xattr file "user.emacs-test"))
+          ;; Removing an attribute which isn't there is fine.
+          (should (set-file-xattr file "user.emacs-test" nil)))
+      (delete-file file))))
+
+(ert-deftest xattr-extended-attributes-copied ()
+  (let ((file (make-temp-file "xattr"))
+        (copy (make-temp-file "xattr")))
+    (unwind-protect
+        (progn
+          (skip-unless (set-file-xattr file "user.emacs-test" "copied"))
+          (should (set-file-extended-attributes
+                   copy (file-extended-attributes file)))
+          (should (equal (file-xattr copy "user.emacs-test") "copied")))
+      (delete-file file)
+      (delete-file copy))))
+
+(ert-deftest xattr-file-acl-entries ()
+  (skip-unless (eq system-type 'gnu/linux))
+  (let ((file (make-temp-file "xattr")))
+    (unwind-protect
+        (progn
+          (set-file-modes file #o640)
+          (should (equal (file-acl-entries file)
+                         '((user nil "rw-") (group nil "r--") (other nil "---"))))
+          (should-not (file-acl-entries file t))
+          (should-not (file-acl-entries (concat file "-nonexistent"))))
+      (delete-file file))))
+
+(provide 'xattr-tests)
+
+;;; xattr-tests.el ends here
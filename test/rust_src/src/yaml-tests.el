@@ -0,0 +1,63 @@
+;;; yaml-tests.el --- tests for native YAML support
+
+;; Copyright 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+(require 'ert)
+
+(ert-deftest yaml-parse-string-object-types ()
+  (let ((input "name: web\nreplicas: 3\nports:\n  - 80\n  - 443\nenabled: true\nextra: ~\n"))
+    (should (equal (yaml-parse-string input :object-type 'alist)
+                   '((name . "web") (replicas . 3) (ports . [80 443])
+                     (enabled . t) (extra . :null))))
+    (should (equal (yaml-parse-string input :object-type 'plist)
+                   '(:name "web" :replicas 3 :ports [80 443]
+                     :enabled t :extra :null)))
+    (let ((table (yaml-parse-string input)))
+      (should (hash-table-p table))
+      (should (equal (gethash "ports" table) [80 443])))))
+
+(ert-deftest yaml-parse-string-scalars ()
+  (should (equal (yaml-parse-string "- false\n- null\n- 1.5\n- \"α\"\n"
+                                    :null-object nil :false-object 'no)
+                 [no nil 1.5 "α"]))
+  (should (equal (yaml-parse-string "hello") "hello"))
+  ;; Numeric keys are read as strings.
+  (should (equal (yaml-parse-string "1: one" :object-type 'alist)
+                 '((\1 . "one"))))
+  ;; Aliases refer back to their anchor.
+  (should (equal (yaml-parse-string "a: &x [1, 2]\nb: *x" :object-type 'alist)
+                 '((a . [1 2]) (b . [1 2])))))
+
+(ert-deftest yaml-parse-string-errors ()
+  (should-error (yaml-parse-string "a: [1, 2") :type 'yaml-parse-error)
+  (should-error (yaml-parse-string "a: b: c") :type 'yaml-error)
+  (should-error (yaml-parse-string "a\0b") :type 'wrong-type-argument)
+  (should-error (yaml-parse-string "a: 1" :object-type 'vector)))
+
+(ert-deftest yaml-serialize-roundtrip ()
+  (let ((object '((name . "web") (ports . [80 443]) (debug . :false))))
+    (should (string-prefix-p "---" (yaml-serialize object)))
+    (should (equal (yaml-parse-string (yaml-serialize object)
+                                      :object-type 'alist)
+                   object)))
+  (should (equal (yaml-parse-string (yaml-serialize '(:a (:b 1))))
+                 (yaml-parse-string "a:\n  b: 1")))
+  (should (equal (yaml-parse-string (yaml-serialize "x")) "x"))
+  (should-error (yaml-serialize '((a . 1)) :object-type 'alist)))
+
+(provide 'yaml-tests)